@@ -50,15 +50,11 @@ pub fn main() {
         return;
     };
 
-    mixer.add_track(&track).unwrap();
-    mixer.add_track(&track2).unwrap();
     mixer.set_normalize_output(true).unwrap();
 
     mixer.set_attribute_bool(est_audio::AudioAttributes::FXEnabled, true).unwrap();
     mixer.set_attribute_f32(est_audio::AudioAttributes::FXTempo, 1.5).unwrap();
     mixer.play(&mut device).unwrap();
 
-    loop {
-        std::thread::sleep(std::time::Duration::from_millis(100));
-    }
+    mixer.wait_until_finished(None);
 }
\ No newline at end of file
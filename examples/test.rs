@@ -42,7 +42,5 @@ pub fn main() {
         .play(&mut device)
         .unwrap();
 
-    loop {
-        std::thread::sleep(std::time::Duration::from_millis(100));
-    }
+    track.wait_until_finished(None);
 }
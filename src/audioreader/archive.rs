@@ -0,0 +1,166 @@
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{Read, Seek, SeekFrom},
+};
+
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum ArchiveError {
+    #[error("Archive not found: {0}")]
+    FileNotFound(String),
+    #[error("Asset not found in the archive: {0}")]
+    AssetNotFound(String),
+    #[error("Asset {0} lies outside of the archive")]
+    InvalidEntry(String),
+    #[error("{0}")]
+    Other(Box<dyn std::error::Error + Send + 'static>),
+}
+
+impl ArchiveError {
+    pub fn from_other<E: std::error::Error + Send + 'static>(error: E) -> Self {
+        ArchiveError::Other(Box::new(error))
+    }
+}
+
+/// Where [Source::Asset](crate::Source::Asset) reads the bytes of an audio file from, such as
+/// an archive the files are packed in.
+pub trait AssetSource: Send + Sync {
+    /// The bytes of the file `name`, encoded as on disk.
+    fn read(&self, name: &str) -> Result<Vec<u8>, ArchiveError>;
+
+    fn contains(&self, name: &str) -> bool;
+}
+
+/// Where a file is stored in a [PakArchive].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PakEntry {
+    /// Byte offset of the file in the archive.
+    pub offset: u64,
+    pub length: u64,
+}
+
+/// A pack file holding audio files back to back, each stored uncompressed at the offset and
+/// length registered for its name. The table of contents is up to the game, register the
+/// files it lists with [PakArchive::add_entry].
+#[derive(Debug, Clone)]
+pub struct PakArchive {
+    path: String,
+    size: u64,
+    entries: HashMap<String, PakEntry>,
+}
+
+impl PakArchive {
+    pub fn new(path: &str) -> Result<Self, ArchiveError> {
+        let Ok(metadata) = std::fs::metadata(path) else {
+            return Err(ArchiveError::FileNotFound(path.to_string()));
+        };
+
+        Ok(Self {
+            path: path.to_string(),
+            size: metadata.len(),
+            entries: HashMap::new(),
+        })
+    }
+
+    /// Register the file `name` stored at `offset` for `length` bytes.
+    pub fn add_entry(&mut self, name: &str, offset: u64, length: u64) -> Result<(), ArchiveError> {
+        let end = offset.checked_add(length);
+        if end.is_none_or(|end| end > self.size) {
+            return Err(ArchiveError::InvalidEntry(name.to_string()));
+        }
+
+        self.entries
+            .insert(name.to_string(), PakEntry { offset, length });
+        Ok(())
+    }
+
+    pub fn remove_entry(&mut self, name: &str) -> Option<PakEntry> {
+        self.entries.remove(name)
+    }
+
+    pub fn entry(&self, name: &str) -> Option<PakEntry> {
+        self.entries.get(name).copied()
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.entries.keys().map(String::as_str)
+    }
+}
+
+impl AssetSource for PakArchive {
+    fn read(&self, name: &str) -> Result<Vec<u8>, ArchiveError> {
+        let Some(entry) = self.entries.get(name) else {
+            return Err(ArchiveError::AssetNotFound(name.to_string()));
+        };
+
+        let mut file =
+            File::open(&self.path).map_err(|_| ArchiveError::FileNotFound(self.path.clone()))?;
+        file.seek(SeekFrom::Start(entry.offset))
+            .map_err(ArchiveError::from_other)?;
+
+        let mut data = vec![0; entry.length as usize];
+        file.read_exact(&mut data)
+            .map_err(|_| ArchiveError::InvalidEntry(name.to_string()))?;
+
+        Ok(data)
+    }
+
+    fn contains(&self, name: &str) -> bool {
+        self.entries.contains_key(name)
+    }
+}
+
+/// A zip archive, files are looked up by their path inside it. Stored and deflated files are
+/// supported.
+#[cfg(feature = "zip")]
+#[derive(Debug)]
+pub struct ZipArchive {
+    archive: std::sync::Mutex<zip::ZipArchive<File>>,
+}
+
+#[cfg(feature = "zip")]
+impl ZipArchive {
+    pub fn new(path: &str) -> Result<Self, ArchiveError> {
+        let file = File::open(path).map_err(|_| ArchiveError::FileNotFound(path.to_string()))?;
+        let archive = zip::ZipArchive::new(file).map_err(ArchiveError::from_other)?;
+
+        Ok(Self {
+            archive: std::sync::Mutex::new(archive),
+        })
+    }
+
+    pub fn names(&self) -> Vec<String> {
+        self.archive
+            .lock()
+            .map(|archive| archive.file_names().map(str::to_string).collect())
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(feature = "zip")]
+impl AssetSource for ZipArchive {
+    fn read(&self, name: &str) -> Result<Vec<u8>, ArchiveError> {
+        let Ok(mut archive) = self.archive.lock() else {
+            return Err(ArchiveError::AssetNotFound(name.to_string()));
+        };
+
+        let mut file = archive
+            .by_name(name)
+            .map_err(|_| ArchiveError::AssetNotFound(name.to_string()))?;
+
+        let mut data = Vec::with_capacity(file.size() as usize);
+        file.read_to_end(&mut data)
+            .map_err(ArchiveError::from_other)?;
+
+        Ok(data)
+    }
+
+    fn contains(&self, name: &str) -> bool {
+        self.archive
+            .lock()
+            .is_ok_and(|archive| archive.index_for_name(name).is_some())
+    }
+}
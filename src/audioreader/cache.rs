@@ -1,15 +1,56 @@
 use once_cell::sync::Lazy;
 use std::collections::HashMap;
-use std::sync::{Arc, Mutex};
+use std::ops::Deref;
+use std::sync::{
+    Arc, Mutex,
+    atomic::{AtomicU64, AtomicUsize, Ordering},
+};
 
 use crate::utils;
 use miniaudio_sys::*;
 
-use super::{AudioReaderError, ogg};
+use super::{AudioReaderError, decoder};
+
+/// Samples of an [AudioCache], decoded onto the heap or read in place from a mapped file.
+#[derive(Debug)]
+pub enum CacheBuffer {
+    Decoded(Vec<f32>),
+    #[cfg(feature = "mmap")]
+    Mapped(super::mapped::MappedPcm),
+}
+
+impl CacheBuffer {
+    /// Bytes allocated on the heap, a mapped file takes none.
+    pub fn heap_bytes(&self) -> usize {
+        match self {
+            CacheBuffer::Decoded(buffer) => buffer.capacity() * std::mem::size_of::<f32>(),
+            #[cfg(feature = "mmap")]
+            CacheBuffer::Mapped(_) => 0,
+        }
+    }
+}
+
+impl From<Vec<f32>> for CacheBuffer {
+    fn from(buffer: Vec<f32>) -> Self {
+        CacheBuffer::Decoded(buffer)
+    }
+}
+
+impl Deref for CacheBuffer {
+    type Target = [f32];
+
+    fn deref(&self) -> &[f32] {
+        match self {
+            CacheBuffer::Decoded(buffer) => buffer,
+            #[cfg(feature = "mmap")]
+            CacheBuffer::Mapped(mapped) => mapped.pcm(),
+        }
+    }
+}
 
 #[derive(Debug)]
 pub struct AudioCache {
-    pub buffer: Vec<f32>,
+    pub buffer: CacheBuffer,
     pub channel_count: usize,
     pub length_in_frames: usize,
     pub sample_rate: f32,
@@ -17,12 +58,20 @@ pub struct AudioCache {
 
 impl AudioCache {
     pub fn create_ma_buffer(&self) -> Box<ma_audio_buffer> {
+        self.create_ma_buffer_range(0, self.length_in_frames)
+    }
+
+    /// A buffer over `frames` frames from frame `offset`, clamped to the cached audio.
+    pub fn create_ma_buffer_range(&self, offset: usize, frames: usize) -> Box<ma_audio_buffer> {
+        let offset = offset.min(self.length_in_frames.saturating_sub(1));
+        let frames = frames.min(self.length_in_frames - offset);
+
         unsafe {
             let mut config = ma_audio_buffer_config_init(
                 ma_format_f32,
                 self.channel_count as u32,
-                self.length_in_frames as u64,
-                &self.buffer[0] as *const f32 as *const std::ffi::c_void,
+                frames as u64,
+                &self.buffer[offset * self.channel_count] as *const f32 as *const std::ffi::c_void,
                 std::ptr::null(),
             );
 
@@ -49,11 +98,79 @@ impl AudioCache {
 pub(crate) struct Handle {
     pub buffer: Arc<AudioCache>,
     pub lifetime: usize,
+    // Cache clock at the last load, idle entries used longest ago are evicted first
+    pub last_used: u64,
 }
 
 static AUDIO_READER_CACHE: Lazy<Mutex<HashMap<String, Handle>>> =
     Lazy::new(|| Mutex::new(HashMap::new()));
 
+// Bytes of decoded audio kept once no sample or track uses it anymore, see [set_cache_budget]
+static CACHE_BUDGET: AtomicUsize = AtomicUsize::new(0);
+static CACHE_CLOCK: AtomicU64 = AtomicU64::new(0);
+
+fn tick() -> u64 {
+    CACHE_CLOCK.fetch_add(1, Ordering::Relaxed)
+}
+
+/// Keep up to `bytes` of decoded audio no sample or track uses anymore, so loading the same
+/// file or buffer again does not decode it again. Past the budget the entries used longest
+/// ago are freed. The default of 0 frees decoded audio as soon as it is unused.
+pub fn set_cache_budget(bytes: usize) {
+    CACHE_BUDGET.store(bytes, Ordering::Relaxed);
+
+    let mut cache = AUDIO_READER_CACHE.lock().unwrap();
+    trim_cache(&mut cache);
+}
+
+pub fn cache_budget() -> usize {
+    CACHE_BUDGET.load(Ordering::Relaxed)
+}
+
+/// Bytes of decoded audio kept for reuse by [set_cache_budget] but not in use.
+pub fn idle_cache_bytes() -> usize {
+    let cache = AUDIO_READER_CACHE.lock().unwrap();
+
+    cache
+        .values()
+        .filter(|handle| handle.lifetime == 0)
+        .map(|handle| handle.buffer.buffer.heap_bytes())
+        .sum()
+}
+
+/// Free all decoded audio kept for reuse, audio in use stays loaded.
+pub fn clear_cache() {
+    let mut cache = AUDIO_READER_CACHE.lock().unwrap();
+    cache.retain(|_, handle| handle.lifetime > 0);
+}
+
+/// Evict unused entries, least recently used first, until they fit the budget.
+fn trim_cache(cache: &mut HashMap<String, Handle>) {
+    let budget = CACHE_BUDGET.load(Ordering::Relaxed);
+
+    let mut idle = cache
+        .iter()
+        .filter(|(_, handle)| handle.lifetime == 0)
+        .map(|(key, handle)| (handle.last_used, handle.buffer.buffer.heap_bytes(), key.clone()))
+        .collect::<Vec<_>>();
+
+    let mut idle_bytes: usize = idle.iter().map(|(_, bytes, _)| bytes).sum();
+    if idle_bytes <= budget {
+        return;
+    }
+
+    idle.sort_unstable_by_key(|(last_used, _, _)| *last_used);
+
+    for (_, bytes, key) in idle {
+        if idle_bytes <= budget {
+            break;
+        }
+
+        cache.remove(&key);
+        idle_bytes -= bytes;
+    }
+}
+
 pub fn load_file_cache(path: &str) -> Result<Arc<AudioCache>, AudioReaderError> {
     if path.is_empty() {
         return Err(AudioReaderError::InvalidParameter);
@@ -67,93 +184,130 @@ pub fn load_file_cache(path: &str) -> Result<Arc<AudioCache>, AudioReaderError>
 
     if let Some(data) = cache.get_mut(path) {
         data.lifetime += 1;
+        data.last_used = tick();
         return Ok(data.buffer.clone());
     }
 
-    if ogg::is_ogg(path) {
-        match ogg::read_ogg_data_file(path) {
-            Ok(buffer) => {
-                let audio_cache = AudioCache {
-                    buffer: buffer.pcm_f32,
-                    channel_count: buffer.channels as usize,
-                    sample_rate: buffer.sample_rate,
-                    length_in_frames: buffer.pcm_length,
-                };
-
-                let arc_cache = Arc::new(audio_cache);
-                cache.insert(
-                    path.to_string(),
-                    Handle {
-                        buffer: Arc::clone(&arc_cache),
-                        lifetime: 1,
-                    },
-                );
+    // Read onto the heap first when transformed or decoded by a registered decoder
+    let transform = super::transform::asset_transform();
+    if transform.is_some() || decoder::find_for_file(path).is_some() {
+        drop(cache);
+        return load_read_cache(path, transform);
+    }
 
-                return Ok(arc_cache);
-            }
-            Err(e) => {
-                return Err(AudioReaderError::from_other(e));
-            }
+    unsafe {
+        let cpath = std::ffi::CString::new(path).unwrap();
+
+        let decoder_config = ma_decoder_config_init(ma_format_f32, 0, 0);
+        let mut decoder: ma_decoder = std::mem::zeroed();
+        let result = ma_decoder_init_file(
+            cpath.as_ptr() as *const i8,
+            &decoder_config,
+            &mut decoder as *mut ma_decoder,
+        );
+
+        if result != MA_SUCCESS {
+            return Err(AudioReaderError::InitializationError(result));
         }
-    } else {
-        unsafe {
-            let cpath = std::ffi::CString::new(path).unwrap();
-
-            let decoder_config = ma_decoder_config_init(ma_format_f32, 0, 0);
-            let mut decoder: ma_decoder = std::mem::zeroed();
-            let result = ma_decoder_init_file(
-                cpath.as_ptr() as *const i8,
-                &decoder_config,
-                &mut decoder as *mut ma_decoder,
-            );
 
-            if result != MA_SUCCESS {
-                return Err(AudioReaderError::InitializationError(result));
-            }
+        let mut pcm_frame = 0;
+        let result = ma_decoder_get_length_in_pcm_frames(&mut decoder, &mut pcm_frame);
+        if result != MA_SUCCESS {
+            ma_decoder_uninit(&mut decoder);
+            return Err(AudioReaderError::InitializationError(result));
+        }
 
-            let mut pcm_frame = 0;
-            let result = ma_decoder_get_length_in_pcm_frames(&mut decoder, &mut pcm_frame);
-            if result != MA_SUCCESS {
-                ma_decoder_uninit(&mut decoder);
-                return Err(AudioReaderError::InitializationError(result));
-            }
+        let mut pcm_f32: Vec<f32> =
+            vec![0.0; (pcm_frame * decoder.outputChannels as u64) as usize];
+        let mut frames_read: u64 = 0;
+        let result = ma_decoder_read_pcm_frames(
+            &mut decoder,
+            &mut pcm_f32[0] as *mut f32 as *mut std::ffi::c_void,
+            pcm_frame,
+            &mut frames_read,
+        );
+
+        if result != MA_SUCCESS {
+            ma_decoder_uninit(&mut decoder);
+            return Err(AudioReaderError::InitializationError(result));
+        }
 
-            let mut pcm_f32: Vec<f32> =
-                vec![0.0; (pcm_frame * decoder.outputChannels as u64) as usize];
-            let mut frames_read: u64 = 0;
-            let result = ma_decoder_read_pcm_frames(
-                &mut decoder,
-                &mut pcm_f32[0] as *mut f32 as *mut std::ffi::c_void,
-                pcm_frame,
-                &mut frames_read,
-            );
+        let buffer = AudioCache {
+            buffer: pcm_f32.into(),
+            channel_count: decoder.outputChannels as usize,
+            sample_rate: decoder.outputSampleRate as f32,
+            length_in_frames: pcm_frame as usize,
+        };
 
-            if result != MA_SUCCESS {
-                ma_decoder_uninit(&mut decoder);
-                return Err(AudioReaderError::InitializationError(result));
-            }
+        ma_decoder_uninit(&mut decoder);
 
-            let buffer = AudioCache {
-                buffer: pcm_f32,
-                channel_count: decoder.outputChannels as usize,
-                sample_rate: decoder.outputSampleRate as f32,
-                length_in_frames: pcm_frame as usize,
-            };
+        let arc_cache = Arc::new(buffer);
+        cache.insert(
+            path.to_string(),
+            Handle {
+                buffer: Arc::clone(&arc_cache),
+                lifetime: 1,
+                last_used: tick(),
+            },
+        );
 
-            ma_decoder_uninit(&mut decoder);
+        Ok(arc_cache)
+    }
+}
 
-            let arc_cache = Arc::new(buffer);
-            cache.insert(
-                path.to_string(),
-                Handle {
-                    buffer: Arc::clone(&arc_cache),
-                    lifetime: 1,
-                },
-            );
+/// Like [load_file_cache] but a 32-bit float WAV file is mapped into memory instead of read,
+/// other files are decoded as usual.
+#[cfg(feature = "mmap")]
+pub fn load_mapped_cache(path: &str) -> Result<Arc<AudioCache>, AudioReaderError> {
+    if path.is_empty() {
+        return Err(AudioReaderError::InvalidParameter);
+    }
 
-            return Ok(arc_cache);
+    {
+        let mut cache = AUDIO_READER_CACHE.lock().unwrap();
+
+        if let Some(data) = cache.get_mut(path) {
+            data.lifetime += 1;
+            data.last_used = tick();
+            return Ok(data.buffer.clone());
         }
     }
+
+    // Transformed files have to be read onto the heap
+    if super::transform::asset_transform().is_some() {
+        return load_file_cache(path);
+    }
+
+    let Some((mapped, format)) = super::mapped::map_wav(path)? else {
+        return load_file_cache(path);
+    };
+
+    let audio_cache = Arc::new(AudioCache {
+        buffer: CacheBuffer::Mapped(mapped),
+        channel_count: format.channels,
+        sample_rate: format.sample_rate,
+        length_in_frames: format.frames,
+    });
+
+    let mut cache = AUDIO_READER_CACHE.lock().unwrap();
+
+    // Loaded by another thread while this one was mapping
+    if let Some(data) = cache.get_mut(path) {
+        data.lifetime += 1;
+        data.last_used = tick();
+        return Ok(data.buffer.clone());
+    }
+
+    cache.insert(
+        path.to_string(),
+        Handle {
+            buffer: Arc::clone(&audio_cache),
+            lifetime: 1,
+            last_used: tick(),
+        },
+    );
+
+    Ok(audio_cache)
 }
 
 pub fn load_buffer_cache(buffer: &[u8]) -> Result<Arc<AudioCache>, AudioReaderError> {
@@ -162,91 +316,118 @@ pub fn load_buffer_cache(buffer: &[u8]) -> Result<Arc<AudioCache>, AudioReaderEr
 
     if let Some(data) = cache.get_mut(&key) {
         data.lifetime += 1;
+        data.last_used = tick();
         return Ok(data.buffer.clone());
     }
 
-    if ogg::is_ogg_buffer(buffer) {
-        match ogg::read_ogg_data_buffer(buffer) {
-            Ok(buffer) => {
-                let audio_cache = AudioCache {
-                    buffer: buffer.pcm_f32,
-                    channel_count: buffer.channels as usize,
-                    sample_rate: buffer.sample_rate,
-                    length_in_frames: buffer.pcm_length as usize,
-                };
-
-                let arc_cache = Arc::new(audio_cache);
-                cache.insert(
-                    key.clone(),
-                    Handle {
-                        buffer: Arc::clone(&arc_cache),
-                        lifetime: 1,
-                    },
-                );
+    let arc_cache = Arc::new(decode_buffer(buffer)?);
+    cache.insert(
+        key,
+        Handle {
+            buffer: Arc::clone(&arc_cache),
+            lifetime: 1,
+            last_used: tick(),
+        },
+    );
+
+    Ok(arc_cache)
+}
 
-                return Ok(arc_cache);
-            }
-            Err(e) => {
-                return Err(AudioReaderError::from_other(e));
-            }
-        }
-    } else {
-        unsafe {
-            let decoder_config = ma_decoder_config_init(ma_format_f32, 0, 0);
-            let mut decoder: ma_decoder = std::mem::zeroed();
-            let result = ma_decoder_init_memory(
-                buffer.as_ptr() as *const std::ffi::c_void,
-                buffer.len(),
-                &decoder_config,
-                &mut decoder as *mut ma_decoder,
-            );
+/// Like [load_file_cache] but the whole file is read before it is decoded, going through the
+/// registered [asset transform](super::transform::set_asset_transform) when there is one.
+fn load_read_cache(
+    path: &str,
+    transform: Option<super::transform::AssetTransform>,
+) -> Result<Arc<AudioCache>, AudioReaderError> {
+    let mut data = std::fs::read(path).map_err(|_| AudioReaderError::FileNotFound(path.to_string()))?;
+    if let Some(transform) = transform {
+        transform(path, &mut data).map_err(AudioReaderError::from_other)?;
+    }
 
-            if result != MA_SUCCESS {
-                return Err(AudioReaderError::InitializationError(result));
-            }
+    let audio_cache = Arc::new(decode_buffer(&data)?);
+    let mut cache = AUDIO_READER_CACHE.lock().unwrap();
 
-            let mut pcm_frame = 0;
-            let result = ma_decoder_get_length_in_pcm_frames(&mut decoder, &mut pcm_frame);
-            if result != MA_SUCCESS {
-                ma_decoder_uninit(&mut decoder);
-                return Err(AudioReaderError::InitializationError(result));
-            }
+    // Loaded by another thread while this one was decoding
+    if let Some(data) = cache.get_mut(path) {
+        data.lifetime += 1;
+        data.last_used = tick();
+        return Ok(data.buffer.clone());
+    }
 
-            let mut pcm_f32: Vec<f32> =
-                vec![0.0; (pcm_frame * decoder.outputChannels as u64) as usize];
-            let mut frames_read: u64 = 0;
-            let result = ma_decoder_read_pcm_frames(
-                &mut decoder,
-                &mut pcm_f32[0] as *mut f32 as *mut std::ffi::c_void,
-                pcm_frame,
-                &mut frames_read,
-            );
+    cache.insert(
+        path.to_string(),
+        Handle {
+            buffer: Arc::clone(&audio_cache),
+            lifetime: 1,
+            last_used: tick(),
+        },
+    );
 
-            if result != MA_SUCCESS {
-                ma_decoder_uninit(&mut decoder);
-                return Err(AudioReaderError::InitializationError(result));
-            }
+    Ok(audio_cache)
+}
 
-            let buffer = AudioCache {
-                buffer: pcm_f32,
-                channel_count: decoder.outputChannels as usize,
-                sample_rate: decoder.outputSampleRate as f32,
-                length_in_frames: pcm_frame as usize,
-            };
+/// Decode an encoded audio file held in memory.
+fn decode_buffer(buffer: &[u8]) -> Result<AudioCache, AudioReaderError> {
+    if let Some(decoder) = decoder::find(buffer) {
+        let decoded = decoder.decode(buffer).map_err(AudioReaderError::from_other)?;
+        if decoded.channels == 0 {
+            return Err(AudioReaderError::InvalidParameter);
+        }
 
-            ma_decoder_uninit(&mut decoder);
+        return Ok(AudioCache {
+            length_in_frames: decoded.data.len() / decoded.channels,
+            buffer: decoded.data.into(),
+            channel_count: decoded.channels,
+            sample_rate: decoded.sample_rate,
+        });
+    }
 
-            let arc_cache = Arc::new(buffer);
-            cache.insert(
-                key.clone(),
-                Handle {
-                    buffer: Arc::clone(&arc_cache),
-                    lifetime: 1,
-                },
-            );
+    unsafe {
+        let decoder_config = ma_decoder_config_init(ma_format_f32, 0, 0);
+        let mut decoder: ma_decoder = std::mem::zeroed();
+        let result = ma_decoder_init_memory(
+            buffer.as_ptr() as *const std::ffi::c_void,
+            buffer.len(),
+            &decoder_config,
+            &mut decoder as *mut ma_decoder,
+        );
+
+        if result != MA_SUCCESS {
+            return Err(AudioReaderError::InitializationError(result));
+        }
 
-            return Ok(arc_cache);
+        let mut pcm_frame = 0;
+        let result = ma_decoder_get_length_in_pcm_frames(&mut decoder, &mut pcm_frame);
+        if result != MA_SUCCESS {
+            ma_decoder_uninit(&mut decoder);
+            return Err(AudioReaderError::InitializationError(result));
         }
+
+        let mut pcm_f32: Vec<f32> =
+            vec![0.0; (pcm_frame * decoder.outputChannels as u64) as usize];
+        let mut frames_read: u64 = 0;
+        let result = ma_decoder_read_pcm_frames(
+            &mut decoder,
+            &mut pcm_f32[0] as *mut f32 as *mut std::ffi::c_void,
+            pcm_frame,
+            &mut frames_read,
+        );
+
+        if result != MA_SUCCESS {
+            ma_decoder_uninit(&mut decoder);
+            return Err(AudioReaderError::InitializationError(result));
+        }
+
+        let buffer = AudioCache {
+            buffer: pcm_f32.into(),
+            channel_count: decoder.outputChannels as usize,
+            sample_rate: decoder.outputSampleRate as f32,
+            length_in_frames: pcm_frame as usize,
+        };
+
+        ma_decoder_uninit(&mut decoder);
+
+        Ok(buffer)
     }
 }
 
@@ -259,6 +440,7 @@ pub fn increment_cache(cache: &Arc<AudioCache>) {
         .find(|(_, v)| Arc::ptr_eq(&v.buffer, &cache))
     {
         value.lifetime += 1;
+        value.last_used = tick();
     }
 }
 
@@ -271,9 +453,9 @@ pub fn return_file_cache(buf: Arc<AudioCache>) {
         .find(|(_, v)| Arc::ptr_eq(&v.buffer, &buf))
         .map(|(k, _)| k.clone());
 
-    // Then, mutate or remove the entry
+    // Then, release the entry and trim the unused ones to the budget
     if let Some(key) = key {
-        let remove_entry = {
+        let idle = {
             let data = cache.get_mut(&key).unwrap();
             if data.lifetime > 0 {
                 data.lifetime -= 1;
@@ -281,8 +463,8 @@ pub fn return_file_cache(buf: Arc<AudioCache>) {
             data.lifetime == 0
         };
 
-        if remove_entry {
-            cache.remove(&key);
+        if idle {
+            trim_cache(&mut cache);
         }
     }
 }
@@ -0,0 +1,88 @@
+use std::{
+    io::Read,
+    sync::{Arc, RwLock},
+};
+
+use once_cell::sync::Lazy;
+
+use crate::BufferInfoOwned;
+
+// Bytes from the start of a file a decoder is probed with
+const PROBE_LEN: usize = 64;
+
+/// Decodes a file format miniaudio does not, such as Ogg Vorbis with the `vorbis` feature.
+/// Registered decoders are probed in turn before a file is left to miniaudio, which reads
+/// WAV, FLAC and MP3, see [register_decoder](crate::register_decoder).
+pub trait AudioDecoder: Send + Sync {
+    /// Unique name, registering a decoder with the name of another replaces it.
+    fn name(&self) -> &str;
+
+    /// Whether the file starting with `header` is one this decoder reads. `header` holds the
+    /// first 64 bytes, fewer for a shorter file.
+    fn probe(&self, header: &[u8]) -> bool;
+
+    /// Decode a whole file to interleaved f32.
+    fn decode(&self, data: &[u8]) -> std::io::Result<BufferInfoOwned>;
+}
+
+static DECODERS: Lazy<RwLock<Vec<Arc<dyn AudioDecoder>>>> =
+    Lazy::new(|| RwLock::new(builtin_decoders()));
+
+#[allow(unused_mut)]
+fn builtin_decoders() -> Vec<Arc<dyn AudioDecoder>> {
+    let mut decoders: Vec<Arc<dyn AudioDecoder>> = Vec::new();
+
+    #[cfg(feature = "vorbis")]
+    decoders.push(Arc::new(super::ogg::VorbisDecoder));
+    #[cfg(feature = "opus")]
+    decoders.push(Arc::new(super::ogg::OpusDecoder));
+
+    decoders
+}
+
+/// Add `decoder` ahead of the registered ones, replacing the one of the same name.
+pub fn register_decoder(decoder: Arc<dyn AudioDecoder>) {
+    if let Ok(mut decoders) = DECODERS.write() {
+        decoders.retain(|other| other.name() != decoder.name());
+        decoders.insert(0, decoder);
+    }
+}
+
+pub fn unregister_decoder(name: &str) -> bool {
+    let Ok(mut decoders) = DECODERS.write() else {
+        return false;
+    };
+
+    let count = decoders.len();
+    decoders.retain(|decoder| decoder.name() != name);
+    decoders.len() != count
+}
+
+pub fn decoder_names() -> Vec<String> {
+    DECODERS
+        .read()
+        .map(|decoders| decoders.iter().map(|decoder| decoder.name().to_string()).collect())
+        .unwrap_or_default()
+}
+
+/// The registered decoder reading the file starting with `header`, None leaves the file to
+/// miniaudio.
+pub fn find(header: &[u8]) -> Option<Arc<dyn AudioDecoder>> {
+    let header = &header[..header.len().min(PROBE_LEN)];
+
+    DECODERS
+        .read()
+        .ok()?
+        .iter()
+        .find(|decoder| decoder.probe(header))
+        .cloned()
+}
+
+pub fn find_for_file(path: &str) -> Option<Arc<dyn AudioDecoder>> {
+    let file = std::fs::File::open(path).ok()?;
+
+    let mut header = Vec::with_capacity(PROBE_LEN);
+    file.take(PROBE_LEN as u64).read_to_end(&mut header).ok()?;
+
+    find(&header)
+}
@@ -0,0 +1,319 @@
+use std::{
+    sync::{
+        Arc, Condvar, Mutex,
+        atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+    },
+    time::Duration,
+};
+
+use super::{
+    AudioReaderError,
+    stream::{FileDecoder, PcmStream, StreamFormat, StreamRead, StreamStatus},
+};
+
+// Frames in each of the two buffers
+const BUFFER_FRAMES: usize = 16384;
+
+// How often the refill thread checks for requests it was not woken for
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+// Published in Shared::playing while the audio thread plays neither buffer
+const NO_HALF: usize = usize::MAX;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum HalfState {
+    /// Waiting for the refill thread to decode from `start`.
+    Requested,
+    Ready,
+}
+
+struct Half {
+    start: usize,
+    frames: usize,
+    // Fewer frames were decoded than asked, the file ends here
+    end: bool,
+    state: HalfState,
+    data: Vec<f32>,
+}
+
+impl Half {
+    fn contains(&self, position: usize) -> bool {
+        position >= self.start && position < self.start + BUFFER_FRAMES
+    }
+
+    fn request(&mut self, start: usize) {
+        self.start = start;
+        self.frames = 0;
+        self.end = false;
+        self.state = HalfState::Requested;
+    }
+
+    fn fill(&mut self, decoder: &mut FileDecoder) {
+        self.frames = decoder.read_at(self.start, &mut self.data).unwrap_or(0);
+        self.end = self.frames < BUFFER_FRAMES;
+        self.state = HalfState::Ready;
+    }
+}
+
+struct Shared {
+    halves: [Mutex<Half>; 2],
+    // Index of the buffer the audio thread plays, never locked by the refill thread
+    playing: AtomicUsize,
+    // Set with a buffer requested, so the refill thread only locks buffers it has to decode
+    pending: [AtomicBool; 2],
+    signal: Mutex<()>,
+    wake: Condvar,
+    stop: AtomicBool,
+    buffered: AtomicUsize,
+    underruns: AtomicU64,
+}
+
+/// A file streamed from disk through two ping-pong buffers, the audio thread plays one while
+/// a refill thread decodes the next part of the file into the other.
+///
+/// Unlike [PrefetchStream](super::prefetch::PrefetchStream) the memory held never grows past
+/// the two buffers, whatever the length of the file.
+pub(crate) struct DoubleBufferStream {
+    shared: Arc<Shared>,
+    format: StreamFormat,
+}
+
+impl DoubleBufferStream {
+    /// Open the file at `path`, both buffers are filled before this returns.
+    pub fn open(path: &str) -> Result<Self, AudioReaderError> {
+        let mut decoder = FileDecoder::open(path)?;
+        let format = decoder.format();
+
+        let mut halves = [0, BUFFER_FRAMES].map(|start| Half {
+            start,
+            frames: 0,
+            end: false,
+            state: HalfState::Requested,
+            data: vec![0.0; BUFFER_FRAMES * format.channels],
+        });
+
+        for half in halves.iter_mut() {
+            half.fill(&mut decoder);
+        }
+
+        let shared = Arc::new(Shared {
+            halves: halves.map(Mutex::new),
+            playing: AtomicUsize::new(0),
+            pending: [AtomicBool::new(false), AtomicBool::new(false)],
+            signal: Mutex::new(()),
+            wake: Condvar::new(),
+            stop: AtomicBool::new(false),
+            buffered: AtomicUsize::new(0),
+            underruns: AtomicU64::new(0),
+        });
+
+        let thread_shared = shared.clone();
+
+        std::thread::Builder::new()
+            .name("estaudio-stream".to_string())
+            .spawn(move || refill_loop(thread_shared, decoder))
+            .map_err(AudioReaderError::from_other)?;
+
+        Ok(Self { shared, format })
+    }
+
+    pub fn format(&self) -> StreamFormat {
+        self.format
+    }
+
+    /// Ask the refill thread to decode the buffer at `index` from `start`, the buffer has to
+    /// be locked by the caller.
+    fn request(&self, index: usize, half: &mut Half, start: usize) {
+        half.request(start);
+        self.shared.pending[index].store(true, Ordering::Release);
+    }
+
+    /// Restart both buffers from `position`, for a cursor neither of them covers.
+    fn restart(&self, position: usize) {
+        self.shared.playing.store(NO_HALF, Ordering::Release);
+
+        for (index, half) in self.shared.halves.iter().enumerate() {
+            if let Ok(mut half) = half.try_lock() {
+                self.request(index, &mut half, position + index * BUFFER_FRAMES);
+            }
+        }
+
+        self.wake();
+    }
+
+    fn wake(&self) {
+        // Taken so the notification cannot land between the pending check of the refill
+        // thread and its wait, the thread only holds it for that check
+        let _signal = self.shared.signal.lock();
+        self.shared.wake.notify_one();
+    }
+}
+
+fn refill_loop(shared: Arc<Shared>, mut decoder: FileDecoder) {
+    while !shared.stop.load(Ordering::Acquire) {
+        for (index, half) in shared.halves.iter().enumerate() {
+            // The buffer being played is left alone, so the audio thread never misses it
+            if shared.playing.load(Ordering::Acquire) == index
+                || !shared.pending[index].swap(false, Ordering::AcqRel)
+            {
+                continue;
+            }
+
+            let Ok(mut half) = half.lock() else {
+                return;
+            };
+
+            if half.state == HalfState::Requested {
+                half.fill(&mut decoder);
+            }
+        }
+
+        let Ok(signal) = shared.signal.lock() else {
+            return;
+        };
+
+        let pending = shared.pending.iter().any(|pending| pending.load(Ordering::Acquire));
+        if !pending && !shared.stop.load(Ordering::Acquire) {
+            _ = shared.wake.wait_timeout(signal, POLL_INTERVAL);
+        }
+    }
+}
+
+impl PcmStream for DoubleBufferStream {
+    fn read(
+        &mut self,
+        position: usize,
+        output: &mut [f32],
+    ) -> Result<StreamRead, AudioReaderError> {
+        let channels = self.format.channels;
+        if position >= self.format.frames {
+            return Ok(StreamRead::played(0));
+        }
+
+        let frames = (output.len() / channels).min(self.format.frames - position);
+        let output = &mut output[..frames * channels];
+
+        let mut copied = 0;
+        let mut ended = false;
+        let mut covered = false;
+
+        while copied < frames {
+            let frame = position + copied;
+            let mut progressed = false;
+            covered = false;
+
+            for (index, half) in self.shared.halves.iter().enumerate() {
+                // The refill thread holds it, so it cannot be the buffer being played
+                let Ok(mut half) = half.try_lock() else {
+                    covered = true;
+                    continue;
+                };
+
+                if !half.contains(frame) {
+                    continue;
+                }
+
+                covered = true;
+                if half.state != HalfState::Ready {
+                    break;
+                }
+
+                self.shared.playing.store(index, Ordering::Release);
+
+                let offset = frame - half.start;
+                if offset >= half.frames {
+                    ended = half.end;
+                    break;
+                }
+
+                let count = (half.frames - offset).min(frames - copied);
+                output[copied * channels..(copied + count) * channels].copy_from_slice(
+                    &half.data[offset * channels..(offset + count) * channels],
+                );
+                copied += count;
+                progressed = true;
+
+                // Played through, refill it with the part after the other buffer
+                if offset + count == half.frames && !half.end {
+                    let start = half.start + 2 * BUFFER_FRAMES;
+                    self.shared.playing.store(1 - index, Ordering::Release);
+                    self.request(index, &mut half, start);
+                    drop(half);
+
+                    self.wake();
+                }
+
+                break;
+            }
+
+            if !progressed {
+                break;
+            }
+        }
+
+        let advanced = copied;
+
+        if copied < frames && !ended {
+            // Seeked away from both buffers, restart them from the cursor
+            if !covered {
+                self.restart(position + copied);
+            }
+
+            // Played as silence, the cursor stays where the decoded frames ran out
+            output[copied * channels..].fill(0.0);
+            self.shared.underruns.fetch_add(1, Ordering::Relaxed);
+            copied = frames;
+        }
+
+        let cursor = position + advanced;
+        let buffered = self
+            .shared
+            .halves
+            .iter()
+            .filter_map(|half| half.try_lock().ok())
+            .filter(|half| half.state == HalfState::Ready)
+            .map(|half| (half.start + half.frames).saturating_sub(cursor.max(half.start)))
+            .sum();
+        self.shared.buffered.store(buffered, Ordering::Relaxed);
+
+        Ok(StreamRead {
+            frames: copied,
+            advanced,
+        })
+    }
+
+    fn seek(&mut self, position: usize) {
+        let covered = self
+            .shared
+            .halves
+            .iter()
+            .any(|half| match half.try_lock() {
+                Ok(half) => half.contains(position),
+                // Being refilled, possibly for this position
+                Err(_) => true,
+            });
+
+        if !covered {
+            self.restart(position);
+        }
+    }
+
+    fn buffered_bytes(&self) -> usize {
+        2 * BUFFER_FRAMES * self.format.channels * std::mem::size_of::<f32>()
+    }
+
+    fn status(&self) -> Option<StreamStatus> {
+        Some(StreamStatus {
+            buffered_frames: self.shared.buffered.load(Ordering::Relaxed),
+            underruns: self.shared.underruns.load(Ordering::Relaxed),
+        })
+    }
+}
+
+impl Drop for DoubleBufferStream {
+    fn drop(&mut self) {
+        // Not joined, the stream may be dropped on the audio thread
+        self.shared.stop.store(true, Ordering::Release);
+        self.wake();
+    }
+}
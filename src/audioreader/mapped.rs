@@ -0,0 +1,123 @@
+use std::fs::File;
+
+use memmap2::Mmap;
+
+use super::AudioReaderError;
+
+/// WAVE_FORMAT_IEEE_FLOAT
+const FORMAT_FLOAT: u16 = 3;
+/// WAVE_FORMAT_EXTENSIBLE, the real format is the first two bytes of the sub format GUID
+const FORMAT_EXTENSIBLE: u16 = 0xFFFE;
+
+/// The sample data of a 32-bit float WAV file mapped into memory, the OS pages it in as it
+/// is read instead of it being copied onto the heap.
+#[derive(Debug)]
+pub struct MappedPcm {
+    map: Mmap,
+    offset: usize,
+    len: usize,
+}
+
+impl MappedPcm {
+    pub fn pcm(&self) -> &[f32] {
+        // Checked in map_wav, the offset is 4 byte aligned and the length fits
+        bytemuck::cast_slice(&self.map[self.offset..self.offset + self.len * 4])
+    }
+}
+
+/// Format of a mapped file.
+#[derive(Debug, Clone, Copy)]
+pub struct MappedFormat {
+    pub channels: usize,
+    pub sample_rate: f32,
+    pub frames: usize,
+}
+
+/// Map the WAV file at `path`, None when it is not a 32-bit float WAV whose samples can be
+/// read in place so the caller decodes it instead.
+pub fn map_wav(path: &str) -> Result<Option<(MappedPcm, MappedFormat)>, AudioReaderError> {
+    // The samples are read as they are stored, little endian
+    if cfg!(target_endian = "big") {
+        return Ok(None);
+    }
+
+    let file = File::open(path).map_err(|_| AudioReaderError::FileNotFound(path.to_string()))?;
+
+    // SAFETY: The mapping is read only, the file must not be truncated or rewritten while it
+    // is mapped which is the same contract as any other asset in use.
+    let map = unsafe { Mmap::map(&file) }.map_err(AudioReaderError::from_other)?;
+
+    let Some((offset, bytes, format)) = parse_wav(&map) else {
+        return Ok(None);
+    };
+
+    // Mappings start on a page boundary, so the samples are aligned when their offset is
+    if offset % 4 != 0 {
+        return Ok(None);
+    }
+
+    let len = bytes / 4 / format.channels * format.channels;
+    if len == 0 {
+        return Ok(None);
+    }
+
+    let format = MappedFormat {
+        frames: len / format.channels,
+        ..format
+    };
+
+    Ok(Some((MappedPcm { map, offset, len }, format)))
+}
+
+/// Offset and length in bytes of the data chunk and the format of a 32-bit float WAV.
+fn parse_wav(bytes: &[u8]) -> Option<(usize, usize, MappedFormat)> {
+    if bytes.len() < 12 || &bytes[0..4] != b"RIFF" || &bytes[8..12] != b"WAVE" {
+        return None;
+    }
+
+    let u16_at = |at: usize| Some(u16::from_le_bytes(bytes.get(at..at + 2)?.try_into().ok()?));
+    let u32_at = |at: usize| Some(u32::from_le_bytes(bytes.get(at..at + 4)?.try_into().ok()?));
+
+    let mut format = None;
+    let mut position = 12;
+
+    while position + 8 <= bytes.len() {
+        let id = &bytes[position..position + 4];
+        let size = u32_at(position + 4)? as usize;
+        let body = position + 8;
+
+        match id {
+            b"fmt " => {
+                let mut tag = u16_at(body)?;
+                if tag == FORMAT_EXTENSIBLE {
+                    tag = u16_at(body + 24)?;
+                }
+
+                let channels = u16_at(body + 2)? as usize;
+                let sample_rate = u32_at(body + 4)?;
+                let bits = u16_at(body + 14)?;
+
+                if tag != FORMAT_FLOAT || bits != 32 || channels == 0 || sample_rate == 0 {
+                    return None;
+                }
+
+                format = Some(MappedFormat {
+                    channels,
+                    sample_rate: sample_rate as f32,
+                    frames: 0,
+                });
+            }
+            b"data" => {
+                // Streamed writers leave the size unset, the data then runs to the end
+                let available = bytes.len() - body;
+                return Some((body, size.min(available), format?));
+            }
+            _ => {}
+        }
+
+        // Chunks are padded to an even length
+        position = body.checked_add(size)?.checked_add(size % 2)?;
+    }
+
+    None
+}
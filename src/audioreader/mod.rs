@@ -3,15 +3,27 @@ use std::{os::raw::c_void, sync::Arc};
 use miniaudio_sys::*;
 use thiserror::Error;
 
-use crate::utils;
+use crate::{generators::AudioGenerator, misc::memoryusage::MemoryUsage, utils};
 
+pub(crate) mod archive;
 pub(crate) mod cache;
+pub(crate) mod decoder;
+pub(crate) mod doublebuffer;
+#[cfg(feature = "mmap")]
+pub(crate) mod mapped;
+#[cfg(any(feature = "vorbis", feature = "opus"))]
 pub(crate) mod ogg;
+pub(crate) mod prefetch;
+pub(crate) mod stream;
+pub(crate) mod transform;
 
-#[derive(Debug)]
 pub struct AudioReader {
     pub cache: Option<Arc<cache::AudioCache>>,
     pub audio_buffer: Option<Box<ma_audio_buffer>>,
+    // Procedural source used instead of the buffer, see [AudioReader::load_generator]
+    pub generator: Option<Box<dyn AudioGenerator>>,
+    // File decoded while it is read, see [AudioReader::load_stream]
+    pub stream: Option<Box<dyn stream::PcmStream>>,
 
     pub sample_rate: f32,
     pub channels: usize,
@@ -19,6 +31,21 @@ pub struct AudioReader {
     pub position: usize,
 }
 
+impl std::fmt::Debug for AudioReader {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AudioReader")
+            .field("cache", &self.cache)
+            .field("audio_buffer", &self.audio_buffer)
+            .field("generator", &self.generator.is_some())
+            .field("stream", &self.stream.is_some())
+            .field("sample_rate", &self.sample_rate)
+            .field("channels", &self.channels)
+            .field("pcm_length", &self.pcm_length)
+            .field("position", &self.position)
+            .finish()
+    }
+}
+
 impl Clone for AudioReader {
     fn clone(&self) -> Self {
         let cache_cloned = self.cache.clone();
@@ -28,9 +55,12 @@ impl Clone for AudioReader {
             cache::increment_cache(cache);
         }
 
+        // A generator or stream holds state of its own and cannot be shared
         Self {
             cache: cache_cloned,
             audio_buffer: buffer_cloned,
+            generator: None,
+            stream: None,
             sample_rate: self.sample_rate,
             channels: self.channels,
             pcm_length: self.pcm_length,
@@ -76,6 +106,8 @@ impl AudioReader {
             Ok(Self {
                 cache: None,
                 audio_buffer: Some(audio_buffer),
+                generator: None,
+                stream: None,
                 sample_rate,
                 channels: channels as usize,
                 pcm_length: pcm_length as usize,
@@ -85,16 +117,31 @@ impl AudioReader {
     }
 
     pub fn load_cache(cache: Arc<cache::AudioCache>) -> Result<Self, AudioReaderError> {
+        let pcm_length = cache.length_in_frames;
+        Self::load_cache_range(cache, 0, pcm_length)
+    }
+
+    /// Read `pcm_length` frames of the cache from frame `offset`, see [Sample::slice](crate::Sample::slice).
+    pub fn load_cache_range(
+        cache: Arc<cache::AudioCache>,
+        offset: usize,
+        pcm_length: usize,
+    ) -> Result<Self, AudioReaderError> {
+        if pcm_length == 0 || offset + pcm_length > cache.length_in_frames {
+            return Err(AudioReaderError::InvalidPCMLength);
+        }
+
         cache::increment_cache(&cache);
 
         let sample_rate = cache.sample_rate;
         let channels = cache.channel_count;
-        let pcm_length = cache.length_in_frames;
-        let audio_buffer = cache.create_ma_buffer();
+        let audio_buffer = cache.create_ma_buffer_range(offset, pcm_length);
 
         Ok(Self {
             cache: Some(cache),
             audio_buffer: Some(audio_buffer),
+            generator: None,
+            stream: None,
             sample_rate,
             channels,
             pcm_length,
@@ -102,12 +149,147 @@ impl AudioReader {
         })
     }
 
+    /// Read from a procedural source, the length is endless so the reader never runs out.
+    pub fn load_generator(generator: Box<dyn AudioGenerator>) -> Result<Self, AudioReaderError> {
+        let sample_rate = generator.sample_rate();
+        let channels = generator.channels();
+
+        if channels == 0 || !sample_rate.is_finite() || sample_rate <= 0.0 {
+            return Err(AudioReaderError::InvalidParameter);
+        }
+
+        Ok(Self {
+            cache: None,
+            audio_buffer: None,
+            generator: Some(generator),
+            stream: None,
+            sample_rate,
+            channels,
+            pcm_length: usize::MAX,
+            position: 0,
+        })
+    }
+
+    /// Decode the file of `source` a chunk at a time as it is read instead of up front.
+    pub(crate) fn load_stream(source: &stream::StreamSource) -> Result<Self, AudioReaderError> {
+        Ok(Self::with_stream(source.open()?, source.format))
+    }
+
+    /// Stream the file at `path` from disk through two buffers refilled off the audio thread.
+    pub(crate) fn load_double_buffered(path: &str) -> Result<Self, AudioReaderError> {
+        let stream = doublebuffer::DoubleBufferStream::open(path)?;
+        let format = stream.format();
+
+        Ok(Self::with_stream(Box::new(stream), format))
+    }
+
+    fn with_stream(stream: Box<dyn stream::PcmStream>, format: stream::StreamFormat) -> Self {
+        Self {
+            cache: None,
+            audio_buffer: None,
+            generator: None,
+            stream: Some(stream),
+            sample_rate: format.sample_rate,
+            channels: format.channels,
+            pcm_length: format.frames,
+            position: 0,
+        }
+    }
+
+    /// A reader over the same decoded audio with a position of its own, None for generators
+    /// and streams which decode as they are read.
+    ///
+    /// A buffer not loaded through the cache is moved into an unlisted cache entry the first
+    /// time, so it is copied once and shared by every reader after.
+    pub(crate) fn share(&mut self) -> Result<Option<Self>, AudioReaderError> {
+        if self.generator.is_some() || self.stream.is_some() {
+            return Ok(None);
+        }
+
+        if self.cache.is_none() {
+            let Some(audio_buffer) = self.audio_buffer.as_ref() else {
+                return Ok(None);
+            };
+
+            let data = unsafe {
+                std::slice::from_raw_parts(
+                    audio_buffer.ref_.pData as *const f32,
+                    self.pcm_length * self.channels,
+                )
+            };
+
+            let cache = Arc::new(cache::AudioCache {
+                buffer: data.to_vec().into(),
+                channel_count: self.channels,
+                length_in_frames: self.pcm_length,
+                sample_rate: self.sample_rate,
+            });
+
+            let mut reader = Self::load_cache(cache)?;
+            reader.seek(self.position)?;
+            *self = reader;
+        }
+
+        let (Some(cache), Some(audio_buffer)) = (self.cache.as_ref(), self.audio_buffer.as_ref())
+        else {
+            return Ok(None);
+        };
+
+        // The reader may cover only part of the cache, see [AudioReader::load_cache_range]
+        let offset = unsafe {
+            (audio_buffer.ref_.pData as *const f32).offset_from(cache.buffer.as_ptr()) as usize
+        } / self.channels;
+
+        Self::load_cache_range(Arc::clone(cache), offset, self.pcm_length).map(Some)
+    }
+
+    /// Decoded audio held by the reader, a buffer borrowed from a [Sample](crate::Sample) is
+    /// counted by the sample.
+    pub fn memory_usage(&self) -> MemoryUsage {
+        if let Some(stream) = self.stream.as_ref() {
+            return MemoryUsage {
+                decoded: stream.buffered_bytes(),
+                ..Default::default()
+            };
+        }
+
+        if let Some(cache) = self.cache.as_ref() {
+            return MemoryUsage {
+                shared: cache.buffer.heap_bytes(),
+                ..Default::default()
+            };
+        }
+
+        match self.audio_buffer.as_ref() {
+            // Copied by ma_audio_buffer_init_copy
+            Some(buffer) if buffer.ownsData != 0 => MemoryUsage {
+                decoded: MemoryUsage::f32_bytes(self.pcm_length * self.channels),
+                ..Default::default()
+            },
+            _ => MemoryUsage::default(),
+        }
+    }
+
     pub fn read(&mut self, output: &mut [f32]) -> Result<usize, AudioReaderError> {
         let frame_count = output.len() / self.channels as usize;
         if frame_count == 0 {
             return Err(AudioReaderError::InvalidPCMLength);
         }
 
+        if let Some(generator) = self.generator.as_mut() {
+            generator.generate(&mut output[..frame_count * self.channels]);
+            self.position = self.position.saturating_add(frame_count);
+
+            return Ok(frame_count);
+        }
+
+        if let Some(stream) = self.stream.as_mut() {
+            let read = stream.read(self.position, &mut output[..frame_count * self.channels])?;
+            self.position += read.advanced;
+
+            return Ok(read.frames);
+        }
+
         let frames_readed;
         let result = unsafe {
             let Some(audio_buffer) = self.audio_buffer.as_mut() else {
@@ -141,6 +323,22 @@ impl AudioReader {
             return Ok(());
         }
 
+        if let Some(generator) = self.generator.as_mut() {
+            if position == 0 {
+                generator.reset();
+            }
+
+            self.position = position;
+            return Ok(());
+        }
+
+        // The stream decodes from wherever the next read starts
+        if let Some(stream) = self.stream.as_mut() {
+            stream.seek(position);
+            self.position = position;
+            return Ok(());
+        }
+
         let Some(audio_buffer) = self.audio_buffer.as_mut() else {
             return Err(AudioReaderError::InvalidOperation);
         };
@@ -1,8 +1,13 @@
-use std::io::{BufReader, Cursor, Read, Seek};
+use std::io::{Cursor, Read, Seek};
 
+#[cfg(feature = "vorbis")]
 use lewton::inside_ogg::OggStreamReader;
 use thiserror::Error;
 
+use crate::BufferInfoOwned;
+
+use super::decoder::AudioDecoder;
+
 #[derive(Debug, Clone, PartialEq, Eq, Error)]
 pub enum OggError {
     #[error("Invalid file format")]
@@ -13,117 +18,60 @@ pub enum OggError {
     ReadError(&'static str),
 }
 
-const OGG_HEADER: &[u8] = b"OggS";
-
-pub fn is_ogg(file_path: &str) -> bool {
-    if let Ok(mut file) = std::fs::File::open(file_path) {
-        let mut buffer = [0; 4];
-        if let Ok(_) = file.read_exact(&mut buffer) {
-            return &buffer == OGG_HEADER;
-        }
+impl From<OggError> for std::io::Error {
+    fn from(error: OggError) -> Self {
+        std::io::Error::new(std::io::ErrorKind::InvalidData, error)
     }
-
-    false
 }
 
-pub fn is_ogg_buffer(buffer: &[u8]) -> bool {
-    if buffer.len() < 4 {
-        return false;
-    }
-    &buffer[0..4] == OGG_HEADER
-}
+const OGG_HEADER: &[u8] = b"OggS";
 
-pub fn read_ogg_data_file(file_path: &str) -> Result<OggBuffer, OggError> {
-    if !is_ogg(file_path) {
-        return Err(OggError::InvalidFileFormat);
-    }
+/// Ogg Vorbis decoded with lewton.
+#[cfg(feature = "vorbis")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct VorbisDecoder;
 
-    let file = std::fs::File::open(file_path);
-    if let Err(_) = file {
-        return Err(OggError::ReadError("Failed to open OGG file"));
+#[cfg(feature = "vorbis")]
+impl AudioDecoder for VorbisDecoder {
+    fn name(&self) -> &str {
+        "vorbis"
     }
 
-    let file = file.unwrap();
-
-    let mut reader = BufReader::new(file);
-
-    let _type = get_ogg_type(&mut reader);
-    if let Err(e) = _type {
-        return Err(e);
+    fn probe(&self, header: &[u8]) -> bool {
+        ogg_type(header) == Some(OggType::Vorbis)
     }
 
-    let _type = _type.unwrap();
+    fn decode(&self, data: &[u8]) -> std::io::Result<BufferInfoOwned> {
+        let Ok(reader) = OggStreamReader::new(Cursor::new(data)) else {
+            return Err(OggError::ReadError("Failed to read OGG Vorbis data").into());
+        };
 
-    let err = reader.seek(std::io::SeekFrom::Start(0x0));
-
-    if err.is_err() {
-        return Err(OggError::ReadError("Failed to seek in OGG file"));
-    }
-
-    match _type {
-        Some(OggType::Opus) => {
-            return read_ogg_opus(reader);
-        }
-        Some(OggType::Vorbis) => {
-            let reader = OggStreamReader::new(reader);
-
-            if let Err(_) = reader {
-                return Err(OggError::ReadError("Failed to read OGG Vorbis data"));
-            }
-
-            return read_ogg_vorbis(reader.unwrap());
-        }
-        _ => {
-            return Err(OggError::UnknownFormat);
-        }
+        Ok(read_ogg_vorbis(reader))
     }
 }
 
-pub fn read_ogg_data_buffer(buffer: &[u8]) -> Result<OggBuffer, OggError> {
-    if !is_ogg_buffer(buffer) {
-        return Err(OggError::InvalidFileFormat);
-    }
+/// Ogg Opus decoded with ogg-opus, always at 48000 Hz.
+#[cfg(feature = "opus")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct OpusDecoder;
 
-    let mut reader = BufReader::new(Cursor::new(buffer));
-    let _type = get_ogg_type(&mut reader);
-    if let Err(e) = _type {
-        return Err(e);
+#[cfg(feature = "opus")]
+impl AudioDecoder for OpusDecoder {
+    fn name(&self) -> &str {
+        "opus"
     }
 
-    let _type = _type.unwrap();
-
-    let err = reader.seek(std::io::SeekFrom::Start(0x0));
-
-    if err.is_err() {
-        return Err(OggError::ReadError("Failed to seek in OGG file"));
+    fn probe(&self, header: &[u8]) -> bool {
+        ogg_type(header) == Some(OggType::Opus)
     }
 
-    match _type {
-        Some(OggType::Opus) => {
-            return read_ogg_opus(reader);
-        }
-        Some(OggType::Vorbis) => {
-            let reader = OggStreamReader::new(reader);
-            if let Err(_) = reader {
-                return Err(OggError::ReadError("Failed to read OGG Vorbis data"));
-            }
-
-            return read_ogg_vorbis(reader.unwrap());
-        }
-        _ => {
-            return Err(OggError::UnknownFormat);
-        }
+    fn decode(&self, data: &[u8]) -> std::io::Result<BufferInfoOwned> {
+        Ok(read_ogg_opus(Cursor::new(data))?)
     }
 }
 
-pub struct OggBuffer {
-    pub pcm_f32: Vec<f32>,
-    pub sample_rate: f32,
-    pub channels: u32,
-    pub pcm_length: usize,
-}
-
-fn read_ogg_vorbis<T: Read + Seek>(mut reader: OggStreamReader<T>) -> Result<OggBuffer, OggError> {
+#[cfg(feature = "vorbis")]
+fn read_ogg_vorbis<T: Read + Seek>(mut reader: OggStreamReader<T>) -> BufferInfoOwned {
     let mut pcm_f32 = Vec::new();
 
     while let Ok(Some(packet)) = reader.read_dec_packet_itl() {
@@ -131,19 +79,15 @@ fn read_ogg_vorbis<T: Read + Seek>(mut reader: OggStreamReader<T>) -> Result<Ogg
         pcm_f32.extend(converted);
     }
 
-    let sample_rate = reader.ident_hdr.audio_sample_rate as f32;
-    let channels = reader.ident_hdr.audio_channels as u32;
-    let pcm_length = pcm_f32.len() / channels as usize;
-
-    return Ok(OggBuffer {
-        pcm_f32,
-        sample_rate,
-        channels,
-        pcm_length,
-    });
+    BufferInfoOwned {
+        data: pcm_f32,
+        channels: reader.ident_hdr.audio_channels as usize,
+        sample_rate: reader.ident_hdr.audio_sample_rate as f32,
+    }
 }
 
-fn read_ogg_opus<T: Seek + Read>(data: T) -> Result<OggBuffer, OggError> {
+#[cfg(feature = "opus")]
+fn read_ogg_opus<T: Seek + Read>(data: T) -> Result<BufferInfoOwned, OggError> {
     let decoded = ogg_opus::decode::<T, 48000>(data);
     if let Err(_) = decoded {
         return Err(OggError::ReadError("Failed to decode OGG Opus data"));
@@ -157,14 +101,11 @@ fn read_ogg_opus<T: Seek + Read>(data: T) -> Result<OggBuffer, OggError> {
     }
 
     const SAMPLE_RATE_OPUS: f32 = 48000.0;
-    let channel = decoded.1.channels;
-    let pcm_length = pcm_f32.len() / channel as usize;
 
-    return Ok(OggBuffer {
-        pcm_f32,
+    return Ok(BufferInfoOwned {
+        data: pcm_f32,
+        channels: decoded.1.channels as usize,
         sample_rate: SAMPLE_RATE_OPUS,
-        channels: channel as u32,
-        pcm_length,
     });
 }
 
@@ -175,6 +116,15 @@ pub enum OggType {
     Opus,
 }
 
+/// The codec of the Ogg file starting with `header`, None when it is not an Ogg file.
+pub fn ogg_type(header: &[u8]) -> Option<OggType> {
+    if !header.starts_with(OGG_HEADER) {
+        return None;
+    }
+
+    get_ogg_type(&mut Cursor::new(header)).ok().flatten()
+}
+
 pub fn get_ogg_type<T: Read + Seek>(reader: &mut T) -> Result<Option<OggType>, OggError> {
     // check header
     let mut header = [0; 4];
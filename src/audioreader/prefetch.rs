@@ -0,0 +1,273 @@
+use std::{
+    collections::VecDeque,
+    sync::{
+        Arc, Condvar, Mutex,
+        atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+    },
+    time::Duration,
+};
+
+use super::{
+    AudioReaderError,
+    stream::{FileDecoder, PcmStream, StreamRead, StreamStatus},
+};
+
+// Frames the decoder thread decodes between checks of the play cursor
+const DECODE_BLOCK: usize = 4096;
+
+// How often the decoder thread checks for a stop while the prebuffer is full
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+struct PrefetchState {
+    queue: VecDeque<f32>,
+    // Frame of the first sample in the queue
+    start: usize,
+    // Set by the audio thread when it reads outside the queue, the decoder restarts there
+    seek: Option<usize>,
+    // The decoder ran out of frames, possibly before the length it reported
+    end: bool,
+}
+
+struct Shared {
+    state: Mutex<PrefetchState>,
+    // Wakes the decoder thread after a read or seek
+    wake: Condvar,
+    // Wakes the opening thread once the first watermark is reached
+    filled: Condvar,
+    stop: AtomicBool,
+    buffered: AtomicUsize,
+    underruns: AtomicU64,
+}
+
+/// A stream decoded by a thread of its own, which keeps up to `prebuffer` frames decoded
+/// ahead of the play cursor and tops them up once fewer than `watermark` are left.
+///
+/// The audio thread never waits on the decoder, when the frames it asks for are not decoded
+/// yet it plays silence instead and counts an underrun.
+pub(crate) struct PrefetchStream {
+    shared: Arc<Shared>,
+    channels: usize,
+    frames: usize,
+    capacity: usize,
+}
+
+impl PrefetchStream {
+    /// Start decoding `decoder` on a new thread, returns once `watermark` frames are decoded.
+    pub fn spawn(decoder: FileDecoder, prebuffer: usize, watermark: usize) -> Result<Self, AudioReaderError> {
+        let format = decoder.format();
+        let channels = format.channels;
+        let prebuffer = prebuffer.max(DECODE_BLOCK);
+        let watermark = watermark.min(prebuffer);
+
+        // Topped up a block at a time, so the queue never holds more than this
+        let capacity = (prebuffer + DECODE_BLOCK) * channels;
+
+        let shared = Arc::new(Shared {
+            state: Mutex::new(PrefetchState {
+                queue: VecDeque::with_capacity(capacity),
+                start: 0,
+                seek: None,
+                end: false,
+            }),
+            wake: Condvar::new(),
+            filled: Condvar::new(),
+            stop: AtomicBool::new(false),
+            buffered: AtomicUsize::new(0),
+            underruns: AtomicU64::new(0),
+        });
+
+        let thread_shared = shared.clone();
+
+        std::thread::Builder::new()
+            .name("estaudio-stream".to_string())
+            .spawn(move || decode_loop(thread_shared, decoder, prebuffer, watermark))
+            .map_err(AudioReaderError::from_other)?;
+
+        {
+            let Ok(mut state) = shared.state.lock() else {
+                return Err(AudioReaderError::InvalidOperation);
+            };
+
+            while state.queue.len() / channels < watermark && !state.end {
+                let Ok(next) = shared.filled.wait(state) else {
+                    return Err(AudioReaderError::InvalidOperation);
+                };
+
+                state = next;
+            }
+        }
+
+        Ok(Self {
+            shared,
+            channels,
+            frames: format.frames,
+            capacity,
+        })
+    }
+}
+
+fn decode_loop(shared: Arc<Shared>, mut decoder: FileDecoder, prebuffer: usize, watermark: usize) {
+    let channels = decoder.format().channels;
+    let mut block = vec![0.0; DECODE_BLOCK * channels];
+    let mut position = 0;
+    let mut filling = true;
+
+    while !shared.stop.load(Ordering::Acquire) {
+        {
+            let Ok(mut state) = shared.state.lock() else {
+                return;
+            };
+
+            if let Some(seek) = state.seek.take() {
+                state.queue.clear();
+                state.start = seek;
+                state.end = false;
+                position = seek;
+                filling = true;
+            }
+
+            let buffered = state.queue.len() / channels;
+            if buffered < watermark {
+                filling = true;
+            }
+
+            if buffered >= prebuffer || state.end {
+                filling = false;
+                shared.filled.notify_all();
+            }
+
+            if !filling {
+                _ = shared.wake.wait_timeout(state, POLL_INTERVAL);
+                continue;
+            }
+        }
+
+        // Decoded without holding the lock, the audio thread only ever try_locks it
+        let read = decoder.read_at(position, &mut block).unwrap_or(0);
+
+        let Ok(mut state) = shared.state.lock() else {
+            return;
+        };
+
+        // Decoded for a cursor the audio thread has left meanwhile
+        if state.seek.is_some() || state.start + state.queue.len() / channels != position {
+            continue;
+        }
+
+        if read == 0 {
+            state.end = true;
+        } else {
+            state.queue.extend(&block[..read * channels]);
+            position += read;
+        }
+
+        shared.buffered.store(state.queue.len() / channels, Ordering::Relaxed);
+        shared.filled.notify_all();
+    }
+}
+
+impl PcmStream for PrefetchStream {
+    fn read(
+        &mut self,
+        position: usize,
+        output: &mut [f32],
+    ) -> Result<StreamRead, AudioReaderError> {
+        let channels = self.channels;
+        if position >= self.frames {
+            return Ok(StreamRead::played(0));
+        }
+
+        let frames = (output.len() / channels).min(self.frames - position);
+        let output = &mut output[..frames * channels];
+
+        // Held by the decoder thread, played as silence without moving the cursor
+        let Ok(mut state) = self.shared.state.try_lock() else {
+            output.fill(0.0);
+            self.shared.underruns.fetch_add(1, Ordering::Relaxed);
+
+            return Ok(StreamRead {
+                frames,
+                advanced: 0,
+            });
+        };
+
+        let end = state.start + state.queue.len() / channels;
+        if state.seek.is_some() || position < state.start || position > end {
+            state.queue.clear();
+            state.start = position;
+            state.seek = Some(position);
+        } else {
+            let skipped = (position - state.start) * channels;
+            state.queue.drain(..skipped);
+            state.start = position;
+        }
+
+        let available = (state.queue.len() / channels).min(frames);
+        for (sample, queued) in output.iter_mut().zip(state.queue.drain(..available * channels)) {
+            *sample = queued;
+        }
+
+        state.start += available;
+
+        let decoder_done = state.end && state.queue.is_empty() && state.seek.is_none();
+        self.shared
+            .buffered
+            .store(state.queue.len() / channels, Ordering::Relaxed);
+
+        drop(state);
+        self.shared.wake.notify_one();
+
+        if available < frames {
+            // The file was shorter than its reported length
+            if decoder_done {
+                return Ok(StreamRead::played(available));
+            }
+
+            // The cursor stays where the decoded frames ran out
+            output[available * channels..].fill(0.0);
+            self.shared.underruns.fetch_add(1, Ordering::Relaxed);
+
+            return Ok(StreamRead {
+                frames,
+                advanced: available,
+            });
+        }
+
+        Ok(StreamRead::played(frames))
+    }
+
+    fn seek(&mut self, position: usize) {
+        let Ok(mut state) = self.shared.state.try_lock() else {
+            return;
+        };
+
+        let end = state.start + state.queue.len() / self.channels;
+        if position < state.start || position > end {
+            state.queue.clear();
+            state.start = position;
+            state.seek = Some(position);
+
+            drop(state);
+            self.shared.wake.notify_one();
+        }
+    }
+
+    fn buffered_bytes(&self) -> usize {
+        self.capacity * std::mem::size_of::<f32>()
+    }
+
+    fn status(&self) -> Option<StreamStatus> {
+        Some(StreamStatus {
+            buffered_frames: self.shared.buffered.load(Ordering::Relaxed),
+            underruns: self.shared.underruns.load(Ordering::Relaxed),
+        })
+    }
+}
+
+impl Drop for PrefetchStream {
+    fn drop(&mut self) {
+        // Not joined, the stream may be dropped on the audio thread
+        self.shared.stop.store(true, Ordering::Release);
+        self.shared.wake.notify_one();
+    }
+}
@@ -0,0 +1,346 @@
+use miniaudio_sys::*;
+
+use super::{AudioReaderError, prefetch::PrefetchStream};
+
+/// How a streamed sample is decoded, see [SampleInfo::stream](crate::SampleInfo::stream).
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct StreamInfo {
+    /// Frames decoded at once, 16384 by default.
+    pub chunk_frames: usize,
+    /// Chunks decoded ahead of the one being read, 2 by default.
+    pub read_ahead: usize,
+    /// Milliseconds kept decoded ahead of the play cursor by a decoder thread, 500 by
+    /// default. None decodes the chunks above on the audio thread instead.
+    pub prebuffer_ms: Option<f32>,
+    /// The decoder thread tops the prebuffer up once fewer milliseconds than this are left,
+    /// 250 by default.
+    pub watermark_ms: f32,
+}
+
+impl Default for StreamInfo {
+    fn default() -> Self {
+        Self {
+            chunk_frames: 16384,
+            read_ahead: 2,
+            prebuffer_ms: Some(500.0),
+            watermark_ms: 250.0,
+        }
+    }
+}
+
+/// Buffer state of a channel of a streamed sample, see `SampleChannel::stream_status`, or
+/// of a track streamed from disk, see [Track::stream_status](crate::Track::stream_status).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct StreamStatus {
+    /// Frames decoded ahead of the play cursor.
+    pub buffered_frames: usize,
+    /// Reads the decoder thread was not ready for, played as silence.
+    pub underruns: u64,
+}
+
+/// Outcome of [PcmStream::read].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct StreamRead {
+    /// Frames written to the output, the silence of an underrun included. Fewer than asked
+    /// only at the end of the stream.
+    pub frames: usize,
+    /// Frames of the stream played, the cursor only moves by these.
+    pub advanced: usize,
+}
+
+impl StreamRead {
+    /// `frames` of the stream played in full.
+    pub fn played(frames: usize) -> Self {
+        Self {
+            frames,
+            advanced: frames,
+        }
+    }
+}
+
+/// Audio decoded while it is read instead of up front, see [AudioReader::load_stream](super::AudioReader::load_stream).
+pub(crate) trait PcmStream: Send {
+    /// Read interleaved frames starting at frame `position` into `output`. A stream the
+    /// decoder thread fell behind on pads the output with silence and leaves the cursor where
+    /// the decoded frames ran out.
+    fn read(
+        &mut self,
+        position: usize,
+        output: &mut [f32],
+    ) -> Result<StreamRead, AudioReaderError>;
+
+    /// Hint that the next read starts at `position`.
+    fn seek(&mut self, _position: usize) {}
+
+    /// Bytes of decoded audio held by the stream.
+    fn buffered_bytes(&self) -> usize;
+
+    /// Prebuffer state, None when the stream decodes as it is read.
+    fn status(&self) -> Option<StreamStatus> {
+        None
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct StreamFormat {
+    pub channels: usize,
+    pub sample_rate: f32,
+    pub frames: usize,
+}
+
+/// A file decoder producing f32 frames at the file rate and channel count.
+pub(crate) struct FileDecoder {
+    decoder: Box<ma_decoder>,
+    format: StreamFormat,
+    // Frame the next read starts at, reads elsewhere seek first
+    cursor: usize,
+}
+
+// SAFETY: The decoder is only used through &mut self, by one thread at a time.
+unsafe impl Send for FileDecoder {}
+
+impl FileDecoder {
+    pub fn open(path: &str) -> Result<Self, AudioReaderError> {
+        if !std::path::Path::new(path).exists() {
+            return Err(AudioReaderError::FileNotFound(path.to_string()));
+        }
+
+        let cpath = std::ffi::CString::new(path).map_err(|_| AudioReaderError::InvalidParameter)?;
+
+        unsafe {
+            let config = ma_decoder_config_init(ma_format_f32, 0, 0);
+            let mut decoder: Box<ma_decoder> = Box::new(std::mem::zeroed());
+
+            let result = ma_decoder_init_file(cpath.as_ptr(), &config, decoder.as_mut());
+            if result != MA_SUCCESS {
+                return Err(AudioReaderError::InitializationError(result));
+            }
+
+            let mut frames = 0;
+            let result = ma_decoder_get_length_in_pcm_frames(decoder.as_mut(), &mut frames);
+            if result != MA_SUCCESS || frames == 0 {
+                ma_decoder_uninit(decoder.as_mut());
+                return Err(AudioReaderError::InvalidPCMLength);
+            }
+
+            let format = StreamFormat {
+                channels: decoder.outputChannels as usize,
+                sample_rate: decoder.outputSampleRate as f32,
+                frames: frames as usize,
+            };
+
+            Ok(Self {
+                decoder,
+                format,
+                cursor: 0,
+            })
+        }
+    }
+
+    pub fn format(&self) -> StreamFormat {
+        self.format
+    }
+
+    /// Decode frames starting at `position` into `output`, returns the frames decoded.
+    pub fn read_at(&mut self, position: usize, output: &mut [f32]) -> Result<usize, AudioReaderError> {
+        let frames = output.len() / self.format.channels;
+        if frames == 0 || position >= self.format.frames {
+            return Ok(0);
+        }
+
+        unsafe {
+            if position != self.cursor {
+                let result = ma_decoder_seek_to_pcm_frame(self.decoder.as_mut(), position as u64);
+                if result != MA_SUCCESS {
+                    return Err(AudioReaderError::SeekError(result));
+                }
+
+                self.cursor = position;
+            }
+
+            let mut read = 0;
+            let result = ma_decoder_read_pcm_frames(
+                self.decoder.as_mut(),
+                output.as_mut_ptr() as *mut std::ffi::c_void,
+                frames as u64,
+                &mut read,
+            );
+
+            if result != MA_SUCCESS && result != MA_AT_END {
+                return Err(AudioReaderError::InitializationError(result));
+            }
+
+            self.cursor += read as usize;
+            Ok(read as usize)
+        }
+    }
+}
+
+impl Drop for FileDecoder {
+    fn drop(&mut self) {
+        unsafe {
+            ma_decoder_uninit(self.decoder.as_mut());
+        }
+    }
+}
+
+struct Chunk {
+    index: usize,
+    frames: usize,
+    data: Vec<f32>,
+    last_used: u64,
+}
+
+/// Decodes a file a chunk at a time as it is read, keeping the chunk being read and a few
+/// decoded ahead of it.
+pub(crate) struct ChunkedDecoder {
+    decoder: FileDecoder,
+    info: StreamInfo,
+    chunks: Vec<Chunk>,
+    // Bumped on every chunk use, the chunk used longest ago is reused first
+    uses: u64,
+}
+
+impl ChunkedDecoder {
+    pub fn open(path: &str, info: StreamInfo) -> Result<Self, AudioReaderError> {
+        let info = StreamInfo {
+            chunk_frames: info.chunk_frames.max(256),
+            read_ahead: info.read_ahead,
+        };
+
+        Ok(Self {
+            decoder: FileDecoder::open(path)?,
+            info,
+            chunks: Vec::with_capacity(info.read_ahead + 2),
+            uses: 0,
+        })
+    }
+
+    /// The chunk at `index`, decoded when it is not held.
+    fn chunk(&mut self, index: usize) -> Result<&Chunk, AudioReaderError> {
+        self.uses += 1;
+
+        if let Some(slot) = self.chunks.iter().position(|chunk| chunk.index == index) {
+            self.chunks[slot].last_used = self.uses;
+            return Ok(&self.chunks[slot]);
+        }
+
+        let channels = self.decoder.format.channels;
+        let len = self.info.chunk_frames * channels;
+
+        // The current chunk, the one before it for loops and seeks back, and the read ahead
+        let slot = if self.chunks.len() < self.info.read_ahead + 2 {
+            self.chunks.push(Chunk {
+                index,
+                frames: 0,
+                data: vec![0.0; len],
+                last_used: 0,
+            });
+
+            self.chunks.len() - 1
+        } else {
+            let (slot, _) = self
+                .chunks
+                .iter()
+                .enumerate()
+                .min_by_key(|(_, chunk)| chunk.last_used)
+                .unwrap();
+
+            slot
+        };
+
+        let position = index * self.info.chunk_frames;
+        let chunk = &mut self.chunks[slot];
+        chunk.index = index;
+        chunk.last_used = self.uses;
+        chunk.frames = self.decoder.read_at(position, &mut chunk.data)?;
+
+        Ok(&self.chunks[slot])
+    }
+}
+
+impl PcmStream for ChunkedDecoder {
+    fn read(
+        &mut self,
+        position: usize,
+        output: &mut [f32],
+    ) -> Result<StreamRead, AudioReaderError> {
+        let channels = self.decoder.format.channels;
+        let chunk_frames = self.info.chunk_frames;
+        let frames = output.len() / channels;
+
+        let mut read = 0;
+        while read < frames {
+            let frame = position + read;
+            let index = frame / chunk_frames;
+            let offset = frame % chunk_frames;
+
+            let chunk = self.chunk(index)?;
+            if offset >= chunk.frames {
+                break;
+            }
+
+            let count = (chunk.frames - offset).min(frames - read);
+            output[read * channels..(read + count) * channels]
+                .copy_from_slice(&chunk.data[offset * channels..(offset + count) * channels]);
+
+            read += count;
+        }
+
+        let last = (position + read.max(1) - 1) / chunk_frames;
+        let total_chunks = self.decoder.format.frames.div_ceil(chunk_frames);
+
+        for index in (last + 1..total_chunks).take(self.info.read_ahead) {
+            self.chunk(index)?;
+        }
+
+        Ok(StreamRead::played(read))
+    }
+
+    fn buffered_bytes(&self) -> usize {
+        self.chunks
+            .iter()
+            .map(|chunk| chunk.data.capacity() * std::mem::size_of::<f32>())
+            .sum()
+    }
+}
+
+/// A file a streamed [Sample](crate::Sample) decodes from, every channel of the sample opens
+/// a decoder of its own.
+#[derive(Debug, Clone)]
+pub(crate) struct StreamSource {
+    pub path: String,
+    pub info: StreamInfo,
+    pub format: StreamFormat,
+}
+
+impl StreamSource {
+    /// Probe the format of the file at `path` without decoding it.
+    pub fn new(path: &str, info: StreamInfo) -> Result<Self, AudioReaderError> {
+        let format = FileDecoder::open(path)?.format();
+
+        Ok(Self {
+            path: path.to_string(),
+            info,
+            format,
+        })
+    }
+
+    pub fn open(&self) -> Result<Box<dyn PcmStream>, AudioReaderError> {
+        let Some(prebuffer_ms) = self.info.prebuffer_ms else {
+            return Ok(Box::new(ChunkedDecoder::open(&self.path, self.info)?));
+        };
+
+        let to_frames = |ms: f32| (ms.max(0.0) / 1000.0 * self.format.sample_rate) as usize;
+
+        let stream = PrefetchStream::spawn(
+            FileDecoder::open(&self.path)?,
+            to_frames(prebuffer_ms),
+            to_frames(self.info.watermark_ms),
+        )?;
+
+        Ok(Box::new(stream))
+    }
+}
@@ -0,0 +1,27 @@
+use std::sync::{Arc, RwLock};
+
+use once_cell::sync::Lazy;
+
+/// Rewrites the bytes of an audio file before it is decoded, given the path or asset name of
+/// the file, see [set_asset_transform](crate::set_asset_transform).
+pub type AssetTransform = Arc<dyn Fn(&str, &mut Vec<u8>) -> std::io::Result<()> + Send + Sync>;
+
+static ASSET_TRANSFORM: Lazy<RwLock<Option<AssetTransform>>> = Lazy::new(|| RwLock::new(None));
+
+pub fn set_asset_transform(transform: Option<AssetTransform>) {
+    if let Ok(mut current) = ASSET_TRANSFORM.write() {
+        *current = transform;
+    }
+}
+
+pub fn asset_transform() -> Option<AssetTransform> {
+    ASSET_TRANSFORM.read().ok().and_then(|transform| transform.clone())
+}
+
+/// Apply the registered transform to the bytes of `name`, a no-op without one.
+pub fn apply(name: &str, data: &mut Vec<u8>) -> std::io::Result<()> {
+    match asset_transform() {
+        Some(transform) => transform(name, data),
+        None => Ok(()),
+    }
+}
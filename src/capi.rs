@@ -1,5 +1,5 @@
 use crate::{
-    effects::{AttenuationModel, Positioning, SpartialListenerHandler, SpatializationHandler as _}, encoder::{EncoderSampleInfo, EncoderTrackInfo}, sample::SampleChannel
+    effects::{AttenuationModel, PanMode, Positioning, SpartialListenerHandler, SpatializationHandler as _}, encoder::{EncoderSampleInfo, EncoderTrackInfo}, sample::SampleChannel
 };
 
 use super::*;
@@ -420,6 +420,43 @@ pub unsafe extern "C" fn estaudio_device_get_attribute_bool(
     }
 }
 
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn estaudio_device_set_pan_mode(device: *mut Device, mode: PanMode) -> bool {
+    if device.is_null() {
+        return false;
+    }
+
+    let device = cast_as_mut!(device, Device);
+
+    match device.set_pan_mode(mode) {
+        Ok(_) => true,
+        Err(e) => {
+            set_last_error(&format!("{:?}", e));
+            false
+        }
+    }
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn estaudio_device_get_pan_mode(device: *const Device) -> PanMode {
+    if device.is_null() {
+        return PanMode::default();
+    }
+
+    let device = cast_as!(device, Device);
+    device.get_pan_mode()
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn estaudio_device_get_time(device: *const Device) -> u64 {
+    if device.is_null() {
+        return 0;
+    }
+
+    let device = cast_as!(device, Device);
+    device.get_time()
+}
+
 #[unsafe(no_mangle)]
 pub unsafe extern "C" fn estaudio_device_spartial_set_position(
     device: *mut Device,
@@ -810,6 +847,7 @@ pub unsafe extern "C" fn estaudio_create_track(info: *const native::TrackInfo) -
         } else {
             Some(info.sample_rate)
         },
+        ..Default::default()
     };
 
     match crate::create_track(track_info) {
@@ -873,6 +911,27 @@ pub unsafe extern "C" fn estaudio_track_stop(track: *mut Track) -> bool {
     }
 }
 
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn estaudio_track_stop_at(
+    track: *mut Track,
+    device_time: u64,
+    fade_ms: f32,
+) -> bool {
+    if track.is_null() {
+        return false;
+    }
+
+    let track = cast_as_mut!(track, Track);
+
+    match track.stop_at(device_time, fade_ms) {
+        Ok(_) => true,
+        Err(e) => {
+            set_last_error(&format!("{:?}", e));
+            false
+        }
+    }
+}
+
 #[unsafe(no_mangle)]
 pub unsafe extern "C" fn estaudio_track_is_playing(track: *const Track) -> bool {
     if track.is_null() {
@@ -941,6 +1000,23 @@ pub unsafe extern "C" fn estaudio_track_seek(track: *mut Track, pcm_position: us
     }
 }
 
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn estaudio_track_seek_secs(track: *mut Track, position: f64) -> bool {
+    if track.is_null() {
+        return false;
+    }
+
+    let track = cast_as_mut!(track, Track);
+
+    match track.seek_secs(position) {
+        Ok(_) => true,
+        Err(e) => {
+            set_last_error(&format!("{:?}", e));
+            false
+        }
+    }
+}
+
 #[unsafe(no_mangle)]
 pub unsafe extern "C" fn estaudio_track_get_position(track: *const Track) -> usize {
     if track.is_null() {
@@ -983,6 +1059,57 @@ pub unsafe extern "C" fn estaudio_track_is_looping(track: *const Track) -> bool
     track.is_looping()
 }
 
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn estaudio_track_set_loop_crossfade_ms(track: *mut Track, ms: usize) -> bool {
+    if track.is_null() {
+        return false;
+    }
+
+    let track = cast_as_mut!(track, Track);
+
+    match track.set_loop_crossfade_ms(ms) {
+        Ok(_) => true,
+        Err(e) => {
+            set_last_error(&format!("{:?}", e));
+            false
+        }
+    }
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn estaudio_track_set_pan_mode(track: *mut Track, mode: PanMode) -> bool {
+    if track.is_null() {
+        return false;
+    }
+
+    let track = cast_as_mut!(track, Track);
+
+    match track.set_pan_mode(mode) {
+        Ok(_) => true,
+        Err(e) => {
+            set_last_error(&format!("{:?}", e));
+            false
+        }
+    }
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn estaudio_track_get_pan_mode(track: *const Track) -> PanMode {
+    if track.is_null() {
+        return PanMode::default();
+    }
+
+    let track = cast_as!(track, Track);
+
+    match track.get_pan_mode() {
+        Ok(mode) => mode,
+        Err(e) => {
+            set_last_error(&format!("{:?}", e));
+            PanMode::default()
+        }
+    }
+}
+
 #[unsafe(no_mangle)]
 pub unsafe extern "C" fn estaudio_track_set_attribute_f32(
     track: *mut Track,
@@ -1004,6 +1131,28 @@ pub unsafe extern "C" fn estaudio_track_set_attribute_f32(
     }
 }
 
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn estaudio_track_set_attribute_f32_at(
+    track: *mut Track,
+    attr_type: native::AudioAttributes,
+    value: f32,
+    device_time: u64,
+) -> bool {
+    if track.is_null() {
+        return false;
+    }
+
+    let track = cast_as_mut!(track, Track);
+
+    match track.set_attribute_f32_at(attr_type.into(), value, device_time) {
+        Ok(_) => true,
+        Err(e) => {
+            set_last_error(&format!("{:?}", e));
+            false
+        }
+    }
+}
+
 #[unsafe(no_mangle)]
 pub unsafe extern "C" fn estaudio_track_get_attribute_f32(
     track: *const Track,
@@ -1171,6 +1320,141 @@ pub unsafe extern "C" fn estaudio_track_spartial_get_velocity(
     }
 }
 
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn estaudio_track_spartial_set_auto_velocity(
+    track: *mut Track,
+    enabled: bool,
+) -> bool {
+    if track.is_null() {
+        return false;
+    }
+
+    let track = cast_as_mut!(track, Track);
+
+    match track.spatial_set_auto_velocity(enabled) {
+        Ok(_) => true,
+        Err(e) => {
+            set_last_error(&format!("{:?}", e));
+            false
+        }
+    }
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn estaudio_track_spartial_get_auto_velocity(
+    track: *const Track,
+    out_enabled: *mut bool,
+) -> bool {
+    if track.is_null() || out_enabled.is_null() {
+        return false;
+    }
+
+    let track = cast_as!(track, Track);
+
+    match track.spatial_get_auto_velocity() {
+        Ok(enabled) => {
+            unsafe {
+                *out_enabled = enabled;
+            }
+            true
+        }
+        Err(e) => {
+            set_last_error(&format!("{:?}", e));
+            false
+        }
+    }
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn estaudio_track_spartial_set_occlusion(
+    track: *mut Track,
+    occlusion: f32,
+) -> bool {
+    if track.is_null() {
+        return false;
+    }
+
+    let track = cast_as_mut!(track, Track);
+
+    match track.spatial_set_occlusion(occlusion) {
+        Ok(_) => true,
+        Err(e) => {
+            set_last_error(&format!("{:?}", e));
+            false
+        }
+    }
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn estaudio_track_spartial_get_occlusion(
+    track: *const Track,
+    out_occlusion: *mut f32,
+) -> bool {
+    if track.is_null() || out_occlusion.is_null() {
+        return false;
+    }
+
+    let track = cast_as!(track, Track);
+
+    match track.spatial_get_occlusion() {
+        Ok(occlusion) => {
+            unsafe {
+                *out_occlusion = occlusion;
+            }
+            true
+        }
+        Err(e) => {
+            set_last_error(&format!("{:?}", e));
+            false
+        }
+    }
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn estaudio_track_spartial_set_obstruction(
+    track: *mut Track,
+    obstruction: f32,
+) -> bool {
+    if track.is_null() {
+        return false;
+    }
+
+    let track = cast_as_mut!(track, Track);
+
+    match track.spatial_set_obstruction(obstruction) {
+        Ok(_) => true,
+        Err(e) => {
+            set_last_error(&format!("{:?}", e));
+            false
+        }
+    }
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn estaudio_track_spartial_get_obstruction(
+    track: *const Track,
+    out_obstruction: *mut f32,
+) -> bool {
+    if track.is_null() || out_obstruction.is_null() {
+        return false;
+    }
+
+    let track = cast_as!(track, Track);
+
+    match track.spatial_get_obstruction() {
+        Ok(obstruction) => {
+            unsafe {
+                *out_obstruction = obstruction;
+            }
+            true
+        }
+        Err(e) => {
+            set_last_error(&format!("{:?}", e));
+            false
+        }
+    }
+}
+
 #[unsafe(no_mangle)]
 pub unsafe extern "C" fn estaudio_track_spartial_set_direction(
     track: *mut Track,
@@ -1774,6 +2058,7 @@ pub unsafe extern "C" fn estaudio_create_sample(info: *const native::SampleInfo)
         } else {
             Some(info.sample_rate)
         },
+        stream: None,
     };
 
     match crate::create_sample(sample_info) {
@@ -2288,6 +2573,27 @@ pub unsafe extern "C" fn estaudio_mixer_stop(mixer: *mut Mixer) -> bool {
     }
 }
 
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn estaudio_mixer_stop_at(
+    mixer: *mut Mixer,
+    position: usize,
+    fade_ms: f32,
+) -> bool {
+    if mixer.is_null() {
+        return false;
+    }
+
+    let mixer = cast_as_mut!(mixer, Mixer);
+
+    match mixer.stop_at(position, fade_ms) {
+        Ok(_) => true,
+        Err(e) => {
+            set_last_error(&format!("{:?}", e));
+            false
+        }
+    }
+}
+
 #[unsafe(no_mangle)]
 pub unsafe extern "C" fn estaudio_mixer_add_track(mixer: *mut Mixer, track: *mut Track) -> bool {
     if mixer.is_null() || track.is_null() {
@@ -2432,6 +2738,40 @@ pub unsafe extern "C" fn estaudio_mixer_is_playing(mixer: *const Mixer) -> bool
     mixer.is_playing()
 }
 
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn estaudio_mixer_seek(mixer: *mut Mixer, pcm_position: usize) -> bool {
+    if mixer.is_null() {
+        return false;
+    }
+
+    let mixer = cast_as_mut!(mixer, Mixer);
+
+    match mixer.seek(pcm_position) {
+        Ok(_) => true,
+        Err(e) => {
+            set_last_error(&format!("{:?}", e));
+            false
+        }
+    }
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn estaudio_mixer_seek_secs(mixer: *mut Mixer, position: f64) -> bool {
+    if mixer.is_null() {
+        return false;
+    }
+
+    let mixer = cast_as_mut!(mixer, Mixer);
+
+    match mixer.seek_secs(position) {
+        Ok(_) => true,
+        Err(e) => {
+            set_last_error(&format!("{:?}", e));
+            false
+        }
+    }
+}
+
 #[unsafe(no_mangle)]
 pub unsafe extern "C" fn estaudio_mixer_get_position(mixer: *const Mixer) -> usize {
     if mixer.is_null() {
@@ -2449,6 +2789,23 @@ pub unsafe extern "C" fn estaudio_mixer_get_position(mixer: *const Mixer) -> usi
     }
 }
 
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn estaudio_mixer_get_position_secs(mixer: *const Mixer) -> f64 {
+    if mixer.is_null() {
+        return 0.0;
+    }
+
+    let mixer = cast_as!(mixer, Mixer);
+
+    match mixer.get_position_secs() {
+        Ok(position) => position,
+        Err(e) => {
+            set_last_error(&format!("{:?}", e));
+            0.0
+        }
+    }
+}
+
 #[unsafe(no_mangle)]
 pub unsafe extern "C" fn estaudio_mixer_set_attribute_f32(
     mixer: *mut Mixer,
@@ -2542,3 +2899,37 @@ pub unsafe extern "C" fn estaudio_mixer_get_attribute_bool(
         }
     }
 }
+
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn estaudio_mixer_set_pan_mode(mixer: *mut Mixer, mode: PanMode) -> bool {
+    if mixer.is_null() {
+        return false;
+    }
+
+    let mixer = cast_as_mut!(mixer, Mixer);
+
+    match mixer.set_pan_mode(mode) {
+        Ok(_) => true,
+        Err(e) => {
+            set_last_error(&format!("{:?}", e));
+            false
+        }
+    }
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn estaudio_mixer_get_pan_mode(mixer: *const Mixer) -> PanMode {
+    if mixer.is_null() {
+        return PanMode::default();
+    }
+
+    let mixer = cast_as!(mixer, Mixer);
+
+    match mixer.get_pan_mode() {
+        Ok(mode) => mode,
+        Err(e) => {
+            set_last_error(&format!("{:?}", e));
+            PanMode::default()
+        }
+    }
+}
@@ -1,10 +1,14 @@
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
+
+use once_cell::sync::Lazy;
 
 use miniaudio_sys::*;
 use thiserror::Error;
 
 use crate::utils;
 
+pub(crate) mod watcher;
+
 #[derive(Debug, Error)]
 #[must_use]
 pub enum ContextError {
@@ -12,6 +16,8 @@ pub enum ContextError {
     InitializationFailed(i32),
     #[error("Audio device enumeration failed with code: {} {}", .0, self.ma_result_to_str())]
     DeviceEnumerationFailed(i32),
+    #[error("Failed to start the device watcher")]
+    WatcherFailed,
 }
 
 impl ContextError {
@@ -19,11 +25,13 @@ impl ContextError {
         match self {
             ContextError::InitializationFailed(code)
             | ContextError::DeviceEnumerationFailed(code) => utils::ma_to_string_result(*code),
+            ContextError::WatcherFailed => "",
         }
     }
 }
 
 #[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum DeviceType {
     /// Playback device (output)
     #[default]
@@ -37,8 +45,15 @@ pub enum DeviceType {
 #[derive(Debug)]
 pub(crate) struct MaContext {
     pub context: Box<ma_context>,
+    // Held while the devices are enumerated, see [enumerate_context]
+    enumeration: Mutex<()>,
 }
 
+// SAFETY: Each miniaudio context function has own mutex, and the enumeration
+// results are guarded by `enumeration`.
+unsafe impl Send for MaContext {}
+unsafe impl Sync for MaContext {}
+
 impl MaContext {
     pub unsafe fn as_ptr(&self) -> *const ma_context {
         self.context.as_ref() as *const ma_context
@@ -53,7 +68,10 @@ impl MaContext {
 
 impl MaContext {
     pub fn new(context: Box<ma_context>) -> Self {
-        Self { context }
+        Self {
+            context,
+            enumeration: Mutex::new(()),
+        }
     }
 }
 
@@ -65,11 +83,68 @@ impl Drop for MaContext {
     }
 }
 
+/// Identifies a device across runs, such as to remember the device the user picked in the
+/// settings and select it again from [HardwareInfos::find_by_id]. Stays the same as long as
+/// the backend reports the same id for the device.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DeviceId(String);
+
+impl DeviceId {
+    const LOOPBACK: &'static str = "loopback";
+
+    fn new(ty: DeviceType, id: Option<&ma_device_id>) -> Self {
+        use sha2::{Digest, Sha256};
+
+        let Some(id) = id else {
+            return Self(Self::LOOPBACK.to_string());
+        };
+
+        // SAFETY: The id is plain data, miniaudio zeroes it before the backend fills it in
+        let bytes = unsafe {
+            std::slice::from_raw_parts(
+                id as *const ma_device_id as *const u8,
+                std::mem::size_of::<ma_device_id>(),
+            )
+        };
+
+        let mut hasher = Sha256::new();
+        hasher.update([ty as u8]);
+        hasher.update(bytes);
+
+        Self(format!("{:x}", hasher.finalize()))
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl std::fmt::Display for DeviceId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl From<String> for DeviceId {
+    fn from(id: String) -> Self {
+        Self(id)
+    }
+}
+
+impl From<&str> for DeviceId {
+    fn from(id: &str) -> Self {
+        Self(id.to_string())
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct AudioHardwareInfo {
     pub name: String,
     pub is_default: bool,
     pub ty: DeviceType,
+    /// Stable identity of the device, see [DeviceId].
+    pub device_id: DeviceId,
 
     pub(crate) id: Option<ma_device_id>,
     pub(crate) ctx: Arc<MaContext>,
@@ -112,6 +187,46 @@ impl Into<i32> for Backend {
     }
 }
 
+static SHARED_CONTEXT: Lazy<Mutex<Option<AudioContext>>> = Lazy::new(|| Mutex::new(None));
+
+/// A miniaudio context, the connection to the audio backend devices are enumerated and
+/// opened through. Creating one is slow on some backends, so enumeration and devices share
+/// [AudioContext::shared] unless given another.
+#[derive(Debug, Clone)]
+pub struct AudioContext {
+    pub(crate) inner: Arc<MaContext>,
+}
+
+impl AudioContext {
+    /// A context of its own on the first of `backends` available, any backend when empty.
+    pub fn new(backends: &[Backend]) -> Result<Self, ContextError> {
+        Ok(Self {
+            inner: init_context(backends)?,
+        })
+    }
+
+    /// The context on the default backend shared by the whole process, created on first use.
+    pub fn shared() -> Result<Self, ContextError> {
+        let Ok(mut shared) = SHARED_CONTEXT.lock() else {
+            return Self::new(&[]);
+        };
+
+        if let Some(context) = shared.as_ref() {
+            return Ok(context.clone());
+        }
+
+        let context = Self::new(&[])?;
+        *shared = Some(context.clone());
+
+        Ok(context)
+    }
+
+    /// The devices of the context, the hardware is enumerated again on every call.
+    pub fn enumerate_devices(&self) -> Result<HardwareInfos, ContextError> {
+        enumerate_context(&self.inner)
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct HardwareInfos {
     loopback_supported: bool,
@@ -136,12 +251,36 @@ impl HardwareInfos {
             None
         }
     }
+
+    /// The output or input device remembered as `id`, None once it is unplugged.
+    pub fn find_by_id(&self, id: &DeviceId) -> Option<&AudioHardwareInfo> {
+        self.output
+            .iter()
+            .chain(self.input.iter())
+            .find(|info| &info.device_id == id)
+    }
+
+    /// The first device of `ty` named `name`, for when the backend changed the id of the
+    /// device. Duplex looks through the outputs.
+    pub fn find_by_name(&self, ty: DeviceType, name: &str) -> Option<&AudioHardwareInfo> {
+        let devices = match ty {
+            DeviceType::Capture => &self.input,
+            DeviceType::Playback | DeviceType::Duplex => &self.output,
+        };
+
+        devices.iter().find(|info| info.name == name)
+    }
 }
 
 pub(crate) fn enumerable(backends: &[Backend]) -> Result<HardwareInfos, ContextError> {
-    // SAFETY: As long the context is properly initialized
-    // the data is always valid and the pointers are not null
-    // within the *count* range.
+    if backends.is_empty() {
+        return AudioContext::shared()?.enumerate_devices();
+    }
+
+    enumerate_context(&init_context(backends)?)
+}
+
+fn init_context(backends: &[Backend]) -> Result<Arc<MaContext>, ContextError> {
     unsafe {
         let backends = backends
             .iter()
@@ -166,6 +305,18 @@ pub(crate) fn enumerable(backends: &[Backend]) -> Result<HardwareInfos, ContextE
             return Err(ContextError::InitializationFailed(result));
         }
 
+        Ok(Arc::new(MaContext::new(context)))
+    }
+}
+
+fn enumerate_context(context: &Arc<MaContext>) -> Result<HardwareInfos, ContextError> {
+    // The device arrays belong to the context and are overwritten by the next enumeration
+    let _enumerating = context.enumeration.lock();
+
+    // SAFETY: As long the context is properly initialized
+    // the data is always valid and the pointers are not null
+    // within the *count* range.
+    unsafe {
         let mut playback_info_array: *mut ma_device_info = std::ptr::null_mut();
         let mut playback_count = 0;
 
@@ -174,7 +325,7 @@ pub(crate) fn enumerable(backends: &[Backend]) -> Result<HardwareInfos, ContextE
 
         let result = {
             ma_context_get_devices(
-                context.as_mut(),
+                context.as_mut_ptr(),
                 &mut playback_info_array,
                 &mut playback_count,
                 &mut capture_info_array,
@@ -186,8 +337,6 @@ pub(crate) fn enumerable(backends: &[Backend]) -> Result<HardwareInfos, ContextE
             return Err(ContextError::DeviceEnumerationFailed(result));
         }
 
-        let context = Arc::new(MaContext::new(context));
-
         let mut output = Vec::new();
         for i in 0..playback_count {
             let device_info = &*playback_info_array.add(i as usize);
@@ -200,10 +349,11 @@ pub(crate) fn enumerable(backends: &[Backend]) -> Result<HardwareInfos, ContextE
 
             output.push(AudioHardwareInfo {
                 name,
+                device_id: DeviceId::new(DeviceType::Playback, Some(&id)),
                 id: Some(id),
                 is_default,
                 ty: DeviceType::Playback,
-                ctx: Arc::clone(&context),
+                ctx: Arc::clone(context),
             });
         }
 
@@ -219,10 +369,11 @@ pub(crate) fn enumerable(backends: &[Backend]) -> Result<HardwareInfos, ContextE
 
             input.push(AudioHardwareInfo {
                 name,
+                device_id: DeviceId::new(DeviceType::Capture, Some(&id)),
                 id: Some(id),
                 is_default,
                 ty: DeviceType::Capture,
-                ctx: Arc::clone(&context),
+                ctx: Arc::clone(context),
             });
         }
 
@@ -230,10 +381,11 @@ pub(crate) fn enumerable(backends: &[Backend]) -> Result<HardwareInfos, ContextE
         if loopback_supported {
             let loop_back_hardware = AudioHardwareInfo {
                 name: "Loopback".to_string(),
+                device_id: DeviceId::new(DeviceType::Capture, None),
                 id: None,
                 is_default: false,
                 ty: DeviceType::Capture,
-                ctx: Arc::clone(&context),
+                ctx: Arc::clone(context),
             };
 
             input.push(loop_back_hardware);
@@ -0,0 +1,126 @@
+use std::{
+    sync::mpsc::{self, RecvTimeoutError},
+    time::Duration,
+};
+
+use super::{Backend, ContextError, DeviceType, HardwareInfos, enumerable};
+
+/// How often [watch_devices](crate::watch_devices) enumerates the devices again.
+pub const DEFAULT_WATCH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// A change of the audio hardware found by a [DeviceWatcher].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum DeviceEvent {
+    Added { ty: DeviceType, name: String },
+    Removed { ty: DeviceType, name: String },
+    /// The default device changed, None when there is no default anymore.
+    DefaultChanged { ty: DeviceType, name: Option<String> },
+}
+
+/// Watches the audio hardware for changes, see [watch_devices](crate::watch_devices). Stops
+/// once dropped.
+#[derive(Debug)]
+pub struct DeviceWatcher {
+    // Dropping it wakes the watcher thread and ends it
+    _stop: mpsc::Sender<()>,
+}
+
+// The devices of one direction by name, and the name of the default one
+struct Snapshot {
+    output: Vec<String>,
+    input: Vec<String>,
+    default_output: Option<String>,
+    default_input: Option<String>,
+}
+
+impl Snapshot {
+    fn new(infos: &HardwareInfos) -> Self {
+        Self {
+            output: infos.output.iter().map(|info| info.name.clone()).collect(),
+            input: infos.input.iter().map(|info| info.name.clone()).collect(),
+            default_output: infos.default_output().map(|info| info.name.clone()),
+            default_input: infos.default_input().map(|info| info.name.clone()),
+        }
+    }
+
+    fn diff(&self, new: &Snapshot) -> Vec<DeviceEvent> {
+        let mut events = Vec::new();
+
+        let lists = [
+            (DeviceType::Playback, &self.output, &new.output),
+            (DeviceType::Capture, &self.input, &new.input),
+        ];
+
+        for (ty, old, new) in lists {
+            for name in new.iter().filter(|name| !old.contains(name)) {
+                events.push(DeviceEvent::Added {
+                    ty,
+                    name: name.clone(),
+                });
+            }
+
+            for name in old.iter().filter(|name| !new.contains(name)) {
+                events.push(DeviceEvent::Removed {
+                    ty,
+                    name: name.clone(),
+                });
+            }
+        }
+
+        if self.default_output != new.default_output {
+            events.push(DeviceEvent::DefaultChanged {
+                ty: DeviceType::Playback,
+                name: new.default_output.clone(),
+            });
+        }
+
+        if self.default_input != new.default_input {
+            events.push(DeviceEvent::DefaultChanged {
+                ty: DeviceType::Capture,
+                name: new.default_input.clone(),
+            });
+        }
+
+        events
+    }
+}
+
+pub(crate) fn watch<F>(
+    backends: &[Backend],
+    interval: Duration,
+    mut callback: F,
+) -> Result<DeviceWatcher, ContextError>
+where
+    F: FnMut(&HardwareInfos, &[DeviceEvent]) + Send + 'static,
+{
+    let backends = backends.to_vec();
+    let mut snapshot = Snapshot::new(&enumerable(&backends)?);
+    let (stop, stopped) = mpsc::channel::<()>();
+
+    std::thread::Builder::new()
+        .name("estaudio-device-watcher".to_string())
+        .spawn(move || {
+            // Miniaudio reports no hardware changes, so the devices are enumerated again
+            while let Err(RecvTimeoutError::Timeout) = stopped.recv_timeout(interval) {
+                let infos = match enumerable(&backends) {
+                    Ok(infos) => infos,
+                    Err(e) => {
+                        eprintln!("Failed to enumerate devices: {}", e);
+                        continue;
+                    }
+                };
+
+                let next = Snapshot::new(&infos);
+                let events = snapshot.diff(&next);
+                snapshot = next;
+
+                if !events.is_empty() {
+                    callback(&infos, &events);
+                }
+            }
+        })
+        .map_err(|_| ContextError::WatcherFailed)?;
+
+    Ok(DeviceWatcher { _stop: stop })
+}
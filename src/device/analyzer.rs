@@ -0,0 +1,192 @@
+use std::{
+    f32::consts::PI,
+    sync::{Arc, Mutex},
+};
+
+use crate::math::fft::Fft;
+
+/// Upper edge in Hz of the bass band of [SpectrumBands].
+const BASS_EDGE: f32 = 250.0;
+/// Upper edge in Hz of the mid band of [SpectrumBands].
+const MID_EDGE: f32 = 4000.0;
+
+/// RMS level of the output in three bands, bass up to 250 Hz, mid up to 4 kHz and treble above.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[repr(C)]
+pub struct SpectrumBands {
+    pub bass: f32,
+    pub mid: f32,
+    pub treble: f32,
+}
+
+#[derive(Debug, Default)]
+struct SpectrumData {
+    magnitudes: Vec<f32>,
+    bands: SpectrumBands,
+    // Bumped on every analysis, lets visualizers skip frames that did not change
+    generation: u64,
+}
+
+/// Spectrum of a device output, see [Device::enable_analyzer](crate::Device::enable_analyzer).
+///
+/// The audio thread analyzes the mono downmix of the output every half window with a Hann
+/// window, so the handle can be cloned and queried from any thread.
+#[derive(Debug, Clone)]
+pub struct SpectrumAnalyzer {
+    data: Arc<Mutex<SpectrumData>>,
+    fft_size: usize,
+    sample_rate: f32,
+}
+
+impl SpectrumAnalyzer {
+    pub(crate) fn new(fft_size: usize, sample_rate: f32) -> Self {
+        let data = SpectrumData {
+            magnitudes: vec![0.0; fft_size / 2 + 1],
+            ..Default::default()
+        };
+
+        Self {
+            data: Arc::new(Mutex::new(data)),
+            fft_size,
+            sample_rate,
+        }
+    }
+
+    pub fn fft_size(&self) -> usize {
+        self.fft_size
+    }
+
+    /// Frequency in Hz of the bin at `index` in [SpectrumAnalyzer::magnitudes].
+    pub fn bin_frequency(&self, index: usize) -> f32 {
+        index as f32 * self.sample_rate / self.fft_size as f32
+    }
+
+    /// Linear magnitude of every bin from 0 Hz up to Nyquist, `fft_size / 2 + 1` values. A full
+    /// scale sine reads about 1.0 in its bin.
+    pub fn magnitudes(&self) -> Vec<f32> {
+        self.data
+            .lock()
+            .map(|data| data.magnitudes.clone())
+            .unwrap_or_default()
+    }
+
+    /// Copy the magnitudes into `output` without allocating, returns the number of bins copied.
+    pub fn read_magnitudes(&self, output: &mut [f32]) -> usize {
+        let Ok(data) = self.data.lock() else {
+            return 0;
+        };
+
+        let count = output.len().min(data.magnitudes.len());
+        output[..count].copy_from_slice(&data.magnitudes[..count]);
+        count
+    }
+
+    pub fn bands(&self) -> SpectrumBands {
+        self.data.lock().map(|data| data.bands).unwrap_or_default()
+    }
+
+    /// Number of analyses done so far.
+    pub fn generation(&self) -> u64 {
+        self.data.lock().map_or(0, |data| data.generation)
+    }
+
+    pub(crate) fn tap(&self) -> AnalyzerTap {
+        AnalyzerTap::new(self.clone())
+    }
+}
+
+/// The audio thread side of a [SpectrumAnalyzer].
+pub(crate) struct AnalyzerTap {
+    analyzer: SpectrumAnalyzer,
+    window: Vec<f32>,
+    window_gain: f32,
+    history: Vec<f32>,
+    position: usize,
+    pending: usize,
+    fft: Fft,
+    magnitudes: Vec<f32>,
+}
+
+impl AnalyzerTap {
+    fn new(analyzer: SpectrumAnalyzer) -> Self {
+        let size = analyzer.fft_size;
+        let window = (0..size)
+            .map(|index| 0.5 - 0.5 * (2.0 * PI * index as f32 / size as f32).cos())
+            .collect::<Vec<_>>();
+
+        let window_gain = window.iter().sum::<f32>() / 2.0;
+
+        Self {
+            window,
+            window_gain,
+            history: vec![0.0; size],
+            position: 0,
+            pending: 0,
+            fft: Fft::new(size),
+            magnitudes: vec![0.0; size / 2 + 1],
+            analyzer,
+        }
+    }
+
+    /// Feed interleaved `output` with `channels` channels.
+    pub fn push(&mut self, output: &[f32], channels: usize) {
+        let channels = channels.max(1);
+        let size = self.analyzer.fft_size;
+
+        for frame in output.chunks_exact(channels) {
+            self.history[self.position] = frame.iter().sum::<f32>() / channels as f32;
+            self.position = (self.position + 1) % size;
+            self.pending += 1;
+
+            if self.pending >= size / 2 {
+                self.pending = 0;
+                self.analyze();
+            }
+        }
+    }
+
+    fn analyze(&mut self) {
+        let size = self.analyzer.fft_size;
+
+        // Oldest sample first
+        for index in 0..size {
+            let sample = self.history[(self.position + index) % size];
+            self.fft.real[index] = sample * self.window[index];
+            self.fft.imag[index] = 0.0;
+        }
+
+        self.fft.forward();
+
+        let mut energy = [0.0f32; 3];
+        let bin_width = self.analyzer.sample_rate / size as f32;
+
+        for (index, magnitude) in self.magnitudes.iter_mut().enumerate() {
+            let (re, im) = (self.fft.real[index], self.fft.imag[index]);
+            *magnitude = (re * re + im * im).sqrt() / self.window_gain;
+
+            let frequency = index as f32 * bin_width;
+            let band = match frequency {
+                f if f < BASS_EDGE => 0,
+                f if f < MID_EDGE => 1,
+                _ => 2,
+            };
+
+            // Sine RMS from its peak magnitude
+            energy[band] += *magnitude * *magnitude / 2.0;
+        }
+
+        // Never blocks the audio thread, a reader holding the lock only delays the update
+        let Ok(mut data) = self.analyzer.data.try_lock() else {
+            return;
+        };
+
+        data.magnitudes.copy_from_slice(&self.magnitudes);
+        data.bands = SpectrumBands {
+            bass: energy[0].sqrt(),
+            mid: energy[1].sqrt(),
+            treble: energy[2].sqrt(),
+        };
+        data.generation += 1;
+    }
+}
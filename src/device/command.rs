@@ -0,0 +1,67 @@
+use ringbuf::{
+    HeapCons, HeapProd, HeapRb,
+    traits::{Consumer, Producer, Split},
+};
+
+use super::DeviceCommand;
+
+// Commands in flight to the audio thread before a send fails on a running device
+const COMMAND_QUEUE_DEPTH: usize = 1024;
+
+// A batch hands back every command it held plus its own Vec, so the way back is deeper
+const GARBAGE_QUEUE_DEPTH: usize = COMMAND_QUEUE_DEPTH * 4;
+
+/// Bounded queues between a [Device](crate::Device) and its audio thread. Commands go to the
+/// audio thread without allocating, and the values they replace come back to be dropped on
+/// the control thread.
+pub(crate) fn command_queue() -> (CommandSender, CommandReceiver) {
+    let (commands, receiver) = HeapRb::<DeviceCommand>::new(COMMAND_QUEUE_DEPTH).split();
+    let (garbage_sender, garbage) = HeapRb::<DeviceCommand>::new(GARBAGE_QUEUE_DEPTH).split();
+
+    let sender = CommandSender { commands, garbage };
+    let receiver = CommandReceiver {
+        commands: receiver,
+        garbage: garbage_sender,
+    };
+
+    (sender, receiver)
+}
+
+/// Control thread side, see [command_queue].
+pub(crate) struct CommandSender {
+    commands: HeapProd<DeviceCommand>,
+    garbage: HeapCons<DeviceCommand>,
+}
+
+impl CommandSender {
+    /// Queue `command`, handed back when the queue is full.
+    pub fn try_send(&mut self, command: DeviceCommand) -> Result<(), DeviceCommand> {
+        self.collect();
+        self.commands.try_push(command)
+    }
+
+    /// Drop the values the audio thread handed back.
+    pub fn collect(&mut self) {
+        while let Some(garbage) = self.garbage.try_pop() {
+            drop(garbage);
+        }
+    }
+}
+
+/// Audio thread side, see [command_queue].
+pub(crate) struct CommandReceiver {
+    commands: HeapCons<DeviceCommand>,
+    garbage: HeapProd<DeviceCommand>,
+}
+
+impl CommandReceiver {
+    pub fn try_recv(&mut self) -> Option<DeviceCommand> {
+        self.commands.try_pop()
+    }
+
+    /// Hand a replaced value back to the control thread. Only dropped in place when the
+    /// control thread stopped collecting and the way back is full.
+    pub fn retire(&mut self, command: DeviceCommand) {
+        _ = self.garbage.try_push(command);
+    }
+}
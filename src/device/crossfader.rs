@@ -0,0 +1,199 @@
+use std::f32::consts::FRAC_PI_2;
+
+use crate::{Device, DeviceError, Mixer, Track, misc::finishsignal::Completion};
+
+use super::splitter::Splitter;
+
+// Width of the fade at each end of the fader with CrossfadeCurve::Cut
+const CUT_WIDTH: f32 = 0.05;
+
+/// One of the two decks of a [Crossfader].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[repr(C)]
+pub enum Deck {
+    A = 0,
+    B = 1,
+}
+
+/// How the level of both decks follows the fader position of a [Crossfader].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[repr(C)]
+pub enum CrossfadeCurve {
+    /// Levels fade in straight lines, the mix dips in loudness at the center.
+    Linear = 0,
+    /// Constant loudness across the fader, for smooth blends.
+    #[default]
+    EqualPower = 1,
+    /// Both decks at full level except close to the ends, for scratching and cuts.
+    Cut = 2,
+}
+
+impl CrossfadeCurve {
+    /// Gains of deck A and deck B at `position`, 0.0 is deck A alone and 1.0 deck B alone.
+    pub fn gains(self, position: f32) -> (f32, f32) {
+        let position = position.clamp(0.0, 1.0);
+
+        match self {
+            CrossfadeCurve::Linear => (1.0 - position, position),
+            CrossfadeCurve::EqualPower => {
+                ((position * FRAC_PI_2).cos(), (position * FRAC_PI_2).sin())
+            }
+            CrossfadeCurve::Cut => (
+                ((1.0 - position) / CUT_WIDTH).min(1.0),
+                (position / CUT_WIDTH).min(1.0),
+            ),
+        }
+    }
+}
+
+/// Two decks, each a track or mixer, blended on an output device by a single fader position
+/// as in a DJ mixer.
+///
+/// Every deck plays through a [Splitter], so a deck can also be cued to a headphone device
+/// with [Crossfader::set_cue_device] and [Crossfader::set_cue] whatever the fader position.
+/// The decks should not be played on a device directly.
+pub struct Crossfader {
+    decks: [Splitter; 2],
+    output: u32,
+    cue: Option<u32>,
+    position: f32,
+    curve: CrossfadeCurve,
+    cued: [bool; 2],
+    cue_volume: f32,
+}
+
+impl Crossfader {
+    /// Blend the splitters `a` and `b` on `output`, with the fader at the center.
+    pub fn new(a: Splitter, b: Splitter, output: &mut Device) -> Result<Self, DeviceError> {
+        let mut crossfader = Self {
+            decks: [a, b],
+            output: output.get_ref_id(),
+            cue: None,
+            position: 0.5,
+            curve: CrossfadeCurve::default(),
+            cued: [false; 2],
+            cue_volume: 1.0,
+        };
+
+        let (gain_a, gain_b) = crossfader.curve.gains(crossfader.position);
+        crossfader.decks[0].add_device(output, gain_a)?;
+        if let Err(e) = crossfader.decks[1].add_device(output, gain_b) {
+            crossfader.decks[0].remove_device(output)?;
+            return Err(e);
+        }
+
+        Ok(crossfader)
+    }
+
+    pub fn from_tracks(a: &Track, b: &Track, output: &mut Device) -> Result<Self, DeviceError> {
+        Self::new(Splitter::from_track(a), Splitter::from_track(b), output)
+    }
+
+    pub fn from_mixers(a: &Mixer, b: &Mixer, output: &mut Device) -> Result<Self, DeviceError> {
+        Self::new(Splitter::from_mixer(a), Splitter::from_mixer(b), output)
+    }
+
+    /// Start `deck` from its beginning (or cue point for a track), see [Splitter::play].
+    pub fn play(&mut self, deck: Deck) -> Result<Completion, DeviceError> {
+        self.decks[deck as usize].play()
+    }
+
+    pub fn stop(&mut self, deck: Deck) -> Result<(), DeviceError> {
+        self.decks[deck as usize].stop()
+    }
+
+    /// Move the fader, 0.0 is deck A alone and 1.0 deck B alone.
+    pub fn set_position(&mut self, position: f32) -> Result<(), DeviceError> {
+        self.position = position.clamp(0.0, 1.0);
+        self.apply_gains()
+    }
+
+    pub fn get_position(&self) -> f32 {
+        self.position
+    }
+
+    pub fn set_curve(&mut self, curve: CrossfadeCurve) -> Result<(), DeviceError> {
+        self.curve = curve;
+        self.apply_gains()
+    }
+
+    pub fn get_curve(&self) -> CrossfadeCurve {
+        self.curve
+    }
+
+    /// Headphone device the cued decks are heard on. It has to share the channel count and
+    /// sample rate of the output device, and fails while another cue device is set.
+    pub fn set_cue_device(&mut self, device: &mut Device) -> Result<(), DeviceError> {
+        let device_ref_id = device.get_ref_id();
+        if self.cue == Some(device_ref_id) {
+            return Ok(());
+        }
+
+        if let Some(cue) = self.cue {
+            return Err(DeviceError::ChannelAlreadyExists(cue as usize));
+        }
+
+        if device_ref_id == self.output {
+            return Err(DeviceError::ChannelAlreadyExists(device_ref_id as usize));
+        }
+
+        self.decks[0].add_device(device, 0.0)?;
+        if let Err(e) = self.decks[1].add_device(device, 0.0) {
+            self.decks[0].remove_device(device)?;
+            return Err(e);
+        }
+
+        self.cue = Some(device_ref_id);
+        self.apply_gains()
+    }
+
+    /// Stop sending the decks to `device`, returns false when it was not the cue device.
+    pub fn remove_cue_device(&mut self, device: &mut Device) -> Result<bool, DeviceError> {
+        if self.cue != Some(device.get_ref_id()) {
+            return Ok(false);
+        }
+
+        self.cue = None;
+        for deck in self.decks.iter_mut() {
+            deck.remove_device(device)?;
+        }
+
+        Ok(true)
+    }
+
+    /// Send `deck` to the cue device at the cue volume, independently of the fader.
+    pub fn set_cue(&mut self, deck: Deck, cued: bool) -> Result<(), DeviceError> {
+        self.cued[deck as usize] = cued;
+        self.apply_gains()
+    }
+
+    pub fn is_cued(&self, deck: Deck) -> bool {
+        self.cued[deck as usize]
+    }
+
+    pub fn set_cue_volume(&mut self, volume: f32) -> Result<(), DeviceError> {
+        self.cue_volume = volume.max(0.0);
+        self.apply_gains()
+    }
+
+    pub fn get_cue_volume(&self) -> f32 {
+        self.cue_volume
+    }
+
+    fn apply_gains(&mut self) -> Result<(), DeviceError> {
+        let (gain_a, gain_b) = self.curve.gains(self.position);
+
+        for (index, gain) in [gain_a, gain_b].into_iter().enumerate() {
+            self.decks[index].set_output_gain(self.output, gain)?;
+
+            if let Some(cue) = self.cue {
+                let cue_gain = if self.cued[index] { self.cue_volume } else { 0.0 };
+                self.decks[index].set_output_gain(cue, cue_gain)?;
+            }
+        }
+
+        Ok(())
+    }
+}
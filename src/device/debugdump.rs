@@ -0,0 +1,393 @@
+use std::{fmt, sync::atomic::Ordering};
+
+use crate::{
+    context::DeviceType,
+    effects::PanMode,
+    mixer::inner::{MixerChannel, MixerEntry},
+    sample::sampleinner::{SampleChannelHandle, SampleChannelStatus},
+    track::inner::TrackChannel,
+};
+
+use super::{
+    AudioHandle, Device,
+    snapshot::{FXSnapshot, SourceKind},
+};
+
+// Mixers nested deeper than this are not walked, guards against a mixer added to itself
+const MAX_DEPTH: usize = 32;
+
+/// A source in a [GraphDump], a mixer holds the sources added to it.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct GraphNode {
+    pub kind: SourceKind,
+    pub ref_id: usize,
+    pub playing: bool,
+    pub looping: bool,
+    /// Played on the device through a [Splitter](crate::Splitter).
+    pub split: bool,
+    /// Muted or soloed in the mixer holding it.
+    pub muted: bool,
+    pub soloed: bool,
+    /// Position and length in frames of the source, the length is None for endless sources.
+    pub position: usize,
+    pub length: Option<usize>,
+    /// Delay and duration in mixer frames of a mixer child.
+    pub delay: Option<usize>,
+    pub duration: Option<usize>,
+    pub channels: usize,
+    /// Rate of the audio and the rate it is resampled to.
+    pub source_sample_rate: f32,
+    pub sample_rate: f32,
+    pub volume: f32,
+    pub pan: f32,
+    pub pan_mode: PanMode,
+    pub fx: Option<FXSnapshot>,
+    pub spatialized: bool,
+    /// Number of effects in the chain of a track, see [Track::set_effects](crate::Track::set_effects).
+    pub effects: usize,
+    /// Frames a mixer reads per call and the length of its buffers, 0 for other sources.
+    pub block_size: usize,
+    pub children: Vec<GraphNode>,
+    /// Children whose source was dropped or is locked by a caller.
+    pub unavailable_children: usize,
+}
+
+/// Everything attached to a device, see [Device::debug_dump](crate::Device::debug_dump).
+///
+/// The [Display](std::fmt::Display) output is an indented tree meant for logs.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct GraphDump {
+    pub device_ref_id: u32,
+    pub device_type: DeviceType,
+    pub channels: usize,
+    pub sample_rate: f32,
+    pub block_size: usize,
+    /// Device clock in frames, see [Device::get_time](crate::Device::get_time).
+    pub time: u64,
+    pub volume: f32,
+    pub pan: f32,
+    pub pan_mode: PanMode,
+    pub force_mono: bool,
+    pub spatialization: bool,
+    pub recording: bool,
+    pub analyzer: bool,
+    pub nodes: Vec<GraphNode>,
+    /// Attached sources that were dropped or are locked by a caller.
+    pub unavailable: usize,
+}
+
+impl GraphDump {
+    pub(crate) fn capture(device: &Device, device_type: DeviceType) -> Option<Self> {
+        let state = device.state.lock().ok()?;
+
+        let mut nodes = Vec::new();
+        let mut unavailable = 0;
+
+        for handle in state.handles.iter() {
+            let split = matches!(handle, AudioHandle::Split(_));
+
+            match GraphNode::from_handle(handle.source(), 0) {
+                Some(mut node) => {
+                    node.split = split;
+                    nodes.push(node);
+                }
+                None => unavailable += 1,
+            }
+        }
+
+        Some(Self {
+            device_ref_id: device.device_ref_id,
+            device_type,
+            channels: device.channels,
+            sample_rate: device.sample_rate,
+            block_size: device.block_size,
+            time: device.clock.load(Ordering::Acquire),
+            volume: state.volume,
+            pan: state.pan,
+            pan_mode: state.pan_mode,
+            force_mono: state.force_mono,
+            spatialization: state.listener.is_some(),
+            recording: device.recorder.is_some(),
+            analyzer: device.analyzer.is_some(),
+            nodes,
+            unavailable,
+        })
+    }
+}
+
+impl GraphNode {
+    fn from_handle(handle: &AudioHandle, depth: usize) -> Option<Self> {
+        match handle {
+            AudioHandle::Track(track) => {
+                let track = track.upgrade()?;
+                let track = track.lock().ok()?;
+                Some(Self::from_track(&track))
+            }
+            AudioHandle::Sample(sample) => {
+                let sample = sample.upgrade()?;
+                let sample = sample.lock().ok()?;
+                Some(Self::from_sample(&sample))
+            }
+            AudioHandle::Mixer(mixer) => {
+                let mixer = mixer.upgrade()?;
+                let mixer = mixer.lock().ok()?;
+                Some(Self::from_mixer(&mixer, depth))
+            }
+            AudioHandle::Split(_) => None,
+        }
+    }
+
+    fn from_track(track: &TrackChannel) -> Self {
+        Self {
+            kind: SourceKind::Track,
+            ref_id: track.ref_id,
+            playing: track.playing.load(Ordering::SeqCst),
+            looping: track.is_looping.load(Ordering::SeqCst),
+            split: false,
+            muted: false,
+            soloed: false,
+            position: track.position.load(Ordering::SeqCst),
+            length: Some(track.reader.pcm_length).filter(|length| *length != usize::MAX),
+            delay: None,
+            duration: None,
+            channels: track.reader.channels,
+            source_sample_rate: track.reader.sample_rate,
+            sample_rate: track.resampler.target_sample_rate,
+            volume: track.gainer.volume,
+            pan: track.panner.pan,
+            pan_mode: track.panner.mode,
+            fx: FXSnapshot::capture(&track.fx),
+            spatialized: track.spatializer.is_some(),
+            effects: track.effects.len(),
+            block_size: 0,
+            children: Vec::new(),
+            unavailable_children: 0,
+        }
+    }
+
+    fn from_sample(sample: &SampleChannelHandle) -> Self {
+        Self {
+            kind: SourceKind::Sample,
+            ref_id: sample.ref_id,
+            playing: sample.status.load(Ordering::Relaxed) == SampleChannelStatus::Playing,
+            looping: false,
+            split: false,
+            muted: false,
+            soloed: false,
+            position: sample.reader.position,
+            length: Some(sample.reader.pcm_length),
+            delay: None,
+            duration: None,
+            channels: sample.reader.channels,
+            source_sample_rate: sample.reader.sample_rate,
+            sample_rate: sample.resampler.target_sample_rate,
+            volume: sample.volume.volume,
+            pan: sample.panner.pan,
+            pan_mode: sample.panner.mode,
+            fx: FXSnapshot::capture(&sample.fx),
+            spatialized: false,
+            effects: 0,
+            block_size: 0,
+            children: Vec::new(),
+            unavailable_children: 0,
+        }
+    }
+
+    fn from_mixer(mixer: &MixerChannel, depth: usize) -> Self {
+        let mut children = Vec::new();
+        let mut unavailable_children = 0;
+
+        for entry in mixer.entries.iter().take_while(|_| depth < MAX_DEPTH) {
+            let (node, delay, duration) = match entry {
+                MixerEntry::TrackChannel {
+                    channel,
+                    delay,
+                    duration,
+                    ..
+                } => {
+                    let node = channel
+                        .upgrade()
+                        .and_then(|track| track.lock().ok().map(|t| Self::from_track(&t)));
+                    (node, delay, duration)
+                }
+                MixerEntry::SampleChannel {
+                    channel,
+                    delay,
+                    duration,
+                    ..
+                } => {
+                    let node = channel
+                        .upgrade()
+                        .and_then(|sample| sample.lock().ok().map(|s| Self::from_sample(&s)));
+                    (node, delay, duration)
+                }
+                MixerEntry::MixerChannel {
+                    mixer,
+                    delay,
+                    duration,
+                    ..
+                } => {
+                    let node = mixer
+                        .upgrade()
+                        .and_then(|child| child.lock().ok().map(|m| Self::from_mixer(&m, depth + 1)));
+                    (node, delay, duration)
+                }
+            };
+
+            let Some(mut node) = node else {
+                unavailable_children += 1;
+                continue;
+            };
+
+            let key = entry.key();
+            node.muted = mixer.muted.contains(&key);
+            node.soloed = mixer.soloed.contains(&key);
+            node.delay = *delay;
+            node.duration = *duration;
+            children.push(node);
+        }
+
+        Self {
+            kind: SourceKind::Mixer,
+            ref_id: mixer.ref_id,
+            playing: mixer.is_playing.load(Ordering::SeqCst),
+            looping: mixer.is_infinite,
+            split: false,
+            muted: false,
+            soloed: false,
+            position: mixer.mixer_position,
+            length: Some(mixer.max_length).filter(|_| !mixer.is_infinite),
+            delay: None,
+            duration: None,
+            channels: mixer.channel_count,
+            source_sample_rate: mixer.sample_rate,
+            sample_rate: mixer.resampler.target_sample_rate,
+            volume: mixer.volume.volume,
+            pan: mixer.panner.pan,
+            pan_mode: mixer.panner.mode,
+            fx: FXSnapshot::capture(&mixer.fx),
+            spatialized: mixer.spatializer.is_some(),
+            effects: 0,
+            block_size: mixer.block_size,
+            children,
+            unavailable_children,
+        }
+    }
+
+    fn write_tree(&self, f: &mut fmt::Formatter<'_>, depth: usize) -> fmt::Result {
+        let indent = "  ".repeat(depth);
+        let state = match self.playing {
+            true => "playing",
+            false => "stopped",
+        };
+
+        write!(f, "{indent}- {:?} #{} [{state}", self.kind, self.ref_id)?;
+
+        for (flag, name) in [
+            (self.looping, "looping"),
+            (self.split, "split"),
+            (self.muted, "muted"),
+            (self.soloed, "soloed"),
+            (self.spatialized, "spatialized"),
+        ] {
+            if flag {
+                write!(f, ", {name}")?;
+            }
+        }
+
+        write!(f, "] position {}", self.position)?;
+        match self.length {
+            Some(length) => write!(f, "/{length}")?,
+            None => write!(f, "/endless")?,
+        }
+
+        writeln!(
+            f,
+            ", {} ch {} Hz -> {} Hz, volume {:.2}, pan {:.2} ({:?})",
+            self.channels, self.source_sample_rate, self.sample_rate, self.volume, self.pan, self.pan_mode
+        )?;
+
+        let detail = format!("{indent}  ");
+
+        if self.delay.is_some() || self.duration.is_some() {
+            writeln!(f, "{detail}delay {:?}, duration {:?}", self.delay, self.duration)?;
+        }
+
+        if let Some(fx) = self.fx {
+            writeln!(f, "{detail}fx tempo {:.2}, pitch {:.2}", fx.tempo, fx.pitch)?;
+        }
+
+        if self.effects > 0 {
+            writeln!(f, "{detail}{} effects", self.effects)?;
+        }
+
+        if self.kind == SourceKind::Mixer {
+            writeln!(
+                f,
+                "{detail}block {} frames, {} children",
+                self.block_size,
+                self.children.len()
+            )?;
+        }
+
+        if self.unavailable_children > 0 {
+            writeln!(f, "{detail}{} children unavailable", self.unavailable_children)?;
+        }
+
+        for child in self.children.iter() {
+            child.write_tree(f, depth + 1)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl fmt::Display for GraphDump {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "Device #{} ({:?}): {} ch {} Hz, block {} frames, time {}",
+            self.device_ref_id,
+            self.device_type,
+            self.channels,
+            self.sample_rate,
+            self.block_size,
+            self.time
+        )?;
+
+        write!(
+            f,
+            "  volume {:.2}, pan {:.2} ({:?})",
+            self.volume, self.pan, self.pan_mode
+        )?;
+
+        for (flag, name) in [
+            (self.force_mono, "mono"),
+            (self.spatialization, "spatialization"),
+            (self.recording, "recording"),
+            (self.analyzer, "analyzer"),
+        ] {
+            if flag {
+                write!(f, ", {name}")?;
+            }
+        }
+
+        writeln!(f)?;
+
+        if self.nodes.is_empty() {
+            writeln!(f, "  no sources attached")?;
+        }
+
+        for node in self.nodes.iter() {
+            node.write_tree(f, 1)?;
+        }
+
+        if self.unavailable > 0 {
+            writeln!(f, "  {} sources unavailable", self.unavailable)?;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,436 @@
+/// Lowers every source of the `target` group while any source of the `trigger` group is
+/// producing audio. With a `threshold_db` only while it is loud enough, so a voice-over ducks
+/// the music as it speaks without being triggered by the application.
+///
+/// Groups are plain numbers assigned on the sources, see [Track::set_ducking_group](crate::Track::set_ducking_group).
+/// Only sources attached directly to the device take part, put a [Mixer](crate::Mixer) in a group
+/// to duck everything it contains.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct DuckingRule {
+    pub trigger: u32,
+    pub target: u32,
+    /// Attenuation applied to the target group, in dB.
+    pub reduction_db: f32,
+    /// Time to reach the full reduction once the trigger group starts, in milliseconds.
+    pub attack_ms: f32,
+    /// Time to recover once the trigger group is silent, in milliseconds.
+    pub release_ms: f32,
+    /// Peak level the trigger group has to reach to duck, in dBFS as heard after its volume.
+    /// None ducks while the trigger group produces any audio.
+    pub threshold_db: Option<f32>,
+    /// Time the reduction is held after the trigger group falls silent or below the
+    /// threshold, before the release starts, in milliseconds.
+    pub hold_ms: f32,
+}
+
+impl Default for DuckingRule {
+    fn default() -> Self {
+        Self {
+            trigger: 0,
+            target: 0,
+            reduction_db: 9.0,
+            attack_ms: 50.0,
+            release_ms: 500.0,
+            threshold_db: None,
+            hold_ms: 0.0,
+        }
+    }
+}
+
+impl DuckingRule {
+    /// Duck `target` under a voice in `trigger`: fast attack once the voice is above -40 dBFS,
+    /// held across the short pauses between words and released slowly.
+    pub fn voice_over(trigger: u32, target: u32) -> Self {
+        Self {
+            trigger,
+            target,
+            reduction_db: 12.0,
+            attack_ms: 20.0,
+            release_ms: 600.0,
+            threshold_db: Some(-40.0),
+            hold_ms: 400.0,
+        }
+    }
+}
+
+/// Peak of a block of a source, only measured for sources in a group as no other source can
+/// trigger a rule.
+pub(crate) fn block_peak(group: Option<u32>, buffer: &[f32]) -> f32 {
+    if group.is_none() {
+        return 0.0;
+    }
+
+    buffer.iter().fold(0.0, |peak, sample| peak.max(sample.abs()))
+}
+
+/// Gain applied to a group during the current block, ramped linearly from `start` to `end`.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct GroupGain {
+    pub group: u32,
+    pub start: f32,
+    pub end: f32,
+}
+
+impl GroupGain {
+    pub fn find(gains: &[GroupGain], group: Option<u32>) -> Option<GroupGain> {
+        let group = group?;
+        gains.iter().find(|gain| gain.group == group).copied()
+    }
+
+    /// Apply the ramp to an interleaved buffer of `channels` channels.
+    pub fn apply(&self, buffer: &mut [f32], channels: usize) {
+        let frame_count = buffer.len() / channels.max(1);
+        self.apply_section(buffer, channels, 0, frame_count);
+    }
+
+    /// Apply the part of a ramp over `total` frames that starts `offset` frames in to an
+    /// interleaved buffer of `channels` channels.
+    pub fn apply_section(&self, buffer: &mut [f32], channels: usize, offset: usize, total: usize) {
+        if total == 0 {
+            return;
+        }
+
+        let step = (self.end - self.start) / total as f32;
+        let mut gain = self.start + step * offset as f32;
+
+        for frame in buffer.chunks_mut(channels.max(1)) {
+            gain += step;
+
+            for sample in frame.iter_mut() {
+                *sample *= gain;
+            }
+        }
+    }
+}
+
+/// Ducking of a source over the device block being rendered, handed to sources that can
+/// encode their output into the ambisonics bus so the level and the gain are taken before
+/// their spatial stage.
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct SourceDucking {
+    group: Option<u32>,
+    gain: Option<GroupGain>,
+    // Frames of the device block and frames of it already processed
+    frames: usize,
+    offset: usize,
+    pub level: f32,
+}
+
+impl SourceDucking {
+    pub fn new(group: Option<u32>, gains: &[GroupGain], frames: usize) -> Self {
+        Self {
+            group,
+            gain: GroupGain::find(gains, group),
+            frames,
+            offset: 0,
+            level: 0.0,
+        }
+    }
+
+    /// Measure the next frames of the block in `buffer`, then apply their part of the gain.
+    pub fn process(&mut self, buffer: &mut [f32], channels: usize) {
+        self.level = self.level.max(block_peak(self.group, buffer));
+
+        if let Some(gain) = self.gain {
+            gain.apply_section(buffer, channels, self.offset, self.frames);
+        }
+
+        self.offset += buffer.len() / channels.max(1);
+    }
+
+    /// Account frames of the block the source left silent.
+    pub fn skip(&mut self, frames: usize) {
+        self.offset += frames;
+    }
+}
+
+/// Audio thread state of the ducking rules, built on the control thread and sent over with
+/// [DeviceCommand::Ducking](super::DeviceCommand::Ducking).
+pub(crate) struct Ducker {
+    rules: Vec<DuckingRule>,
+    // Envelope of every rule, same order as `rules`
+    envelopes: Vec<f32>,
+    // Frames the reduction of every rule is still held for, same order as `rules`
+    holds: Vec<f32>,
+    // One entry per target group, rebuilt every block
+    gains: Vec<GroupGain>,
+    // Trigger groups that produced audio in the previous block, with their highest peak
+    active: Vec<(u32, f32)>,
+}
+
+impl Ducker {
+    pub fn new(rules: Vec<DuckingRule>) -> Self {
+        let mut gains: Vec<GroupGain> = Vec::with_capacity(rules.len());
+        for rule in rules.iter() {
+            if !gains.iter().any(|gain| gain.group == rule.target) {
+                gains.push(GroupGain {
+                    group: rule.target,
+                    start: 1.0,
+                    end: 1.0,
+                });
+            }
+        }
+
+        Self {
+            envelopes: vec![1.0; rules.len()],
+            holds: vec![0.0; rules.len()],
+            active: Vec::with_capacity(rules.len()),
+            gains,
+            rules,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// Carry the envelopes of matching rules over from the previous set, so replacing the rules
+    /// does not make the ducked groups jump back to full volume.
+    pub fn inherit(&mut self, previous: &Ducker) {
+        let states = self.envelopes.iter_mut().zip(self.holds.iter_mut());
+        for (rule, (envelope, hold)) in self.rules.iter().zip(states) {
+            let matching = previous
+                .rules
+                .iter()
+                .position(|old| old.trigger == rule.trigger && old.target == rule.target);
+
+            if let Some(index) = matching {
+                *envelope = previous.envelopes[index];
+                *hold = previous.holds[index];
+            }
+        }
+
+        for (group, level) in previous.active.iter() {
+            self.mark_active(Some(*group), *level);
+        }
+    }
+
+    pub fn gains(&self) -> &[GroupGain] {
+        &self.gains
+    }
+
+    /// Advance every envelope by one block based on the trigger activity of the previous block.
+    pub fn begin_block(&mut self, frame_count: usize, sample_rate: f32) {
+        for gain in self.gains.iter_mut() {
+            gain.start = f32::MAX;
+            gain.end = f32::MAX;
+        }
+
+        let states = self.envelopes.iter_mut().zip(self.holds.iter_mut());
+        for (rule, (envelope, hold)) in self.rules.iter().zip(states) {
+            let start = *envelope;
+
+            let level = self
+                .active
+                .iter()
+                .find(|(group, _)| *group == rule.trigger)
+                .map(|(_, level)| *level);
+
+            let triggered = match (level, rule.threshold_db) {
+                (Some(level), Some(threshold_db)) => level >= 10f32.powf(threshold_db / 20.0),
+                (Some(_), None) => true,
+                (None, _) => false,
+            };
+
+            // The hold keeps the reduction through the gaps of the trigger, such as the pauses
+            // between words
+            let ducked = if triggered {
+                *hold = rule.hold_ms.max(0.0) / 1000.0 * sample_rate;
+                true
+            } else {
+                let held = *hold > 0.0;
+                *hold = (*hold - frame_count as f32).max(0.0);
+                held
+            };
+
+            let (target, time_ms) = if ducked {
+                (10f32.powf(-rule.reduction_db.abs() / 20.0), rule.attack_ms)
+            } else {
+                (1.0, rule.release_ms)
+            };
+
+            let time_frames = (time_ms / 1000.0 * sample_rate).max(1.0);
+            let coef = 1.0 - (-(frame_count as f32) / time_frames).exp();
+            *envelope += (target - *envelope) * coef;
+
+            if let Some(gain) = self.gains.iter_mut().find(|gain| gain.group == rule.target) {
+                gain.start = gain.start.min(start);
+                gain.end = gain.end.min(*envelope);
+            }
+        }
+
+        self.active.clear();
+    }
+
+    /// Record that a source of `group` produced audio in the current block, peaking at `level`.
+    pub fn mark_active(&mut self, group: Option<u32>, level: f32) {
+        let Some(group) = group else {
+            return;
+        };
+
+        // Only trigger groups matter, which also bounds `active` by the reserved capacity
+        if let Some((_, peak)) = self.active.iter_mut().find(|(active, _)| *active == group) {
+            *peak = peak.max(level);
+        } else if self.rules.iter().any(|rule| rule.trigger == group) {
+            self.active.push((group, level));
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const SAMPLE_RATE: f32 = 48000.0;
+    const BLOCK: usize = 480;
+
+    fn target_gain(ducker: &Ducker) -> GroupGain {
+        GroupGain::find(ducker.gains(), Some(2)).unwrap()
+    }
+
+    // Render one block with the trigger group peaking at `level`, None leaves it silent
+    fn block(ducker: &mut Ducker, level: Option<f32>) -> GroupGain {
+        if let Some(level) = level {
+            ducker.mark_active(Some(1), level);
+        }
+
+        ducker.begin_block(BLOCK, SAMPLE_RATE);
+        target_gain(ducker)
+    }
+
+    #[test]
+    fn test_group_gain_ramp() {
+        let gain = GroupGain {
+            group: 0,
+            start: 1.0,
+            end: 0.5,
+        };
+
+        let mut buffer = [1.0f32; 8];
+        gain.apply(&mut buffer, 2);
+        assert_eq!(buffer, [0.875, 0.875, 0.75, 0.75, 0.625, 0.625, 0.5, 0.5]);
+
+        // Applied in sections the ramp carries on where the previous section stopped
+        let mut sections = [1.0f32; 4];
+        gain.apply_section(&mut sections[..2], 1, 0, 4);
+        gain.apply_section(&mut sections[2..], 1, 2, 4);
+        assert_eq!(sections, [0.875, 0.75, 0.625, 0.5]);
+    }
+
+    #[test]
+    fn test_source_ducking_sections() {
+        let gains = [GroupGain {
+            group: 3,
+            start: 1.0,
+            end: 0.5,
+        }];
+
+        let mut ducking = SourceDucking::new(Some(3), &gains, 4);
+        let mut buffer = [1.0f32, -0.25, 0.5, 0.5];
+        ducking.process(&mut buffer[..1], 1);
+        ducking.skip(1);
+        ducking.process(&mut buffer[2..], 1);
+
+        assert_eq!(buffer, [0.875, -0.25, 0.3125, 0.25]);
+        assert_eq!(ducking.level, 1.0);
+    }
+
+    #[test]
+    fn test_ducking_attack_and_release() {
+        let rule = DuckingRule {
+            trigger: 1,
+            target: 2,
+            ..Default::default()
+        };
+
+        let reduced = 10f32.powf(-rule.reduction_db / 20.0);
+        let mut ducker = Ducker::new(vec![rule]);
+
+        // Ramps down block by block towards the reduction
+        let mut previous = 1.0;
+        for _ in 0..30 {
+            let gain = block(&mut ducker, Some(0.5));
+            assert_eq!(gain.start, previous);
+            assert!(gain.end < previous && gain.end >= reduced);
+            previous = gain.end;
+        }
+
+        assert!((previous - reduced).abs() < 1e-2);
+
+        // Without a hold the release starts with the first silent block
+        for _ in 0..400 {
+            let gain = block(&mut ducker, None);
+            assert!(gain.end > previous && gain.end <= 1.0);
+            previous = gain.end;
+        }
+
+        assert!((previous - 1.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_ducking_threshold() {
+        let rule = DuckingRule::voice_over(1, 2);
+        let mut ducker = Ducker::new(vec![rule]);
+
+        // -40 dBFS is 0.01, anything quieter does not duck
+        for _ in 0..10 {
+            let gain = block(&mut ducker, Some(0.005));
+            assert_eq!((gain.start, gain.end), (1.0, 1.0));
+        }
+
+        let gain = block(&mut ducker, Some(0.02));
+        assert!(gain.end < 1.0);
+    }
+
+    #[test]
+    fn test_ducking_hold() {
+        let rule = DuckingRule::voice_over(1, 2);
+        let hold_blocks = (rule.hold_ms / 1000.0 * SAMPLE_RATE / BLOCK as f32).ceil() as usize;
+        let mut ducker = Ducker::new(vec![rule]);
+
+        let mut previous = 1.0;
+        for _ in 0..20 {
+            previous = block(&mut ducker, Some(0.5)).end;
+        }
+
+        // Held through the pause, the reduction keeps going towards its target
+        for _ in 0..hold_blocks {
+            let gain = block(&mut ducker, None);
+            assert!(gain.end <= previous);
+            previous = gain.end;
+        }
+
+        let gain = block(&mut ducker, None);
+        assert!(gain.end > previous);
+
+        // Triggering again restarts the hold
+        block(&mut ducker, Some(0.5));
+        previous = block(&mut ducker, None).end;
+        let gain = block(&mut ducker, None);
+        assert!(gain.end <= previous);
+    }
+
+    #[test]
+    fn test_ducking_inherit() {
+        let rule = DuckingRule {
+            trigger: 1,
+            target: 2,
+            ..Default::default()
+        };
+
+        let mut ducker = Ducker::new(vec![rule]);
+        for _ in 0..10 {
+            block(&mut ducker, Some(0.5));
+        }
+
+        let ducked = target_gain(&ducker).end;
+
+        // The replaced rules start from the reduction reached instead of full volume
+        let mut replaced = Ducker::new(vec![rule]);
+        replaced.inherit(&ducker);
+        let gain = block(&mut replaced, Some(0.5));
+        assert_eq!(gain.start, ducked);
+    }
+}
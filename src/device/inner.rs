@@ -1,68 +1,183 @@
 use miniaudio_sys::*;
-use std::sync::{Arc, TryLockError, mpsc::Receiver};
+use std::{
+    sync::{
+        Arc, TryLockError,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::Instant,
+};
 
 use crate::{
     DeviceInfo,
-    context::{DeviceType, MaContext},
-    device::{AudioHandle, DeviceError},
-    effects::{AudioPanner, SpatializationListener, AudioVolume, ChannelConverter},
-    math::{MathUtils, MathUtilsTrait as _},
+    context::{AudioContext, DeviceType, MaContext},
+    device::{
+        AudioHandle, DeviceCommand, DeviceError,
+        analyzer::AnalyzerTap,
+        command::{self, CommandReceiver, CommandSender},
+        monitor::MonitorFifo,
+        ducking::{Ducker, GroupGain, SourceDucking, block_peak},
+        parallel::ParallelMixer,
+        stats::{StatsHandle, StatsTap},
+    },
+    effects::{
+        AudioEffect, AudioPanner, SpatializationListener, AudioVolume, ChannelConverter, HrtfSet, Limiter,
+        ListenerSelection, Positioning, Spatialization,
+    },
+    math::{MathUtils, MathUtilsTrait as _, Vector3},
+    misc::{
+        denormal::DenormalGuard,
+        mixingmode::MixingMode,
+        precision::{self, MixBus, Precision},
+    },
+    encoder::recorder::RecorderTap,
+    utils,
 };
 
+pub(crate) type DeviceCallback = Box<dyn FnMut(&[f32], &mut [f32]) + Send + 'static>;
+pub(crate) type InputCallback = Box<dyn FnMut(&[f32]) + Send + 'static>;
+pub(crate) type OutputCallback = Box<dyn FnMut(&mut [f32]) + Send + 'static>;
+
 pub struct TrackChannelHandle {
     pub channel: AudioHandle,
     pub removed: bool,
+    // Updated by render_handle, read back for the ducking rules
+    pub group: Option<u32>,
+    pub active: bool,
+    // Peak of the last block before ducking, only measured for sources in a group
+    pub level: f32,
+}
+
+// Removes the monitors that were closed, handing them back with `wrap` to be dropped
+fn retire_inactive(
+    fifos: &mut Vec<Arc<MonitorFifo>>,
+    commands: &mut CommandReceiver,
+    wrap: fn(Arc<MonitorFifo>) -> DeviceCommand,
+) {
+    let mut index = 0;
+    while index < fifos.len() {
+        if fifos[index].is_active() {
+            index += 1;
+        } else {
+            commands.retire(wrap(fifos.remove(index)));
+        }
+    }
 }
 
 pub(crate) struct DeviceInner {
     pub context: Option<Arc<MaContext>>,
     pub device: Box<ma_device>,
     pub ty: DeviceType,
+    // No hardware is opened, rendered by [Device::advance](crate::Device::advance)
+    pub virtual_clock: bool,
+    // Set between start and stop, the callback only runs meanwhile
+    pub started: bool,
 
     pub handles: Vec<TrackChannelHandle>,
     pub volume: AudioVolume,
     pub panner: AudioPanner,
-    pub channel_converter: ChannelConverter,
-    pub buffer1: Vec<f32>,
-    pub buffer2: Vec<f32>,
+    pub scratch: MixScratch,
+    pub block_size: usize,
+    pub mixing_mode: MixingMode,
+    pub limiter: Limiter,
+    // Run on the summed sources before the callbacks and the mixing mode
+    pub master_effects: Vec<Box<dyn AudioEffect>>,
+    // Run on the captured input before it reaches the callbacks and the monitoring routes
+    pub input_effects: Vec<Box<dyn AudioEffect>>,
+    pub input_buffer: Vec<f32>,
+    // Silence fed to a virtual capture or duplex device, see [Device::advance_into](crate::Device::advance_into)
+    pub virtual_input: Vec<f32>,
+    pub precision: Precision,
+    pub ducker: Ducker,
+    // f64 bus used instead of the output block with [Precision::Double]
+    pub accumulator: Vec<f64>,
 
     // DSP callback
-    pub callback: Option<Box<dyn FnMut(&[f32], &mut [f32]) + Send + 'static>>,
-    pub input_callback: Option<Box<dyn FnMut(&[f32]) + Send + 'static>>,
-    pub output_callback: Option<Box<dyn FnMut(&mut [f32]) + Send + 'static>>,
+    pub callback: Option<DeviceCallback>,
+    pub input_callback: Option<InputCallback>,
+    pub output_callback: Option<OutputCallback>,
+
+    // Receives the final output while recording
+    pub recorder: Option<RecorderTap>,
+    pub analyzer: Option<AnalyzerTap>,
+
+    // Input monitoring routes, see [Device::monitor_input](crate::Device::monitor_input)
+    pub monitor_sends: Vec<Arc<MonitorFifo>>,
+    pub monitor_returns: Vec<Arc<MonitorFifo>>,
+
+    // Device time in frames of the block being mixed, see [Device::get_time](crate::Device::get_time)
+    pub clock: Arc<AtomicU64>,
+    // Published after every callback, see [Device::stats](crate::Device::stats)
+    pub stats: StatsTap,
 
     // Spatialization
     pub spatialization: Option<SpatializationListener>,
+    // Listeners after the primary one, index 1 onwards, see [ListenerSelection]
+    pub listeners: Vec<SpatializationListener>,
+    pub hrtf: Option<Arc<HrtfSet>>,
+
+    // Optional worker threads for mixing many sources
+    pub parallel: Option<ParallelMixer>,
 
-    pub receiver: Receiver<AudioHandle>,
+    // Only drained from the audio thread, see [DeviceCommand]
+    pub commands: CommandReceiver,
 }
 
 impl DeviceInner {
     pub fn new(
         config: DeviceInfo,
-    ) -> Result<(Box<Self>, std::sync::mpsc::Sender<AudioHandle>), DeviceError> {
+    ) -> Result<(Box<Self>, CommandSender), DeviceError> {
         unsafe {
-            let (sender, receiver) = std::sync::mpsc::channel();
+            let (sender, commands) = command::command_queue();
 
             let channel_count = config.channel;
             let sample_rate = config.sample_rate;
             let device_type = config.ty;
+            let block_size = match config.block_size {
+                0 => utils::DEFAULT_BLOCK_SIZE,
+                size => size,
+            };
+
+            let stats = StatsHandle::new(channel_count);
 
             let mut inner = Box::new(Self {
                 context: None,
                 device: Box::default(),
                 handles: Vec::new(),
                 ty: device_type,
-                buffer1: vec![0.0f32; 4096 * channel_count],
-                buffer2: vec![0.0f32; 4096 * channel_count],
+                virtual_clock: config.virtual_clock,
+                started: false,
+                scratch: MixScratch::new(utils::block_buffer_len(block_size, channel_count)),
+                block_size,
+                mixing_mode: MixingMode::default(),
+                limiter: Limiter::new(channel_count, sample_rate),
+                master_effects: Vec::new(),
+                input_effects: Vec::new(),
+                input_buffer: Vec::with_capacity(utils::block_buffer_len(
+                    block_size,
+                    channel_count.max(2),
+                )),
+                virtual_input: Vec::new(),
+                precision: config.precision,
+                ducker: Ducker::new(Vec::new()),
+                accumulator: vec![0.0f64; utils::block_buffer_len(block_size, channel_count)],
                 spatialization: None,
+                listeners: Vec::new(),
+                hrtf: None,
+                parallel: None,
                 volume: AudioVolume::new(channel_count).map_err(DeviceError::from_other)?,
                 panner: AudioPanner::new(channel_count).map_err(DeviceError::from_other)?,
-                channel_converter: ChannelConverter::new(),
                 callback: None,
                 input_callback: None,
-                output_callback: None,
-                receiver,
+                output_callback: config.dsp_callback.clone().map(|callback| {
+                    Box::new(move |output: &mut [f32]| callback(output)) as OutputCallback
+                }),
+                recorder: None,
+                analyzer: None,
+                monitor_sends: Vec::new(),
+                monitor_returns: Vec::new(),
+                clock: Arc::new(AtomicU64::new(0)),
+                stats: stats.tap(sample_rate),
+                commands,
             });
 
             let device_type = match config.ty {
@@ -71,11 +186,29 @@ impl DeviceInner {
                 DeviceType::Duplex => ma_device_type_duplex,
             };
 
+            if let Some(volume) = config.volume {
+                inner.volume.set_volume(volume);
+            }
+
+            if let Some(pan) = config.pan {
+                inner.panner.set_pan(pan);
+            }
+
+            if config.virtual_clock {
+                // Read back by process in place of the values miniaudio fills in
+                inner.device.playback.channels = channel_count as u32;
+                inner.device.sampleRate = sample_rate as u32;
+
+                return Ok((inner, sender));
+            }
+
             let mut devconfig = ma_device_config_init(device_type);
 
             devconfig.playback.format = ma_format_f32;
             devconfig.playback.channels = channel_count as u32;
+            devconfig.capture.format = ma_format_f32;
             devconfig.sampleRate = sample_rate as u32;
+            devconfig.periodSizeInFrames = config.buffer_frames as u32;
             devconfig.dataCallback = Some(audio_callback);
             devconfig.pUserData = inner.as_mut() as *mut _ as *mut std::ffi::c_void;
             devconfig.noClip = MA_TRUE as u8; // We use SIMD clamping
@@ -144,6 +277,21 @@ impl DeviceInner {
                 }
             }
 
+            let mismatched = matches!(
+                (&context, config.context),
+                (Some(context), Some(requested)) if !Arc::ptr_eq(context, &requested.inner)
+            );
+
+            if mismatched {
+                return Err(DeviceError::UnsupportedHardwareDevice);
+            }
+
+            // Without hardware from an enumeration, open the default device through the
+            // requested or shared context instead of a new one
+            let context = context
+                .or_else(|| config.context.map(|context| Arc::clone(&context.inner)))
+                .or_else(|| AudioContext::shared().ok().map(|context| context.inner));
+
             let result = if let Some(context) = context {
                 inner.context = Some(Arc::clone(&context));
                 ma_device_init(context.as_mut_ptr(), &devconfig, inner.device.as_mut())
@@ -155,250 +303,749 @@ impl DeviceInner {
                 return Err(DeviceError::InitializationError(result));
             }
 
+            // The capture side may open with more channels than the playback side
+            let input_channels = inner.input_channels();
+            inner
+                .input_buffer
+                .reserve(utils::block_buffer_len(block_size, input_channels));
+
             Ok((inner, sender))
         }
     }
 
     pub fn start(&mut self) -> Result<(), DeviceError> {
+        if self.virtual_clock {
+            return Ok(());
+        }
+
         unsafe {
             let result = ma_device_start(self.device.as_mut());
             if result != MA_SUCCESS {
                 return Err(DeviceError::InitializationError(result));
             }
         }
+        self.started = true;
         Ok(())
     }
 
     pub fn stop(&mut self) -> Result<(), DeviceError> {
+        if self.virtual_clock {
+            return Ok(());
+        }
+
         unsafe {
             let result = ma_device_stop(self.device.as_mut());
             if result != MA_SUCCESS {
                 return Err(DeviceError::InitializationError(result));
             }
         }
+        self.started = false;
         Ok(())
     }
 
-    pub fn set_callback<F>(&mut self, callback: Option<F>) -> Result<(), DeviceError>
-    where
-        F: FnMut(&[f32], &mut [f32]) + Send + 'static,
-    {
-        self.callback =
-            callback.map(|cb| Box::new(cb) as Box<dyn FnMut(&[f32], &mut [f32]) + Send + 'static>);
-        Ok(())
+    /// Whether the audio thread may be inside the callback. A virtual clock device only
+    /// renders under the device lock.
+    pub fn is_running(&self) -> bool {
+        self.started && !self.virtual_clock
     }
 
-    pub fn set_input_callback<F>(&mut self, callback: Option<F>) -> Result<(), DeviceError>
-    where
-        F: FnMut(&[f32]) + Send + 'static,
-    {
-        self.input_callback =
-            callback.map(|cb| Box::new(cb) as Box<dyn FnMut(&[f32]) + Send + 'static>);
-        Ok(())
+    pub fn drain_commands(&mut self) {
+        while let Some(command) = self.commands.try_recv() {
+            self.apply_command(command);
+        }
     }
 
-    pub fn set_output_callback<F>(&mut self, callback: Option<F>) -> Result<(), DeviceError>
-    where
-        F: FnMut(&mut [f32]) + Send + 'static,
-    {
-        self.output_callback =
-            callback.map(|cb| Box::new(cb) as Box<dyn FnMut(&mut [f32]) + Send + 'static>);
-        Ok(())
+    /// Values replaced by a command are handed back to the control thread to be dropped, so
+    /// the audio thread never frees memory or joins threads.
+    fn apply_command(&mut self, command: DeviceCommand) {
+        match command {
+            DeviceCommand::Attach(handle) => {
+                // Playing a source again keeps its entry, a second one would read it twice
+                let attached = self
+                    .handles
+                    .iter_mut()
+                    .find(|attached| attached.channel.ptr_eq(&handle));
+
+                if let Some(attached) = attached {
+                    attached.removed = false;
+                    self.commands.retire(DeviceCommand::Attach(handle));
+                    return;
+                }
+
+                self.handles.push(TrackChannelHandle {
+                    channel: handle,
+                    removed: false,
+                    group: None,
+                    active: false,
+                    level: 0.0,
+                });
+            }
+            DeviceCommand::Detach(handle) => {
+                self.handles
+                    .iter_mut()
+                    .filter(|attached| attached.channel.ptr_eq(&handle))
+                    .for_each(|attached| attached.removed = true);
+
+                self.commands.retire(DeviceCommand::Detach(handle));
+            }
+            DeviceCommand::Volume(volume) => self.volume.set_volume(volume),
+            DeviceCommand::Pan(pan) => self.panner.set_pan(pan),
+            DeviceCommand::PanMode(mode) => self.panner.set_mode(mode),
+            DeviceCommand::ForceMono(enabled) => self.panner.force_mono = enabled,
+            DeviceCommand::SwapChannels(enabled) => self.panner.swap_channels = enabled,
+            DeviceCommand::Callback(callback) => {
+                let old = std::mem::replace(&mut self.callback, callback);
+                self.commands.retire(DeviceCommand::Callback(old));
+            }
+            DeviceCommand::InputCallback(callback) => {
+                let old = std::mem::replace(&mut self.input_callback, callback);
+                self.commands.retire(DeviceCommand::InputCallback(old));
+            }
+            DeviceCommand::OutputCallback(callback) => {
+                let old = std::mem::replace(&mut self.output_callback, callback);
+                self.commands.retire(DeviceCommand::OutputCallback(old));
+            }
+            DeviceCommand::Spatialization(listener) => {
+                let listener = listener.map(|mut listener| {
+                    listener.hrtf = self.hrtf.clone();
+                    listener
+                });
+
+                let old = std::mem::replace(&mut self.spatialization, listener);
+                self.commands.retire(DeviceCommand::Spatialization(old));
+            }
+            DeviceCommand::Listeners(mut listeners) => {
+                for listener in listeners.iter_mut() {
+                    listener.hrtf = self.hrtf.clone();
+                }
+
+                let old = std::mem::replace(&mut self.listeners, listeners);
+                self.commands.retire(DeviceCommand::Listeners(old));
+            }
+            DeviceCommand::Ambisonics(bus) => match self.spatialization.as_mut() {
+                Some(listener) => {
+                    let old = std::mem::replace(&mut listener.ambisonics, bus);
+                    self.commands.retire(DeviceCommand::Ambisonics(old));
+                }
+                None => self.commands.retire(DeviceCommand::Ambisonics(bus)),
+            },
+            DeviceCommand::Reverb(bus) => match self.spatialization.as_mut() {
+                Some(listener) => {
+                    let old = std::mem::replace(&mut listener.reverb, bus);
+                    self.commands.retire(DeviceCommand::Reverb(old));
+                }
+                None => self.commands.retire(DeviceCommand::Reverb(bus)),
+            },
+            DeviceCommand::ReverbZones(zones) => {
+                match self
+                    .spatialization
+                    .as_mut()
+                    .and_then(|listener| listener.reverb.as_mut())
+                {
+                    Some(bus) => {
+                        let old = std::mem::replace(&mut bus.zones, zones);
+                        self.commands.retire(DeviceCommand::ReverbZones(old));
+                    }
+                    None => self.commands.retire(DeviceCommand::ReverbZones(zones)),
+                }
+            }
+            DeviceCommand::Hrtf(hrtf) => {
+                if let Some(listener) = self.spatialization.as_mut() {
+                    let old = std::mem::replace(&mut listener.hrtf, hrtf.clone());
+                    self.commands.retire(DeviceCommand::Hrtf(old));
+                }
+
+                for listener in self.listeners.iter_mut() {
+                    let old = std::mem::replace(&mut listener.hrtf, hrtf.clone());
+                    self.commands.retire(DeviceCommand::Hrtf(old));
+                }
+
+                let old = std::mem::replace(&mut self.hrtf, hrtf);
+                self.commands.retire(DeviceCommand::Hrtf(old));
+            }
+            DeviceCommand::ParallelMixer(parallel) => {
+                let old = std::mem::replace(&mut self.parallel, parallel);
+                self.commands.retire(DeviceCommand::ParallelMixer(old));
+            }
+            DeviceCommand::Recorder(recorder) => {
+                let old = std::mem::replace(&mut self.recorder, recorder);
+                self.commands.retire(DeviceCommand::Recorder(old));
+            }
+            DeviceCommand::Analyzer(analyzer) => {
+                let old = std::mem::replace(&mut self.analyzer, analyzer);
+                self.commands.retire(DeviceCommand::Analyzer(old));
+            }
+            DeviceCommand::MonitorSend(fifo) => self.monitor_sends.push(fifo),
+            DeviceCommand::MonitorReturn(fifo) => self.monitor_returns.push(fifo),
+            DeviceCommand::Precision(precision) => self.precision = precision,
+            DeviceCommand::Ducking(mut ducker) => {
+                ducker.inherit(&self.ducker);
+                let old = std::mem::replace(&mut self.ducker, ducker);
+                self.commands.retire(DeviceCommand::Ducking(old));
+            }
+            DeviceCommand::MasterEffects(effects) => {
+                let old = std::mem::replace(&mut self.master_effects, effects);
+                self.commands.retire(DeviceCommand::MasterEffects(old));
+            }
+            DeviceCommand::InputEffects(effects) => {
+                let old = std::mem::replace(&mut self.input_effects, effects);
+                self.commands.retire(DeviceCommand::InputEffects(old));
+            }
+            DeviceCommand::MixingMode(mode) => {
+                self.limiter.reset();
+                self.mixing_mode = mode;
+            }
+            DeviceCommand::Listener(snapshot) => {
+                if let Some(listener) = self.spatialization.as_mut() {
+                    snapshot.apply(listener);
+                }
+            }
+            DeviceCommand::ListenerAt(index, snapshot) => {
+                if let Some(listener) = self.listeners.get_mut(index.wrapping_sub(1)) {
+                    snapshot.apply(listener);
+                }
+            }
+            DeviceCommand::Batch(mut commands) => {
+                for command in commands.drain(..) {
+                    self.apply_command(command);
+                }
+
+                self.commands.retire(DeviceCommand::Batch(commands));
+            }
+        }
+    }
+
+    /// Sum every attached source into `output`, returns the number of sources that produced
+    /// frames.
+    fn mix_block<O: MixBus + ?Sized>(
+        &mut self,
+        output: &mut O,
+        frame_count: usize,
+        channels: usize,
+    ) -> usize {
+        // Workers render against their own copy of the primary listener, which has no
+        // ambisonics or reverb bus and no extra listeners next to it
+        let shared_bus = self
+            .spatialization
+            .as_ref()
+            .is_some_and(|listener| listener.ambisonics.is_some() || listener.reverb.is_some())
+            || !self.listeners.is_empty();
+
+        if let Some(parallel) = self.parallel.as_ref().filter(|_| !shared_bus) {
+            if let Some(active_sources) = parallel.mix(
+                &mut self.handles,
+                self.spatialization.as_mut(),
+                &mut self.scratch,
+                self.ducker.gains(),
+                output,
+                frame_count,
+            ) {
+                return active_sources;
+            }
+        }
+
+        let mut active_sources = 0;
+        for handle in self.handles.iter_mut() {
+            active_sources += render_handle(
+                handle,
+                self.spatialization.as_mut(),
+                &mut self.listeners,
+                &mut self.scratch,
+                self.ducker.gains(),
+                output,
+                frame_count,
+                channels,
+            ) as usize;
+        }
+
+        active_sources
     }
 
     pub fn process(
         &mut self,
         input: &[f32],
         output: &mut [f32],
+    ) -> Result<(), DeviceError> {
+        let started = Instant::now();
+        self.drain_commands();
+
+        let result = match self.input_effects.is_empty() || input.is_empty() {
+            true => self.process_input(input, output),
+            false => self.process_effected(input, output),
+        };
+
+        let channels = (self.device.playback.channels as usize).max(1);
+        let audio = match output.is_empty() {
+            true => input,
+            false => &*output,
+        };
+
+        let active_voices = self.handles.iter().filter(|handle| handle.active).count();
+        self.stats
+            .record(started, audio.len() / channels, active_voices, audio, channels);
+
+        result
+    }
+
+    fn process_effected(
+        &mut self,
+        input: &[f32],
+        output: &mut [f32],
+    ) -> Result<(), DeviceError> {
+        // Taken out for the duration of the callback so the processed input can be borrowed
+        // alongside the rest of self, sized up front for a single block.
+        let mut processed = std::mem::take(&mut self.input_buffer);
+
+        let input_channels = self.input_channels();
+        let output_channels = (self.device.playback.channels as usize).max(1);
+        let frame_count = crate::macros::frame_count_from!(input.len(), input_channels);
+
+        // Run in blocks so host periods larger than the block size never outgrow the buffer
+        let mut result = Ok(());
+        let mut start = 0;
+        while start < frame_count {
+            let end = (start + self.block_size).min(frame_count);
+
+            processed.clear();
+            processed.extend_from_slice(&input[start * input_channels..end * input_channels]);
+
+            for effect in self.input_effects.iter_mut() {
+                effect.process(&mut processed, input_channels);
+            }
+
+            let block = output
+                .get_mut(start * output_channels..end * output_channels)
+                .unwrap_or_default();
+
+            result = result.and(self.process_input(&processed, block));
+            start = end;
+        }
+
+        self.input_buffer = processed;
+
+        result
+    }
+
+    /// Channel count of the captured input, the playback count until the capture side opens.
+    fn input_channels(&self) -> usize {
+        match self.device.capture.channels {
+            0 => (self.device.playback.channels as usize).max(1),
+            channels => channels as usize,
+        }
+    }
+
+    fn process_input(
+        &mut self,
+        input: &[f32],
+        output: &mut [f32],
     ) -> Result<(), DeviceError> {
         MathUtils::simd_set(output, 0.0);
-        MathUtils::simd_set(&mut self.buffer1, 0.0);
-        MathUtils::simd_set(&mut self.buffer2, 0.0);
+        MathUtils::simd_set(&mut self.scratch.buffer1, 0.0);
+        MathUtils::simd_set(&mut self.scratch.buffer2, 0.0);
 
         let target_channel_count = self.device.playback.channels;
 
-        while let Ok(handle) = self.receiver.try_recv() {
-            self.handles.push(TrackChannelHandle {
-                channel: handle,
-                removed: false,
-            });
+        let commands = &mut self.commands;
+        retire_inactive(&mut self.monitor_sends, commands, DeviceCommand::MonitorSend);
+        retire_inactive(&mut self.monitor_returns, commands, DeviceCommand::MonitorReturn);
+
+        // Queued before mixing so a duplex device monitoring itself hears this callback's input
+        for fifo in self.monitor_sends.iter() {
+            fifo.push(input);
         }
 
-        if self.handles.is_empty() && self.callback.is_none() {
+        if self.handles.is_empty() && self.callback.is_none() && self.monitor_returns.is_empty() {
+            let frame_count = output.len() / (target_channel_count as usize).max(1);
+            self.clock.fetch_add(frame_count as u64, Ordering::Release);
+
+            if let Some(recorder) = self.recorder.as_mut() {
+                recorder.push(output);
+            }
+
+            if let Some(analyzer) = self.analyzer.as_mut() {
+                analyzer.push(output, target_channel_count as usize);
+            }
+
             return Ok(());
         }
 
-        let frame_count =
-            crate::macros::frame_count_from!(output.len(), target_channel_count as usize);
+        let channels = target_channel_count as usize;
+        let sample_rate = self.device.sampleRate as f32;
+        let block_len = crate::macros::array_len_from!(self.block_size, channels);
 
-        for handle in self.handles.iter_mut() {
-            if handle.removed {
-                continue;
-            }
-
-            match &handle.channel {
-                AudioHandle::Track(track_weak) => {
-                    if let Some(track_mutex) = track_weak.upgrade() {
-                        match track_mutex.try_lock() {
-                            Ok(mut track) => {
-                                match track.read(
-                                    self.spatialization.as_mut(),
-                                    &mut self.channel_converter,
-                                    &mut self.buffer1,
-                                    &mut self.buffer2,
-                                    frame_count,
-                                ) {
-                                    Ok(pcm_length) => {
-                                        if pcm_length > 0 {
-                                            let size =
-                                                pcm_length as usize * target_channel_count as usize;
-                                            MathUtils::simd_add(
-                                                &mut output[..size],
-                                                &self.buffer1[..size],
-                                            );
-                                        } else {
-                                            handle.removed = true;
-                                        }
-                                    }
-                                    Err(err) => {
-                                        eprintln!("Error reading PCM frames: {}", err);
-                                        handle.removed = true;
-                                    }
+        // Render in blocks so callbacks larger than the block size never outgrow the buffers
+        for block in output.chunks_mut(block_len) {
+            let frame_count = crate::macros::frame_count_from!(block.len(), channels);
+
+            if !self.ducker.is_empty() {
+                self.ducker.begin_block(frame_count, sample_rate);
+            }
+
+            let active_sources = match self.precision {
+                Precision::Single => self.mix_block(block, frame_count, channels),
+                Precision::Double => {
+                    // Taken out for the duration of the block so the bus can be borrowed
+                    // alongside the rest of self, this never reallocates.
+                    let mut accumulator = std::mem::take(&mut self.accumulator);
+                    let bus = &mut accumulator[..block.len()];
+                    MathUtils::simd_set(bus, 0.0);
+
+                    let active_sources = self.mix_block(bus, frame_count, channels);
+                    precision::narrow(bus, block);
+
+                    self.accumulator = accumulator;
+                    active_sources
+                }
+            };
+
+            if let Some(bus) = self
+                .spatialization
+                .as_mut()
+                .and_then(|listener| listener.ambisonics.as_mut())
+            {
+                bus.decode(block, channels);
+            }
+
+            if let Some(listener) = self.spatialization.as_mut() {
+                let position = listener.get_position();
+
+                if let Some(bus) = listener.reverb.as_mut() {
+                    bus.process(position, block, channels);
+                }
+            }
+
+            for handle in self.handles.iter().filter(|handle| handle.active) {
+                self.ducker.mark_active(handle.group, handle.level);
+            }
+
+            for fifo in self.monitor_returns.iter() {
+                fifo.pull(block, channels);
+            }
+
+            for effect in self.master_effects.iter_mut() {
+                effect.process(block, channels);
+            }
+
+            self.mixing_mode.apply(&mut self.limiter, block, active_sources);
+            self.clock.fetch_add(frame_count as u64, Ordering::Release);
+        }
+
+        if let Some(callback) = &mut self.callback {
+            callback(input, output);
+        }
+
+        if let Some(input_callback) = &mut self.input_callback {
+            input_callback(input);
+        }
+
+        if let Some(output_callback) = &mut self.output_callback {
+            output_callback(output);
+        }
+
+        for block in output.chunks_mut(block_len) {
+            let buffer1 = &mut self.scratch.buffer1[..block.len()];
+
+            if let Err(e) = self.panner.process(block, buffer1) {
+                eprintln!("Error processing panner: {}", e);
+            }
+
+            if let Err(e) = self.volume.process(buffer1, block) {
+                eprintln!("Error processing volume: {}", e);
+            }
+        }
+
+        // The last Weak of a source frees its allocation, so it is dropped on the control side
+        let mut index = 0;
+        while index < self.handles.len() {
+            if self.handles[index].removed {
+                let handle = self.handles.remove(index);
+                self.commands.retire(DeviceCommand::Detach(handle.channel));
+            } else {
+                index += 1;
+            }
+        }
+
+        MathUtils::simd_clamp(output, -1.0, 1.0);
+
+        if let Some(recorder) = self.recorder.as_mut() {
+            recorder.push(output);
+        }
+
+        if let Some(analyzer) = self.analyzer.as_mut() {
+            analyzer.push(output, channels);
+        }
+
+        return Ok(());
+    }
+}
+
+/// Working buffers used while reading sources, one set per mixing thread.
+pub(crate) struct MixScratch {
+    pub channel_converter: ChannelConverter,
+    pub buffer1: Vec<f32>,
+    pub buffer2: Vec<f32>,
+}
+
+impl MixScratch {
+    pub fn new(size: usize) -> Self {
+        Self {
+            channel_converter: ChannelConverter::new(),
+            buffer1: vec![0.0f32; size],
+            buffer2: vec![0.0f32; size],
+        }
+    }
+}
+
+/// Read a single attached source and add its output on top of `output`, returns whether
+/// the source produced any frames.
+pub(crate) fn render_handle<O: MixBus + ?Sized>(
+    handle: &mut TrackChannelHandle,
+    spatialization: Option<&mut SpatializationListener>,
+    listeners: &mut [SpatializationListener],
+    scratch: &mut MixScratch,
+    gains: &[GroupGain],
+    output: &mut O,
+    frame_count: usize,
+    channels: usize,
+) -> bool {
+    handle.active = false;
+    handle.level = 0.0;
+
+    if handle.removed {
+        return false;
+    }
+
+    let MixScratch {
+        channel_converter,
+        buffer1,
+        buffer2,
+    } = scratch;
+
+    match &handle.channel {
+        AudioHandle::Track(track_weak) => {
+            if let Some(track_mutex) = track_weak.upgrade() {
+                match track_mutex.try_lock() {
+                    Ok(mut track) => {
+                        track.ducking = SourceDucking::new(track.group, gains, frame_count);
+
+                        let listener = track.listener;
+                        let position = source_position(&track.spatializer);
+                        let result = track.read(
+                            select_listener(spatialization, listeners, listener, position),
+                            channel_converter,
+                            buffer1,
+                            buffer2,
+                            frame_count,
+                        );
+
+                        // Measured and ducked ahead of the spatial stage, which may have
+                        // encoded the output into the ambisonics bus
+                        let ducking = std::mem::take(&mut track.ducking);
+
+                        match result {
+                            Ok(pcm_length) => {
+                                if pcm_length > 0 {
+                                    let size = pcm_length as usize * channels;
+                                    handle.group = track.group;
+                                    handle.active = true;
+                                    handle.level = ducking.level;
+
+                                    output.accumulate(&buffer1[..size]);
+                                    return true;
+                                } else {
+                                    handle.removed = true;
                                 }
                             }
-                            Err(TryLockError::Poisoned(channel)) => {
-                                let ref_id = channel.get_ref().ref_id;
-
-                                eprintln!("Warning: Audio channel {} is poisoned", ref_id);
+                            Err(err) => {
+                                eprintln!("Error reading PCM frames: {}", err);
                                 handle.removed = true;
                             }
-                            Err(TryLockError::WouldBlock) => {
-                                continue;
-                            }
                         }
-                    } else {
+                    }
+                    Err(TryLockError::Poisoned(channel)) => {
+                        let ref_id = channel.get_ref().ref_id;
+
+                        eprintln!("Warning: Audio channel {} is poisoned", ref_id);
                         handle.removed = true;
                     }
+                    Err(TryLockError::WouldBlock) => {}
                 }
-                AudioHandle::Sample(sample_weak) => {
-                    if let Some(sample_mutex) = sample_weak.upgrade() {
-                        match sample_mutex.try_lock() {
-                            Ok(mut sample) => {
-                                match sample.read(
-                                    self.spatialization.as_mut(),
-                                    &mut self.channel_converter,
-                                    &mut self.buffer1,
-                                    &mut self.buffer2,
-                                    frame_count,
-                                ) {
-                                    Ok(pcm_length) => {
-                                        if pcm_length > 0 {
-                                            let size =
-                                                pcm_length as usize * target_channel_count as usize;
-                                            MathUtils::simd_add(
-                                                &mut output[..size],
-                                                &self.buffer1[..size],
-                                            );
-                                        } else {
-                                            handle.removed = true;
-                                        }
-                                    }
-                                    Err(err) => {
-                                        eprintln!("Error reading PCM frames from sample: {}", err);
-                                        handle.removed = true;
+            } else {
+                handle.removed = true;
+            }
+        }
+        AudioHandle::Sample(sample_weak) => {
+            if let Some(sample_mutex) = sample_weak.upgrade() {
+                match sample_mutex.try_lock() {
+                    Ok(mut sample) => {
+                        match sample.read(
+                            spatialization,
+                            channel_converter,
+                            buffer1,
+                            buffer2,
+                            frame_count,
+                        ) {
+                            Ok(pcm_length) => {
+                                if pcm_length > 0 {
+                                    let size = pcm_length as usize * channels;
+                                    handle.group = sample.group;
+                                    handle.active = true;
+                                    handle.level = block_peak(handle.group, &buffer1[..size]);
+
+                                    if let Some(gain) = GroupGain::find(gains, handle.group) {
+                                        gain.apply(&mut buffer1[..size], channels);
                                     }
+
+                                    output.accumulate(&buffer1[..size]);
+                                    return true;
+                                } else {
+                                    handle.removed = true;
                                 }
                             }
-                            Err(TryLockError::Poisoned(sample)) => {
-                                let ref_id = sample.get_ref().ref_id;
-
-                                eprintln!("Warning: Sample channel {} is poisoned", ref_id);
+                            Err(err) => {
+                                eprintln!("Error reading PCM frames from sample: {}", err);
                                 handle.removed = true;
                             }
-                            Err(TryLockError::WouldBlock) => {
-                                continue;
-                            }
                         }
-                    } else {
+                    }
+                    Err(TryLockError::Poisoned(sample)) => {
+                        let ref_id = sample.get_ref().ref_id;
+
+                        eprintln!("Warning: Sample channel {} is poisoned", ref_id);
                         handle.removed = true;
                     }
+                    Err(TryLockError::WouldBlock) => {}
                 }
-                AudioHandle::Mixer(mixer_weak) => {
-                    if let Some(mixer_mutex) = mixer_weak.upgrade() {
-                        match mixer_mutex.try_lock() {
-                            Ok(mut mixer) => {
-                                match mixer.read(
-                                    self.spatialization.as_mut(),
-                                    &mut self.channel_converter,
-                                    &mut self.buffer1,
-                                    &mut self.buffer2,
-                                    frame_count,
-                                ) {
-                                    Ok(pcm_length) => {
-                                        if pcm_length > 0 {
-                                            let size =
-                                                pcm_length as usize * target_channel_count as usize;
-                                            MathUtils::simd_add(
-                                                &mut output[..size],
-                                                &self.buffer1[..size],
-                                            );
-                                        } else {
-                                            handle.removed = true;
-                                        }
-                                    }
-                                    Err(err) => {
-                                        eprintln!("Error reading PCM frames from mixer: {}", err);
-                                        handle.removed = true;
-                                    }
+            } else {
+                handle.removed = true;
+            }
+        }
+        AudioHandle::Mixer(mixer_weak) => {
+            if let Some(mixer_mutex) = mixer_weak.upgrade() {
+                match mixer_mutex.try_lock() {
+                    Ok(mut mixer) => {
+                        mixer.ducking = SourceDucking::new(mixer.group, gains, frame_count);
+
+                        let listener = mixer.listener;
+                        let position = source_position(&mixer.spatializer);
+                        let result = mixer.read(
+                            select_listener(spatialization, listeners, listener, position),
+                            channel_converter,
+                            buffer1,
+                            buffer2,
+                            frame_count,
+                        );
+
+                        // Measured and ducked ahead of the spatial stage, which may have
+                        // encoded the output into the ambisonics bus
+                        let ducking = std::mem::take(&mut mixer.ducking);
+
+                        match result {
+                            Ok(pcm_length) => {
+                                if pcm_length > 0 {
+                                    let size = pcm_length as usize * channels;
+                                    handle.group = mixer.group;
+                                    handle.active = true;
+                                    handle.level = ducking.level;
+
+                                    output.accumulate(&buffer1[..size]);
+                                    return true;
+                                } else {
+                                    handle.removed = true;
                                 }
                             }
-                            Err(TryLockError::Poisoned(mixer)) => {
-                                let ref_id = mixer.get_ref().ref_id;
-
-                                eprintln!("Warning: Mixer channel {} is poisoned", ref_id);
+                            Err(err) => {
+                                eprintln!("Error reading PCM frames from mixer: {}", err);
                                 handle.removed = true;
                             }
-                            Err(TryLockError::WouldBlock) => {
-                                continue;
-                            }
                         }
-                    } else {
+                    }
+                    Err(TryLockError::Poisoned(mixer)) => {
+                        let ref_id = mixer.get_ref().ref_id;
+
+                        eprintln!("Warning: Mixer channel {} is poisoned", ref_id);
                         handle.removed = true;
                     }
+                    Err(TryLockError::WouldBlock) => {}
                 }
+            } else {
+                handle.removed = true;
             }
         }
+        AudioHandle::Split(split) => {
+            match split.read(
+                spatialization,
+                channel_converter,
+                buffer1,
+                buffer2,
+                frame_count,
+            ) {
+                Some(pcm_length) => {
+                    if pcm_length > 0 {
+                        let size = pcm_length as usize * channels;
+                        handle.group = split.output.group();
+                        handle.active = true;
+                        handle.level = block_peak(handle.group, &buffer1[..size]);
 
-        if let Some(callback) = &mut self.callback {
-            callback(input, output);
-        }
+                        if let Some(gain) = GroupGain::find(gains, handle.group) {
+                            gain.apply(&mut buffer1[..size], channels);
+                        }
 
-        if let Some(input_callback) = &mut self.input_callback {
-            input_callback(input);
+                        output.accumulate(&buffer1[..size]);
+                        return true;
+                    }
+                }
+                None => handle.removed = true,
+            }
         }
+    }
 
-        if let Some(output_callback) = &mut self.output_callback {
-            output_callback(output);
-        }
+    false
+}
 
-        let buffer1 = crate::macros::make_slice_mut!(
-            self.buffer1,
-            frame_count,
-            target_channel_count as usize
-        );
+/// World position of a spatialized source, None for sources placed relative to the listener.
+fn source_position(spatializer: &Option<Spatialization>) -> Option<Vector3<f32>> {
+    spatializer
+        .as_ref()
+        .filter(|spatializer| spatializer.get_positioning() == Positioning::Absolute)
+        .map(|spatializer| spatializer.get_position())
+}
 
-        if let Err(e) = self.panner.process(output, buffer1) {
-            eprintln!("Error processing panner: {}", e);
-        }
+/// The listener `selection` points at, `position` is only used by [ListenerSelection::Closest].
+/// Falls back to the primary listener when the selection does not resolve.
+fn select_listener<'a>(
+    primary: Option<&'a mut SpatializationListener>,
+    listeners: &'a mut [SpatializationListener],
+    selection: ListenerSelection,
+    position: Option<Vector3<f32>>,
+) -> Option<&'a mut SpatializationListener> {
+    // Extra listeners are only rendered while spatialization is enabled
+    let primary = primary?;
 
-        if let Err(e) = self.volume.process(buffer1, output) {
-            eprintln!("Error processing volume: {}", e);
-        }
+    let index = match (selection, position) {
+        (ListenerSelection::Index(index), _) => index,
+        (ListenerSelection::Closest, None) => 0,
+        (ListenerSelection::Closest, Some(position)) => {
+            let distance = |listener: &SpatializationListener| {
+                let offset = listener.get_position() - position;
+                offset.dot(offset)
+            };
 
-        self.handles.retain(|ch| !ch.removed);
-        MathUtils::simd_clamp(output, -1.0, 1.0);
+            let mut closest = (0, distance(&*primary));
+            for (index, listener) in listeners.iter().enumerate() {
+                let distance = distance(listener);
+                if distance < closest.1 {
+                    closest = (index + 1, distance);
+                }
+            }
 
-        return Ok(());
+            closest.0
+        }
+    };
+
+    match index.checked_sub(1) {
+        Some(index) if index < listeners.len() => Some(&mut listeners[index]),
+        _ => Some(primary),
     }
 }
 
@@ -410,6 +1057,8 @@ pub(crate) extern "C" fn audio_callback(
     _frameCount: u32,
 ) {
     let result = std::panic::catch_unwind(|| {
+        let _denormals = DenormalGuard::new();
+
         // SAFETY: All the pointers are valid and the function is called in a safe context.
         // The pointers were constructed by the miniaudio library and are valid for the duration of the callback
         // as long as the device is running and the array bounds within the frame count x channels are respected.
@@ -424,6 +1073,7 @@ pub(crate) extern "C" fn audio_callback(
                 .unwrap();
 
             let channel_count = device.playback.channels as usize;
+            let capture_channel_count = device.capture.channels as usize;
 
             let empty_input = [0f32; 0];
             let mut empty_output = [0f32; 0];
@@ -440,7 +1090,7 @@ pub(crate) extern "C" fn audio_callback(
                 DeviceType::Capture => {
                     let input = std::slice::from_raw_parts(
                         _pInput as *mut f32,
-                        _frameCount as usize * channel_count,
+                        _frameCount as usize * capture_channel_count,
                     );
 
                     (input, empty_output.as_mut_slice())
@@ -448,7 +1098,7 @@ pub(crate) extern "C" fn audio_callback(
                 DeviceType::Duplex => {
                     let input = std::slice::from_raw_parts(
                         _pInput as *mut f32,
-                        _frameCount as usize * channel_count,
+                        _frameCount as usize * capture_channel_count,
                     );
 
                     let output = std::slice::from_raw_parts_mut(
@@ -473,6 +1123,10 @@ pub(crate) extern "C" fn audio_callback(
 
 impl Drop for DeviceInner {
     fn drop(&mut self) {
+        if self.virtual_clock {
+            return;
+        }
+
         _ = self.stop();
 
         // SAFETY: This function is safe because it properly uninitializes the audio device and decoders.
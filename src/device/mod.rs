@@ -1,19 +1,51 @@
 use once_cell::sync::Lazy;
-use std::sync::{Arc, Mutex, Weak, mpsc::Sender};
+use std::sync::{
+    Arc, Mutex, Weak,
+    atomic::{AtomicU64, Ordering},
+};
 use thiserror::Error;
 
-use inner::DeviceInner;
+use analyzer::{AnalyzerTap, SpectrumAnalyzer};
+use command::CommandSender;
+use debugdump::GraphDump;
+use ducking::{Ducker, DuckingRule};
+use monitor::{InputMonitor, MonitorFifo};
+use inner::{DeviceCallback, DeviceInner, InputCallback, OutputCallback};
+use parallel::{ParallelMixer, ParallelMixerHandle};
+use snapshot::{DeviceSnapshot, ListenerSnapshot, SourceKind, SourceSnapshot};
+use splitter::SplitHandle;
+use stats::{Stats, StatsHandle};
+use tempo::{BeatClock, Quantize};
 
 use crate::{
-    context::{AudioHardwareInfo, DeviceType}, effects::{
-        SpartialListenerHandler, SpatializationListener, SpatializationListenerError,
+    context::{AudioContext, AudioHardwareInfo, DeviceType}, encoder::recorder::{RecordTarget, Recorder, RecorderTap}, effects::{
+        AmbisonicsBus, AmbisonicsDecoder, AudioEffect, HrtfSet, PanMode, ReverbBus, ReverbZone, SpartialListenerHandler, SpatializationListener, SpatializationListenerError,
     }, math::Vector3, misc::{
         audioattributes::AudioAttributes,
+        attributeobserver::{AttributeEvent, AttributeObservers},
         audiopropertyhandler::{PropertyError, PropertyHandler},
-    }, mixer::inner::MixerChannel, sample::sampleinner::SampleChannelHandle as SampleChannel, track::inner::TrackChannel, utils
+        bufferpool::{BufferPool, DEFAULT_POOL_SLOTS},
+        customattributes::{AttributeValue, CustomAttributes},
+        denormal::DenormalGuard,
+        finishsignal::PlaybackEnd,
+        memoryusage::MemoryUsage,
+        mixingmode::MixingMode,
+        precision::Precision,
+    }, mixer::inner::MixerChannel, sample::sampleinner::{SampleChannelHandle as SampleChannel, SampleChannelStatus}, track::inner::TrackChannel, utils
 };
 
+pub(crate) mod analyzer;
+pub(crate) mod command;
+pub(crate) mod crossfader;
+pub(crate) mod debugdump;
+pub(crate) mod ducking;
 pub(crate) mod inner;
+pub(crate) mod monitor;
+pub(crate) mod parallel;
+pub(crate) mod snapshot;
+pub(crate) mod splitter;
+pub(crate) mod stats;
+pub(crate) mod tempo;
 
 #[derive(Debug, Error)]
 pub enum DeviceError {
@@ -37,6 +69,22 @@ pub enum DeviceError {
     UnsupportedHardwareDevice,
     #[error("Failed to send audio handle to audio thread")]
     SendAudioHandleFailed,
+    #[error("Failed to send command to audio thread")]
+    SendCommandFailed,
+    #[error("Split devices must share the channel count and sample rate of the first device")]
+    SplitFormatMismatch,
+    #[error("Invalid tempo: {0} bpm")]
+    InvalidTempo(f32),
+    #[error("The device has no tempo set")]
+    NoTempo,
+    #[error("Input monitoring needs a capture or duplex device routed to a playback or duplex device")]
+    InvalidMonitorRoute,
+    #[error("Invalid analyzer size {0}, expected a power of two between 64 and 16384")]
+    InvalidAnalyzerSize(usize),
+    #[error("The device is not running on a virtual clock")]
+    NotVirtual,
+    #[error("Output holds fewer than the {0} samples to render")]
+    OutputTooShort(usize),
     #[error("{0}")]
     Other(Box<dyn std::error::Error + Send + 'static>), // Wraps other errors
 }
@@ -55,26 +103,308 @@ impl DeviceError {
     }
 }
 
+#[derive(Clone)]
 pub(crate) enum AudioHandle {
     Track(Weak<Mutex<TrackChannel>>),
     Sample(Weak<Mutex<SampleChannel>>),
     Mixer(Weak<Mutex<MixerChannel>>),
+    /// A track or mixer played on several devices through a [Splitter](splitter::Splitter).
+    Split(SplitHandle),
+}
+
+impl AudioHandle {
+    pub fn is_alive(&self) -> bool {
+        match self {
+            AudioHandle::Track(weak) => weak.strong_count() > 0,
+            AudioHandle::Sample(weak) => weak.strong_count() > 0,
+            AudioHandle::Mixer(weak) => weak.strong_count() > 0,
+            AudioHandle::Split(split) => split.channel.strong_count() > 0,
+        }
+    }
+
+    /// Whether the source still plays, the audio thread detaches it otherwise. Locks the
+    /// source, so only used on the control thread.
+    pub fn is_playing(&self) -> bool {
+        match self {
+            AudioHandle::Track(weak) => weak
+                .upgrade()
+                .is_some_and(|track| track.lock().is_ok_and(|t| t.playing.load(Ordering::SeqCst))),
+            AudioHandle::Sample(weak) => weak.upgrade().is_some_and(|sample| {
+                sample
+                    .lock()
+                    .is_ok_and(|s| s.status.load(Ordering::Relaxed) == SampleChannelStatus::Playing)
+            }),
+            AudioHandle::Mixer(weak) => weak.upgrade().is_some_and(|mixer| {
+                mixer
+                    .lock()
+                    .is_ok_and(|m| m.is_playing.load(Ordering::SeqCst))
+            }),
+            AudioHandle::Split(split) => {
+                split.channel.strong_count() > 0
+                    && !split.output.is_removed()
+                    && split.source.is_playing()
+            }
+        }
+    }
+
+    pub fn kind(&self) -> SourceKind {
+        match self {
+            AudioHandle::Track(_) => SourceKind::Track,
+            AudioHandle::Sample(_) => SourceKind::Sample,
+            AudioHandle::Mixer(_) => SourceKind::Mixer,
+            AudioHandle::Split(split) => split.source.kind(),
+        }
+    }
+
+    /// The handle of the source itself, a split resolves to the track or mixer it splits.
+    pub fn source(&self) -> &AudioHandle {
+        match self {
+            AudioHandle::Split(split) => split.source.as_ref(),
+            handle => handle,
+        }
+    }
+
+    /// Ref id of the source, None when it was dropped. Locks the source, so only used on
+    /// the control thread.
+    pub fn ref_id(&self) -> Option<usize> {
+        match self {
+            AudioHandle::Track(weak) => weak.upgrade()?.lock().ok().map(|t| t.ref_id),
+            AudioHandle::Sample(weak) => weak.upgrade()?.lock().ok().map(|s| s.ref_id),
+            AudioHandle::Mixer(weak) => weak.upgrade()?.lock().ok().map(|m| m.ref_id),
+            AudioHandle::Split(split) => split.source.ref_id(),
+        }
+    }
+
+    /// Memory held by the source, nothing once it was dropped. Locks the source, so only
+    /// used on the control thread.
+    pub fn memory_usage(&self) -> MemoryUsage {
+        let usage = match self {
+            AudioHandle::Track(weak) => weak
+                .upgrade()
+                .and_then(|t| t.lock().ok().map(|t| t.memory_usage())),
+            AudioHandle::Sample(weak) => weak
+                .upgrade()
+                .and_then(|s| s.lock().ok().map(|s| s.memory_usage())),
+            AudioHandle::Mixer(weak) => weak
+                .upgrade()
+                .and_then(|m| m.lock().ok().map(|m| m.memory_usage())),
+            AudioHandle::Split(split) => Some(split.source.memory_usage()),
+        };
+
+        usage.unwrap_or_default()
+    }
+
+    pub fn is(&self, kind: SourceKind, ref_id: usize) -> bool {
+        self.kind() == kind && self.ref_id() == Some(ref_id)
+    }
+
+    pub fn ptr_eq(&self, other: &AudioHandle) -> bool {
+        match (self, other) {
+            (AudioHandle::Track(a), AudioHandle::Track(b)) => Weak::ptr_eq(a, b),
+            (AudioHandle::Sample(a), AudioHandle::Sample(b)) => Weak::ptr_eq(a, b),
+            (AudioHandle::Mixer(a), AudioHandle::Mixer(b)) => Weak::ptr_eq(a, b),
+            (AudioHandle::Split(a), AudioHandle::Split(b)) => {
+                Weak::ptr_eq(&a.channel, &b.channel) && a.device_ref_id == b.device_ref_id
+            }
+            _ => false,
+        }
+    }
+
+    /// Stop the source so anything waiting on it sees it finish. A split only stops playing
+    /// on its device, the other devices keep playing the source.
+    fn stop(&self) {
+        match self {
+            AudioHandle::Track(weak) => {
+                if let Some(Ok(track)) = weak.upgrade().as_deref().map(Mutex::lock) {
+                    track.playing.store(false, Ordering::SeqCst);
+                    track.finished.notify(PlaybackEnd::Stopped);
+                }
+            }
+            AudioHandle::Sample(weak) => {
+                if let Some(Ok(sample)) = weak.upgrade().as_deref().map(Mutex::lock) {
+                    sample
+                        .status
+                        .store(SampleChannelStatus::Finished, Ordering::Relaxed);
+                }
+            }
+            AudioHandle::Mixer(weak) => {
+                if let Some(Ok(mut mixer)) = weak.upgrade().as_deref().map(Mutex::lock) {
+                    mixer.stop();
+                }
+            }
+            AudioHandle::Split(split) => {
+                if let Some(Ok(mut splitter)) = split.channel.upgrade().as_deref().map(Mutex::lock) {
+                    splitter.remove_output(split.device_ref_id);
+                }
+            }
+        }
+    }
+}
+
+/// Messages sent from the control thread to the audio thread.
+///
+/// The audio thread drains the queue at the start of every callback, this way
+/// the device state is only ever mutated by the audio thread and the callback
+/// never has to wait on a mutex held by the control thread.
+pub(crate) enum DeviceCommand {
+    Attach(AudioHandle),
+    Detach(AudioHandle),
+    Volume(f32),
+    Pan(f32),
+    PanMode(PanMode),
+    ForceMono(bool),
+    SwapChannels(bool),
+    Callback(Option<DeviceCallback>),
+    InputCallback(Option<InputCallback>),
+    OutputCallback(Option<OutputCallback>),
+    Spatialization(Option<SpatializationListener>),
+    Listener(ListenerSnapshot),
+    /// Listeners after the primary one, see [ListenerSelection](crate::ListenerSelection).
+    Listeners(Vec<SpatializationListener>),
+    ListenerAt(usize, ListenerSnapshot),
+    ParallelMixer(Option<ParallelMixer>),
+    MixingMode(MixingMode),
+    MasterEffects(Vec<Box<dyn AudioEffect>>),
+    InputEffects(Vec<Box<dyn AudioEffect>>),
+    Precision(Precision),
+    Ducking(Ducker),
+    Hrtf(Option<Arc<HrtfSet>>),
+    Ambisonics(Option<AmbisonicsBus>),
+    Reverb(Option<ReverbBus>),
+    ReverbZones(Vec<ReverbZone>),
+    Recorder(Option<RecorderTap>),
+    Analyzer(Option<AnalyzerTap>),
+    /// Queue the input of this device into the fifo.
+    MonitorSend(Arc<MonitorFifo>),
+    /// Mix the frames of the fifo into the output of this device.
+    MonitorReturn(Arc<MonitorFifo>),
+    /// Applied together in the same callback.
+    Batch(Vec<DeviceCommand>),
+}
+
+/// Control thread mirror of the values sent to the audio thread, used to answer
+/// getters without touching [DeviceInner].
+pub(crate) struct DeviceState {
+    pub volume: f32,
+    pub pan: f32,
+    pub pan_mode: PanMode,
+    pub force_mono: bool,
+    pub swap_channels: bool,
+    pub listener: Option<ListenerSnapshot>,
+    pub listeners: Vec<ListenerSnapshot>,
+    pub mixing_mode: MixingMode,
+    pub precision: Precision,
+    pub ducking_rules: Vec<DuckingRule>,
+    pub hrtf: Option<Arc<HrtfSet>>,
+    pub ambisonics: Option<AmbisonicsDecoder>,
+    pub reverb_zones: Vec<ReverbZone>,
+    pub handles: Vec<AudioHandle>,
+    pub custom_attributes: CustomAttributes,
+    pub tempo: Option<BeatClock>,
 }
 
-#[derive(Default, Debug, Clone)]
+/// Post-mix processing on the device output, see [DeviceInfo::dsp_callback].
+pub type DspCallback = Arc<dyn Fn(&mut [f32]) + Send + Sync + 'static>;
+
+#[derive(Default, Clone)]
 pub struct DeviceInfo<'a> {
     pub ty: DeviceType,
     pub channel: usize,
     pub sample_rate: f32,
     pub input: Option<&'a AudioHardwareInfo>,
     pub output: Option<&'a AudioHardwareInfo>,
+    /// Context the device is opened through, the one `input` and `output` were enumerated
+    /// from when set. None uses their context, or [AudioContext::shared] without them.
+    pub context: Option<&'a AudioContext>,
+    /// Frames rendered per block, larger callbacks are split into blocks of this size.
+    /// 0 uses the default of 4096 frames.
+    pub block_size: usize,
+    /// Frames per hardware period requested from the backend, 0 lets the backend decide.
+    pub buffer_frames: usize,
+    /// Format the attached sources are summed in, see [Precision].
+    pub precision: Precision,
+    /// Master volume from the first callback on, defaults to 1.0.
+    pub volume: Option<f32>,
+    /// Master pan from the first callback on, defaults to 0.0.
+    pub pan: Option<f32>,
+    /// Installed as the output callback before the device starts, see
+    /// [Device::set_output_callback].
+    pub dsp_callback: Option<DspCallback>,
+    /// Open no hardware and only render when [Device::advance] is called, so tests of
+    /// mixers, fades and scheduling produce the same output on every run.
+    pub virtual_clock: bool,
+}
+
+impl std::fmt::Debug for DeviceInfo<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("DeviceInfo")
+            .field("ty", &self.ty)
+            .field("channel", &self.channel)
+            .field("sample_rate", &self.sample_rate)
+            .field("input", &self.input)
+            .field("output", &self.output)
+            .field("context", &self.context)
+            .field("block_size", &self.block_size)
+            .field("buffer_frames", &self.buffer_frames)
+            .field("precision", &self.precision)
+            .field("volume", &self.volume)
+            .field("pan", &self.pan)
+            .field("dsp_callback", &self.dsp_callback.is_some())
+            .field("virtual_clock", &self.virtual_clock)
+            .finish()
+    }
+}
+
+/// The settings of a [DeviceInfo] as an owned value, so devices can be described in data
+/// files. The info from [DeviceConfig::info] opens the default hardware without a callback.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct DeviceConfig {
+    pub ty: DeviceType,
+    pub channel: usize,
+    pub sample_rate: f32,
+    pub block_size: usize,
+    pub buffer_frames: usize,
+    pub precision: Precision,
+    pub volume: Option<f32>,
+    pub pan: Option<f32>,
+    pub virtual_clock: bool,
+}
+
+impl DeviceConfig {
+    pub fn info(&self) -> DeviceInfo<'static> {
+        DeviceInfo {
+            ty: self.ty,
+            channel: self.channel,
+            sample_rate: self.sample_rate,
+            block_size: self.block_size,
+            buffer_frames: self.buffer_frames,
+            precision: self.precision,
+            volume: self.volume,
+            pan: self.pan,
+            virtual_clock: self.virtual_clock,
+            ..Default::default()
+        }
+    }
 }
 
 /// A hardware audio device, used to play audio comes from Channel and Mixer.
 pub struct Device {
     pub(crate) device_ref_id: u32,
     pub(crate) inner: Arc<Mutex<Box<DeviceInner>>>,
-    pub(crate) sender: Sender<AudioHandle>,
+    pub(crate) sender: Mutex<CommandSender>,
+    pub(crate) state: Mutex<DeviceState>,
+    pub(crate) channels: usize,
+    pub(crate) sample_rate: f32,
+    pub(crate) block_size: usize,
+    pub(crate) pool: Arc<BufferPool>,
+    pub(crate) clock: Arc<AtomicU64>,
+    pub(crate) stats: StatsHandle,
+    pub(crate) parallel: Option<ParallelMixerHandle>,
+    pub(crate) recorder: Option<Recorder>,
+    pub(crate) analyzer: Option<SpectrumAnalyzer>,
+    pub(crate) observers: AttributeObservers,
 
     // Used for lifetime management of the hardware context
     #[allow(dead_code)]
@@ -95,6 +425,14 @@ impl Device {
     pub(crate) fn new(config: DeviceInfo) -> Result<Self, DeviceError> {
         let input = config.input.cloned();
         let output = config.output.cloned();
+        let channels = config.channel;
+        let sample_rate = config.sample_rate;
+        let precision = config.precision;
+        let volume = config.volume.unwrap_or(1.0);
+        let pan = config.pan.unwrap_or(0.0);
+
+        // Large enough for the AudioFX pre-roll of one second of audio at the device rate
+        let preroll_capacity = config.sample_rate as usize * channels.max(2);
 
         let result = DeviceInner::new(config);
         if let Err(e) = result {
@@ -102,13 +440,54 @@ impl Device {
         }
 
         let (inner, sender) = result.unwrap();
+        let block_size = inner.block_size;
+        let clock = inner.clock.clone();
+        let stats = inner.stats.handle();
+
+        // Every mixing thread can hold a few nested render buffers at once
+        let threads = std::thread::available_parallelism().map_or(1, |n| n.get());
+        let pool = BufferPool::new(&[
+            (
+                DEFAULT_POOL_SLOTS * threads,
+                utils::block_buffer_len(block_size, channels.max(2)),
+            ),
+            (DEFAULT_POOL_SLOTS, preroll_capacity),
+        ]);
 
         let new_id = generate_device_id();
 
         Ok(Device {
             device_ref_id: new_id,
             inner: Arc::new(Mutex::new(inner)),
-            sender,
+            sender: Mutex::new(sender),
+            state: Mutex::new(DeviceState {
+                volume,
+                pan,
+                pan_mode: PanMode::default(),
+                force_mono: false,
+                swap_channels: false,
+                listener: None,
+                listeners: Vec::new(),
+                mixing_mode: MixingMode::default(),
+                precision,
+                ducking_rules: Vec::new(),
+                hrtf: None,
+                ambisonics: None,
+                reverb_zones: Vec::new(),
+                handles: Vec::new(),
+                custom_attributes: CustomAttributes::default(),
+                tempo: None,
+            }),
+            channels,
+            sample_rate,
+            block_size,
+            pool: Arc::new(pool),
+            clock,
+            stats,
+            parallel: None,
+            recorder: None,
+            analyzer: None,
+            observers: AttributeObservers::default(),
             input,
             output,
         })
@@ -130,16 +509,148 @@ impl Device {
         inner.stop()
     }
 
+    /// Frames rendered since the device was created, advanced once per mixed block. Used as
+    /// the time base for [Track::set_attribute_f32_at](crate::Track::set_attribute_f32_at).
+    pub fn get_time(&self) -> u64 {
+        self.clock.load(Ordering::Acquire)
+    }
+
+    /// [Device::get_time] in seconds.
+    pub fn get_time_secs(&self) -> f64 {
+        self.get_time() as f64 / self.sample_rate as f64
+    }
+
+    /// Whether the device was created with [DeviceInfo::virtual_clock].
+    pub fn is_virtual(&self) -> bool {
+        self.inner.lock().is_ok_and(|inner| inner.virtual_clock)
+    }
+
+    /// Render the next `frames` frames of a virtual clock device and move its clock forward,
+    /// returns the interleaved output. A capture device is fed silence and returns no output.
+    pub fn advance(&mut self, frames: usize) -> Result<Vec<f32>, DeviceError> {
+        let mut output = match self.device_type()? {
+            DeviceType::Capture => Vec::new(),
+            DeviceType::Playback | DeviceType::Duplex => vec![0.0f32; frames * self.channels],
+        };
+
+        self.advance_into(&mut output, frames)?;
+        Ok(output)
+    }
+
+    /// Like [Device::advance] rendering into the start of `output`, which a capture device
+    /// leaves untouched. The silence fed to capture and duplex devices is kept by the device,
+    /// so rendering allocates nothing once a block of this size was rendered.
+    pub fn advance_into(&mut self, output: &mut [f32], frames: usize) -> Result<(), DeviceError> {
+        let Ok(mut inner) = self.inner.lock() else {
+            return Err(DeviceError::InvalidOperation(-1)); // Use a custom error code for lock failure
+        };
+
+        if !inner.virtual_clock {
+            return Err(DeviceError::NotVirtual);
+        }
+
+        let len = frames * self.channels;
+        if inner.ty != DeviceType::Capture && output.len() < len {
+            return Err(DeviceError::OutputTooShort(len));
+        }
+
+        let mut silence = std::mem::take(&mut inner.virtual_input);
+        if inner.ty != DeviceType::Playback && silence.len() < len {
+            silence.resize(len, 0.0);
+        }
+
+        let (input, output) = match inner.ty {
+            DeviceType::Playback => (&silence[..0], &mut output[..len]),
+            DeviceType::Capture => (&silence[..len], &mut output[..0]),
+            DeviceType::Duplex => (&silence[..len], &mut output[..len]),
+        };
+
+        let _denormals = DenormalGuard::new();
+        let result = inner.process(input, output);
+        inner.virtual_input = silence;
+
+        result
+    }
+
+    /// Lay a tempo grid over the device clock with bar one starting now, used by the
+    /// quantized plays such as [Track::play_quantized](crate::Track::play_quantized).
+    pub fn set_tempo(&mut self, bpm: f32, beats_per_bar: u32) -> Result<(), DeviceError> {
+        self.set_tempo_at(bpm, beats_per_bar, self.get_time())
+    }
+
+    /// Like [Device::set_tempo] with bar one starting at device time `origin`, see
+    /// [Device::get_time].
+    pub fn set_tempo_at(
+        &mut self,
+        bpm: f32,
+        beats_per_bar: u32,
+        origin: u64,
+    ) -> Result<(), DeviceError> {
+        if !bpm.is_finite() || bpm <= 0.0 {
+            return Err(DeviceError::InvalidTempo(bpm));
+        }
+
+        let Ok(mut state) = self.state.lock() else {
+            return Err(DeviceError::InvalidOperation(-1)); // Use a custom error code for lock failure
+        };
+
+        state.tempo = Some(BeatClock {
+            bpm,
+            beats_per_bar: beats_per_bar.max(1),
+            origin,
+        });
+
+        Ok(())
+    }
+
+    pub fn clear_tempo(&mut self) -> Result<(), DeviceError> {
+        let Ok(mut state) = self.state.lock() else {
+            return Err(DeviceError::InvalidOperation(-1)); // Use a custom error code for lock failure
+        };
+
+        state.tempo = None;
+        Ok(())
+    }
+
+    /// Beats per minute and beats per bar of the tempo grid, None when no tempo is set.
+    pub fn get_tempo(&self) -> Option<(f32, u32)> {
+        self.state
+            .lock()
+            .ok()
+            .and_then(|state| state.tempo)
+            .map(|tempo| (tempo.bpm, tempo.beats_per_bar))
+    }
+
+    /// Device time of the next beat or bar of the tempo grid.
+    pub fn next_grid_time(&self, quantize: Quantize) -> Result<u64, DeviceError> {
+        let Some(tempo) = self.state.lock().ok().and_then(|state| state.tempo) else {
+            return Err(DeviceError::NoTempo);
+        };
+
+        Ok(tempo.next(quantize, self.get_time(), self.sample_rate))
+    }
+
+    /// Call `callback` whenever a master attribute is changed through [PropertyHandler], with
+    /// the attribute and its new value.
+    pub fn on_attribute_changed<F>(&mut self, callback: F)
+    where
+        F: FnMut(&AttributeEvent) + Send + 'static,
+    {
+        self.observers.add(Box::new(callback));
+    }
+
+    /// Remove every callback added with [Device::on_attribute_changed].
+    pub fn clear_attribute_observers(&mut self) {
+        self.observers.clear();
+    }
+
     /// Set callback for both input and output. If you want to set them separately, use set_input_callback and set_output_callback instead.
     pub fn set_callback<F>(&mut self, callback: Option<F>) -> Result<(), DeviceError>
     where
         F: FnMut(&[f32], &mut [f32]) + Send + 'static,
     {
-        let Ok(mut inner) = self.inner.lock() else {
-            return Err(DeviceError::InvalidOperation(-1)); // Use a custom error code for lock failure
-        };
-
-        inner.set_callback(callback)
+        let callback = callback.map(|cb| Box::new(cb) as DeviceCallback);
+        self.send(DeviceCommand::Callback(callback))
     }
 
     /// Set callback for input only. If you want to set both input and output callback at the same time, use set_callback instead.
@@ -147,11 +658,8 @@ impl Device {
     where
         F: FnMut(&[f32]) + Send + 'static,
     {
-        let Ok(mut inner) = self.inner.lock() else {
-            return Err(DeviceError::InvalidOperation(-1)); // Use a custom error code for lock failure
-        };
-
-        inner.set_input_callback(callback)
+        let callback = callback.map(|cb| Box::new(cb) as InputCallback);
+        self.send(DeviceCommand::InputCallback(callback))
     }
 
     /// Set callback for output only. If you want to set both input and output callback at the same time, use set_callback instead.
@@ -159,295 +667,1056 @@ impl Device {
     where
         F: FnMut(&mut [f32]) + Send + 'static,
     {
-        let Ok(mut inner) = self.inner.lock() else {
+        let callback = callback.map(|cb| Box::new(cb) as OutputCallback);
+        self.send(DeviceCommand::OutputCallback(callback))
+    }
+
+    /// Set how the attached sources are summed into the output.
+    pub fn set_mixing_mode(&mut self, mode: MixingMode) -> Result<(), DeviceError> {
+        let Ok(mut state) = self.state.lock() else {
             return Err(DeviceError::InvalidOperation(-1)); // Use a custom error code for lock failure
         };
 
-        inner.set_output_callback(callback)
+        state.mixing_mode = mode;
+        self.send(DeviceCommand::MixingMode(mode))
     }
 
-    pub(crate) fn get_ref_id(&self) -> u32 {
-        self.device_ref_id
+    /// Replace the effects run on the sum of every source, in order, before the DSP callbacks
+    /// and the clipping of the mixing mode. Each effect is reset before it is inserted.
+    pub fn set_master_effects(
+        &mut self,
+        mut effects: Vec<Box<dyn AudioEffect>>,
+    ) -> Result<(), DeviceError> {
+        effects.iter_mut().for_each(|effect| effect.reset());
+        self.send(DeviceCommand::MasterEffects(effects))
     }
 
-    pub(crate) fn attach_track(&mut self, track: &crate::Track) -> Result<(), DeviceError> {
-        let weak = Arc::downgrade(&track.inner);
-
-        if let Err(_) = self.sender.send(AudioHandle::Track(weak)) {
-            return Err(DeviceError::SendAudioHandleFailed);
-        }
-
-        Ok(())
+    pub fn clear_master_effects(&mut self) -> Result<(), DeviceError> {
+        self.send(DeviceCommand::MasterEffects(Vec::new()))
     }
 
-    pub(crate) fn attach_sample(
+    /// Replace the effects run on the input of a capture or duplex device, in order, before
+    /// the input reaches the callbacks and the monitoring routes. Each effect is reset before
+    /// it is inserted, see [NoiseSuppressor](crate::NoiseSuppressor) to clean up a microphone.
+    pub fn set_input_effects(
         &mut self,
-        sample: &crate::sample::SampleChannel,
+        mut effects: Vec<Box<dyn AudioEffect>>,
     ) -> Result<(), DeviceError> {
-        let weak = Arc::downgrade(&sample.inner);
+        effects.iter_mut().for_each(|effect| effect.reset());
+        self.send(DeviceCommand::InputEffects(effects))
+    }
 
-        if let Err(_) = self.sender.send(AudioHandle::Sample(weak)) {
-            return Err(DeviceError::SendAudioHandleFailed);
-        }
+    pub fn clear_input_effects(&mut self) -> Result<(), DeviceError> {
+        self.send(DeviceCommand::InputEffects(Vec::new()))
+    }
 
-        Ok(())
+    pub fn get_mixing_mode(&self) -> MixingMode {
+        self.state
+            .lock()
+            .map(|state| state.mixing_mode)
+            .unwrap_or_default()
     }
 
-    pub(crate) fn attach_mixer(&mut self, mixer: &crate::Mixer) -> Result<(), DeviceError> {
-        let weak = Arc::downgrade(&mixer.inner);
+    /// Set the format the attached sources are summed in before the output is handed to the
+    /// hardware as f32.
+    pub fn set_precision(&mut self, precision: Precision) -> Result<(), DeviceError> {
+        let Ok(mut state) = self.state.lock() else {
+            return Err(DeviceError::InvalidOperation(-1)); // Use a custom error code for lock failure
+        };
 
-        if let Err(_) = self.sender.send(AudioHandle::Mixer(weak)) {
-            return Err(DeviceError::SendAudioHandleFailed);
-        }
+        state.precision = precision;
+        self.send(DeviceCommand::Precision(precision))
+    }
 
-        Ok(())
+    pub fn get_precision(&self) -> Precision {
+        self.state
+            .lock()
+            .map(|state| state.precision)
+            .unwrap_or_default()
     }
-}
 
-impl PropertyHandler for Device {
-    fn get_attribute_f32(&self, _type: AudioAttributes) -> Result<f32, PropertyError> {
-        let inner = self.inner.lock().unwrap();
+    /// Replace the ducking rules evaluated between source groups, an empty list disables ducking.
+    pub fn set_ducking_rules(&mut self, rules: &[DuckingRule]) -> Result<(), DeviceError> {
+        let Ok(mut state) = self.state.lock() else {
+            return Err(DeviceError::InvalidOperation(-1)); // Use a custom error code for lock failure
+        };
 
-        match _type {
-            AudioAttributes::Unknown => {
-                Err(PropertyError::UnsupportedAttribute("Unknown attribute"))
-            }
-            AudioAttributes::Volume => Ok(inner.volume.volume),
-            AudioAttributes::Pan => Ok(inner.panner.pan),
-            AudioAttributes::FXEnabled => Err(PropertyError::UnsupportedAttribute(
-                "AudioFX is not supported, use set_attribute_bool to enable it",
-            )),
-            AudioAttributes::SpatializationEnabled => Err(PropertyError::UnsupportedAttribute(
-                "AudioSpatialization is not supported, use set_attribute_bool to enable it",
-            )),
-            _ => Err(PropertyError::UnsupportedAttribute("Unsupported attribute")),
-        }
+        state.ducking_rules = rules.to_vec();
+        self.send(DeviceCommand::Ducking(Ducker::new(rules.to_vec())))
     }
 
-    fn set_attribute_f32(
-        &mut self,
-        _type: AudioAttributes,
-        _value: f32,
-    ) -> Result<(), PropertyError> {
-        let mut inner = self.inner.lock().unwrap();
+    pub fn get_ducking_rules(&self) -> Vec<DuckingRule> {
+        self.state
+            .lock()
+            .map(|state| state.ducking_rules.clone())
+            .unwrap_or_default()
+    }
 
-        match _type {
-            AudioAttributes::Unknown => {
-                Err(PropertyError::UnsupportedAttribute("Unknown attribute"))
-            }
-            AudioAttributes::Volume => {
-                inner.volume.set_volume(_value);
-                Ok(())
-            }
-            AudioAttributes::Pan => {
-                inner.panner.set_pan(_value);
-                Ok(())
-            }
-            AudioAttributes::FXEnabled => Err(PropertyError::UnsupportedAttribute(
-                "AudioFX is not supported, use set_attribute_bool to enable it",
-            )),
-            AudioAttributes::SpatializationEnabled => Err(PropertyError::UnsupportedAttribute(
-                "AudioSpatialization is not supported, use set_attribute_bool to enable it",
-            )),
-            _ => Err(PropertyError::UnsupportedAttribute("Unsupported attribute")),
-        }
+    /// Choose how [AudioAttributes::Pan] moves stereo content, see [PanMode].
+    pub fn set_pan_mode(&mut self, mode: PanMode) -> Result<(), DeviceError> {
+        let Ok(mut state) = self.state.lock() else {
+            return Err(DeviceError::InvalidOperation(-1)); // Use a custom error code for lock failure
+        };
+
+        state.pan_mode = mode;
+        self.send(DeviceCommand::PanMode(mode))
     }
 
-    fn get_attribute_bool(&self, _type: AudioAttributes) -> Result<bool, PropertyError> {
-        let inner = self.inner.lock().unwrap();
+    pub fn get_pan_mode(&self) -> PanMode {
+        self.state
+            .lock()
+            .map(|state| state.pan_mode)
+            .unwrap_or_default()
+    }
 
-        match _type {
-            AudioAttributes::Unknown => {
-                Err(PropertyError::UnsupportedAttribute("Unknown attribute"))
+    /// Render spatialized sources binaurally through `hrtf` instead of panning them, None goes
+    /// back to panning. The set must match the device sample rate.
+    pub fn set_hrtf(&mut self, hrtf: Option<Arc<HrtfSet>>) -> Result<(), DeviceError> {
+        if let Some(hrtf) = hrtf.as_ref() {
+            if hrtf.sample_rate() != self.sample_rate {
+                return Err(DeviceError::InvalidSampleRate);
             }
-            AudioAttributes::SpatializationEnabled => Ok(inner.spatialization.is_some()),
-            _ => Err(PropertyError::UnsupportedAttribute("Unsupported attribute")),
         }
-    }
 
-    fn set_attribute_bool(
-        &mut self,
-        _type: AudioAttributes,
-        _value: bool,
-    ) -> Result<(), PropertyError> {
-        let mut inner = self.inner.lock().unwrap();
+        let Ok(mut state) = self.state.lock() else {
+            return Err(DeviceError::InvalidOperation(-1)); // Use a custom error code for lock failure
+        };
 
-        match _type {
-            AudioAttributes::Unknown => {
-                Err(PropertyError::UnsupportedAttribute("Unknown attribute"))
-            }
-            AudioAttributes::SpatializationEnabled => {
-                if _value {
-                    let spatialization =
-                        SpatializationListener::new(inner.device.playback.channels);
-                    if let Err(e) = spatialization {
-                        return Err(PropertyError::from_other(e));
-                    }
+        state.hrtf = hrtf.clone();
+        self.send(DeviceCommand::Hrtf(hrtf))
+    }
 
-                    inner.spatialization = spatialization.ok();
-                } else {
-                    inner.spatialization = None;
-                }
-                Ok(())
-            }
-            _ => Err(PropertyError::UnsupportedAttribute("Unsupported attribute")),
-        }
+    pub fn get_hrtf(&self) -> Option<Arc<HrtfSet>> {
+        self.state.lock().ok().and_then(|state| state.hrtf.clone())
     }
-}
 
-impl SpartialListenerHandler for Device {
-    fn set_position(&self, position: Vector3<f32>) -> Result<(), SpatializationListenerError> {
-        let mut inner_lock = self.inner.lock().unwrap();
+    /// Encode spatialized sources into a first-order ambisonics bus decoded to `decoder`,
+    /// instead of spatializing every source on its own. None goes back to per source
+    /// spatialization.
+    ///
+    /// The bus is decoded on the audio thread, so mixing threads are not used while it is on.
+    pub fn set_ambisonics(&mut self, decoder: Option<AmbisonicsDecoder>) -> Result<(), DeviceError> {
+        let Ok(mut state) = self.state.lock() else {
+            return Err(DeviceError::InvalidOperation(-1)); // Use a custom error code for lock failure
+        };
 
-        if let Some(spatialization) = inner_lock.spatialization.as_mut() {
-            spatialization.set_position(position);
-            Ok(())
-        } else {
-            Err(SpatializationListenerError::NotInitialized)
+        state.ambisonics = decoder;
+
+        // Otherwise picked up when spatialization gets enabled
+        if state.listener.is_some() {
+            let bus = decoder.map(|decoder| self.ambisonics_bus(decoder));
+            self.send(DeviceCommand::Ambisonics(bus))?;
         }
+
+        Ok(())
     }
 
-    fn get_position(&self) -> Result<Vector3<f32>, SpatializationListenerError> {
-        let inner_lock = self.inner.lock().unwrap();
+    pub fn get_ambisonics(&self) -> Option<AmbisonicsDecoder> {
+        self.state.lock().ok().and_then(|state| state.ambisonics)
+    }
 
-        if let Some(spatialization) = inner_lock.spatialization.as_ref() {
-            Ok(spatialization.get_position())
-        } else {
-            Err(SpatializationListenerError::NotInitialized)
-        }
+    fn ambisonics_bus(&self, decoder: AmbisonicsDecoder) -> AmbisonicsBus {
+        AmbisonicsBus::new(decoder, self.block_size, self.sample_rate)
     }
 
-    fn set_direction(
-        &self,
-        position: Vector3<f32>,
-    ) -> Result<(), SpatializationListenerError> {
-        let mut inner_lock = self.inner.lock().unwrap();
+    /// Reverb zones picked by the listener position, spatialized sources send to the shared
+    /// reverb more the further away they are. An empty slice turns the reverb off.
+    ///
+    /// Like ambisonics, the reverb runs on the audio thread so mixing threads are not used
+    /// while zones are set.
+    pub fn set_reverb_zones(&mut self, zones: &[ReverbZone]) -> Result<(), DeviceError> {
+        let Ok(mut state) = self.state.lock() else {
+            return Err(DeviceError::InvalidOperation(-1)); // Use a custom error code for lock failure
+        };
 
-        if let Some(spatialization) = inner_lock.spatialization.as_mut() {
-            spatialization.set_direction(position);
-            Ok(())
-        } else {
-            Err(SpatializationListenerError::NotInitialized)
+        let had_zones = !state.reverb_zones.is_empty();
+        state.reverb_zones = zones.to_vec();
+
+        // Otherwise picked up when spatialization gets enabled
+        if state.listener.is_none() {
+            return Ok(());
+        }
+
+        // Keep the running bus when only the zones change so the tail is not cut
+        match (had_zones, zones.is_empty()) {
+            (_, true) => self.send(DeviceCommand::Reverb(None)),
+            (true, false) => self.send(DeviceCommand::ReverbZones(zones.to_vec())),
+            (false, false) => {
+                let bus = self.reverb_bus(zones.to_vec());
+                self.send(DeviceCommand::Reverb(bus))
+            }
         }
     }
 
-    fn get_direction(&self) -> Result<Vector3<f32>, SpatializationListenerError> {
-        let inner_lock = self.inner.lock().unwrap();
+    pub fn get_reverb_zones(&self) -> Vec<ReverbZone> {
+        self.state
+            .lock()
+            .map(|state| state.reverb_zones.clone())
+            .unwrap_or_default()
+    }
 
-        if let Some(spatialization) = inner_lock.spatialization.as_ref() {
-            Ok(spatialization.get_direction())
-        } else {
-            Err(SpatializationListenerError::NotInitialized)
+    fn reverb_bus(&self, zones: Vec<ReverbZone>) -> Option<ReverbBus> {
+        if zones.is_empty() {
+            return None;
         }
+
+        Some(ReverbBus::new(zones, self.block_size, self.sample_rate))
     }
 
-    fn set_velocity(&self, position: Vector3<f32>) -> Result<(), SpatializationListenerError> {
-        let mut inner_lock = self.inner.lock().unwrap();
+    /// Add a listener next to the primary one, returns its index for
+    /// [ListenerSelection::Index](crate::ListenerSelection::Index). Spatialization has to be
+    /// enabled, turning it off removes every added listener.
+    ///
+    /// Ambisonics and the reverb zones keep following the primary listener. Mixing threads are
+    /// not used while extra listeners exist.
+    pub fn add_listener(&mut self, listener: ListenerSnapshot) -> Result<usize, DeviceError> {
+        let Ok(mut state) = self.state.lock() else {
+            return Err(DeviceError::InvalidOperation(-1)); // Use a custom error code for lock failure
+        };
 
-        if let Some(spatialization) = inner_lock.spatialization.as_mut() {
-            spatialization.set_velocity(position);
-            Ok(())
-        } else {
-            Err(SpatializationListenerError::NotInitialized)
+        if state.listener.is_none() {
+            let err = SpatializationListenerError::NotInitialized;
+            return Err(DeviceError::from_other(err));
         }
+
+        let mut listeners = state.listeners.clone();
+        listeners.push(listener);
+
+        let command = DeviceCommand::Listeners(self.spatialization_listeners(&listeners)?);
+        self.send(command)?;
+        state.listeners = listeners;
+
+        Ok(state.listeners.len())
     }
 
-    fn get_velocity(&self) -> Result<Vector3<f32>, SpatializationListenerError> {
-        let inner_lock = self.inner.lock().unwrap();
+    /// Remove a listener added with [Device::add_listener], the ones after it move down an
+    /// index. Sources still selecting a removed index fall back to the primary listener.
+    pub fn remove_listener(&mut self, index: usize) -> Result<(), DeviceError> {
+        let Ok(mut state) = self.state.lock() else {
+            return Err(DeviceError::InvalidOperation(-1)); // Use a custom error code for lock failure
+        };
 
-        if let Some(spatialization) = inner_lock.spatialization.as_ref() {
-            Ok(spatialization.get_velocity())
-        } else {
-            Err(SpatializationListenerError::NotInitialized)
+        if index == 0 || index > state.listeners.len() {
+            let err = SpatializationListenerError::InvalidIndex(index);
+            return Err(DeviceError::from_other(err));
         }
+
+        let mut listeners = state.listeners.clone();
+        listeners.remove(index - 1);
+
+        let command = DeviceCommand::Listeners(self.spatialization_listeners(&listeners)?);
+        self.send(command)?;
+        state.listeners = listeners;
+
+        Ok(())
     }
 
-    fn set_speed_of_sound(&self, speed: f32) -> Result<(), SpatializationListenerError> {
-        let mut inner_lock = self.inner.lock().unwrap();
+    /// Move or reconfigure the listener at `index`, 0 being the primary listener.
+    pub fn set_listener(
+        &mut self,
+        index: usize,
+        listener: ListenerSnapshot,
+    ) -> Result<(), DeviceError> {
+        let Ok(mut state) = self.state.lock() else {
+            return Err(DeviceError::InvalidOperation(-1)); // Use a custom error code for lock failure
+        };
 
-        if let Some(spatialization) = inner_lock.spatialization.as_mut() {
-            spatialization.set_speed_of_sound(speed);
-            Ok(())
-        } else {
-            Err(SpatializationListenerError::NotInitialized)
+        let command = match index {
+            0 if state.listener.is_some() => {
+                state.listener = Some(listener);
+                DeviceCommand::Listener(listener)
+            }
+            0 => {
+                let err = SpatializationListenerError::NotInitialized;
+                return Err(DeviceError::from_other(err));
+            }
+            _ => {
+                let Some(slot) = state.listeners.get_mut(index - 1) else {
+                    let err = SpatializationListenerError::InvalidIndex(index);
+                    return Err(DeviceError::from_other(err));
+                };
+
+                *slot = listener;
+                DeviceCommand::ListenerAt(index, listener)
+            }
+        };
+
+        self.send(command)
+    }
+
+    /// The listener at `index`, 0 being the primary listener.
+    pub fn get_listener(&self, index: usize) -> Option<ListenerSnapshot> {
+        let state = self.state.lock().ok()?;
+
+        match index {
+            0 => state.listener,
+            _ => state.listeners.get(index - 1).copied(),
         }
     }
 
-    fn get_speed_of_sound(&self) -> Result<f32, SpatializationListenerError> {
-        let inner_lock = self.inner.lock().unwrap();
+    /// Number of listeners including the primary one, 0 while spatialization is disabled.
+    pub fn get_listener_count(&self) -> usize {
+        self.state
+            .lock()
+            .map(|state| state.listener.map_or(0, |_| 1 + state.listeners.len()))
+            .unwrap_or_default()
+    }
 
-        if let Some(spatialization) = inner_lock.spatialization.as_ref() {
-            Ok(spatialization.get_speed_of_sound())
-        } else {
-            Err(SpatializationListenerError::NotInitialized)
+    fn spatialization_listeners(
+        &self,
+        snapshots: &[ListenerSnapshot],
+    ) -> Result<Vec<SpatializationListener>, DeviceError> {
+        snapshots
+            .iter()
+            .map(|snapshot| {
+                let mut listener = SpatializationListener::new(self.channels as u32)
+                    .map_err(DeviceError::from_other)?;
+
+                snapshot.apply(&mut listener);
+                Ok(listener)
+            })
+            .collect()
+    }
+
+    /// Render attached sources on `threads` worker threads in addition to the audio thread,
+    /// which helps keeping the callback under budget with many sources playing at once.
+    ///
+    /// Pass 0 to go back to mixing everything on the audio thread.
+    pub fn set_mixing_threads(&mut self, threads: usize) -> Result<(), DeviceError> {
+        if threads == 0 {
+            self.send(DeviceCommand::ParallelMixer(None))?;
+            self.parallel = None;
+
+            return Ok(());
+        }
+
+        let (mixer, handle) = parallel::spawn(threads, self.block_size, self.channels)?;
+
+        self.send(DeviceCommand::ParallelMixer(Some(mixer)))?;
+        self.parallel = Some(handle);
+
+        Ok(())
+    }
+
+    /// Record the final output of the device, after the master volume and the DSP callbacks,
+    /// into `target`. The output is written on a background thread.
+    ///
+    /// A recording already running is stopped first.
+    pub fn record_output(&mut self, target: RecordTarget) -> Result<(), DeviceError> {
+        self.stop_recording()?;
+
+        let (tap, recorder) =
+            Recorder::spawn(target, self.channels, self.sample_rate, self.block_size)
+                .map_err(DeviceError::from_other)?;
+
+        self.send(DeviceCommand::Recorder(Some(tap)))?;
+        self.recorder = Some(recorder);
+
+        Ok(())
+    }
+
+    /// Route the input of this device to the output of `playback` at `gain`, or to its own
+    /// output when `playback` is None which needs a duplex device. This device has to capture
+    /// and `playback` has to play.
+    ///
+    /// The route stays until [InputMonitor::stop] is called or either device is dropped.
+    pub fn monitor_input(
+        &mut self,
+        playback: Option<&mut Device>,
+        gain: f32,
+    ) -> Result<InputMonitor, DeviceError> {
+        let capture_type = self.device_type()?;
+        let playback_type = match playback.as_deref() {
+            Some(device) => device.device_type()?,
+            None => capture_type,
+        };
+
+        if capture_type == DeviceType::Playback || playback_type == DeviceType::Capture {
+            return Err(DeviceError::InvalidMonitorRoute);
+        }
+
+        let monitor = InputMonitor::new(self.channels, gain);
+        self.send(DeviceCommand::MonitorSend(monitor.fifo.clone()))?;
+
+        match playback {
+            Some(device) => device.send(DeviceCommand::MonitorReturn(monitor.fifo.clone()))?,
+            None => self.send(DeviceCommand::MonitorReturn(monitor.fifo.clone()))?,
         }
+
+        Ok(monitor)
     }
 
-    fn set_world_up(&self, position: Vector3<f32>) -> Result<(), SpatializationListenerError> {
-        let mut inner_lock = self.inner.lock().unwrap();
+    fn device_type(&self) -> Result<DeviceType, DeviceError> {
+        let Ok(inner) = self.inner.lock() else {
+            return Err(DeviceError::InvalidOperation(-1)); // Use a custom error code for lock failure
+        };
 
-        if let Some(spatialization) = inner_lock.spatialization.as_mut() {
-            spatialization.set_world_up(position);
-            Ok(())
+        Ok(inner.ty)
+    }
+
+    /// Start analyzing the output with windows of `fft_size` frames, a power of two between 64
+    /// and 16384. Replaces the analyzer already running.
+    pub fn enable_analyzer(&mut self, fft_size: usize) -> Result<SpectrumAnalyzer, DeviceError> {
+        if !fft_size.is_power_of_two() || !(64..=16384).contains(&fft_size) {
+            return Err(DeviceError::InvalidAnalyzerSize(fft_size));
+        }
+
+        let analyzer = SpectrumAnalyzer::new(fft_size, self.sample_rate);
+        self.send(DeviceCommand::Analyzer(Some(analyzer.tap())))?;
+        self.analyzer = Some(analyzer.clone());
+
+        Ok(analyzer)
+    }
+
+    pub fn disable_analyzer(&mut self) -> Result<(), DeviceError> {
+        if self.analyzer.take().is_some() {
+            self.send(DeviceCommand::Analyzer(None))?;
+        }
+
+        Ok(())
+    }
+
+    /// The analyzer enabled with [Device::enable_analyzer], if any.
+    pub fn analyzer(&self) -> Option<SpectrumAnalyzer> {
+        self.analyzer.clone()
+    }
+
+    /// Stop the recording and wait for the queued output to be written.
+    pub fn stop_recording(&mut self) -> Result<(), DeviceError> {
+        let Ok(mut inner) = self.inner.lock() else {
+            return Err(DeviceError::InvalidOperation(-1)); // Use a custom error code for lock failure
+        };
+
+        let Some(recorder) = self.recorder.take() else {
+            return Ok(());
+        };
+
+        let result = if inner.is_running() {
+            drop(inner);
+            self.send(DeviceCommand::Recorder(None))
         } else {
-            Err(SpatializationListenerError::NotInitialized)
+            // No callback would drain the command, so the tap is dropped here instead
+            inner.drain_commands();
+            inner.recorder = None;
+            Ok(())
+        };
+
+        // The writer thread stops on its own flag, so this never waits on the callback
+        recorder.finish();
+
+        result
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.recorder.is_some()
+    }
+
+    /// Mixing buffers and scratch pool of the device and the memory of every source attached
+    /// to it.
+    pub fn memory_usage(&self) -> Result<MemoryUsage, DeviceError> {
+        let Ok(state) = self.state.lock() else {
+            return Err(DeviceError::InvalidOperation(-1)); // Use a custom error code for lock failure
+        };
+
+        // Allocated once with the device, see DeviceInner::new
+        let block_len = utils::block_buffer_len(self.block_size, self.channels);
+        let mut usage = MemoryUsage {
+            scratch: MemoryUsage::f32_bytes(block_len * 2)
+                + MemoryUsage::f64_bytes(block_len)
+                + self.pool.size_in_bytes(),
+            ..Default::default()
+        };
+
+        for handle in state.handles.iter() {
+            usage += handle.memory_usage();
         }
+
+        Ok(usage)
     }
 
-    fn get_world_up(&self) -> Result<Vector3<f32>, SpatializationListenerError> {
-        let inner_lock = self.inner.lock().unwrap();
+    /// Callback count, frames rendered, active voices, peak levels, underruns and callback
+    /// durations of this device, copied as published after the last callback.
+    pub fn stats(&self) -> Result<Stats, DeviceError> {
+        let Some(mut stats) = self.stats.get() else {
+            return Err(DeviceError::InvalidOperation(-1)); // Use a custom error code for lock failure
+        };
+
+        stats.scratch_exhausted = self.pool.exhausted();
+        Ok(stats)
+    }
+
+    /// Zero the counters of [Device::stats].
+    pub fn reset_stats(&self) {
+        self.stats.reset();
+        self.pool.reset_exhausted();
+    }
+
+    /// Capture the master attributes, spatialization listener and the settings of every
+    /// source currently attached to this device.
+    pub fn snapshot(&self) -> Result<DeviceSnapshot, DeviceError> {
+        let Ok(state) = self.state.lock() else {
+            return Err(DeviceError::InvalidOperation(-1)); // Use a custom error code for lock failure
+        };
+
+        Ok(DeviceSnapshot::capture(&state))
+    }
+
+    /// Describe everything attached to this device: the sources and their nesting in mixers,
+    /// their attributes, FX, positions and buffer sizes. Print it with `{}` for a readable
+    /// tree when tracking down why a source is silent.
+    pub fn debug_dump(&self) -> Result<GraphDump, DeviceError> {
+        let device_type = self.device_type()?;
+
+        let Some(dump) = GraphDump::capture(self, device_type) else {
+            return Err(DeviceError::InvalidOperation(-1)); // Use a custom error code for lock failure
+        };
+
+        Ok(dump)
+    }
+
+    /// Tracks and sample channels currently attached to this device, in attach order.
+    pub fn channels(&self) -> Result<Vec<SourceSnapshot>, DeviceError> {
+        self.attached(|handle| !matches!(handle.source(), AudioHandle::Mixer(_)))
+    }
+
+    /// Mixers currently attached directly to this device, in attach order.
+    pub fn mixers(&self) -> Result<Vec<SourceSnapshot>, DeviceError> {
+        self.attached(|handle| matches!(handle.source(), AudioHandle::Mixer(_)))
+    }
+
+    fn attached<F>(&self, filter: F) -> Result<Vec<SourceSnapshot>, DeviceError>
+    where
+        F: Fn(&AudioHandle) -> bool,
+    {
+        let Ok(state) = self.state.lock() else {
+            return Err(DeviceError::InvalidOperation(-1)); // Use a custom error code for lock failure
+        };
+
+        // A source on the device through several handles is listed once
+        let mut sources: Vec<SourceSnapshot> = Vec::new();
+        for handle in state.handles.iter() {
+            if !filter(handle) || !handle.is_playing() {
+                continue;
+            }
+
+            let Some(source) = SourceSnapshot::capture(handle) else {
+                continue;
+            };
+
+            let listed = sources
+                .iter()
+                .any(|listed| listed.kind == source.kind && listed.ref_id == source.ref_id);
+
+            if !listed {
+                sources.push(source);
+            }
+        }
+
+        Ok(sources)
+    }
+
+    /// Whether a source of `kind` with `ref_id` is attached to this device. Ref ids are
+    /// only unique per kind.
+    pub fn contains_channel(&self, kind: SourceKind, ref_id: usize) -> bool {
+        let Ok(state) = self.state.lock() else {
+            return false;
+        };
+
+        state
+            .handles
+            .iter()
+            .any(|handle| handle.is(kind, ref_id) && handle.is_playing())
+    }
+
+    /// Stop and detach the source of `kind` with `ref_id`, returns false when no such source
+    /// is attached.
+    pub fn remove_channel(&mut self, kind: SourceKind, ref_id: usize) -> Result<bool, DeviceError> {
+        let handle = {
+            let Ok(mut state) = self.state.lock() else {
+                return Err(DeviceError::InvalidOperation(-1)); // Use a custom error code for lock failure
+            };
+
+            state.handles.retain(AudioHandle::is_playing);
+
+            let Some(index) = state.handles.iter().position(|handle| handle.is(kind, ref_id))
+            else {
+                return Ok(false);
+            };
+
+            state.handles.remove(index)
+        };
+
+        handle.stop();
+        self.send(DeviceCommand::Detach(handle))?;
+
+        Ok(true)
+    }
+
+    /// Restore a snapshot previously taken with [Device::snapshot].
+    ///
+    /// Sources are matched by their ref id, sources that are no longer attached to the device are skipped.
+    pub fn restore(&mut self, snapshot: &DeviceSnapshot) -> Result<(), DeviceError> {
+        snapshot.apply(self)
+    }
+
+    pub(crate) fn get_ref_id(&self) -> u32 {
+        self.device_ref_id
+    }
+
+    pub(crate) fn send(&self, command: DeviceCommand) -> Result<(), DeviceError> {
+        let Ok(mut sender) = self.sender.lock() else {
+            return Err(DeviceError::SendCommandFailed);
+        };
+
+        let Err(command) = sender.try_send(command) else {
+            return Ok(());
+        };
+
+        // A stopped device never drains the queue, so it is applied here to make room
+        let Ok(mut inner) = self.inner.lock() else {
+            return Err(DeviceError::SendCommandFailed);
+        };
+
+        if inner.is_running() {
+            return Err(DeviceError::SendCommandFailed);
+        }
+
+        inner.drain_commands();
+        drop(inner);
+
+        match sender.try_send(command) {
+            Ok(()) => Ok(()),
+            Err(_) => Err(DeviceError::SendCommandFailed),
+        }
+    }
+
+    /// Send `commands` as one command, so the audio thread applies them in the same callback.
+    pub(crate) fn send_batch(&self, mut commands: Vec<DeviceCommand>) -> Result<(), DeviceError> {
+        match commands.len() {
+            0 => Ok(()),
+            1 => self.send(commands.remove(0)),
+            _ => self.send(DeviceCommand::Batch(commands)),
+        }
+    }
+
+    pub(crate) fn listener_snapshot(&self) -> Option<ListenerSnapshot> {
+        self.state.lock().ok().and_then(|state| state.listener)
+    }
+
+    fn attach(&mut self, handle: AudioHandle) -> Result<(), DeviceError> {
+        let Ok(mut state) = self.state.lock() else {
+            return Err(DeviceError::InvalidOperation(-1)); // Use a custom error code for lock failure
+        };
+
+        // Finished sources were detached by the audio thread, a replayed one is listed once
+        state.handles.retain(AudioHandle::is_playing);
+        let listed = state
+            .handles
+            .iter()
+            .any(|attached| attached.ptr_eq(&handle));
+        if !listed {
+            state.handles.push(handle.clone());
+        }
+
+        if let Err(_) = self.send(DeviceCommand::Attach(handle)) {
+            return Err(DeviceError::SendAudioHandleFailed);
+        }
+
+        Ok(())
+    }
+
+    pub(crate) fn attach_track(&mut self, track: &crate::Track) -> Result<(), DeviceError> {
+        if let Ok(mut inner) = track.inner.lock() {
+            inner.pool = Some(self.pool.clone());
+            inner.clock = Some(self.clock.clone());
+        }
+
+        let weak = Arc::downgrade(&track.inner);
+        self.attach(AudioHandle::Track(weak))
+    }
+
+    pub(crate) fn attach_sample(
+        &mut self,
+        sample: &crate::sample::SampleChannel,
+    ) -> Result<(), DeviceError> {
+        if let Ok(mut inner) = sample.inner.lock() {
+            inner.pool = Some(self.pool.clone());
+        }
+
+        let weak = Arc::downgrade(&sample.inner);
+        self.attach(AudioHandle::Sample(weak))
+    }
 
-        if let Some(spatialization) = inner_lock.spatialization.as_ref() {
-            Ok(spatialization.get_world_up())
+    pub(crate) fn attach_mixer(&mut self, mixer: &crate::Mixer) -> Result<(), DeviceError> {
+        self.attach_mixer_channel(&mixer.inner)
+    }
+
+    pub(crate) fn attach_mixer_channel(
+        &mut self,
+        mixer: &Arc<Mutex<MixerChannel>>,
+    ) -> Result<(), DeviceError> {
+        if let Ok(mut inner) = mixer.lock() {
+            MixerChannel::recursive_set_pool(&mut inner, Some(self.pool.clone()), 0);
+            inner.clock = Some(self.clock.clone());
+        }
+
+        let weak = Arc::downgrade(mixer);
+        self.attach(AudioHandle::Mixer(weak))
+    }
+
+    pub(crate) fn attach_split(&mut self, split: SplitHandle) -> Result<(), DeviceError> {
+        self.attach(AudioHandle::Split(split))
+    }
+
+    pub(crate) fn detach(&mut self, handle: AudioHandle) -> Result<(), DeviceError> {
+        {
+            let Ok(mut state) = self.state.lock() else {
+                return Err(DeviceError::InvalidOperation(-1)); // Use a custom error code for lock failure
+            };
+
+            state.handles.retain(|attached| !attached.ptr_eq(&handle));
+        }
+
+        self.send(DeviceCommand::Detach(handle))
+    }
+
+    fn update_listener<F>(&self, update: F) -> Result<(), SpatializationListenerError>
+    where
+        F: FnOnce(&mut ListenerSnapshot),
+    {
+        let mut state = self.state.lock().unwrap();
+
+        let Some(listener) = state.listener.as_mut() else {
+            return Err(SpatializationListenerError::NotInitialized);
+        };
+
+        update(listener);
+        _ = self.send(DeviceCommand::Listener(*listener));
+
+        Ok(())
+    }
+
+    fn read_listener<T, F>(&self, read: F) -> Result<T, SpatializationListenerError>
+    where
+        F: FnOnce(&ListenerSnapshot) -> T,
+    {
+        let state = self.state.lock().unwrap();
+
+        if let Some(listener) = state.listener.as_ref() {
+            Ok(read(listener))
         } else {
             Err(SpatializationListenerError::NotInitialized)
         }
     }
+}
 
-    fn set_cone(
+impl Device {
+    fn apply_attribute_f32(
         &self,
-        inner_angle: f32,
-        outer_angle: f32,
-        outer_gain: f32,
-    ) -> Result<(), SpatializationListenerError> {
-        let mut inner_lock = self.inner.lock().unwrap();
+        state: &mut DeviceState,
+        _type: AudioAttributes,
+        _value: f32,
+        commands: &mut Vec<DeviceCommand>,
+    ) -> Result<(), PropertyError> {
+        match _type {
+            AudioAttributes::Unknown => {
+                return Err(PropertyError::UnsupportedAttribute("Unknown attribute"));
+            }
+            AudioAttributes::Volume => {
+                state.volume = _value.clamp(0.0, 1.0);
+                commands.push(DeviceCommand::Volume(state.volume));
+            }
+            AudioAttributes::Pan => {
+                state.pan = _value.clamp(-1.0, 1.0);
+                commands.push(DeviceCommand::Pan(state.pan));
+            }
+            AudioAttributes::Balance => {
+                state.pan = _value.clamp(-1.0, 1.0);
+                state.pan_mode = PanMode::Balance;
+                commands.push(DeviceCommand::PanMode(state.pan_mode));
+                commands.push(DeviceCommand::Pan(state.pan));
+            }
+            AudioAttributes::FXEnabled => {
+                return Err(PropertyError::UnsupportedAttribute(
+                    "AudioFX is not supported, use set_attribute_bool to enable it",
+                ));
+            }
+            AudioAttributes::SpatializationEnabled => {
+                return Err(PropertyError::UnsupportedAttribute(
+                    "AudioSpatialization is not supported, use set_attribute_bool to enable it",
+                ));
+            }
+            _ => return Err(PropertyError::UnsupportedAttribute("Unsupported attribute")),
+        }
 
-        if let Some(spatialization) = inner_lock.spatialization.as_mut() {
-            spatialization.set_cone(inner_angle, outer_angle, outer_gain);
-            Ok(())
-        } else {
-            Err(SpatializationListenerError::NotInitialized)
+        Ok(())
+    }
+
+    fn apply_attribute_bool(
+        &self,
+        state: &mut DeviceState,
+        _type: AudioAttributes,
+        _value: bool,
+        commands: &mut Vec<DeviceCommand>,
+    ) -> Result<(), PropertyError> {
+        match _type {
+            AudioAttributes::Unknown => {
+                return Err(PropertyError::UnsupportedAttribute("Unknown attribute"));
+            }
+            AudioAttributes::SpatializationEnabled => {
+                if _value {
+                    let spatialization = SpatializationListener::new(self.channels as u32);
+                    if let Err(e) = spatialization {
+                        return Err(PropertyError::from_other(e));
+                    }
+
+                    let mut spatialization = spatialization.unwrap();
+                    spatialization.ambisonics =
+                        state.ambisonics.map(|decoder| self.ambisonics_bus(decoder));
+                    spatialization.reverb = self.reverb_bus(state.reverb_zones.clone());
+                    state.listener = Some(ListenerSnapshot::capture(&spatialization));
+
+                    commands.push(DeviceCommand::Spatialization(Some(spatialization)));
+                } else {
+                    state.listener = None;
+                    commands.push(DeviceCommand::Spatialization(None));
+
+                    if !state.listeners.is_empty() {
+                        state.listeners.clear();
+                        commands.push(DeviceCommand::Listeners(Vec::new()));
+                    }
+                }
+            }
+            AudioAttributes::ForceMono => {
+                state.force_mono = _value;
+                commands.push(DeviceCommand::ForceMono(_value));
+            }
+            AudioAttributes::SwapChannels => {
+                state.swap_channels = _value;
+                commands.push(DeviceCommand::SwapChannels(_value));
+            }
+            _ => return Err(PropertyError::UnsupportedAttribute("Unsupported attribute")),
         }
+
+        Ok(())
     }
 
-    fn get_cone(&self) -> Result<(f32, f32, f32), SpatializationListenerError> {
-        let inner_lock = self.inner.lock().unwrap();
+    /// Update the state for each attribute and send the changes as one batch. When an
+    /// attribute or the send fails the state is restored and nothing is applied.
+    fn apply_attributes(
+        &self,
+        attributes: &[(AudioAttributes, AttributeValue)],
+    ) -> Result<(), PropertyError> {
+        let mut state = self.state.lock().unwrap();
+        let mut commands = Vec::new();
+
+        // Every field the attribute setters touch
+        let saved = (
+            state.volume,
+            state.pan,
+            state.pan_mode,
+            state.force_mono,
+            state.swap_channels,
+            state.listener,
+        );
+
+        let result = attributes.iter().try_for_each(|&(attribute, value)| match value {
+            AttributeValue::F32(value) => {
+                self.apply_attribute_f32(&mut state, attribute, value, &mut commands)
+            }
+            AttributeValue::Bool(value) => {
+                self.apply_attribute_bool(&mut state, attribute, value, &mut commands)
+            }
+        });
+        let result =
+            result.and_then(|()| self.send_batch(commands).map_err(PropertyError::from_other));
 
-        if let Some(spatialization) = inner_lock.spatialization.as_ref() {
-            Ok(spatialization.get_cone())
-        } else {
-            Err(SpatializationListenerError::NotInitialized)
+        if let Err(e) = result {
+            (
+                state.volume,
+                state.pan,
+                state.pan_mode,
+                state.force_mono,
+                state.swap_channels,
+                state.listener,
+            ) = saved;
+
+            return Err(e);
         }
+
+        Ok(())
     }
 
-    fn set_enabled(&self, is_enabled: bool) -> Result<(), SpatializationListenerError> {
-        let mut inner_lock = self.inner.lock().unwrap();
+    fn apply_custom_attribute(
+        &mut self,
+        name: &str,
+        value: AttributeValue,
+    ) -> Result<(), PropertyError> {
+        let mut state = self.state.lock().unwrap();
+        state.custom_attributes.set(name, value);
+        Ok(())
+    }
+}
 
-        if let Some(spatialization) = inner_lock.spatialization.as_mut() {
-            spatialization.set_enabled(is_enabled);
-            Ok(())
-        } else {
-            Err(SpatializationListenerError::NotInitialized)
+impl PropertyHandler for Device {
+    fn get_attribute_f32(&self, _type: AudioAttributes) -> Result<f32, PropertyError> {
+        let state = self.state.lock().unwrap();
+
+        match _type {
+            AudioAttributes::Unknown => {
+                Err(PropertyError::UnsupportedAttribute("Unknown attribute"))
+            }
+            AudioAttributes::Volume => Ok(state.volume),
+            AudioAttributes::Pan => Ok(state.pan),
+            AudioAttributes::Balance => match state.pan_mode {
+                PanMode::Balance => Ok(state.pan),
+                PanMode::Pan => Ok(0.0),
+            },
+            AudioAttributes::FXEnabled => Err(PropertyError::UnsupportedAttribute(
+                "AudioFX is not supported, use set_attribute_bool to enable it",
+            )),
+            AudioAttributes::SpatializationEnabled => Err(PropertyError::UnsupportedAttribute(
+                "AudioSpatialization is not supported, use set_attribute_bool to enable it",
+            )),
+            _ => Err(PropertyError::UnsupportedAttribute("Unsupported attribute")),
         }
     }
 
-    fn is_enabled(&self) -> Result<bool, SpatializationListenerError> {
-        let inner_lock = self.inner.lock().unwrap();
+    fn set_attribute_f32(
+        &mut self,
+        _type: AudioAttributes,
+        value: f32,
+    ) -> Result<(), PropertyError> {
+        self.set_attributes(&[(_type, AttributeValue::F32(value))])
+    }
 
-        if let Some(spatialization) = inner_lock.spatialization.as_ref() {
-            Ok(spatialization.is_enabled())
-        } else {
-            Err(SpatializationListenerError::NotInitialized)
+    fn get_attribute_bool(&self, _type: AudioAttributes) -> Result<bool, PropertyError> {
+        let state = self.state.lock().unwrap();
+
+        match _type {
+            AudioAttributes::Unknown => {
+                Err(PropertyError::UnsupportedAttribute("Unknown attribute"))
+            }
+            AudioAttributes::SpatializationEnabled => Ok(state.listener.is_some()),
+            AudioAttributes::ForceMono => Ok(state.force_mono),
+            AudioAttributes::SwapChannels => Ok(state.swap_channels),
+            _ => Err(PropertyError::UnsupportedAttribute("Unsupported attribute")),
         }
     }
+
+    fn set_attribute_bool(
+        &mut self,
+        _type: AudioAttributes,
+        value: bool,
+    ) -> Result<(), PropertyError> {
+        self.set_attributes(&[(_type, AttributeValue::Bool(value))])
+    }
+
+    fn get_custom_attribute(&self, name: &str) -> Result<AttributeValue, PropertyError> {
+        let state = self.state.lock().unwrap();
+        state.custom_attributes.get(name)
+    }
+
+    fn set_custom_attribute(
+        &mut self,
+        name: &str,
+        value: AttributeValue,
+    ) -> Result<(), PropertyError> {
+        self.apply_custom_attribute(name, value)?;
+
+        self.observers.notify(AttributeEvent {
+            attribute: AudioAttributes::Unknown,
+            name: Some(name),
+            value,
+        });
+
+        Ok(())
+    }
+
+    fn supported_attributes(&self) -> &'static [AudioAttributes] {
+        &[
+            AudioAttributes::Volume,
+            AudioAttributes::Pan,
+            AudioAttributes::Balance,
+            AudioAttributes::SpatializationEnabled,
+            AudioAttributes::ForceMono,
+            AudioAttributes::SwapChannels,
+        ]
+    }
+
+    fn set_attributes(
+        &mut self,
+        attributes: &[(AudioAttributes, AttributeValue)],
+    ) -> Result<(), PropertyError> {
+        self.apply_attributes(attributes)?;
+
+        self.observers.notify_attributes(&*self, attributes);
+        Ok(())
+    }
+}
+
+impl SpartialListenerHandler for Device {
+    fn set_position(&self, position: Vector3<f32>) -> Result<(), SpatializationListenerError> {
+        self.update_listener(|listener| listener.position = position)
+    }
+
+    fn get_position(&self) -> Result<Vector3<f32>, SpatializationListenerError> {
+        self.read_listener(|listener| listener.position)
+    }
+
+    fn set_direction(
+        &self,
+        position: Vector3<f32>,
+    ) -> Result<(), SpatializationListenerError> {
+        self.update_listener(|listener| listener.direction = position)
+    }
+
+    fn get_direction(&self) -> Result<Vector3<f32>, SpatializationListenerError> {
+        self.read_listener(|listener| listener.direction)
+    }
+
+    fn set_velocity(&self, position: Vector3<f32>) -> Result<(), SpatializationListenerError> {
+        self.update_listener(|listener| listener.velocity = position)
+    }
+
+    fn get_velocity(&self) -> Result<Vector3<f32>, SpatializationListenerError> {
+        self.read_listener(|listener| listener.velocity)
+    }
+
+    fn set_speed_of_sound(&self, speed: f32) -> Result<(), SpatializationListenerError> {
+        self.update_listener(|listener| listener.speed_of_sound = speed)
+    }
+
+    fn get_speed_of_sound(&self) -> Result<f32, SpatializationListenerError> {
+        self.read_listener(|listener| listener.speed_of_sound)
+    }
+
+    fn set_world_up(&self, position: Vector3<f32>) -> Result<(), SpatializationListenerError> {
+        self.update_listener(|listener| listener.world_up = position)
+    }
+
+    fn get_world_up(&self) -> Result<Vector3<f32>, SpatializationListenerError> {
+        self.read_listener(|listener| listener.world_up)
+    }
+
+    fn set_cone(
+        &self,
+        inner_angle: f32,
+        outer_angle: f32,
+        outer_gain: f32,
+    ) -> Result<(), SpatializationListenerError> {
+        self.update_listener(|listener| listener.cone = (inner_angle, outer_angle, outer_gain))
+    }
+
+    fn get_cone(&self) -> Result<(f32, f32, f32), SpatializationListenerError> {
+        self.read_listener(|listener| listener.cone)
+    }
+
+    fn set_enabled(&self, is_enabled: bool) -> Result<(), SpatializationListenerError> {
+        self.update_listener(|listener| listener.enabled = is_enabled)
+    }
+
+    fn is_enabled(&self) -> Result<bool, SpatializationListenerError> {
+        self.read_listener(|listener| listener.enabled)
+    }
 }
@@ -0,0 +1,105 @@
+use std::{
+    collections::VecDeque,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, AtomicU32, Ordering},
+    },
+};
+
+/// Frames the monitored input may queue up before its oldest frames are dropped, keeps the
+/// added latency bounded when the two devices drift apart.
+pub(crate) const DEFAULT_MONITOR_LATENCY: usize = 1024;
+
+/// Input frames on their way from a capture device to a playback device.
+#[derive(Debug)]
+pub(crate) struct MonitorFifo {
+    samples: Mutex<VecDeque<f32>>,
+    channels: usize,
+    max_latency: usize,
+    gain: AtomicU32,
+    active: AtomicBool,
+}
+
+impl MonitorFifo {
+    pub fn is_active(&self) -> bool {
+        self.active.load(Ordering::Acquire)
+    }
+
+    /// Queue interleaved `input` from the capture device, never blocks. Input arriving while
+    /// the playback device holds the queue is dropped.
+    pub fn push(&self, input: &[f32]) {
+        let Ok(mut samples) = self.samples.try_lock() else {
+            return;
+        };
+
+        let limit = self.max_latency * self.channels;
+        let excess = (samples.len() + input.len()).saturating_sub(limit);
+        samples.drain(..excess.min(samples.len()));
+        samples.extend(input.iter().copied());
+    }
+
+    /// Add the queued frames on top of interleaved `output` with `channels` channels, mapping
+    /// the input channels onto the output ones. Never blocks.
+    pub fn pull(&self, output: &mut [f32], channels: usize) {
+        let Ok(mut samples) = self.samples.try_lock() else {
+            return;
+        };
+
+        let gain = f32::from_bits(self.gain.load(Ordering::Relaxed));
+        let input_channels = self.channels;
+        let frames = (output.len() / channels).min(samples.len() / input_channels);
+
+        for frame in output.chunks_exact_mut(channels).take(frames) {
+            for (channel, sample) in frame.iter_mut().enumerate() {
+                *sample += samples[channel % input_channels] * gain;
+            }
+
+            samples.drain(..input_channels);
+        }
+    }
+}
+
+/// Routes the input of a capture or duplex device to the output of a playback device, see
+/// [Device::monitor_input](crate::Device::monitor_input).
+///
+/// The input is mixed into the output of the playback device before its master effects, so
+/// a guitar can be monitored through them. On a duplex device monitoring itself the input of
+/// a callback is heard in the output of the same callback.
+#[derive(Debug, Clone)]
+pub struct InputMonitor {
+    pub(crate) fifo: Arc<MonitorFifo>,
+}
+
+impl InputMonitor {
+    pub(crate) fn new(channels: usize, gain: f32) -> Self {
+        let channels = channels.max(1);
+        let fifo = MonitorFifo {
+            samples: Mutex::new(VecDeque::with_capacity(DEFAULT_MONITOR_LATENCY * channels * 2)),
+            channels,
+            max_latency: DEFAULT_MONITOR_LATENCY,
+            gain: AtomicU32::new(gain.max(0.0).to_bits()),
+            active: AtomicBool::new(true),
+        };
+
+        Self {
+            fifo: Arc::new(fifo),
+        }
+    }
+
+    pub fn set_gain(&self, gain: f32) {
+        self.fifo.gain.store(gain.max(0.0).to_bits(), Ordering::Relaxed);
+    }
+
+    pub fn get_gain(&self) -> f32 {
+        f32::from_bits(self.fifo.gain.load(Ordering::Relaxed))
+    }
+
+    /// Stop monitoring, both devices let go of the route on their next callback.
+    pub fn stop(&self) {
+        self.fifo.active.store(false, Ordering::Release);
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.fifo.is_active()
+    }
+}
@@ -0,0 +1,353 @@
+use std::{
+    cell::UnsafeCell,
+    sync::{
+        Arc,
+        atomic::{AtomicPtr, AtomicU8, AtomicUsize, Ordering},
+    },
+    thread::{JoinHandle, Thread},
+};
+
+use crate::{
+    effects::{HrtfSet, SpatializationListener},
+    math::{MathUtils, MathUtilsTrait as _},
+    misc::{denormal::DenormalGuard, precision::MixBus},
+    utils,
+};
+
+use super::{
+    DeviceError,
+    ducking::GroupGain,
+    inner::{MixScratch, TrackChannelHandle, render_handle},
+    snapshot::ListenerSnapshot,
+};
+
+const STATE_IDLE: u8 = 0;
+const STATE_RUNNING: u8 = 1;
+const STATE_SHUTDOWN: u8 = 2;
+
+/// Work published by the audio thread for a single callback.
+struct MixJob {
+    handles: *mut TrackChannelHandle,
+    len: usize,
+    gains: *const GroupGain,
+    gains_len: usize,
+    frame_count: usize,
+    channels: usize,
+    converter_channels: usize,
+    listener: Option<ListenerSnapshot>,
+    hrtf: Option<Arc<HrtfSet>>,
+}
+
+struct Shared {
+    state: AtomicU8,
+    generation: AtomicUsize,
+    // Next handle index to render, claimed by the audio thread and the workers alike
+    next: AtomicUsize,
+    // Workers currently holding the job
+    busy: AtomicUsize,
+    active: AtomicUsize,
+    job: AtomicPtr<MixJob>,
+    // One accumulator per worker, only written by its worker while it holds the job. These stay
+    // f32 regardless of the device precision, only the final sum goes through the device bus.
+    accumulators: Box<[UnsafeCell<Vec<f32>>]>,
+    // Generation of the last job each worker joined, the other accumulators hold stale data
+    joined: Box<[AtomicUsize]>,
+}
+
+// SAFETY: Each accumulator is written by a single worker while it is counted in `busy` and only
+// read by the audio thread after the job was withdrawn and `busy` dropped to zero.
+unsafe impl Sync for Shared {}
+
+/// Audio thread side of the parallel mixer, owned by [DeviceInner](super::inner::DeviceInner).
+pub(crate) struct ParallelMixer {
+    shared: Arc<Shared>,
+    threads: Vec<Thread>,
+    channels: usize,
+}
+
+/// Control thread side of the parallel mixer, stops and joins the workers on drop.
+pub(crate) struct ParallelMixerHandle {
+    shared: Arc<Shared>,
+    workers: Vec<JoinHandle<()>>,
+}
+
+/// Spawn `threads` mixing workers, each with its own scratch buffers for `block_size` frames.
+pub(crate) fn spawn(
+    threads: usize,
+    block_size: usize,
+    channels: usize,
+) -> Result<(ParallelMixer, ParallelMixerHandle), DeviceError> {
+    let buffer_size = utils::block_buffer_len(block_size, channels);
+    let accumulators = (0..threads)
+        .map(|_| UnsafeCell::new(vec![0.0f32; block_size * channels]))
+        .collect::<Vec<_>>()
+        .into_boxed_slice();
+    let joined = (0..threads)
+        .map(|_| AtomicUsize::new(0))
+        .collect::<Vec<_>>()
+        .into_boxed_slice();
+
+    let shared = Arc::new(Shared {
+        state: AtomicU8::new(STATE_IDLE),
+        generation: AtomicUsize::new(0),
+        next: AtomicUsize::new(0),
+        busy: AtomicUsize::new(0),
+        active: AtomicUsize::new(0),
+        job: AtomicPtr::new(std::ptr::null_mut()),
+        accumulators,
+        joined,
+    });
+
+    let mut handle = ParallelMixerHandle {
+        shared: shared.clone(),
+        workers: Vec::with_capacity(threads),
+    };
+
+    for index in 0..threads {
+        let shared = shared.clone();
+
+        let worker = std::thread::Builder::new()
+            .name(format!("estaudio-mixer-{}", index))
+            .spawn(move || worker_main(shared, index, buffer_size))
+            .map_err(DeviceError::from_other)?;
+
+        handle.workers.push(worker);
+    }
+
+    let threads = handle
+        .workers
+        .iter()
+        .map(|worker| worker.thread().clone())
+        .collect();
+
+    Ok((
+        ParallelMixer {
+            shared,
+            threads,
+            channels,
+        },
+        handle,
+    ))
+}
+
+impl ParallelMixer {
+    /// Render the handles across the workers and the calling thread, then sum everything into
+    /// `output`. Returns the number of sources that produced frames, or None when the workers
+    /// are shutting down and nothing was rendered.
+    ///
+    /// The calling thread keeps claiming handles until none are left, so a worker that is slow
+    /// to wake only costs the parallelism. The wait afterwards is bounded by the handles the
+    /// workers are rendering at that moment.
+    pub fn mix<O: MixBus + ?Sized>(
+        &self,
+        handles: &mut [TrackChannelHandle],
+        mut spatialization: Option<&mut SpatializationListener>,
+        scratch: &mut MixScratch,
+        gains: &[GroupGain],
+        output: &mut O,
+        frame_count: usize,
+    ) -> Option<usize> {
+        // Not worth waking the workers for a single source
+        if handles.len() < 2 {
+            return None;
+        }
+
+        let shared = &self.shared;
+        if shared
+            .state
+            .compare_exchange(STATE_IDLE, STATE_RUNNING, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            return None;
+        }
+
+        let channels = self.channels;
+        let job = MixJob {
+            handles: handles.as_mut_ptr(),
+            len: handles.len(),
+            gains: gains.as_ptr(),
+            gains_len: gains.len(),
+            frame_count,
+            channels,
+            converter_channels: scratch.channel_converter.get_output_channels(),
+            listener: spatialization.as_deref().map(ListenerSnapshot::capture),
+            hrtf: spatialization
+                .as_deref()
+                .and_then(|listener| listener.hrtf.clone()),
+        };
+
+        shared.active.store(0, Ordering::Relaxed);
+        shared.next.store(0, Ordering::Relaxed);
+        let generation = shared.generation.fetch_add(1, Ordering::SeqCst) + 1;
+        shared.job.store(&job as *const MixJob as *mut MixJob, Ordering::SeqCst);
+
+        for thread in self.threads.iter() {
+            thread.unpark();
+        }
+
+        let mut active_sources = 0;
+        loop {
+            let index = shared.next.fetch_add(1, Ordering::Relaxed);
+            if index >= job.len {
+                break;
+            }
+
+            // SAFETY: Every index is claimed once, nobody else touches this handle.
+            let handle = unsafe { &mut *job.handles.add(index) };
+
+            active_sources += render_handle(
+                handle,
+                spatialization.as_deref_mut(),
+                &mut [],
+                scratch,
+                gains,
+                output,
+                frame_count,
+                channels,
+            ) as usize;
+        }
+
+        // Workers that have not joined yet see no job and skip it, the rest only finish the
+        // handle they already claimed
+        shared.job.store(std::ptr::null_mut(), Ordering::SeqCst);
+        while shared.busy.load(Ordering::SeqCst) > 0 {
+            std::hint::spin_loop();
+        }
+
+        let size = frame_count * channels;
+        for (accumulator, joined) in shared.accumulators.iter().zip(shared.joined.iter()) {
+            if joined.load(Ordering::Acquire) != generation {
+                continue;
+            }
+
+            // SAFETY: The job is withdrawn and no worker holds it anymore, see `busy` above.
+            let accumulator = unsafe { &*accumulator.get() };
+            output.accumulate(&accumulator[..size]);
+        }
+
+        shared.state.store(STATE_IDLE, Ordering::Release);
+
+        Some(active_sources + shared.active.load(Ordering::Relaxed))
+    }
+}
+
+impl Drop for ParallelMixerHandle {
+    fn drop(&mut self) {
+        // Wait for the audio thread to finish the current job, if any
+        while self
+            .shared
+            .state
+            .compare_exchange(STATE_IDLE, STATE_SHUTDOWN, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            std::thread::yield_now();
+        }
+
+        for worker in self.workers.iter() {
+            worker.thread().unpark();
+        }
+
+        for worker in self.workers.drain(..) {
+            _ = worker.join();
+        }
+    }
+}
+
+fn worker_main(shared: Arc<Shared>, index: usize, buffer_size: usize) {
+    // Workers only ever run mixing jobs, keep flush-to-zero on for their whole lifetime
+    let _denormals = DenormalGuard::new();
+    let mut scratch = MixScratch::new(buffer_size);
+    let mut listener: Option<SpatializationListener> = None;
+    let mut generation = 0;
+
+    loop {
+        if shared.state.load(Ordering::Acquire) == STATE_SHUTDOWN {
+            return;
+        }
+
+        let current = shared.generation.load(Ordering::Acquire);
+        if current == generation {
+            std::thread::park();
+            continue;
+        }
+
+        generation = current;
+
+        // Counted before looking at the job, so the audio thread either waits for this worker
+        // or this worker sees the job withdrawn
+        shared.busy.fetch_add(1, Ordering::SeqCst);
+
+        let job = shared.job.load(Ordering::SeqCst);
+        if job.is_null() {
+            shared.busy.fetch_sub(1, Ordering::SeqCst);
+            continue;
+        }
+
+        // Published before the job, a newer generation means this worker woke up late
+        generation = shared.generation.load(Ordering::SeqCst);
+
+        // SAFETY: The job outlives its withdrawal, after which the audio thread waits until
+        // every worker holding it decrements `busy` below.
+        let job = unsafe { &*job };
+
+        // SAFETY: Only this worker writes its accumulator while the job is running.
+        let accumulator = unsafe { &mut *shared.accumulators[index].get() };
+        let size = job.frame_count * job.channels;
+        MathUtils::simd_set(&mut accumulator[..size], 0.0);
+
+        scratch
+            .channel_converter
+            .set_output_channels(job.converter_channels);
+
+        match job.listener {
+            Some(snapshot) => {
+                if listener.is_none() {
+                    listener = SpatializationListener::new(job.channels as u32).ok();
+                }
+
+                if let Some(listener) = listener.as_mut() {
+                    snapshot.apply(listener);
+                    listener.hrtf = job.hrtf.clone();
+                }
+            }
+            None => listener = None,
+        }
+
+        // SAFETY: The gains are owned by the audio thread and left untouched until the job ends.
+        let gains = unsafe { std::slice::from_raw_parts(job.gains, job.gains_len) };
+
+        shared.joined[index].store(generation, Ordering::Release);
+
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            let mut active_sources = 0;
+            loop {
+                let handle_index = shared.next.fetch_add(1, Ordering::Relaxed);
+                if handle_index >= job.len {
+                    break;
+                }
+
+                // SAFETY: Every index is claimed once, nobody else touches this handle.
+                let handle = unsafe { &mut *job.handles.add(handle_index) };
+
+                active_sources += render_handle(
+                    handle,
+                    listener.as_mut(),
+                    &mut [],
+                    &mut scratch,
+                    gains,
+                    accumulator,
+                    job.frame_count,
+                    job.channels,
+                ) as usize;
+            }
+
+            shared.active.fetch_add(active_sources, Ordering::Relaxed);
+        }));
+
+        if let Err(err) = result {
+            eprintln!("Rust panic! in mixer worker: {:?}", err);
+        }
+
+        shared.busy.fetch_sub(1, Ordering::SeqCst);
+    }
+}
@@ -0,0 +1,362 @@
+use crate::{
+    effects::{AudioFX, PanMode, SpatializationListener},
+    math::Vector3,
+    mixer::inner::MixerChannel,
+    sample::sampleinner::{SampleChannelHandle, SampleChannelStatus},
+    track::inner::TrackChannel,
+};
+
+use std::sync::atomic::Ordering;
+
+use super::{AudioHandle, Device, DeviceCommand, DeviceError, DeviceState};
+
+/// The kind of source a [SourceSnapshot] was captured from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum SourceKind {
+    Track,
+    Sample,
+    Mixer,
+}
+
+/// Tempo and pitch of an enabled AudioFX.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FXSnapshot {
+    pub tempo: f32,
+    pub pitch: f32,
+}
+
+/// State of the device spatialization listener.
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ListenerSnapshot {
+    pub position: Vector3<f32>,
+    pub direction: Vector3<f32>,
+    pub velocity: Vector3<f32>,
+    pub world_up: Vector3<f32>,
+    pub speed_of_sound: f32,
+    pub cone: (f32, f32, f32),
+    pub enabled: bool,
+}
+
+/// Settings of a single source attached to the device.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SourceSnapshot {
+    pub kind: SourceKind,
+    pub ref_id: usize,
+    pub playing: bool,
+    pub looping: bool,
+    pub position: usize,
+    pub volume: f32,
+    pub pan: f32,
+    pub pan_mode: PanMode,
+    pub sample_rate: f32,
+    pub fx: Option<FXSnapshot>,
+}
+
+/// A point-in-time capture of the device master state and its attached sources.
+///
+/// Created with [Device::snapshot](crate::Device::snapshot) and applied back with
+/// [Device::restore](crate::Device::restore).
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DeviceSnapshot {
+    pub volume: f32,
+    pub pan: f32,
+    pub pan_mode: PanMode,
+    pub listener: Option<ListenerSnapshot>,
+    pub sources: Vec<SourceSnapshot>,
+}
+
+impl ListenerSnapshot {
+    pub(crate) fn capture(listener: &SpatializationListener) -> Self {
+        Self {
+            position: listener.get_position(),
+            direction: listener.get_direction(),
+            velocity: listener.get_velocity(),
+            world_up: listener.get_world_up(),
+            speed_of_sound: listener.get_speed_of_sound(),
+            cone: listener.get_cone(),
+            enabled: listener.is_enabled(),
+        }
+    }
+
+    pub(crate) fn apply(&self, listener: &mut SpatializationListener) {
+        listener.set_position(self.position);
+        listener.set_direction(self.direction);
+        listener.set_velocity(self.velocity);
+        listener.set_world_up(self.world_up);
+        listener.set_speed_of_sound(self.speed_of_sound);
+        listener.set_cone(self.cone.0, self.cone.1, self.cone.2);
+        listener.set_enabled(self.enabled);
+    }
+}
+
+impl FXSnapshot {
+    pub(super) fn capture(fx: &Option<AudioFX>) -> Option<Self> {
+        fx.as_ref().map(|fx| Self {
+            tempo: fx.tempo,
+            pitch: fx.octave,
+        })
+    }
+
+    fn apply(
+        snapshot: &Option<Self>,
+        fx: &mut Option<AudioFX>,
+        channels: usize,
+        sample_rate: f32,
+    ) -> Result<(), DeviceError> {
+        let Some(snapshot) = snapshot else {
+            *fx = None;
+            return Ok(());
+        };
+
+        if fx.is_none() {
+            *fx = Some(AudioFX::new(channels, sample_rate).map_err(DeviceError::from_other)?);
+        }
+
+        let fx = fx.as_mut().unwrap();
+        fx.set_tempo(snapshot.tempo).map_err(DeviceError::from_other)?;
+        fx.set_octave(snapshot.pitch).map_err(DeviceError::from_other)?;
+
+        Ok(())
+    }
+}
+
+impl SourceSnapshot {
+    /// Capture the source behind `handle`, None when it was dropped or its lock is poisoned.
+    pub(crate) fn capture(handle: &AudioHandle) -> Option<Self> {
+        match handle.source() {
+            AudioHandle::Track(track) => track
+                .upgrade()
+                .and_then(|track| track.lock().ok().map(|t| SourceSnapshot::from_track(&t))),
+            AudioHandle::Sample(sample) => sample
+                .upgrade()
+                .and_then(|sample| sample.lock().ok().map(|s| SourceSnapshot::from_sample(&s))),
+            AudioHandle::Mixer(mixer) => mixer
+                .upgrade()
+                .and_then(|mixer| mixer.lock().ok().map(|m| SourceSnapshot::from_mixer(&m))),
+            AudioHandle::Split(_) => None,
+        }
+    }
+
+    pub(crate) fn from_track(track: &TrackChannel) -> Self {
+        Self {
+            kind: SourceKind::Track,
+            ref_id: track.ref_id,
+            playing: track.playing.load(Ordering::SeqCst),
+            looping: track.is_looping.load(Ordering::SeqCst),
+            position: track.position.load(Ordering::SeqCst),
+            volume: track.gainer.volume,
+            pan: track.panner.pan,
+            pan_mode: track.panner.mode,
+            sample_rate: track.resampler.target_sample_rate,
+            fx: FXSnapshot::capture(&track.fx),
+        }
+    }
+
+    pub(crate) fn from_sample(sample: &SampleChannelHandle) -> Self {
+        Self {
+            kind: SourceKind::Sample,
+            ref_id: sample.ref_id,
+            playing: sample.status.load(Ordering::Relaxed) == SampleChannelStatus::Playing,
+            looping: false,
+            position: sample.reader.position,
+            volume: sample.volume.volume,
+            pan: sample.panner.pan,
+            pan_mode: sample.panner.mode,
+            sample_rate: sample.resampler.target_sample_rate,
+            fx: FXSnapshot::capture(&sample.fx),
+        }
+    }
+
+    pub(crate) fn from_mixer(mixer: &MixerChannel) -> Self {
+        Self {
+            kind: SourceKind::Mixer,
+            ref_id: mixer.ref_id,
+            playing: mixer.is_playing.load(Ordering::SeqCst),
+            looping: mixer.is_infinite,
+            position: mixer.mixer_position,
+            volume: mixer.volume.volume,
+            pan: mixer.panner.pan,
+            pan_mode: mixer.panner.mode,
+            sample_rate: mixer.resampler.target_sample_rate,
+            fx: FXSnapshot::capture(&mixer.fx),
+        }
+    }
+
+    pub(crate) fn apply_track(&self, track: &mut TrackChannel) -> Result<(), DeviceError> {
+        track.gainer.set_volume(self.volume);
+        track.panner.set_mode(self.pan_mode);
+        track.panner.set_pan(self.pan);
+        track.resampler.set_target_sample_rate(self.sample_rate);
+        track.is_looping.store(self.looping, Ordering::SeqCst);
+
+        let (channels, sample_rate) = (track.reader.channels, track.reader.sample_rate);
+        FXSnapshot::apply(&self.fx, &mut track.fx, channels, sample_rate)?;
+
+        if self.position < track.reader.pcm_length {
+            track.seek(self.position).map_err(DeviceError::from_other)?;
+        }
+
+        track.playing.store(self.playing, Ordering::SeqCst);
+        Ok(())
+    }
+
+    pub(crate) fn apply_sample(&self, sample: &mut SampleChannelHandle) -> Result<(), DeviceError> {
+        sample.volume.set_volume(self.volume);
+        sample.panner.set_mode(self.pan_mode);
+        sample.panner.set_pan(self.pan);
+        sample.resampler.set_target_sample_rate(self.sample_rate);
+
+        let (channels, sample_rate) = (sample.reader.channels, sample.reader.sample_rate);
+        FXSnapshot::apply(&self.fx, &mut sample.fx, channels, sample_rate)?;
+
+        if self.position < sample.reader.pcm_length {
+            sample.seek(self.position).map_err(DeviceError::from_other)?;
+        }
+
+        sample.status.store(
+            match self.playing {
+                true => SampleChannelStatus::Playing,
+                false => SampleChannelStatus::Finished,
+            },
+            Ordering::Relaxed,
+        );
+
+        Ok(())
+    }
+
+    pub(crate) fn apply_mixer(&self, mixer: &mut MixerChannel) -> Result<(), DeviceError> {
+        mixer.volume.set_volume(self.volume);
+        mixer.panner.set_mode(self.pan_mode);
+        mixer.panner.set_pan(self.pan);
+        mixer.resampler.set_target_sample_rate(self.sample_rate);
+
+        let (channels, sample_rate) = (mixer.channel_count, mixer.resampler.sample_rate);
+        FXSnapshot::apply(&self.fx, &mut mixer.fx, channels, sample_rate)?;
+
+        mixer
+            .seek(Some(self.position))
+            .map_err(DeviceError::from_other)?;
+
+        match self.playing {
+            true => mixer.start(),
+            false => mixer.stop(),
+        }
+
+        Ok(())
+    }
+}
+
+impl DeviceSnapshot {
+    pub(crate) fn capture(state: &DeviceState) -> Self {
+        let sources = state
+            .handles
+            .iter()
+            .filter_map(SourceSnapshot::capture)
+            .collect();
+
+        Self {
+            volume: state.volume,
+            pan: state.pan,
+            pan_mode: state.pan_mode,
+            listener: state.listener,
+            sources,
+        }
+    }
+
+    /// Sources are matched by kind and ref id, sources in the snapshot which no longer
+    /// attached to the device are ignored.
+    pub(crate) fn apply(&self, device: &Device) -> Result<(), DeviceError> {
+        let Ok(mut state) = device.state.lock() else {
+            return Err(DeviceError::InvalidOperation(-1)); // Use a custom error code for lock failure
+        };
+
+        state.volume = self.volume;
+        state.pan = self.pan;
+        state.pan_mode = self.pan_mode;
+        device.send(DeviceCommand::Volume(self.volume))?;
+        device.send(DeviceCommand::PanMode(self.pan_mode))?;
+        device.send(DeviceCommand::Pan(self.pan))?;
+
+        match (&self.listener, state.listener.is_some()) {
+            (Some(snapshot), true) => {
+                device.send(DeviceCommand::Listener(*snapshot))?;
+            }
+            (Some(snapshot), false) => {
+                let mut listener = SpatializationListener::new(device.channels as u32)
+                    .map_err(DeviceError::from_other)?;
+
+                snapshot.apply(&mut listener);
+                device.send(DeviceCommand::Spatialization(Some(listener)))?;
+            }
+            (None, true) => {
+                device.send(DeviceCommand::Spatialization(None))?;
+
+                if !state.listeners.is_empty() {
+                    state.listeners.clear();
+                    device.send(DeviceCommand::Listeners(Vec::new()))?;
+                }
+            }
+            (None, false) => {}
+        }
+
+        state.listener = self.listener;
+
+        for handle in state.handles.iter() {
+            match handle.source() {
+                AudioHandle::Track(track) => {
+                    let Some(track) = track.upgrade() else {
+                        continue;
+                    };
+
+                    let Ok(mut track) = track.lock() else {
+                        continue;
+                    };
+
+                    if let Some(source) = self.find(SourceKind::Track, track.ref_id) {
+                        source.apply_track(&mut track)?;
+                    }
+                }
+                AudioHandle::Sample(sample) => {
+                    let Some(sample) = sample.upgrade() else {
+                        continue;
+                    };
+
+                    let Ok(mut sample) = sample.lock() else {
+                        continue;
+                    };
+
+                    if let Some(source) = self.find(SourceKind::Sample, sample.ref_id) {
+                        source.apply_sample(&mut sample)?;
+                    }
+                }
+                AudioHandle::Mixer(mixer) => {
+                    let Some(mixer) = mixer.upgrade() else {
+                        continue;
+                    };
+
+                    let Ok(mut mixer) = mixer.lock() else {
+                        continue;
+                    };
+
+                    if let Some(source) = self.find(SourceKind::Mixer, mixer.ref_id) {
+                        source.apply_mixer(&mut mixer)?;
+                    }
+                }
+                AudioHandle::Split(_) => {}
+            }
+        }
+
+        Ok(())
+    }
+
+    fn find(&self, kind: SourceKind, ref_id: usize) -> Option<&SourceSnapshot> {
+        self.sources
+            .iter()
+            .find(|source| source.kind == kind && source.ref_id == ref_id)
+    }
+}
@@ -0,0 +1,566 @@
+use std::sync::{
+    Arc, Mutex, TryLockError, Weak,
+    atomic::{AtomicBool, AtomicI64, AtomicU32, Ordering},
+};
+
+use ringbuf::{
+    HeapCons, HeapProd, HeapRb,
+    traits::{Consumer, Observer, Producer, Split},
+};
+
+use crate::{
+    Device, DeviceError, Mixer, Track,
+    effects::{ChannelConverter, SpatializationListener},
+    misc::finishsignal::Completion,
+    mixer::inner::MixerChannel,
+};
+
+use super::AudioHandle;
+
+/// Frames an output may fall behind the fastest device before its newest frames are dropped.
+pub(crate) const DEFAULT_SPLIT_LATENCY: usize = 4096;
+
+// Stored in SplitOutput::group for a source outside of any ducking group
+const NO_GROUP: i64 = -1;
+
+/// The frames one device of a [Splitter] plays, shared between the control thread and the
+/// audio thread of that device.
+pub(crate) struct SplitOutput {
+    device_ref_id: u32,
+    channels: usize,
+    gain: AtomicU32,
+    group: AtomicI64,
+    removed: AtomicBool,
+    // Only popped by the audio thread of the device, the control thread swaps it when the
+    // latency grows past the ring
+    fifo: Mutex<HeapCons<f32>>,
+}
+
+impl SplitOutput {
+    pub fn group(&self) -> Option<u32> {
+        match self.group.load(Ordering::Relaxed) {
+            NO_GROUP => None,
+            group => Some(group as u32),
+        }
+    }
+
+    /// Whether the device was taken off the splitter, it stops playing the source then.
+    pub fn is_removed(&self) -> bool {
+        self.removed.load(Ordering::Acquire)
+    }
+}
+
+/// The pushing end of the ring of an output.
+struct SplitFeed {
+    output: Arc<SplitOutput>,
+    producer: HeapProd<f32>,
+}
+
+/// The control side of a [Splitter], also locked by the audio thread reading the source.
+///
+/// Every device pulls its frames from the lock-free ring of its own [SplitOutput]. A device
+/// finding its ring short reads the source once and pushes the frames to the ring of every
+/// output, so the source advances once no matter how many devices play it. A device finding
+/// the source being read by another device plays what its ring holds instead.
+pub(crate) struct SplitterChannel {
+    pub source: AudioHandle,
+    feeds: Vec<SplitFeed>,
+    channels: usize,
+    sample_rate: f32,
+    block_size: usize,
+    max_latency: usize,
+}
+
+/// Handle given to each device a split source plays on, see [AudioHandle::Split].
+#[derive(Clone)]
+pub(crate) struct SplitHandle {
+    pub channel: Weak<Mutex<SplitterChannel>>,
+    pub output: Arc<SplitOutput>,
+    pub source: Box<AudioHandle>,
+    pub device_ref_id: u32,
+}
+
+impl SplitterChannel {
+    fn feed(&self, device_ref_id: u32) -> Option<&SplitFeed> {
+        self.feeds
+            .iter()
+            .find(|feed| feed.output.device_ref_id == device_ref_id)
+    }
+
+    pub fn remove_output(&mut self, device_ref_id: u32) -> bool {
+        let count = self.feeds.len();
+        self.feeds.retain(|feed| {
+            let keep = feed.output.device_ref_id != device_ref_id;
+            if !keep {
+                feed.output.removed.store(true, Ordering::Release);
+            }
+
+            keep
+        });
+
+        self.feeds.len() != count
+    }
+
+    /// A ring holding the max latency plus one block of every device.
+    fn ring(&self) -> (HeapProd<f32>, HeapCons<f32>) {
+        HeapRb::<f32>::new(self.ring_len()).split()
+    }
+
+    fn ring_len(&self) -> usize {
+        (self.max_latency + self.block_size) * self.channels
+    }
+
+    /// Give every output a ring sized for the current latency, the queued frames are dropped.
+    fn resize_rings(&mut self) {
+        let len = self.ring_len();
+
+        for feed in self.feeds.iter_mut() {
+            let (producer, consumer) = HeapRb::<f32>::new(len).split();
+
+            // The audio thread only try_locks the ring and skips a block when it misses
+            if let Ok(mut fifo) = feed.output.fifo.lock() {
+                *fifo = consumer;
+                feed.producer = producer;
+            }
+        }
+    }
+
+    /// Read the source until the ring of `device_ref_id` holds `frame_count` frames on top of
+    /// the `queued` samples, pushing every block to all outputs. An output already
+    /// `max_latency` frames behind skips the block. Returns false once the source ended.
+    fn fill(
+        &mut self,
+        device_ref_id: u32,
+        mut spatialization: Option<&mut SpatializationListener>,
+        channel_converter: &mut ChannelConverter,
+        buffer1: &mut [f32],
+        buffer2: &mut [f32],
+        frame_count: usize,
+        mut queued: usize,
+    ) -> bool {
+        let channels = self.channels;
+        let needed = frame_count * channels;
+        let limit = (self.max_latency * channels).max(needed);
+
+        if self.feed(device_ref_id).is_none() {
+            return true;
+        }
+
+        while queued < needed {
+            let frames = match read_source(
+                &self.source,
+                spatialization.as_deref_mut(),
+                channel_converter,
+                buffer1,
+                buffer2,
+                frame_count,
+            ) {
+                SourceRead::Frames(0) | SourceRead::Busy => break,
+                SourceRead::Frames(frames) => frames,
+                SourceRead::Ended => return false,
+            };
+
+            let group = source_group(&self.source);
+            let group = group.map(|group| group.map_or(NO_GROUP, i64::from));
+            let samples = &buffer1[..frames * channels];
+            let mut pushed = false;
+
+            for feed in self.feeds.iter_mut() {
+                if let Some(group) = group {
+                    feed.output.group.store(group, Ordering::Relaxed);
+                }
+
+                // Whole blocks only, so the ring always holds whole frames
+                if feed.producer.occupied_len() + samples.len() > limit
+                    || feed.producer.vacant_len() < samples.len()
+                {
+                    continue;
+                }
+
+                feed.producer.push_slice(samples);
+                if feed.output.device_ref_id == device_ref_id {
+                    queued += samples.len();
+                    pushed = true;
+                }
+            }
+
+            if !pushed {
+                break;
+            }
+        }
+
+        true
+    }
+}
+
+impl SplitHandle {
+    /// Fill `buffer1` with the next `frame_count` frames of this device, reading the source
+    /// when the ring runs short.
+    ///
+    /// A stopped source, or one read by another device or locked by the control thread,
+    /// yields what is left in the ring. Returns None when the device is no longer an output
+    /// or the source is gone, the output is removed then.
+    pub fn read(
+        &self,
+        spatialization: Option<&mut SpatializationListener>,
+        channel_converter: &mut ChannelConverter,
+        buffer1: &mut [f32],
+        buffer2: &mut [f32],
+        frame_count: usize,
+    ) -> Option<usize> {
+        if self.output.removed.load(Ordering::Acquire) {
+            return None;
+        }
+
+        let Some(splitter) = self.channel.upgrade() else {
+            return None;
+        };
+
+        // Only contended while the control thread grows the ring
+        let Ok(mut fifo) = self.output.fifo.try_lock() else {
+            return Some(0);
+        };
+
+        let channels = self.output.channels;
+        let needed = frame_count * channels;
+
+        if fifo.occupied_len() < needed {
+            match splitter.try_lock() {
+                Ok(mut splitter) => {
+                    let queued = fifo.occupied_len();
+                    let ended = !splitter.fill(
+                        self.device_ref_id,
+                        spatialization,
+                        channel_converter,
+                        buffer1,
+                        buffer2,
+                        frame_count,
+                        queued,
+                    );
+
+                    if ended {
+                        splitter.remove_output(self.device_ref_id);
+                        return None;
+                    }
+                }
+                Err(TryLockError::Poisoned(_)) => {
+                    eprintln!("Warning: Audio splitter is poisoned");
+                    return None;
+                }
+                Err(TryLockError::WouldBlock) => {}
+            }
+        }
+
+        let available = fifo.occupied_len().min(needed) / channels * channels;
+        let popped = fifo.pop_slice(&mut buffer1[..available]);
+        let gain = f32::from_bits(self.output.gain.load(Ordering::Relaxed));
+
+        for sample in buffer1[..popped].iter_mut() {
+            *sample *= gain;
+        }
+
+        Some(popped / channels)
+    }
+}
+
+enum SourceRead {
+    Frames(usize),
+    Busy,
+    Ended,
+}
+
+fn read_source(
+    source: &AudioHandle,
+    spatialization: Option<&mut SpatializationListener>,
+    channel_converter: &mut ChannelConverter,
+    buffer1: &mut [f32],
+    buffer2: &mut [f32],
+    frame_count: usize,
+) -> SourceRead {
+    macro_rules! read {
+        ($weak:expr, $kind:literal) => {{
+            let Some(source) = $weak.upgrade() else {
+                return SourceRead::Ended;
+            };
+
+            match source.try_lock() {
+                Ok(mut source) => match source.read(
+                    spatialization,
+                    channel_converter,
+                    buffer1,
+                    buffer2,
+                    frame_count,
+                ) {
+                    Ok(frames) => SourceRead::Frames(frames),
+                    Err(err) => {
+                        eprintln!("Error reading PCM frames from split {}: {}", $kind, err);
+                        SourceRead::Ended
+                    }
+                },
+                Err(TryLockError::Poisoned(source)) => {
+                    let ref_id = source.get_ref().ref_id;
+
+                    eprintln!("Warning: Split {} {} is poisoned", $kind, ref_id);
+                    SourceRead::Ended
+                }
+                Err(TryLockError::WouldBlock) => SourceRead::Busy,
+            }
+        }};
+    }
+
+    match source {
+        AudioHandle::Track(track) => read!(track, "track"),
+        AudioHandle::Mixer(mixer) => read!(mixer, "mixer"),
+        _ => SourceRead::Ended,
+    }
+}
+
+/// Ducking group of the source, read right after the source was read so its lock is free.
+fn source_group(source: &AudioHandle) -> Option<Option<u32>> {
+    match source {
+        AudioHandle::Track(track) => track.upgrade()?.try_lock().ok().map(|t| t.group),
+        AudioHandle::Mixer(mixer) => mixer.upgrade()?.try_lock().ok().map(|m| m.group),
+        _ => None,
+    }
+}
+
+/// Plays one track or mixer on several devices at once, each with its own gain.
+///
+/// A source attached to two devices directly would be read by both audio threads and advance
+/// twice as fast. The splitter reads the source once and hands every device a copy through a
+/// fifo, so a headphone cue device can follow the main speakers.
+///
+/// Every device has to share the channel count and sample rate of the first one added. Devices
+/// run on their own clocks, a device falling more than [Splitter::set_max_latency] frames
+/// behind the others skips the newest frames until it caught up.
+pub struct Splitter {
+    inner: Arc<Mutex<SplitterChannel>>,
+    source: AudioHandle,
+}
+
+impl Splitter {
+    /// Split `track`, the track should not be played on a device directly while split.
+    pub fn from_track(track: &Track) -> Self {
+        Self::new(AudioHandle::Track(Arc::downgrade(&track.inner)))
+    }
+
+    /// Split `mixer`, the mixer should not be played on a device directly while split.
+    pub fn from_mixer(mixer: &Mixer) -> Self {
+        Self::new(AudioHandle::Mixer(Arc::downgrade(&mixer.inner)))
+    }
+
+    fn new(source: AudioHandle) -> Self {
+        let inner = SplitterChannel {
+            source: source.clone(),
+            feeds: Vec::new(),
+            channels: 0,
+            sample_rate: 0.0,
+            block_size: 0,
+            max_latency: DEFAULT_SPLIT_LATENCY,
+        };
+
+        Self {
+            inner: Arc::new(Mutex::new(inner)),
+            source,
+        }
+    }
+
+    /// Start the source from its beginning (or cue point for a track), the returned
+    /// [Completion] resolves once the source ends or is stopped.
+    pub fn play(&mut self) -> Result<Completion, DeviceError> {
+        match &self.source {
+            AudioHandle::Track(track) => {
+                let Some(track) = track.upgrade() else {
+                    return Err(DeviceError::InvalidOperation(-1));
+                };
+
+                let Ok(mut track) = track.lock() else {
+                    return Err(DeviceError::InvalidOperation(-1)); // Use a custom error code for lock failure
+                };
+
+                track.begin_playback().map_err(DeviceError::from_other)
+            }
+            AudioHandle::Mixer(mixer) => {
+                let Some(mixer) = mixer.upgrade() else {
+                    return Err(DeviceError::InvalidOperation(-1));
+                };
+
+                let Ok(mut mixer) = mixer.lock() else {
+                    return Err(DeviceError::InvalidOperation(-1)); // Use a custom error code for lock failure
+                };
+
+                mixer.begin_playback().map_err(DeviceError::from_other)
+            }
+            _ => Err(DeviceError::InvalidOperation(-1)),
+        }
+    }
+
+    /// Stop the source on every device.
+    pub fn stop(&mut self) -> Result<(), DeviceError> {
+        self.source.stop();
+        Ok(())
+    }
+
+    /// Play the source on `device` as well, at `gain`.
+    pub fn add_device(&mut self, device: &mut Device, gain: f32) -> Result<(), DeviceError> {
+        let output = {
+            let Ok(mut inner) = self.inner.lock() else {
+                return Err(DeviceError::InvalidOperation(-1)); // Use a custom error code for lock failure
+            };
+
+            if inner.feed(device.get_ref_id()).is_some() {
+                return Err(DeviceError::ChannelAlreadyExists(device.get_ref_id() as usize));
+            }
+
+            if inner.feeds.is_empty() {
+                inner.channels = device.channels;
+                inner.sample_rate = device.sample_rate;
+                attach_source(&self.source, device);
+            } else if inner.channels != device.channels || inner.sample_rate != device.sample_rate
+            {
+                return Err(DeviceError::SplitFormatMismatch);
+            }
+
+            // The ring of every output holds the max latency plus a block of the largest device
+            if device.block_size > inner.block_size {
+                inner.block_size = device.block_size;
+                inner.resize_rings();
+            }
+
+            let (producer, consumer) = inner.ring();
+            let output = Arc::new(SplitOutput {
+                device_ref_id: device.get_ref_id(),
+                channels: inner.channels,
+                gain: AtomicU32::new(gain.to_bits()),
+                group: AtomicI64::new(NO_GROUP),
+                removed: AtomicBool::new(false),
+                fifo: Mutex::new(consumer),
+            });
+
+            inner.feeds.push(SplitFeed {
+                output: output.clone(),
+                producer,
+            });
+
+            output
+        };
+
+        let result = device.attach_split(SplitHandle {
+            channel: Arc::downgrade(&self.inner),
+            output,
+            source: Box::new(self.source.clone()),
+            device_ref_id: device.get_ref_id(),
+        });
+
+        if let Err(e) = result {
+            if let Ok(mut inner) = self.inner.lock() {
+                inner.remove_output(device.get_ref_id());
+            }
+
+            return Err(e);
+        }
+
+        Ok(())
+    }
+
+    /// Stop playing the source on `device`, returns false when it was not added.
+    pub fn remove_device(&mut self, device: &mut Device) -> Result<bool, DeviceError> {
+        let output = {
+            let Ok(mut inner) = self.inner.lock() else {
+                return Err(DeviceError::InvalidOperation(-1)); // Use a custom error code for lock failure
+            };
+
+            let output = inner.feed(device.get_ref_id()).map(|feed| feed.output.clone());
+            inner.remove_output(device.get_ref_id());
+            output
+        };
+
+        let Some(output) = output else {
+            return Ok(false);
+        };
+
+        device.detach(AudioHandle::Split(SplitHandle {
+            channel: Arc::downgrade(&self.inner),
+            output,
+            source: Box::new(self.source.clone()),
+            device_ref_id: device.get_ref_id(),
+        }))?;
+
+        Ok(true)
+    }
+
+    pub fn set_gain(&mut self, device: &Device, gain: f32) -> Result<(), DeviceError> {
+        self.set_output_gain(device.get_ref_id(), gain)
+    }
+
+    pub(crate) fn set_output_gain(
+        &mut self,
+        device_ref_id: u32,
+        gain: f32,
+    ) -> Result<(), DeviceError> {
+        let Ok(inner) = self.inner.lock() else {
+            return Err(DeviceError::InvalidOperation(-1)); // Use a custom error code for lock failure
+        };
+
+        let Some(feed) = inner.feed(device_ref_id) else {
+            return Err(DeviceError::ChannelNotFound(device_ref_id as usize));
+        };
+
+        feed.output.gain.store(gain.to_bits(), Ordering::Relaxed);
+        Ok(())
+    }
+
+    pub fn get_gain(&self, device: &Device) -> Result<f32, DeviceError> {
+        let Ok(inner) = self.inner.lock() else {
+            return Err(DeviceError::InvalidOperation(-1)); // Use a custom error code for lock failure
+        };
+
+        let Some(feed) = inner.feed(device.get_ref_id()) else {
+            return Err(DeviceError::ChannelNotFound(device.get_ref_id() as usize));
+        };
+
+        Ok(f32::from_bits(feed.output.gain.load(Ordering::Relaxed)))
+    }
+
+    /// Frames a device may fall behind the fastest device before its newest frames are
+    /// skipped, defaults to 4096. The rings are reallocated here when they grow, never on an
+    /// audio thread.
+    pub fn set_max_latency(&mut self, frames: usize) -> Result<(), DeviceError> {
+        let Ok(mut inner) = self.inner.lock() else {
+            return Err(DeviceError::InvalidOperation(-1)); // Use a custom error code for lock failure
+        };
+
+        let frames = frames.max(1);
+        let grows = frames > inner.max_latency;
+        inner.max_latency = frames;
+
+        if grows {
+            inner.resize_rings();
+        }
+
+        Ok(())
+    }
+
+    /// Number of devices the source plays on.
+    pub fn device_count(&self) -> usize {
+        self.inner.lock().map_or(0, |inner| inner.feeds.len())
+    }
+}
+
+/// Give the source the buffer pool and clock of the first device it plays on.
+fn attach_source(source: &AudioHandle, device: &Device) {
+    match source {
+        AudioHandle::Track(track) => {
+            if let Some(Ok(mut track)) = track.upgrade().as_deref().map(Mutex::lock) {
+                track.pool = Some(device.pool.clone());
+                track.clock = Some(device.clock.clone());
+            }
+        }
+        AudioHandle::Mixer(mixer) => {
+            if let Some(Ok(mut mixer)) = mixer.upgrade().as_deref().map(Mutex::lock) {
+                MixerChannel::recursive_set_pool(&mut mixer, Some(device.pool.clone()), 0);
+            }
+        }
+        _ => {}
+    }
+}
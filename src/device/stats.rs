@@ -0,0 +1,168 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+/// Engine counters of a device, see [Device::stats](crate::Device::stats).
+///
+/// The counters add up from the device creation or the last
+/// [Device::reset_stats](crate::Device::reset_stats), the rest describes the last callback.
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Stats {
+    /// Audio callbacks run.
+    pub callbacks: u64,
+    /// Frames rendered over all callbacks.
+    pub frames: u64,
+    /// Sources that produced frames in the last callback.
+    pub active_voices: usize,
+    /// Highest absolute sample per channel of the last callback output, or of the input on a
+    /// capture device.
+    pub peaks: Vec<f32>,
+    /// Callbacks that took longer than the audio they rendered lasts, each of which is likely
+    /// heard as a dropout.
+    pub underruns: u64,
+    /// Scratch buffers the device pool had none free for, each of which left a source silent
+    /// for a block, played a loop seam without its crossfade or failed a seek.
+    pub scratch_exhausted: u64,
+    pub average_callback: Duration,
+    pub max_callback: Duration,
+}
+
+#[derive(Debug, Default)]
+struct StatsData {
+    stats: Stats,
+    reset: bool,
+}
+
+/// The control side of the device statistics, cheap to clone.
+#[derive(Debug, Clone)]
+pub(crate) struct StatsHandle {
+    data: Arc<Mutex<StatsData>>,
+}
+
+impl StatsHandle {
+    pub fn new(channels: usize) -> Self {
+        let data = StatsData {
+            stats: Stats {
+                peaks: vec![0.0; channels],
+                ..Default::default()
+            },
+            reset: false,
+        };
+
+        Self {
+            data: Arc::new(Mutex::new(data)),
+        }
+    }
+
+    /// The statistics as published after the last callback, one consistent copy.
+    pub fn get(&self) -> Option<Stats> {
+        self.data.lock().ok().map(|data| data.stats.clone())
+    }
+
+    /// Zero the counters, the audio thread starts over from its next callback.
+    pub fn reset(&self) {
+        if let Ok(mut data) = self.data.lock() {
+            data.stats.reset();
+            data.reset = true;
+        }
+    }
+
+    pub fn tap(&self, sample_rate: f32) -> StatsTap {
+        let stats = self.get().unwrap_or_default();
+
+        StatsTap {
+            handle: self.clone(),
+            stats,
+            total_time: Duration::ZERO,
+            sample_rate,
+        }
+    }
+}
+
+/// The audio thread side of a [StatsHandle].
+pub(crate) struct StatsTap {
+    handle: StatsHandle,
+    stats: Stats,
+    total_time: Duration,
+    sample_rate: f32,
+}
+
+impl StatsTap {
+    pub fn handle(&self) -> StatsHandle {
+        self.handle.clone()
+    }
+
+    /// Account a callback that started at `started`, rendered `frames` frames and ended with
+    /// `audio` holding the interleaved output of `channels` channels.
+    pub fn record(
+        &mut self,
+        started: Instant,
+        frames: usize,
+        active_voices: usize,
+        audio: &[f32],
+        channels: usize,
+    ) {
+        let elapsed = started.elapsed();
+
+        self.stats.callbacks += 1;
+        self.stats.frames += frames as u64;
+        self.stats.active_voices = active_voices;
+
+        let budget = Duration::from_secs_f64(frames as f64 / self.sample_rate as f64);
+        if elapsed > budget {
+            self.stats.underruns += 1;
+        }
+
+        self.total_time += elapsed;
+        self.stats.average_callback =
+            Duration::from_secs_f64(self.total_time.as_secs_f64() / self.stats.callbacks as f64);
+        self.stats.max_callback = self.stats.max_callback.max(elapsed);
+
+        self.stats.peaks.fill(0.0);
+        for frame in audio.chunks_exact(channels.max(1)) {
+            for (peak, sample) in self.stats.peaks.iter_mut().zip(frame) {
+                *peak = peak.max(sample.abs());
+            }
+        }
+
+        // Never blocks the audio thread, the counters keep adding up until the lock is free
+        let Ok(mut data) = self.handle.data.try_lock() else {
+            return;
+        };
+
+        if data.reset {
+            data.reset = false;
+            self.stats.reset();
+            self.total_time = Duration::ZERO;
+        }
+
+        data.stats.copy_from(&self.stats);
+    }
+}
+
+impl Stats {
+    fn reset(&mut self) {
+        self.callbacks = 0;
+        self.frames = 0;
+        self.active_voices = 0;
+        self.peaks.fill(0.0);
+        self.underruns = 0;
+        self.scratch_exhausted = 0;
+        self.average_callback = Duration::ZERO;
+        self.max_callback = Duration::ZERO;
+    }
+
+    // Field by field so the peaks are copied in place, the derived clone_from allocates
+    fn copy_from(&mut self, other: &Stats) {
+        self.callbacks = other.callbacks;
+        self.frames = other.frames;
+        self.active_voices = other.active_voices;
+        self.peaks.clone_from(&other.peaks);
+        self.underruns = other.underruns;
+        self.scratch_exhausted = other.scratch_exhausted;
+        self.average_callback = other.average_callback;
+        self.max_callback = other.max_callback;
+    }
+}
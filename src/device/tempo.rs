@@ -0,0 +1,35 @@
+/// Grid point a quantized play waits for, see [Device::set_tempo](crate::Device::set_tempo).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[repr(C)]
+pub enum Quantize {
+    NextBeat = 0,
+    NextBar = 1,
+}
+
+/// Tempo grid laid over the device clock.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct BeatClock {
+    pub bpm: f32,
+    pub beats_per_bar: u32,
+    // Device time of the first beat of bar one
+    pub origin: u64,
+}
+
+impl BeatClock {
+    /// Device time of the first grid point at or after `now`.
+    pub fn next(&self, quantize: Quantize, now: u64, sample_rate: f32) -> u64 {
+        let beat_frames = sample_rate as f64 * 60.0 / self.bpm as f64;
+        let step = match quantize {
+            Quantize::NextBeat => beat_frames,
+            Quantize::NextBar => beat_frames * self.beats_per_bar as f64,
+        };
+
+        if now <= self.origin {
+            return self.origin;
+        }
+
+        let steps = ((now - self.origin) as f64 / step).ceil();
+        self.origin + (steps * step).round() as u64
+    }
+}
@@ -0,0 +1,208 @@
+use crate::math::Vector3;
+
+use super::hrtf::{HrtfRenderer, HrtfSet};
+
+/// Output layout the first-order ambisonics bus is decoded to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum AmbisonicsDecoder {
+    /// Two virtual cardioids pointing left and right.
+    Stereo,
+    /// Four speakers at 45 and 135 degrees, written as front left, front right, back left,
+    /// back right.
+    Quad,
+    /// Six virtual speakers rendered through the built-in HRTF set, for headphones.
+    Binaural,
+}
+
+// Channels of the B-format bus, ACN order with SN3D normalization
+const BFORMAT_CHANNELS: usize = 4;
+
+// Octahedron of virtual speakers used by the binaural decoder, in listener space
+// (+X right, +Y up, -Z forward)
+const BINAURAL_SPEAKERS: [(f32, f32, f32); 6] = [
+    (0.0, 0.0, -1.0),
+    (0.0, 0.0, 1.0),
+    (-1.0, 0.0, 0.0),
+    (1.0, 0.0, 0.0),
+    (0.0, 1.0, 0.0),
+    (0.0, -1.0, 0.0),
+];
+
+// Quad speakers as (forward, left) components, in front left, front right, back left, back right order
+const QUAD_SPEAKERS: [(f32, f32); 4] = [
+    (std::f32::consts::FRAC_1_SQRT_2, std::f32::consts::FRAC_1_SQRT_2),
+    (std::f32::consts::FRAC_1_SQRT_2, -std::f32::consts::FRAC_1_SQRT_2),
+    (-std::f32::consts::FRAC_1_SQRT_2, std::f32::consts::FRAC_1_SQRT_2),
+    (-std::f32::consts::FRAC_1_SQRT_2, -std::f32::consts::FRAC_1_SQRT_2),
+];
+
+/// First-order B-format bus owned by the device listener.
+///
+/// Spatialized sources are encoded relative to the listener, so turning the listener rotates
+/// the whole sound field. The bus is decoded once per block by the device.
+pub(crate) struct AmbisonicsBus {
+    decoder: AmbisonicsDecoder,
+    bformat: Vec<f32>,
+    frames: usize,
+
+    // Binaural decoding only
+    hrtf: Option<HrtfSet>,
+    renderers: Vec<HrtfRenderer>,
+    speaker: Vec<f32>,
+    rendered: Vec<f32>,
+}
+
+impl AmbisonicsBus {
+    /// Bus for blocks of up to `block_size` frames, `sample_rate` is only used by the binaural
+    /// decoder.
+    pub fn new(decoder: AmbisonicsDecoder, block_size: usize, sample_rate: f32) -> Self {
+        let (hrtf, renderers, speaker_len) = match decoder {
+            AmbisonicsDecoder::Binaural => (
+                HrtfSet::builtin(sample_rate).ok(),
+                (0..BINAURAL_SPEAKERS.len())
+                    .map(|_| HrtfRenderer::default())
+                    .collect(),
+                block_size * 2,
+            ),
+            _ => (None, Vec::new(), 0),
+        };
+
+        Self {
+            decoder,
+            bformat: vec![0.0; block_size * BFORMAT_CHANNELS],
+            frames: 0,
+            hrtf,
+            renderers,
+            speaker: vec![0.0; speaker_len],
+            rendered: vec![0.0; speaker_len],
+        }
+    }
+
+    pub fn decoder(&self) -> AmbisonicsDecoder {
+        self.decoder
+    }
+
+    /// Encode interleaved `input` as a plane wave coming from `direction` (listener space,
+    /// normalized) and add it to the bus.
+    pub fn encode(&mut self, direction: Vector3<f32>, gain: f32, input: &[f32], channels: usize) {
+        let channels = channels.max(1);
+        let frame_count = (input.len() / channels).min(self.bformat.len() / BFORMAT_CHANNELS);
+
+        // Listener space to ambisonics axes, X forward, Y left, Z up
+        let (x, y, z) = (-direction.z, -direction.x, direction.y);
+
+        for (frame, bus) in input
+            .chunks(channels)
+            .zip(self.bformat.chunks_mut(BFORMAT_CHANNELS))
+            .take(frame_count)
+        {
+            let sample = frame.iter().sum::<f32>() / channels as f32 * gain;
+
+            // ACN order: W, Y, Z, X
+            bus[0] += sample;
+            bus[1] += sample * y;
+            bus[2] += sample * z;
+            bus[3] += sample * x;
+        }
+
+        self.frames = self.frames.max(frame_count);
+    }
+
+    /// Decode the bus on top of interleaved `output` and clear it for the next block.
+    pub fn decode(&mut self, output: &mut [f32], channels: usize) {
+        let channels = channels.max(1);
+        let frame_count = (output.len() / channels).min(self.frames);
+
+        match self.decoder {
+            AmbisonicsDecoder::Stereo => self.decode_stereo(output, channels, frame_count),
+            AmbisonicsDecoder::Quad => self.decode_quad(output, channels, frame_count),
+            AmbisonicsDecoder::Binaural => self.decode_binaural(output, channels, frame_count),
+        }
+
+        self.bformat[..self.frames * BFORMAT_CHANNELS].fill(0.0);
+        self.frames = 0;
+    }
+
+    fn decode_stereo(&self, output: &mut [f32], channels: usize, frame_count: usize) {
+        for (frame, bus) in output
+            .chunks_mut(channels)
+            .zip(self.bformat.chunks(BFORMAT_CHANNELS))
+            .take(frame_count)
+        {
+            let left = 0.5 * (bus[0] + bus[1]);
+            let right = 0.5 * (bus[0] - bus[1]);
+
+            write_pair(frame, left, right);
+        }
+    }
+
+    fn decode_quad(&self, output: &mut [f32], channels: usize, frame_count: usize) {
+        if channels < QUAD_SPEAKERS.len() {
+            return self.decode_stereo(output, channels, frame_count);
+        }
+
+        for (frame, bus) in output
+            .chunks_mut(channels)
+            .zip(self.bformat.chunks(BFORMAT_CHANNELS))
+            .take(frame_count)
+        {
+            for (sample, (forward, left)) in frame.iter_mut().zip(QUAD_SPEAKERS.iter()) {
+                *sample += 0.25 * (bus[0] + 2.0 * (bus[3] * forward + bus[1] * left));
+            }
+        }
+    }
+
+    fn decode_binaural(&mut self, output: &mut [f32], channels: usize, frame_count: usize) {
+        let Some(hrtf) = self.hrtf.as_ref() else {
+            return self.decode_stereo(output, channels, frame_count);
+        };
+
+        let frame_count = frame_count.min(self.speaker.len() / 2);
+        let speaker_count = BINAURAL_SPEAKERS.len() as f32;
+
+        for ((x, y, z), renderer) in BINAURAL_SPEAKERS.iter().zip(self.renderers.iter_mut()) {
+            // Same axes swap as in encode
+            let (forward, left, up) = (-z, -x, *y);
+
+            // The speaker feed is duplicated on both channels so the renderer can write stereo
+            for (pair, bus) in self.speaker[..frame_count * 2]
+                .chunks_mut(2)
+                .zip(self.bformat.chunks(BFORMAT_CHANNELS))
+            {
+                let sample = (bus[0] + 3.0 * (bus[3] * forward + bus[1] * left + bus[2] * up))
+                    / speaker_count;
+
+                pair.fill(sample);
+            }
+
+            renderer.process(
+                hrtf,
+                Vector3::new(*x, *y, *z),
+                1.0,
+                &self.speaker[..frame_count * 2],
+                &mut self.rendered[..frame_count * 2],
+                2,
+            );
+
+            for (frame, rendered) in output
+                .chunks_mut(channels)
+                .zip(self.rendered.chunks(2))
+                .take(frame_count)
+            {
+                write_pair(frame, rendered[0], rendered[1]);
+            }
+        }
+    }
+}
+
+#[inline(always)]
+fn write_pair(frame: &mut [f32], left: f32, right: f32) {
+    match frame.len() {
+        1 => frame[0] += (left + right) * 0.5,
+        _ => {
+            frame[0] += left;
+            frame[1] += right;
+        }
+    }
+}
@@ -0,0 +1,813 @@
+use std::{
+    ffi::{CStr, CString, c_char, c_void},
+    marker::PhantomData,
+    ptr,
+    sync::{Arc, Mutex},
+};
+
+use clap_sys::{
+    audio_buffer::clap_audio_buffer,
+    entry::clap_plugin_entry,
+    events::{
+        CLAP_CORE_EVENT_SPACE_ID, CLAP_EVENT_PARAM_VALUE, clap_event_header,
+        clap_event_param_value, clap_input_events, clap_output_events,
+    },
+    ext::{
+        audio_ports::{CLAP_EXT_AUDIO_PORTS, clap_audio_port_info, clap_plugin_audio_ports},
+        params::{CLAP_EXT_PARAMS, clap_param_info, clap_plugin_params},
+        state::{CLAP_EXT_STATE, clap_plugin_state},
+    },
+    factory::plugin_factory::{CLAP_PLUGIN_FACTORY_ID, clap_plugin_factory},
+    host::clap_host,
+    plugin::{clap_plugin, clap_plugin_descriptor},
+    process::{CLAP_PROCESS_ERROR, clap_process},
+    stream::{clap_istream, clap_ostream},
+    version::CLAP_VERSION,
+};
+use thiserror::Error;
+
+use super::effect::AudioEffect;
+
+// Frames handed to the plugin per process call, longer buffers are split
+const MAX_FRAMES: usize = 4096;
+
+// Parameter changes delivered with a single process call, the rest wait for the next one
+const MAX_EVENTS: usize = 256;
+
+// Every loaded plugin, so an effect dropped on the audio thread never destroys its plugin
+// there, see ClapPlugin::release_unused
+static INSTANCES: Mutex<Vec<Arc<Instance>>> = Mutex::new(Vec::new());
+
+#[derive(Debug, Error)]
+pub enum ClapError {
+    #[error("Failed to load CLAP library: {0}")]
+    LibraryNotFound(String),
+    #[error("Not a CLAP library: {0}")]
+    InvalidLibrary(String),
+    #[error("Plugin not found in the library: {0}")]
+    PluginNotFound(String),
+    #[error("Plugin failed to initialize")]
+    InitFailed,
+    #[error("Plugin failed to activate")]
+    ActivateFailed,
+    #[error("Plugin has no audio port with {0} channels")]
+    UnsupportedChannels(usize),
+    #[error("Parameter not found: {0}")]
+    ParamNotFound(u32),
+    #[error("Plugin does not support {0}")]
+    UnsupportedExtension(&'static str),
+    #[error("Plugin failed to save or load its state")]
+    StateFailed,
+    #[error("Lock failed")]
+    LockFailed,
+    #[error("{0}")]
+    Other(Box<dyn std::error::Error + Send + 'static>),
+}
+
+impl ClapError {
+    pub fn from_other<E: std::error::Error + Send + 'static>(error: E) -> Self {
+        ClapError::Other(Box::new(error))
+    }
+}
+
+/// A plugin found in a CLAP library, see [ClapPlugin::list].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ClapDescriptor {
+    pub id: String,
+    pub name: String,
+    pub vendor: String,
+    pub version: String,
+}
+
+/// A parameter of a CLAP plugin, see [ClapHandle::list_params].
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ClapParam {
+    pub id: u32,
+    pub name: String,
+    /// Group of the parameter in the plugin, such as `"Filter/Envelope"`.
+    pub module: String,
+    pub min: f64,
+    pub max: f64,
+    pub default: f64,
+}
+
+// The host the plugins are created for, the crate answers no host extension
+static HOST_NAME: &CStr = c"est-audio";
+static HOST_VENDOR: &CStr = c"Estrol";
+static HOST_URL: &CStr = c"https://github.com/Estrol/estaudio-rs";
+static HOST_VERSION: &CStr = c"0.1.0";
+
+unsafe extern "C" fn host_get_extension(
+    _host: *const clap_host,
+    _extension_id: *const c_char,
+) -> *const c_void {
+    ptr::null()
+}
+
+unsafe extern "C" fn host_request(_host: *const clap_host) {}
+
+fn new_host() -> Box<clap_host> {
+    Box::new(clap_host {
+        clap_version: CLAP_VERSION,
+        host_data: ptr::null_mut(),
+        name: HOST_NAME.as_ptr(),
+        vendor: HOST_VENDOR.as_ptr(),
+        url: HOST_URL.as_ptr(),
+        version: HOST_VERSION.as_ptr(),
+        get_extension: Some(host_get_extension),
+        request_restart: Some(host_request),
+        request_process: Some(host_request),
+        request_callback: Some(host_request),
+    })
+}
+
+/// An open CLAP library, initialized until dropped.
+struct Library {
+    entry: *const clap_plugin_entry,
+    factory: *const clap_plugin_factory,
+    // Dropped last, the entry and factory point into it
+    _library: libloading::Library,
+}
+
+impl Library {
+    fn open(path: &str) -> Result<Self, ClapError> {
+        let library = unsafe { libloading::Library::new(path) }
+            .map_err(|_| ClapError::LibraryNotFound(path.to_string()))?;
+
+        let entry = unsafe {
+            library
+                .get::<*const clap_plugin_entry>(b"clap_entry\0")
+                .map(|symbol| *symbol)
+                .map_err(|_| ClapError::InvalidLibrary(path.to_string()))?
+        };
+
+        let path_c = CString::new(path).map_err(ClapError::from_other)?;
+
+        let factory = unsafe {
+            let Some(init) = (*entry).init else {
+                return Err(ClapError::InvalidLibrary(path.to_string()));
+            };
+
+            if !init(path_c.as_ptr()) {
+                return Err(ClapError::InitFailed);
+            }
+
+            (*entry)
+                .get_factory
+                .map_or(ptr::null(), |get_factory| {
+                    get_factory(CLAP_PLUGIN_FACTORY_ID.as_ptr())
+                }) as *const clap_plugin_factory
+        };
+
+        let library = Self {
+            entry,
+            factory,
+            _library: library,
+        };
+
+        if factory.is_null() {
+            return Err(ClapError::InvalidLibrary(path.to_string()));
+        }
+
+        Ok(library)
+    }
+
+    fn descriptors(&self) -> Vec<*const clap_plugin_descriptor> {
+        unsafe {
+            let factory = &*self.factory;
+            let (Some(count), Some(get)) = (factory.get_plugin_count, factory.get_plugin_descriptor)
+            else {
+                return Vec::new();
+            };
+
+            (0..count(self.factory))
+                .map(|index| get(self.factory, index))
+                .filter(|descriptor| !descriptor.is_null())
+                .collect()
+        }
+    }
+}
+
+impl Drop for Library {
+    fn drop(&mut self) {
+        unsafe {
+            if let Some(deinit) = (*self.entry).deinit {
+                deinit();
+            }
+        }
+    }
+}
+
+fn c_string(value: *const c_char) -> String {
+    if value.is_null() {
+        return String::new();
+    }
+
+    unsafe { CStr::from_ptr(value) }.to_string_lossy().into_owned()
+}
+
+fn descriptor_info(descriptor: *const clap_plugin_descriptor) -> ClapDescriptor {
+    let descriptor = unsafe { &*descriptor };
+
+    ClapDescriptor {
+        id: c_string(descriptor.id),
+        name: c_string(descriptor.name),
+        vendor: c_string(descriptor.vendor),
+        version: c_string(descriptor.version),
+    }
+}
+
+/// The plugin instance, shared by the effect and its handles.
+struct Instance {
+    plugin: *const clap_plugin,
+    params: *const clap_plugin_params,
+    state: *const clap_plugin_state,
+    active: bool,
+    // Parameter changes waiting for the next process call
+    pending: Mutex<Vec<(u32, f64)>>,
+    // Read by the plugin through the pointer given to it
+    _host: Box<clap_host>,
+    // Dropped after the plugin is destroyed
+    _library: Library,
+}
+
+// The plugin is only called from the threads CLAP allows for each function, the pointers are
+// owned by the instance and valid until it is dropped
+unsafe impl Send for Instance {}
+unsafe impl Sync for Instance {}
+
+impl Instance {
+    fn extension<T>(plugin: *const clap_plugin, id: &CStr) -> *const T {
+        unsafe {
+            (*plugin)
+                .get_extension
+                .map_or(ptr::null(), |get| get(plugin, id.as_ptr())) as *const T
+        }
+    }
+
+    fn supports_channels(&self, channels: usize) -> bool {
+        let ports = Self::extension::<clap_plugin_audio_ports>(self.plugin, CLAP_EXT_AUDIO_PORTS);
+
+        // Without the extension the plugin takes any layout
+        if ports.is_null() {
+            return true;
+        }
+
+        unsafe {
+            let ports = &*ports;
+            let (Some(count), Some(get)) = (ports.count, ports.get) else {
+                return true;
+            };
+
+            [true, false].into_iter().all(|is_input| {
+                if count(self.plugin, is_input) == 0 {
+                    return false;
+                }
+
+                let mut info: clap_audio_port_info = std::mem::zeroed();
+                get(self.plugin, 0, is_input, &mut info) && info.channel_count as usize == channels
+            })
+        }
+    }
+}
+
+impl Drop for Instance {
+    fn drop(&mut self) {
+        unsafe {
+            let plugin = &*self.plugin;
+            match plugin.deactivate {
+                Some(deactivate) if self.active => deactivate(self.plugin),
+                _ => {}
+            }
+
+            if let Some(destroy) = plugin.destroy {
+                destroy(self.plugin);
+            }
+        }
+    }
+}
+
+/// A CLAP effect plugin inserted into an effect chain, such as with
+/// [Track::set_effects](crate::Track::set_effects),
+/// [Mixer::set_effects](crate::Mixer::set_effects) or
+/// [Device::set_master_effects](crate::Device::set_master_effects).
+///
+/// The plugin is activated for one sample rate and channel count when loaded, buffers with
+/// another channel count pass through untouched. Keep a [ClapHandle] to change parameters and
+/// save the state while the effect is in a chain.
+pub struct ClapPlugin {
+    instance: Arc<Instance>,
+    channels: usize,
+    processing: bool,
+    needs_reset: bool,
+    steady_time: u64,
+    inputs: Vec<Vec<f32>>,
+    outputs: Vec<Vec<f32>>,
+    input_ptrs: Vec<*mut f32>,
+    output_ptrs: Vec<*mut f32>,
+    events: Vec<clap_event_param_value>,
+}
+
+// The buffer pointers only point into the buffers owned by the effect
+unsafe impl Send for ClapPlugin {}
+
+impl std::fmt::Debug for ClapPlugin {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ClapPlugin")
+            .field("descriptor", &self.descriptor())
+            .field("channels", &self.channels)
+            .field("processing", &self.processing)
+            .finish()
+    }
+}
+
+impl ClapPlugin {
+    /// The plugins in the CLAP library at `path`.
+    pub fn list(path: &str) -> Result<Vec<ClapDescriptor>, ClapError> {
+        let library = Library::open(path)?;
+
+        Ok(library
+            .descriptors()
+            .into_iter()
+            .map(descriptor_info)
+            .collect())
+    }
+
+    /// Load the plugin `id` from the CLAP library at `path`, the first plugin of the library
+    /// when `id` is None, and activate it for `sample_rate` and `channels`.
+    ///
+    /// Called on the main thread, as CLAP requires of creating and activating plugins.
+    pub fn load(
+        path: &str,
+        id: Option<&str>,
+        sample_rate: f32,
+        channels: usize,
+    ) -> Result<Self, ClapError> {
+        Self::release_unused();
+
+        let library = Library::open(path)?;
+
+        let descriptor = library
+            .descriptors()
+            .into_iter()
+            .find(|descriptor| id.is_none_or(|id| descriptor_info(*descriptor).id == id))
+            .ok_or_else(|| ClapError::PluginNotFound(id.unwrap_or(path).to_string()))?;
+
+        let host = new_host();
+        let plugin = unsafe {
+            let Some(create) = (*library.factory).create_plugin else {
+                return Err(ClapError::InvalidLibrary(path.to_string()));
+            };
+
+            create(library.factory, &*host, (*descriptor).id)
+        };
+
+        if plugin.is_null() {
+            return Err(ClapError::PluginNotFound(descriptor_info(descriptor).id));
+        }
+
+        let initialized = unsafe { (*plugin).init.is_some_and(|init| init(plugin)) };
+        if !initialized {
+            unsafe {
+                if let Some(destroy) = (*plugin).destroy {
+                    destroy(plugin);
+                }
+            }
+
+            return Err(ClapError::InitFailed);
+        }
+
+        let mut instance = Instance {
+            plugin,
+            params: Instance::extension(plugin, CLAP_EXT_PARAMS),
+            state: Instance::extension(plugin, CLAP_EXT_STATE),
+            active: false,
+            pending: Mutex::new(Vec::with_capacity(MAX_EVENTS)),
+            _host: host,
+            _library: library,
+        };
+
+        if channels == 0 || !instance.supports_channels(channels) {
+            return Err(ClapError::UnsupportedChannels(channels));
+        }
+
+        instance.active = unsafe {
+            (*plugin).activate.is_some_and(|activate| {
+                activate(plugin, sample_rate as f64, 1, MAX_FRAMES as u32)
+            })
+        };
+
+        if !instance.active {
+            return Err(ClapError::ActivateFailed);
+        }
+
+        let inputs = vec![vec![0.0; MAX_FRAMES]; channels];
+        let outputs = vec![vec![0.0; MAX_FRAMES]; channels];
+
+        let instance = Arc::new(instance);
+        let Ok(mut instances) = INSTANCES.lock() else {
+            return Err(ClapError::LockFailed);
+        };
+
+        instances.push(instance.clone());
+        drop(instances);
+
+        Ok(Self {
+            instance,
+            channels,
+            processing: false,
+            needs_reset: false,
+            steady_time: 0,
+            inputs,
+            outputs,
+            input_ptrs: vec![ptr::null_mut(); channels],
+            output_ptrs: vec![ptr::null_mut(); channels],
+            events: Vec::with_capacity(MAX_EVENTS),
+        })
+    }
+
+    /// Destroy the plugins no effect or handle uses anymore, called on the main thread.
+    ///
+    /// Effects are often dropped on the audio thread, where CLAP forbids destroying a plugin,
+    /// so a dropped plugin stays loaded until this runs. Every [ClapPlugin::load] calls it.
+    pub fn release_unused() {
+        if let Ok(mut instances) = INSTANCES.lock() {
+            instances.retain(|instance| Arc::strong_count(instance) > 1);
+        }
+    }
+
+    pub fn descriptor(&self) -> ClapDescriptor {
+        descriptor_info(unsafe { (*self.instance.plugin).desc })
+    }
+
+    /// A handle to the parameters and state of the plugin, usable once the effect is moved
+    /// into a chain.
+    pub fn handle(&self) -> ClapHandle {
+        ClapHandle {
+            instance: self.instance.clone(),
+            _main_thread: PhantomData,
+        }
+    }
+
+    fn take_pending(&mut self) {
+        self.events.clear();
+
+        let Ok(mut pending) = self.instance.pending.try_lock() else {
+            return;
+        };
+
+        let count = pending.len().min(MAX_EVENTS);
+        for (param_id, value) in pending.drain(..count) {
+            self.events.push(clap_event_param_value {
+                header: clap_event_header {
+                    size: std::mem::size_of::<clap_event_param_value>() as u32,
+                    time: 0,
+                    space_id: CLAP_CORE_EVENT_SPACE_ID,
+                    type_: CLAP_EVENT_PARAM_VALUE,
+                    flags: 0,
+                },
+                param_id,
+                cookie: ptr::null_mut(),
+                note_id: -1,
+                port_index: -1,
+                channel: -1,
+                key: -1,
+                value,
+            });
+        }
+    }
+
+    fn process_block(&mut self, block: &mut [f32]) {
+        let channels = self.channels;
+        let frames = block.len() / channels;
+
+        for (index, frame) in block.chunks_exact(channels).enumerate() {
+            for (channel, sample) in frame.iter().enumerate() {
+                self.inputs[channel][index] = *sample;
+            }
+        }
+
+        for channel in 0..channels {
+            self.input_ptrs[channel] = self.inputs[channel].as_mut_ptr();
+            self.output_ptrs[channel] = self.outputs[channel].as_mut_ptr();
+        }
+
+        let input = clap_audio_buffer {
+            data32: self.input_ptrs.as_mut_ptr(),
+            data64: ptr::null_mut(),
+            channel_count: channels as u32,
+            latency: 0,
+            constant_mask: 0,
+        };
+
+        let mut output = clap_audio_buffer {
+            data32: self.output_ptrs.as_mut_ptr(),
+            data64: ptr::null_mut(),
+            channel_count: channels as u32,
+            latency: 0,
+            constant_mask: 0,
+        };
+
+        let in_events = clap_input_events {
+            ctx: &self.events as *const Vec<clap_event_param_value> as *mut c_void,
+            size: Some(input_events_size),
+            get: Some(input_events_get),
+        };
+
+        let out_events = clap_output_events {
+            ctx: ptr::null_mut(),
+            try_push: Some(output_events_push),
+        };
+
+        let process = clap_process {
+            steady_time: self.steady_time as i64,
+            frames_count: frames as u32,
+            transport: ptr::null(),
+            audio_inputs: &input,
+            audio_outputs: &mut output,
+            audio_inputs_count: 1,
+            audio_outputs_count: 1,
+            in_events: &in_events,
+            out_events: &out_events,
+        };
+
+        let plugin = self.instance.plugin;
+        let status = unsafe {
+            (*plugin)
+                .process
+                .map_or(CLAP_PROCESS_ERROR, |process_fn| process_fn(plugin, &process))
+        };
+
+        self.steady_time += frames as u64;
+        self.events.clear();
+
+        // A failed block is left dry
+        if status == CLAP_PROCESS_ERROR {
+            return;
+        }
+
+        for (index, frame) in block.chunks_exact_mut(channels).enumerate() {
+            for (channel, sample) in frame.iter_mut().enumerate() {
+                *sample = self.outputs[channel][index];
+            }
+        }
+    }
+}
+
+unsafe extern "C" fn input_events_size(list: *const clap_input_events) -> u32 {
+    unsafe {
+        let events = &*((*list).ctx as *const Vec<clap_event_param_value>);
+        events.len() as u32
+    }
+}
+
+unsafe extern "C" fn input_events_get(
+    list: *const clap_input_events,
+    index: u32,
+) -> *const clap_event_header {
+    unsafe {
+        let events = &*((*list).ctx as *const Vec<clap_event_param_value>);
+        events
+            .get(index as usize)
+            .map_or(ptr::null(), |event| &event.header as *const clap_event_header)
+    }
+}
+
+// Events sent back by the plugin, such as parameter gestures, are not used
+unsafe extern "C" fn output_events_push(
+    _list: *const clap_output_events,
+    _event: *const clap_event_header,
+) -> bool {
+    true
+}
+
+impl AudioEffect for ClapPlugin {
+    fn process(&mut self, buffer: &mut [f32], channels: usize) {
+        if channels != self.channels {
+            return;
+        }
+
+        let plugin = self.instance.plugin;
+
+        if !self.processing {
+            let started = unsafe {
+                (*plugin)
+                    .start_processing
+                    .is_some_and(|start| start(plugin))
+            };
+
+            if !started {
+                return;
+            }
+
+            self.processing = true;
+        }
+
+        // CLAP resets from the audio thread, so the request is carried over from the chain
+        if self.needs_reset {
+            unsafe {
+                if let Some(reset) = (*plugin).reset {
+                    reset(plugin);
+                }
+            }
+
+            self.needs_reset = false;
+        }
+
+        self.take_pending();
+
+        for block in buffer.chunks_mut(MAX_FRAMES * channels) {
+            self.process_block(block);
+        }
+    }
+
+    fn reset(&mut self) {
+        self.needs_reset = true;
+    }
+}
+
+// Only stops processing, which CLAP allows on the audio thread. The plugin itself is destroyed
+// by ClapPlugin::release_unused
+impl Drop for ClapPlugin {
+    fn drop(&mut self) {
+        if !self.processing {
+            return;
+        }
+
+        let plugin = self.instance.plugin;
+        unsafe {
+            if let Some(stop) = (*plugin).stop_processing {
+                stop(plugin);
+            }
+        }
+    }
+}
+
+/// Controls the parameters and state of a [ClapPlugin] from the main thread, see
+/// [ClapPlugin::handle]. The plugin stays loaded while a handle is kept.
+///
+/// CLAP only allows these calls on the main thread, so the handle is neither Send nor Sync
+/// and stays on the thread that loaded the plugin.
+#[derive(Clone)]
+pub struct ClapHandle {
+    instance: Arc<Instance>,
+    // Keeps the handle on the main thread
+    _main_thread: PhantomData<*const ()>,
+}
+
+impl std::fmt::Debug for ClapHandle {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ClapHandle")
+            .field("descriptor", &descriptor_info(unsafe { (*self.instance.plugin).desc }))
+            .finish()
+    }
+}
+
+impl ClapHandle {
+    fn params(&self) -> Result<&clap_plugin_params, ClapError> {
+        if self.instance.params.is_null() {
+            return Err(ClapError::UnsupportedExtension("parameters"));
+        }
+
+        Ok(unsafe { &*self.instance.params })
+    }
+
+    /// The parameters the plugin exposes.
+    pub fn list_params(&self) -> Result<Vec<ClapParam>, ClapError> {
+        let params = self.params()?;
+        let plugin = self.instance.plugin;
+
+        let (Some(count), Some(get_info)) = (params.count, params.get_info) else {
+            return Ok(Vec::new());
+        };
+
+        let mut list = Vec::new();
+        for index in 0..unsafe { count(plugin) } {
+            let mut info: clap_param_info = unsafe { std::mem::zeroed() };
+            if !unsafe { get_info(plugin, index, &mut info) } {
+                continue;
+            }
+
+            list.push(ClapParam {
+                id: info.id,
+                name: c_string(info.name.as_ptr()),
+                module: c_string(info.module.as_ptr()),
+                min: info.min_value,
+                max: info.max_value,
+                default: info.default_value,
+            });
+        }
+
+        Ok(list)
+    }
+
+    pub fn get_param(&self, id: u32) -> Result<f64, ClapError> {
+        let params = self.params()?;
+        let Some(get_value) = params.get_value else {
+            return Err(ClapError::UnsupportedExtension("parameters"));
+        };
+
+        let mut value = 0.0;
+        if !unsafe { get_value(self.instance.plugin, id, &mut value) } {
+            return Err(ClapError::ParamNotFound(id));
+        }
+
+        Ok(value)
+    }
+
+    /// Set the parameter `id`, delivered to the plugin with the next block it processes.
+    pub fn set_param(&self, id: u32, value: f64) -> Result<(), ClapError> {
+        if !self.list_params()?.iter().any(|param| param.id == id) {
+            return Err(ClapError::ParamNotFound(id));
+        }
+
+        let Ok(mut pending) = self.instance.pending.lock() else {
+            return Err(ClapError::LockFailed);
+        };
+
+        pending.retain(|(param_id, _)| *param_id != id);
+        pending.push((id, value));
+        Ok(())
+    }
+
+    /// The state of the plugin as an opaque blob, restored with [ClapHandle::load_state].
+    pub fn save_state(&self) -> Result<Vec<u8>, ClapError> {
+        if self.instance.state.is_null() {
+            return Err(ClapError::UnsupportedExtension("state"));
+        }
+
+        let Some(save) = (unsafe { (*self.instance.state).save }) else {
+            return Err(ClapError::UnsupportedExtension("state"));
+        };
+
+        let mut data = Vec::<u8>::new();
+        let stream = clap_ostream {
+            ctx: &mut data as *mut Vec<u8> as *mut c_void,
+            write: Some(ostream_write),
+        };
+
+        if !unsafe { save(self.instance.plugin, &stream) } {
+            return Err(ClapError::StateFailed);
+        }
+
+        Ok(data)
+    }
+
+    pub fn load_state(&self, data: &[u8]) -> Result<(), ClapError> {
+        if self.instance.state.is_null() {
+            return Err(ClapError::UnsupportedExtension("state"));
+        }
+
+        let Some(load) = (unsafe { (*self.instance.state).load }) else {
+            return Err(ClapError::UnsupportedExtension("state"));
+        };
+
+        let mut reader = StateReader { data, position: 0 };
+        let stream = clap_istream {
+            ctx: &mut reader as *mut StateReader as *mut c_void,
+            read: Some(istream_read),
+        };
+
+        if !unsafe { load(self.instance.plugin, &stream) } {
+            return Err(ClapError::StateFailed);
+        }
+
+        Ok(())
+    }
+}
+
+struct StateReader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+unsafe extern "C" fn ostream_write(
+    stream: *const clap_ostream,
+    buffer: *const c_void,
+    size: u64,
+) -> i64 {
+    unsafe {
+        let data = &mut *((*stream).ctx as *mut Vec<u8>);
+        data.extend_from_slice(std::slice::from_raw_parts(buffer as *const u8, size as usize));
+    }
+
+    size as i64
+}
+
+unsafe extern "C" fn istream_read(stream: *const clap_istream, buffer: *mut c_void, size: u64) -> i64 {
+    unsafe {
+        let reader = &mut *((*stream).ctx as *mut StateReader);
+        let count = (reader.data.len() - reader.position).min(size as usize);
+
+        ptr::copy_nonoverlapping(
+            reader.data[reader.position..].as_ptr(),
+            buffer as *mut u8,
+            count,
+        );
+        reader.position += count;
+
+        count as i64
+    }
+}
@@ -0,0 +1,188 @@
+use super::effect::AudioEffect;
+
+/// Parameters of a [Compressor], so a compressor can be described in data files.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct CompressorSettings {
+    pub threshold_db: f32,
+    pub ratio: f32,
+    pub knee_db: f32,
+    pub makeup_db: f32,
+    pub attack_ms: f32,
+    pub release_ms: f32,
+}
+
+impl Default for CompressorSettings {
+    fn default() -> Self {
+        Self {
+            threshold_db: -18.0,
+            ratio: 4.0,
+            knee_db: 6.0,
+            makeup_db: 0.0,
+            attack_ms: 10.0,
+            release_ms: 100.0,
+        }
+    }
+}
+
+/// Feed forward compressor, the gain is computed from the loudest channel so the stereo image
+/// does not shift.
+#[derive(Debug, Clone)]
+pub struct Compressor {
+    sample_rate: f32,
+    threshold_db: f32,
+    ratio: f32,
+    knee_db: f32,
+    makeup_db: f32,
+    attack_ms: f32,
+    release_ms: f32,
+    attack_coef: f32,
+    release_coef: f32,
+    // Gain reduction in dB, 0 or below
+    reduction: f32,
+}
+
+impl Compressor {
+    pub fn new(sample_rate: f32) -> Self {
+        Self::with_settings(sample_rate, &CompressorSettings::default())
+    }
+
+    pub fn with_settings(sample_rate: f32, settings: &CompressorSettings) -> Self {
+        let mut compressor = Self {
+            sample_rate,
+            threshold_db: 0.0,
+            ratio: 1.0,
+            knee_db: 0.0,
+            makeup_db: 0.0,
+            attack_ms: 0.0,
+            release_ms: 0.0,
+            attack_coef: 0.0,
+            release_coef: 0.0,
+            reduction: 0.0,
+        };
+
+        compressor.set_settings(settings);
+        compressor
+    }
+
+    /// Apply every parameter of `settings`, clamped like the setters.
+    pub fn set_settings(&mut self, settings: &CompressorSettings) {
+        self.set_threshold(settings.threshold_db);
+        self.set_ratio(settings.ratio);
+        self.set_knee(settings.knee_db);
+        self.set_makeup(settings.makeup_db);
+        self.set_attack(settings.attack_ms);
+        self.set_release(settings.release_ms);
+    }
+
+    pub fn get_settings(&self) -> CompressorSettings {
+        CompressorSettings {
+            threshold_db: self.threshold_db,
+            ratio: self.ratio,
+            knee_db: self.knee_db,
+            makeup_db: self.makeup_db,
+            attack_ms: self.attack_ms,
+            release_ms: self.release_ms,
+        }
+    }
+
+    /// Level in dBFS above which the signal is compressed, -18 by default.
+    pub fn set_threshold(&mut self, threshold_db: f32) {
+        self.threshold_db = threshold_db.min(0.0);
+    }
+
+    pub fn get_threshold(&self) -> f32 {
+        self.threshold_db
+    }
+
+    /// Input dB above the threshold per output dB, 4 by default.
+    pub fn set_ratio(&mut self, ratio: f32) {
+        self.ratio = ratio.max(1.0);
+    }
+
+    pub fn get_ratio(&self) -> f32 {
+        self.ratio
+    }
+
+    /// Width in dB of the soft knee around the threshold, 6 by default.
+    pub fn set_knee(&mut self, knee_db: f32) {
+        self.knee_db = knee_db.max(0.0);
+    }
+
+    pub fn get_knee(&self) -> f32 {
+        self.knee_db
+    }
+
+    /// Gain in dB added after compression.
+    pub fn set_makeup(&mut self, makeup_db: f32) {
+        self.makeup_db = makeup_db;
+    }
+
+    pub fn get_makeup(&self) -> f32 {
+        self.makeup_db
+    }
+
+    pub fn set_attack(&mut self, attack_ms: f32) {
+        self.attack_ms = attack_ms;
+        self.attack_coef = Self::coefficient(self.sample_rate, attack_ms);
+    }
+
+    pub fn set_release(&mut self, release_ms: f32) {
+        self.release_ms = release_ms;
+        self.release_coef = Self::coefficient(self.sample_rate, release_ms);
+    }
+
+    /// Current gain reduction in dB, 0 or below.
+    pub fn get_reduction(&self) -> f32 {
+        self.reduction
+    }
+
+    fn coefficient(sample_rate: f32, ms: f32) -> f32 {
+        let frames = (ms / 1000.0 * sample_rate).max(1.0);
+        1.0 - (-1.0 / frames).exp()
+    }
+
+    /// Static curve, the reduction in dB for an input level in dB.
+    fn curve(&self, level_db: f32) -> f32 {
+        let over = level_db - self.threshold_db;
+        let slope = 1.0 / self.ratio - 1.0;
+        let half_knee = self.knee_db / 2.0;
+
+        if over <= -half_knee {
+            0.0
+        } else if over < half_knee {
+            let x = over + half_knee;
+            slope * x * x / (2.0 * self.knee_db)
+        } else {
+            slope * over
+        }
+    }
+}
+
+impl AudioEffect for Compressor {
+    fn process(&mut self, buffer: &mut [f32], channels: usize) {
+        let channels = channels.max(1);
+
+        for frame in buffer.chunks_exact_mut(channels) {
+            let peak = frame.iter().fold(0.0f32, |peak, sample| peak.max(sample.abs()));
+            let level_db = 20.0 * peak.max(1e-6).log10();
+            let target = self.curve(level_db);
+
+            let coef = if target < self.reduction {
+                self.attack_coef
+            } else {
+                self.release_coef
+            };
+
+            self.reduction += (target - self.reduction) * coef;
+
+            let gain = 10f32.powf((self.reduction + self.makeup_db) / 20.0);
+            frame.iter_mut().for_each(|sample| *sample *= gain);
+        }
+    }
+
+    fn reset(&mut self) {
+        self.reduction = 0.0;
+    }
+}
@@ -0,0 +1,12 @@
+/// An effect that processes interleaved audio in place, used for the master chain of a
+/// [Device](crate::Device), see [Device::set_master_effects](crate::Device::set_master_effects),
+/// and the chains of tracks and mixers.
+///
+/// Runs on the audio thread, so `process` should not allocate or block.
+pub trait AudioEffect: Send {
+    /// Process `buffer` holding interleaved frames with `channels` channels.
+    fn process(&mut self, buffer: &mut [f32], channels: usize);
+
+    /// Clear the internal state, called when the effect is inserted into a chain.
+    fn reset(&mut self) {}
+}
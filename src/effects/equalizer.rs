@@ -0,0 +1,207 @@
+use std::f32::consts::PI;
+
+use super::effect::AudioEffect;
+
+/// Most channels an [Equalizer] keeps filter state for, the rest pass through unchanged.
+const MAX_CHANNELS: usize = 8;
+
+/// Shape of an [EqBand].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[repr(C)]
+pub enum EqBandKind {
+    /// Boost or cut around the band frequency.
+    #[default]
+    Peaking = 0,
+    /// Boost or cut below the band frequency.
+    LowShelf = 1,
+    /// Boost or cut above the band frequency.
+    HighShelf = 2,
+    /// Remove the content below the band frequency, the gain is ignored.
+    HighPass = 3,
+    /// Remove the content above the band frequency, the gain is ignored.
+    LowPass = 4,
+}
+
+/// A band of an [Equalizer].
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct EqBand {
+    pub kind: EqBandKind,
+    /// Center or corner frequency in Hz.
+    pub frequency: f32,
+    pub gain_db: f32,
+    /// Bandwidth of a peaking band, resonance of the pass filters, slope of the shelves.
+    pub q: f32,
+}
+
+impl EqBand {
+    pub fn new(kind: EqBandKind, frequency: f32, gain_db: f32, q: f32) -> Self {
+        Self {
+            kind,
+            frequency,
+            gain_db,
+            q,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub(super) struct Coefficients {
+    b0: f32,
+    b1: f32,
+    b2: f32,
+    a1: f32,
+    a2: f32,
+}
+
+impl Coefficients {
+    // RBJ audio EQ cookbook
+    pub fn new(band: &EqBand, sample_rate: f32) -> Self {
+        let frequency = band.frequency.clamp(10.0, sample_rate * 0.49);
+        let q = band.q.max(0.01);
+        let a = 10f32.powf(band.gain_db / 40.0);
+        let w0 = 2.0 * PI * frequency / sample_rate;
+        let (sin, cos) = w0.sin_cos();
+        let alpha = sin / (2.0 * q);
+
+        let (b0, b1, b2, a0, a1, a2) = match band.kind {
+            EqBandKind::Peaking => (
+                1.0 + alpha * a,
+                -2.0 * cos,
+                1.0 - alpha * a,
+                1.0 + alpha / a,
+                -2.0 * cos,
+                1.0 - alpha / a,
+            ),
+            EqBandKind::LowShelf => {
+                let sqrt = 2.0 * a.sqrt() * alpha;
+                (
+                    a * ((a + 1.0) - (a - 1.0) * cos + sqrt),
+                    2.0 * a * ((a - 1.0) - (a + 1.0) * cos),
+                    a * ((a + 1.0) - (a - 1.0) * cos - sqrt),
+                    (a + 1.0) + (a - 1.0) * cos + sqrt,
+                    -2.0 * ((a - 1.0) + (a + 1.0) * cos),
+                    (a + 1.0) + (a - 1.0) * cos - sqrt,
+                )
+            }
+            EqBandKind::HighShelf => {
+                let sqrt = 2.0 * a.sqrt() * alpha;
+                (
+                    a * ((a + 1.0) + (a - 1.0) * cos + sqrt),
+                    -2.0 * a * ((a - 1.0) + (a + 1.0) * cos),
+                    a * ((a + 1.0) + (a - 1.0) * cos - sqrt),
+                    (a + 1.0) - (a - 1.0) * cos + sqrt,
+                    2.0 * ((a - 1.0) - (a + 1.0) * cos),
+                    (a + 1.0) - (a - 1.0) * cos - sqrt,
+                )
+            }
+            EqBandKind::HighPass => (
+                (1.0 + cos) / 2.0,
+                -(1.0 + cos),
+                (1.0 + cos) / 2.0,
+                1.0 + alpha,
+                -2.0 * cos,
+                1.0 - alpha,
+            ),
+            EqBandKind::LowPass => (
+                (1.0 - cos) / 2.0,
+                1.0 - cos,
+                (1.0 - cos) / 2.0,
+                1.0 + alpha,
+                -2.0 * cos,
+                1.0 - alpha,
+            ),
+        };
+
+        Self {
+            b0: b0 / a0,
+            b1: b1 / a0,
+            b2: b2 / a0,
+            a1: a1 / a0,
+            a2: a2 / a0,
+        }
+    }
+
+    /// Filter one sample, `state` is the transposed direct form II state of the channel.
+    #[inline(always)]
+    pub fn process(&self, state: &mut [f32; 2], input: f32) -> f32 {
+        let output = self.b0 * input + state[0];
+
+        state[0] = self.b1 * input - self.a1 * output + state[1];
+        state[1] = self.b2 * input - self.a2 * output;
+        output
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Filter {
+    band: EqBand,
+    coefficients: Coefficients,
+    // Transposed direct form II state per channel
+    state: [[f32; 2]; MAX_CHANNELS],
+}
+
+/// Parametric equalizer made of biquad bands run in series.
+#[derive(Debug, Clone)]
+pub struct Equalizer {
+    sample_rate: f32,
+    filters: Vec<Filter>,
+}
+
+impl Equalizer {
+    pub fn new(sample_rate: f32) -> Self {
+        Self {
+            sample_rate,
+            filters: Vec::new(),
+        }
+    }
+
+    /// Replace the bands, the filter state of bands at the same index is kept so the change
+    /// does not click.
+    pub fn set_bands(&mut self, bands: &[EqBand]) {
+        self.filters.truncate(bands.len());
+
+        for (index, band) in bands.iter().enumerate() {
+            let coefficients = Coefficients::new(band, self.sample_rate);
+
+            match self.filters.get_mut(index) {
+                Some(filter) => {
+                    filter.band = *band;
+                    filter.coefficients = coefficients;
+                }
+                None => self.filters.push(Filter {
+                    band: *band,
+                    coefficients,
+                    state: [[0.0; 2]; MAX_CHANNELS],
+                }),
+            }
+        }
+    }
+
+    pub fn get_bands(&self) -> Vec<EqBand> {
+        self.filters.iter().map(|filter| filter.band).collect()
+    }
+}
+
+impl AudioEffect for Equalizer {
+    fn process(&mut self, buffer: &mut [f32], channels: usize) {
+        let channels = channels.max(1);
+
+        for filter in self.filters.iter_mut() {
+            let c = filter.coefficients;
+
+            for frame in buffer.chunks_exact_mut(channels) {
+                for (sample, state) in frame.iter_mut().zip(filter.state.iter_mut()) {
+                    *sample = c.process(state, *sample);
+                }
+            }
+        }
+    }
+
+    fn reset(&mut self) {
+        for filter in self.filters.iter_mut() {
+            filter.state = [[0.0; 2]; MAX_CHANNELS];
+        }
+    }
+}
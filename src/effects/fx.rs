@@ -1,6 +1,42 @@
 use astretch::Stretch;
 use thiserror::Error;
 
+/// How a seek primes the time stretcher, which needs its input latency worth of audio before
+/// it can produce output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[repr(C)]
+pub enum FXSeekMode {
+    /// Prime with the audio right after the seek position, playback starts the latency
+    /// later than requested.
+    #[default]
+    Fast = 0,
+    /// Prime with the audio right before the seek position so playback starts exactly there,
+    /// padded with silence near the start of the audio.
+    Exact = 1,
+}
+
+impl FXSeekMode {
+    /// Source frame to start priming from and how many frames of silence come before it.
+    pub fn preroll(&self, position: usize, latency: usize) -> (usize, usize) {
+        match self {
+            FXSeekMode::Fast => (position, 0),
+            FXSeekMode::Exact => {
+                let start = position.saturating_sub(latency);
+                (start, latency - (position - start))
+            }
+        }
+    }
+
+    /// Source position playback resumes from after priming.
+    pub fn compensated_position(&self, position: usize, latency: usize) -> usize {
+        match self {
+            FXSeekMode::Fast => position + latency,
+            FXSeekMode::Exact => position,
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct AudioFX {
     pub stretch: Stretch<f32>,
@@ -89,6 +125,12 @@ impl AudioFX {
         self.stretch.output_latency() as usize
     }
 
+    /// Output latency in source frames, how far the audio coming out trails the input already
+    /// fed at the current tempo. The input latency is covered by priming on seek.
+    pub fn get_source_latency(&self) -> usize {
+        (self.get_output_latency() as f32 * self.tempo).round() as usize
+    }
+
     pub fn get_seek_length(&self) -> usize {
         self.stretch.output_seek_length(self.tempo) as usize
     }
@@ -125,9 +167,7 @@ impl AudioFX {
     }
 
     pub fn set_octave(&mut self, octave: f32) -> Result<(), AudioFXError> {
-        if octave < 0.5 {
-            return Err(AudioFXError::InvalidOctave);
-        }
+        Self::validate_octave(octave)?;
 
         let tonacity_limit = 4000.0 / self.sample_rate as f32;
 
@@ -140,6 +180,23 @@ impl AudioFX {
     }
 
     pub fn set_tempo(&mut self, tempo: f32) -> Result<(), AudioFXError> {
+        Self::validate_tempo(tempo)?;
+
+        self.tempo = tempo;
+        Ok(())
+    }
+
+    /// Fails for an octave [AudioFX::set_octave] rejects.
+    pub fn validate_octave(octave: f32) -> Result<(), AudioFXError> {
+        if octave < 0.5 {
+            return Err(AudioFXError::InvalidOctave);
+        }
+
+        Ok(())
+    }
+
+    /// Fails for a tempo [AudioFX::set_tempo] rejects.
+    pub fn validate_tempo(tempo: f32) -> Result<(), AudioFXError> {
         if tempo < 0.5 {
             return Err(AudioFXError::InvalidTempo);
         }
@@ -148,7 +205,6 @@ impl AudioFX {
             return Err(AudioFXError::InvalidTempo);
         }
 
-        self.tempo = tempo;
         Ok(())
     }
 
@@ -0,0 +1,294 @@
+use thiserror::Error;
+
+use crate::math::Vector3;
+
+#[derive(Debug, Error)]
+pub enum HrtfError {
+    #[error("HRTF set has no measurements")]
+    Empty,
+    #[error("HRIR length mismatch, expected {0} taps but got {1}")]
+    LengthMismatch(usize, usize),
+    #[error("Invalid sample rate: {0}")]
+    InvalidSampleRate(f32),
+}
+
+/// A single head related impulse response pair.
+///
+/// Angles are in degrees, azimuth 0 is straight ahead and positive azimuth goes to the left,
+/// positive elevation goes up. This matches the spherical coordinates used by SOFA files, so
+/// measurements read from one can be passed in as-is.
+#[derive(Debug, Clone)]
+pub struct HrirMeasurement {
+    pub azimuth: f32,
+    pub elevation: f32,
+    pub left: Vec<f32>,
+    pub right: Vec<f32>,
+}
+
+#[derive(Debug)]
+struct Hrir {
+    direction: Vector3<f32>,
+    left: Box<[f32]>,
+    right: Box<[f32]>,
+}
+
+/// A set of HRIRs used by the binaural spatialization mode, see
+/// [Device::set_hrtf](crate::Device::set_hrtf).
+#[derive(Debug)]
+pub struct HrtfSet {
+    sample_rate: f32,
+    length: usize,
+    hrirs: Box<[Hrir]>,
+}
+
+// Spherical head model constants, see Brown & Duda, "A structural model for binaural sound synthesis"
+const HEAD_RADIUS: f32 = 0.0875;
+const SPEED_OF_SOUND: f32 = 343.0;
+const BUILTIN_LENGTH: usize = 128;
+
+impl HrtfSet {
+    /// Built-in set generated from a spherical head model, 15 degree azimuth and 20 degree
+    /// elevation steps.
+    pub fn builtin(sample_rate: f32) -> Result<Self, HrtfError> {
+        if !(8000.0..=192000.0).contains(&sample_rate) {
+            return Err(HrtfError::InvalidSampleRate(sample_rate));
+        }
+
+        let mut measurements = Vec::new();
+        for elevation in (-40..=80).step_by(20) {
+            for azimuth in (0..360).step_by(15) {
+                let direction = direction_from_angles(azimuth as f32, elevation as f32);
+
+                measurements.push(HrirMeasurement {
+                    azimuth: azimuth as f32,
+                    elevation: elevation as f32,
+                    left: spherical_head_hrir(direction, 1.0, sample_rate),
+                    right: spherical_head_hrir(direction, -1.0, sample_rate),
+                });
+            }
+        }
+
+        Self::from_measurements(sample_rate, measurements)
+    }
+
+    /// Build a set from user supplied measurements, every HRIR must have the same length and
+    /// be recorded at `sample_rate`.
+    pub fn from_measurements(
+        sample_rate: f32,
+        measurements: Vec<HrirMeasurement>,
+    ) -> Result<Self, HrtfError> {
+        if !(8000.0..=192000.0).contains(&sample_rate) {
+            return Err(HrtfError::InvalidSampleRate(sample_rate));
+        }
+
+        let Some(first) = measurements.first() else {
+            return Err(HrtfError::Empty);
+        };
+
+        let length = first.left.len();
+        if length == 0 {
+            return Err(HrtfError::Empty);
+        }
+
+        let mut hrirs = Vec::with_capacity(measurements.len());
+        for measurement in measurements {
+            for taps in [&measurement.left, &measurement.right] {
+                if taps.len() != length {
+                    return Err(HrtfError::LengthMismatch(length, taps.len()));
+                }
+            }
+
+            hrirs.push(Hrir {
+                direction: direction_from_angles(measurement.azimuth, measurement.elevation),
+                left: measurement.left.into_boxed_slice(),
+                right: measurement.right.into_boxed_slice(),
+            });
+        }
+
+        Ok(Self {
+            sample_rate,
+            length,
+            hrirs: hrirs.into_boxed_slice(),
+        })
+    }
+
+    pub fn sample_rate(&self) -> f32 {
+        self.sample_rate
+    }
+
+    pub fn length(&self) -> usize {
+        self.length
+    }
+
+    /// Index of the HRIR closest to `direction`, given in listener space.
+    fn nearest(&self, direction: Vector3<f32>) -> usize {
+        let mut best = 0;
+        let mut best_dot = f32::MIN;
+
+        for (index, hrir) in self.hrirs.iter().enumerate() {
+            let dot = hrir.direction.x * direction.x
+                + hrir.direction.y * direction.y
+                + hrir.direction.z * direction.z;
+
+            if dot > best_dot {
+                best_dot = dot;
+                best = index;
+            }
+        }
+
+        best
+    }
+}
+
+/// Listener space direction (+X right, +Y up, -Z forward) of a SOFA style azimuth/elevation.
+fn direction_from_angles(azimuth: f32, elevation: f32) -> Vector3<f32> {
+    let (azimuth, elevation) = (azimuth.to_radians(), elevation.to_radians());
+
+    Vector3::new(
+        -azimuth.sin() * elevation.cos(),
+        elevation.sin(),
+        -azimuth.cos() * elevation.cos(),
+    )
+}
+
+/// HRIR of one ear of a rigid sphere, `side` is 1.0 for the left ear and -1.0 for the right.
+fn spherical_head_hrir(direction: Vector3<f32>, side: f32, sample_rate: f32) -> Vec<f32> {
+    // Angle between the source and the ear axis
+    let cos_theta = (-side * direction.x).clamp(-1.0, 1.0);
+    let theta = cos_theta.acos();
+
+    // Woodworth ITD, offset so the nearest ear is never negative
+    let delay = if theta < std::f32::consts::FRAC_PI_2 {
+        HEAD_RADIUS / SPEED_OF_SOUND * (1.0 - cos_theta)
+    } else {
+        HEAD_RADIUS / SPEED_OF_SOUND * (1.0 + theta - std::f32::consts::FRAC_PI_2)
+    };
+
+    let delay = delay * sample_rate;
+    let mut taps = vec![0.0f32; BUILTIN_LENGTH];
+    let index = delay.floor() as usize;
+    let fraction = delay - delay.floor();
+
+    if index + 1 < BUILTIN_LENGTH {
+        taps[index] = 1.0 - fraction;
+        taps[index + 1] = fraction;
+    }
+
+    // Head shadow, one-pole one-zero filter bilinear transformed
+    let alpha_min = 0.1;
+    let theta_min = 150f32.to_radians();
+    let alpha = (1.0 + alpha_min / 2.0)
+        + (1.0 - alpha_min / 2.0) * (theta / theta_min * std::f32::consts::PI).cos();
+
+    let k = sample_rate * HEAD_RADIUS / SPEED_OF_SOUND;
+    let b0 = (1.0 + alpha * k) / (1.0 + k);
+    let b1 = (1.0 - alpha * k) / (1.0 + k);
+    let a1 = (1.0 - k) / (1.0 + k);
+
+    let (mut x1, mut y1) = (0.0f32, 0.0f32);
+    for tap in taps.iter_mut() {
+        let x0 = *tap;
+        let y0 = b0 * x0 + b1 * x1 - a1 * y1;
+
+        x1 = x0;
+        y1 = y0;
+        *tap = y0;
+    }
+
+    taps
+}
+
+/// Per source binaural renderer, convolves a mono downmix with the HRIR closest to the source
+/// direction and crossfades over one block when that HRIR changes.
+#[derive(Debug, Default)]
+pub(crate) struct HrtfRenderer {
+    // The last `length - 1` mono input samples followed by the current block
+    history: Vec<f32>,
+    current: Option<usize>,
+    gain: f32,
+}
+
+impl HrtfRenderer {
+    /// Render interleaved `input` into `output`, both with `channels` channels. The left and
+    /// right ears go to the first two output channels, a mono output gets their average.
+    pub fn process(
+        &mut self,
+        set: &HrtfSet,
+        direction: Vector3<f32>,
+        gain: f32,
+        input: &[f32],
+        output: &mut [f32],
+        channels: usize,
+    ) {
+        let input_channels = channels.max(1);
+        let output_channels = channels.max(1);
+        let frame_count = (input.len() / input_channels).min(output.len() / output_channels);
+
+        let length = set.length;
+        if self.history.len() != length - 1 {
+            self.history.clear();
+            self.history.resize(length - 1, 0.0);
+        }
+
+        for frame in input.chunks(input_channels).take(frame_count) {
+            self.history.push(frame.iter().sum::<f32>() / input_channels as f32);
+        }
+
+        let next = set.nearest(direction);
+        let previous = self.current.unwrap_or(next);
+        let previous_gain = if self.current.is_some() { self.gain } else { gain };
+
+        let new = &set.hrirs[next];
+        let old = &set.hrirs[previous];
+
+        for (index, frame) in output
+            .chunks_mut(output_channels)
+            .take(frame_count)
+            .enumerate()
+        {
+            let window = &self.history[index..index + length];
+            let (mut left, mut right) = convolve(window, &new.left, &new.right);
+
+            let mix = (index + 1) as f32 / frame_count as f32;
+            let frame_gain = previous_gain + (gain - previous_gain) * mix;
+
+            if previous != next {
+                let (old_left, old_right) = convolve(window, &old.left, &old.right);
+                left = old_left + (left - old_left) * mix;
+                right = old_right + (right - old_right) * mix;
+            }
+
+            match output_channels {
+                1 => frame[0] = (left + right) * 0.5 * frame_gain,
+                _ => {
+                    frame[0] = left * frame_gain;
+                    frame[1] = right * frame_gain;
+                    frame[2..].fill(0.0);
+                }
+            }
+        }
+
+        self.history.drain(..frame_count);
+        self.current = Some(next);
+        self.gain = gain;
+    }
+}
+
+#[inline(always)]
+fn convolve(window: &[f32], left: &[f32], right: &[f32]) -> (f32, f32) {
+    // `window` holds the oldest sample first, the HRIR holds the newest tap first
+    let mut sum_left = 0.0;
+    let mut sum_right = 0.0;
+
+    for ((sample, tap_left), tap_right) in window
+        .iter()
+        .rev()
+        .zip(left.iter())
+        .zip(right.iter())
+    {
+        sum_left += sample * tap_left;
+        sum_right += sample * tap_right;
+    }
+
+    (sum_left, sum_right)
+}
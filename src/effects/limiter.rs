@@ -0,0 +1,70 @@
+use super::effect::AudioEffect;
+
+/// Simple peak limiter with instant attack and exponential release.
+#[derive(Debug, Clone)]
+pub struct Limiter {
+    pub channels: usize,
+    pub threshold: f32,
+    release_coef: f32,
+    gain: f32,
+}
+
+impl Limiter {
+    pub fn new(channels: usize, sample_rate: f32) -> Self {
+        let mut limiter = Self {
+            channels: channels.max(1),
+            threshold: 1.0,
+            release_coef: 0.0,
+            gain: 1.0,
+        };
+
+        limiter.set_release(sample_rate, 50.0);
+        limiter
+    }
+
+    pub fn set_threshold(&mut self, threshold: f32) {
+        self.threshold = threshold.clamp(0.0, 1.0);
+    }
+
+    pub fn set_release(&mut self, sample_rate: f32, release_ms: f32) {
+        let release_frames = (release_ms / 1000.0 * sample_rate).max(1.0);
+        self.release_coef = 1.0 - (-1.0 / release_frames).exp();
+    }
+
+    pub fn reset(&mut self) {
+        self.gain = 1.0;
+    }
+
+    pub fn process(&mut self, buffer: &mut [f32]) {
+        for frame in buffer.chunks_mut(self.channels) {
+            let peak = frame.iter().fold(0.0f32, |peak, sample| peak.max(sample.abs()));
+
+            let target = if peak * self.gain > self.threshold {
+                self.threshold / peak
+            } else {
+                1.0
+            };
+
+            if target < self.gain {
+                self.gain = target;
+            } else {
+                self.gain += (target - self.gain) * self.release_coef;
+            }
+
+            for sample in frame.iter_mut() {
+                *sample *= self.gain;
+            }
+        }
+    }
+}
+
+impl AudioEffect for Limiter {
+    fn process(&mut self, buffer: &mut [f32], channels: usize) {
+        self.channels = channels.max(1);
+        Limiter::process(self, buffer);
+    }
+
+    fn reset(&mut self) {
+        Limiter::reset(self);
+    }
+}
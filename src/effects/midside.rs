@@ -0,0 +1,98 @@
+/// Convert interleaved stereo `input` into separate mid and side buffers.
+///
+/// `mid` and `side` must hold at least one sample per frame of `input`.
+pub fn encode_mid_side(input: &[f32], mid: &mut [f32], side: &mut [f32]) {
+    for ((frame, mid), side) in input.chunks_exact(2).zip(mid.iter_mut()).zip(side.iter_mut()) {
+        *mid = (frame[0] + frame[1]) * 0.5;
+        *side = (frame[0] - frame[1]) * 0.5;
+    }
+}
+
+/// Convert mid and side buffers back into interleaved stereo `output`, the inverse of
+/// [encode_mid_side].
+pub fn decode_mid_side(mid: &[f32], side: &[f32], output: &mut [f32]) {
+    for ((frame, mid), side) in output.chunks_exact_mut(2).zip(mid.iter()).zip(side.iter()) {
+        frame[0] = mid + side;
+        frame[1] = mid - side;
+    }
+}
+
+/// Runs processing on the mid and side components of a stereo buffer separately, e.g. an EQ on
+/// the mid only or widening by scaling the side.
+///
+/// Buffers with other channel counts are left untouched.
+#[derive(Debug, Clone)]
+pub struct MidSide {
+    pub mid_gain: f32,
+    pub side_gain: f32,
+    mid: Vec<f32>,
+    side: Vec<f32>,
+}
+
+impl Default for MidSide {
+    fn default() -> Self {
+        Self::new(0)
+    }
+}
+
+impl MidSide {
+    /// `frame_count` is only used to preallocate, larger buffers grow the internal storage.
+    pub fn new(frame_count: usize) -> Self {
+        Self {
+            mid_gain: 1.0,
+            side_gain: 1.0,
+            mid: vec![0.0; frame_count],
+            side: vec![0.0; frame_count],
+        }
+    }
+
+    /// Stereo width, 0.0 collapses to mono, 1.0 leaves the image as is and up to 2.0 widens it.
+    pub fn set_width(&mut self, width: f32) {
+        self.side_gain = width.clamp(0.0, 2.0);
+    }
+
+    pub fn get_width(&self) -> f32 {
+        self.side_gain
+    }
+
+    /// Apply only the mid and side gains.
+    pub fn process(&mut self, buffer: &mut [f32], channels: usize) {
+        self.process_with(buffer, channels, |_| {}, |_| {});
+    }
+
+    /// Encode `buffer` into mid and side, run `mid` and `side` over each component and decode
+    /// the result back into `buffer`.
+    pub fn process_with<M, S>(&mut self, buffer: &mut [f32], channels: usize, mut mid: M, mut side: S)
+    where
+        M: FnMut(&mut [f32]),
+        S: FnMut(&mut [f32]),
+    {
+        if channels != 2 {
+            return;
+        }
+
+        let frame_count = buffer.len() / 2;
+        if self.mid.len() < frame_count {
+            self.mid.resize(frame_count, 0.0);
+            self.side.resize(frame_count, 0.0);
+        }
+
+        let mid_buffer = &mut self.mid[..frame_count];
+        let side_buffer = &mut self.side[..frame_count];
+
+        encode_mid_side(buffer, mid_buffer, side_buffer);
+
+        mid(mid_buffer);
+        side(side_buffer);
+
+        if self.mid_gain != 1.0 {
+            mid_buffer.iter_mut().for_each(|sample| *sample *= self.mid_gain);
+        }
+
+        if self.side_gain != 1.0 {
+            side_buffer.iter_mut().for_each(|sample| *sample *= self.side_gain);
+        }
+
+        decode_mid_side(mid_buffer, side_buffer, buffer);
+    }
+}
@@ -1,20 +1,55 @@
+mod ambisonics;
 mod channel_converter;
+#[cfg(feature = "clap")]
+mod clap;
+mod compressor;
+mod effect;
+mod equalizer;
 mod fx;
+mod hrtf;
+mod limiter;
+mod midside;
+mod noisesuppressor;
+mod occlusion;
 mod panner;
 mod resampler;
+mod reverb;
+mod reverbzone;
+mod routing;
 mod spartilization_listener;
 mod spatialization;
+mod vocalremover;
 mod volume;
 
+pub use ambisonics::AmbisonicsDecoder;
+pub(crate) use ambisonics::AmbisonicsBus;
 pub use channel_converter::ChannelConverter;
-pub use fx::{AudioFX, AudioFXError};
-pub use panner::AudioPanner;
+#[cfg(feature = "clap")]
+pub use clap::{ClapDescriptor, ClapError, ClapHandle, ClapParam, ClapPlugin};
+pub use compressor::{Compressor, CompressorSettings};
+pub use effect::AudioEffect;
+pub use equalizer::{EqBand, EqBandKind, Equalizer};
+pub use fx::{AudioFX, AudioFXError, FXSeekMode};
+pub use hrtf::{HrirMeasurement, HrtfError, HrtfSet};
+pub(crate) use hrtf::HrtfRenderer;
+pub use limiter::Limiter;
+pub use midside::{MidSide, decode_mid_side, encode_mid_side};
+pub use noisesuppressor::NoiseSuppressor;
+pub(crate) use occlusion::OcclusionFilter;
+pub use panner::{AudioPanner, PanMode};
 pub use resampler::Resampler;
+pub use reverb::{Reverb, ReverbPreset};
+pub use reverbzone::ReverbZone;
+pub(crate) use reverbzone::ReverbBus;
+pub use routing::{RoutingMatrix, RoutingMatrixError};
 pub use spartilization_listener::{
-    SpartialListenerHandler, SpatializationListener, SpatializationListenerError,
+    ListenerSelection, SpartialListenerHandler, SpatializationListener,
+    SpatializationListenerError,
 };
 pub use spatialization::{
     AttenuationModel, Spatialization, SpatializationError, SpatializationHandler,
     Positioning,
 };
+pub(crate) use spatialization::SpatializationSource;
+pub use vocalremover::VocalRemover;
 pub use volume::AudioVolume;
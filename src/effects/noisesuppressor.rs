@@ -0,0 +1,211 @@
+use std::f32::consts::PI;
+
+use crate::math::fft::Fft;
+
+use super::effect::AudioEffect;
+
+/// Frames per analysis window, about 21ms at 48kHz.
+const FRAME_SIZE: usize = 1024;
+const HOP_SIZE: usize = FRAME_SIZE / 2;
+const BINS: usize = FRAME_SIZE / 2 + 1;
+
+// Weight of the previous gain of a bin, smooths the gains over time against musical noise
+const GAIN_SMOOTHING: f32 = 0.6;
+
+#[derive(Debug, Clone)]
+struct ChannelState {
+    input: Vec<f32>,
+    output: Vec<f32>,
+    gains: Vec<f32>,
+}
+
+impl ChannelState {
+    fn new() -> Self {
+        Self {
+            input: vec![0.0; FRAME_SIZE],
+            output: vec![0.0; FRAME_SIZE],
+            gains: vec![1.0; BINS],
+        }
+    }
+}
+
+/// Spectral subtraction noise reduction for steady noise such as hiss, hum or fans.
+///
+/// The noise is learned from a stretch of input holding only noise, see
+/// [NoiseSuppressor::learn], and subtracted from the magnitude spectrum of the input
+/// afterwards. Adds one window of latency, 1024 frames.
+pub struct NoiseSuppressor {
+    channels: usize,
+    window: Vec<f32>,
+    fft: Fft,
+    states: Vec<ChannelState>,
+    index: usize,
+
+    profile: Vec<f32>,
+    learn_frames: usize,
+    learned_windows: usize,
+
+    floor: f32,
+    oversubtraction: f32,
+}
+
+impl NoiseSuppressor {
+    pub fn new(channels: usize) -> Self {
+        let channels = channels.max(1);
+
+        // Square root Hann on analysis and synthesis sums to one at half window overlap
+        let window = (0..FRAME_SIZE)
+            .map(|index| (0.5 - 0.5 * (2.0 * PI * index as f32 / FRAME_SIZE as f32).cos()).sqrt())
+            .collect();
+
+        Self {
+            channels,
+            window,
+            fft: Fft::new(FRAME_SIZE),
+            states: (0..channels).map(|_| ChannelState::new()).collect(),
+            index: 0,
+            profile: vec![0.0; BINS],
+            learn_frames: 0,
+            learned_windows: 0,
+            floor: 10f32.powf(-12.0 / 20.0),
+            oversubtraction: 1.5,
+        }
+    }
+
+    /// Learn the noise from the next `frames` frames of input, replacing the profile. The
+    /// input passes through unchanged meanwhile.
+    pub fn learn(&mut self, frames: usize) {
+        self.learn_frames = frames;
+        self.learned_windows = 0;
+        self.profile.fill(0.0);
+    }
+
+    pub fn is_learning(&self) -> bool {
+        self.learn_frames > 0
+    }
+
+    /// Average magnitude of the noise per frequency bin, 513 values.
+    pub fn get_noise_profile(&self) -> &[f32] {
+        &self.profile
+    }
+
+    /// Restore a profile taken with [NoiseSuppressor::get_noise_profile], ignored when its
+    /// length does not match.
+    pub fn set_noise_profile(&mut self, profile: &[f32]) {
+        if profile.len() == BINS {
+            self.profile.copy_from_slice(profile);
+            self.learn_frames = 0;
+        }
+    }
+
+    /// Most attenuation applied to a bin in dB, 12 by default. Deeper reduction removes more
+    /// noise but makes the remainder warble.
+    pub fn set_reduction(&mut self, reduction_db: f32) {
+        self.floor = 10f32.powf(-reduction_db.max(0.0) / 20.0);
+    }
+
+    pub fn get_reduction(&self) -> f32 {
+        -20.0 * self.floor.log10()
+    }
+
+    /// Multiple of the noise profile subtracted, 1.5 by default.
+    pub fn set_oversubtraction(&mut self, factor: f32) {
+        self.oversubtraction = factor.max(0.0);
+    }
+
+    pub fn get_oversubtraction(&self) -> f32 {
+        self.oversubtraction
+    }
+
+    fn process_window(&mut self, channel: usize) {
+        let learning = self.learn_frames > 0;
+        let has_profile = self.learned_windows > 0 || self.profile.iter().any(|bin| *bin > 0.0);
+        let state = &mut self.states[channel];
+
+        for index in 0..FRAME_SIZE {
+            self.fft.real[index] = state.input[index] * self.window[index];
+            self.fft.imag[index] = 0.0;
+        }
+
+        self.fft.forward();
+
+        for bin in 0..BINS {
+            let (re, im) = (self.fft.real[bin], self.fft.imag[bin]);
+            let magnitude = (re * re + im * im).sqrt();
+
+            let gain = if learning {
+                // Running mean over every window of every channel
+                let count = self.learned_windows as f32;
+                self.profile[bin] = (self.profile[bin] * count + magnitude) / (count + 1.0);
+                1.0
+            } else if has_profile && magnitude > 0.0 {
+                let target = 1.0 - self.oversubtraction * self.profile[bin] / magnitude;
+                let target = target.max(self.floor);
+
+                GAIN_SMOOTHING * state.gains[bin] + (1.0 - GAIN_SMOOTHING) * target
+            } else {
+                1.0
+            };
+
+            state.gains[bin] = gain;
+            self.fft.real[bin] *= gain;
+            self.fft.imag[bin] *= gain;
+
+            // Keep the spectrum conjugate symmetric so the output stays real
+            if bin > 0 && bin < FRAME_SIZE / 2 {
+                self.fft.real[FRAME_SIZE - bin] = self.fft.real[bin];
+                self.fft.imag[FRAME_SIZE - bin] = -self.fft.imag[bin];
+            }
+        }
+
+        if learning {
+            self.learned_windows += 1;
+        }
+
+        self.fft.inverse();
+
+        for index in 0..HOP_SIZE {
+            state.output[index] = state.output[HOP_SIZE + index]
+                + self.fft.real[index] * self.window[index];
+            state.output[HOP_SIZE + index] =
+                self.fft.real[HOP_SIZE + index] * self.window[HOP_SIZE + index];
+        }
+
+        state.input.copy_within(HOP_SIZE.., 0);
+    }
+}
+
+impl AudioEffect for NoiseSuppressor {
+    fn process(&mut self, buffer: &mut [f32], channels: usize) {
+        if channels != self.channels {
+            return;
+        }
+
+        for frame in buffer.chunks_exact_mut(channels) {
+            for (channel, sample) in frame.iter_mut().enumerate() {
+                let state = &mut self.states[channel];
+                state.input[HOP_SIZE + self.index] = *sample;
+                *sample = state.output[self.index];
+            }
+
+            self.index += 1;
+            if self.index == HOP_SIZE {
+                self.index = 0;
+
+                for channel in 0..channels {
+                    self.process_window(channel);
+                }
+            }
+
+            self.learn_frames = self.learn_frames.saturating_sub(1);
+        }
+    }
+
+    fn reset(&mut self) {
+        for state in self.states.iter_mut() {
+            *state = ChannelState::new();
+        }
+
+        self.index = 0;
+    }
+}
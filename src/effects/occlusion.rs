@@ -0,0 +1,79 @@
+// Cutoff range of the muffling low-pass, from fully open to fully blocked
+const MAX_CUTOFF: f32 = 20000.0;
+const MIN_CUTOFF: f32 = 400.0;
+
+// Attenuation at full occlusion and full obstruction
+const OCCLUSION_ATTENUATION: f32 = 0.8;
+const OBSTRUCTION_ATTENUATION: f32 = 0.3;
+
+/// Low-pass and gain reduction for sources blocked from the listener.
+///
+/// Occlusion means the source is fully behind geometry and mostly drops in level, obstruction
+/// means only the direct path is blocked and mostly loses its high end.
+#[derive(Debug, Clone)]
+pub(crate) struct OcclusionFilter {
+    pub occlusion: f32,
+    pub obstruction: f32,
+    state: [f32; 8],
+    gain: f32,
+}
+
+impl Default for OcclusionFilter {
+    fn default() -> Self {
+        Self {
+            occlusion: 0.0,
+            obstruction: 0.0,
+            state: [0.0; 8],
+            gain: 1.0,
+        }
+    }
+}
+
+impl OcclusionFilter {
+    pub fn set_occlusion(&mut self, occlusion: f32) {
+        self.occlusion = occlusion.clamp(0.0, 1.0);
+    }
+
+    pub fn set_obstruction(&mut self, obstruction: f32) {
+        self.obstruction = obstruction.clamp(0.0, 1.0);
+    }
+
+    fn bypass(&self) -> bool {
+        self.occlusion <= 0.0 && self.obstruction <= 0.0 && self.gain >= 1.0
+    }
+
+    pub fn process(&mut self, buffer: &mut [f32], channels: usize, sample_rate: f32) {
+        if self.bypass() {
+            self.state = [0.0; 8];
+            return;
+        }
+
+        let channels = channels.clamp(1, self.state.len());
+        let frame_count = buffer.len() / channels;
+        if frame_count == 0 {
+            return;
+        }
+
+        let muffle = self.occlusion.max(self.obstruction * 0.7);
+        let cutoff = (MAX_CUTOFF * (MIN_CUTOFF / MAX_CUTOFF).powf(muffle)).min(sample_rate * 0.45);
+        let coef = 1.0 - (-2.0 * std::f32::consts::PI * cutoff / sample_rate).exp();
+
+        let gain = (1.0 - self.occlusion * OCCLUSION_ATTENUATION)
+            * (1.0 - self.obstruction * OBSTRUCTION_ATTENUATION);
+
+        // Ramp the gain over the block so parameter updates from raycasts do not click
+        let step = (gain - self.gain) / frame_count as f32;
+        let mut current = self.gain;
+
+        for frame in buffer.chunks_mut(channels) {
+            current += step;
+
+            for (sample, state) in frame.iter_mut().zip(self.state.iter_mut()) {
+                *state += (*sample - *state) * coef;
+                *sample = *state * current;
+            }
+        }
+
+        self.gain = gain;
+    }
+}
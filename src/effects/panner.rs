@@ -26,11 +26,38 @@ impl AudioPannerError {
     }
 }
 
+/// How [AudioAttributes::Pan](crate::AudioAttributes::Pan) is applied to stereo sources.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[repr(C)]
+pub enum PanMode {
+    /// Attenuate the opposite side, the content of each side stays where it is.
+    #[default]
+    Balance = 0,
+    /// Move the content of the opposite side over, nothing is lost when panning hard.
+    Pan = 1,
+}
+
+impl From<i32> for PanMode {
+    fn from(value: i32) -> Self {
+        match value {
+            0 => PanMode::Balance,
+            1 => PanMode::Pan,
+            _ => panic!("Invalid value for PanMode"),
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct AudioPanner {
     pub instance: Box<ma_panner>,
     pub channels: usize,
     pub pan: f32,
+    pub mode: PanMode,
+    // Sum every channel down to mono after panning, see [AudioAttributes::ForceMono](crate::AudioAttributes::ForceMono)
+    pub force_mono: bool,
+    // Swap left and right after panning, see [AudioAttributes::SwapChannels](crate::AudioAttributes::SwapChannels)
+    pub swap_channels: bool,
 }
 
 impl AudioPanner {
@@ -56,6 +83,9 @@ impl AudioPanner {
                 instance: panner,
                 channels,
                 pan: 0.0,
+                mode: PanMode::Balance,
+                force_mono: false,
+                swap_channels: false,
             })
         }
     }
@@ -71,6 +101,29 @@ impl AudioPanner {
         }
     }
 
+    pub fn set_mode(&mut self, mode: PanMode) {
+        // SAFETY: Same as set_pan, the panner is initialized for its whole lifetime.
+        unsafe {
+            self.mode = mode;
+
+            ma_panner_set_mode(self.instance.as_mut(), mode as i32);
+        }
+    }
+
+    /// Set the pan in [PanMode::Balance] mode, as used by [AudioAttributes::Balance](crate::AudioAttributes::Balance).
+    pub fn set_balance(&mut self, balance: f32) {
+        self.set_mode(PanMode::Balance);
+        self.set_pan(balance);
+    }
+
+    /// The pan when in [PanMode::Balance] mode, 0.0 otherwise.
+    pub fn get_balance(&self) -> f32 {
+        match self.mode {
+            PanMode::Balance => self.pan,
+            PanMode::Pan => 0.0,
+        }
+    }
+
     pub fn process(&mut self, input: &[f32], output: &mut [f32]) -> Result<(), AudioPannerError> {
         if input.len() != output.len() {
             return Err(AudioPannerError::BufferSizeMismatch(
@@ -102,6 +155,40 @@ impl AudioPanner {
             }
         }
 
+        if self.swap_channels {
+            swap_stereo(output, self.channels);
+        }
+
+        if self.force_mono {
+            downmix_mono(output, self.channels);
+        }
+
         Ok(())
     }
 }
+
+/// Swap the first two channels of each frame, other channels are left as they are.
+pub(crate) fn swap_stereo(buffer: &mut [f32], channels: usize) {
+    if channels < 2 {
+        return;
+    }
+
+    for frame in buffer.chunks_exact_mut(channels) {
+        frame.swap(0, 1);
+    }
+}
+
+/// Replace every channel of each frame with the average of the frame, averaging instead of
+/// summing keeps correlated content at the same level.
+pub(crate) fn downmix_mono(buffer: &mut [f32], channels: usize) {
+    if channels < 2 {
+        return;
+    }
+
+    let gain = 1.0 / channels as f32;
+
+    for frame in buffer.chunks_exact_mut(channels) {
+        let mono = frame.iter().sum::<f32>() * gain;
+        frame.iter_mut().for_each(|sample| *sample = mono);
+    }
+}
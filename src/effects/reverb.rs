@@ -0,0 +1,196 @@
+use crate::misc::denormal::DENORMAL_DITHER;
+
+/// Parameters of the [Reverb], every field goes from 0.0 to 1.0.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ReverbPreset {
+    pub room_size: f32,
+    pub damping: f32,
+    pub wet: f32,
+    pub width: f32,
+}
+
+impl ReverbPreset {
+    pub const ROOM: ReverbPreset = ReverbPreset {
+        room_size: 0.45,
+        damping: 0.6,
+        wet: 0.25,
+        width: 0.8,
+    };
+
+    pub const HALL: ReverbPreset = ReverbPreset {
+        room_size: 0.8,
+        damping: 0.35,
+        wet: 0.4,
+        width: 1.0,
+    };
+
+    pub const CAVE: ReverbPreset = ReverbPreset {
+        room_size: 0.95,
+        damping: 0.15,
+        wet: 0.55,
+        width: 1.0,
+    };
+
+    /// No reverb at all.
+    pub const DRY: ReverbPreset = ReverbPreset {
+        room_size: 0.0,
+        damping: 0.0,
+        wet: 0.0,
+        width: 0.0,
+    };
+
+    pub(crate) fn lerp(&self, other: &ReverbPreset, amount: f32) -> ReverbPreset {
+        ReverbPreset {
+            room_size: self.room_size + (other.room_size - self.room_size) * amount,
+            damping: self.damping + (other.damping - self.damping) * amount,
+            wet: self.wet + (other.wet - self.wet) * amount,
+            width: self.width + (other.width - self.width) * amount,
+        }
+    }
+}
+
+impl Default for ReverbPreset {
+    fn default() -> Self {
+        ReverbPreset::ROOM
+    }
+}
+
+// Freeverb tunings in samples at 44.1kHz
+const COMB_TUNINGS: [usize; 8] = [1116, 1188, 1277, 1356, 1422, 1491, 1557, 1617];
+const ALLPASS_TUNINGS: [usize; 4] = [556, 441, 341, 225];
+const STEREO_SPREAD: usize = 23;
+const INPUT_GAIN: f32 = 0.015;
+
+#[derive(Debug, Clone)]
+struct Comb {
+    buffer: Box<[f32]>,
+    index: usize,
+    store: f32,
+}
+
+impl Comb {
+    fn new(length: usize) -> Self {
+        Self {
+            buffer: vec![0.0; length.max(1)].into_boxed_slice(),
+            index: 0,
+            store: 0.0,
+        }
+    }
+
+    #[inline(always)]
+    fn process(&mut self, input: f32, feedback: f32, damping: f32) -> f32 {
+        let output = self.buffer[self.index];
+        self.store = output * (1.0 - damping) + self.store * damping;
+        self.buffer[self.index] = input + self.store * feedback;
+
+        self.index = (self.index + 1) % self.buffer.len();
+        output
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Allpass {
+    buffer: Box<[f32]>,
+    index: usize,
+}
+
+impl Allpass {
+    fn new(length: usize) -> Self {
+        Self {
+            buffer: vec![0.0; length.max(1)].into_boxed_slice(),
+            index: 0,
+        }
+    }
+
+    #[inline(always)]
+    fn process(&mut self, input: f32) -> f32 {
+        let buffered = self.buffer[self.index];
+        self.buffer[self.index] = input + buffered * 0.5;
+
+        self.index = (self.index + 1) % self.buffer.len();
+        buffered - input
+    }
+}
+
+/// Stereo Schroeder/Moorer reverb in the Freeverb layout, mono input and wet only output.
+#[derive(Debug, Clone)]
+pub struct Reverb {
+    preset: ReverbPreset,
+    combs: [Vec<Comb>; 2],
+    allpasses: [Vec<Allpass>; 2],
+}
+
+impl Reverb {
+    pub fn new(sample_rate: f32) -> Self {
+        let scale = sample_rate / 44100.0;
+        let scaled = |length: usize| (length as f32 * scale) as usize;
+
+        let combs = [0, STEREO_SPREAD].map(|spread| {
+            COMB_TUNINGS
+                .iter()
+                .map(|length| Comb::new(scaled(length + spread)))
+                .collect()
+        });
+
+        let allpasses = [0, STEREO_SPREAD].map(|spread| {
+            ALLPASS_TUNINGS
+                .iter()
+                .map(|length| Allpass::new(scaled(length + spread)))
+                .collect()
+        });
+
+        Self {
+            preset: ReverbPreset::default(),
+            combs,
+            allpasses,
+        }
+    }
+
+    pub fn set_preset(&mut self, preset: ReverbPreset) {
+        self.preset = preset;
+    }
+
+    pub fn get_preset(&self) -> ReverbPreset {
+        self.preset
+    }
+
+    /// Run the reverb over a mono `input` and add the wet signal on top of interleaved
+    /// `output` with `channels` channels.
+    pub fn process(&mut self, input: &[f32], output: &mut [f32], channels: usize) {
+        let channels = channels.max(1);
+        let preset = self.preset;
+
+        let feedback = preset.room_size.clamp(0.0, 1.0) * 0.28 + 0.7;
+        let damping = preset.damping.clamp(0.0, 1.0) * 0.4;
+        let wet1 = preset.wet * (preset.width / 2.0 + 0.5);
+        let wet2 = preset.wet * ((1.0 - preset.width) / 2.0);
+
+        for (sample, frame) in input.iter().zip(output.chunks_mut(channels)) {
+            // Keeps the feedback paths out of the subnormal range while the tail decays
+            let input = *sample * INPUT_GAIN + DENORMAL_DITHER;
+            let mut wet = [0.0f32; 2];
+
+            for (side, wet) in wet.iter_mut().enumerate() {
+                for comb in self.combs[side].iter_mut() {
+                    *wet += comb.process(input, feedback, damping);
+                }
+
+                for allpass in self.allpasses[side].iter_mut() {
+                    *wet = allpass.process(*wet);
+                }
+            }
+
+            let left = wet[0] * wet1 + wet[1] * wet2;
+            let right = wet[1] * wet1 + wet[0] * wet2;
+
+            match frame.len() {
+                1 => frame[0] += (left + right) * 0.5,
+                _ => {
+                    frame[0] += left;
+                    frame[1] += right;
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,112 @@
+use crate::math::Vector3;
+
+use super::reverb::{Reverb, ReverbPreset};
+
+/// A spherical area with its own reverb preset, see
+/// [Device::set_reverb_zones](crate::Device::set_reverb_zones).
+///
+/// The preset applies fully within `radius` of `center` and fades out over the next `fade`
+/// units, overlapping zones are blended by weight.
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ReverbZone {
+    pub center: Vector3<f32>,
+    pub radius: f32,
+    pub fade: f32,
+    pub preset: ReverbPreset,
+}
+
+impl ReverbZone {
+    fn weight(&self, position: Vector3<f32>) -> f32 {
+        let (dx, dy, dz) = (
+            position.x - self.center.x,
+            position.y - self.center.y,
+            position.z - self.center.z,
+        );
+
+        let distance = (dx * dx + dy * dy + dz * dz).sqrt();
+        if distance <= self.radius {
+            return 1.0;
+        }
+
+        if self.fade <= 0.0 {
+            return 0.0;
+        }
+
+        (1.0 - (distance - self.radius) / self.fade).clamp(0.0, 1.0)
+    }
+}
+
+// Fraction of the way to the zone preset covered per block, avoids jumps when teleporting
+const PRESET_SMOOTHING: f32 = 0.1;
+
+/// Shared reverb bus owned by the device listener.
+///
+/// Spatialized sources add a send to it scaled by their distance, the device runs the reverb
+/// once per block with the preset picked from the listener position.
+pub(crate) struct ReverbBus {
+    pub zones: Vec<ReverbZone>,
+    reverb: Reverb,
+    preset: ReverbPreset,
+    send: Vec<f32>,
+    frames: usize,
+}
+
+impl ReverbBus {
+    pub fn new(zones: Vec<ReverbZone>, block_size: usize, sample_rate: f32) -> Self {
+        Self {
+            zones,
+            reverb: Reverb::new(sample_rate),
+            preset: ReverbPreset::DRY,
+            send: vec![0.0; block_size],
+            frames: 0,
+        }
+    }
+
+    /// Add interleaved `input` to the bus, downmixed to mono and scaled by `level`.
+    pub fn send(&mut self, input: &[f32], channels: usize, level: f32) {
+        if level <= 0.0 {
+            return;
+        }
+
+        let channels = channels.max(1);
+        let frame_count = (input.len() / channels).min(self.send.len());
+
+        for (frame, send) in input.chunks(channels).zip(self.send.iter_mut()).take(frame_count) {
+            *send += frame.iter().sum::<f32>() / channels as f32 * level;
+        }
+
+        self.frames = self.frames.max(frame_count);
+    }
+
+    /// Blend the zone presets for `listener_position`, run the reverb on top of `output` and
+    /// clear the bus.
+    pub fn process(&mut self, listener_position: Vector3<f32>, output: &mut [f32], channels: usize) {
+        let mut total = 0.0;
+        let mut target = ReverbPreset::DRY;
+
+        for zone in self.zones.iter() {
+            let weight = zone.weight(listener_position);
+            if weight <= 0.0 {
+                continue;
+            }
+
+            total += weight;
+            target = target.lerp(&zone.preset, weight / total);
+        }
+
+        // Fading out of every zone also fades out the reverb
+        if total < 1.0 {
+            target.wet *= total;
+        }
+
+        self.preset = self.preset.lerp(&target, PRESET_SMOOTHING);
+        self.reverb.set_preset(self.preset);
+
+        let frame_count = (output.len() / channels.max(1)).min(self.send.len());
+        self.reverb.process(&self.send[..frame_count], output, channels);
+
+        self.send[..self.frames].fill(0.0);
+        self.frames = 0;
+    }
+}
@@ -0,0 +1,106 @@
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum RoutingMatrixError {
+    #[error("Invalid number of channels: {0}")]
+    InvalidChannels(usize),
+    #[error("Channel index out of range: {0}")]
+    IndexOutOfRange(usize),
+}
+
+/// Gains from each input channel of a source to each output speaker, used instead of the
+/// default channel conversion when a source is mixed into the device.
+///
+/// E.g. a mono bed can be sent only to the LFE of a 5.1 device with
+/// `RoutingMatrix::new(1, 6)` and `set_gain(0, 3, 1.0)`.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RoutingMatrix {
+    inputs: usize,
+    outputs: usize,
+    // Row major, gains[output * inputs + input]
+    gains: Vec<f32>,
+}
+
+impl RoutingMatrix {
+    /// Matrix with every gain at 0.0, nothing is routed until set.
+    pub fn new(inputs: usize, outputs: usize) -> Result<Self, RoutingMatrixError> {
+        if inputs < 1 || inputs > 8 {
+            return Err(RoutingMatrixError::InvalidChannels(inputs));
+        }
+
+        if outputs < 1 || outputs > 8 {
+            return Err(RoutingMatrixError::InvalidChannels(outputs));
+        }
+
+        Ok(Self {
+            inputs,
+            outputs,
+            gains: vec![0.0; inputs * outputs],
+        })
+    }
+
+    /// Input channel N goes to output speaker N, extra outputs stay silent.
+    pub fn identity(inputs: usize, outputs: usize) -> Result<Self, RoutingMatrixError> {
+        let mut matrix = Self::new(inputs, outputs)?;
+
+        for channel in 0..inputs.min(outputs) {
+            matrix.gains[channel * inputs + channel] = 1.0;
+        }
+
+        Ok(matrix)
+    }
+
+    pub fn inputs(&self) -> usize {
+        self.inputs
+    }
+
+    pub fn outputs(&self) -> usize {
+        self.outputs
+    }
+
+    pub fn set_gain(
+        &mut self,
+        input: usize,
+        output: usize,
+        gain: f32,
+    ) -> Result<(), RoutingMatrixError> {
+        let index = self.index(input, output)?;
+        self.gains[index] = gain.max(0.0);
+
+        Ok(())
+    }
+
+    pub fn get_gain(&self, input: usize, output: usize) -> Result<f32, RoutingMatrixError> {
+        let index = self.index(input, output)?;
+        Ok(self.gains[index])
+    }
+
+    fn index(&self, input: usize, output: usize) -> Result<usize, RoutingMatrixError> {
+        if input >= self.inputs {
+            return Err(RoutingMatrixError::IndexOutOfRange(input));
+        }
+
+        if output >= self.outputs {
+            return Err(RoutingMatrixError::IndexOutOfRange(output));
+        }
+
+        Ok(output * self.inputs + input)
+    }
+
+    /// Route interleaved `input` with [RoutingMatrix::inputs] channels into interleaved `output`
+    /// with [RoutingMatrix::outputs] channels.
+    pub(crate) fn process(&self, input: &[f32], output: &mut [f32]) {
+        let frame_count = (input.len() / self.inputs).min(output.len() / self.outputs);
+
+        let input = input.chunks_exact(self.inputs).take(frame_count);
+        let output = output.chunks_exact_mut(self.outputs);
+
+        for (input, output) in input.zip(output) {
+            for (speaker, sample) in output.iter_mut().enumerate() {
+                let gains = &self.gains[speaker * self.inputs..(speaker + 1) * self.inputs];
+                *sample = input.iter().zip(gains).map(|(input, gain)| input * gain).sum();
+            }
+        }
+    }
+}
@@ -3,8 +3,12 @@
 use miniaudio_sys::*;
 use thiserror::Error;
 
+use std::sync::Arc;
+
 use crate::{math::Vector3, utils};
 
+use super::{ambisonics::AmbisonicsBus, hrtf::HrtfSet, reverbzone::ReverbBus};
+
 #[derive(Debug, Error)]
 pub enum SpatializationListenerError {
     #[error("Initialization failed with error code: {} {}", .0, self.ma_error_to_str())]
@@ -13,6 +17,8 @@ pub enum SpatializationListenerError {
     InvalidChannels(u32), // Holds the invalid channel count
     #[error("Spatialization listener not initialized")]
     NotInitialized, // Indicates that the spatialization listener has not been initialized
+    #[error("Invalid listener index: {0}")]
+    InvalidIndex(usize), // Holds the index that has no listener behind it
 }
 
 impl SpatializationListenerError {
@@ -24,8 +30,31 @@ impl SpatializationListenerError {
     }
 }
 
+/// Which device listener a spatialized source is rendered against.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ListenerSelection {
+    /// The listener at this index, 0 being the primary one. Falls back to the primary listener
+    /// while the index is not in use.
+    Index(usize),
+    /// Whichever listener is nearest to the source position, picked every block.
+    Closest,
+}
+
+impl Default for ListenerSelection {
+    fn default() -> Self {
+        ListenerSelection::Index(0)
+    }
+}
+
 pub struct SpatializationListener {
     pub handle: Box<ma_spatializer_listener>,
+    // Sources render binaurally through this set instead of panning when present
+    pub hrtf: Option<Arc<HrtfSet>>,
+    // Sources encode into this bus instead of panning when present, decoded by the device
+    pub(crate) ambisonics: Option<AmbisonicsBus>,
+    // Shared reverb sources send to by distance, processed by the device
+    pub(crate) reverb: Option<ReverbBus>,
 }
 
 impl SpatializationListener {
@@ -56,6 +85,9 @@ impl SpatializationListener {
 
             Ok(SpatializationListener {
                 handle: spatializer,
+                hrtf: None,
+                ambisonics: None,
+                reverb: None,
             })
         }
     }
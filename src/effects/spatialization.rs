@@ -1,11 +1,17 @@
 #![allow(dead_code)]
 
+use std::time::Instant;
+
 use miniaudio_sys::*;
 use thiserror::Error;
 
 use crate::{device::Device, math::Vector3, utils};
 
-use super::spartilization_listener::SpatializationListener;
+use super::{
+    hrtf::{HrtfRenderer, HrtfSet},
+    occlusion::OcclusionFilter,
+    spartilization_listener::SpatializationListener,
+};
 
 #[derive(Debug, Error)]
 pub enum SpatializationError {
@@ -19,6 +25,8 @@ pub enum SpatializationError {
     OperationError(i32), // Holds a custom error message for general operation errors
     #[error("Instance not initialized")]
     NotInitialized,
+    #[error("Not implemented by this audio source")]
+    NotImplemented,
     #[error("{0}")]
     Other(Box<dyn std::error::Error + Send + 'static>),
 }
@@ -40,12 +48,21 @@ impl SpatializationError {
     }
 }
 
+// Reverb send of a source at or inside its min distance
+const MIN_REVERB_SEND: f32 = 0.2;
+
 #[derive(Debug)]
 pub struct Spatialization {
     pub handle: Box<ma_spatializer>,
+    pub auto_velocity: bool,
+    // Position of the previous move_to call, used by auto_velocity
+    last_position: Option<Vector3<f32>>,
+    hrtf: HrtfRenderer,
+    occlusion: OcclusionFilter,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[repr(C)]
 pub enum AttenuationModel {
     None = 0,
@@ -67,6 +84,7 @@ impl From<i32> for AttenuationModel {
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[repr(C)]
 pub enum Positioning {
     Absolute = 0,
@@ -113,6 +131,10 @@ impl Spatialization {
 
             Ok(Spatialization {
                 handle,
+                auto_velocity: false,
+                last_position: None,
+                hrtf: HrtfRenderer::default(),
+                occlusion: OcclusionFilter::default(),
             })
         }
     }
@@ -152,6 +174,162 @@ impl Spatialization {
         }
     }
 
+    /// Binaural alternative to [Spatialization::process], renders the source through the HRIR
+    /// closest to its direction relative to the listener.
+    ///
+    /// Distance attenuation follows the attenuation model, Doppler and cone are not applied.
+    pub fn process_hrtf(
+        &mut self,
+        listener: &SpatializationListener,
+        set: &HrtfSet,
+        input: &[f32],
+        output: &mut [f32],
+        channels: usize,
+    ) -> Result<(), SpatializationError> {
+        if channels < 1 || channels > 8 {
+            return Err(SpatializationError::InvalidChannels(channels));
+        }
+
+        let (direction, gain) = self.direction_and_gain(listener);
+
+        self.hrtf
+            .process(set, direction, gain, input, output, channels);
+
+        Ok(())
+    }
+
+    /// Encode the source into the ambisonics bus of the listener, `output` is silenced since
+    /// the bus is decoded by the device.
+    pub fn process_ambisonics(
+        &mut self,
+        listener: &mut SpatializationListener,
+        input: &[f32],
+        output: &mut [f32],
+        channels: usize,
+    ) -> Result<(), SpatializationError> {
+        if channels < 1 || channels > 8 {
+            return Err(SpatializationError::InvalidChannels(channels));
+        }
+
+        let (direction, gain) = self.direction_and_gain(listener);
+
+        let Some(bus) = listener.ambisonics.as_mut() else {
+            return Err(SpatializationError::NotInitialized);
+        };
+
+        bus.encode(direction, gain, input, channels);
+        output.fill(0.0);
+
+        Ok(())
+    }
+
+    /// Normalized direction of the source relative to the listener and its distance gain.
+    fn direction_and_gain(&self, listener: &SpatializationListener) -> (Vector3<f32>, f32) {
+        let (position, _) = self.get_relative_position_and_direction(listener);
+        let distance =
+            (position.x * position.x + position.y * position.y + position.z * position.z).sqrt();
+
+        let direction = if distance > f32::EPSILON {
+            Vector3::new(position.x / distance, position.y / distance, position.z / distance)
+        } else {
+            Vector3::new(0.0, 0.0, -1.0)
+        };
+
+        let gain = self.distance_gain(distance) * self.get_master_volume().unwrap_or(1.0);
+        (direction, gain)
+    }
+
+    fn distance_gain(&self, distance: f32) -> f32 {
+        let min_distance = self.get_min_distance();
+        let max_distance = self.get_max_distance().max(min_distance);
+        let distance = distance.clamp(min_distance, max_distance);
+        let rolloff = self.get_rolloff();
+
+        let gain = match self.get_attenuation_model() {
+            AttenuationModel::None => 1.0,
+            AttenuationModel::Inverse => {
+                if min_distance <= 0.0 {
+                    1.0
+                } else {
+                    min_distance / (min_distance + rolloff * (distance - min_distance))
+                }
+            }
+            AttenuationModel::Linear => {
+                if max_distance <= min_distance {
+                    1.0
+                } else {
+                    1.0 - rolloff * (distance - min_distance) / (max_distance - min_distance)
+                }
+            }
+            AttenuationModel::Exponential => {
+                if min_distance <= 0.0 {
+                    1.0
+                } else {
+                    (distance / min_distance).powf(-rolloff)
+                }
+            }
+        };
+
+        gain.clamp(self.get_min_gain(), self.get_max_gain())
+    }
+
+    /// How much the source is fully blocked from the listener, 0.0 to 1.0.
+    pub fn set_occlusion(&mut self, occlusion: f32) {
+        self.occlusion.set_occlusion(occlusion);
+    }
+
+    pub fn get_occlusion(&self) -> f32 {
+        self.occlusion.occlusion
+    }
+
+    /// How much the direct path between the source and the listener is blocked, 0.0 to 1.0.
+    pub fn set_obstruction(&mut self, obstruction: f32) {
+        self.occlusion.set_obstruction(obstruction);
+    }
+
+    pub fn get_obstruction(&self) -> f32 {
+        self.occlusion.obstruction
+    }
+
+    /// Muffle `buffer` in place according to the occlusion and obstruction amounts, runs ahead
+    /// of whichever spatialization mode is in use.
+    pub fn process_occlusion(&mut self, buffer: &mut [f32], channels: usize, sample_rate: f32) {
+        self.occlusion.process(buffer, channels, sample_rate);
+    }
+
+    /// Send `input` to the reverb bus of the listener, if any. The send grows from
+    /// a fifth at the min distance to full at the max distance so far sources sound
+    /// more reverberant.
+    pub fn process_reverb_send(
+        &self,
+        listener: &mut SpatializationListener,
+        input: &[f32],
+        channels: usize,
+    ) {
+        if listener.reverb.is_none() {
+            return;
+        }
+
+        let (position, _) = self.get_relative_position_and_direction(listener);
+        let distance =
+            (position.x * position.x + position.y * position.y + position.z * position.z).sqrt();
+
+        let min_distance = self.get_min_distance();
+        let max_distance = self.get_max_distance().max(min_distance);
+        let amount = if max_distance > min_distance {
+            ((distance - min_distance) / (max_distance - min_distance)).clamp(0.0, 1.0)
+        } else {
+            0.0
+        };
+
+        let level = (MIN_REVERB_SEND + (1.0 - MIN_REVERB_SEND) * amount)
+            * self.get_master_volume().unwrap_or(1.0);
+
+        if let Some(reverb) = listener.reverb.as_mut() {
+            reverb.send(input, channels, level);
+        }
+    }
+
     pub fn set_master_volume(&mut self, volume: f32) -> Result<(), SpatializationError> {
         unsafe {
             let result = ma_spatializer_set_master_volume(self.handle.as_mut(), volume);
@@ -309,6 +487,32 @@ impl Spatialization {
         unsafe { ma_spatializer_get_directional_attenuation_factor(self.handle.as_ref()) }
     }
 
+    /// Derive the velocity from the position delta and the time elapsed between
+    /// [Spatialization::move_to] calls, for Doppler without computing velocities manually.
+    pub fn set_auto_velocity(&mut self, enabled: bool) {
+        self.auto_velocity = enabled;
+        self.last_position = None;
+    }
+
+    /// Set the position `elapsed` seconds after the previous move, deriving the velocity from
+    /// it with auto velocity enabled.
+    pub fn move_to(&mut self, position: Vector3<f32>, elapsed: f32) {
+        if self.auto_velocity {
+            let previous = self.last_position.replace(position);
+
+            if let Some(previous) = previous.filter(|_| elapsed > 0.0) {
+                let delta = position - previous;
+                self.set_velocity(Vector3::new(
+                    delta.x / elapsed,
+                    delta.y / elapsed,
+                    delta.z / elapsed,
+                ));
+            }
+        }
+
+        self.set_position(position);
+    }
+
     pub fn set_position(&mut self, position: Vector3<f32>) {
         unsafe {
             ma_spatializer_set_position(self.handle.as_mut(), position.x, position.y, position.z);
@@ -389,6 +593,36 @@ pub trait SpatializationHandler {
     /// Get the position of the audio source in 3D space.
     fn spatial_get_position(&self) -> Result<Vector3<f32>, SpatializationError>;
 
+    /// Derive the velocity of the audio source from consecutive [SpatializationHandler::spatial_set_position] calls.
+    fn spatial_set_auto_velocity(&mut self, _enabled: bool) -> Result<(), SpatializationError> {
+        Err(SpatializationError::NotImplemented)
+    }
+
+    /// Check if the velocity of the audio source is derived from its position updates.
+    fn spatial_get_auto_velocity(&self) -> Result<bool, SpatializationError> {
+        Err(SpatializationError::NotImplemented)
+    }
+
+    /// Set how much the audio source is fully blocked from the listener, 0.0 to 1.0.
+    fn spatial_set_occlusion(&mut self, _occlusion: f32) -> Result<(), SpatializationError> {
+        Err(SpatializationError::NotImplemented)
+    }
+
+    /// Get the occlusion amount of the audio source.
+    fn spatial_get_occlusion(&self) -> Result<f32, SpatializationError> {
+        Err(SpatializationError::NotImplemented)
+    }
+
+    /// Set how much the direct path of the audio source is blocked, 0.0 to 1.0.
+    fn spatial_set_obstruction(&mut self, _obstruction: f32) -> Result<(), SpatializationError> {
+        Err(SpatializationError::NotImplemented)
+    }
+
+    /// Get the obstruction amount of the audio source.
+    fn spatial_get_obstruction(&self) -> Result<f32, SpatializationError> {
+        Err(SpatializationError::NotImplemented)
+    }
+
     /// Set the velocity of the audio source in 3D space.
     fn spatial_set_velocity(&mut self, position: Vector3<f32>) -> Result<(), SpatializationError>;
 
@@ -479,3 +713,192 @@ pub trait SpatializationHandler {
         listener: &Device,
     ) -> Result<(Vector3<f32>, Vector3<f32>), SpatializationError>;
 }
+
+/// Access to the [Spatialization] of an audio source behind its lock, every source providing it
+/// gets [SpatializationHandler].
+pub(crate) trait SpatializationSource {
+    /// Run `f` on the spatialization and the time of the last position update of the source,
+    /// [SpatializationError::NotInitialized] while it has no spatialization.
+    fn with_spatialization<R>(
+        &self,
+        f: impl FnOnce(&mut Spatialization, &mut Instant) -> R,
+    ) -> Result<R, SpatializationError>;
+}
+
+impl<T: SpatializationSource> SpatializationHandler for T {
+    fn spatial_set_position(&mut self, position: Vector3<f32>) -> Result<(), SpatializationError> {
+        self.with_spatialization(|spatializer, last_time| {
+            // Time of the previous position update, for the auto velocity
+            let now = Instant::now();
+            let elapsed = now.duration_since(*last_time).as_secs_f32();
+            *last_time = now;
+
+            spatializer.move_to(position, elapsed);
+        })
+    }
+
+    fn spatial_get_position(&self) -> Result<Vector3<f32>, SpatializationError> {
+        self.with_spatialization(|spatializer, _| spatializer.get_position())
+    }
+
+    fn spatial_set_auto_velocity(&mut self, enabled: bool) -> Result<(), SpatializationError> {
+        self.with_spatialization(|spatializer, _| spatializer.set_auto_velocity(enabled))
+    }
+
+    fn spatial_get_auto_velocity(&self) -> Result<bool, SpatializationError> {
+        self.with_spatialization(|spatializer, _| spatializer.auto_velocity)
+    }
+
+    fn spatial_set_occlusion(&mut self, occlusion: f32) -> Result<(), SpatializationError> {
+        self.with_spatialization(|spatializer, _| spatializer.set_occlusion(occlusion))
+    }
+
+    fn spatial_get_occlusion(&self) -> Result<f32, SpatializationError> {
+        self.with_spatialization(|spatializer, _| spatializer.get_occlusion())
+    }
+
+    fn spatial_set_obstruction(&mut self, obstruction: f32) -> Result<(), SpatializationError> {
+        self.with_spatialization(|spatializer, _| spatializer.set_obstruction(obstruction))
+    }
+
+    fn spatial_get_obstruction(&self) -> Result<f32, SpatializationError> {
+        self.with_spatialization(|spatializer, _| spatializer.get_obstruction())
+    }
+
+    fn spatial_set_velocity(&mut self, position: Vector3<f32>) -> Result<(), SpatializationError> {
+        self.with_spatialization(|spatializer, _| spatializer.set_velocity(position))
+    }
+
+    fn spatial_get_velocity(&self) -> Result<Vector3<f32>, SpatializationError> {
+        self.with_spatialization(|spatializer, _| spatializer.get_velocity())
+    }
+
+    fn spatial_set_direction(&mut self, position: Vector3<f32>) -> Result<(), SpatializationError> {
+        self.with_spatialization(|spatializer, _| spatializer.set_direction(position))
+    }
+
+    fn spatial_get_direction(&self) -> Result<Vector3<f32>, SpatializationError> {
+        self.with_spatialization(|spatializer, _| spatializer.get_direction())
+    }
+
+    fn spatial_set_doppler_factor(
+        &mut self,
+        doppler_factor: f32,
+    ) -> Result<(), SpatializationError> {
+        self.with_spatialization(|spatializer, _| spatializer.set_doppler_factor(doppler_factor))
+    }
+
+    fn spatial_get_doppler_factor(&self) -> Result<f32, SpatializationError> {
+        self.with_spatialization(|spatializer, _| spatializer.get_doppler_factor())
+    }
+
+    fn spatial_set_attenuation_model(
+        &mut self,
+        attenuation_model: AttenuationModel,
+    ) -> Result<(), SpatializationError> {
+        self.with_spatialization(|spatializer, _| {
+            spatializer.set_attenuation_model(attenuation_model)
+        })
+    }
+
+    fn spatial_get_attenuation_model(&self) -> Result<AttenuationModel, SpatializationError> {
+        self.with_spatialization(|spatializer, _| spatializer.get_attenuation_model())
+    }
+
+    fn spatial_set_positioning(
+        &mut self,
+        positioning: Positioning,
+    ) -> Result<(), SpatializationError> {
+        self.with_spatialization(|spatializer, _| spatializer.set_positioning(positioning))
+    }
+
+    fn spatial_get_positioning(&self) -> Result<Positioning, SpatializationError> {
+        self.with_spatialization(|spatializer, _| spatializer.get_positioning())
+    }
+
+    fn spatial_set_rolloff(&mut self, rolloff: f32) -> Result<(), SpatializationError> {
+        self.with_spatialization(|spatializer, _| spatializer.set_rolloff(rolloff))
+    }
+
+    fn spatial_get_rolloff(&self) -> Result<f32, SpatializationError> {
+        self.with_spatialization(|spatializer, _| spatializer.get_rolloff())
+    }
+
+    fn spatial_set_min_gain(&mut self, min_gain: f32) -> Result<(), SpatializationError> {
+        self.with_spatialization(|spatializer, _| spatializer.set_min_gain(min_gain))
+    }
+
+    fn spatial_get_min_gain(&self) -> Result<f32, SpatializationError> {
+        self.with_spatialization(|spatializer, _| spatializer.get_min_gain())
+    }
+
+    fn spatial_set_max_gain(&mut self, max_gain: f32) -> Result<(), SpatializationError> {
+        self.with_spatialization(|spatializer, _| spatializer.set_max_gain(max_gain))
+    }
+
+    fn spatial_get_max_gain(&self) -> Result<f32, SpatializationError> {
+        self.with_spatialization(|spatializer, _| spatializer.get_max_gain())
+    }
+
+    fn spatial_set_min_distance(&mut self, min_distance: f32) -> Result<(), SpatializationError> {
+        self.with_spatialization(|spatializer, _| spatializer.set_min_distance(min_distance))
+    }
+
+    fn spatial_get_min_distance(&self) -> Result<f32, SpatializationError> {
+        self.with_spatialization(|spatializer, _| spatializer.get_min_distance())
+    }
+
+    fn spatial_set_max_distance(&mut self, max_distance: f32) -> Result<(), SpatializationError> {
+        self.with_spatialization(|spatializer, _| spatializer.set_max_distance(max_distance))
+    }
+
+    fn spatial_get_max_distance(&self) -> Result<f32, SpatializationError> {
+        self.with_spatialization(|spatializer, _| spatializer.get_max_distance())
+    }
+
+    fn spatial_set_cone(
+        &mut self,
+        inner_angle: f32,
+        outer_angle: f32,
+        outer_gain: f32,
+    ) -> Result<(), SpatializationError> {
+        self.with_spatialization(|spatializer, _| {
+            spatializer.set_cone(inner_angle, outer_angle, outer_gain)
+        })
+    }
+
+    fn spatial_get_cone(&self) -> Result<(f32, f32, f32), SpatializationError> {
+        self.with_spatialization(|spatializer, _| spatializer.get_cone())
+    }
+
+    fn spatial_set_directional_attenuation_factor(
+        &mut self,
+        directional_attenuation_factor: f32,
+    ) -> Result<(), SpatializationError> {
+        self.with_spatialization(|spatializer, _| {
+            spatializer.set_directional_attenuation_factor(directional_attenuation_factor)
+        })
+    }
+
+    fn spatial_get_directional_attenuation_factor(&self) -> Result<f32, SpatializationError> {
+        self.with_spatialization(|spatializer, _| spatializer.get_directional_attenuation_factor())
+    }
+
+    fn spatial_get_relative_position_and_direction(
+        &self,
+        listener: &Device,
+    ) -> Result<(Vector3<f32>, Vector3<f32>), SpatializationError> {
+        let Some(listener_snapshot) = listener.listener_snapshot() else {
+            return Err(SpatializationError::NotInitialized);
+        };
+
+        // The live listener is owned by the audio thread, compute against a copy of it.
+        let mut listener_spatializer = SpatializationListener::new(listener.channels as u32)
+            .map_err(SpatializationError::from_other)?;
+        listener_snapshot.apply(&mut listener_spatializer);
+
+        self.with_spatialization(|spatializer, _| {
+            spatializer.get_relative_position_and_direction(&listener_spatializer)
+        })
+    }
+}
@@ -0,0 +1,88 @@
+use super::{
+    effect::AudioEffect,
+    equalizer::{Coefficients, EqBand, EqBandKind},
+};
+
+/// Karaoke effect removing what is panned to the center of a stereo signal, usually the lead
+/// vocal.
+///
+/// The mid signal (L + R) / 2 is subtracted from both sides. With a band set only the mid
+/// content inside it is removed, keeping the bass and kick which are usually centered as well.
+/// Signals that are not stereo pass through unchanged.
+#[derive(Debug, Clone)]
+pub struct VocalRemover {
+    sample_rate: f32,
+    wet: f32,
+    band: Option<(f32, f32)>,
+    filters: Option<(Coefficients, Coefficients)>,
+    state: [[f32; 2]; 2],
+}
+
+impl VocalRemover {
+    pub fn new(sample_rate: f32) -> Self {
+        let mut remover = Self {
+            sample_rate,
+            wet: 1.0,
+            band: None,
+            filters: None,
+            state: [[0.0; 2]; 2],
+        };
+
+        remover.set_band(Some((120.0, 8000.0)));
+        remover
+    }
+
+    /// Amount of center removed from 0.0 (dry) to 1.0, 1.0 by default.
+    pub fn set_wet(&mut self, wet: f32) {
+        self.wet = wet.clamp(0.0, 1.0);
+    }
+
+    pub fn get_wet(&self) -> f32 {
+        self.wet
+    }
+
+    /// Only remove the center between `low` and `high` Hz, None removes the whole center.
+    /// Defaults to 120 Hz up to 8 kHz.
+    pub fn set_band(&mut self, band: Option<(f32, f32)>) {
+        const Q: f32 = std::f32::consts::FRAC_1_SQRT_2;
+
+        self.band = band;
+        self.filters = band.map(|(low, high)| {
+            let high_pass = EqBand::new(EqBandKind::HighPass, low, 0.0, Q);
+            let low_pass = EqBand::new(EqBandKind::LowPass, high.max(low), 0.0, Q);
+
+            (
+                Coefficients::new(&high_pass, self.sample_rate),
+                Coefficients::new(&low_pass, self.sample_rate),
+            )
+        });
+    }
+
+    pub fn get_band(&self) -> Option<(f32, f32)> {
+        self.band
+    }
+}
+
+impl AudioEffect for VocalRemover {
+    fn process(&mut self, buffer: &mut [f32], channels: usize) {
+        if channels != 2 {
+            return;
+        }
+
+        for frame in buffer.chunks_exact_mut(2) {
+            let mut mid = (frame[0] + frame[1]) * 0.5;
+
+            if let Some((high_pass, low_pass)) = self.filters.as_ref() {
+                mid = high_pass.process(&mut self.state[0], mid);
+                mid = low_pass.process(&mut self.state[1], mid);
+            }
+
+            frame[0] -= mid * self.wet;
+            frame[1] -= mid * self.wet;
+        }
+    }
+
+    fn reset(&mut self) {
+        self.state = [[0.0; 2]; 2];
+    }
+}
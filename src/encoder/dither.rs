@@ -0,0 +1,75 @@
+/// How f32 samples are quantized when written with fewer bits, see
+/// [WriteFormat::Wav16](super::writer::WriteFormat::Wav16).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[repr(C)]
+pub enum Dither {
+    /// Round to the nearest step, quiet passages distort into harmonics of the signal.
+    None = 0,
+    /// Add triangular noise of two steps peak to peak before rounding, the error becomes a
+    /// constant hiss independent of the signal.
+    #[default]
+    Triangular = 1,
+    /// Triangular dither with the error fed back so the hiss moves towards the high
+    /// frequencies where it is less audible.
+    NoiseShaped = 2,
+}
+
+// Most channels error feedback state is kept for, the rest are dithered without shaping
+const MAX_CHANNELS: usize = 8;
+
+/// Converts f32 samples to i16 with a [Dither].
+#[derive(Debug, Clone)]
+pub(crate) struct Quantizer {
+    dither: Dither,
+    state: u32,
+    error: [f32; MAX_CHANNELS],
+}
+
+impl Quantizer {
+    pub fn new(dither: Dither) -> Self {
+        Self {
+            dither,
+            state: 0x9E37_79B9,
+            error: [0.0; MAX_CHANNELS],
+        }
+    }
+
+    // xorshift32, uniform in -0.5..0.5
+    fn uniform(&mut self) -> f32 {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 17;
+        self.state ^= self.state << 5;
+
+        self.state as f32 / u32::MAX as f32 - 0.5
+    }
+
+    /// Quantize interleaved `input` with `channels` channels into `output`.
+    pub fn process(&mut self, input: &[f32], output: &mut [i16], channels: usize) {
+        const SCALE: f32 = i16::MAX as f32;
+
+        let channels = channels.max(1);
+
+        for (index, (sample, quantized)) in input.iter().zip(output.iter_mut()).enumerate() {
+            let channel = index % channels;
+            let mut value = sample.clamp(-1.0, 1.0) * SCALE;
+
+            let shaped = self.dither == Dither::NoiseShaped && channel < MAX_CHANNELS;
+            if shaped {
+                value -= self.error[channel];
+            }
+
+            let noise = match self.dither {
+                Dither::None => 0.0,
+                Dither::Triangular | Dither::NoiseShaped => self.uniform() + self.uniform(),
+            };
+
+            let rounded = (value + noise).round().clamp(i16::MIN as f32, SCALE);
+            if shaped {
+                self.error[channel] = rounded - value;
+            }
+
+            *quantized = rounded as i16;
+        }
+    }
+}
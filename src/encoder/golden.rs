@@ -0,0 +1,246 @@
+use std::{
+    path::Path,
+    sync::{Arc, Mutex},
+};
+
+use thiserror::Error;
+
+use crate::{Device, Mixer, SampleInfo, Source};
+
+use super::{
+    recorder::RecordTarget,
+    writer::{WriteFormat, Writer},
+};
+
+/// Rewrites the golden files compared by [GoldenRender::check] when set to `1`.
+pub const GOLDEN_UPDATE_ENV: &str = "EST_AUDIO_UPDATE_GOLDEN";
+
+#[derive(Debug, Error)]
+pub enum GoldenError {
+    #[error("Golden file {0} could not be loaded: {1}")]
+    LoadFailed(String, String),
+    #[error("Golden file {0} could not be written: {1}")]
+    SaveFailed(String, String),
+    #[error("Format mismatch, expected {expected:?} channels and sample rate but rendered {actual:?}")]
+    FormatMismatch {
+        expected: (usize, f32),
+        actual: (usize, f32),
+    },
+    #[error("Length mismatch, expected {expected} frames but rendered {actual}")]
+    LengthMismatch { expected: usize, actual: usize },
+    #[error("Output differs from the golden file from frame {frame}, off by up to {max_error}")]
+    Mismatch { frame: usize, max_error: f32 },
+    #[error("{0}")]
+    Other(Box<dyn std::error::Error + Send + 'static>),
+}
+
+impl GoldenError {
+    pub fn from_other<E: std::error::Error + Send + 'static>(error: E) -> Self {
+        GoldenError::Other(Box::new(error))
+    }
+}
+
+/// Result of comparing a [GoldenRender] against its golden file.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct GoldenDiff {
+    pub frames: usize,
+    /// Largest and RMS difference of a sample.
+    pub max_error: f32,
+    pub rms_error: f32,
+    /// First frame with a sample off by more than the tolerance.
+    pub first_mismatch: Option<usize>,
+}
+
+impl GoldenDiff {
+    pub fn matches(&self) -> bool {
+        self.first_mismatch.is_none()
+    }
+}
+
+/// Interleaved output of an offline render, compared against a golden file for DSP
+/// regression tests.
+///
+/// Golden files are 32-bit float WAV files so a render is stored without loss.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GoldenRender {
+    pcm: Vec<f32>,
+    channels: usize,
+    sample_rate: f32,
+}
+
+impl GoldenRender {
+    pub fn new(pcm: Vec<f32>, channels: usize, sample_rate: f32) -> Self {
+        Self {
+            pcm,
+            channels: channels.max(1),
+            sample_rate,
+        }
+    }
+
+    /// Advance a [virtual clock](crate::DeviceInfo::virtual_clock) device by `frames` frames.
+    pub fn from_device(device: &mut Device, frames: usize) -> Result<Self, GoldenError> {
+        let pcm = device.advance(frames).map_err(GoldenError::from_other)?;
+        Ok(Self::new(pcm, device.channels, device.sample_rate))
+    }
+
+    /// Render a mixer offline, see [Mixer::render].
+    pub fn from_mixer(mixer: &mut Mixer, max_frames: Option<usize>) -> Result<Self, GoldenError> {
+        let (channels, sample_rate) = {
+            let Ok(inner) = mixer.inner.lock() else {
+                return Err(GoldenError::from_other(crate::MixerError::LockFailed));
+            };
+
+            (inner.channel_count, inner.sample_rate)
+        };
+
+        let pcm = Arc::new(Mutex::new(Vec::new()));
+        let sink = pcm.clone();
+
+        mixer
+            .render(
+                RecordTarget::Sink(Box::new(move |block: &[f32]| {
+                    if let Ok(mut pcm) = sink.lock() {
+                        pcm.extend_from_slice(block);
+                    }
+                })),
+                max_frames,
+            )
+            .map_err(GoldenError::from_other)?;
+
+        let pcm = pcm
+            .lock()
+            .map(|mut pcm| std::mem::take(&mut *pcm))
+            .unwrap_or_default();
+        Ok(Self::new(pcm, channels, sample_rate))
+    }
+
+    pub fn pcm(&self) -> &[f32] {
+        &self.pcm
+    }
+
+    pub fn channels(&self) -> usize {
+        self.channels
+    }
+
+    pub fn sample_rate(&self) -> f32 {
+        self.sample_rate
+    }
+
+    pub fn frames(&self) -> usize {
+        self.pcm.len() / self.channels
+    }
+
+    /// FNV-1a hash of the samples, equal only for bit-identical renders.
+    pub fn hash(&self) -> u64 {
+        let mut hash = 0xcbf2_9ce4_8422_2325u64;
+
+        for sample in self.pcm.iter() {
+            for byte in sample.to_bits().to_le_bytes() {
+                hash ^= byte as u64;
+                hash = hash.wrapping_mul(0x0000_0100_0000_01b3);
+            }
+        }
+
+        hash
+    }
+
+    /// Store the render as a golden file.
+    pub fn save(&self, path: &str) -> Result<(), GoldenError> {
+        let mut writer = Writer::new(path, WriteFormat::Wav, self.channels, self.sample_rate)
+            .map_err(|e| GoldenError::SaveFailed(path.to_string(), e.to_string()))?;
+
+        writer
+            .write(&self.pcm)
+            .map_err(|e| GoldenError::SaveFailed(path.to_string(), e.to_string()))?;
+
+        Ok(())
+    }
+
+    /// Load a golden file saved with [GoldenRender::save].
+    pub fn load(path: &str) -> Result<Self, GoldenError> {
+        let sample = crate::create_sample(SampleInfo {
+            source: Source::Path(path),
+            ..Default::default()
+        })
+        .map_err(|e| GoldenError::LoadFailed(path.to_string(), e.to_string()))?;
+
+        Ok(Self::new(sample.pcm().to_vec(), sample.channels, sample.sample_rate))
+    }
+
+    /// Compare against `golden` sample by sample, samples off by at most `tolerance` match.
+    pub fn compare(&self, golden: &GoldenRender, tolerance: f32) -> Result<GoldenDiff, GoldenError> {
+        if self.channels != golden.channels || self.sample_rate != golden.sample_rate {
+            return Err(GoldenError::FormatMismatch {
+                expected: (golden.channels, golden.sample_rate),
+                actual: (self.channels, self.sample_rate),
+            });
+        }
+
+        if self.pcm.len() != golden.pcm.len() {
+            return Err(GoldenError::LengthMismatch {
+                expected: golden.frames(),
+                actual: self.frames(),
+            });
+        }
+
+        let mut max_error = 0.0f32;
+        let mut sum = 0.0f64;
+        let mut first_mismatch = None;
+
+        for (index, (actual, expected)) in self.pcm.iter().zip(golden.pcm.iter()).enumerate() {
+            let error = (actual - expected).abs();
+
+            // A NaN never matches, not even another NaN
+            if (error > tolerance || error.is_nan()) && first_mismatch.is_none() {
+                first_mismatch = Some(index / self.channels);
+            }
+
+            max_error = max_error.max(error);
+            sum += error as f64 * error as f64;
+        }
+
+        let rms_error = match self.pcm.len() {
+            0 => 0.0,
+            len => (sum / len as f64).sqrt() as f32,
+        };
+
+        Ok(GoldenDiff {
+            frames: self.frames(),
+            max_error,
+            rms_error,
+            first_mismatch,
+        })
+    }
+
+    /// Compare against the golden file at `path`, failing with [GoldenError::Mismatch] when a
+    /// sample is off by more than `tolerance`.
+    ///
+    /// A missing golden file is created from this render, as is every golden file while the
+    /// [GOLDEN_UPDATE_ENV] variable is set to `1`, so new tests and intended changes only
+    /// need a rerun.
+    pub fn check(&self, path: &str, tolerance: f32) -> Result<GoldenDiff, GoldenError> {
+        let update = std::env::var(GOLDEN_UPDATE_ENV).is_ok_and(|value| value == "1");
+
+        if update || !Path::new(path).exists() {
+            self.save(path)?;
+
+            return Ok(GoldenDiff {
+                frames: self.frames(),
+                max_error: 0.0,
+                rms_error: 0.0,
+                first_mismatch: None,
+            });
+        }
+
+        let diff = self.compare(&Self::load(path)?, tolerance)?;
+
+        match diff.first_mismatch {
+            Some(frame) => Err(GoldenError::Mismatch {
+                frame,
+                max_error: diff.max_error,
+            }),
+            None => Ok(diff),
+        }
+    }
+}
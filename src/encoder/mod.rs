@@ -13,6 +13,9 @@ use crate::{
 use astretch::Stretch;
 use thiserror::Error;
 
+pub mod dither;
+pub mod golden;
+pub mod recorder;
 pub mod writer;
 
 #[derive(Debug, Default)]
@@ -21,12 +24,14 @@ pub struct EncoderInfo<'a> {
 }
 
 #[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct EncoderTrackInfo {
     pub channel: Option<usize>,
     pub sample_rate: Option<f32>,
 }
 
 #[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct EncoderSampleInfo {
     pub channel: Option<usize>,
     pub sample_rate: Option<f32>,
@@ -300,6 +305,7 @@ impl PropertyHandler for Encoder {
             AudioAttributes::FXTempo => Ok(self.fx_rate),
             AudioAttributes::FXPitch => Ok(self.fx_pitch),
             AudioAttributes::Pan => Ok(self.panner.pan),
+            AudioAttributes::Balance => Ok(self.panner.get_balance()),
             AudioAttributes::Volume => Ok(self.volume.volume),
             AudioAttributes::SampleRate => Ok(self.resampler.target_sample_rate as f32),
             _ => Err(PropertyError::NotImplemented),
@@ -327,6 +333,11 @@ impl PropertyHandler for Encoder {
                 self.dirty = true;
                 Ok(())
             }
+            AudioAttributes::Balance => {
+                self.panner.set_balance(_value);
+                self.dirty = true;
+                Ok(())
+            }
             AudioAttributes::Volume => {
                 self.volume.set_volume(_value);
                 self.dirty = true;
@@ -340,6 +351,17 @@ impl PropertyHandler for Encoder {
             _ => Err(PropertyError::NotImplemented),
         }
     }
+
+    fn supported_attributes(&self) -> &'static [AudioAttributes] {
+        &[
+            AudioAttributes::SampleRate,
+            AudioAttributes::Volume,
+            AudioAttributes::Pan,
+            AudioAttributes::Balance,
+            AudioAttributes::FXPitch,
+            AudioAttributes::FXTempo,
+        ]
+    }
 }
 
 #[derive(Debug, Error)]
@@ -0,0 +1,226 @@
+use std::{
+    sync::{
+        Arc,
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        mpsc::{self, Receiver, RecvTimeoutError, SyncSender},
+    },
+    thread::JoinHandle,
+    time::Duration,
+};
+
+use super::{
+    EncoderError,
+    writer::{WriteFormat, Writer},
+};
+
+// Blocks in flight between the audio thread and the writer thread before blocks get dropped
+const QUEUE_DEPTH: usize = 64;
+
+// How often the writer thread checks for a stop while no blocks arrive
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Where a recording is written to.
+pub enum RecordTarget<'a> {
+    /// Encode into a file at the given path.
+    Path(&'a str, WriteFormat),
+    /// Hand every recorded block to a callback, called from the writer thread.
+    Sink(Box<dyn FnMut(&[f32]) + Send + 'static>),
+}
+
+impl std::fmt::Debug for RecordTarget<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RecordTarget::Path(path, format) => {
+                write!(f, "RecordTarget::Path({}, {:?})", path, format)
+            }
+            RecordTarget::Sink(_) => write!(f, "RecordTarget::Sink(...)"),
+        }
+    }
+}
+
+/// An opened [RecordTarget].
+pub(crate) enum Output {
+    Writer(Writer),
+    Sink(Box<dyn FnMut(&[f32]) + Send + 'static>),
+}
+
+impl Output {
+    pub fn open(target: RecordTarget, channels: usize, sample_rate: f32) -> Result<Self, EncoderError> {
+        match target {
+            RecordTarget::Path(path, format) => Writer::new(path, format, channels, sample_rate)
+                .map(Output::Writer)
+                .map_err(EncoderError::from_other),
+            RecordTarget::Sink(sink) => Ok(Output::Sink(sink)),
+        }
+    }
+
+    pub fn write(&mut self, data: &[f32]) {
+        match self {
+            Output::Writer(writer) => {
+                if let Err(e) = writer.write(data) {
+                    eprintln!("Failed to write recorded audio: {}", e);
+                }
+            }
+            Output::Sink(sink) => sink(data),
+        }
+    }
+}
+
+/// Audio thread side of a recording, copies blocks into recycled buffers and queues them to
+/// the writer thread without blocking or allocating.
+pub(crate) struct RecorderTap {
+    sender: SyncSender<Vec<f32>>,
+    recycled: Receiver<Vec<f32>>,
+    // A buffer the writer thread could not take, reused by the next block
+    spare: Option<Vec<f32>>,
+    channels: usize,
+    block_len: usize,
+    dropped: Arc<AtomicUsize>,
+}
+
+impl RecorderTap {
+    pub fn push(&mut self, data: &[f32]) {
+        for chunk in data.chunks(self.block_len) {
+            self.push_block(chunk);
+        }
+    }
+
+    /// Queue `data` of at most one block, dropped and counted when every buffer is in use.
+    fn push_block(&mut self, data: &[f32]) {
+        let block = match self.spare.take() {
+            Some(block) => Some(block),
+            None => self.recycled.try_recv().ok(),
+        };
+
+        let Some(mut block) = block else {
+            self.drop_block(data);
+            return;
+        };
+
+        block.clear();
+        block.extend_from_slice(data);
+
+        if let Err(e) = self.sender.try_send(block) {
+            self.spare = Some(e.into_inner());
+            self.drop_block(data);
+        }
+    }
+
+    fn drop_block(&self, data: &[f32]) {
+        self.dropped
+            .fetch_add(data.len() / self.channels, Ordering::Relaxed);
+    }
+}
+
+/// Control thread side of a recording, owns the writer thread.
+#[derive(Debug)]
+pub(crate) struct Recorder {
+    thread: JoinHandle<()>,
+    dropped: Arc<AtomicUsize>,
+    stop: Arc<AtomicBool>,
+}
+
+impl Recorder {
+    /// Start the writer thread for `target`, the tap is handed to the audio thread. The
+    /// buffers of `block_size` frames the tap copies into are all allocated here.
+    pub fn spawn(
+        target: RecordTarget,
+        channels: usize,
+        sample_rate: f32,
+        block_size: usize,
+    ) -> Result<(RecorderTap, Recorder), EncoderError> {
+        let channels = channels.max(1);
+        let block_len = block_size.max(1) * channels;
+
+        let (sender, receiver) = mpsc::sync_channel::<Vec<f32>>(QUEUE_DEPTH);
+        let (recycle_sender, recycled) = mpsc::sync_channel::<Vec<f32>>(QUEUE_DEPTH);
+
+        // As many buffers as the queue holds, so the writer thread can always hand one back
+        for _ in 0..QUEUE_DEPTH {
+            _ = recycle_sender.try_send(Vec::with_capacity(block_len));
+        }
+
+        let (ready_sender, ready) = mpsc::channel::<Result<(), EncoderError>>();
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread_stop = stop.clone();
+
+        // The miniaudio encoder is not Send, so the file is opened on the writer thread
+        let target = match target {
+            RecordTarget::Path(path, format) => Ok((path.to_string(), format)),
+            RecordTarget::Sink(sink) => Err(sink),
+        };
+
+        let thread = std::thread::Builder::new()
+            .name("estaudio-recorder".to_string())
+            .spawn(move || {
+                let record_target = match target {
+                    Ok((ref path, format)) => RecordTarget::Path(path, format),
+                    Err(sink) => RecordTarget::Sink(sink),
+                };
+
+                let mut output = match Output::open(record_target, channels, sample_rate) {
+                    Ok(output) => output,
+                    Err(e) => {
+                        _ = ready_sender.send(Err(e));
+                        return;
+                    }
+                };
+
+                _ = ready_sender.send(Ok(()));
+
+                // The tap may live on an audio thread that is not running anymore, so a stop
+                // request ends the recording without waiting for the queue to close
+                while !thread_stop.load(Ordering::Acquire) {
+                    match receiver.recv_timeout(POLL_INTERVAL) {
+                        Ok(block) => {
+                            output.write(&block);
+                            _ = recycle_sender.try_send(block);
+                        }
+                        Err(RecvTimeoutError::Timeout) => {}
+                        Err(RecvTimeoutError::Disconnected) => break,
+                    }
+                }
+
+                while let Ok(block) = receiver.try_recv() {
+                    output.write(&block);
+                }
+            })
+            .map_err(EncoderError::from_other)?;
+
+        match ready.recv() {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => return Err(e),
+            Err(_) => return Err(EncoderError::InitFailed),
+        }
+
+        let dropped = Arc::new(AtomicUsize::new(0));
+
+        let tap = RecorderTap {
+            sender,
+            recycled,
+            spare: None,
+            channels,
+            block_len,
+            dropped: dropped.clone(),
+        };
+
+        let recorder = Recorder {
+            thread,
+            dropped,
+            stop,
+        };
+
+        Ok((tap, recorder))
+    }
+
+    /// Frames that could not be queued because the writer thread fell behind.
+    pub fn dropped_frames(&self) -> usize {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    /// Write the blocks already queued, close the output and wait for the writer thread.
+    pub fn finish(self) {
+        self.stop.store(true, Ordering::Release);
+        _ = self.thread.join();
+    }
+}
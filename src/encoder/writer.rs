@@ -3,13 +3,23 @@ use thiserror::Error;
 
 use crate::utils;
 
+use super::dither::{Dither, Quantizer};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum WriteFormat {
+    /// 32-bit float WAV.
     Wav,
+    /// 16-bit PCM WAV, quantized with the given [Dither].
+    Wav16(Dither),
 }
 
 pub struct Writer {
     writer: Box<ma::ma_encoder>,
     channels: usize,
+    quantizer: Option<Quantizer>,
+    // Converted samples of the block being written with a 16-bit format
+    quantized: Vec<i16>,
 }
 
 impl Writer {
@@ -20,11 +30,14 @@ impl Writer {
         sample_rate: f32,
     ) -> Result<Self, WriterError> {
         unsafe {
+            let (sample_format, quantizer) = match format {
+                WriteFormat::Wav => (ma::ma_format_f32, None),
+                WriteFormat::Wav16(dither) => (ma::ma_format_s16, Some(Quantizer::new(dither))),
+            };
+
             let config = ma::ma_encoder_config_init(
-                match format {
-                    WriteFormat::Wav => ma::ma_encoding_format_wav,
-                },
-                ma::ma_format_f32,
+                ma::ma_encoding_format_wav,
+                sample_format,
                 channels as u32,
                 sample_rate as u32,
             );
@@ -39,6 +52,8 @@ impl Writer {
             Ok(Self {
                 writer: encoder,
                 channels,
+                quantizer,
+                quantized: Vec::new(),
             })
         }
     }
@@ -48,9 +63,18 @@ impl Writer {
             let input_frames = (data.len() / self.channels as usize) as u64;
             let mut written_frames = 0;
 
+            let pointer = match self.quantizer.as_mut() {
+                Some(quantizer) => {
+                    self.quantized.resize(data.len(), 0);
+                    quantizer.process(data, &mut self.quantized, self.channels);
+                    self.quantized.as_ptr() as *const std::ffi::c_void
+                }
+                None => data.as_ptr() as *const std::ffi::c_void,
+            };
+
             let result = ma::ma_encoder_write_pcm_frames(
                 self.writer.as_mut(),
-                data.as_ptr() as *const std::ffi::c_void,
+                pointer,
                 input_frames,
                 &mut written_frames,
             );
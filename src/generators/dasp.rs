@@ -0,0 +1,60 @@
+use dasp::{Frame, Sample, Signal, sample::ToSample};
+
+use super::AudioGenerator;
+
+/// Plays a `dasp` signal through a [Track](crate::Track), see
+/// [Source::Generator](crate::Source::Generator). The track plays silence once the signal is
+/// exhausted.
+pub struct DaspGenerator<S>
+where
+    S: Signal + Send,
+    <S::Frame as Frame>::Sample: ToSample<f32>,
+{
+    signal: S,
+    sample_rate: f32,
+}
+
+impl<S> DaspGenerator<S>
+where
+    S: Signal + Send,
+    <S::Frame as Frame>::Sample: ToSample<f32>,
+{
+    /// A signal has no sample rate, `sample_rate` is the one it was built for.
+    pub fn new(signal: S, sample_rate: f32) -> Self {
+        Self {
+            signal,
+            sample_rate,
+        }
+    }
+
+    pub fn into_inner(self) -> S {
+        self.signal
+    }
+}
+
+impl<S> AudioGenerator for DaspGenerator<S>
+where
+    S: Signal + Send,
+    <S::Frame as Frame>::Sample: ToSample<f32>,
+{
+    fn channels(&self) -> usize {
+        S::Frame::CHANNELS
+    }
+
+    fn sample_rate(&self) -> f32 {
+        self.sample_rate
+    }
+
+    fn generate(&mut self, output: &mut [f32]) {
+        for frame in output.chunks_exact_mut(S::Frame::CHANNELS) {
+            if self.signal.is_exhausted() {
+                frame.fill(0.0);
+                continue;
+            }
+
+            for (sample, value) in frame.iter_mut().zip(self.signal.next().channels()) {
+                *sample = value.to_sample::<f32>();
+            }
+        }
+    }
+}
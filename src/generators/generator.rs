@@ -0,0 +1,15 @@
+/// A procedural source played like a file through a [Track](crate::Track), see
+/// [Source::Generator](crate::Source::Generator). Never ends, the track runs until stopped.
+///
+/// Runs on the audio thread, so `generate` should not allocate or block.
+pub trait AudioGenerator: Send {
+    fn channels(&self) -> usize;
+
+    fn sample_rate(&self) -> f32;
+
+    /// Fill `output` holding interleaved frames with [AudioGenerator::channels] channels.
+    fn generate(&mut self, output: &mut [f32]);
+
+    /// Start over, called when the track seeks back to the start.
+    fn reset(&mut self) {}
+}
@@ -0,0 +1,304 @@
+use std::{
+    f32::consts::PI,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering},
+    },
+};
+
+use thiserror::Error;
+
+use crate::{Sample, Source};
+
+use super::generator::AudioGenerator;
+
+/// Grains sounding at once, a grain due while this many play is skipped.
+const MAX_GRAINS: usize = 128;
+
+#[derive(Debug, Error)]
+pub enum GranularError {
+    #[error("The sample is empty")]
+    EmptySample,
+    #[error("Invalid sample rate: {0}")]
+    InvalidSampleRate(f32),
+}
+
+#[derive(Debug)]
+struct GranularParams {
+    // f32 bits, written from the control thread and read once per block
+    grain_size: AtomicU32,
+    density: AtomicU32,
+    position_spray: AtomicU32,
+    pitch: AtomicU32,
+    pitch_spray: AtomicU32,
+    speed: AtomicU32,
+    // Scan position in seconds as f64 bits, a jump is requested through `seek`
+    position: AtomicU64,
+    seek: AtomicU64,
+    seek_pending: AtomicBool,
+}
+
+impl GranularParams {
+    fn load(value: &AtomicU32) -> f32 {
+        f32::from_bits(value.load(Ordering::Relaxed))
+    }
+
+    fn store(value: &AtomicU32, new: f32) {
+        value.store(new.to_bits(), Ordering::Relaxed);
+    }
+}
+
+/// Granular player over a [Sample](crate::Sample), as the source of a
+/// [Track](crate::Track) created from [Granular::source].
+///
+/// Short windowed grains are cut from around a scan position that moves through the sample
+/// at [Granular::set_speed], so a low speed stretches the sample without changing its pitch.
+/// The scan position wraps at the end of the sample. The settings apply from the next block
+/// of the device.
+#[derive(Debug, Clone)]
+pub struct Granular {
+    sample: Sample,
+    sample_rate: f32,
+    params: Arc<GranularParams>,
+}
+
+impl Granular {
+    /// A player rendering the channels of `sample` at `sample_rate`, with 100 ms grains at
+    /// 20 grains per second.
+    pub fn new(sample: Sample, sample_rate: f32) -> Result<Self, GranularError> {
+        if sample.pcm().is_empty() {
+            return Err(GranularError::EmptySample);
+        }
+
+        if !sample_rate.is_finite() || sample_rate <= 0.0 {
+            return Err(GranularError::InvalidSampleRate(sample_rate));
+        }
+
+        let params = GranularParams {
+            grain_size: AtomicU32::new(0.1f32.to_bits()),
+            density: AtomicU32::new(20f32.to_bits()),
+            position_spray: AtomicU32::new(0f32.to_bits()),
+            pitch: AtomicU32::new(0f32.to_bits()),
+            pitch_spray: AtomicU32::new(0f32.to_bits()),
+            speed: AtomicU32::new(1f32.to_bits()),
+            position: AtomicU64::new(0f64.to_bits()),
+            seek: AtomicU64::new(0f64.to_bits()),
+            seek_pending: AtomicBool::new(false),
+        };
+
+        Ok(Self {
+            sample,
+            sample_rate,
+            params: Arc::new(params),
+        })
+    }
+
+    /// A new source playing from the start of the sample, every source shares the settings.
+    pub fn source(&self) -> Source<'static> {
+        Source::Generator(Box::new(GranularSource {
+            sample: self.sample.clone(),
+            sample_rate: self.sample_rate,
+            params: self.params.clone(),
+            grains: Vec::with_capacity(MAX_GRAINS),
+            scan: 0.0,
+            countdown: 0.0,
+            state: 0x9E37_79B9,
+        }))
+    }
+
+    /// Length of a grain in seconds, 0.1 by default.
+    pub fn set_grain_size(&self, seconds: f32) {
+        GranularParams::store(&self.params.grain_size, seconds.clamp(0.001, 10.0));
+    }
+
+    pub fn get_grain_size(&self) -> f32 {
+        GranularParams::load(&self.params.grain_size)
+    }
+
+    /// Grains started per second, 20 by default.
+    pub fn set_density(&self, grains_per_second: f32) {
+        GranularParams::store(&self.params.density, grains_per_second.clamp(0.1, 1000.0));
+    }
+
+    pub fn get_density(&self) -> f32 {
+        GranularParams::load(&self.params.density)
+    }
+
+    /// Most distance in seconds a grain starts from the scan position, either way.
+    pub fn set_position_spray(&self, seconds: f32) {
+        GranularParams::store(&self.params.position_spray, seconds.max(0.0));
+    }
+
+    pub fn get_position_spray(&self) -> f32 {
+        GranularParams::load(&self.params.position_spray)
+    }
+
+    /// Pitch of the grains in semitones.
+    pub fn set_pitch(&self, semitones: f32) {
+        GranularParams::store(&self.params.pitch, semitones.clamp(-48.0, 48.0));
+    }
+
+    pub fn get_pitch(&self) -> f32 {
+        GranularParams::load(&self.params.pitch)
+    }
+
+    /// Most random pitch offset in semitones of a grain, either way.
+    pub fn set_pitch_spray(&self, semitones: f32) {
+        GranularParams::store(&self.params.pitch_spray, semitones.clamp(0.0, 48.0));
+    }
+
+    pub fn get_pitch_spray(&self) -> f32 {
+        GranularParams::load(&self.params.pitch_spray)
+    }
+
+    /// Rate the scan position moves at, 1.0 follows the sample in real time and 0.0 freezes
+    /// it. Negative rates scan backwards.
+    pub fn set_speed(&self, speed: f32) {
+        GranularParams::store(&self.params.speed, speed);
+    }
+
+    pub fn get_speed(&self) -> f32 {
+        GranularParams::load(&self.params.speed)
+    }
+
+    /// Move the scan position to `seconds` into the sample.
+    pub fn set_position(&self, seconds: f64) {
+        self.params.seek.store(seconds.max(0.0).to_bits(), Ordering::Relaxed);
+        self.params.seek_pending.store(true, Ordering::Release);
+    }
+
+    /// Scan position in seconds as of the last block.
+    pub fn get_position(&self) -> f64 {
+        f64::from_bits(self.params.position.load(Ordering::Relaxed))
+    }
+}
+
+#[derive(Debug)]
+struct Grain {
+    position: f64,
+    step: f64,
+    age: usize,
+    length: usize,
+}
+
+/// The audio thread side of a [Granular].
+struct GranularSource {
+    sample: Sample,
+    sample_rate: f32,
+    params: Arc<GranularParams>,
+    grains: Vec<Grain>,
+    // Scan position in frames of the sample
+    scan: f64,
+    // Output frames until the next grain starts
+    countdown: f32,
+    state: u32,
+}
+
+impl GranularSource {
+    // xorshift32, uniform in -1..1
+    fn random(&mut self) -> f32 {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 17;
+        self.state ^= self.state << 5;
+
+        self.state as f32 / u32::MAX as f32 * 2.0 - 1.0
+    }
+}
+
+impl AudioGenerator for GranularSource {
+    fn channels(&self) -> usize {
+        self.sample.channels
+    }
+
+    fn sample_rate(&self) -> f32 {
+        self.sample_rate
+    }
+
+    fn generate(&mut self, output: &mut [f32]) {
+        let params = self.params.clone();
+        let source_rate = self.sample.sample_rate as f64;
+        let length = self.sample.pcm_length;
+        let channels = self.sample.channels;
+
+        if params.seek_pending.swap(false, Ordering::Acquire) {
+            let seconds = f64::from_bits(params.seek.load(Ordering::Relaxed));
+            self.scan = (seconds * source_rate).min(length as f64 - 1.0);
+        }
+
+        let grain_size = GranularParams::load(&params.grain_size);
+        let density = GranularParams::load(&params.density);
+        let position_spray = GranularParams::load(&params.position_spray) as f64 * source_rate;
+        let pitch = GranularParams::load(&params.pitch);
+        let pitch_spray = GranularParams::load(&params.pitch_spray);
+        let speed = GranularParams::load(&params.speed) as f64;
+
+        let interval = self.sample_rate / density;
+        let grain_length = (grain_size * self.sample_rate).max(1.0) as usize;
+        let scan_step = speed * source_rate / self.sample_rate as f64;
+
+        // Hann windows average to one half, keep the overlapping grains near unity
+        let overlap = grain_size * density;
+        let gain = (2.0 / overlap).min(1.0);
+
+        output.fill(0.0);
+
+        for frame in output.chunks_exact_mut(channels) {
+            self.countdown -= 1.0;
+
+            if self.countdown <= 0.0 {
+                self.countdown += interval;
+
+                if self.grains.len() < MAX_GRAINS {
+                    let offset = self.random() as f64 * position_spray;
+                    let semitones = pitch + self.random() * pitch_spray;
+
+                    self.grains.push(Grain {
+                        position: (self.scan + offset).clamp(0.0, length as f64 - 1.0),
+                        step: 2f64.powf(semitones as f64 / 12.0) * source_rate
+                            / self.sample_rate as f64,
+                        age: 0,
+                        length: grain_length,
+                    });
+                }
+            }
+
+            let pcm = self.sample.pcm();
+
+            for grain in self.grains.iter_mut() {
+                let index = grain.position as usize;
+                if index + 1 >= length {
+                    grain.age = grain.length;
+                    continue;
+                }
+
+                let fraction = (grain.position - index as f64) as f32;
+                let phase = grain.age as f32 / grain.length as f32;
+                let window = (0.5 - 0.5 * (2.0 * PI * phase).cos()) * gain;
+
+                for (channel, sample) in frame.iter_mut().enumerate() {
+                    let a = pcm[index * channels + channel];
+                    let b = pcm[(index + 1) * channels + channel];
+
+                    *sample += (a + (b - a) * fraction) * window;
+                }
+
+                grain.position += grain.step;
+                grain.age += 1;
+            }
+
+            self.grains.retain(|grain| grain.age < grain.length);
+
+            self.scan = (self.scan + scan_step).rem_euclid(length as f64);
+        }
+
+        params
+            .position
+            .store((self.scan / source_rate).to_bits(), Ordering::Relaxed);
+    }
+
+    fn reset(&mut self) {
+        self.grains.clear();
+        self.scan = 0.0;
+        self.countdown = 0.0;
+    }
+}
@@ -0,0 +1,22 @@
+#[cfg(feature = "dasp")]
+mod dasp;
+mod generator;
+mod granular;
+#[cfg(feature = "rodio")]
+mod rodio;
+mod sampler;
+mod sf2;
+mod sfz;
+mod tones;
+mod wavetable;
+
+#[cfg(feature = "dasp")]
+pub use self::dasp::DaspGenerator;
+pub use generator::AudioGenerator;
+pub use granular::{Granular, GranularError};
+#[cfg(feature = "rodio")]
+pub use self::rodio::RodioGenerator;
+pub use sampler::{Instrument, LoopMode, Sampler, SamplerError, SamplerZone};
+pub use tones::{BeepInfo, DtmfInfo, ToneEnvelope};
+pub(crate) use tones::{create_beep, create_dtmf};
+pub use wavetable::{Waveform, Wavetable, WavetableError, WavetableSynth};
@@ -0,0 +1,66 @@
+use std::time::Duration;
+
+use rodio::Sample as _;
+
+use super::AudioGenerator;
+
+/// Plays a `rodio::Source` through a [Track](crate::Track), see
+/// [Source::Generator](crate::Source::Generator).
+///
+/// The channels and sample rate are the ones the source reports when wrapped, a source changing
+/// format midway keeps being read with them. Once the source ends the track plays silence.
+pub struct RodioGenerator<S>
+where
+    S: rodio::Source + Send,
+    S::Item: rodio::Sample,
+{
+    source: S,
+    channels: usize,
+    sample_rate: f32,
+}
+
+impl<S> RodioGenerator<S>
+where
+    S: rodio::Source + Send,
+    S::Item: rodio::Sample,
+{
+    pub fn new(source: S) -> Self {
+        let channels = source.channels() as usize;
+        let sample_rate = source.sample_rate() as f32;
+
+        Self {
+            source,
+            channels,
+            sample_rate,
+        }
+    }
+
+    pub fn into_inner(self) -> S {
+        self.source
+    }
+}
+
+impl<S> AudioGenerator for RodioGenerator<S>
+where
+    S: rodio::Source + Send,
+    S::Item: rodio::Sample,
+{
+    fn channels(&self) -> usize {
+        self.channels
+    }
+
+    fn sample_rate(&self) -> f32 {
+        self.sample_rate
+    }
+
+    fn generate(&mut self, output: &mut [f32]) {
+        for sample in output.iter_mut() {
+            *sample = self.source.next().map_or(0.0, |value| value.to_f32());
+        }
+    }
+
+    fn reset(&mut self) {
+        // Sources that cannot seek carry on from where they are
+        _ = self.source.try_seek(Duration::ZERO);
+    }
+}
@@ -0,0 +1,372 @@
+use std::sync::{
+    Arc,
+    mpsc::{self, Receiver, Sender},
+};
+
+use thiserror::Error;
+
+use crate::{Sample, Source};
+
+use super::{generator::AudioGenerator, sf2, sfz};
+
+/// Voices sounding at once, the oldest voice is cut when a note needs one more.
+const MAX_VOICES: usize = 64;
+
+// Shortest release, keeps a note off from clicking
+const MIN_RELEASE_SECS: f32 = 0.005;
+
+#[derive(Debug, Error)]
+pub enum SamplerError {
+    #[error("Failed to read {0}: {1}")]
+    Io(String, std::io::Error),
+    #[error("Invalid instrument file: {0}")]
+    InvalidFile(&'static str),
+    #[error("Failed to load sample {0}: {1}")]
+    SampleLoad(String, String),
+    #[error("No preset {1} in bank {0}")]
+    PresetNotFound(u16, u16),
+    #[error("Instrument has no zones")]
+    Empty,
+    #[error("Invalid sample rate: {0}")]
+    InvalidSampleRate(f32),
+    #[error("The sampler source was dropped")]
+    SendFailed,
+}
+
+/// How a zone loops its sample.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[repr(C)]
+pub enum LoopMode {
+    /// Play to the end of the sample, note off releases.
+    #[default]
+    NoLoop = 0,
+    /// Play to the end of the sample, note off is ignored.
+    OneShot = 1,
+    /// Loop until the release has faded out.
+    Continuous = 2,
+    /// Loop while the key is held, then play on to the end of the sample.
+    Sustain = 3,
+}
+
+/// A sample mapped to a key and velocity range of an [Instrument].
+#[derive(Debug, Clone)]
+pub struct SamplerZone {
+    pub sample: Sample,
+    /// Lowest and highest MIDI key, inclusive.
+    pub key_range: (u8, u8),
+    /// Lowest and highest velocity, inclusive.
+    pub velocity_range: (u8, u8),
+    /// Key the sample plays at its own pitch.
+    pub root_key: u8,
+    /// Pitch offset in cents.
+    pub tune: f32,
+    /// Gain in dB.
+    pub volume: f32,
+    /// -1.0 is left and 1.0 is right.
+    pub pan: f32,
+    pub loop_mode: LoopMode,
+    /// Loop start and end in frames of the sample, the end is exclusive. None loops the whole
+    /// sample.
+    pub loop_points: Option<(usize, usize)>,
+    /// Fade in and fade out after note off, in seconds.
+    pub attack: f32,
+    pub release: f32,
+}
+
+impl SamplerZone {
+    /// A zone playing `sample` over every key and velocity with key 60 at its own pitch.
+    pub fn new(sample: Sample) -> Self {
+        Self {
+            sample,
+            key_range: (0, 127),
+            velocity_range: (1, 127),
+            root_key: 60,
+            tune: 0.0,
+            volume: 0.0,
+            pan: 0.0,
+            loop_mode: LoopMode::NoLoop,
+            loop_points: None,
+            attack: 0.0,
+            release: 0.0,
+        }
+    }
+
+    fn matches(&self, key: u8, velocity: u8) -> bool {
+        (self.key_range.0..=self.key_range.1).contains(&key)
+            && (self.velocity_range.0..=self.velocity_range.1).contains(&velocity)
+    }
+}
+
+/// Zones of samples played by a [Sampler], loaded from an SFZ or SF2 file or built by hand.
+#[derive(Debug, Clone)]
+pub struct Instrument {
+    zones: Vec<SamplerZone>,
+}
+
+impl Instrument {
+    pub fn new(zones: Vec<SamplerZone>) -> Result<Self, SamplerError> {
+        if zones.is_empty() {
+            return Err(SamplerError::Empty);
+        }
+
+        Ok(Self { zones })
+    }
+
+    /// Load the regions of an SFZ file, sample paths are relative to the file. Supports the
+    /// key, velocity, pitch, volume, pan, loop and amplitude envelope opcodes.
+    pub fn load_sfz(path: &str) -> Result<Self, SamplerError> {
+        Self::new(sfz::parse(path)?)
+    }
+
+    /// Load the preset `preset` of bank `bank` from an SF2 file.
+    pub fn load_sf2(path: &str, bank: u16, preset: u16) -> Result<Self, SamplerError> {
+        let data = std::fs::read(path).map_err(|e| SamplerError::Io(path.to_string(), e))?;
+        Self::from_sf2(&data, bank, preset)
+    }
+
+    /// [Instrument::load_sf2] from the file contents.
+    pub fn from_sf2(data: &[u8], bank: u16, preset: u16) -> Result<Self, SamplerError> {
+        Self::new(sf2::parse(data, bank, preset)?)
+    }
+
+    pub fn zones(&self) -> &[SamplerZone] {
+        &self.zones
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum NoteEvent {
+    On(u8, u8),
+    Off(u8),
+    AllOff,
+}
+
+/// Plays an [Instrument] from notes as the source of a [Track](crate::Track), created from
+/// [Sampler::source] and played like any other track.
+///
+/// Notes are picked up at the start of the next block of the device.
+#[derive(Debug)]
+pub struct Sampler {
+    instrument: Arc<Instrument>,
+    sample_rate: f32,
+    sender: Sender<NoteEvent>,
+}
+
+impl Sampler {
+    /// A sampler rendering stereo at `sample_rate`.
+    pub fn new(instrument: Instrument, sample_rate: f32) -> Result<Self, SamplerError> {
+        if !sample_rate.is_finite() || sample_rate <= 0.0 {
+            return Err(SamplerError::InvalidSampleRate(sample_rate));
+        }
+
+        Ok(Self {
+            instrument: Arc::new(instrument),
+            sample_rate,
+            sender: mpsc::channel().0,
+        })
+    }
+
+    /// The source to create the track from. The notes go to the source created last.
+    pub fn source(&mut self) -> Source<'static> {
+        let (sender, receiver) = mpsc::channel();
+        self.sender = sender;
+
+        Source::Generator(Box::new(SamplerSource {
+            instrument: self.instrument.clone(),
+            sample_rate: self.sample_rate,
+            receiver,
+            voices: Vec::with_capacity(MAX_VOICES),
+            age: 0,
+        }))
+    }
+
+    /// Start `key` on every zone it and `velocity` fall in. A velocity of 0 is a note off.
+    pub fn note_on(&self, key: u8, velocity: u8) -> Result<(), SamplerError> {
+        match velocity {
+            0 => self.send(NoteEvent::Off(key)),
+            velocity => self.send(NoteEvent::On(key.min(127), velocity.min(127))),
+        }
+    }
+
+    pub fn note_off(&self, key: u8) -> Result<(), SamplerError> {
+        self.send(NoteEvent::Off(key))
+    }
+
+    /// Release every sounding note.
+    pub fn all_notes_off(&self) -> Result<(), SamplerError> {
+        self.send(NoteEvent::AllOff)
+    }
+
+    pub fn get_instrument(&self) -> &Instrument {
+        &self.instrument
+    }
+
+    fn send(&self, event: NoteEvent) -> Result<(), SamplerError> {
+        self.sender.send(event).map_err(|_| SamplerError::SendFailed)
+    }
+}
+
+#[derive(Debug)]
+struct Voice {
+    zone: usize,
+    key: u8,
+    // Frame position in the sample and frames advanced per output frame
+    position: f64,
+    step: f64,
+    gains: [f32; 2],
+    envelope: f32,
+    attack_step: f32,
+    release_step: f32,
+    released: bool,
+    age: u64,
+}
+
+/// The audio thread side of a [Sampler].
+struct SamplerSource {
+    instrument: Arc<Instrument>,
+    sample_rate: f32,
+    receiver: Receiver<NoteEvent>,
+    voices: Vec<Voice>,
+    age: u64,
+}
+
+impl SamplerSource {
+    fn note_on(&mut self, key: u8, velocity: u8) {
+        let velocity_gain = (velocity as f32 / 127.0).powi(2);
+
+        for (index, zone) in self.instrument.zones.iter().enumerate() {
+            if !zone.matches(key, velocity) || zone.sample.pcm().is_empty() {
+                continue;
+            }
+
+            if self.voices.len() == MAX_VOICES {
+                let oldest = (0..self.voices.len())
+                    .min_by_key(|index| self.voices[*index].age)
+                    .unwrap_or(0);
+                self.voices.swap_remove(oldest);
+            }
+
+            let semitones = key as f64 - zone.root_key as f64 + zone.tune as f64 / 100.0;
+            let step = 2f64.powf(semitones / 12.0) * zone.sample.sample_rate as f64
+                / self.sample_rate as f64;
+
+            let gain = 10f32.powf(zone.volume / 20.0) * velocity_gain;
+            let pan = zone.pan.clamp(-1.0, 1.0);
+            let gains = [gain * (1.0 - pan).min(1.0), gain * (1.0 + pan).min(1.0)];
+
+            let attack_frames = zone.attack.max(0.0) * self.sample_rate;
+            let release_frames = zone.release.max(MIN_RELEASE_SECS) * self.sample_rate;
+
+            self.age += 1;
+            self.voices.push(Voice {
+                zone: index,
+                key,
+                position: 0.0,
+                step,
+                gains,
+                envelope: if attack_frames >= 1.0 { 0.0 } else { 1.0 },
+                attack_step: 1.0 / attack_frames.max(1.0),
+                release_step: 1.0 / release_frames,
+                released: false,
+                age: self.age,
+            });
+        }
+    }
+
+    fn note_off(&mut self, key: Option<u8>) {
+        for voice in self.voices.iter_mut() {
+            let zone = &self.instrument.zones[voice.zone];
+            if key.is_none_or(|key| key == voice.key) && zone.loop_mode != LoopMode::OneShot {
+                voice.released = true;
+            }
+        }
+    }
+}
+
+impl AudioGenerator for SamplerSource {
+    fn channels(&self) -> usize {
+        2
+    }
+
+    fn sample_rate(&self) -> f32 {
+        self.sample_rate
+    }
+
+    fn generate(&mut self, output: &mut [f32]) {
+        while let Ok(event) = self.receiver.try_recv() {
+            match event {
+                NoteEvent::On(key, velocity) => self.note_on(key, velocity),
+                NoteEvent::Off(key) => self.note_off(Some(key)),
+                NoteEvent::AllOff => self.note_off(None),
+            }
+        }
+
+        output.fill(0.0);
+
+        let instrument = &self.instrument;
+        self.voices.retain_mut(|voice| render_voice(voice, instrument, output));
+    }
+
+    fn reset(&mut self) {
+        self.voices.clear();
+    }
+}
+
+/// Add `voice` on top of stereo `output`, returns false once the voice has ended.
+fn render_voice(voice: &mut Voice, instrument: &Instrument, output: &mut [f32]) -> bool {
+    let zone = &instrument.zones[voice.zone];
+    let pcm = zone.sample.pcm();
+    let channels = zone.sample.channels;
+    let length = zone.sample.pcm_length;
+
+    let (loop_start, loop_end) = zone
+        .loop_points
+        .filter(|(start, end)| start < end && *end <= length)
+        .unwrap_or((0, length));
+
+    for frame in output.chunks_exact_mut(2) {
+        let looping = match zone.loop_mode {
+            LoopMode::Continuous => true,
+            LoopMode::Sustain => !voice.released,
+            _ => false,
+        };
+
+        if looping && voice.position >= loop_end as f64 {
+            voice.position -= (loop_end - loop_start) as f64;
+        }
+
+        let index = voice.position as usize;
+        if index >= length {
+            return false;
+        }
+
+        let next = match index + 1 {
+            next if looping && next >= loop_end => loop_start,
+            next => next.min(length - 1),
+        };
+
+        let fraction = (voice.position - index as f64) as f32;
+
+        if voice.released {
+            voice.envelope -= voice.release_step;
+            if voice.envelope <= 0.0 {
+                return false;
+            }
+        } else if voice.envelope < 1.0 {
+            voice.envelope = (voice.envelope + voice.attack_step).min(1.0);
+        }
+
+        for (channel, sample) in frame.iter_mut().enumerate() {
+            let source = channel.min(channels - 1);
+            let a = pcm[index * channels + source];
+            let b = pcm[next * channels + source];
+
+            *sample += (a + (b - a) * fraction) * voice.gains[channel] * voice.envelope;
+        }
+
+        voice.position += voice.step;
+    }
+
+    true
+}
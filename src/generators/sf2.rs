@@ -0,0 +1,499 @@
+use std::collections::HashMap;
+
+use crate::{BufferInfo, Sample, SampleInfo, Source};
+
+use super::sampler::{LoopMode, SamplerError, SamplerZone};
+
+// Generator operators of the SF2 2.01 spec that the sampler maps
+const GEN_PAN: u16 = 17;
+const GEN_ATTACK_VOL_ENV: u16 = 34;
+const GEN_RELEASE_VOL_ENV: u16 = 38;
+const GEN_INSTRUMENT: u16 = 41;
+const GEN_KEY_RANGE: u16 = 43;
+const GEN_VEL_RANGE: u16 = 44;
+const GEN_INITIAL_ATTENUATION: u16 = 48;
+const GEN_COARSE_TUNE: u16 = 51;
+const GEN_FINE_TUNE: u16 = 52;
+const GEN_SAMPLE_ID: u16 = 53;
+const GEN_SAMPLE_MODES: u16 = 54;
+const GEN_OVERRIDING_ROOT_KEY: u16 = 58;
+
+// Default envelope times, about 1 ms
+const DEFAULT_TIMECENTS: i32 = -12000;
+
+#[derive(Debug, Clone, Copy)]
+struct Generator {
+    operator: u16,
+    amount: [u8; 2],
+}
+
+impl Generator {
+    fn signed(&self) -> i32 {
+        i16::from_le_bytes(self.amount) as i32
+    }
+
+    fn unsigned(&self) -> usize {
+        u16::from_le_bytes(self.amount) as usize
+    }
+
+    fn range(&self) -> (u8, u8) {
+        (self.amount[0], self.amount[1])
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct SampleHeader {
+    start: usize,
+    end: usize,
+    loop_start: usize,
+    loop_end: usize,
+    sample_rate: u32,
+    original_pitch: u8,
+    pitch_correction: i8,
+}
+
+/// Generators of one preset or instrument zone, the global zone merged in.
+#[derive(Debug, Clone, Default)]
+struct ZoneGenerators {
+    values: HashMap<u16, Generator>,
+}
+
+impl ZoneGenerators {
+    fn get(&self, operator: u16) -> Option<&Generator> {
+        self.values.get(&operator)
+    }
+
+    fn signed_or(&self, operator: u16, default: i32) -> i32 {
+        self.get(operator).map_or(default, Generator::signed)
+    }
+
+    fn range(&self, operator: u16) -> (u8, u8) {
+        self.get(operator).map_or((0, 127), Generator::range)
+    }
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&self, offset: usize, length: usize) -> Result<&'a [u8], SamplerError> {
+        self.data
+            .get(offset..offset + length)
+            .ok_or(SamplerError::InvalidFile("Truncated SF2 chunk"))
+    }
+
+    fn u16(&self, offset: usize) -> Result<u16, SamplerError> {
+        let bytes = self.bytes(offset, 2)?;
+        Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn u32(&self, offset: usize) -> Result<u32, SamplerError> {
+        let bytes = self.bytes(offset, 4)?;
+        Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+}
+
+/// Sub chunks of a RIFF list body by id.
+fn chunks(data: &[u8]) -> Result<Vec<(&[u8], &[u8])>, SamplerError> {
+    let reader = Reader { data };
+    let mut chunks = Vec::new();
+    let mut offset = 0;
+
+    while offset + 8 <= data.len() {
+        let id = reader.bytes(offset, 4)?;
+        let size = reader.u32(offset + 4)? as usize;
+        let body = reader.bytes(offset + 8, size)?;
+
+        chunks.push((id, body));
+
+        // Chunks are padded to an even size
+        offset += 8 + size + (size & 1);
+    }
+
+    Ok(chunks)
+}
+
+fn find<'a>(chunks: &[(&[u8], &'a [u8])], id: &[u8]) -> Result<&'a [u8], SamplerError> {
+    chunks
+        .iter()
+        .find(|(chunk_id, _)| *chunk_id == id)
+        .map(|(_, body)| *body)
+        .ok_or(SamplerError::InvalidFile("Missing SF2 chunk"))
+}
+
+/// Body of the LIST chunk of type `kind`.
+fn list<'a>(chunks: &[(&[u8], &'a [u8])], kind: &[u8]) -> Result<&'a [u8], SamplerError> {
+    chunks
+        .iter()
+        .find(|(id, body)| *id == b"LIST" && body.get(..4) == Some(kind))
+        .map(|&(_, body)| &body[4..])
+        .ok_or(SamplerError::InvalidFile("Missing SF2 list"))
+}
+
+/// Zones of the preset `preset` in bank `bank` of the SF2 file in `data`.
+pub(super) fn parse(data: &[u8], bank: u16, preset: u16) -> Result<Vec<SamplerZone>, SamplerError> {
+    if data.len() < 12 || &data[..4] != b"RIFF" || &data[8..12] != b"sfbk" {
+        return Err(SamplerError::InvalidFile("Not an SF2 file"));
+    }
+
+    let top = chunks(&data[12..])?;
+    let sdta = chunks(list(&top, b"sdta")?)?;
+    let pdta = chunks(list(&top, b"pdta")?)?;
+
+    let smpl = find(&sdta, b"smpl")?;
+    let phdr = Reader { data: find(&pdta, b"phdr")? };
+    let pbag = Reader { data: find(&pdta, b"pbag")? };
+    let pgen = find(&pdta, b"pgen")?;
+    let inst = Reader { data: find(&pdta, b"inst")? };
+    let ibag = Reader { data: find(&pdta, b"ibag")? };
+    let igen = find(&pdta, b"igen")?;
+    let shdr = Reader { data: find(&pdta, b"shdr")? };
+
+    let pgen = generators(pgen);
+    let igen = generators(igen);
+
+    // The last record of every list is a terminator
+    let preset_count = (phdr.data.len() / 38).saturating_sub(1);
+    let Some(preset_index) = (0..preset_count).find(|index| {
+        phdr.u16(index * 38 + 20).ok() == Some(preset) && phdr.u16(index * 38 + 22).ok() == Some(bank)
+    }) else {
+        return Err(SamplerError::PresetNotFound(bank, preset));
+    };
+
+    let bag_start = phdr.u16(preset_index * 38 + 24)? as usize;
+    let bag_end = phdr.u16(preset_index * 38 + 38 + 24)? as usize;
+    let preset_zones = zones(&pbag, &pgen, bag_start, bag_end, GEN_INSTRUMENT)?;
+
+    let mut samples = HashMap::<usize, Sample>::new();
+    let mut result = Vec::new();
+
+    for preset_zone in preset_zones.iter() {
+        let Some(instrument) = preset_zone.get(GEN_INSTRUMENT).map(Generator::unsigned) else {
+            continue;
+        };
+
+        let bag_start = inst.u16(instrument * 22 + 20)? as usize;
+        let bag_end = inst.u16(instrument * 22 + 22 + 20)? as usize;
+        let instrument_zones = zones(&ibag, &igen, bag_start, bag_end, GEN_SAMPLE_ID)?;
+
+        for zone in instrument_zones.iter() {
+            let Some(sample_id) = zone.get(GEN_SAMPLE_ID).map(Generator::unsigned) else {
+                continue;
+            };
+
+            let header = sample_header(&shdr, sample_id)?;
+            if header.end <= header.start || header.end * 2 > smpl.len() || header.sample_rate == 0 {
+                continue;
+            }
+
+            let sample = match samples.get(&sample_id) {
+                Some(sample) => sample.clone(),
+                None => {
+                    let sample = load_sample(smpl, &header)?;
+                    samples.insert(sample_id, sample.clone());
+                    sample
+                }
+            };
+
+            if let Some(zone) = build_zone(sample, &header, preset_zone, zone) {
+                result.push(zone);
+            }
+        }
+    }
+
+    Ok(result)
+}
+
+fn generators(data: &[u8]) -> Vec<Generator> {
+    data.chunks_exact(4)
+        .map(|record| Generator {
+            operator: u16::from_le_bytes([record[0], record[1]]),
+            amount: [record[2], record[3]],
+        })
+        .collect()
+}
+
+/// Zones of the bags `start..end`, each with the global zone merged in. A first zone that
+/// does not end with `terminal` is the global zone.
+fn zones(
+    bags: &Reader,
+    generators: &[Generator],
+    start: usize,
+    end: usize,
+    terminal: u16,
+) -> Result<Vec<ZoneGenerators>, SamplerError> {
+    let mut global = ZoneGenerators::default();
+    let mut result = Vec::new();
+
+    for bag in start..end {
+        let generator_start = bags.u16(bag * 4)? as usize;
+        let generator_end = bags.u16(bag * 4 + 4)? as usize;
+
+        let Some(list) = generators.get(generator_start..generator_end) else {
+            return Err(SamplerError::InvalidFile("SF2 zone out of range"));
+        };
+
+        let is_global = bag == start && list.last().is_none_or(|last| last.operator != terminal);
+        if is_global {
+            global.values.extend(list.iter().map(|generator| (generator.operator, *generator)));
+            continue;
+        }
+
+        let mut zone = global.clone();
+        zone.values.extend(list.iter().map(|generator| (generator.operator, *generator)));
+        result.push(zone);
+    }
+
+    Ok(result)
+}
+
+fn sample_header(shdr: &Reader, index: usize) -> Result<SampleHeader, SamplerError> {
+    let offset = index * 46;
+
+    Ok(SampleHeader {
+        start: shdr.u32(offset + 20)? as usize,
+        end: shdr.u32(offset + 24)? as usize,
+        loop_start: shdr.u32(offset + 28)? as usize,
+        loop_end: shdr.u32(offset + 32)? as usize,
+        sample_rate: shdr.u32(offset + 36)?,
+        original_pitch: shdr.bytes(offset + 40, 1)?[0],
+        pitch_correction: shdr.bytes(offset + 41, 1)?[0] as i8,
+    })
+}
+
+fn load_sample(smpl: &[u8], header: &SampleHeader) -> Result<Sample, SamplerError> {
+    let pcm = smpl[header.start * 2..header.end * 2]
+        .chunks_exact(2)
+        .map(|bytes| i16::from_le_bytes([bytes[0], bytes[1]]) as f32 / 32768.0)
+        .collect::<Vec<_>>();
+
+    crate::create_sample(SampleInfo {
+        source: Source::Buffer(BufferInfo {
+            data: &pcm,
+            channels: 1,
+            sample_rate: header.sample_rate as f32,
+        }),
+        ..Default::default()
+    })
+    .map_err(|e| SamplerError::SampleLoad(format!("sample {}", header.start), e.to_string()))
+}
+
+/// Zone from an instrument zone and the preset zone it was reached from, the preset adds to
+/// the pitch, level, pan and envelope times and narrows the ranges.
+fn build_zone(
+    sample: Sample,
+    header: &SampleHeader,
+    preset: &ZoneGenerators,
+    instrument: &ZoneGenerators,
+) -> Option<SamplerZone> {
+    let intersect = |operator: u16| {
+        let (a, b) = (preset.range(operator), instrument.range(operator));
+        let range = (a.0.max(b.0), a.1.min(b.1));
+        (range.0 <= range.1).then_some(range)
+    };
+
+    let sum = |operator: u16, default: i32| {
+        preset.signed_or(operator, 0) + instrument.signed_or(operator, default)
+    };
+
+    let mut zone = SamplerZone::new(sample);
+    zone.key_range = intersect(GEN_KEY_RANGE)?;
+    zone.velocity_range = intersect(GEN_VEL_RANGE)?;
+
+    zone.root_key = match instrument.signed_or(GEN_OVERRIDING_ROOT_KEY, -1) {
+        key @ 0..=127 => key as u8,
+        _ if header.original_pitch <= 127 => header.original_pitch,
+        _ => 60,
+    };
+
+    zone.tune = (sum(GEN_COARSE_TUNE, 0) * 100
+        + sum(GEN_FINE_TUNE, 0)
+        + header.pitch_correction as i32) as f32;
+
+    // Attenuation is in centibels and pan in tenths of a percent
+    zone.volume = -(sum(GEN_INITIAL_ATTENUATION, 0).max(0) as f32) / 10.0;
+    zone.pan = (sum(GEN_PAN, 0) as f32 / 500.0).clamp(-1.0, 1.0);
+
+    let seconds = |timecents: i32| 2f32.powf(timecents as f32 / 1200.0);
+    zone.attack = seconds(sum(GEN_ATTACK_VOL_ENV, DEFAULT_TIMECENTS));
+    zone.release = seconds(sum(GEN_RELEASE_VOL_ENV, DEFAULT_TIMECENTS));
+
+    zone.loop_mode = match instrument.signed_or(GEN_SAMPLE_MODES, 0) & 3 {
+        1 => LoopMode::Continuous,
+        3 => LoopMode::Sustain,
+        _ => LoopMode::NoLoop,
+    };
+
+    let loop_start = header.loop_start.saturating_sub(header.start);
+    let loop_end = header.loop_end.saturating_sub(header.start);
+    zone.loop_points = Some((loop_start, loop_end));
+
+    Some(zone)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn riff_chunk(id: &[u8], body: &[u8]) -> Vec<u8> {
+        let mut chunk = id.to_vec();
+        chunk.extend_from_slice(&(body.len() as u32).to_le_bytes());
+        chunk.extend_from_slice(body);
+
+        if body.len() % 2 == 1 {
+            chunk.push(0);
+        }
+
+        chunk
+    }
+
+    fn riff_list(kind: &[u8], chunks: &[Vec<u8>]) -> Vec<u8> {
+        let mut body = kind.to_vec();
+        for chunk in chunks {
+            body.extend_from_slice(chunk);
+        }
+
+        riff_chunk(b"LIST", &body)
+    }
+
+    fn records(records: &[&[u8]]) -> Vec<u8> {
+        records.concat()
+    }
+
+    fn generator(operator: u16, amount: [u8; 2]) -> Vec<u8> {
+        [&operator.to_le_bytes()[..], &amount[..]].concat()
+    }
+
+    fn amount(value: i16) -> [u8; 2] {
+        value.to_le_bytes()
+    }
+
+    fn bag(generator: u16) -> Vec<u8> {
+        [generator.to_le_bytes(), 0u16.to_le_bytes()].concat()
+    }
+
+    fn preset_header(preset: u16, bank: u16, bag: u16) -> Vec<u8> {
+        let mut header = vec![0u8; 20];
+        header.extend_from_slice(&preset.to_le_bytes());
+        header.extend_from_slice(&bank.to_le_bytes());
+        header.extend_from_slice(&bag.to_le_bytes());
+        header.extend_from_slice(&[0u8; 12]);
+        header
+    }
+
+    fn instrument_header(bag: u16) -> Vec<u8> {
+        let mut header = vec![0u8; 20];
+        header.extend_from_slice(&bag.to_le_bytes());
+        header
+    }
+
+    fn sample_header(start: u32, end: u32, loop_points: (u32, u32), pitch: (u8, i8)) -> Vec<u8> {
+        let mut header = vec![0u8; 20];
+        for value in [start, end, loop_points.0, loop_points.1, 22050] {
+            header.extend_from_slice(&value.to_le_bytes());
+        }
+
+        header.extend_from_slice(&[pitch.0, pitch.1 as u8, 0, 0, 1, 0]);
+        header
+    }
+
+    // Preset 5 of bank 1: a global zone with 6 dB of attenuation and a zone over keys 40 to 80
+    // tuned up a semitone, playing an instrument zone over keys 60 to 100 of a 4 frame sample
+    fn soundfont() -> Vec<u8> {
+        let smpl = [0i16, 16384, -16384, 32767, 0, 0, 0, 0]
+            .iter()
+            .flat_map(|sample| sample.to_le_bytes())
+            .collect::<Vec<_>>();
+
+        let pdta = [
+            riff_chunk(
+                b"phdr",
+                &records(&[&preset_header(5, 1, 0), &preset_header(0, 0, 2)]),
+            ),
+            riff_chunk(b"pbag", &records(&[&bag(0), &bag(1), &bag(4)])),
+            riff_chunk(
+                b"pgen",
+                &records(&[
+                    &generator(GEN_INITIAL_ATTENUATION, amount(60)),
+                    &generator(GEN_KEY_RANGE, [40, 80]),
+                    &generator(GEN_COARSE_TUNE, amount(1)),
+                    &generator(GEN_INSTRUMENT, amount(0)),
+                    &generator(0, amount(0)),
+                ]),
+            ),
+            riff_chunk(b"pmod", &[0u8; 10]),
+            riff_chunk(
+                b"inst",
+                &records(&[&instrument_header(0), &instrument_header(1)]),
+            ),
+            riff_chunk(b"ibag", &records(&[&bag(0), &bag(5)])),
+            riff_chunk(
+                b"igen",
+                &records(&[
+                    &generator(GEN_KEY_RANGE, [60, 100]),
+                    &generator(GEN_VEL_RANGE, [10, 120]),
+                    &generator(GEN_SAMPLE_MODES, amount(1)),
+                    &generator(GEN_PAN, amount(250)),
+                    &generator(GEN_SAMPLE_ID, amount(0)),
+                    &generator(0, amount(0)),
+                ]),
+            ),
+            riff_chunk(b"imod", &[0u8; 10]),
+            riff_chunk(
+                b"shdr",
+                &records(&[&sample_header(0, 4, (1, 3), (64, -5)), &[0u8; 46]]),
+            ),
+        ];
+
+        let body = [
+            riff_list(b"sdta", &[riff_chunk(b"smpl", &smpl)]),
+            riff_list(b"pdta", &pdta),
+        ]
+        .concat();
+
+        let mut data = b"RIFF".to_vec();
+        data.extend_from_slice(&(body.len() as u32 + 4).to_le_bytes());
+        data.extend_from_slice(b"sfbk");
+        data.extend_from_slice(&body);
+        data
+    }
+
+    #[test]
+    fn test_sf2_zone() {
+        let zones = parse(&soundfont(), 1, 5).unwrap();
+        assert_eq!(zones.len(), 1);
+
+        let zone = &zones[0];
+        assert_eq!(zone.key_range, (60, 80));
+        assert_eq!(zone.velocity_range, (10, 120));
+        assert_eq!(zone.root_key, 64);
+        assert_eq!(zone.tune, 95.0);
+        assert_eq!(zone.volume, -6.0);
+        assert_eq!(zone.pan, 0.5);
+        assert_eq!(zone.loop_mode, LoopMode::Continuous);
+        assert_eq!(zone.loop_points, Some((1, 3)));
+        assert!((zone.attack - 2f32.powi(-10)).abs() < 1e-6);
+        assert!((zone.release - 2f32.powi(-10)).abs() < 1e-6);
+
+        assert_eq!(zone.sample.sample_rate, 22050.0);
+        assert_eq!(zone.sample.pcm(), &[0.0, 0.5, -0.5, 32767.0 / 32768.0]);
+    }
+
+    #[test]
+    fn test_sf2_missing_preset() {
+        let result = parse(&soundfont(), 1, 6);
+        assert!(matches!(result, Err(SamplerError::PresetNotFound(1, 6))));
+    }
+
+    #[test]
+    fn test_sf2_invalid_file() {
+        let result = parse(b"RIFF\0\0\0\0WAVEfmt ", 0, 0);
+        assert!(matches!(result, Err(SamplerError::InvalidFile(_))));
+
+        // A truncated file is rejected instead of read past its end
+        let mut data = soundfont();
+        data.truncate(data.len() - 20);
+        assert!(parse(&data, 1, 5).is_err());
+    }
+}
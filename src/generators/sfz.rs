@@ -0,0 +1,262 @@
+use std::{collections::HashMap, path::Path};
+
+use crate::{Sample, SampleInfo, Source};
+
+use super::sampler::{LoopMode, SamplerError, SamplerZone};
+
+type Opcodes = HashMap<String, String>;
+
+/// Headers an opcode is inherited through, outermost first.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Level {
+    Control,
+    Global,
+    Master,
+    Group,
+    Region,
+    // Headers this loader does not know, their opcodes are skipped
+    Other,
+}
+
+#[derive(Debug, Default)]
+struct Parser {
+    default_path: String,
+    global: Opcodes,
+    master: Opcodes,
+    group: Opcodes,
+    region: Option<Opcodes>,
+    regions: Vec<Opcodes>,
+}
+
+impl Parser {
+    fn header(&mut self, name: &str) -> Level {
+        self.end_region();
+
+        match name {
+            "control" => Level::Control,
+            "global" => {
+                self.global.clear();
+                self.master.clear();
+                self.group.clear();
+                Level::Global
+            }
+            "master" => {
+                self.master.clear();
+                self.group.clear();
+                Level::Master
+            }
+            "group" => {
+                self.group.clear();
+                Level::Group
+            }
+            "region" => {
+                self.region = Some(Opcodes::new());
+                Level::Region
+            }
+            _ => Level::Other,
+        }
+    }
+
+    fn opcode(&mut self, level: Level, key: &str, value: &str) {
+        let opcodes = match level {
+            Level::Control => {
+                if key == "default_path" {
+                    self.default_path = value.replace('\\', "/");
+                }
+                return;
+            }
+            Level::Global => &mut self.global,
+            Level::Master => &mut self.master,
+            Level::Group => &mut self.group,
+            Level::Region => self.region.get_or_insert_default(),
+            Level::Other => return,
+        };
+
+        opcodes.insert(key.to_string(), value.to_string());
+    }
+
+    fn end_region(&mut self) {
+        let Some(region) = self.region.take() else {
+            return;
+        };
+
+        let mut opcodes = self.global.clone();
+        opcodes.extend(self.master.clone());
+        opcodes.extend(self.group.clone());
+        opcodes.extend(region);
+
+        self.regions.push(opcodes);
+    }
+}
+
+/// Zones of the regions in the SFZ file at `path`.
+pub(super) fn parse(path: &str) -> Result<Vec<SamplerZone>, SamplerError> {
+    let text = std::fs::read_to_string(path).map_err(|e| SamplerError::Io(path.to_string(), e))?;
+    let directory = Path::new(path).parent().unwrap_or(Path::new(""));
+
+    let mut parser = Parser::default();
+    let mut level = Level::Other;
+
+    for line in text.lines() {
+        let line = line.split("//").next().unwrap_or("");
+        let mut rest = line;
+
+        while !rest.trim().is_empty() {
+            // Opcodes up to the next header
+            let (opcodes, after) = match rest.find('<') {
+                Some(start) => rest.split_at(start),
+                None => (rest, ""),
+            };
+
+            for (key, value) in split_opcodes(opcodes) {
+                parser.opcode(level, key, value);
+            }
+
+            if after.is_empty() {
+                break;
+            }
+
+            let Some(end) = after.find('>') else {
+                return Err(SamplerError::InvalidFile("Unterminated SFZ header"));
+            };
+
+            level = parser.header(after[1..end].trim());
+            rest = &after[end + 1..];
+        }
+    }
+
+    parser.end_region();
+
+    let mut samples = HashMap::<String, Sample>::new();
+    let mut zones = Vec::with_capacity(parser.regions.len());
+
+    for opcodes in parser.regions.iter() {
+        let Some(name) = opcodes.get("sample") else {
+            continue;
+        };
+
+        let sample_path = directory
+            .join(&parser.default_path)
+            .join(name.replace('\\', "/"))
+            .to_string_lossy()
+            .into_owned();
+
+        let sample = match samples.get(&sample_path) {
+            Some(sample) => sample.clone(),
+            None => {
+                let sample = crate::create_sample(SampleInfo {
+                    source: Source::Path(&sample_path),
+                    ..Default::default()
+                })
+                .map_err(|e| SamplerError::SampleLoad(sample_path.clone(), e.to_string()))?;
+
+                samples.insert(sample_path.clone(), sample.clone());
+                sample
+            }
+        };
+
+        zones.push(region_zone(sample, opcodes));
+    }
+
+    Ok(zones)
+}
+
+fn region_zone(sample: Sample, opcodes: &Opcodes) -> SamplerZone {
+    let get = |key: &str| opcodes.get(key).map(String::as_str);
+    let number = |key: &str| get(key).and_then(|value| value.parse::<f32>().ok());
+    let note = |key: &str| get(key).and_then(parse_note);
+
+    let mut zone = SamplerZone::new(sample);
+
+    if let Some(key) = note("key") {
+        zone.key_range = (key, key);
+        zone.root_key = key;
+    }
+
+    zone.key_range.0 = note("lokey").unwrap_or(zone.key_range.0);
+    zone.key_range.1 = note("hikey").unwrap_or(zone.key_range.1);
+    zone.root_key = note("pitch_keycenter").unwrap_or(zone.root_key);
+
+    let velocity = |key: &str| number(key).map(|value| value.clamp(0.0, 127.0) as u8);
+    zone.velocity_range.0 = velocity("lovel").unwrap_or(zone.velocity_range.0);
+    zone.velocity_range.1 = velocity("hivel").unwrap_or(zone.velocity_range.1);
+
+    zone.tune = number("tune").unwrap_or(0.0) + number("transpose").unwrap_or(0.0) * 100.0;
+    zone.volume = number("volume").unwrap_or(0.0);
+    zone.pan = number("pan").unwrap_or(0.0) / 100.0;
+    zone.attack = number("ampeg_attack").unwrap_or(0.0);
+    zone.release = number("ampeg_release").unwrap_or(0.0);
+
+    zone.loop_mode = match get("loop_mode").or(get("loopmode")) {
+        Some("one_shot") => LoopMode::OneShot,
+        Some("loop_continuous") => LoopMode::Continuous,
+        Some("loop_sustain") => LoopMode::Sustain,
+        _ => LoopMode::NoLoop,
+    };
+
+    let loop_start = number("loop_start").or(number("loopstart"));
+    let loop_end = number("loop_end").or(number("loopend"));
+
+    // The SFZ loop end is the last frame of the loop
+    if let (Some(start), Some(end)) = (loop_start, loop_end) {
+        zone.loop_points = Some((start as usize, end as usize + 1));
+    }
+
+    zone
+}
+
+/// `key=value` pairs of a run of opcodes, a value lasts until the next key so sample paths
+/// may hold spaces.
+fn split_opcodes(text: &str) -> Vec<(&str, &str)> {
+    let mut starts = Vec::new();
+
+    for (index, _) in text.match_indices('=') {
+        let key_start = text[..index]
+            .rfind(|c: char| c.is_whitespace())
+            .map_or(0, |position| position + 1);
+
+        if key_start < index {
+            starts.push((key_start, index));
+        }
+    }
+
+    starts
+        .iter()
+        .enumerate()
+        .map(|(position, (key_start, equals))| {
+            let value_end = starts.get(position + 1).map_or(text.len(), |next| next.0);
+            (&text[*key_start..*equals], text[equals + 1..value_end].trim())
+        })
+        .collect()
+}
+
+/// MIDI key from a number or a note name such as `c4` or `f#3`, `c4` being key 60.
+fn parse_note(value: &str) -> Option<u8> {
+    if let Ok(key) = value.parse::<i32>() {
+        return Some(key.clamp(0, 127) as u8);
+    }
+
+    let mut chars = value.chars();
+    let semitone = match chars.next()?.to_ascii_lowercase() {
+        'c' => 0,
+        'd' => 2,
+        'e' => 4,
+        'f' => 5,
+        'g' => 7,
+        'a' => 9,
+        'b' => 11,
+        _ => return None,
+    };
+
+    let rest = chars.as_str();
+    let (accidental, octave) = match rest.chars().next()? {
+        '#' => (1, &rest[1..]),
+        'b' if rest.len() > 1 => (-1, &rest[1..]),
+        _ => (0, rest),
+    };
+
+    let octave = octave.parse::<i32>().ok()?;
+    let key = (octave + 1) * 12 + semitone + accidental;
+
+    Some(key.clamp(0, 127) as u8)
+}
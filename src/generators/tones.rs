@@ -0,0 +1,197 @@
+use std::f32::consts::PI;
+
+use crate::{BufferInfo, Sample, SampleError, SampleInfo, Source};
+
+use super::wavetable::{self, TABLE_SIZE, Waveform};
+
+// Fade at both ends of every DTMF tone so the digits do not click
+const DTMF_FADE_SECS: f32 = 0.005;
+
+const DTMF_ROWS: [f32; 4] = [697.0, 770.0, 852.0, 941.0];
+const DTMF_COLUMNS: [f32; 4] = [1209.0, 1336.0, 1477.0, 1633.0];
+const DTMF_KEYS: [&str; 4] = ["123A", "456B", "789C", "*0#D"];
+
+/// Attack, decay, sustain and release of a beep. Times are in seconds, the sustain is a level
+/// in 0..1 and the release starts once the beep duration is over.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ToneEnvelope {
+    pub attack: f32,
+    pub decay: f32,
+    pub sustain: f32,
+    pub release: f32,
+}
+
+impl Default for ToneEnvelope {
+    fn default() -> Self {
+        Self {
+            attack: 0.005,
+            decay: 0.0,
+            sustain: 1.0,
+            release: 0.02,
+        }
+    }
+}
+
+impl ToneEnvelope {
+    /// Level at `time` seconds into a beep lasting `duration` seconds.
+    fn level(&self, time: f32, duration: f32) -> f32 {
+        let held = |time: f32| {
+            if time < self.attack {
+                time / self.attack
+            } else if time < self.attack + self.decay {
+                1.0 - (1.0 - self.sustain) * (time - self.attack) / self.decay
+            } else {
+                self.sustain
+            }
+        };
+
+        if time < duration {
+            held(time)
+        } else if self.release > 0.0 {
+            held(duration) * (1.0 - (time - duration) / self.release).max(0.0)
+        } else {
+            0.0
+        }
+    }
+}
+
+/// A beep created with [create_beep](crate::create_beep).
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct BeepInfo {
+    pub frequency: f32,
+    /// Seconds until the release starts.
+    pub duration: f32,
+    pub waveform: Waveform,
+    pub volume: f32,
+    pub envelope: ToneEnvelope,
+    pub sample_rate: f32,
+}
+
+impl Default for BeepInfo {
+    fn default() -> Self {
+        Self {
+            frequency: 1000.0,
+            duration: 0.1,
+            waveform: Waveform::Sine,
+            volume: 0.5,
+            envelope: ToneEnvelope::default(),
+            sample_rate: 48000.0,
+        }
+    }
+}
+
+/// Touch tones created with [create_dtmf](crate::create_dtmf).
+#[derive(Debug, Clone)]
+pub struct DtmfInfo<'a> {
+    /// Digits `0` to `9`, `*`, `#` and `A` to `D`, whitespace is skipped and `,` pauses for
+    /// one tone and gap.
+    pub digits: &'a str,
+    /// Seconds every digit sounds.
+    pub tone: f32,
+    /// Seconds of silence after every digit.
+    pub gap: f32,
+    pub volume: f32,
+    pub sample_rate: f32,
+}
+
+impl Default for DtmfInfo<'_> {
+    fn default() -> Self {
+        Self {
+            digits: "",
+            tone: 0.1,
+            gap: 0.05,
+            volume: 0.5,
+            sample_rate: 48000.0,
+        }
+    }
+}
+
+pub(crate) fn create_beep(info: BeepInfo) -> Result<Sample, SampleError> {
+    check_sample_rate(info.sample_rate)?;
+
+    let sample_rate = info.sample_rate;
+    let length = ((info.duration.max(0.0) + info.envelope.release.max(0.0)) * sample_rate) as usize;
+    let table = wavetable::shape(info.waveform);
+    let step = info.frequency.max(0.0) as f64 / sample_rate as f64;
+
+    let pcm = (0..length)
+        .map(|index| {
+            let time = index as f32 / sample_rate;
+            let position = (index as f64 * step).fract() * TABLE_SIZE as f64;
+
+            table[position as usize % TABLE_SIZE]
+                * info.envelope.level(time, info.duration)
+                * info.volume
+        })
+        .collect::<Vec<_>>();
+
+    mono_sample(&pcm, sample_rate)
+}
+
+pub(crate) fn create_dtmf(info: DtmfInfo) -> Result<Sample, SampleError> {
+    check_sample_rate(info.sample_rate)?;
+
+    let sample_rate = info.sample_rate;
+    let tone = (info.tone.max(0.0) * sample_rate) as usize;
+    let gap = (info.gap.max(0.0) * sample_rate) as usize;
+    let fade = ((DTMF_FADE_SECS * sample_rate) as usize).min(tone / 2).max(1);
+
+    let mut pcm = Vec::new();
+
+    for digit in info.digits.chars().filter(|digit| !digit.is_whitespace()) {
+        if digit == ',' {
+            pcm.resize(pcm.len() + tone + gap, 0.0);
+            continue;
+        }
+
+        let Some((low, high)) = dtmf_frequencies(digit) else {
+            return Err(SampleError::InvalidOperation("Invalid DTMF digit"));
+        };
+
+        pcm.extend((0..tone).map(|index| {
+            let time = index as f32 / sample_rate;
+            let edge = index.min(tone - 1 - index);
+            let gain = (edge as f32 / fade as f32).min(1.0) * info.volume * 0.5;
+
+            ((2.0 * PI * low * time).sin() + (2.0 * PI * high * time).sin()) * gain
+        }));
+
+        pcm.resize(pcm.len() + gap, 0.0);
+    }
+
+    mono_sample(&pcm, sample_rate)
+}
+
+fn dtmf_frequencies(digit: char) -> Option<(f32, f32)> {
+    let digit = digit.to_ascii_uppercase();
+
+    DTMF_KEYS.iter().enumerate().find_map(|(row, keys)| {
+        keys.find(digit)
+            .map(|column| (DTMF_ROWS[row], DTMF_COLUMNS[column]))
+    })
+}
+
+fn check_sample_rate(sample_rate: f32) -> Result<(), SampleError> {
+    if !sample_rate.is_finite() || sample_rate < 1.0 {
+        return Err(SampleError::InvalidSampleRate(sample_rate as u32));
+    }
+
+    Ok(())
+}
+
+fn mono_sample(pcm: &[f32], sample_rate: f32) -> Result<Sample, SampleError> {
+    if pcm.is_empty() {
+        return Err(SampleError::InvalidOperation("Generated sound is empty"));
+    }
+
+    crate::create_sample(SampleInfo {
+        source: Source::Buffer(BufferInfo {
+            data: pcm,
+            channels: 1,
+            sample_rate,
+        }),
+        ..Default::default()
+    })
+}
@@ -0,0 +1,345 @@
+use std::{
+    f32::consts::PI,
+    sync::{
+        Arc,
+        atomic::{AtomicU32, Ordering},
+    },
+};
+
+use thiserror::Error;
+
+use crate::{Sample, Source};
+
+use super::generator::AudioGenerator;
+
+/// Samples per table, cycles of other lengths are resampled to it.
+pub(super) const TABLE_SIZE: usize = 2048;
+/// Harmonics the classic shapes are built from, keeps them free of aliasing up to about
+/// 300 Hz at 44.1 kHz and soft above.
+const SHAPE_HARMONICS: usize = 64;
+/// Most unison voices.
+const MAX_UNISON: usize = 16;
+
+#[derive(Debug, Error)]
+pub enum WavetableError {
+    #[error("A wavetable needs at least one table")]
+    Empty,
+    #[error("Invalid cycle length: {0}")]
+    InvalidCycleLength(usize),
+    #[error("Invalid sample rate: {0}")]
+    InvalidSampleRate(f32),
+}
+
+/// Classic single-cycle shapes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[repr(C)]
+pub enum Waveform {
+    Sine = 0,
+    Saw = 1,
+    Square = 2,
+    Triangle = 3,
+}
+
+/// Single-cycle tables a [WavetableSynth] morphs across, first to last.
+#[derive(Debug, Clone)]
+pub struct Wavetable {
+    tables: Vec<Vec<f32>>,
+}
+
+impl Wavetable {
+    /// Tables of classic shapes, band limited to 64 harmonics.
+    pub fn from_waveforms(waveforms: &[Waveform]) -> Result<Self, WavetableError> {
+        let tables = waveforms.iter().map(|waveform| shape(*waveform)).collect();
+        Self::from_tables(tables)
+    }
+
+    /// Tables from single cycles of any length, each resampled to 2048 samples.
+    pub fn from_cycles(cycles: &[&[f32]]) -> Result<Self, WavetableError> {
+        let mut tables = Vec::with_capacity(cycles.len());
+
+        for cycle in cycles {
+            if cycle.is_empty() {
+                return Err(WavetableError::InvalidCycleLength(0));
+            }
+
+            tables.push(resample_cycle(cycle));
+        }
+
+        Self::from_tables(tables)
+    }
+
+    /// Tables cut from the first channel of `sample`, every `cycle_length` frames holding one
+    /// cycle as in the 2048 frame wavetable files of common synths.
+    pub fn from_sample(sample: &Sample, cycle_length: usize) -> Result<Self, WavetableError> {
+        if cycle_length == 0 || cycle_length > sample.pcm().len() / sample.channels {
+            return Err(WavetableError::InvalidCycleLength(cycle_length));
+        }
+
+        let channels = sample.channels;
+        let mono = sample
+            .pcm()
+            .chunks_exact(channels)
+            .map(|frame| frame[0])
+            .collect::<Vec<_>>();
+
+        let tables = mono
+            .chunks_exact(cycle_length)
+            .map(resample_cycle)
+            .collect();
+
+        Self::from_tables(tables)
+    }
+
+    fn from_tables(tables: Vec<Vec<f32>>) -> Result<Self, WavetableError> {
+        if tables.is_empty() {
+            return Err(WavetableError::Empty);
+        }
+
+        Ok(Self { tables })
+    }
+
+    pub fn table_count(&self) -> usize {
+        self.tables.len()
+    }
+
+    /// Value at `phase` in 0..1 with `morph` in 0..1 blending between neighbouring tables.
+    fn lookup(&self, phase: f64, morph: f32) -> f32 {
+        let position = phase * TABLE_SIZE as f64;
+        let index = position as usize % TABLE_SIZE;
+        let next = (index + 1) % TABLE_SIZE;
+        let fraction = (position - position.floor()) as f32;
+
+        let read = |table: &[f32]| table[index] + (table[next] - table[index]) * fraction;
+
+        let table_position = morph.clamp(0.0, 1.0) * (self.tables.len() - 1) as f32;
+        let table = table_position as usize;
+
+        match self.tables.get(table + 1) {
+            Some(upper) => {
+                let blend = table_position - table as f32;
+                let lower = read(&self.tables[table]);
+                lower + (read(upper) - lower) * blend
+            }
+            None => read(&self.tables[table]),
+        }
+    }
+}
+
+pub(super) fn shape(waveform: Waveform) -> Vec<f32> {
+    let harmonic = |n: usize| -> f32 {
+        let alternating = if n % 2 == 1 { 1.0 } else { -1.0 };
+
+        match waveform {
+            Waveform::Sine if n == 1 => 1.0,
+            Waveform::Saw => 2.0 / PI * alternating / n as f32,
+            Waveform::Square if n % 2 == 1 => 4.0 / PI / n as f32,
+            Waveform::Triangle if n % 2 == 1 => {
+                let sign = if (n / 2) % 2 == 0 { 1.0 } else { -1.0 };
+                8.0 / (PI * PI) * sign / (n * n) as f32
+            }
+            _ => 0.0,
+        }
+    };
+
+    let amplitudes = (1..=SHAPE_HARMONICS).map(harmonic).collect::<Vec<_>>();
+
+    (0..TABLE_SIZE)
+        .map(|index| {
+            let phase = 2.0 * PI * index as f32 / TABLE_SIZE as f32;
+            amplitudes
+                .iter()
+                .enumerate()
+                .map(|(n, amplitude)| amplitude * ((n + 1) as f32 * phase).sin())
+                .sum()
+        })
+        .collect()
+}
+
+fn resample_cycle(cycle: &[f32]) -> Vec<f32> {
+    let length = cycle.len();
+
+    (0..TABLE_SIZE)
+        .map(|index| {
+            let position = index as f64 * length as f64 / TABLE_SIZE as f64;
+            let low = position as usize % length;
+            let high = (low + 1) % length;
+            let fraction = (position - position.floor()) as f32;
+
+            cycle[low] + (cycle[high] - cycle[low]) * fraction
+        })
+        .collect()
+}
+
+#[derive(Debug)]
+struct WavetableParams {
+    // f32 bits but the voice count, read once per block
+    frequency: AtomicU32,
+    morph: AtomicU32,
+    unison: AtomicU32,
+    detune: AtomicU32,
+    spread: AtomicU32,
+}
+
+/// A wavetable oscillator playing a [Wavetable] as the source of a [Track](crate::Track)
+/// created from [WavetableSynth::source]. Renders stereo, unison voices are spread across
+/// the stereo field.
+///
+/// The settings apply from the next block of the device.
+#[derive(Debug, Clone)]
+pub struct WavetableSynth {
+    wavetable: Arc<Wavetable>,
+    sample_rate: f32,
+    params: Arc<WavetableParams>,
+}
+
+impl WavetableSynth {
+    /// An oscillator at 440 Hz with a single voice.
+    pub fn new(wavetable: Wavetable, sample_rate: f32) -> Result<Self, WavetableError> {
+        if !sample_rate.is_finite() || sample_rate <= 0.0 {
+            return Err(WavetableError::InvalidSampleRate(sample_rate));
+        }
+
+        let params = WavetableParams {
+            frequency: AtomicU32::new(440f32.to_bits()),
+            morph: AtomicU32::new(0f32.to_bits()),
+            unison: AtomicU32::new(1),
+            detune: AtomicU32::new(0f32.to_bits()),
+            spread: AtomicU32::new(1f32.to_bits()),
+        };
+
+        Ok(Self {
+            wavetable: Arc::new(wavetable),
+            sample_rate,
+            params: Arc::new(params),
+        })
+    }
+
+    /// A new source, every source shares the settings.
+    pub fn source(&self) -> Source<'static> {
+        // Spread the starting phases so unison voices do not start in phase
+        let mut phases = [0.0; MAX_UNISON];
+        for (index, phase) in phases.iter_mut().enumerate() {
+            *phase = (index as f64 * 0.618_034).fract();
+        }
+
+        Source::Generator(Box::new(WavetableSource {
+            wavetable: self.wavetable.clone(),
+            sample_rate: self.sample_rate,
+            params: self.params.clone(),
+            phases,
+        }))
+    }
+
+    pub fn set_frequency(&self, frequency: f32) {
+        let frequency = frequency.clamp(0.0, self.sample_rate / 2.0);
+        self.params.frequency.store(frequency.to_bits(), Ordering::Relaxed);
+    }
+
+    pub fn get_frequency(&self) -> f32 {
+        f32::from_bits(self.params.frequency.load(Ordering::Relaxed))
+    }
+
+    /// Play the MIDI `key`, 69 being 440 Hz.
+    pub fn set_note(&self, key: u8) {
+        self.set_frequency(440.0 * 2f32.powf((key as f32 - 69.0) / 12.0));
+    }
+
+    /// Position across the tables, 0.0 is the first table and 1.0 the last.
+    pub fn set_morph(&self, morph: f32) {
+        self.params.morph.store(morph.clamp(0.0, 1.0).to_bits(), Ordering::Relaxed);
+    }
+
+    pub fn get_morph(&self) -> f32 {
+        f32::from_bits(self.params.morph.load(Ordering::Relaxed))
+    }
+
+    /// Play `voices` detuned copies, up to 16, spread evenly over `detune_cents` either way.
+    pub fn set_unison(&self, voices: usize, detune_cents: f32) {
+        let voices = voices.clamp(1, MAX_UNISON) as u32;
+        self.params.unison.store(voices, Ordering::Relaxed);
+        self.params
+            .detune
+            .store(detune_cents.clamp(0.0, 100.0).to_bits(), Ordering::Relaxed);
+    }
+
+    /// Number of unison voices and their detune in cents.
+    pub fn get_unison(&self) -> (usize, f32) {
+        let voices = self.params.unison.load(Ordering::Relaxed) as usize;
+        let detune = f32::from_bits(self.params.detune.load(Ordering::Relaxed));
+        (voices, detune)
+    }
+
+    /// Stereo width of the unison voices, 0.0 is mono and 1.0, the default, is full width.
+    pub fn set_spread(&self, spread: f32) {
+        self.params.spread.store(spread.clamp(0.0, 1.0).to_bits(), Ordering::Relaxed);
+    }
+
+    pub fn get_spread(&self) -> f32 {
+        f32::from_bits(self.params.spread.load(Ordering::Relaxed))
+    }
+}
+
+/// The audio thread side of a [WavetableSynth].
+struct WavetableSource {
+    wavetable: Arc<Wavetable>,
+    sample_rate: f32,
+    params: Arc<WavetableParams>,
+    phases: [f64; MAX_UNISON],
+}
+
+impl AudioGenerator for WavetableSource {
+    fn channels(&self) -> usize {
+        2
+    }
+
+    fn sample_rate(&self) -> f32 {
+        self.sample_rate
+    }
+
+    fn generate(&mut self, output: &mut [f32]) {
+        let load = |value: &AtomicU32| f32::from_bits(value.load(Ordering::Relaxed));
+
+        let frequency = load(&self.params.frequency);
+        let morph = load(&self.params.morph);
+        let detune = load(&self.params.detune);
+        let spread = load(&self.params.spread);
+        let voices = (self.params.unison.load(Ordering::Relaxed) as usize).clamp(1, MAX_UNISON);
+
+        let mut steps = [0.0f64; MAX_UNISON];
+        let mut gains = [[0.0f32; 2]; MAX_UNISON];
+        let level = 1.0 / (voices as f32).sqrt();
+
+        let targets = steps.iter_mut().zip(gains.iter_mut()).enumerate().take(voices);
+        for (voice, (step, gain)) in targets {
+            // -1..1 across the voices, 0 for a single voice
+            let offset = match voices {
+                1 => 0.0,
+                _ => voice as f32 / (voices - 1) as f32 * 2.0 - 1.0,
+            };
+
+            let cents = offset * detune;
+            *step = frequency as f64 * 2f64.powf(cents as f64 / 1200.0) / self.sample_rate as f64;
+
+            let pan = offset * spread;
+            *gain = [level * (1.0 - pan).min(1.0), level * (1.0 + pan).min(1.0)];
+        }
+
+        for frame in output.chunks_exact_mut(2) {
+            let mut left = 0.0;
+            let mut right = 0.0;
+
+            let voices = self.phases.iter_mut().zip(steps.iter().zip(gains.iter())).take(voices);
+            for (phase, (step, gain)) in voices {
+                let value = self.wavetable.lookup(*phase, morph);
+                left += value * gain[0];
+                right += value * gain[1];
+
+                *phase = (*phase + step).fract();
+            }
+
+            frame[0] = left;
+            frame[1] = right;
+        }
+    }
+}
@@ -6,30 +6,102 @@ pub(crate) mod audioreader;
 pub(crate) mod context;
 pub(crate) mod device;
 pub(crate) mod encoder;
+pub(crate) mod generators;
 pub(crate) mod macros;
 pub(crate) mod misc;
 pub(crate) mod mixer;
 pub(crate) mod sample;
 pub(crate) mod track;
+pub(crate) mod transport;
 
-use std::sync::Arc;
+use std::{sync::Arc, time::Duration};
 use crate::audioreader::cache::AudioCache;
 
-pub use crate::context::{Backend, ContextError, DeviceType, HardwareInfos};
+pub use crate::context::{
+    AudioContext, AudioHardwareInfo, Backend, ContextError, DeviceId, DeviceType, HardwareInfos,
+    watcher::{DEFAULT_WATCH_INTERVAL, DeviceEvent, DeviceWatcher},
+};
 
-pub use crate::device::{Device, DeviceError, DeviceInfo};
+pub use crate::device::{
+    Device, DeviceConfig, DeviceError, DeviceInfo, DspCallback,
+    analyzer::{SpectrumAnalyzer, SpectrumBands},
+    crossfader::{CrossfadeCurve, Crossfader, Deck},
+    debugdump::{GraphDump, GraphNode},
+    ducking::DuckingRule,
+    monitor::InputMonitor,
+    snapshot::{DeviceSnapshot, FXSnapshot, ListenerSnapshot, SourceKind, SourceSnapshot},
+    splitter::Splitter,
+    stats::Stats,
+    tempo::Quantize,
+};
 
-pub use crate::encoder::{Encoder, EncoderError, EncoderInfo, writer::WriteFormat};
+pub use crate::encoder::{
+    Encoder, EncoderError, EncoderInfo,
+    dither::Dither,
+    golden::{GOLDEN_UPDATE_ENV, GoldenDiff, GoldenError, GoldenRender},
+    recorder::RecordTarget,
+    writer::WriteFormat,
+};
+
+pub use crate::generators::{
+    AudioGenerator, BeepInfo, DtmfInfo, Granular, GranularError, Instrument, LoopMode, Sampler,
+    SamplerError, SamplerZone, ToneEnvelope, Waveform, Wavetable, WavetableError, WavetableSynth,
+};
 
-pub use crate::mixer::{Mixer, MixerError, MixerInfo, MixerInput};
+pub use crate::mixer::{
+    Mixer, MixerConfig, MixerEntryInfo, MixerError, MixerInfo, MixerInput, TapPoint,
+    snapshot::{MixerEntrySnapshot, MixerSnapshot},
+    tempomap::{MusicalTime, TempoChange, TempoMap, TempoMapError},
+    transaction::MixerTransaction,
+};
+
+pub use crate::sample::{Sample, SampleAttributes, SampleChannelInfo, SampleError, SampleInfo};
+
+pub use crate::audioreader::{
+    archive::{ArchiveError, AssetSource, PakArchive, PakEntry},
+    decoder::AudioDecoder,
+    stream::{StreamInfo, StreamStatus},
+    transform::AssetTransform,
+};
 
-pub use crate::sample::{Sample, SampleError, SampleInfo};
+#[cfg(feature = "zip")]
+pub use crate::audioreader::archive::ZipArchive;
 
-pub use crate::track::{Track, TrackError, TrackInfo};
+#[cfg(feature = "clap")]
+pub use crate::effects::{ClapDescriptor, ClapError, ClapHandle, ClapParam, ClapPlugin};
+
+#[cfg(feature = "dasp")]
+pub use crate::generators::DaspGenerator;
+
+#[cfg(feature = "rodio")]
+pub use crate::{generators::RodioGenerator, mixer::rodio::MixerSource};
+
+pub use crate::track::{
+    LinkGroup, ScrubMode, SpeedTrainer, StemPlayer, Track, TrackConfig, TrackError, TrackInfo,
+};
+
+pub use crate::transport::{Transport, TransportError, TransportState};
+
+pub use crate::utils::TweenType;
+
+pub use crate::effects::{
+    AmbisonicsDecoder, AudioEffect, Compressor, CompressorSettings, EqBand, EqBandKind, Equalizer,
+    FXSeekMode, HrirMeasurement, HrtfError, HrtfSet, Limiter, ListenerSelection, MidSide,
+    NoiseSuppressor, PanMode, Reverb, ReverbPreset, ReverbZone, RoutingMatrix, RoutingMatrixError,
+    VocalRemover, decode_mid_side, encode_mid_side,
+};
 
 pub use crate::misc::{
+    attributeobserver::AttributeEvent,
     audioattributes::AudioAttributes,
     audiopropertyhandler::{PropertyError, PropertyHandler},
+    customattributes::AttributeValue,
+    envelope::{Envelope, EnvelopeError, Keyframe},
+    finishsignal::{Completion, PlaybackEnd},
+    memoryusage::MemoryUsage,
+    mixingmode::MixingMode,
+    pcmsample::PcmSample,
+    precision::Precision,
 };
 
 #[derive(Debug)]
@@ -57,6 +129,35 @@ pub struct BufferInfoOwned {
 }
 
 impl BufferInfoOwned {
+    /// Interleaved samples of any [PcmSample] format converted to f32, such as the i16 audio
+    /// of codecs and capture APIs.
+    pub fn from_interleaved<T: PcmSample>(data: &[T], channels: usize, sample_rate: f32) -> Self {
+        Self {
+            data: data.iter().map(|sample| sample.to_f32()).collect(),
+            channels,
+            sample_rate,
+        }
+    }
+
+    /// One slice per channel interleaved into f32, cut to the shortest slice.
+    pub fn from_planar<T: PcmSample>(planes: &[&[T]], sample_rate: f32) -> Self {
+        let channels = planes.len();
+        let frames = planes.iter().map(|plane| plane.len()).min().unwrap_or(0);
+
+        let mut data = Vec::with_capacity(frames * channels);
+        for frame in 0..frames {
+            for plane in planes {
+                data.push(plane[frame].to_f32());
+            }
+        }
+
+        Self {
+            data,
+            channels,
+            sample_rate,
+        }
+    }
+
     pub fn get_ref(&self) -> BufferInfo<'_> {
         BufferInfo {
             data: &self.data,
@@ -71,9 +172,18 @@ pub enum Source<'a> {
     #[default]
     None,
     Path(&'a str),
+    /// Like [Source::Path] but a 32-bit float WAV file is memory-mapped and read in place
+    /// instead of copied onto the heap, for very large files. Other files are decoded as with
+    /// [Source::Path]. The file must not change while it is in use.
+    #[cfg(feature = "mmap")]
+    MappedPath(&'a str),
     Memory(&'a [u8]),
+    /// A file read from an [AssetSource] such as a [PakArchive], by its name in the source.
+    Asset(&'a dyn AssetSource, &'a str),
     Stream(Box<dyn std::io::Read + Send>),
     Buffer(BufferInfo<'a>),
+    /// A procedural source, only tracks can play it.
+    Generator(Box<dyn AudioGenerator>),
 }
 
 impl std::fmt::Debug for Source<'_> {
@@ -81,8 +191,12 @@ impl std::fmt::Debug for Source<'_> {
         match self {
             Source::None => write!(f, "Source::None"),
             Source::Path(path) => write!(f, "Source::Path({})", path),
+            #[cfg(feature = "mmap")]
+            Source::MappedPath(path) => write!(f, "Source::MappedPath({})", path),
             Source::Memory(_) => write!(f, "Source::Memory(...)"),
+            Source::Asset(_, name) => write!(f, "Source::Asset(..., {})", name),
             Source::Stream(_) => write!(f, "Source::Stream(...)"),
+            Source::Generator(_) => write!(f, "Source::Generator(...)"),
             Source::Buffer(buffer) => write!(
                 f,
                 "Source::Buffer {{ data: [...], channels: {}, sample_rate: {} }}",
@@ -114,6 +228,36 @@ impl<'a> Source<'a> {
 
                 (Some(cache), None)
             }
+            #[cfg(feature = "mmap")]
+            Source::MappedPath(path) => {
+                let Ok(cache) = cache::load_mapped_cache(path) else {
+                    eprintln!("Failed to map file for path: {}", path);
+                    return (None, None);
+                };
+
+                (Some(cache), None)
+            }
+            Source::Asset(assets, name) => {
+                let mut data = match assets.read(name) {
+                    Ok(data) => data,
+                    Err(e) => {
+                        eprintln!("Failed to read asset {}: {}", name, e);
+                        return (None, None);
+                    }
+                };
+
+                if let Err(e) = audioreader::transform::apply(name, &mut data) {
+                    eprintln!("Failed to transform asset {}: {}", name, e);
+                    return (None, None);
+                }
+
+                let Ok(cache) = cache::load_buffer_cache(data.as_slice()) else {
+                    eprintln!("Failed to load buffer cache for asset: {}", name);
+                    return (None, None);
+                };
+
+                (Some(cache), None)
+            }
             Source::Stream(mut stream) => {
                 let mut buf = Vec::new();
                 if let Err(e) = stream.read_to_end(&mut buf) {
@@ -128,7 +272,7 @@ impl<'a> Source<'a> {
 
                 (Some(cache), None)
             }
-            Source::None => (None, None),
+            Source::None | Source::Generator(_) => (None, None),
         }
     }
 }
@@ -137,6 +281,77 @@ pub fn enumerate_devices(backends: &[Backend]) -> Result<HardwareInfos, ContextE
     context::enumerable(backends)
 }
 
+/// Call `callback` with the devices and what changed whenever audio hardware is added or
+/// removed or the default device changes, such as to refresh a device picker. The devices are
+/// enumerated again every [DEFAULT_WATCH_INTERVAL] on a background thread the callback runs
+/// on, until the returned watcher is dropped.
+pub fn watch_devices<F>(callback: F) -> Result<DeviceWatcher, ContextError>
+where
+    F: FnMut(&HardwareInfos, &[DeviceEvent]) + Send + 'static,
+{
+    context::watcher::watch(&[], DEFAULT_WATCH_INTERVAL, callback)
+}
+
+/// Like [watch_devices] with the backends to enumerate and how often.
+pub fn watch_devices_ex<F>(
+    backends: &[Backend],
+    interval: Duration,
+    callback: F,
+) -> Result<DeviceWatcher, ContextError>
+where
+    F: FnMut(&HardwareInfos, &[DeviceEvent]) + Send + 'static,
+{
+    context::watcher::watch(backends, interval, callback)
+}
+
+/// Keep up to `bytes` of decoded audio of files and buffers no sample or track uses anymore,
+/// so creating them again from the same file does not decode it again. Least recently used
+/// audio is freed first once over the budget, the default of 0 frees audio once unused.
+pub fn set_decode_cache_budget(bytes: usize) {
+    audioreader::cache::set_cache_budget(bytes)
+}
+
+pub fn get_decode_cache_budget() -> usize {
+    audioreader::cache::cache_budget()
+}
+
+/// Bytes of decoded audio kept by [set_decode_cache_budget] that is not in use.
+pub fn get_decode_cache_idle_bytes() -> usize {
+    audioreader::cache::idle_cache_bytes()
+}
+
+/// Free the decoded audio kept by [set_decode_cache_budget] that is not in use.
+pub fn clear_decode_cache() {
+    audioreader::cache::clear_cache()
+}
+
+/// Register a hook rewriting the bytes of every audio file read from a path or an
+/// [AssetSource] before it is decoded, such as decrypting assets stored encrypted. The hook is
+/// given the path or asset name. None removes it.
+///
+/// While a hook is set, files are always read and decoded whole, streaming and memory mapping
+/// fall back to it.
+pub fn set_asset_transform(transform: Option<AssetTransform>) {
+    audioreader::transform::set_asset_transform(transform)
+}
+
+/// Register a decoder for a file format, probed ahead of the registered ones. Built in are
+/// Ogg Vorbis with the `vorbis` feature and Ogg Opus with the `opus` feature, files no
+/// decoder reads are left to miniaudio, which reads WAV, FLAC and MP3.
+pub fn register_decoder(decoder: Arc<dyn AudioDecoder>) {
+    audioreader::decoder::register_decoder(decoder)
+}
+
+/// Remove the decoder named `name`, returns whether one was registered.
+pub fn unregister_decoder(name: &str) -> bool {
+    audioreader::decoder::unregister_decoder(name)
+}
+
+/// Names of the registered decoders, in the order they are probed.
+pub fn get_decoders() -> Vec<String> {
+    audioreader::decoder::decoder_names()
+}
+
 pub fn create_device(
     config: DeviceInfo,
 ) -> Result<Device, DeviceError> {
@@ -147,10 +362,25 @@ pub fn create_sample(config: SampleInfo) -> Result<Sample, SampleError> {
     Sample::new(config)
 }
 
+/// Synthesize a beep into a sample, for UI sounds without asset files.
+pub fn create_beep(config: BeepInfo) -> Result<Sample, SampleError> {
+    generators::create_beep(config)
+}
+
+/// Synthesize a run of DTMF touch tones into a sample.
+pub fn create_dtmf(config: DtmfInfo) -> Result<Sample, SampleError> {
+    generators::create_dtmf(config)
+}
+
 pub fn create_track(config: TrackInfo) -> Result<Track, TrackError> {
     Track::new(config)
 }
 
+/// Load the aligned files of a multitrack as the stems of one player, see [StemPlayer].
+pub fn create_stem_player(paths: &[&str]) -> Result<StemPlayer, TrackError> {
+    StemPlayer::new(paths)
+}
+
 pub fn create_encoder(config: EncoderInfo) -> Result<Encoder, EncoderError> {
     Encoder::new(config)
 }
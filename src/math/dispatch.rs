@@ -0,0 +1,244 @@
+// Runtime selected f32 kernels for the hot mixing paths.
+//
+// `wide` picks its backend at compile time, so a default x86_64 build only gets SSE2.
+// The kernels here are chosen once on first use based on what the running CPU supports.
+
+use once_cell::sync::Lazy;
+
+use super::{AddUtil, CopyUtil, MulUtil, SetUtil, SimdAdd, SimdCopy, SimdMul, SimdSet};
+
+pub(crate) struct F32Kernels {
+    pub add: fn(&mut [f32], &[f32]),
+    pub mul: fn(&mut [f32], &[f32]),
+    pub copy: fn(&[f32], &mut [f32]),
+    pub set: fn(&mut [f32], f32),
+}
+
+pub(crate) static F32_KERNELS: Lazy<F32Kernels> = Lazy::new(detect);
+
+fn detect() -> F32Kernels {
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    {
+        if std::is_x86_feature_detected!("avx") {
+            return F32Kernels {
+                add: avx::add,
+                mul: avx::mul,
+                copy: avx::copy,
+                set: avx::set,
+            };
+        }
+    }
+
+    F32Kernels {
+        add: <AddUtil as SimdAdd<f32>>::simd_add,
+        mul: <MulUtil as SimdMul<f32>>::simd_mul,
+        copy: <CopyUtil as SimdCopy<f32>>::simd_copy,
+        set: <SetUtil as SimdSet<f32>>::simd_set,
+    }
+}
+
+#[inline(always)]
+pub(crate) fn simd_add_f32(array: &mut [f32], value: &[f32]) {
+    (F32_KERNELS.add)(array, value)
+}
+
+#[inline(always)]
+pub(crate) fn simd_mul_f32(array: &mut [f32], value: &[f32]) {
+    (F32_KERNELS.mul)(array, value)
+}
+
+#[inline(always)]
+pub(crate) fn simd_copy_f32(src: &[f32], dst: &mut [f32]) {
+    (F32_KERNELS.copy)(src, dst)
+}
+
+#[inline(always)]
+pub(crate) fn simd_set_f32(array: &mut [f32], value: f32) {
+    (F32_KERNELS.set)(array, value)
+}
+
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+mod avx {
+    #[cfg(target_arch = "x86")]
+    use std::arch::x86::*;
+    #[cfg(target_arch = "x86_64")]
+    use std::arch::x86_64::*;
+
+    const LANES: usize = 8;
+
+    // The safe wrappers below are only installed after `is_x86_feature_detected!("avx")`.
+
+    pub fn add(array: &mut [f32], value: &[f32]) {
+        if array.len() != value.len() {
+            panic!("Input and value arrays must have the same length");
+        }
+
+        unsafe { add_avx(array, value) }
+    }
+
+    pub fn mul(array: &mut [f32], value: &[f32]) {
+        if array.len() != value.len() {
+            panic!("Input and value arrays must have the same length");
+        }
+
+        unsafe { mul_avx(array, value) }
+    }
+
+    pub fn copy(src: &[f32], dst: &mut [f32]) {
+        assert_eq!(src.len(), dst.len());
+
+        unsafe { copy_avx(src, dst) }
+    }
+
+    pub fn set(array: &mut [f32], value: f32) {
+        unsafe { set_avx(array, value) }
+    }
+
+    #[target_feature(enable = "avx")]
+    unsafe fn add_avx(array: &mut [f32], value: &[f32]) {
+        let len = array.len();
+        let mut i = 0;
+
+        while i + LANES <= len {
+            unsafe {
+                let a = _mm256_loadu_ps(array.as_ptr().add(i));
+                let b = _mm256_loadu_ps(value.as_ptr().add(i));
+                _mm256_storeu_ps(array.as_mut_ptr().add(i), _mm256_add_ps(a, b));
+            }
+
+            i += LANES;
+        }
+
+        while i < len {
+            array[i] += value[i];
+            i += 1;
+        }
+    }
+
+    #[target_feature(enable = "avx")]
+    unsafe fn mul_avx(array: &mut [f32], value: &[f32]) {
+        let len = array.len();
+        let mut i = 0;
+
+        while i + LANES <= len {
+            unsafe {
+                let a = _mm256_loadu_ps(array.as_ptr().add(i));
+                let b = _mm256_loadu_ps(value.as_ptr().add(i));
+                _mm256_storeu_ps(array.as_mut_ptr().add(i), _mm256_mul_ps(a, b));
+            }
+
+            i += LANES;
+        }
+
+        while i < len {
+            array[i] *= value[i];
+            i += 1;
+        }
+    }
+
+    #[target_feature(enable = "avx")]
+    unsafe fn copy_avx(src: &[f32], dst: &mut [f32]) {
+        let len = src.len();
+        let mut i = 0;
+
+        while i + LANES <= len {
+            unsafe {
+                let a = _mm256_loadu_ps(src.as_ptr().add(i));
+                _mm256_storeu_ps(dst.as_mut_ptr().add(i), a);
+            }
+
+            i += LANES;
+        }
+
+        dst[i..].copy_from_slice(&src[i..]);
+    }
+
+    #[target_feature(enable = "avx")]
+    unsafe fn set_avx(array: &mut [f32], value: f32) {
+        let len = array.len();
+        let mut i = 0;
+
+        while i + LANES <= len {
+            unsafe {
+                _mm256_storeu_ps(array.as_mut_ptr().add(i), _mm256_set1_ps(value));
+            }
+
+            i += LANES;
+        }
+
+        array[i..].fill(value);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::GoldenRender;
+
+    // Long enough to cover the 8 lane loop and every length of the remainder
+    const MAX_LEN: usize = 40;
+
+    fn signal(len: usize, seed: f32) -> Vec<f32> {
+        (0..len).map(|i| ((i as f32 + seed) * 0.37).sin()).collect()
+    }
+
+    // Compared through the golden harness, the kernels have to agree bit for bit
+    fn assert_identical(actual: Vec<f32>, expected: Vec<f32>) {
+        let actual = GoldenRender::new(actual, 1, 48000.0);
+        let expected = GoldenRender::new(expected, 1, 48000.0);
+
+        let diff = actual.compare(&expected, 0.0).unwrap();
+        assert!(diff.matches(), "off by up to {}", diff.max_error);
+        assert_eq!(actual.hash(), expected.hash());
+    }
+
+    fn check_kernels(kernels: &F32Kernels) {
+        for len in 0..=MAX_LEN {
+            let value = signal(len, 100.0);
+
+            let mut actual = signal(len, 0.0);
+            let mut expected = actual.clone();
+            (kernels.add)(&mut actual, &value);
+            <AddUtil as SimdAdd<f32>>::simd_add(&mut expected, &value);
+            assert_identical(actual, expected);
+
+            let mut actual = signal(len, 0.0);
+            let mut expected = actual.clone();
+            (kernels.mul)(&mut actual, &value);
+            <MulUtil as SimdMul<f32>>::simd_mul(&mut expected, &value);
+            assert_identical(actual, expected);
+
+            let mut actual = vec![0.0; len];
+            let mut expected = vec![0.0; len];
+            (kernels.copy)(&value, &mut actual);
+            <CopyUtil as SimdCopy<f32>>::simd_copy(&value, &mut expected);
+            assert_identical(actual, expected);
+
+            let mut actual = vec![0.0; len];
+            let mut expected = vec![0.0; len];
+            (kernels.set)(&mut actual, 0.25);
+            <SetUtil as SimdSet<f32>>::simd_set(&mut expected, 0.25);
+            assert_identical(actual, expected);
+        }
+    }
+
+    #[test]
+    fn test_detected_kernels_match_scalar() {
+        check_kernels(&F32_KERNELS);
+    }
+
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    #[test]
+    fn test_avx_kernels_match_scalar() {
+        if !std::is_x86_feature_detected!("avx") {
+            return;
+        }
+
+        check_kernels(&F32Kernels {
+            add: avx::add,
+            mul: avx::mul,
+            copy: avx::copy,
+            set: avx::set,
+        });
+    }
+}
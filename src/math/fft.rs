@@ -0,0 +1,137 @@
+use std::f32::consts::PI;
+
+/// In place iterative radix 2 FFT of a fixed power of two size.
+pub(crate) struct Fft {
+    pub real: Vec<f32>,
+    pub imag: Vec<f32>,
+    cos: Vec<f32>,
+    sin: Vec<f32>,
+}
+
+impl Fft {
+    pub fn new(size: usize) -> Self {
+        let (sin, cos): (Vec<f32>, Vec<f32>) = (0..size / 2)
+            .map(|index| (-2.0 * PI * index as f32 / size as f32).sin_cos())
+            .unzip();
+
+        Self {
+            real: vec![0.0; size],
+            imag: vec![0.0; size],
+            cos,
+            sin,
+        }
+    }
+
+    pub fn size(&self) -> usize {
+        self.real.len()
+    }
+
+    /// Inverse transform, scaled by 1 / size so a forward then inverse round trips.
+    pub fn inverse(&mut self) {
+        let scale = 1.0 / self.real.len() as f32;
+
+        self.imag.iter_mut().for_each(|imag| *imag = -*imag);
+        self.forward();
+
+        self.real.iter_mut().for_each(|real| *real *= scale);
+        self.imag.iter_mut().for_each(|imag| *imag *= -scale);
+    }
+
+    pub fn forward(&mut self) {
+        let size = self.real.len();
+        let bits = size.trailing_zeros();
+
+        for index in 0..size {
+            let reversed = index.reverse_bits() >> (usize::BITS - bits);
+            if reversed > index {
+                self.real.swap(index, reversed);
+                self.imag.swap(index, reversed);
+            }
+        }
+
+        let mut length = 2;
+        while length <= size {
+            let half = length / 2;
+            let stride = size / length;
+
+            for start in (0..size).step_by(length) {
+                for k in 0..half {
+                    let (cos, sin) = (self.cos[k * stride], self.sin[k * stride]);
+                    let (a, b) = (start + k, start + k + half);
+
+                    let re = self.real[b] * cos - self.imag[b] * sin;
+                    let im = self.real[b] * sin + self.imag[b] * cos;
+
+                    self.real[b] = self.real[a] - re;
+                    self.imag[b] = self.imag[a] - im;
+                    self.real[a] += re;
+                    self.imag[a] += im;
+                }
+            }
+
+            length *= 2;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const SIZE: usize = 64;
+
+    fn magnitude(fft: &Fft, bin: usize) -> f32 {
+        fft.real[bin].hypot(fft.imag[bin])
+    }
+
+    #[test]
+    fn test_fft_impulse() {
+        let mut fft = Fft::new(SIZE);
+        fft.real[0] = 1.0;
+        fft.forward();
+
+        for bin in 0..SIZE {
+            assert!((fft.real[bin] - 1.0).abs() < 1e-6);
+            assert!(fft.imag[bin].abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn test_fft_cosine_bin() {
+        let mut fft = Fft::new(SIZE);
+        for (index, real) in fft.real.iter_mut().enumerate() {
+            *real = (2.0 * PI * 4.0 * index as f32 / SIZE as f32).cos();
+        }
+
+        fft.forward();
+
+        // A real cosine splits evenly between its bin and the mirrored one
+        for bin in 0..SIZE {
+            let expected = if bin == 4 || bin == SIZE - 4 {
+                SIZE as f32 / 2.0
+            } else {
+                0.0
+            };
+
+            assert!((magnitude(&fft, bin) - expected).abs() < 1e-3, "bin {bin}");
+        }
+    }
+
+    #[test]
+    fn test_fft_round_trip() {
+        let input = (0..SIZE)
+            .map(|index| (index as f32 * 0.61).sin() * 0.5 + (index as f32 * 0.13).cos() * 0.25)
+            .collect::<Vec<_>>();
+
+        let mut fft = Fft::new(SIZE);
+        fft.real.copy_from_slice(&input);
+        fft.forward();
+        fft.inverse();
+
+        for (actual, expected) in fft.real.iter().zip(input.iter()) {
+            assert!((actual - expected).abs() < 1e-5);
+        }
+
+        assert!(fft.imag.iter().all(|imag| imag.abs() < 1e-5));
+    }
+}
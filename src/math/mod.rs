@@ -1,5 +1,8 @@
 #![allow(dead_code)]
 
+mod dispatch;
+pub(crate) mod fft;
+
 trait SimdDiv<T: Copy> {
     fn simd_div(array: &mut [T], value: &[T]);
 }
@@ -541,6 +544,16 @@ pub struct MathUtils<T: Copy>(std::marker::PhantomData<T>);
 
 macro_rules! impl_math_utils {
     ($ty:ty, $wide_ty:ty) => {
+        impl_math_utils!(
+            $ty,
+            $wide_ty,
+            AddUtil::simd_add,
+            MulUtil::simd_mul,
+            CopyUtil::simd_copy,
+            SetUtil::simd_set
+        );
+    };
+    ($ty:ty, $wide_ty:ty, $add:path, $mul:path, $copy:path, $set:path) => {
         impl MathUtilsTrait<$ty> for MathUtils<$ty> {
             #[inline(always)]
             fn simd_div(array: &mut [$ty], value: &[$ty]) {
@@ -549,12 +562,12 @@ macro_rules! impl_math_utils {
 
             #[inline(always)]
             fn simd_mul(array: &mut [$ty], value: &[$ty]) {
-                MulUtil::simd_mul(array, value);
+                $mul(array, value);
             }
 
             #[inline(always)]
             fn simd_add(array: &mut [$ty], value: &[$ty]) {
-                AddUtil::simd_add(array, value);
+                $add(array, value);
             }
 
             #[inline(always)]
@@ -579,12 +592,12 @@ macro_rules! impl_math_utils {
 
             #[inline(always)]
             fn simd_copy(src: &[$ty], dst: &mut [$ty]) {
-                CopyUtil::simd_copy(src, dst);
+                $copy(src, dst);
             }
 
             #[inline(always)]
             fn simd_set(array: &mut [$ty], value: $ty) {
-                SetUtil::simd_set(array, value);
+                $set(array, value);
             }
 
             #[inline(always)]
@@ -600,7 +613,14 @@ macro_rules! impl_math_utils {
     };
 }
 
-impl_math_utils!(f32, wide::f32x4);
+impl_math_utils!(
+    f32,
+    wide::f32x4,
+    dispatch::simd_add_f32,
+    dispatch::simd_mul_f32,
+    dispatch::simd_copy_f32,
+    dispatch::simd_set_f32
+);
 impl_math_utils!(f64, wide::f64x2);
 impl_math_utils!(u32, wide::u32x4);
 impl_math_utils!(i32, wide::i32x4);
@@ -696,6 +716,7 @@ mod test {
 
 #[repr(C)]
 #[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Vector3<T: Copy> {
     pub x: T,
     pub y: T,
@@ -0,0 +1,254 @@
+use std::{
+    cell::{Cell, RefCell},
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+};
+
+use ringbuf::{
+    HeapCons, HeapProd, HeapRb,
+    traits::{Consumer, Producer, Split},
+};
+
+use super::{
+    audioattributes::AudioAttributes, audiopropertyhandler::PropertyHandler,
+    customattributes::AttributeValue,
+};
+
+/// An attribute changed through a [PropertyHandler](crate::PropertyHandler) setter.
+#[derive(Debug, Clone, Copy)]
+pub struct AttributeEvent<'a> {
+    /// [AudioAttributes::Unknown] for custom attributes.
+    pub attribute: AudioAttributes,
+    /// Name of the custom attribute, None for the builtin attributes.
+    pub name: Option<&'a str>,
+    /// The value after the change, read back so clamping by the setter is reflected.
+    pub value: AttributeValue,
+}
+
+pub(crate) type AttributeCallback = Box<dyn FnMut(&AttributeEvent) + Send + 'static>;
+
+// Changes made by the audio thread held until the control thread dispatches them, later
+// changes are dropped while the queue is full
+const AUTOMATED_QUEUE_DEPTH: usize = 256;
+
+/// An attribute changed by a scheduled change or an envelope on the audio thread.
+#[derive(Debug, Clone, Copy)]
+struct AutomatedChange {
+    attribute: AudioAttributes,
+    value: f32,
+}
+
+/// Audio thread side of [AttributeObservers], see [AttributeObservers::with_automation].
+pub(crate) struct AutomatedChanges {
+    changes: HeapProd<AutomatedChange>,
+}
+
+impl AutomatedChanges {
+    /// Queue a change for the observers without allocating.
+    pub fn push(&mut self, attribute: AudioAttributes, value: f32) {
+        _ = self.changes.try_push(AutomatedChange { attribute, value });
+    }
+}
+
+impl std::fmt::Debug for AutomatedChanges {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AutomatedChanges").finish_non_exhaustive()
+    }
+}
+
+thread_local! {
+    // Set while callbacks run on this thread, the changes they make are queued until they return
+    static DISPATCHING: Cell<bool> = const { Cell::new(false) };
+    static PENDING: RefCell<VecDeque<PendingEvent>> = const { RefCell::new(VecDeque::new()) };
+}
+
+/// A change made from inside a callback, reported once the running callbacks return.
+struct PendingEvent {
+    observers: AttributeObservers,
+    // None when only the automated changes are dispatched
+    event: Option<(AudioAttributes, Option<String>, AttributeValue)>,
+}
+
+impl PendingEvent {
+    fn new(observers: &AttributeObservers, event: Option<&AttributeEvent>) -> Self {
+        Self {
+            observers: observers.clone(),
+            event: event.map(|event| (event.attribute, event.name.map(str::to_owned), event.value)),
+        }
+    }
+
+    fn deliver(&self) {
+        let event = self
+            .event
+            .as_ref()
+            .map(|(attribute, name, value)| AttributeEvent {
+                attribute: *attribute,
+                name: name.as_deref(),
+                value: *value,
+            });
+
+        self.observers.run(event.as_ref());
+    }
+}
+
+/// Marks the thread as running callbacks until dropped, panicking callbacks included.
+struct Dispatching;
+
+impl Dispatching {
+    fn enter() -> Self {
+        DISPATCHING.set(true);
+        Self
+    }
+}
+
+impl Drop for Dispatching {
+    fn drop(&mut self) {
+        DISPATCHING.set(false);
+        PENDING.with_borrow_mut(VecDeque::clear);
+    }
+}
+
+#[derive(Default)]
+struct Observers {
+    callbacks: Vec<AttributeCallback>,
+    // Set for sources with attributes automated from the audio thread
+    automated: Option<HeapCons<AutomatedChange>>,
+}
+
+impl Observers {
+    fn notify(&mut self, event: &AttributeEvent) {
+        for callback in self.callbacks.iter_mut() {
+            callback(event);
+        }
+    }
+
+    fn dispatch(&mut self) {
+        let Some(automated) = self.automated.as_mut() else {
+            return;
+        };
+
+        while let Some(change) = automated.try_pop() {
+            let event = AttributeEvent {
+                attribute: change.attribute,
+                name: None,
+                value: AttributeValue::F32(change.value),
+            };
+
+            for callback in self.callbacks.iter_mut() {
+                callback(&event);
+            }
+        }
+    }
+}
+
+/// Callbacks registered with `on_attribute_changed`, shared between clones of a handle.
+#[derive(Clone, Default)]
+pub(crate) struct AttributeObservers {
+    observers: Arc<Mutex<Observers>>,
+}
+
+impl AttributeObservers {
+    /// Observers of a source whose attributes are also changed on the audio thread, the
+    /// changes pushed to the returned queue are reported by [AttributeObservers::dispatch].
+    pub fn with_automation() -> (Self, AutomatedChanges) {
+        let (changes, automated) = HeapRb::<AutomatedChange>::new(AUTOMATED_QUEUE_DEPTH).split();
+
+        let observers = Observers {
+            callbacks: Vec::new(),
+            automated: Some(automated),
+        };
+
+        let observers = Self {
+            observers: Arc::new(Mutex::new(observers)),
+        };
+
+        (observers, AutomatedChanges { changes })
+    }
+
+    pub fn add(&self, callback: AttributeCallback) {
+        if let Ok(mut observers) = self.observers.lock() {
+            observers.callbacks.push(callback);
+        }
+    }
+
+    pub fn clear(&self) {
+        if let Ok(mut observers) = self.observers.lock() {
+            observers.callbacks.clear();
+        }
+    }
+
+    /// Report the changes queued by the audio thread since the last dispatch.
+    pub fn dispatch(&self) {
+        self.deliver(None);
+    }
+
+    pub fn notify(&self, event: AttributeEvent) {
+        self.deliver(Some(&event));
+    }
+
+    /// Run the callbacks, or queue the change when called from one of them so it is
+    /// reported after the callbacks running now instead of being dropped.
+    fn deliver(&self, event: Option<&AttributeEvent>) {
+        if DISPATCHING.get() {
+            PENDING.with_borrow_mut(|pending| pending.push_back(PendingEvent::new(self, event)));
+            return;
+        }
+
+        let _dispatching = Dispatching::enter();
+        self.run(event);
+
+        while let Some(pending) = PENDING.with_borrow_mut(VecDeque::pop_front) {
+            pending.deliver();
+        }
+    }
+
+    fn run(&self, event: Option<&AttributeEvent>) {
+        let Ok(mut observers) = self.observers.lock() else {
+            return;
+        };
+
+        // The audio thread changes queued so far happened before this one
+        observers.dispatch();
+
+        if let Some(event) = event {
+            observers.notify(event);
+        }
+    }
+
+    /// Report the attributes applied by a `set_attributes` call, read back from `handler`.
+    pub fn notify_attributes<H: PropertyHandler + ?Sized>(
+        &self,
+        handler: &H,
+        attributes: &[(AudioAttributes, AttributeValue)],
+    ) {
+        for &(attribute, value) in attributes {
+            let value = match value {
+                AttributeValue::F32(value) => {
+                    AttributeValue::F32(handler.get_attribute_f32(attribute).unwrap_or(value))
+                }
+                AttributeValue::Bool(value) => {
+                    AttributeValue::Bool(handler.get_attribute_bool(attribute).unwrap_or(value))
+                }
+            };
+
+            self.notify(AttributeEvent {
+                attribute,
+                name: None,
+                value,
+            });
+        }
+    }
+}
+
+impl std::fmt::Debug for AttributeObservers {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let count = self
+            .observers
+            .lock()
+            .map(|observers| observers.callbacks.len())
+            .unwrap_or(0);
+        f.debug_struct("AttributeObservers")
+            .field("callbacks", &count)
+            .finish()
+    }
+}
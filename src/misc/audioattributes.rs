@@ -1,4 +1,8 @@
+use crate::{effects::AudioFXError, misc::audiopropertyhandler::PropertyError};
+
 #[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum AudioAttributes {
     Unknown,
     /// The sample rate of the audio channel, device or mixer.
@@ -17,9 +21,56 @@ pub enum AudioAttributes {
     FXEnabled,
     /// Enable or disable the AudioSpatialization used for 3D Audio on the audio channel, device or mixer.
     SpatializationEnabled,
+    /// The balance of the audio channel, device or mixer. \
+    /// Same range as [AudioAttributes::Pan] but always attenuates the opposite side instead of
+    /// following the [PanMode](crate::PanMode), setting it switches the panner to balance mode.
+    Balance,
+    /// Sum every output channel of the audio channel, device or mixer down to mono.
+    ForceMono,
+    /// Swap the left and right output channels of the audio channel, device or mixer.
+    SwapChannels,
 }
 
 impl AudioAttributes {
+    /// Every attribute except [AudioAttributes::Unknown].
+    pub const fn all() -> &'static [AudioAttributes] {
+        &[
+            AudioAttributes::SampleRate,
+            AudioAttributes::Volume,
+            AudioAttributes::Pan,
+            AudioAttributes::FXPitch,
+            AudioAttributes::FXTempo,
+            AudioAttributes::FXEnabled,
+            AudioAttributes::SpatializationEnabled,
+            AudioAttributes::Balance,
+            AudioAttributes::ForceMono,
+            AudioAttributes::SwapChannels,
+        ]
+    }
+
+    /// Check `value` before it is handed to the audio thread, where a failure cannot be
+    /// reported. Volume, pan and balance are clamped and always accepted when finite.
+    pub(crate) fn check_value(&self, value: f32) -> Result<(), PropertyError> {
+        if !value.is_finite() {
+            return Err(PropertyError::InvalidOperation("Attribute value must be finite"));
+        }
+
+        match self {
+            AudioAttributes::FXTempo if !(0.5..=2.0).contains(&value) => {
+                Err(PropertyError::from_other(AudioFXError::InvalidTempo))
+            }
+            AudioAttributes::FXPitch if value < 0.5 => {
+                Err(PropertyError::from_other(AudioFXError::InvalidOctave))
+            }
+            AudioAttributes::SampleRate if value <= 0.0 => {
+                Err(PropertyError::InvalidOperation("Sample rate must be above 0"))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Parse the name returned by [AudioAttributes::to_string], the variant names are
+    /// accepted as well.
     pub fn from(name: &str) -> Self {
         match name {
             "SampleRate" => AudioAttributes::SampleRate,
@@ -27,6 +78,13 @@ impl AudioAttributes {
             "Pan" => AudioAttributes::Pan,
             "FXPitch" => AudioAttributes::FXPitch,
             "FXTempo" => AudioAttributes::FXTempo,
+            "FXEnabled" | "AudioFX" => AudioAttributes::FXEnabled,
+            "SpatializationEnabled" | "AudioSpatialization" => {
+                AudioAttributes::SpatializationEnabled
+            }
+            "Balance" => AudioAttributes::Balance,
+            "ForceMono" => AudioAttributes::ForceMono,
+            "SwapChannels" => AudioAttributes::SwapChannels,
             _ => AudioAttributes::Unknown,
         }
     }
@@ -40,6 +98,9 @@ impl AudioAttributes {
             AudioAttributes::FXTempo => "FXTempo".to_string(),
             AudioAttributes::FXEnabled => "FXEnabled".to_string(),
             AudioAttributes::SpatializationEnabled => "AudioSpatialization".to_string(),
+            AudioAttributes::Balance => "Balance".to_string(),
+            AudioAttributes::ForceMono => "ForceMono".to_string(),
+            AudioAttributes::SwapChannels => "SwapChannels".to_string(),
             AudioAttributes::Unknown => "Unknown".to_string(),
         }
     }
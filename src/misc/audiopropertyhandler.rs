@@ -1,8 +1,14 @@
 use thiserror::Error;
 
-use super::audioattributes::AudioAttributes;
+use crate::effects::{AudioFX, AudioFXError};
+
+use super::{audioattributes::AudioAttributes, customattributes::AttributeValue};
 
 pub trait PropertyHandler {
+    /// The [AudioAttributes] this object handles, some only while a feature such as AudioFX is enabled.
+    fn supported_attributes(&self) -> &'static [AudioAttributes] {
+        &[]
+    }
     /// Get the [AudioAttributes] value (f32) of the [AudioChannel], [AudioDevice] or [AudioMixer].
     fn get_attribute_f32(&self, _type: AudioAttributes) -> Result<f32, PropertyError> {
         Err(PropertyError::NotImplemented)
@@ -27,6 +33,58 @@ pub trait PropertyHandler {
     ) -> Result<(), PropertyError> {
         Err(PropertyError::NotImplemented)
     }
+    /// Set several [AudioAttributes] at once, the value type picks between the f32 and bool
+    /// setters. The channel, device and mixer apply the whole list between two audio callbacks
+    /// under a single lock, so a change of volume, pan and pitch is heard together.
+    ///
+    /// The channel, device and mixer check the whole list first and apply none of it when an
+    /// attribute is invalid. Other handlers stop at the first attribute that fails, the ones
+    /// before it stay applied.
+    fn set_attributes(
+        &mut self,
+        attributes: &[(AudioAttributes, AttributeValue)],
+    ) -> Result<(), PropertyError> {
+        for (attribute, value) in attributes {
+            match *value {
+                AttributeValue::F32(value) => self.set_attribute_f32(*attribute, value)?,
+                AttributeValue::Bool(value) => self.set_attribute_bool(*attribute, value)?,
+            }
+        }
+
+        Ok(())
+    }
+    /// Get an application defined attribute previously set with [PropertyHandler::set_custom_attribute].
+    fn get_custom_attribute(&self, _name: &str) -> Result<AttributeValue, PropertyError> {
+        Err(PropertyError::NotImplemented)
+    }
+    /// Store an application defined attribute on the object, replacing any previous value.
+    fn set_custom_attribute(
+        &mut self,
+        _name: &str,
+        _value: AttributeValue,
+    ) -> Result<(), PropertyError> {
+        Err(PropertyError::NotImplemented)
+    }
+    /// Typed [PropertyHandler::get_custom_attribute], fails when the value is not a f32.
+    fn get_custom_attribute_f32(&self, name: &str) -> Result<f32, PropertyError> {
+        match self.get_custom_attribute(name)? {
+            AttributeValue::F32(value) => Ok(value),
+            _ => Err(PropertyError::InvalidOperation("Custom attribute is not a f32")),
+        }
+    }
+    fn set_custom_attribute_f32(&mut self, name: &str, value: f32) -> Result<(), PropertyError> {
+        self.set_custom_attribute(name, AttributeValue::F32(value))
+    }
+    /// Typed [PropertyHandler::get_custom_attribute], fails when the value is not a bool.
+    fn get_custom_attribute_bool(&self, name: &str) -> Result<bool, PropertyError> {
+        match self.get_custom_attribute(name)? {
+            AttributeValue::Bool(value) => Ok(value),
+            _ => Err(PropertyError::InvalidOperation("Custom attribute is not a bool")),
+        }
+    }
+    fn set_custom_attribute_bool(&mut self, name: &str, value: bool) -> Result<(), PropertyError> {
+        self.set_custom_attribute(name, AttributeValue::Bool(value))
+    }
 }
 
 #[derive(Debug, Error)]
@@ -35,6 +93,8 @@ pub enum PropertyError {
     NotImplemented,
     #[error("Unsupported attribute: {0}")]
     UnsupportedAttribute(&'static str),
+    #[error("Unknown attribute: {0}")]
+    UnknownAttribute(String),
     #[error("Invalid operation: {0}")]
     InvalidOperation(&'static str),
     #[error("{0}")]
@@ -46,3 +106,44 @@ impl PropertyError {
         PropertyError::Other(Box::new(error))
     }
 }
+
+/// Check a [PropertyHandler::set_attributes] list of a track or mixer before any of it is
+/// applied. `fx_enabled` is whether AudioFX is enabled ahead of the list, an FXEnabled entry
+/// in the list counts for the entries after it.
+pub(crate) fn validate_channel_attributes(
+    attributes: &[(AudioAttributes, AttributeValue)],
+    mut fx_enabled: bool,
+) -> Result<(), PropertyError> {
+    for &(attribute, value) in attributes {
+        match (attribute, value) {
+            (AudioAttributes::FXTempo | AudioAttributes::FXPitch, AttributeValue::F32(_))
+                if !fx_enabled =>
+            {
+                return Err(PropertyError::from_other(AudioFXError::NotEnabled));
+            }
+            (AudioAttributes::FXTempo, AttributeValue::F32(value)) => {
+                AudioFX::validate_tempo(value).map_err(PropertyError::from_other)?
+            }
+            (AudioAttributes::FXPitch, AttributeValue::F32(value)) => {
+                AudioFX::validate_octave(value).map_err(PropertyError::from_other)?
+            }
+            (
+                AudioAttributes::SampleRate
+                | AudioAttributes::Volume
+                | AudioAttributes::Pan
+                | AudioAttributes::Balance,
+                AttributeValue::F32(_),
+            ) => {}
+            (AudioAttributes::FXEnabled, AttributeValue::Bool(enabled)) => fx_enabled = enabled,
+            (
+                AudioAttributes::SpatializationEnabled
+                | AudioAttributes::ForceMono
+                | AudioAttributes::SwapChannels,
+                AttributeValue::Bool(_),
+            ) => {}
+            _ => return Err(PropertyError::UnsupportedAttribute("Unknown attribute")),
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,228 @@
+use std::{
+    cell::UnsafeCell,
+    ops::{Deref, DerefMut},
+    sync::atomic::{AtomicBool, AtomicU64, Ordering},
+};
+
+/// Default amount of scratch buffers of each size allocated per device, and per mixing
+/// thread for the block sized ones.
+pub(crate) const DEFAULT_POOL_SLOTS: usize = 4;
+
+struct BufferSlot {
+    in_use: AtomicBool,
+    data: UnsafeCell<Box<[f32]>>,
+}
+
+/// Fixed set of scratch buffers allocated once when the device is created.
+///
+/// Acquiring and releasing a buffer only touches an atomic flag, so it is safe
+/// to do from the audio thread.
+pub(crate) struct BufferPool {
+    // Sorted by capacity, so a request takes the smallest buffer it fits in
+    slots: Box<[BufferSlot]>,
+    // Requests no free buffer was large enough for
+    exhausted: AtomicU64,
+}
+
+// SAFETY: A slot data is only reachable through a [PooledBuffer], which is handed out
+// to a single owner at a time by the `in_use` flag.
+unsafe impl Sync for BufferPool {}
+
+impl BufferPool {
+    /// A pool with `count` buffers of `capacity` samples for every `(count, capacity)` of
+    /// `sizes`.
+    pub fn new(sizes: &[(usize, usize)]) -> Self {
+        let mut sizes = sizes.to_vec();
+        sizes.sort_by_key(|(_, capacity)| *capacity);
+
+        let slots = sizes
+            .into_iter()
+            .flat_map(|(count, capacity)| (0..count).map(move |_| capacity))
+            .map(|capacity| BufferSlot {
+                in_use: AtomicBool::new(false),
+                data: UnsafeCell::new(vec![0.0f32; capacity].into_boxed_slice()),
+            })
+            .collect::<Vec<_>>()
+            .into_boxed_slice();
+
+        Self {
+            slots,
+            exhausted: AtomicU64::new(0),
+        }
+    }
+
+    /// Bytes allocated for the slots.
+    pub fn size_in_bytes(&self) -> usize {
+        self.slots
+            .iter()
+            // SAFETY: Only the length is read, which never changes.
+            .map(|slot| unsafe { (*slot.data.get()).len() })
+            .sum::<usize>()
+            * std::mem::size_of::<f32>()
+    }
+
+    /// Requests [BufferPool::acquire] could not serve since the last reset.
+    pub fn exhausted(&self) -> u64 {
+        self.exhausted.load(Ordering::Relaxed)
+    }
+
+    pub fn reset_exhausted(&self) {
+        self.exhausted.store(0, Ordering::Relaxed);
+    }
+
+    /// Take a zeroed buffer of `len` samples, returns None and counts it when no free slot
+    /// holds `len` samples.
+    pub fn acquire(&self, len: usize) -> Option<PooledBuffer<'_>> {
+        for slot in self.slots.iter() {
+            // SAFETY: Only the length is read, which never changes.
+            if unsafe { (*slot.data.get()).len() } < len {
+                continue;
+            }
+
+            if slot
+                .in_use
+                .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
+                .is_err()
+            {
+                continue;
+            }
+
+            // SAFETY: The slot was just claimed, nobody else can reach its data.
+            let data = unsafe { &mut *slot.data.get() };
+            data[..len].fill(0.0);
+
+            return Some(PooledBuffer { slot, len });
+        }
+
+        self.exhausted.fetch_add(1, Ordering::Relaxed);
+        None
+    }
+}
+
+impl std::fmt::Debug for BufferPool {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("BufferPool")
+            .field("slots", &self.slots.len())
+            .field("exhausted", &self.exhausted())
+            .finish()
+    }
+}
+
+/// A buffer borrowed from a [BufferPool], given back to the pool on drop.
+pub(crate) struct PooledBuffer<'a> {
+    slot: &'a BufferSlot,
+    len: usize,
+}
+
+impl Deref for PooledBuffer<'_> {
+    type Target = [f32];
+
+    fn deref(&self) -> &[f32] {
+        // SAFETY: Owned exclusively until dropped.
+        unsafe { &(*self.slot.data.get())[..self.len] }
+    }
+}
+
+impl DerefMut for PooledBuffer<'_> {
+    fn deref_mut(&mut self) -> &mut [f32] {
+        // SAFETY: Owned exclusively until dropped.
+        unsafe { &mut (*self.slot.data.get())[..self.len] }
+    }
+}
+
+impl Drop for PooledBuffer<'_> {
+    fn drop(&mut self) {
+        self.slot.in_use.store(false, Ordering::Release);
+    }
+}
+
+/// Scratch memory taken from a pool, or allocated when there is no pool available.
+///
+/// Sources only allocate when they are not attached to a device, and so never render on an
+/// audio thread.
+pub(crate) enum ScratchBuffer<'a> {
+    Pooled(PooledBuffer<'a>),
+    Owned(Vec<f32>),
+}
+
+impl<'a> ScratchBuffer<'a> {
+    /// A zeroed buffer of `len` samples, None when the pool has no free buffer that large.
+    pub fn new(pool: Option<&'a BufferPool>, len: usize) -> Option<Self> {
+        match pool {
+            Some(pool) => pool.acquire(len).map(ScratchBuffer::Pooled),
+            None => Some(ScratchBuffer::Owned(vec![0.0f32; len])),
+        }
+    }
+}
+
+impl Deref for ScratchBuffer<'_> {
+    type Target = [f32];
+
+    fn deref(&self) -> &[f32] {
+        match self {
+            ScratchBuffer::Pooled(buffer) => buffer,
+            ScratchBuffer::Owned(buffer) => buffer,
+        }
+    }
+}
+
+impl DerefMut for ScratchBuffer<'_> {
+    fn deref_mut(&mut self) -> &mut [f32] {
+        match self {
+            ScratchBuffer::Pooled(buffer) => buffer,
+            ScratchBuffer::Owned(buffer) => buffer,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_acquire_smallest_fit() {
+        let pool = BufferPool::new(&[(1, 256), (1, 64)]);
+        assert_eq!(pool.size_in_bytes(), 320 * std::mem::size_of::<f32>());
+
+        let small = pool.acquire(32).unwrap();
+        assert_eq!(small.len(), 32);
+
+        // The small slot is taken, the next request falls back to the large one
+        let large = pool.acquire(32).unwrap();
+        assert!(pool.acquire(1).is_none());
+        assert_eq!(pool.exhausted(), 1);
+
+        drop(small);
+        drop(large);
+        assert!(pool.acquire(256).is_some());
+        assert!(pool.acquire(257).is_none());
+        assert_eq!(pool.exhausted(), 2);
+
+        pool.reset_exhausted();
+        assert_eq!(pool.exhausted(), 0);
+    }
+
+    #[test]
+    fn test_released_buffer_is_zeroed() {
+        let pool = BufferPool::new(&[(1, 16)]);
+
+        let mut buffer = pool.acquire(16).unwrap();
+        buffer.fill(1.0);
+        drop(buffer);
+
+        let buffer = pool.acquire(8).unwrap();
+        assert!(buffer.iter().all(|&sample| sample == 0.0));
+    }
+
+    #[test]
+    fn test_scratch_buffer() {
+        let owned = ScratchBuffer::new(None, 8).unwrap();
+        assert!(matches!(owned, ScratchBuffer::Owned(_)));
+        assert_eq!(owned.len(), 8);
+
+        let pool = BufferPool::new(&[(1, 8)]);
+        let pooled = ScratchBuffer::new(Some(&pool), 8).unwrap();
+        assert!(matches!(pooled, ScratchBuffer::Pooled(_)));
+        assert!(ScratchBuffer::new(Some(&pool), 8).is_none());
+    }
+}
@@ -0,0 +1,34 @@
+use std::collections::HashMap;
+
+use super::audiopropertyhandler::PropertyError;
+
+/// Value of a custom attribute, see [PropertyHandler::set_custom_attribute](crate::PropertyHandler::set_custom_attribute).
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum AttributeValue {
+    F32(f32),
+    Bool(bool),
+}
+
+/// String keyed attributes stored per channel, mixer or device. They are not used by the
+/// library itself, applications attach their own parameters with them.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct CustomAttributes {
+    values: HashMap<String, AttributeValue>,
+}
+
+impl CustomAttributes {
+    pub fn get(&self, name: &str) -> Result<AttributeValue, PropertyError> {
+        match self.values.get(name) {
+            Some(value) => Ok(*value),
+            None => Err(PropertyError::UnknownAttribute(name.to_string())),
+        }
+    }
+
+    pub fn set(&mut self, name: &str, value: AttributeValue) {
+        match self.values.get_mut(name) {
+            Some(current) => *current = value,
+            None => _ = self.values.insert(name.to_string(), value),
+        }
+    }
+}
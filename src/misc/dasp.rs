@@ -0,0 +1,79 @@
+use dasp::{
+    Frame, Sample, Signal,
+    sample::{FromSample, ToSample},
+};
+
+use crate::{BufferInfo, BufferInfoOwned};
+
+impl BufferInfoOwned {
+    /// Interleave `dasp` frames into f32, with the channels of the frame type.
+    pub fn from_frames<F>(frames: &[F], sample_rate: f32) -> Self
+    where
+        F: Frame,
+        F::Sample: ToSample<f32>,
+    {
+        let mut data = Vec::with_capacity(frames.len() * F::CHANNELS);
+        for frame in frames {
+            data.extend(frame.channels().map(|sample| sample.to_sample::<f32>()));
+        }
+
+        Self {
+            data,
+            channels: F::CHANNELS,
+            sample_rate,
+        }
+    }
+
+    /// Read up to `frames` frames of a `dasp` signal, fewer when the signal is exhausted
+    /// first. A signal has no sample rate, `sample_rate` is the one it was built for.
+    pub fn from_signal<S>(mut signal: S, frames: usize, sample_rate: f32) -> Self
+    where
+        S: Signal,
+        <S::Frame as Frame>::Sample: ToSample<f32>,
+    {
+        let mut data = Vec::with_capacity(frames * S::Frame::CHANNELS);
+        for _ in 0..frames {
+            if signal.is_exhausted() {
+                break;
+            }
+
+            data.extend(signal.next().channels().map(|sample| sample.to_sample::<f32>()));
+        }
+
+        Self {
+            data,
+            channels: S::Frame::CHANNELS,
+            sample_rate,
+        }
+    }
+}
+
+impl<'a> BufferInfo<'a> {
+    /// The buffer as `dasp` frames, `None` when the frame type has another channel count.
+    pub fn frames<F>(&self) -> Option<impl Iterator<Item = F> + 'a>
+    where
+        F: Frame + 'a,
+        F::Sample: FromSample<f32>,
+    {
+        if self.channels != F::CHANNELS {
+            return None;
+        }
+
+        let data: &'a [f32] = self.data;
+        let frames = data.chunks_exact(F::CHANNELS).filter_map(|frame| {
+            F::from_samples(&mut frame.iter().map(|sample| F::Sample::from_sample(*sample)))
+        });
+
+        Some(frames)
+    }
+
+    /// The buffer as a `dasp` signal, exhausted at the end of the buffer. `None` when the frame
+    /// type has another channel count.
+    pub fn signal<F>(&self) -> Option<impl Signal<Frame = F> + 'a>
+    where
+        F: Frame + 'a,
+        F::Sample: FromSample<f32>,
+    {
+        self.frames().map(dasp::signal::from_iter)
+    }
+}
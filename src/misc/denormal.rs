@@ -0,0 +1,115 @@
+// Denormal protection for the audio threads.
+//
+// Feedback paths decaying towards silence end up in the subnormal range, which x86 handles in
+// microcode at a large cost per operation. The guard switches the current thread to flush
+// subnormals to zero, the dither keeps state out of that range where the guard is not active.
+
+/// Offset added to the input of feedback effects, far below anything audible.
+pub(crate) const DENORMAL_DITHER: f32 = 1.0e-18;
+
+/// Add [DENORMAL_DITHER] to every sample.
+#[inline(always)]
+pub(crate) fn dither(buffer: &mut [f32]) {
+    for sample in buffer.iter_mut() {
+        *sample += DENORMAL_DITHER;
+    }
+}
+
+/// Enables flush-to-zero and denormals-are-zero on the current thread, the previous mode is
+/// restored on drop.
+pub(crate) struct DenormalGuard {
+    previous: Option<u64>,
+}
+
+impl DenormalGuard {
+    pub fn new() -> Self {
+        let previous = imp::read();
+        if let Some(previous) = previous {
+            imp::write(previous | imp::FLUSH_BITS);
+        }
+
+        Self { previous }
+    }
+}
+
+impl Drop for DenormalGuard {
+    fn drop(&mut self) {
+        if let Some(previous) = self.previous {
+            imp::write(previous);
+        }
+    }
+}
+
+#[cfg(all(
+    any(target_arch = "x86", target_arch = "x86_64"),
+    target_feature = "sse"
+))]
+mod imp {
+    use std::arch::asm;
+
+    // MXCSR flush-to-zero (bit 15) and denormals-are-zero (bit 6)
+    pub const FLUSH_BITS: u64 = 0x8040;
+
+    pub fn read() -> Option<u64> {
+        let mut csr: u32 = 0;
+
+        // SAFETY: Only stores the MXCSR register into a local.
+        unsafe {
+            asm!("stmxcsr [{}]", in(reg) &mut csr, options(nostack, preserves_flags));
+        }
+
+        Some(csr as u64)
+    }
+
+    pub fn write(value: u64) {
+        let csr = value as u32;
+
+        // SAFETY: Only the rounding control bits change, which do not affect memory safety.
+        unsafe {
+            asm!("ldmxcsr [{}]", in(reg) &csr, options(nostack, readonly, preserves_flags));
+        }
+    }
+}
+
+#[cfg(target_arch = "aarch64")]
+mod imp {
+    use std::arch::asm;
+
+    // FPCR flush-to-zero (bit 24), also covers denormal inputs on aarch64
+    pub const FLUSH_BITS: u64 = 1 << 24;
+
+    pub fn read() -> Option<u64> {
+        let fpcr: u64;
+
+        // SAFETY: Reading FPCR has no side effects.
+        unsafe {
+            asm!("mrs {}, fpcr", out(reg) fpcr, options(nomem, nostack, preserves_flags));
+        }
+
+        Some(fpcr)
+    }
+
+    pub fn write(value: u64) {
+        // SAFETY: Only the floating point control bits change, which do not affect memory safety.
+        unsafe {
+            asm!("msr fpcr, {}", in(reg) value, options(nomem, nostack, preserves_flags));
+        }
+    }
+}
+
+#[cfg(not(any(
+    all(
+        any(target_arch = "x86", target_arch = "x86_64"),
+        target_feature = "sse"
+    ),
+    target_arch = "aarch64"
+)))]
+mod imp {
+    pub const FLUSH_BITS: u64 = 0;
+
+    pub fn read() -> Option<u64> {
+        None
+    }
+
+    pub fn write(_value: u64) {}
+}
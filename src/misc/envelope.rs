@@ -0,0 +1,225 @@
+use thiserror::Error;
+
+use crate::utils::{TweenType, tween};
+
+use super::audioattributes::AudioAttributes;
+
+/// Frames rendered between two envelope evaluations, small enough that a volume ride does not
+/// step audibly.
+pub(crate) const ENVELOPE_STEP: usize = 256;
+
+#[derive(Debug, Error)]
+pub enum EnvelopeError {
+    #[error("An envelope needs at least one keyframe")]
+    Empty,
+    #[error("Invalid keyframe time: {0}")]
+    InvalidTime(f64),
+    #[error("Invalid keyframe value: {0}")]
+    InvalidValue(f32),
+}
+
+/// A point of an [Envelope].
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Keyframe {
+    /// Seconds on the timeline of the source the envelope is attached to.
+    pub time: f64,
+    pub value: f32,
+    /// Curve from the previous keyframe into this one.
+    pub curve: TweenType,
+}
+
+impl Keyframe {
+    pub fn new(time: f64, value: f32, curve: TweenType) -> Self {
+        Self { time, value, curve }
+    }
+}
+
+/// Automation for an f32 attribute, evaluated from the source position while processing.
+///
+/// Holds the first value before the first keyframe and the last value after the last one.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Envelope {
+    keyframes: Vec<Keyframe>,
+}
+
+impl Envelope {
+    /// Keyframes are sorted by time, keyframes sharing a time keep their order so a jump can
+    /// be authored with two keyframes at the same time.
+    pub fn new(mut keyframes: Vec<Keyframe>) -> Result<Self, EnvelopeError> {
+        if keyframes.is_empty() {
+            return Err(EnvelopeError::Empty);
+        }
+
+        for keyframe in keyframes.iter() {
+            if !keyframe.time.is_finite() || keyframe.time < 0.0 {
+                return Err(EnvelopeError::InvalidTime(keyframe.time));
+            }
+
+            if !keyframe.value.is_finite() {
+                return Err(EnvelopeError::InvalidValue(keyframe.value));
+            }
+        }
+
+        keyframes.sort_by(|a, b| a.time.total_cmp(&b.time));
+
+        Ok(Self { keyframes })
+    }
+
+    pub fn keyframes(&self) -> &[Keyframe] {
+        &self.keyframes
+    }
+
+    /// Seconds of the last keyframe.
+    pub fn duration(&self) -> f64 {
+        self.keyframes.last().map_or(0.0, |keyframe| keyframe.time)
+    }
+
+    pub fn value_at(&self, time: f64) -> f32 {
+        let index = self.keyframes.partition_point(|keyframe| keyframe.time <= time);
+
+        if index == 0 {
+            return self.keyframes[0].value;
+        }
+
+        let from = &self.keyframes[index - 1];
+        let Some(to) = self.keyframes.get(index) else {
+            return from.value;
+        };
+
+        let t = ((time - from.time) / (to.time - from.time)) as f32;
+        from.value + (to.value - from.value) * tween(to.curve, t.clamp(0.0, 1.0))
+    }
+}
+
+/// An envelope attached to an attribute, stopped by the audio thread once a value it produced
+/// was rejected.
+#[derive(Debug, Clone)]
+struct AttachedEnvelope {
+    attribute: AudioAttributes,
+    envelope: Envelope,
+    stopped: bool,
+}
+
+/// Envelopes attached to the attributes of a source, at most one per attribute.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Envelopes {
+    envelopes: Vec<AttachedEnvelope>,
+}
+
+impl Envelopes {
+    /// Attach `envelope` to `attribute` replacing the previous one, None detaches it.
+    pub fn set(&mut self, attribute: AudioAttributes, envelope: Option<Envelope>) {
+        self.envelopes.retain(|attached| attached.attribute != attribute);
+
+        if let Some(envelope) = envelope {
+            self.envelopes.push(AttachedEnvelope {
+                attribute,
+                envelope,
+                stopped: false,
+            });
+        }
+    }
+
+    pub fn get(&self, attribute: AudioAttributes) -> Option<&Envelope> {
+        self.find(attribute).map(|attached| &attached.envelope)
+    }
+
+    /// Whether the envelope of `attribute` was stopped after a value was rejected.
+    pub fn is_stopped(&self, attribute: AudioAttributes) -> bool {
+        self.find(attribute).is_some_and(|attached| attached.stopped)
+    }
+
+    pub fn clear(&mut self) {
+        self.envelopes.clear();
+    }
+
+    /// No envelope left running.
+    pub fn is_empty(&self) -> bool {
+        self.envelopes.iter().all(|attached| attached.stopped)
+    }
+
+    /// Hand the value of every running envelope at `time` to `apply`, an envelope whose value
+    /// is rejected is stopped. Runs on the audio thread so nothing is freed or printed.
+    pub fn apply_at(&mut self, time: f64, mut apply: impl FnMut(AudioAttributes, f32) -> bool) {
+        for attached in self.envelopes.iter_mut().filter(|attached| !attached.stopped) {
+            let value = attached.envelope.value_at(time);
+            attached.stopped = !apply(attached.attribute, value);
+        }
+    }
+
+    fn find(&self, attribute: AudioAttributes) -> Option<&AttachedEnvelope> {
+        self.envelopes
+            .iter()
+            .find(|attached| attached.attribute == attribute)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn ramp(curve: TweenType) -> Envelope {
+        Envelope::new(vec![
+            Keyframe::new(1.0, 0.0, TweenType::Linear),
+            Keyframe::new(3.0, 1.0, curve),
+        ])
+        .unwrap()
+    }
+
+    #[test]
+    fn test_value_at() {
+        let envelope = ramp(TweenType::Linear);
+        assert_eq!(envelope.value_at(2.0), 0.5);
+        assert_eq!(envelope.duration(), 3.0);
+
+        // Held outside of the keyframes
+        assert_eq!(envelope.value_at(0.0), 0.0);
+        assert_eq!(envelope.value_at(5.0), 1.0);
+
+        let envelope = ramp(TweenType::Quadratic);
+        assert_eq!(envelope.value_at(2.0), 0.25);
+    }
+
+    #[test]
+    fn test_jump() {
+        let envelope = Envelope::new(vec![
+            Keyframe::new(2.0, 1.0, TweenType::Linear),
+            Keyframe::new(1.0, 0.0, TweenType::Linear),
+            Keyframe::new(0.0, 0.0, TweenType::Linear),
+            Keyframe::new(1.0, 1.0, TweenType::Linear),
+        ])
+        .unwrap();
+
+        // Sorted by time, the keyframes at 1.0 keep their order
+        assert_eq!(envelope.keyframes()[1].value, 0.0);
+        assert_eq!(envelope.value_at(0.999), 0.0);
+        assert_eq!(envelope.value_at(1.0), 1.0);
+    }
+
+    #[test]
+    fn test_invalid_keyframes() {
+        assert!(Envelope::new(Vec::new()).is_err());
+        assert!(Envelope::new(vec![Keyframe::new(-1.0, 0.0, TweenType::Linear)]).is_err());
+        assert!(Envelope::new(vec![Keyframe::new(0.0, f32::NAN, TweenType::Linear)]).is_err());
+    }
+
+    #[test]
+    fn test_envelopes_stop() {
+        let mut envelopes = Envelopes::default();
+        envelopes.set(AudioAttributes::Volume, Some(ramp(TweenType::Linear)));
+        envelopes.set(AudioAttributes::Pan, Some(ramp(TweenType::Linear)));
+
+        envelopes.apply_at(2.0, |attribute, _| attribute != AudioAttributes::Pan);
+        assert!(envelopes.is_stopped(AudioAttributes::Pan));
+        assert!(!envelopes.is_stopped(AudioAttributes::Volume));
+        assert!(!envelopes.is_empty());
+
+        envelopes.apply_at(2.0, |_, _| false);
+        assert!(envelopes.is_empty());
+
+        envelopes.set(AudioAttributes::Pan, None);
+        assert!(envelopes.get(AudioAttributes::Pan).is_none());
+    }
+}
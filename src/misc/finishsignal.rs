@@ -0,0 +1,229 @@
+use std::{
+    sync::{
+        Arc,
+        atomic::{AtomicBool, AtomicU64, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+#[cfg(feature = "async")]
+use std::sync::{Mutex, OnceLock};
+
+#[cfg(feature = "async")]
+use std::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll, Waker},
+};
+
+/// Why a playback resolved its [Completion].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[repr(C)]
+pub enum PlaybackEnd {
+    /// Reached the end of the audio.
+    Ended = 0,
+    /// Reached the end of the audio and started over, the source keeps playing.
+    Looped = 1,
+    /// Stopped or dropped before reaching the end.
+    Stopped = 2,
+}
+
+impl From<u8> for PlaybackEnd {
+    fn from(value: u8) -> Self {
+        match value {
+            0 => PlaybackEnd::Ended,
+            1 => PlaybackEnd::Looped,
+            _ => PlaybackEnd::Stopped,
+        }
+    }
+}
+
+// How often a thread waiting on a FinishSignal checks it again
+const POLL_INTERVAL: Duration = Duration::from_millis(2);
+
+/// Wakes threads waiting for a source to stop playing, notified wherever the playing flag
+/// of the source is cleared or the source loops.
+///
+/// Notified from the audio thread, so it only stores atomics and the waiting threads poll
+/// them instead of sleeping on a condvar.
+#[derive(Debug, Default)]
+pub(crate) struct FinishSignal {
+    // Bumped on every play, each play hands out a Completion for its generation
+    started: AtomicU64,
+    // Generation of the last completion in the high bits, its PlaybackEnd in the low byte
+    finished: AtomicU64,
+}
+
+impl FinishSignal {
+    /// Start a new playback generation, the returned handle resolves on the next notify.
+    pub fn arm(self: &Arc<Self>) -> Completion {
+        let generation = self.started.fetch_add(1, Ordering::SeqCst) + 1;
+
+        Completion {
+            signal: Arc::clone(self),
+            generation,
+        }
+    }
+
+    pub fn notify(&self, reason: PlaybackEnd) {
+        let generation = self.started.load(Ordering::SeqCst);
+        self.finished
+            .store(generation << 8 | reason as u64, Ordering::SeqCst);
+    }
+
+    fn result(&self, generation: u64) -> Option<PlaybackEnd> {
+        let state = self.finished.load(Ordering::SeqCst);
+        (state >> 8 >= generation).then(|| PlaybackEnd::from(state as u8))
+    }
+
+    /// Block while `playing` is set, false when the timeout elapsed first.
+    pub fn wait(&self, playing: &AtomicBool, timeout: Option<Duration>) -> bool {
+        self.wait_while(|| playing.load(Ordering::SeqCst), timeout)
+    }
+
+    fn wait_while<F: Fn() -> bool>(&self, condition: F, timeout: Option<Duration>) -> bool {
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+
+        while condition() {
+            let mut interval = POLL_INTERVAL;
+
+            if let Some(deadline) = deadline {
+                let now = Instant::now();
+                if now >= deadline {
+                    return false;
+                }
+
+                interval = interval.min(deadline - now);
+            }
+
+            std::thread::sleep(interval);
+        }
+
+        true
+    }
+}
+
+/// Handle returned by `play`, resolved once that playback ends, loops or is stopped.
+///
+/// Can be polled with [Completion::get_result], waited on with [Completion::wait], or
+/// awaited with the `async` feature.
+#[derive(Debug, Clone)]
+pub struct Completion {
+    signal: Arc<FinishSignal>,
+    generation: u64,
+}
+
+impl Completion {
+    /// The reason the playback resolved, None while it is still going.
+    pub fn get_result(&self) -> Option<PlaybackEnd> {
+        self.signal.result(self.generation)
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.get_result().is_some()
+    }
+
+    /// Block the calling thread until resolved, None when `timeout` elapsed first.
+    pub fn wait(&self, timeout: Option<Duration>) -> Option<PlaybackEnd> {
+        self.signal
+            .wait_while(|| !self.is_complete(), timeout)
+            .then(|| self.get_result())
+            .flatten()
+    }
+}
+
+// Completions awaited by a task, woken by the waker thread once they resolve
+#[cfg(feature = "async")]
+static WAITING: Mutex<Vec<(Completion, Waker)>> = Mutex::new(Vec::new());
+
+#[cfg(feature = "async")]
+static WAKER_THREAD: OnceLock<Option<std::thread::Thread>> = OnceLock::new();
+
+/// Hand `waker` to the waker thread, which wakes it once `completion` resolves. The audio
+/// thread notifying the signal never runs a waker itself.
+#[cfg(feature = "async")]
+fn register_waker(completion: &Completion, waker: &Waker) {
+    let Ok(mut waiting) = WAITING.lock() else {
+        return;
+    };
+
+    let registered = waiting.iter_mut().find(|(waiting, _)| {
+        Arc::ptr_eq(&waiting.signal, &completion.signal)
+            && waiting.generation == completion.generation
+    });
+
+    match registered {
+        Some((_, registered)) => {
+            if !registered.will_wake(waker) {
+                registered.clone_from(waker);
+            }
+        }
+        None => waiting.push((completion.clone(), waker.clone())),
+    }
+
+    drop(waiting);
+
+    let thread = WAKER_THREAD.get_or_init(|| {
+        std::thread::Builder::new()
+            .name("estaudio-waker".to_string())
+            .spawn(waker_loop)
+            .ok()
+            .map(|handle| handle.thread().clone())
+    });
+
+    if let Some(thread) = thread {
+        thread.unpark();
+    }
+}
+
+#[cfg(feature = "async")]
+fn waker_loop() {
+    loop {
+        let resolved = {
+            let Ok(mut waiting) = WAITING.lock() else {
+                return;
+            };
+
+            if waiting.is_empty() {
+                drop(waiting);
+                std::thread::park();
+                continue;
+            }
+
+            let mut resolved = Vec::new();
+            let mut index = 0;
+            while index < waiting.len() {
+                if waiting[index].0.is_complete() {
+                    resolved.push(waiting.swap_remove(index).1);
+                } else {
+                    index += 1;
+                }
+            }
+
+            resolved
+        };
+
+        resolved.into_iter().for_each(Waker::wake);
+        std::thread::sleep(POLL_INTERVAL);
+    }
+}
+
+#[cfg(feature = "async")]
+impl Future for Completion {
+    type Output = PlaybackEnd;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        if let Some(result) = self.get_result() {
+            return Poll::Ready(result);
+        }
+
+        register_waker(&self, cx.waker());
+
+        // Notified between the first check and registering the waker
+        match self.get_result() {
+            Some(result) => Poll::Ready(result),
+            None => Poll::Pending,
+        }
+    }
+}
@@ -0,0 +1,47 @@
+use std::ops::{Add, AddAssign};
+
+/// Bytes held by a sample, track, mixer or device, see
+/// [Device::memory_usage](crate::Device::memory_usage).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MemoryUsage {
+    /// Decoded audio owned by the object, freed when it is dropped.
+    pub decoded: usize,
+    /// Decoded audio of files loaded through the file cache, shared with every other sample
+    /// and track reading the same file and only freed with the last of them.
+    pub shared: usize,
+    /// Working buffers used while mixing.
+    pub scratch: usize,
+}
+
+impl MemoryUsage {
+    pub fn total(&self) -> usize {
+        self.decoded + self.shared + self.scratch
+    }
+
+    pub(crate) fn f32_bytes(len: usize) -> usize {
+        len * std::mem::size_of::<f32>()
+    }
+
+    pub(crate) fn f64_bytes(len: usize) -> usize {
+        len * std::mem::size_of::<f64>()
+    }
+}
+
+impl Add for MemoryUsage {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        Self {
+            decoded: self.decoded + other.decoded,
+            shared: self.shared + other.shared,
+            scratch: self.scratch + other.scratch,
+        }
+    }
+}
+
+impl AddAssign for MemoryUsage {
+    fn add_assign(&mut self, other: Self) {
+        *self = *self + other;
+    }
+}
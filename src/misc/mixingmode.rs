@@ -0,0 +1,40 @@
+use crate::{
+    effects::Limiter,
+    math::{MathUtils, MathUtilsTrait as _},
+};
+
+/// How the sum of every source is brought back into range.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum MixingMode {
+    /// Plain sum, hard clipped to -1.0..1.0.
+    #[default]
+    Clip,
+    /// Plain sum through a peak limiter.
+    Limit,
+    /// Divide by the number of sources that produced audio in the current block.
+    Normalize,
+    /// Attenuate the sum by a fixed amount in dB, then hard clip.
+    Headroom(f32),
+}
+
+impl MixingMode {
+    pub(crate) fn apply(&self, limiter: &mut Limiter, buffer: &mut [f32], active_sources: usize) {
+        match self {
+            MixingMode::Clip => {}
+            MixingMode::Limit => limiter.process(buffer),
+            MixingMode::Normalize => {
+                if active_sources > 1 {
+                    let gain = 1.0 / active_sources as f32;
+                    buffer.iter_mut().for_each(|sample| *sample *= gain);
+                }
+            }
+            MixingMode::Headroom(db) => {
+                let gain = 10f32.powf(-db.abs() / 20.0);
+                buffer.iter_mut().for_each(|sample| *sample *= gain);
+            }
+        }
+
+        MathUtils::simd_clamp(buffer, -1.0, 1.0);
+    }
+}
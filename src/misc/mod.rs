@@ -1,2 +1,15 @@
 pub mod audioattributes;
+pub(crate) mod attributeobserver;
 pub mod audiopropertyhandler;
+pub(crate) mod customattributes;
+#[cfg(feature = "dasp")]
+pub(crate) mod dasp;
+pub(crate) mod bufferpool;
+pub(crate) mod denormal;
+pub mod envelope;
+pub(crate) mod finishsignal;
+pub(crate) mod memoryusage;
+pub mod mixingmode;
+pub(crate) mod pcmsample;
+pub mod precision;
+pub(crate) mod syncstart;
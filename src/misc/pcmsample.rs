@@ -0,0 +1,36 @@
+/// An integer or float sample format converted to f32 when building a
+/// [BufferInfoOwned](crate::BufferInfoOwned), see
+/// [BufferInfoOwned::from_interleaved](crate::BufferInfoOwned::from_interleaved).
+pub trait PcmSample: Copy {
+    /// The sample scaled to -1.0..1.0.
+    fn to_f32(self) -> f32;
+}
+
+impl PcmSample for f32 {
+    #[inline(always)]
+    fn to_f32(self) -> f32 {
+        self
+    }
+}
+
+impl PcmSample for i16 {
+    #[inline(always)]
+    fn to_f32(self) -> f32 {
+        self as f32 / 32768.0
+    }
+}
+
+impl PcmSample for i32 {
+    #[inline(always)]
+    fn to_f32(self) -> f32 {
+        (self as f64 / 2147483648.0) as f32
+    }
+}
+
+/// Unsigned 8-bit, centered on 128 as in 8-bit WAV files.
+impl PcmSample for u8 {
+    #[inline(always)]
+    fn to_f32(self) -> f32 {
+        (self as f32 - 128.0) / 128.0
+    }
+}
@@ -0,0 +1,47 @@
+use crate::math::{MathUtils, MathUtilsTrait as _};
+
+/// Sample format used when summing sources, the result is always handed on as f32.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Precision {
+    /// Sum directly into f32 buffers.
+    #[default]
+    Single,
+    /// Sum into an f64 bus and convert back to f32 once per block, keeps the rounding error of
+    /// many summed or cascaded sources out of the output.
+    ///
+    /// Only the sum is f64, the sources, AudioFX, the resampler and the effects of a mixer
+    /// still run on f32 after it.
+    Double,
+}
+
+/// Destination that rendered f32 sources are added on top of.
+pub(crate) trait MixBus {
+    /// Add `samples` on top of the first `samples.len()` entries.
+    fn accumulate(&mut self, samples: &[f32]);
+}
+
+impl MixBus for [f32] {
+    #[inline(always)]
+    fn accumulate(&mut self, samples: &[f32]) {
+        MathUtils::simd_add(&mut self[..samples.len()], samples);
+    }
+}
+
+impl MixBus for [f64] {
+    #[inline(always)]
+    fn accumulate(&mut self, samples: &[f32]) {
+        for (bus, sample) in self.iter_mut().zip(samples.iter()) {
+            *bus += *sample as f64;
+        }
+    }
+}
+
+/// Convert an f64 bus back to f32, `src` and `dst` must have the same length.
+pub(crate) fn narrow(src: &[f64], dst: &mut [f32]) {
+    assert_eq!(src.len(), dst.len());
+
+    for (dst, src) in dst.iter_mut().zip(src.iter()) {
+        *dst = *src as f32;
+    }
+}
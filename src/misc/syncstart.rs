@@ -0,0 +1,37 @@
+use std::sync::{
+    Arc,
+    atomic::{AtomicU64, Ordering},
+};
+
+use crate::device::Device;
+
+// Blocks of the device a group change is scheduled ahead, so every source is released before
+// the frame it starts on is rendered
+const LEAD_BLOCKS: u64 = 2;
+
+/// Device frames a group of sources played on `device` is scheduled ahead, shared by
+/// [LinkGroup](crate::LinkGroup) and [Transport](crate::Transport).
+pub(crate) fn lead_frames(device: &Device) -> u64 {
+    device.block_size as u64 * LEAD_BLOCKS
+}
+
+/// Device frame `lead` frames ahead of the first clock in `clocks`, None when no source is
+/// attached to a device.
+pub(crate) fn start_time<'a>(
+    clocks: impl IntoIterator<Item = Option<&'a Arc<AtomicU64>>>,
+    lead: u64,
+) -> Option<u64> {
+    clocks
+        .into_iter()
+        .flatten()
+        .next()
+        .map(|clock| clock.load(Ordering::Acquire) + lead)
+}
+
+/// Lock every source before any change so the audio thread sees all or none of them.
+pub(crate) fn lock_all<'a, S, G, E>(
+    sources: &'a [S],
+    lock: impl FnMut(&'a S) -> Result<G, E>,
+) -> Result<Vec<G>, E> {
+    sources.iter().map(lock).collect()
+}
@@ -1,19 +1,39 @@
-use std::sync::{
-    Arc, Mutex, Weak,
-    atomic::{AtomicBool, Ordering},
+use std::{
+    sync::{
+        Arc, Mutex, Weak,
+        atomic::{AtomicBool, AtomicU64, Ordering},
+    },
+    time::Instant,
 };
 
 use crate::{
+    device::{ducking::SourceDucking, snapshot::SourceKind},
+    encoder::recorder::RecorderTap,
     effects::{
-        AudioFX, AudioPanner, SpatializationListener, AudioVolume, ChannelConverter, Resampler,
+        AudioEffect, AudioFX, AudioFXError, AudioPanner, ListenerSelection, Spatialization,
+        SpatializationListener, AudioVolume, ChannelConverter, Limiter, Resampler,
     },
     math::{MathUtils, MathUtilsTrait},
-    mixer::MixerError,
+    misc::{
+        attributeobserver::AutomatedChanges,
+        audioattributes::AudioAttributes,
+        audiopropertyhandler::PropertyError,
+        bufferpool::{BufferPool, ScratchBuffer},
+        customattributes::{AttributeValue, CustomAttributes},
+        denormal,
+        envelope::{ENVELOPE_STEP, Envelopes},
+        finishsignal::{Completion, FinishSignal, PlaybackEnd},
+        memoryusage::MemoryUsage,
+        mixingmode::MixingMode,
+        precision::{self, MixBus, Precision},
+    },
+    mixer::{MixerError, TapPoint},
+    utils,
     sample::sampleinner::{SampleChannelHandle as SampleChannel, SampleChannelStatus},
     track::inner::TrackChannel,
 };
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum MixerEntry {
     TrackChannel {
         ref_id: usize,
@@ -35,30 +55,97 @@ pub enum MixerEntry {
     },
 }
 
+impl MixerEntry {
+    pub fn key(&self) -> (SourceKind, usize) {
+        match self {
+            MixerEntry::TrackChannel { ref_id, .. } => (SourceKind::Track, *ref_id),
+            MixerEntry::MixerChannel { ref_id, .. } => (SourceKind::Mixer, *ref_id),
+            MixerEntry::SampleChannel { ref_id, .. } => (SourceKind::Sample, *ref_id),
+        }
+    }
+
+    /// Whether the child is still alive, entries are not removed when it is dropped.
+    pub fn is_alive(&self) -> bool {
+        match self {
+            MixerEntry::TrackChannel { channel, .. } => channel.strong_count() > 0,
+            MixerEntry::MixerChannel { mixer, .. } => mixer.strong_count() > 0,
+            MixerEntry::SampleChannel { channel, .. } => channel.strong_count() > 0,
+        }
+    }
+}
+
 #[allow(dead_code)]
 pub(crate) struct MixerChannel {
     pub ref_id: usize,
     pub marked_as_deleted: bool,
-    pub normalize_output: bool,
+    pub mixing_mode: Option<MixingMode>,
+    pub limiter: Limiter,
+    pub precision: Precision,
 
     pub entries: Vec<MixerEntry>,
+    // Entries by kind and ref id, see [Mixer::set_muted](crate::Mixer::set_muted)
+    pub muted: Vec<(SourceKind, usize)>,
+    pub soloed: Vec<(SourceKind, usize)>,
     pub is_playing: Arc<AtomicBool>,
+    pub finished: Arc<FinishSignal>,
     pub max_length: usize,
     pub mixer_position: usize,
     pub is_infinite: bool,
     pub dsp_callback: Option<Box<dyn FnMut(&[f32]) + Send + 'static>>,
+    pub callback_tap: TapPoint,
     pub channel_converter: ChannelConverter,
 
     pub channel_count: usize,
     pub sample_rate: f32,
+    pub block_size: usize,
 
     pub buffer: Vec<f32>,
     pub intermediate_buffer: Vec<f32>,
+    // Children are summed here instead of `buffer` with [Precision::Double], empty otherwise
+    pub accumulator: Vec<f64>,
 
     pub resampler: Resampler,
     pub panner: AudioPanner,
     pub volume: AudioVolume,
     pub fx: Option<AudioFX>,
+    // Run after the volume and pan, see [Mixer::set_effects](crate::Mixer::set_effects)
+    pub effects: Vec<Box<dyn AudioEffect>>,
+    // Positions the whole submix as one source against the device listener
+    pub spatializer: Option<Spatialization>,
+    // Device listener the submix is rendered against, children follow the same listener
+    pub listener: ListenerSelection,
+    // Time of the last position update of the spatializer
+    pub last_time: Instant,
+
+    // Ducking group, see [DuckingRule](crate::DuckingRule)
+    pub group: Option<u32>,
+    // Set by the device for the block being rendered, applied ahead of the spatial stage
+    pub ducking: SourceDucking,
+
+    pub custom_attributes: CustomAttributes,
+
+    // Mixer position and fade length in frames, see [Mixer::stop_at](crate::Mixer::stop_at)
+    pub stop_at: Option<(usize, usize)>,
+    // Frames left and total of the fade started by stop_at
+    pub fade_out: Option<(usize, usize)>,
+    // Device time the output starts at, see [Mixer::play_at](crate::Mixer::play_at)
+    pub start_at: Option<u64>,
+    // Mixer frames played over and over, see [Transport::set_loop_region](crate::Transport::set_loop_region)
+    pub loop_region: Option<(usize, usize)>,
+    // Device clock, set when attached to a device
+    pub clock: Option<Arc<AtomicU64>>,
+
+    // Evaluated from the mixer position while reading, see [Mixer::set_envelope](crate::Mixer::set_envelope)
+    pub envelopes: Envelopes,
+    // Envelope changes for the observers, see [Mixer::on_attribute_changed](crate::Mixer::on_attribute_changed)
+    pub automated: Option<AutomatedChanges>,
+
+    // Set when attached to a device, shared with every child
+    pub pool: Option<Arc<BufferPool>>,
+
+    // Receives the processed output while recording, see [Mixer::start_recording](crate::Mixer::start_recording)
+    pub recorder: Option<RecorderTap>,
+    pub recorder_tap: TapPoint,
 }
 
 impl std::fmt::Debug for MixerChannel {
@@ -66,7 +153,8 @@ impl std::fmt::Debug for MixerChannel {
         f.debug_struct("MixerChannel")
             .field("ref_id", &self.ref_id)
             .field("marked_as_deleted", &self.marked_as_deleted)
-            .field("normalize_output", &self.normalize_output)
+            .field("mixing_mode", &self.mixing_mode)
+            .field("precision", &self.precision)
             .field("entries_count", &self.entries.len())
             .field("is_playing", &self.is_playing.load(Ordering::SeqCst))
             .field("max_length", &self.max_length)
@@ -74,13 +162,52 @@ impl std::fmt::Debug for MixerChannel {
             .field("is_infinite", &self.is_infinite)
             .field("channel_count", &self.channel_count)
             .field("sample_rate", &self.sample_rate)
+            .field(
+                "spatializer",
+                &self
+                    .spatializer
+                    .as_ref()
+                    .map(|_| "AudioSpatialization { ... }"),
+            )
             .finish()
     }
 }
 
 #[allow(dead_code)]
 impl MixerChannel {
-    pub fn new(channels: usize, sample_rate: f32, ref_id: usize) -> Result<Self, MixerError> {
+    /// Buffers of the mixer and the memory of every source added to it.
+    pub fn memory_usage(&self) -> MemoryUsage {
+        let mut usage = MemoryUsage {
+            scratch: MemoryUsage::f32_bytes(
+                self.buffer.capacity() + self.intermediate_buffer.capacity(),
+            ) + MemoryUsage::f64_bytes(self.accumulator.capacity()),
+            ..Default::default()
+        };
+
+        for entry in self.entries.iter() {
+            usage += match entry {
+                MixerEntry::TrackChannel { channel, .. } => channel
+                    .upgrade()
+                    .and_then(|track| track.lock().ok().map(|t| t.memory_usage())),
+                MixerEntry::MixerChannel { mixer, .. } => mixer
+                    .upgrade()
+                    .and_then(|mixer| mixer.lock().ok().map(|m| m.memory_usage())),
+                MixerEntry::SampleChannel { channel, .. } => channel
+                    .upgrade()
+                    .and_then(|sample| sample.lock().ok().map(|s| s.memory_usage())),
+            }
+            .unwrap_or_default();
+        }
+
+        usage
+    }
+
+    pub fn new(
+        channels: usize,
+        sample_rate: f32,
+        block_size: usize,
+        ref_id: usize,
+    ) -> Result<Self, MixerError> {
         if channels < 1 || channels > 8 {
             return Err(MixerError::InvalidChannelCount(channels));
         }
@@ -89,6 +216,10 @@ impl MixerChannel {
             return Err(MixerError::InvalidSampleRate(sample_rate as f32));
         }
 
+        if block_size == 0 {
+            return Err(MixerError::InvalidBlockSize(block_size));
+        }
+
         let is_playing = Arc::new(AtomicBool::new(false));
 
         let resampler = Resampler::new(channels, sample_rate).map_err(MixerError::from_other)?;
@@ -101,34 +232,383 @@ impl MixerChannel {
         let inner = MixerChannel {
             ref_id,
             marked_as_deleted: false,
-            normalize_output: false,
+            mixing_mode: None,
+            limiter: Limiter::new(channels, sample_rate),
+            precision: Precision::Single,
             entries: Vec::new(),
+            muted: Vec::new(),
+            soloed: Vec::new(),
             channel_converter,
             is_playing: is_playing.clone(),
+            finished: Arc::new(FinishSignal::default()),
             max_length: 0,
             mixer_position: 0,
             is_infinite: false,
             dsp_callback: None,
+            callback_tap: TapPoint::default(),
             channel_count: channels as usize,
             sample_rate,
-            buffer: vec![0.0; 4096 * channels as usize],
-            intermediate_buffer: vec![0.0; 4096 * channels as usize],
+            block_size,
+            buffer: vec![0.0; utils::block_buffer_len(block_size, channels)],
+            intermediate_buffer: vec![0.0; utils::block_buffer_len(block_size, channels)],
+            accumulator: Vec::new(),
             resampler,
             panner,
             volume,
             fx: None,
+            effects: Vec::new(),
+            spatializer: None,
+            listener: ListenerSelection::default(),
+            last_time: Instant::now(),
+            group: None,
+            ducking: SourceDucking::default(),
+            custom_attributes: CustomAttributes::default(),
+            stop_at: None,
+            fade_out: None,
+            start_at: None,
+            loop_region: None,
+            clock: None,
+            envelopes: Envelopes::default(),
+            automated: None,
+            pool: None,
+            recorder: None,
+            recorder_tap: TapPoint::default(),
         };
 
         Ok(inner)
     }
 
-    pub fn set_normalize_output(&mut self, value: bool) {
-        self.normalize_output = value;
+    pub fn set_block_size(&mut self, block_size: usize) -> Result<(), MixerError> {
+        if block_size == 0 {
+            return Err(MixerError::InvalidBlockSize(block_size));
+        }
+
+        let size = utils::block_buffer_len(block_size, self.channel_count);
+        self.buffer.resize(size, 0.0);
+        self.intermediate_buffer.resize(size, 0.0);
+        if self.precision == Precision::Double {
+            self.accumulator.resize(size, 0.0);
+        }
+        self.block_size = block_size;
+
+        Ok(())
+    }
+
+    pub fn set_precision(&mut self, precision: Precision) {
+        self.accumulator = match precision {
+            Precision::Single => Vec::new(),
+            Precision::Double => vec![0.0; self.buffer.len()],
+        };
+
+        self.precision = precision;
+    }
+
+    pub fn set_mixing_mode(&mut self, mode: Option<MixingMode>) {
+        self.limiter.reset();
+        self.mixing_mode = mode;
+    }
+
+    // Current value of an attribute automated from the audio thread
+    fn automated_value(&self, attribute: AudioAttributes) -> Option<f32> {
+        match attribute {
+            AudioAttributes::Volume => Some(self.volume.volume),
+            AudioAttributes::Pan => Some(self.panner.pan),
+            AudioAttributes::Balance => Some(self.panner.get_balance()),
+            AudioAttributes::SampleRate => Some(self.resampler.sample_rate),
+            AudioAttributes::FXTempo => self.fx.as_ref().map(|fx| fx.tempo),
+            AudioAttributes::FXPitch => self.fx.as_ref().map(|fx| fx.octave),
+            _ => None,
+        }
+    }
+
+    pub fn set_attribute_f32(
+        &mut self,
+        attribute: AudioAttributes,
+        value: f32,
+    ) -> Result<(), PropertyError> {
+        match attribute {
+            AudioAttributes::SampleRate => {
+                self.resampler.set_target_sample_rate(value);
+                Ok(())
+            }
+            AudioAttributes::Volume => {
+                self.volume.set_volume(value);
+                Ok(())
+            }
+            AudioAttributes::Pan => {
+                self.panner.set_pan(value);
+                Ok(())
+            }
+            AudioAttributes::Balance => {
+                self.panner.set_balance(value);
+                Ok(())
+            }
+            AudioAttributes::FXPitch => {
+                if let Some(fx) = self.fx.as_mut() {
+                    if let Err(e) = fx.set_octave(value) {
+                        return Err(PropertyError::from_other(e));
+                    }
+
+                    Ok(())
+                } else {
+                    Err(PropertyError::from_other(AudioFXError::NotEnabled))
+                }
+            }
+            AudioAttributes::FXTempo => {
+                if let Some(fx) = self.fx.as_mut() {
+                    if let Err(e) = fx.set_tempo(value) {
+                        return Err(PropertyError::from_other(e));
+                    }
+
+                    Ok(())
+                } else {
+                    Err(PropertyError::from_other(AudioFXError::NotEnabled))
+                }
+            }
+            _ => Err(PropertyError::UnsupportedAttribute("Unknown attribute")),
+        }
+    }
+
+    pub fn set_attribute_bool(
+        &mut self,
+        attribute: AudioAttributes,
+        value: bool,
+    ) -> Result<(), PropertyError> {
+        match attribute {
+            AudioAttributes::FXEnabled => {
+                if value {
+                    let fx = AudioFX::new(self.channel_count, self.resampler.sample_rate)
+                        .map_err(PropertyError::from_other)?;
+
+                    self.fx = Some(fx);
+                } else {
+                    self.fx = None;
+                }
+
+                let seek_pos = self.mixer_position;
+                _ = self.seek(Some(seek_pos));
+
+                Ok(())
+            }
+            AudioAttributes::SpatializationEnabled => {
+                if value {
+                    if self.spatializer.is_none() {
+                        let spatializer =
+                            Spatialization::new(self.channel_count, self.channel_count)
+                                .map_err(PropertyError::from_other)?;
+
+                        self.spatializer = Some(spatializer);
+                    }
+                } else {
+                    self.spatializer = None;
+                }
+
+                Ok(())
+            }
+            AudioAttributes::ForceMono => {
+                self.panner.force_mono = value;
+                Ok(())
+            }
+            AudioAttributes::SwapChannels => {
+                self.panner.swap_channels = value;
+                Ok(())
+            }
+            _ => Err(PropertyError::UnsupportedAttribute("Unknown attribute")),
+        }
+    }
+
+    pub fn set_attribute(
+        &mut self,
+        attribute: AudioAttributes,
+        value: AttributeValue,
+    ) -> Result<(), PropertyError> {
+        match value {
+            AttributeValue::F32(value) => self.set_attribute_f32(attribute, value),
+            AttributeValue::Bool(value) => self.set_attribute_bool(attribute, value),
+        }
     }
 
+    /// Read `frame_count` frames, evaluating the envelopes every [ENVELOPE_STEP] frames while
+    /// any is attached and splitting the block where a scheduled stop starts. A device block
+    /// larger than the mixer block size is rendered in chunks of the mixer block size.
     pub fn read(
         &mut self,
-        _spatialization: Option<&mut SpatializationListener>,
+        mut spatialization: Option<&mut SpatializationListener>,
+        channel_converter: &mut ChannelConverter,
+        buffer: &mut [f32],
+        temp_buffer: &mut [f32],
+        frame_count: usize,
+    ) -> Result<usize, MixerError> {
+        if self.envelopes.is_empty()
+            && self.stop_at.is_none()
+            && self.fade_out.is_none()
+            && self.start_at.is_none()
+            && self.loop_region.is_none()
+            && frame_count <= self.block_size
+        {
+            return self.read_block(
+                spatialization,
+                channel_converter,
+                buffer,
+                temp_buffer,
+                frame_count,
+            );
+        }
+
+        if !self.is_playing.load(Ordering::SeqCst) {
+            return Ok(0);
+        }
+
+        let channels = channel_converter.get_output_channels() as usize;
+        let pool = self.pool.clone();
+        // Returning no frames detaches the mixer, so a block without a free buffer is left
+        // silent instead and counted in the device stats
+        let Some(mut rendered_output) = ScratchBuffer::new(pool.as_deref(), frame_count * channels)
+        else {
+            buffer[..frame_count * channels].fill(0.0);
+            return Ok(frame_count);
+        };
+        let mut rendered = 0;
+
+        // Only needed while a start is pending
+        let block_time = self
+            .clock
+            .as_ref()
+            .filter(|_| self.start_at.is_some())
+            .map(|clock| clock.load(Ordering::Acquire));
+
+        while rendered < frame_count {
+            let mut count = (frame_count - rendered).min(self.block_size);
+
+            if let (Some(start_time), Some(block_time)) = (self.start_at, block_time) {
+                let time = block_time + rendered as u64;
+
+                if start_time > time {
+                    let silence = count.min((start_time - time) as usize);
+                    rendered_output[rendered * channels..(rendered + silence) * channels]
+                        .fill(0.0);
+
+                    self.ducking.skip(silence);
+                    rendered += silence;
+                    continue;
+                }
+
+                self.start_at = None;
+            }
+
+            match self.stop_at {
+                Some((position, fade)) if position <= self.mixer_position => {
+                    self.stop_at = None;
+
+                    if fade == 0 {
+                        self.is_playing.store(false, Ordering::SeqCst);
+                        self.finished.notify(PlaybackEnd::Stopped);
+                        break;
+                    }
+
+                    self.fade_out.get_or_insert((fade, fade));
+                }
+                Some((position, _)) => count = count.min(position - self.mixer_position),
+                None => {}
+            }
+
+            if let Some((start, end)) = self.loop_region {
+                count = count.min(self.wrap_loop_region(start, end)?);
+            }
+
+            if !self.envelopes.is_empty() {
+                self.apply_envelopes();
+                count = count.min(ENVELOPE_STEP);
+            }
+
+            let read = self.read_block(
+                spatialization.as_deref_mut(),
+                channel_converter,
+                buffer,
+                temp_buffer,
+                count,
+            )?;
+
+            let mut faded = false;
+            if let Some((remaining, total)) = self.fade_out.as_mut() {
+                for frame in buffer[..read * channels].chunks_exact_mut(channels) {
+                    let gain = *remaining as f32 / *total as f32;
+                    frame.iter_mut().for_each(|sample| *sample *= gain);
+                    *remaining = remaining.saturating_sub(1);
+                }
+
+                faded = *remaining == 0;
+            }
+
+            rendered_output[rendered * channels..(rendered + read) * channels]
+                .copy_from_slice(&buffer[..read * channels]);
+            rendered += read;
+
+            if faded {
+                self.fade_out = None;
+                self.is_playing.store(false, Ordering::SeqCst);
+                self.finished.notify(PlaybackEnd::Stopped);
+                break;
+            }
+
+            if read < count || !self.is_playing.load(Ordering::SeqCst) {
+                break;
+            }
+        }
+
+        buffer[..rendered * channels].copy_from_slice(&rendered_output[..rendered * channels]);
+        Ok(rendered)
+    }
+
+    /// Seek back to `start` once the mixer reached `end`, returns the output frames left
+    /// until it reaches `end` again.
+    fn wrap_loop_region(&mut self, start: usize, end: usize) -> Result<usize, MixerError> {
+        if self.mixer_position >= end {
+            self.seek(Some(start))?;
+            self.finished.notify(PlaybackEnd::Looped);
+        }
+
+        let tempo = match self.fx.as_ref() {
+            Some(fx) if !fx.tempo_bypass() => fx.tempo,
+            _ => 1.0,
+        };
+
+        let remaining = end.saturating_sub(self.mixer_position) as f32;
+        let frames = (remaining / (self.resampler.ratio() * tempo)).ceil() as usize;
+
+        Ok(frames.max(1))
+    }
+
+    /// Set an attribute from the audio thread, the change is queued for the observers when it
+    /// moved the value. False when the value was rejected.
+    fn apply_automated(&mut self, attribute: AudioAttributes, value: f32) -> bool {
+        let previous = self.automated_value(attribute);
+        if self.set_attribute_f32(attribute, value).is_err() {
+            return false;
+        }
+
+        let value = self.automated_value(attribute).unwrap_or(value);
+        if let Some(automated) = self.automated.as_mut().filter(|_| previous != Some(value)) {
+            automated.push(attribute, value);
+        }
+
+        true
+    }
+
+    fn apply_envelopes(&mut self) {
+        let time = self.mixer_position as f64 / self.sample_rate as f64;
+        let mut envelopes = std::mem::take(&mut self.envelopes);
+
+        envelopes.apply_at(time, |attribute, value| {
+            self.apply_automated(attribute, value)
+        });
+
+        self.envelopes = envelopes;
+    }
+
+    fn read_block(
+        &mut self,
+        spatialization: Option<&mut SpatializationListener>,
         channel_converter: &mut ChannelConverter,
         buffer: &mut [f32],
         temp_buffer: &mut [f32],
@@ -138,6 +618,10 @@ impl MixerChannel {
             return Ok(0);
         }
 
+        if frame_count > self.block_size {
+            return Err(MixerError::InvalidBlockSize(frame_count));
+        }
+
         let sample_count = frame_count as usize * self.channel_count;
         let required_frame_count = self.resampler.get_required_input(frame_count).unwrap_or(0);
 
@@ -170,6 +654,12 @@ impl MixerChannel {
             let fx = self.fx.as_mut().unwrap();
 
             if fx.frame_available > 0 {
+                denormal::dither(crate::macros::make_slice_mut!(
+                    buffer,
+                    target_frame_count,
+                    self.channel_count
+                ));
+
                 fx.process(
                     crate::macros::make_slice!(buffer, target_frame_count, self.channel_count),
                     crate::macros::make_slice_mut!(
@@ -225,6 +715,15 @@ impl MixerChannel {
                 );
             }
 
+            feed_taps(
+                &mut self.dsp_callback,
+                self.callback_tap,
+                &mut self.recorder,
+                self.recorder_tap,
+                TapPoint::PreFader,
+                &self.buffer[..sample_count],
+            );
+
             self.panner
                 .process(&self.buffer, temp_buffer)
                 .map_err(MixerError::from_other)?;
@@ -233,22 +732,82 @@ impl MixerChannel {
                 .process(&temp_buffer, &mut self.buffer)
                 .map_err(MixerError::from_other)?;
 
-            if self.normalize_output {
-                for i in 0..sample_count {
-                    buffer[i] /= mixed_sources as f32;
-                }
+            let size = crate::macros::array_len_from!(frame_count, self.channel_count);
+
+            for effect in self.effects.iter_mut() {
+                effect.process(&mut self.buffer[..size], self.channel_count);
+            }
+
+            if let Some(mode) = self.mixing_mode {
+                mode.apply(&mut self.limiter, &mut self.buffer[..size], mixed_sources);
+            }
+
+            self.ducking
+                .process(&mut self.buffer[..size], self.channel_count);
+
+            if let (Some(spatializer), Some(listener)) =
+                (self.spatializer.as_mut(), spatialization)
+            {
+                let output = &mut self.buffer[..size];
+                let temp_buffer = &mut temp_buffer[..size];
+                let hrtf = listener.hrtf.clone();
+
+                spatializer.process_occlusion(
+                    output,
+                    self.channel_count,
+                    self.resampler.target_sample_rate,
+                );
+                spatializer.process_reverb_send(listener, output, self.channel_count);
+
+                let result = match hrtf.as_deref() {
+                    _ if listener.ambisonics.is_some() => spatializer.process_ambisonics(
+                        listener,
+                        output,
+                        temp_buffer,
+                        self.channel_count,
+                    ),
+                    Some(hrtf) => spatializer.process_hrtf(
+                        listener,
+                        hrtf,
+                        output,
+                        temp_buffer,
+                        self.channel_count,
+                    ),
+                    None => spatializer.process(listener, output, temp_buffer),
+                };
+
+                result.map_err(MixerError::from_other)?;
+
+                MathUtils::simd_copy(temp_buffer.as_ref(), output.as_mut());
             }
 
-            let size = crate::macros::array_len_from!(frame_count, self.channel_count);
             MathUtils::simd_copy(self.buffer[..size].as_ref(), buffer[..size].as_mut());
+        } else {
+            MathUtils::simd_set(buffer[..sample_count].as_mut(), 0.0);
+            self.ducking.skip(frame_count);
+
+            feed_taps(
+                &mut self.dsp_callback,
+                self.callback_tap,
+                &mut self.recorder,
+                self.recorder_tap,
+                TapPoint::PreFader,
+                &buffer[..sample_count],
+            );
         }
 
-        if let Some(callback) = self.dsp_callback.as_mut() {
-            callback(&buffer[..sample_count]);
-        }
+        feed_taps(
+            &mut self.dsp_callback,
+            self.callback_tap,
+            &mut self.recorder,
+            self.recorder_tap,
+            TapPoint::PostFader,
+            &buffer[..sample_count],
+        );
 
         if self.mixer_position >= self.max_length && !self.is_infinite {
             self.is_playing.store(false, Ordering::SeqCst);
+            self.finished.notify(PlaybackEnd::Ended);
         }
 
         self.channel_converter
@@ -271,8 +830,16 @@ impl MixerChannel {
 
         // Clear intermediate buffer
         MathUtils::simd_set(self.buffer[..sample_count].as_mut(), 0.0);
+        if self.precision == Precision::Double {
+            MathUtils::simd_set(self.accumulator[..sample_count].as_mut(), 0.0);
+        }
 
         for entry in self.entries.iter_mut() {
+            // Muted entries are still read so they stay in sync with the mixer position
+            let key = entry.key();
+            let audible = !self.muted.contains(&key)
+                && (self.soloed.is_empty() || self.soloed.contains(&key));
+
             match entry {
                 MixerEntry::TrackChannel {
                     channel,
@@ -311,7 +878,7 @@ impl MixerChannel {
                         )
                         .map_err(MixerError::from_other)?;
 
-                    if channel_frame_count > 0 {
+                    if channel_frame_count > 0 && audible {
                         let size =
                             crate::macros::array_len_from!(channel_frame_count, self.channel_count);
 
@@ -319,10 +886,12 @@ impl MixerChannel {
                             MathUtils::simd_not_any(&self.intermediate_buffer[..size], 0.0)
                                 as usize;
 
-                        MathUtils::simd_add(
-                            self.intermediate_buffer[..size].as_mut(),
-                            self.buffer[..size].as_ref(),
-                        );
+                        match self.precision {
+                            Precision::Single => self.buffer[..size]
+                                .accumulate(&self.intermediate_buffer[..size]),
+                            Precision::Double => self.accumulator[..size]
+                                .accumulate(&self.intermediate_buffer[..size]),
+                        }
                     }
                 }
                 MixerEntry::MixerChannel {
@@ -360,7 +929,7 @@ impl MixerChannel {
                         read_frames,
                     )?;
 
-                    if mixer_frame_count > 0 {
+                    if mixer_frame_count > 0 && audible {
                         let size =
                             crate::macros::array_len_from!(mixer_frame_count, self.channel_count);
 
@@ -368,10 +937,12 @@ impl MixerChannel {
                             MathUtils::simd_not_any(&self.intermediate_buffer[..size], 0.0)
                                 as usize;
 
-                        MathUtils::simd_add(
-                            self.intermediate_buffer[..size].as_mut(),
-                            self.buffer[..size].as_ref(),
-                        );
+                        match self.precision {
+                            Precision::Single => self.buffer[..size]
+                                .accumulate(&self.intermediate_buffer[..size]),
+                            Precision::Double => self.accumulator[..size]
+                                .accumulate(&self.intermediate_buffer[..size]),
+                        }
                     }
                 }
                 MixerEntry::SampleChannel {
@@ -410,7 +981,7 @@ impl MixerChannel {
                         )
                         .map_err(MixerError::from_other)?;
 
-                    if channel_frame_count > 0 {
+                    if channel_frame_count > 0 && audible {
                         let size =
                             crate::macros::array_len_from!(channel_frame_count, self.channel_count);
 
@@ -418,26 +989,42 @@ impl MixerChannel {
                             MathUtils::simd_not_any(&self.intermediate_buffer[..size], 0.0)
                                 as usize;
 
-                        MathUtils::simd_add(
-                            self.intermediate_buffer[..size].as_mut(),
-                            self.buffer[..size].as_ref(),
-                        );
+                        match self.precision {
+                            Precision::Single => self.buffer[..size]
+                                .accumulate(&self.intermediate_buffer[..size]),
+                            Precision::Double => self.accumulator[..size]
+                                .accumulate(&self.intermediate_buffer[..size]),
+                        }
                     }
                 }
             }
         }
 
+        if self.precision == Precision::Double {
+            precision::narrow(
+                &self.accumulator[..sample_count],
+                &mut self.buffer[..sample_count],
+            );
+        }
+
         self.mixer_position += frame_count;
 
         Ok(mixed_sources)
     }
 
+    /// Mixer frame currently coming out, accounting for the FX tempo and latency.
+    pub fn get_played_position(&self) -> usize {
+        match self.fx.as_ref() {
+            Some(fx) => self.mixer_position.saturating_sub(fx.get_source_latency()),
+            None => self.mixer_position,
+        }
+    }
+
     pub fn is_playing(&self) -> bool {
         self.is_playing.load(Ordering::SeqCst)
     }
 
     pub fn seek(&mut self, position: Option<usize>) -> Result<usize, MixerError> {
-        self.mixer_position = 0;
         let mut max_channel_seeked = 0;
         let position = position.unwrap_or(0);
 
@@ -540,6 +1127,11 @@ impl MixerChannel {
             }
         }
 
+        // Children are seeked relative to their delay, the mixer timeline follows the target
+        // so delayed children start where they should. Pre-rolling the fx below moves it
+        // forward by the latency together with the children.
+        self.mixer_position = position;
+
         // Well, if infinite we have to eat the latency
         if self.fx.is_some() && !self.is_infinite {
             let input_latency = {
@@ -549,7 +1141,11 @@ impl MixerChannel {
             };
 
             if input_latency > 0 {
-                let mut temp_buffer = vec![0.0; (input_latency as usize) * self.channel_count];
+                let pool = self.pool.clone();
+                let len = input_latency as usize * self.channel_count;
+                let Some(mut temp_buffer) = ScratchBuffer::new(pool.as_deref(), len) else {
+                    return Err(MixerError::ScratchExhausted(len));
+                };
                 self.mix_children_into_buffer(&mut temp_buffer, input_latency)?;
 
                 let fx = self.fx.as_mut().unwrap();
@@ -562,7 +1158,7 @@ impl MixerChannel {
         Ok(max_channel_seeked)
     }
 
-    fn compute_mixer_length(&mut self) -> Result<usize, MixerError> {
+    pub fn compute_mixer_length(&mut self) -> Result<usize, MixerError> {
         let mut max_length = 0;
         let mut has_infinite = false;
 
@@ -586,9 +1182,11 @@ impl MixerChannel {
                     let actual_length = channel.reader.pcm_length;
 
                     let duration = duration.unwrap_or(actual_length);
-                    let end_pcm = start_pcm + duration;
+                    let end_pcm = start_pcm.saturating_add(duration);
 
-                    has_infinite = has_infinite || channel.is_looping.load(Ordering::SeqCst);
+                    has_infinite = has_infinite
+                        || channel.is_looping.load(Ordering::SeqCst)
+                        || channel.reader.generator.is_some();
                     max_length = max_length.max(end_pcm);
                 }
                 MixerEntry::SampleChannel {
@@ -609,7 +1207,7 @@ impl MixerChannel {
                     let actual_length = channel.reader.pcm_length;
 
                     let duration = duration.unwrap_or(actual_length);
-                    let end_pcm = start_pcm + duration;
+                    let end_pcm = start_pcm.saturating_add(duration);
 
                     max_length = max_length.max(end_pcm);
                 }
@@ -631,7 +1229,7 @@ impl MixerChannel {
                     let actual_length = mixer.max_length;
 
                     let duration = duration.unwrap_or(actual_length);
-                    let end_pcm = start_pcm + duration;
+                    let end_pcm = start_pcm.saturating_add(duration);
 
                     has_infinite = has_infinite || mixer.is_infinite;
                     max_length = max_length.max(end_pcm);
@@ -657,7 +1255,10 @@ impl MixerChannel {
         };
 
         let ref_id = match channel_up.lock() {
-            Ok(channel) => channel.ref_id,
+            Ok(mut channel) => {
+                channel.pool = self.pool.clone();
+                channel.ref_id
+            }
             Err(_) => return Err(MixerError::InvalidOperation("Failed to lock TrackChannel")),
         };
 
@@ -687,7 +1288,11 @@ impl MixerChannel {
         };
 
         let ref_id = match mixer_up.lock() {
-            Ok(mixer) => mixer.ref_id,
+            Ok(mut mixer) => {
+                let pool = self.pool.clone();
+                Self::recursive_set_pool(&mut mixer, pool, 0);
+                mixer.ref_id
+            }
             Err(_) => return Err(MixerError::InvalidOperation("Failed to lock MixerChannel")),
         };
 
@@ -719,7 +1324,9 @@ impl MixerChannel {
         if let Some(index) = self.entries.iter().position(|entry| {
             matches!(entry, MixerEntry::TrackChannel { ref_id: entry_ref_id, .. } if *entry_ref_id == ref_id)
         }) {
-            self.entries.remove(index);
+            let key = self.entries.remove(index).key();
+            self.muted.retain(|muted| *muted != key);
+            self.soloed.retain(|soloed| *soloed != key);
             self.compute_mixer_length()?;
             Ok(())
         } else {
@@ -742,7 +1349,9 @@ impl MixerChannel {
         if let Some(index) = self.entries.iter().position(|entry| {
             matches!(entry, MixerEntry::SampleChannel { ref_id: entry_ref_id, .. } if *entry_ref_id == ref_id)
         }) {
-            self.entries.remove(index);
+            let key = self.entries.remove(index).key();
+            self.muted.retain(|muted| *muted != key);
+            self.soloed.retain(|soloed| *soloed != key);
             self.compute_mixer_length()?;
             Ok(())
         } else {
@@ -765,7 +1374,9 @@ impl MixerChannel {
         if let Some(index) = self.entries.iter().position(|entry| {
             matches!(entry, MixerEntry::MixerChannel { ref_id: entry_ref_id, .. } if *entry_ref_id == ref_id)
         }) {
-            self.entries.remove(index);
+            let key = self.entries.remove(index).key();
+            self.muted.retain(|muted| *muted != key);
+            self.soloed.retain(|soloed| *soloed != key);
             self.compute_mixer_length()?;
             Ok(())
         } else {
@@ -786,7 +1397,10 @@ impl MixerChannel {
         };
 
         let ref_id = match channel_up.lock() {
-            Ok(channel) => channel.ref_id,
+            Ok(mut channel) => {
+                channel.pool = self.pool.clone();
+                channel.ref_id
+            }
             Err(_) => return Err(MixerError::InvalidOperation("Failed to lock SampleChannel")),
         };
 
@@ -815,6 +1429,19 @@ impl MixerChannel {
         Self::recursive_play(self, true, 0);
     }
 
+    /// Start playing from the beginning and arm a new [Completion].
+    pub fn begin_playback(&mut self) -> Result<Completion, MixerError> {
+        let completion = self.finished.arm();
+
+        self.stop_at = None;
+        self.fade_out = None;
+        self.start_at = None;
+        self.start();
+        self.seek(Some(0))?;
+
+        Ok(completion)
+    }
+
     pub fn stop(&mut self) {
         Self::recursive_play(self, false, 0);
     }
@@ -828,6 +1455,9 @@ impl MixerChannel {
         }
 
         mixer.is_playing.store(playing, Ordering::SeqCst);
+        if !playing {
+            mixer.finished.notify(PlaybackEnd::Stopped);
+        }
 
         for entry in mixer.entries.iter() {
             match entry {
@@ -841,6 +1471,9 @@ impl MixerChannel {
                     };
 
                     channel.playing.store(playing, Ordering::SeqCst);
+                    if !playing {
+                        channel.finished.notify(PlaybackEnd::Stopped);
+                    }
                 }
                 MixerEntry::SampleChannel { channel, .. } => {
                     let Some(channel) = channel.upgrade() else {
@@ -873,4 +1506,75 @@ impl MixerChannel {
             }
         }
     }
+
+    pub fn recursive_set_pool(mixer: &mut MixerChannel, pool: Option<Arc<BufferPool>>, depth: usize) {
+        const MAX_DEPTH: usize = 16;
+
+        if depth > MAX_DEPTH {
+            eprintln!("Maximum mixer recursion depth exceeded");
+            return;
+        }
+
+        for entry in mixer.entries.iter() {
+            match entry {
+                MixerEntry::TrackChannel { channel, .. } => {
+                    let Some(channel) = channel.upgrade() else {
+                        continue;
+                    };
+
+                    let Ok(mut channel) = channel.lock() else {
+                        continue;
+                    };
+
+                    channel.pool = pool.clone();
+                }
+                MixerEntry::SampleChannel { channel, .. } => {
+                    let Some(channel) = channel.upgrade() else {
+                        continue;
+                    };
+
+                    let Ok(mut channel) = channel.lock() else {
+                        continue;
+                    };
+
+                    channel.pool = pool.clone();
+                }
+                MixerEntry::MixerChannel { mixer, .. } => {
+                    let Some(mixer) = mixer.upgrade() else {
+                        continue;
+                    };
+
+                    let Ok(mut mixer) = mixer.lock() else {
+                        continue;
+                    };
+
+                    Self::recursive_set_pool(&mut mixer, pool.clone(), depth + 1);
+                }
+            }
+        }
+
+        mixer.pool = pool;
+    }
+}
+
+/// Hand `data` to the callback and the recorder whose tap point is `point`.
+fn feed_taps(
+    callback: &mut Option<Box<dyn FnMut(&[f32]) + Send + 'static>>,
+    callback_tap: TapPoint,
+    recorder: &mut Option<RecorderTap>,
+    recorder_tap: TapPoint,
+    point: TapPoint,
+    data: &[f32],
+) {
+    if recorder_tap == point {
+        if let Some(recorder) = recorder.as_mut() {
+            recorder.push(data);
+        }
+    }
+
+    if callback_tap == point {
+        if let Some(callback) = callback.as_mut() {
+            callback(data);
+        }
+    }
 }
@@ -1,22 +1,53 @@
-use std::sync::{
-    Arc, Mutex,
-    atomic::{AtomicBool, AtomicUsize, Ordering},
+use std::{
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+    },
+    time::{Duration, Instant},
 };
 
 use inner::MixerChannel;
+use snapshot::MixerSnapshot;
+use tempomap::{MusicalTime, TempoMap};
+use transaction::MixerTransaction;
 use thiserror::Error;
 
 use crate::{
-    Device, effects::{AudioFX, AudioFXError}, misc::{
+    Device, device::{snapshot::SourceKind, tempo::Quantize}, encoder::recorder::{Output, RecordTarget, Recorder}, effects::{
+        AudioEffect, AudioFXError, ChannelConverter, ListenerSelection, PanMode, Spatialization,
+        SpatializationError, SpatializationSource,
+    }, misc::{
+        attributeobserver::{AttributeEvent, AttributeObservers},
         audioattributes::AudioAttributes,
-        audiopropertyhandler::{PropertyError, PropertyHandler},
-    }, sample::SampleChannel, track::Track
+        audiopropertyhandler::{PropertyError, PropertyHandler, validate_channel_attributes},
+        customattributes::AttributeValue,
+        envelope::Envelope,
+        finishsignal::{Completion, FinishSignal, PlaybackEnd},
+        memoryusage::MemoryUsage,
+        mixingmode::MixingMode,
+        precision::Precision,
+    }, sample::SampleChannel, track::Track, utils
 };
 
 pub(crate) mod inner;
+#[cfg(feature = "rodio")]
+pub(crate) mod rodio;
+pub(crate) mod snapshot;
+pub(crate) mod tempomap;
+pub(crate) mod transaction;
 
 static MIXER_ID: AtomicUsize = AtomicUsize::new(0);
 
+// Attributes that can follow an envelope from the audio thread
+const AUTOMATABLE_ATTRIBUTES: &[AudioAttributes] = &[
+    AudioAttributes::Volume,
+    AudioAttributes::Pan,
+    AudioAttributes::Balance,
+    AudioAttributes::SampleRate,
+    AudioAttributes::FXTempo,
+    AudioAttributes::FXPitch,
+];
+
 #[derive(Debug, Error)]
 pub enum MixerError {
     #[error("Mixer already in use by another device with ref id: {0}")]
@@ -25,22 +56,40 @@ pub enum MixerError {
     InvalidChannelCount(usize),
     #[error("Invalid sample rate: {0}")]
     InvalidSampleRate(f32),
+    #[error("Invalid block size (or frame count exceeds the block size): {0}")]
+    InvalidBlockSize(usize),
     #[error("Seek out of bounds: {0}")]
     IndexOutOfBounds(usize),
     #[error("Invalid operation: {0}")]
     InvalidOperation(&'static str),
     #[error("Failed to lock mixer")]
     LockFailed,
+    #[error("Output holds fewer than the {0} samples to read")]
+    OutputTooShort(usize),
+    #[error("The scratch buffer pool has no free buffer of {0} samples")]
+    ScratchExhausted(usize),
     #[error("{0}")]
-    Other(Box<dyn std::error::Error + 'static>),
+    Other(Box<dyn std::error::Error + Send + 'static>),
 }
 
 impl MixerError {
-    pub fn from_other<E: std::error::Error + 'static>(error: E) -> Self {
+    pub fn from_other<E: std::error::Error + Send + 'static>(error: E) -> Self {
         MixerError::Other(Box::new(error))
     }
 }
 
+/// Where in the mixer chain the DSP callback or the recording receives the output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[repr(C)]
+pub enum TapPoint {
+    /// The summed children after AudioFX and resampling, before the mixer pan and volume.
+    PreFader = 0,
+    /// The final output after pan, volume, the mixing mode and spatialization.
+    #[default]
+    PostFader = 1,
+}
+
 #[derive(Debug)]
 pub enum MixerInput<'a> {
     Track(&'a Track),
@@ -48,11 +97,94 @@ pub enum MixerInput<'a> {
     Sample(&'a SampleChannel),
 }
 
+impl MixerInput<'_> {
+    fn same_source(&self, other: &MixerInput) -> bool {
+        match (self, other) {
+            (MixerInput::Track(a), MixerInput::Track(b)) => Arc::ptr_eq(&a.inner, &b.inner),
+            (MixerInput::Mixer(a), MixerInput::Mixer(b)) => Arc::ptr_eq(&a.inner, &b.inner),
+            (MixerInput::Sample(a), MixerInput::Sample(b)) => Arc::ptr_eq(&a.inner, &b.inner),
+            _ => false,
+        }
+    }
+
+    fn key(&self) -> Result<(SourceKind, usize), MixerError> {
+        let key = match self {
+            MixerInput::Track(track) => (SourceKind::Track, track.ref_id),
+            MixerInput::Mixer(mixer) => (SourceKind::Mixer, mixer.ref_id()),
+            MixerInput::Sample(sample) => {
+                let Ok(handle) = sample.inner.lock() else {
+                    return Err(MixerError::LockFailed);
+                };
+
+                (SourceKind::Sample, handle.ref_id)
+            }
+        };
+
+        Ok(key)
+    }
+}
+
+/// A child added with a delay and duration when the mixer is created, see
+/// [Mixer::add_track_ex].
+#[derive(Debug)]
+pub struct MixerEntryInfo<'a> {
+    pub input: MixerInput<'a>,
+    pub delay: Option<usize>,
+    pub duration: Option<usize>,
+}
+
 #[derive(Debug, Default)]
 pub struct MixerInfo<'a> {
     pub sample_rate: f32,
     pub channel: usize,
+    /// Maximum frames read per call, should be at least the block size of the device
+    /// the mixer plays on. 0 uses the default of 4096 frames.
+    pub block_size: usize,
+    /// Format the children are summed in, see [Precision].
+    pub precision: Precision,
+    /// Children added from the start of the mixer.
     pub tracks: Vec<MixerInput<'a>>,
+    /// Children added with a delay and duration, after `tracks`.
+    pub entries: Vec<MixerEntryInfo<'a>>,
+}
+
+/// The format of a [MixerInfo] as an owned value, so mixers can be described in data files.
+/// The children are added once created, the info from [MixerConfig::info] has none.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct MixerConfig {
+    pub sample_rate: f32,
+    pub channel: usize,
+    pub block_size: usize,
+    pub precision: Precision,
+}
+
+impl MixerConfig {
+    pub fn info(&self) -> MixerInfo<'static> {
+        MixerInfo {
+            sample_rate: self.sample_rate,
+            channel: self.channel,
+            block_size: self.block_size,
+            precision: self.precision,
+            ..Default::default()
+        }
+    }
+}
+
+/// Buffers kept between [Mixer::read_into] calls.
+struct OfflineReader {
+    channel_converter: ChannelConverter,
+    buffer: Vec<f32>,
+    temp_buffer: Vec<f32>,
+}
+
+impl std::fmt::Debug for OfflineReader {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("OfflineReader")
+            .field("buffer_len", &self.buffer.len())
+            .finish()
+    }
 }
 
 #[derive(Debug)]
@@ -60,30 +192,116 @@ pub struct Mixer {
     pub(crate) device_ref_id: u32,
     pub(crate) inner: Arc<Mutex<MixerChannel>>,
     is_playing: Arc<AtomicBool>,
+    finished: Arc<FinishSignal>,
+    recorder: Option<Recorder>,
+    observers: AttributeObservers,
+    tempo_map: Option<TempoMap>,
+    reader: Option<OfflineReader>,
 }
 
 impl Mixer {
     pub fn new(info: MixerInfo) -> Result<Self, MixerError> {
-        let inner = MixerChannel::new(
+        let block_size = match info.block_size {
+            0 => utils::DEFAULT_BLOCK_SIZE,
+            size => size,
+        };
+
+        let mut inner = MixerChannel::new(
             info.channel,
             info.sample_rate,
+            block_size,
             MIXER_ID.fetch_add(1, Ordering::SeqCst),
         )?;
 
+        inner.set_precision(info.precision);
+
+        let (observers, automated) = AttributeObservers::with_automation();
+        inner.automated = Some(automated);
+
         let is_playing = {
             let lock = inner.is_playing.clone();
             lock.store(false, Ordering::SeqCst);
             lock
         };
 
-        Ok(Self {
+        let finished = inner.finished.clone();
+
+        let mut mixer = Self {
             inner: Arc::new(Mutex::new(inner)),
             is_playing,
+            finished,
             device_ref_id: u32::MAX,
-        })
+            recorder: None,
+            observers,
+            tempo_map: None,
+            reader: None,
+        };
+
+        let entries = info
+            .tracks
+            .into_iter()
+            .map(|input| MixerEntryInfo {
+                input,
+                delay: None,
+                duration: None,
+            })
+            .chain(info.entries)
+            .collect::<Vec<_>>();
+
+        for (index, entry) in entries.iter().enumerate() {
+            if entry.duration == Some(0) {
+                return Err(MixerError::InvalidOperation("Mixer entry duration must not be 0"));
+            }
+
+            if entries[..index].iter().any(|other| other.input.same_source(&entry.input)) {
+                return Err(MixerError::InvalidOperation("Mixer entry added more than once"));
+            }
+        }
+
+        for entry in entries {
+            match entry.input {
+                MixerInput::Track(track) => mixer.add_track_ex(track, entry.delay, entry.duration)?,
+                MixerInput::Mixer(child) => mixer.add_mixer_ex(child, entry.delay, entry.duration)?,
+                MixerInput::Sample(sample) => {
+                    mixer.add_sample_ex(sample, entry.delay, entry.duration)?
+                }
+            }
+        }
+
+        Ok(mixer)
+    }
+
+    /// Play the mixer on `device`, the returned [Completion] resolves once the mixer reaches
+    /// its end or is stopped.
+    pub fn play(&mut self, device: &mut Device) -> Result<Completion, MixerError> {
+        self.start_playback(device, None)
     }
 
-    pub fn play(&mut self, device: &mut Device) -> Result<(), MixerError> {
+    /// Play the mixer on `device` with the output held silent until the device clock reaches
+    /// `device_time`, see [Device::get_time]. A time already passed starts right away.
+    pub fn play_at(&mut self, device: &mut Device, device_time: u64) -> Result<Completion, MixerError> {
+        self.start_playback(device, Some(device_time))
+    }
+
+    /// Play the mixer on `device` starting exactly on the next beat or bar of its tempo grid,
+    /// see [Device::set_tempo]. Fails when the device has no tempo set.
+    pub fn play_quantized(
+        &mut self,
+        device: &mut Device,
+        quantize: Quantize,
+    ) -> Result<Completion, MixerError> {
+        let device_time = device
+            .next_grid_time(quantize)
+            .map_err(MixerError::from_other)?;
+
+        self.start_playback(device, Some(device_time))
+    }
+
+    fn start_playback(
+        &mut self,
+        device: &mut Device,
+        start_at: Option<u64>,
+    ) -> Result<Completion, MixerError> {
         let device_id = device.get_ref_id();
         if device_id != self.device_ref_id && self.device_ref_id != u32::MAX {
             return Err(MixerError::InvalidDeviceRefId(self.device_ref_id));
@@ -99,10 +317,10 @@ impl Mixer {
             return Err(MixerError::LockFailed);
         };
 
-        inner.start();
-        inner.seek(Some(0))?;
+        let completion = inner.begin_playback()?;
+        inner.start_at = start_at;
 
-        Ok(())
+        Ok(completion)
     }
 
     pub fn stop(&mut self) -> Result<(), MixerError> {
@@ -111,9 +329,229 @@ impl Mixer {
         };
 
         inner.stop();
+        self.reader = None;
         Ok(())
     }
 
+    /// Stop when the mixer reaches `position`, in mixer frames on its own timeline like
+    /// [Mixer::seek]. With a `fade_ms` above 0 the output fades out from that frame and the
+    /// mixer stops once the fade is heard. Replaces the stop already scheduled, a position
+    /// already passed stops at the start of the next block.
+    pub fn stop_at(&mut self, position: usize, fade_ms: f32) -> Result<(), MixerError> {
+        let Ok(mut inner) = self.inner.lock() else {
+            return Err(MixerError::LockFailed);
+        };
+
+        let fade = fade_ms.max(0.0) / 1000.0 * inner.sample_rate;
+        inner.stop_at = Some((position, fade as usize));
+        Ok(())
+    }
+
+    /// Cancel the stop scheduled with [Mixer::stop_at], a fade already started keeps going.
+    pub fn cancel_stop_at(&mut self) -> Result<(), MixerError> {
+        let Ok(mut inner) = self.inner.lock() else {
+            return Err(MixerError::LockFailed);
+        };
+
+        inner.stop_at = None;
+        Ok(())
+    }
+
+    /// Copy the processed output of the mixer into `target` while it plays, the output is
+    /// written on a background thread at the mixer channel count and sample rate.
+    ///
+    /// A recording already running is stopped first.
+    pub fn start_recording(&mut self, target: RecordTarget) -> Result<(), MixerError> {
+        self.stop_recording()?;
+
+        let Ok(mut inner) = self.inner.lock() else {
+            return Err(MixerError::LockFailed);
+        };
+
+        let (tap, recorder) = Recorder::spawn(
+            target,
+            inner.channel_count,
+            inner.sample_rate,
+            inner.block_size,
+        )
+        .map_err(MixerError::from_other)?;
+
+        inner.recorder = Some(tap);
+        self.recorder = Some(recorder);
+
+        Ok(())
+    }
+
+    /// Render the mixer from its start into `target` as fast as possible, one block at a time
+    /// so a render of any length only holds a single block in memory. Stops at the end of the
+    /// mixer, or after `max_frames` which an infinite mixer requires. Returns the frames
+    /// rendered.
+    ///
+    /// The mixer must not be playing on a device, its lock is held until the render is done.
+    pub fn render(
+        &mut self,
+        target: RecordTarget,
+        max_frames: Option<usize>,
+    ) -> Result<usize, MixerError> {
+        if self.device_ref_id != u32::MAX {
+            return Err(MixerError::InvalidDeviceRefId(self.device_ref_id));
+        }
+
+        let Ok(mut inner) = self.inner.lock() else {
+            return Err(MixerError::LockFailed);
+        };
+
+        if inner.is_infinite && max_frames.is_none() {
+            return Err(MixerError::InvalidOperation(
+                "An infinite mixer needs a frame limit to render",
+            ));
+        }
+
+        let channels = inner.channel_count;
+        let block_size = inner.block_size;
+        let mut output = Output::open(target, channels, inner.sample_rate)
+            .map_err(MixerError::from_other)?;
+
+        let mut channel_converter = ChannelConverter::new();
+        channel_converter.set_output_channels(channels);
+
+        let mut buffer = vec![0.0f32; utils::block_buffer_len(block_size, channels)];
+        let mut temp_buffer = vec![0.0f32; buffer.len()];
+
+        _ = inner.begin_playback()?;
+
+        let mut rendered = 0;
+        let limit = max_frames.unwrap_or(usize::MAX);
+
+        while rendered < limit {
+            let count = block_size.min(limit - rendered);
+            let frames = inner.read(
+                None,
+                &mut channel_converter,
+                &mut buffer,
+                &mut temp_buffer,
+                count,
+            )?;
+
+            if frames == 0 {
+                break;
+            }
+
+            output.write(&buffer[..frames * channels]);
+            rendered += frames;
+        }
+
+        if inner.is_playing() {
+            inner.stop();
+        }
+
+        Ok(rendered)
+    }
+
+    /// Read the next `frames` frames of the mixer into the start of `output` without a
+    /// device, a block at a time for any frame count. The first read starts the mixer from
+    /// the beginning, later reads carry on until [Mixer::stop]. Returns the frames read,
+    /// fewer once the mixer ends.
+    ///
+    /// The buffers are kept by the mixer, so only the first read allocates. The mixer must
+    /// not be playing on a device.
+    pub fn read_into(&mut self, output: &mut [f32], frames: usize) -> Result<usize, MixerError> {
+        if self.device_ref_id != u32::MAX {
+            return Err(MixerError::InvalidDeviceRefId(self.device_ref_id));
+        }
+
+        let Ok(mut inner) = self.inner.lock() else {
+            return Err(MixerError::LockFailed);
+        };
+
+        let channels = inner.channel_count;
+        let block_size = inner.block_size;
+        if output.len() < frames * channels {
+            return Err(MixerError::OutputTooShort(frames * channels));
+        }
+
+        let reader = match self.reader.as_mut() {
+            Some(reader) => reader,
+            None => {
+                _ = inner.begin_playback()?;
+
+                let mut channel_converter = ChannelConverter::new();
+                channel_converter.set_output_channels(channels);
+
+                let buffer = vec![0.0f32; utils::block_buffer_len(block_size, channels)];
+                let temp_buffer = vec![0.0f32; buffer.len()];
+
+                self.reader.insert(OfflineReader {
+                    channel_converter,
+                    buffer,
+                    temp_buffer,
+                })
+            }
+        };
+
+        let mut read = 0;
+        while read < frames {
+            let count = block_size.min(frames - read);
+            let block = inner.read(
+                None,
+                &mut reader.channel_converter,
+                &mut reader.buffer,
+                &mut reader.temp_buffer,
+                count,
+            )?;
+
+            if block == 0 {
+                break;
+            }
+
+            output[read * channels..(read + block) * channels]
+                .copy_from_slice(&reader.buffer[..block * channels]);
+            read += block;
+        }
+
+        Ok(read)
+    }
+
+    /// Stop the recording and wait for the remaining output to be written.
+    pub fn stop_recording(&mut self) -> Result<(), MixerError> {
+        let Some(recorder) = self.recorder.take() else {
+            return Ok(());
+        };
+
+        {
+            let Ok(mut inner) = self.inner.lock() else {
+                return Err(MixerError::LockFailed);
+            };
+
+            inner.recorder = None;
+        }
+
+        recorder.finish();
+        Ok(())
+    }
+
+    /// Buffers of the mixer and the decoded audio of every source added to it, nested mixers
+    /// included.
+    pub fn memory_usage(&self) -> Result<MemoryUsage, MixerError> {
+        let Ok(inner) = self.inner.lock() else {
+            return Err(MixerError::LockFailed);
+        };
+
+        Ok(inner.memory_usage())
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.recorder.is_some()
+    }
+
+    /// Frames lost since the recording started because the writer could not keep up.
+    pub fn get_recording_dropped_frames(&self) -> usize {
+        self.recorder
+            .as_ref()
+            .map(|recorder| recorder.dropped_frames())
+            .unwrap_or(0)
+    }
+
     pub fn seek(&mut self, position: usize) -> Result<usize, MixerError> {
         let Ok(mut inner) = self.inner.lock() else {
             return Err(MixerError::LockFailed);
@@ -122,15 +560,128 @@ impl Mixer {
         inner.seek(Some(position))
     }
 
+    pub fn seek_ms(&mut self, position: usize) -> Result<usize, MixerError> {
+        self.seek_secs(position as f64 / 1000.0)
+    }
+
+    /// Seek to a time in seconds, rounded to the nearest frame.
+    pub fn seek_secs(&mut self, position: f64) -> Result<usize, MixerError> {
+        let Ok(mut inner) = self.inner.lock() else {
+            return Err(MixerError::LockFailed);
+        };
+
+        if !position.is_finite() || position < 0.0 {
+            return Err(MixerError::InvalidOperation("Seek position must be a positive time"));
+        }
+
+        let position = (position * inner.sample_rate as f64).round();
+        inner.seek(Some(position as usize))
+    }
+
+    /// Shorthand for [Mixer::set_mixing_mode] with [MixingMode::Normalize], or no mode at all.
     pub fn set_normalize_output(&mut self, value: bool) -> Result<(), MixerError> {
+        self.set_mixing_mode(value.then_some(MixingMode::Normalize))
+    }
+
+    /// Set how children are summed, None passes the raw sum on to the parent.
+    pub fn set_mixing_mode(&mut self, mode: Option<MixingMode>) -> Result<(), MixerError> {
+        let Ok(mut inner) = self.inner.lock() else {
+            return Err(MixerError::LockFailed);
+        };
+
+        inner.set_mixing_mode(mode);
+        Ok(())
+    }
+
+    /// Replace the effects run on the summed children after the mixer volume and pan, in
+    /// order. Each effect is reset before it is inserted.
+    pub fn set_effects(&mut self, mut effects: Vec<Box<dyn AudioEffect>>) -> Result<(), MixerError> {
+        let Ok(mut inner) = self.inner.lock() else {
+            return Err(MixerError::LockFailed);
+        };
+
+        effects.iter_mut().for_each(|effect| effect.reset());
+        inner.effects = effects;
+        Ok(())
+    }
+
+    pub fn clear_effects(&mut self) -> Result<(), MixerError> {
+        let Ok(mut inner) = self.inner.lock() else {
+            return Err(MixerError::LockFailed);
+        };
+
+        inner.effects.clear();
+        Ok(())
+    }
+
+    /// Put the mixer in a ducking group, see [DuckingRule](crate::DuckingRule).
+    pub fn set_ducking_group(&mut self, group: Option<u32>) -> Result<(), MixerError> {
+        let Ok(mut inner) = self.inner.lock() else {
+            return Err(MixerError::LockFailed);
+        };
+
+        inner.group = group;
+        Ok(())
+    }
+
+    /// Pick which device listener the submix is spatialized against, see [ListenerSelection].
+    /// Spatialized tracks inside the mixer follow the same listener.
+    pub fn set_listener(&mut self, listener: ListenerSelection) -> Result<(), MixerError> {
+        let Ok(mut inner) = self.inner.lock() else {
+            return Err(MixerError::LockFailed);
+        };
+
+        inner.listener = listener;
+        Ok(())
+    }
+
+    pub fn get_listener(&self) -> Result<ListenerSelection, MixerError> {
+        let Ok(inner) = self.inner.lock() else {
+            return Err(MixerError::LockFailed);
+        };
+
+        Ok(inner.listener)
+    }
+
+    /// Choose how [AudioAttributes::Pan] moves stereo content, see [PanMode].
+    pub fn set_pan_mode(&mut self, mode: PanMode) -> Result<(), MixerError> {
         let Ok(mut inner) = self.inner.lock() else {
             return Err(MixerError::LockFailed);
         };
 
-        inner.set_normalize_output(value);
+        inner.panner.set_mode(mode);
         Ok(())
     }
 
+    pub fn get_pan_mode(&self) -> Result<PanMode, MixerError> {
+        let Ok(inner) = self.inner.lock() else {
+            return Err(MixerError::LockFailed);
+        };
+
+        Ok(inner.panner.mode)
+    }
+
+    /// Set the format the children are summed in, the mixer output stays f32. The sum is
+    /// narrowed back to f32 before the AudioFX, resampler, pan, volume and effects of the mixer.
+    pub fn set_precision(&mut self, precision: Precision) -> Result<(), MixerError> {
+        let Ok(mut inner) = self.inner.lock() else {
+            return Err(MixerError::LockFailed);
+        };
+
+        inner.set_precision(precision);
+        Ok(())
+    }
+
+    /// Resize the internal buffers for reads of up to `block_size` frames, larger device blocks
+    /// are rendered in chunks of `block_size`.
+    pub fn set_block_size(&mut self, block_size: usize) -> Result<(), MixerError> {
+        let Ok(mut inner) = self.inner.lock() else {
+            return Err(MixerError::LockFailed);
+        };
+
+        inner.set_block_size(block_size)
+    }
+
     pub fn set_callback<F>(&mut self, callback: F) -> Result<(), MixerError>
     where
         F: FnMut(&[f32]) + Send + 'static,
@@ -143,6 +694,42 @@ impl Mixer {
         Ok(())
     }
 
+    /// Choose where the callback set with [Mixer::set_callback] taps the mixer.
+    pub fn set_callback_tap(&mut self, tap: TapPoint) -> Result<(), MixerError> {
+        let Ok(mut inner) = self.inner.lock() else {
+            return Err(MixerError::LockFailed);
+        };
+
+        inner.callback_tap = tap;
+        Ok(())
+    }
+
+    pub fn get_callback_tap(&self) -> Result<TapPoint, MixerError> {
+        let Ok(inner) = self.inner.lock() else {
+            return Err(MixerError::LockFailed);
+        };
+
+        Ok(inner.callback_tap)
+    }
+
+    /// Choose where [Mixer::start_recording] taps the mixer, kept across recordings.
+    pub fn set_recording_tap(&mut self, tap: TapPoint) -> Result<(), MixerError> {
+        let Ok(mut inner) = self.inner.lock() else {
+            return Err(MixerError::LockFailed);
+        };
+
+        inner.recorder_tap = tap;
+        Ok(())
+    }
+
+    pub fn get_recording_tap(&self) -> Result<TapPoint, MixerError> {
+        let Ok(inner) = self.inner.lock() else {
+            return Err(MixerError::LockFailed);
+        };
+
+        Ok(inner.recorder_tap)
+    }
+
     pub fn add_track(&mut self, channel: &Track) -> Result<(), MixerError> {
         self.add_track_ex(channel, None, None)
     }
@@ -215,6 +802,64 @@ impl Mixer {
         inner.add_sample(sample_weak, delay, duration)
     }
 
+    /// Add a child with its delay and duration in bars and beats, converted to mixer frames
+    /// with the tempo map, see [Mixer::set_tempo_map]. The duration follows the tempo changes
+    /// it spans.
+    pub fn add_input_musical(
+        &mut self,
+        input: MixerInput,
+        delay: Option<MusicalTime>,
+        duration: Option<MusicalTime>,
+    ) -> Result<(), MixerError> {
+        let Some(tempo_map) = self.tempo_map.as_ref() else {
+            return Err(MixerError::InvalidOperation("The mixer has no tempo map"));
+        };
+
+        let delay = delay.map(|delay| tempo_map.to_frames(delay));
+        let duration = duration
+            .map(|duration| tempo_map.length_to_frames(delay.unwrap_or(0), duration))
+            .filter(|frames| *frames > 0);
+
+        match input {
+            MixerInput::Track(track) => self.add_track_ex(track, delay, duration),
+            MixerInput::Mixer(mixer) => self.add_mixer_ex(mixer, delay, duration),
+            MixerInput::Sample(sample) => self.add_sample_ex(sample, delay, duration),
+        }
+    }
+
+    /// Lay a tempo map over the mixer timeline for [Mixer::add_input_musical] and
+    /// [Mixer::to_frames]. The map must use the sample rate of the mixer.
+    pub fn set_tempo_map(&mut self, tempo_map: TempoMap) -> Result<(), MixerError> {
+        let Ok(inner) = self.inner.lock() else {
+            return Err(MixerError::LockFailed);
+        };
+
+        if tempo_map.sample_rate() != inner.sample_rate {
+            return Err(MixerError::InvalidSampleRate(tempo_map.sample_rate()));
+        }
+
+        drop(inner);
+        self.tempo_map = Some(tempo_map);
+        Ok(())
+    }
+
+    pub fn get_tempo_map(&self) -> Option<&TempoMap> {
+        self.tempo_map.as_ref()
+    }
+
+    pub fn clear_tempo_map(&mut self) {
+        self.tempo_map = None;
+    }
+
+    /// Mixer frame of a position in bars and beats, for [Mixer::seek] or [Mixer::stop_at].
+    pub fn to_frames(&self, time: MusicalTime) -> Result<usize, MixerError> {
+        let Some(tempo_map) = self.tempo_map.as_ref() else {
+            return Err(MixerError::InvalidOperation("The mixer has no tempo map"));
+        };
+
+        Ok(tempo_map.to_frames(time))
+    }
+
     pub fn remove_sample(&mut self, sample: &SampleChannel) -> Result<(), MixerError> {
         let Ok(mut inner) = self.inner.lock() else {
             return Err(MixerError::LockFailed);
@@ -224,6 +869,119 @@ impl Mixer {
         inner.remove_sample(&sample_weak)
     }
 
+    /// Stage changes to the children with `edit` and apply them together once it returns,
+    /// between two blocks of the mixer, so a playing timeline is never heard half rearranged.
+    /// When any change fails none of them is applied.
+    pub fn edit<F, R>(&mut self, edit: F) -> Result<R, MixerError>
+    where
+        F: FnOnce(&mut MixerTransaction) -> R,
+    {
+        let mut transaction = MixerTransaction::default();
+        let result = edit(&mut transaction);
+
+        let Ok(mut inner) = self.inner.lock() else {
+            return Err(MixerError::LockFailed);
+        };
+
+        transaction.apply(&mut inner)?;
+        Ok(result)
+    }
+
+    /// Capture the children with their timing, mute and solo, and the settings and positions
+    /// of every child and of the mixer, see [MixerSnapshot].
+    pub fn snapshot(&self) -> Result<MixerSnapshot, MixerError> {
+        let Ok(inner) = self.inner.lock() else {
+            return Err(MixerError::LockFailed);
+        };
+
+        Ok(MixerSnapshot::capture(&inner))
+    }
+
+    /// Restore a snapshot previously taken with [Mixer::snapshot], the children are put back
+    /// as they were between two blocks of the mixer.
+    ///
+    /// Children are matched by kind and ref id, children added since are removed and children
+    /// removed since are added back while they are still alive.
+    pub fn restore(&mut self, snapshot: &MixerSnapshot) -> Result<(), MixerError> {
+        let Ok(mut inner) = self.inner.lock() else {
+            return Err(MixerError::LockFailed);
+        };
+
+        snapshot.apply(&mut inner)
+    }
+
+    /// Silence a child without removing it, a muted child keeps playing in sync with the
+    /// mixer.
+    pub fn set_muted(&mut self, input: MixerInput, muted: bool) -> Result<(), MixerError> {
+        let key = input.key()?;
+
+        let Ok(mut inner) = self.inner.lock() else {
+            return Err(MixerError::LockFailed);
+        };
+
+        if !inner.entries.iter().any(|entry| entry.key() == key) {
+            return Err(MixerError::InvalidOperation("Entry not found in mixer"));
+        }
+
+        inner.muted.retain(|entry| *entry != key);
+        if muted {
+            inner.muted.push(key);
+        }
+
+        Ok(())
+    }
+
+    pub fn is_muted(&self, input: MixerInput) -> Result<bool, MixerError> {
+        let key = input.key()?;
+
+        let Ok(inner) = self.inner.lock() else {
+            return Err(MixerError::LockFailed);
+        };
+
+        Ok(inner.muted.contains(&key))
+    }
+
+    /// While any child is soloed only the soloed children are heard, a muted child stays
+    /// silent even when soloed.
+    pub fn set_solo(&mut self, input: MixerInput, soloed: bool) -> Result<(), MixerError> {
+        let key = input.key()?;
+
+        let Ok(mut inner) = self.inner.lock() else {
+            return Err(MixerError::LockFailed);
+        };
+
+        if !inner.entries.iter().any(|entry| entry.key() == key) {
+            return Err(MixerError::InvalidOperation("Entry not found in mixer"));
+        }
+
+        inner.soloed.retain(|entry| *entry != key);
+        if soloed {
+            inner.soloed.push(key);
+        }
+
+        Ok(())
+    }
+
+    pub fn is_soloed(&self, input: MixerInput) -> Result<bool, MixerError> {
+        let key = input.key()?;
+
+        let Ok(inner) = self.inner.lock() else {
+            return Err(MixerError::LockFailed);
+        };
+
+        Ok(inner.soloed.contains(&key))
+    }
+
+    /// Unsolo every child, all children that are not muted are heard again.
+    pub fn clear_solos(&mut self) -> Result<(), MixerError> {
+        let Ok(mut inner) = self.inner.lock() else {
+            return Err(MixerError::LockFailed);
+        };
+
+        inner.soloed.clear();
+        Ok(())
+    }
+
     pub fn get_length(&self) -> Result<usize, MixerError> {
         let Ok(inner) = self.inner.lock() else {
             return Err(MixerError::LockFailed);
@@ -236,6 +994,9 @@ impl Mixer {
         Ok(inner.max_length)
     }
 
+    /// Current position on the mixer timeline in mixer frames, the same timeline
+    /// [Mixer::seek] and the child delays use. With AudioFX enabled this is ahead of what is
+    /// heard by the FX latency, see [Mixer::get_played_position].
     pub fn get_position(&self) -> Result<usize, MixerError> {
         let Ok(inner) = self.inner.lock() else {
             return Err(MixerError::LockFailed);
@@ -244,10 +1005,123 @@ impl Mixer {
         Ok(inner.mixer_position)
     }
 
+    /// [Mixer::get_position] in seconds.
+    pub fn get_position_secs(&self) -> Result<f64, MixerError> {
+        let Ok(inner) = self.inner.lock() else {
+            return Err(MixerError::LockFailed);
+        };
+
+        Ok(inner.mixer_position as f64 / inner.sample_rate as f64)
+    }
+
+    /// [Mixer::get_played_position] in seconds, suited for a timeline cursor.
+    pub fn get_played_position_secs(&self) -> Result<f64, MixerError> {
+        let Ok(inner) = self.inner.lock() else {
+            return Err(MixerError::LockFailed);
+        };
+
+        Ok(inner.get_played_position() as f64 / inner.sample_rate as f64)
+    }
+
+    /// Mixer frame the listener is hearing, unlike [Mixer::get_position] this follows the
+    /// FXTempo stretch ratio and the AudioFX latency.
+    pub fn get_played_position(&self) -> Result<usize, MixerError> {
+        let Ok(inner) = self.inner.lock() else {
+            return Err(MixerError::LockFailed);
+        };
+
+        Ok(inner.get_played_position())
+    }
+
     pub fn is_playing(&self) -> bool {
         self.is_playing.load(Ordering::SeqCst)
     }
 
+    /// Block the calling thread until the mixer reaches its end or is stopped, or until
+    /// `timeout` elapsed. Returns false on timeout.
+    pub fn wait_until_finished(&self, timeout: Option<Duration>) -> bool {
+        self.finished.wait(&self.is_playing, timeout)
+    }
+
+    /// Call `callback` whenever an attribute is changed through [PropertyHandler], with the
+    /// attribute and its new value.
+    ///
+    /// Changes made while playing by [Mixer::set_envelope] are reported by
+    /// [Mixer::dispatch_attribute_changes].
+    pub fn on_attribute_changed<F>(&mut self, callback: F)
+    where
+        F: FnMut(&AttributeEvent) + Send + 'static,
+    {
+        self.observers.add(Box::new(callback));
+    }
+
+    /// Remove every callback added with [Mixer::on_attribute_changed].
+    pub fn clear_attribute_observers(&mut self) {
+        self.observers.clear();
+    }
+
+    /// Report the attributes changed by envelopes since the last call to the callbacks added
+    /// with [Mixer::on_attribute_changed]. Call it regularly from the control thread, such as
+    /// once per UI frame.
+    pub fn dispatch_attribute_changes(&self) {
+        self.observers.dispatch();
+    }
+
+    /// Automate an attribute with `envelope`, keyframe times are seconds of the mixer
+    /// position. Replaces the envelope already attached to `attribute`, None detaches it and
+    /// leaves the attribute at its last value.
+    ///
+    /// The envelope overrides [PropertyHandler::set_attribute_f32] for that attribute while
+    /// attached.
+    pub fn set_envelope(
+        &mut self,
+        attribute: AudioAttributes,
+        envelope: Option<Envelope>,
+    ) -> Result<(), PropertyError> {
+        if !AUTOMATABLE_ATTRIBUTES.contains(&attribute) {
+            return Err(PropertyError::UnsupportedAttribute("Unsupported attribute"));
+        }
+
+        for keyframe in envelope.iter().flat_map(|envelope| envelope.keyframes()) {
+            attribute.check_value(keyframe.value)?;
+        }
+
+        let Ok(mut inner) = self.inner.lock() else {
+            return Err(PropertyError::from_other(MixerError::LockFailed));
+        };
+
+        inner.envelopes.set(attribute, envelope);
+        Ok(())
+    }
+
+    pub fn get_envelope(&self, attribute: AudioAttributes) -> Option<Envelope> {
+        let Ok(inner) = self.inner.lock() else {
+            return None;
+        };
+
+        inner.envelopes.get(attribute).cloned()
+    }
+
+    /// Whether the envelope of `attribute` was stopped while playing, after it produced a
+    /// value the attribute rejects such as an FXTempo change with AudioFX disabled.
+    pub fn is_envelope_stopped(&self, attribute: AudioAttributes) -> bool {
+        let Ok(inner) = self.inner.lock() else {
+            return false;
+        };
+
+        inner.envelopes.is_stopped(attribute)
+    }
+
+    /// Detach every envelope added with [Mixer::set_envelope].
+    pub fn clear_envelopes(&mut self) -> Result<(), MixerError> {
+        let Ok(mut inner) = self.inner.lock() else {
+            return Err(MixerError::LockFailed);
+        };
+
+        inner.envelopes.clear();
+        Ok(())
+    }
+
     pub fn ref_id(&self) -> usize {
         let Ok(inner) = self.inner.lock() else {
             return usize::MAX;
@@ -257,6 +1131,55 @@ impl Mixer {
     }
 }
 
+impl Mixer {
+    fn apply_attribute_f32(
+        &mut self,
+        _type: AudioAttributes,
+        _value: f32,
+    ) -> Result<(), PropertyError> {
+        let inner = self.inner.lock();
+        if inner.is_err() {
+            return Err(PropertyError::from_other(MixerError::InvalidOperation(
+                "Failed to lock mixer state",
+            )));
+        }
+
+        let mut inner = inner.unwrap();
+
+        inner.set_attribute_f32(_type, _value)
+    }
+
+    fn apply_attribute_bool(
+        &mut self,
+        _type: AudioAttributes,
+        _value: bool,
+    ) -> Result<(), PropertyError> {
+        let inner = self.inner.lock();
+        if inner.is_err() {
+            return Err(PropertyError::from_other(MixerError::InvalidOperation(
+                "Failed to lock mixer state",
+            )));
+        }
+
+        let mut inner = inner.unwrap();
+
+        inner.set_attribute_bool(_type, _value)
+    }
+
+    fn apply_custom_attribute(
+        &mut self,
+        name: &str,
+        value: AttributeValue,
+    ) -> Result<(), PropertyError> {
+        let Ok(mut inner) = self.inner.lock() else {
+            return Err(PropertyError::from_other(MixerError::LockFailed));
+        };
+
+        inner.custom_attributes.set(name, value);
+        Ok(())
+    }
+}
+
 impl PropertyHandler for Mixer {
     fn get_attribute_f32(&self, _type: AudioAttributes) -> Result<f32, PropertyError> {
         let inner = self.inner.lock();
@@ -272,6 +1195,7 @@ impl PropertyHandler for Mixer {
             AudioAttributes::SampleRate => Ok(inner.resampler.sample_rate as f32),
             AudioAttributes::Volume => Ok(inner.volume.volume as f32),
             AudioAttributes::Pan => Ok(inner.panner.pan as f32),
+            AudioAttributes::Balance => Ok(inner.panner.get_balance()),
             AudioAttributes::FXPitch => {
                 if let Some(fx) = inner.fx.as_ref() {
                     Ok(fx.octave as f32)
@@ -293,54 +1217,18 @@ impl PropertyHandler for Mixer {
     fn set_attribute_f32(
         &mut self,
         _type: AudioAttributes,
-        _value: f32,
+        value: f32,
     ) -> Result<(), PropertyError> {
-        let inner = self.inner.lock();
-        if inner.is_err() {
-            return Err(PropertyError::from_other(MixerError::InvalidOperation(
-                "Failed to lock mixer state",
-            )));
-        }
-
-        let mut inner = inner.unwrap();
+        self.apply_attribute_f32(_type, value)?;
 
-        match _type {
-            AudioAttributes::SampleRate => {
-                inner.resampler.set_target_sample_rate(_value);
-                Ok(())
-            }
-            AudioAttributes::Volume => {
-                inner.volume.set_volume(_value);
-                Ok(())
-            }
-            AudioAttributes::Pan => {
-                inner.panner.set_pan(_value);
-                Ok(())
-            }
-            AudioAttributes::FXPitch => {
-                if let Some(fx) = inner.fx.as_mut() {
-                    if let Err(e) = fx.set_octave(_value) {
-                        return Err(PropertyError::from_other(e));
-                    }
-
-                    Ok(())
-                } else {
-                    Err(PropertyError::from_other(AudioFXError::NotEnabled))
-                }
-            }
-            AudioAttributes::FXTempo => {
-                if let Some(fx) = inner.fx.as_mut() {
-                    if let Err(e) = fx.set_tempo(_value) {
-                        return Err(PropertyError::from_other(e));
-                    }
+        let value = self.get_attribute_f32(_type).unwrap_or(value);
+        self.observers.notify(AttributeEvent {
+            attribute: _type,
+            name: None,
+            value: AttributeValue::F32(value),
+        });
 
-                    Ok(())
-                } else {
-                    Err(PropertyError::from_other(AudioFXError::NotEnabled))
-                }
-            }
-            _ => Err(PropertyError::UnsupportedAttribute("Unknown attribute")),
-        }
+        Ok(())
     }
 
     fn get_attribute_bool(&self, _type: AudioAttributes) -> Result<bool, PropertyError> {
@@ -355,10 +1243,9 @@ impl PropertyHandler for Mixer {
 
         match _type {
             AudioAttributes::FXEnabled => Ok(inner.fx.is_some()),
-            AudioAttributes::SpatializationEnabled => {
-                // TODO:
-                Ok(false)
-            }
+            AudioAttributes::SpatializationEnabled => Ok(inner.spatializer.is_some()),
+            AudioAttributes::ForceMono => Ok(inner.panner.force_mono),
+            AudioAttributes::SwapChannels => Ok(inner.panner.swap_channels),
             _ => Err(PropertyError::UnsupportedAttribute("Unknown attribute")),
         }
     }
@@ -366,39 +1253,97 @@ impl PropertyHandler for Mixer {
     fn set_attribute_bool(
         &mut self,
         _type: AudioAttributes,
-        _value: bool,
+        value: bool,
     ) -> Result<(), PropertyError> {
-        let inner = self.inner.lock();
-        if inner.is_err() {
-            return Err(PropertyError::from_other(MixerError::InvalidOperation(
-                "Failed to lock mixer state",
-            )));
-        }
+        self.apply_attribute_bool(_type, value)?;
 
-        let mut inner = inner.unwrap();
+        let value = self.get_attribute_bool(_type).unwrap_or(value);
+        self.observers.notify(AttributeEvent {
+            attribute: _type,
+            name: None,
+            value: AttributeValue::Bool(value),
+        });
 
-        match _type {
-            AudioAttributes::FXEnabled => {
-                if _value {
-                    let fx = AudioFX::new(inner.channel_count, inner.resampler.sample_rate)
-                        .map_err(PropertyError::from_other)?;
+        Ok(())
+    }
 
-                    inner.fx = Some(fx);
-                } else {
-                    inner.fx = None;
-                }
+    fn get_custom_attribute(&self, name: &str) -> Result<AttributeValue, PropertyError> {
+        let Ok(inner) = self.inner.lock() else {
+            return Err(PropertyError::from_other(MixerError::LockFailed));
+        };
 
-                let seek_pos = inner.mixer_position;
-                _ = inner.seek(Some(seek_pos));
+        inner.custom_attributes.get(name)
+    }
 
-                Ok(())
-            }
-            AudioAttributes::SpatializationEnabled => {
-                // TODO
-                Ok(())
-            }
-            _ => Err(PropertyError::UnsupportedAttribute("Unknown attribute")),
-        }
+    fn set_custom_attribute(
+        &mut self,
+        name: &str,
+        value: AttributeValue,
+    ) -> Result<(), PropertyError> {
+        self.apply_custom_attribute(name, value)?;
+
+        self.observers.notify(AttributeEvent {
+            attribute: AudioAttributes::Unknown,
+            name: Some(name),
+            value,
+        });
+
+        Ok(())
+    }
+
+    fn supported_attributes(&self) -> &'static [AudioAttributes] {
+        &[
+            AudioAttributes::SampleRate,
+            AudioAttributes::Volume,
+            AudioAttributes::Pan,
+            AudioAttributes::Balance,
+            AudioAttributes::FXPitch,
+            AudioAttributes::FXTempo,
+            AudioAttributes::FXEnabled,
+            AudioAttributes::SpatializationEnabled,
+            AudioAttributes::ForceMono,
+            AudioAttributes::SwapChannels,
+        ]
+    }
+
+    fn set_attributes(
+        &mut self,
+        attributes: &[(AudioAttributes, AttributeValue)],
+    ) -> Result<(), PropertyError> {
+        let Ok(mut inner) = self.inner.lock() else {
+            return Err(PropertyError::from_other(MixerError::LockFailed));
+        };
+
+        validate_channel_attributes(attributes, inner.fx.is_some())?;
+
+        let mut applied = 0;
+        let result = attributes.iter().try_for_each(|&(attribute, value)| {
+            inner.set_attribute(attribute, value)?;
+            applied += 1;
+            Ok(())
+        });
+
+        drop(inner);
+        self.observers.notify_attributes(&*self, &attributes[..applied]);
+        result
+    }
+}
+
+impl SpatializationSource for Mixer {
+    fn with_spatialization<R>(
+        &self,
+        f: impl FnOnce(&mut Spatialization, &mut Instant) -> R,
+    ) -> Result<R, SpatializationError> {
+        let Ok(mut inner) = self.inner.lock() else {
+            return Err(SpatializationError::from_other(MixerError::LockFailed));
+        };
+
+        let inner = &mut *inner;
+        let Some(spatializer) = inner.spatializer.as_mut() else {
+            return Err(SpatializationError::NotInitialized);
+        };
+
+        Ok(f(spatializer, &mut inner.last_time))
     }
 }
 
@@ -412,6 +1357,10 @@ impl Drop for Mixer {
         let mut inner = inner.unwrap();
 
         inner.is_playing.store(false, Ordering::SeqCst);
+        inner.finished.notify(PlaybackEnd::Stopped);
         inner.marked_as_deleted = true;
+
+        // Closes the queue, the writer thread finishes the file on its own
+        inner.recorder = None;
     }
 }
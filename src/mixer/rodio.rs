@@ -0,0 +1,146 @@
+use std::time::Duration;
+
+use crate::track::Track;
+
+use super::{Mixer, MixerError, MixerInfo};
+
+/// A [Mixer] pulled by a `rodio` output instead of a [Device](crate::Device), see
+/// [Mixer::into_rodio_source]. Renders a block of the mixer whenever the previous one is
+/// played through, on the thread iterating the source.
+#[derive(Debug)]
+pub struct MixerSource {
+    mixer: Mixer,
+    channels: usize,
+    sample_rate: f32,
+    block_size: usize,
+    buffer: Vec<f32>,
+    // Samples of the current block in `buffer` and how many were played
+    length: usize,
+    position: usize,
+    ended: bool,
+}
+
+impl Mixer {
+    /// Play the mixer through `rodio`, such as by appending the source to a `rodio::Sink`. The
+    /// mixer starts from the beginning and the source ends with it.
+    ///
+    /// The mixer must not be playing on a device.
+    pub fn into_rodio_source(mut self) -> Result<MixerSource, MixerError> {
+        if self.device_ref_id != u32::MAX {
+            return Err(MixerError::InvalidDeviceRefId(self.device_ref_id));
+        }
+
+        // Restarts from the beginning on the first read
+        self.stop()?;
+
+        let (channels, sample_rate, block_size) = {
+            let Ok(inner) = self.inner.lock() else {
+                return Err(MixerError::LockFailed);
+            };
+
+            (inner.channel_count, inner.sample_rate, inner.block_size)
+        };
+
+        Ok(MixerSource {
+            mixer: self,
+            channels,
+            sample_rate,
+            block_size,
+            buffer: vec![0.0f32; block_size * channels],
+            length: 0,
+            position: 0,
+            ended: false,
+        })
+    }
+}
+
+impl MixerSource {
+    /// Play `track` through `rodio`, in a mixer of its own with the channels and sample rate
+    /// the track plays at.
+    pub fn from_track(track: &Track) -> Result<Self, MixerError> {
+        let (channels, sample_rate) = {
+            let Ok(inner) = track.inner.lock() else {
+                return Err(MixerError::LockFailed);
+            };
+
+            (
+                inner.channel_converter.get_output_channels(),
+                inner.resampler.target_sample_rate,
+            )
+        };
+
+        let mut mixer = Mixer::new(MixerInfo {
+            sample_rate,
+            channel: channels,
+            ..Default::default()
+        })?;
+
+        mixer.add_track(track)?;
+        mixer.into_rodio_source()
+    }
+
+    /// The mixer being played, to change its attributes or children while it plays.
+    pub fn mixer(&mut self) -> &mut Mixer {
+        &mut self.mixer
+    }
+
+    /// Stop rendering and return the mixer.
+    pub fn into_mixer(mut self) -> Mixer {
+        _ = self.mixer.stop();
+        self.mixer
+    }
+
+    fn render_block(&mut self) -> bool {
+        let frames = match self.mixer.read_into(&mut self.buffer, self.block_size) {
+            Ok(frames) => frames,
+            Err(e) => {
+                eprintln!("Failed to render the mixer for rodio: {}", e);
+                0
+            }
+        };
+
+        self.length = frames * self.channels;
+        self.position = 0;
+
+        frames > 0
+    }
+}
+
+impl Iterator for MixerSource {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        if self.position >= self.length && (self.ended || !self.render_block()) {
+            self.ended = true;
+            return None;
+        }
+
+        let sample = self.buffer[self.position];
+        self.position += 1;
+
+        Some(sample)
+    }
+}
+
+impl rodio::Source for MixerSource {
+    fn current_frame_len(&self) -> Option<usize> {
+        if self.ended {
+            return Some(0);
+        }
+
+        // The format never changes, the block boundaries do not matter to rodio
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        self.channels as u16
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate as u32
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        None
+    }
+}
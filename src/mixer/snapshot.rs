@@ -0,0 +1,211 @@
+use std::sync::{Mutex, Weak};
+
+use crate::device::snapshot::{SourceKind, SourceSnapshot};
+
+use super::{
+    MixerError,
+    inner::{MixerChannel, MixerEntry},
+};
+
+/// A child of a mixer in a [MixerSnapshot] and where it sits on the timeline.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MixerEntrySnapshot {
+    pub kind: SourceKind,
+    pub ref_id: usize,
+    pub delay: Option<usize>,
+    pub duration: Option<usize>,
+    pub muted: bool,
+    pub soloed: bool,
+    /// Settings of the child, None when it was already dropped. A child mixer is captured
+    /// without its own children.
+    pub source: Option<SourceSnapshot>,
+}
+
+/// A point-in-time capture of a mixer arrangement: its children with their timing, mute and
+/// solo, the settings and positions of every child and of the mixer itself.
+///
+/// Created with [Mixer::snapshot](crate::Mixer::snapshot) and applied back with
+/// [Mixer::restore](crate::Mixer::restore), keeping a stack of them gives undo and redo.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MixerSnapshot {
+    pub mixer: SourceSnapshot,
+    pub entries: Vec<MixerEntrySnapshot>,
+    // Keeps removed children reachable for a restore, lost when serialized
+    #[cfg_attr(feature = "serde", serde(skip))]
+    handles: Vec<MixerEntry>,
+}
+
+impl MixerEntrySnapshot {
+    fn capture(mixer: &MixerChannel, entry: &MixerEntry) -> Self {
+        let (kind, ref_id) = entry.key();
+        let (delay, duration, source) = match entry {
+            MixerEntry::TrackChannel {
+                channel,
+                delay,
+                duration,
+                ..
+            } => {
+                let source = lock_with(channel, |track| SourceSnapshot::from_track(track));
+                (*delay, *duration, source)
+            }
+            MixerEntry::SampleChannel {
+                channel,
+                delay,
+                duration,
+                ..
+            } => {
+                let source = lock_with(channel, |sample| SourceSnapshot::from_sample(sample));
+                (*delay, *duration, source)
+            }
+            MixerEntry::MixerChannel {
+                mixer,
+                delay,
+                duration,
+                ..
+            } => {
+                let source = lock_with(mixer, |mixer| SourceSnapshot::from_mixer(mixer));
+                (*delay, *duration, source)
+            }
+        };
+
+        Self {
+            kind,
+            ref_id,
+            delay,
+            duration,
+            muted: mixer.muted.contains(&(kind, ref_id)),
+            soloed: mixer.soloed.contains(&(kind, ref_id)),
+            source,
+        }
+    }
+
+    fn apply(&self, entry: &MixerEntry) -> Result<(), MixerError> {
+        let Some(source) = self.source.as_ref() else {
+            return Ok(());
+        };
+
+        let result = match entry {
+            MixerEntry::TrackChannel { channel, .. } => {
+                lock_with(channel, |track| source.apply_track(track))
+            }
+            MixerEntry::SampleChannel { channel, .. } => {
+                lock_with(channel, |sample| source.apply_sample(sample))
+            }
+            MixerEntry::MixerChannel { mixer, .. } => {
+                lock_with(mixer, |mixer| source.apply_mixer(mixer))
+            }
+        };
+
+        // A child dropped since the lookup is skipped like one missing from the snapshot
+        result.unwrap_or(Ok(())).map_err(MixerError::from_other)
+    }
+}
+
+impl MixerSnapshot {
+    pub(crate) fn capture(mixer: &MixerChannel) -> Self {
+        let entries = mixer
+            .entries
+            .iter()
+            .map(|entry| MixerEntrySnapshot::capture(mixer, entry))
+            .collect();
+
+        Self {
+            mixer: SourceSnapshot::from_mixer(mixer),
+            entries,
+            handles: mixer.entries.clone(),
+        }
+    }
+
+    /// Children are matched by kind and ref id. A child removed since the snapshot is added
+    /// back while it is still alive, one dropped or only known from a deserialized snapshot
+    /// is skipped. Children added since the snapshot are removed.
+    pub(crate) fn apply(&self, mixer: &mut MixerChannel) -> Result<(), MixerError> {
+        let entries = std::mem::take(&mut mixer.entries);
+        let muted = std::mem::take(&mut mixer.muted);
+        let soloed = std::mem::take(&mut mixer.soloed);
+
+        if let Err(e) = self.apply_entries(mixer, &entries) {
+            mixer.entries = entries;
+            mixer.muted = muted;
+            mixer.soloed = soloed;
+            _ = mixer.compute_mixer_length();
+
+            return Err(e);
+        }
+
+        for entry in mixer.entries.iter() {
+            if let Some(snapshot) = self.find(entry.key()) {
+                snapshot.apply(entry)?;
+            }
+        }
+
+        self.mixer
+            .apply_mixer(mixer)
+            .map_err(MixerError::from_other)
+    }
+
+    fn apply_entries(
+        &self,
+        mixer: &mut MixerChannel,
+        current: &[MixerEntry],
+    ) -> Result<(), MixerError> {
+        for snapshot in self.entries.iter() {
+            let key = (snapshot.kind, snapshot.ref_id);
+            let Some(entry) = self.handle(current, key) else {
+                continue;
+            };
+
+            let (delay, duration) = (snapshot.delay, snapshot.duration);
+            match entry {
+                MixerEntry::TrackChannel { channel, .. } => {
+                    mixer.add_track(channel.clone(), delay, duration)?
+                }
+                MixerEntry::SampleChannel { channel, .. } => {
+                    mixer.add_sample(channel.clone(), delay, duration)?
+                }
+                MixerEntry::MixerChannel { mixer: child, .. } => {
+                    mixer.add_mixer(child.clone(), delay, duration)?
+                }
+            }
+
+            if snapshot.muted {
+                mixer.muted.push(key);
+            }
+
+            if snapshot.soloed {
+                mixer.soloed.push(key);
+            }
+        }
+
+        Ok(())
+    }
+
+    // The captured child, or the attached one for a deserialized snapshot
+    fn handle<'a>(
+        &'a self,
+        current: &'a [MixerEntry],
+        key: (SourceKind, usize),
+    ) -> Option<&'a MixerEntry> {
+        self.handles
+            .iter()
+            .chain(current.iter())
+            .filter(|entry| entry.key() == key)
+            .find(|entry| entry.is_alive())
+    }
+
+    fn find(&self, key: (SourceKind, usize)) -> Option<&MixerEntrySnapshot> {
+        self.entries
+            .iter()
+            .find(|entry| (entry.kind, entry.ref_id) == key)
+    }
+}
+
+// Run `f` on the locked child, None when it was dropped or its lock is poisoned
+fn lock_with<T, R>(child: &Weak<Mutex<T>>, f: impl FnOnce(&mut T) -> R) -> Option<R> {
+    let child = child.upgrade()?;
+    let mut guard = child.lock().ok()?;
+
+    Some(f(&mut guard))
+}
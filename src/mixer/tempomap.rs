@@ -0,0 +1,298 @@
+use thiserror::Error;
+
+/// A position or length on the mixer timeline in bars and beats, zero based so bar 0 beat 0
+/// is the start of the mixer. `beat` may hold a fraction and go past the end of the bar.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[repr(C)]
+pub struct MusicalTime {
+    pub bar: u32,
+    pub beat: f64,
+}
+
+impl MusicalTime {
+    pub fn new(bar: u32, beat: f64) -> Self {
+        Self { bar, beat }
+    }
+
+    /// A length of whole bars.
+    pub fn bars(bar: u32) -> Self {
+        Self { bar, beat: 0.0 }
+    }
+
+    /// A length of beats, may hold more than one bar.
+    pub fn beats(beat: f64) -> Self {
+        Self { bar: 0, beat }
+    }
+}
+
+/// A tempo taking effect at a mixer position, see [TempoMap::add_change].
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TempoChange {
+    /// Mixer frame the tempo starts at.
+    pub position: usize,
+    pub bpm: f64,
+    pub beats_per_bar: u32,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Segment {
+    change: TempoChange,
+    // Beats and bars elapsed before the change
+    start_beat: f64,
+    start_bar: u32,
+}
+
+/// BPM and meter changes along the mixer timeline, converts bars and beats to mixer frames,
+/// see [Mixer::set_tempo_map](crate::Mixer::set_tempo_map).
+///
+/// A meter change landing inside a bar ends that bar early, the new meter starts a new bar.
+#[derive(Debug, Clone)]
+pub struct TempoMap {
+    segments: Vec<Segment>,
+    sample_rate: f32,
+}
+
+impl TempoMap {
+    /// A map with a single tempo from the start, positions are frames at `sample_rate`.
+    pub fn new(sample_rate: f32, bpm: f64, beats_per_bar: u32) -> Result<Self, TempoMapError> {
+        if !sample_rate.is_finite() || sample_rate <= 0.0 {
+            return Err(TempoMapError::InvalidSampleRate(sample_rate));
+        }
+
+        let mut map = Self {
+            segments: Vec::new(),
+            sample_rate,
+        };
+
+        map.add_change(0, bpm, beats_per_bar)?;
+        Ok(map)
+    }
+
+    pub fn sample_rate(&self) -> f32 {
+        self.sample_rate
+    }
+
+    /// Change the tempo and meter at mixer frame `position`, replacing a change already
+    /// there.
+    pub fn add_change(
+        &mut self,
+        position: usize,
+        bpm: f64,
+        beats_per_bar: u32,
+    ) -> Result<(), TempoMapError> {
+        if !bpm.is_finite() || bpm <= 0.0 {
+            return Err(TempoMapError::InvalidTempo(bpm));
+        }
+
+        if beats_per_bar == 0 {
+            return Err(TempoMapError::InvalidMeter(beats_per_bar));
+        }
+
+        let change = TempoChange {
+            position,
+            bpm,
+            beats_per_bar,
+        };
+
+        let mut changes = self.changes().collect::<Vec<_>>();
+        changes.retain(|other| other.position != position);
+        changes.push(change);
+        changes.sort_by_key(|change| change.position);
+
+        self.rebuild(changes);
+        Ok(())
+    }
+
+    /// Remove the change at mixer frame `position`, the first change can only be replaced.
+    pub fn remove_change(&mut self, position: usize) -> bool {
+        if position == 0 {
+            return false;
+        }
+
+        let mut changes = self.changes().collect::<Vec<_>>();
+        let count = changes.len();
+        changes.retain(|change| change.position != position);
+
+        let removed = changes.len() != count;
+        self.rebuild(changes);
+        removed
+    }
+
+    pub fn changes(&self) -> impl Iterator<Item = TempoChange> + '_ {
+        self.segments.iter().map(|segment| segment.change)
+    }
+
+    fn rebuild(&mut self, mut changes: Vec<TempoChange>) {
+        // The timeline always starts with a tempo
+        if changes.first().is_some_and(|change| change.position != 0) {
+            let first = TempoChange {
+                position: 0,
+                ..changes[0]
+            };
+            changes.insert(0, first);
+        }
+
+        self.segments.clear();
+
+        let mut start_beat = 0.0;
+        let mut start_bar = 0;
+
+        for (index, change) in changes.iter().enumerate() {
+            if index > 0 {
+                let previous = &changes[index - 1];
+                let beats = self.frames_to_beats_in(previous, change.position - previous.position);
+
+                start_beat += beats;
+                start_bar += (beats / previous.beats_per_bar as f64 - 1e-9).ceil().max(0.0) as u32;
+            }
+
+            self.segments.push(Segment {
+                change: *change,
+                start_beat,
+                start_bar,
+            });
+        }
+    }
+
+    fn frames_per_beat(&self, change: &TempoChange) -> f64 {
+        self.sample_rate as f64 * 60.0 / change.bpm
+    }
+
+    fn frames_to_beats_in(&self, change: &TempoChange, frames: usize) -> f64 {
+        frames as f64 / self.frames_per_beat(change)
+    }
+
+    /// Mixer frame of a position in bars and beats.
+    pub fn to_frames(&self, time: MusicalTime) -> usize {
+        let segment = self
+            .segments
+            .iter()
+            .rev()
+            .find(|segment| segment.start_bar <= time.bar)
+            .unwrap_or(&self.segments[0]);
+
+        let beats = (time.bar - segment.start_bar) as f64 * segment.change.beats_per_bar as f64
+            + time.beat;
+
+        self.beat_to_frames(segment.start_beat + beats)
+    }
+
+    /// Number of frames `length` lasts when it starts at mixer frame `position`, follows the
+    /// tempo changes inside it.
+    pub fn length_to_frames(&self, position: usize, length: MusicalTime) -> usize {
+        let segment = self.segment_at(position);
+        let start_beat = segment.start_beat
+            + self.frames_to_beats_in(&segment.change, position - segment.change.position);
+
+        let beats = length.bar as f64 * segment.change.beats_per_bar as f64 + length.beat;
+        self.beat_to_frames(start_beat + beats).saturating_sub(position)
+    }
+
+    /// Position in bars and beats of mixer frame `position`.
+    pub fn to_musical(&self, position: usize) -> MusicalTime {
+        let segment = self.segment_at(position);
+        let beats = self.frames_to_beats_in(&segment.change, position - segment.change.position);
+        let beats_per_bar = segment.change.beats_per_bar as f64;
+        let bars = (beats / beats_per_bar).floor();
+
+        MusicalTime {
+            bar: segment.start_bar + bars as u32,
+            beat: beats - bars * beats_per_bar,
+        }
+    }
+
+    /// Tempo in BPM and beats per bar at mixer frame `position`.
+    pub fn tempo_at(&self, position: usize) -> (f64, u32) {
+        let change = self.segment_at(position).change;
+        (change.bpm, change.beats_per_bar)
+    }
+
+    fn segment_at(&self, position: usize) -> &Segment {
+        self.segments
+            .iter()
+            .rev()
+            .find(|segment| segment.change.position <= position)
+            .unwrap_or(&self.segments[0])
+    }
+
+    fn beat_to_frames(&self, beat: f64) -> usize {
+        let beat = beat.max(0.0);
+        let segment = self
+            .segments
+            .iter()
+            .rev()
+            .find(|segment| segment.start_beat <= beat)
+            .unwrap_or(&self.segments[0]);
+
+        let frames = (beat - segment.start_beat) * self.frames_per_beat(&segment.change);
+        segment.change.position + frames.round() as usize
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum TempoMapError {
+    #[error("Invalid sample rate: {0}")]
+    InvalidSampleRate(f32),
+    #[error("Invalid tempo: {0} bpm")]
+    InvalidTempo(f64),
+    #[error("Invalid beats per bar: {0}")]
+    InvalidMeter(u32),
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // 24000 frames per beat at 120 bpm
+    const SAMPLE_RATE: f32 = 48000.0;
+
+    #[test]
+    fn test_single_tempo() {
+        let map = TempoMap::new(SAMPLE_RATE, 120.0, 4).unwrap();
+        assert_eq!(map.to_frames(MusicalTime::bars(1)), 96000);
+        assert_eq!(map.to_frames(MusicalTime::new(1, 2.0)), 144000);
+        assert_eq!(map.to_musical(144000), MusicalTime::new(1, 2.0));
+    }
+
+    #[test]
+    fn test_tempo_segments() {
+        let mut map = TempoMap::new(SAMPLE_RATE, 120.0, 4).unwrap();
+        map.add_change(192000, 60.0, 3).unwrap();
+
+        // Bar 2 starts the 3/4 segment at 48000 frames per beat
+        assert_eq!(map.to_frames(MusicalTime::bars(2)), 192000);
+        assert_eq!(map.to_frames(MusicalTime::bars(3)), 336000);
+        assert_eq!(map.to_musical(336000), MusicalTime::new(3, 0.0));
+        assert_eq!(map.tempo_at(191999), (120.0, 4));
+        assert_eq!(map.tempo_at(192000), (60.0, 3));
+
+        // One bar of the first tempo then four beats of the second
+        assert_eq!(map.length_to_frames(96000, MusicalTime::bars(2)), 288000);
+    }
+
+    #[test]
+    fn test_meter_change_inside_bar() {
+        let mut map = TempoMap::new(SAMPLE_RATE, 120.0, 4).unwrap();
+        map.add_change(48000, 120.0, 3).unwrap();
+
+        assert_eq!(map.to_frames(MusicalTime::bars(1)), 48000);
+        assert_eq!(map.to_frames(MusicalTime::bars(2)), 120000);
+    }
+
+    #[test]
+    fn test_changes() {
+        let mut map = TempoMap::new(SAMPLE_RATE, 120.0, 4).unwrap();
+        map.add_change(192000, 60.0, 3).unwrap();
+
+        assert!(!map.remove_change(0));
+        assert!(map.remove_change(192000));
+        assert!(!map.remove_change(192000));
+        assert_eq!(map.tempo_at(200000), (120.0, 4));
+
+        assert!(TempoMap::new(0.0, 120.0, 4).is_err());
+        assert!(map.add_change(0, 0.0, 4).is_err());
+        assert!(map.add_change(0, 120.0, 0).is_err());
+    }
+}
@@ -0,0 +1,192 @@
+use std::sync::{Arc, Mutex, Weak};
+
+use crate::{
+    device::snapshot::SourceKind,
+    sample::{SampleChannel, sampleinner::SampleChannelHandle},
+    track::{Track, inner::TrackChannel},
+};
+
+use super::{
+    Mixer, MixerError, MixerInput,
+    inner::{MixerChannel, MixerEntry},
+};
+
+#[derive(Debug)]
+enum MixerEdit {
+    AddTrack(Weak<Mutex<TrackChannel>>, Option<usize>, Option<usize>),
+    AddMixer(Weak<Mutex<MixerChannel>>, Option<usize>, Option<usize>),
+    AddSample(Weak<Mutex<SampleChannelHandle>>, Option<usize>, Option<usize>),
+    RemoveTrack(Weak<Mutex<TrackChannel>>),
+    RemoveMixer(Weak<Mutex<MixerChannel>>),
+    RemoveSample(Weak<Mutex<SampleChannelHandle>>),
+    Muted((SourceKind, usize), bool),
+    Solo((SourceKind, usize), bool),
+}
+
+/// Changes to the children of a mixer staged by [Mixer::edit]. Nothing is heard until the
+/// edit returns, then every change is applied between two blocks of the mixer.
+#[derive(Debug, Default)]
+pub struct MixerTransaction {
+    edits: Vec<MixerEdit>,
+    // First error met while staging, the edit is then dropped as a whole
+    error: Option<MixerError>,
+}
+
+impl MixerTransaction {
+    pub fn add_track(&mut self, track: &Track) {
+        self.add_track_ex(track, None, None);
+    }
+
+    pub fn add_track_ex(&mut self, track: &Track, delay: Option<usize>, duration: Option<usize>) {
+        let track = Arc::downgrade(&track.inner);
+        self.edits.push(MixerEdit::AddTrack(track, delay, duration));
+    }
+
+    pub fn remove_track(&mut self, track: &Track) {
+        let track = Arc::downgrade(&track.inner);
+        self.edits.push(MixerEdit::RemoveTrack(track));
+    }
+
+    pub fn add_mixer(&mut self, mixer: &Mixer) {
+        self.add_mixer_ex(mixer, None, None);
+    }
+
+    pub fn add_mixer_ex(&mut self, mixer: &Mixer, delay: Option<usize>, duration: Option<usize>) {
+        let mixer = Arc::downgrade(&mixer.inner);
+        self.edits.push(MixerEdit::AddMixer(mixer, delay, duration));
+    }
+
+    pub fn remove_mixer(&mut self, mixer: &Mixer) {
+        let mixer = Arc::downgrade(&mixer.inner);
+        self.edits.push(MixerEdit::RemoveMixer(mixer));
+    }
+
+    pub fn add_sample(&mut self, sample: &SampleChannel) {
+        self.add_sample_ex(sample, None, None);
+    }
+
+    pub fn add_sample_ex(
+        &mut self,
+        sample: &SampleChannel,
+        delay: Option<usize>,
+        duration: Option<usize>,
+    ) {
+        let sample = Arc::downgrade(&sample.inner);
+        self.edits.push(MixerEdit::AddSample(sample, delay, duration));
+    }
+
+    pub fn remove_sample(&mut self, sample: &SampleChannel) {
+        let sample = Arc::downgrade(&sample.inner);
+        self.edits.push(MixerEdit::RemoveSample(sample));
+    }
+
+    /// Add any kind of child, see [Mixer::add_track_ex].
+    pub fn add(&mut self, input: MixerInput, delay: Option<usize>, duration: Option<usize>) {
+        match input {
+            MixerInput::Track(track) => self.add_track_ex(track, delay, duration),
+            MixerInput::Mixer(mixer) => self.add_mixer_ex(mixer, delay, duration),
+            MixerInput::Sample(sample) => self.add_sample_ex(sample, delay, duration),
+        }
+    }
+
+    pub fn remove(&mut self, input: MixerInput) {
+        match input {
+            MixerInput::Track(track) => self.remove_track(track),
+            MixerInput::Mixer(mixer) => self.remove_mixer(mixer),
+            MixerInput::Sample(sample) => self.remove_sample(sample),
+        }
+    }
+
+    /// See [Mixer::set_muted], the child may be one added earlier in the same edit.
+    pub fn set_muted(&mut self, input: MixerInput, muted: bool) {
+        match input.key() {
+            Ok(key) => self.edits.push(MixerEdit::Muted(key, muted)),
+            Err(e) => self.fail(e),
+        }
+    }
+
+    /// See [Mixer::set_solo], the child may be one added earlier in the same edit.
+    pub fn set_solo(&mut self, input: MixerInput, soloed: bool) {
+        match input.key() {
+            Ok(key) => self.edits.push(MixerEdit::Solo(key, soloed)),
+            Err(e) => self.fail(e),
+        }
+    }
+
+    /// Number of staged changes.
+    pub fn len(&self) -> usize {
+        self.edits.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.edits.is_empty()
+    }
+
+    fn fail(&mut self, error: MixerError) {
+        if self.error.is_none() {
+            self.error = Some(error);
+        }
+    }
+
+    /// Apply every change in order under the lock the mixer is already held with, on failure
+    /// the children are restored as they were before the first change.
+    pub(crate) fn apply(self, inner: &mut MixerChannel) -> Result<(), MixerError> {
+        if let Some(error) = self.error {
+            return Err(error);
+        }
+
+        let entries = inner.entries.clone();
+        let muted = inner.muted.clone();
+        let soloed = inner.soloed.clone();
+
+        for edit in self.edits {
+            if let Err(e) = Self::apply_edit(inner, edit) {
+                inner.entries = entries;
+                inner.muted = muted;
+                inner.soloed = soloed;
+                _ = inner.compute_mixer_length();
+
+                return Err(e);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn apply_edit(inner: &mut MixerChannel, edit: MixerEdit) -> Result<(), MixerError> {
+        match edit {
+            MixerEdit::AddTrack(track, delay, duration) => inner.add_track(track, delay, duration),
+            MixerEdit::AddMixer(mixer, delay, duration) => inner.add_mixer(mixer, delay, duration),
+            MixerEdit::AddSample(sample, delay, duration) => {
+                inner.add_sample(sample, delay, duration)
+            }
+            MixerEdit::RemoveTrack(track) => inner.remove_track(&track),
+            MixerEdit::RemoveMixer(mixer) => inner.remove_mixer(&mixer),
+            MixerEdit::RemoveSample(sample) => inner.remove_sample(&sample),
+            MixerEdit::Muted(key, muted) => {
+                Self::set_flag(&inner.entries, &mut inner.muted, key, muted)
+            }
+            MixerEdit::Solo(key, soloed) => {
+                Self::set_flag(&inner.entries, &mut inner.soloed, key, soloed)
+            }
+        }
+    }
+
+    fn set_flag(
+        entries: &[MixerEntry],
+        keys: &mut Vec<(SourceKind, usize)>,
+        key: (SourceKind, usize),
+        value: bool,
+    ) -> Result<(), MixerError> {
+        if !entries.iter().any(|entry| entry.key() == key) {
+            return Err(MixerError::InvalidOperation("Entry not found in mixer"));
+        }
+
+        keys.retain(|entry| *entry != key);
+        if value {
+            keys.push(key);
+        }
+
+        Ok(())
+    }
+}
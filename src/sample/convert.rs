@@ -0,0 +1,63 @@
+use crate::effects::{ChannelConverter, Resampler};
+
+use super::SampleError;
+
+/// Convert interleaved `pcm` to `target_channels` channels at `target_sample_rate`, through
+/// the same resampler and channel converter channels play through.
+pub(crate) fn convert_pcm(
+    pcm: &[f32],
+    channels: usize,
+    sample_rate: f32,
+    target_channels: usize,
+    target_sample_rate: f32,
+) -> Result<Vec<f32>, SampleError> {
+    if channels == 0 || target_channels == 0 {
+        return Err(SampleError::InvalidChannels(target_channels as u32));
+    }
+
+    let pcm = resample(pcm, channels, sample_rate, target_sample_rate)?;
+    if channels == target_channels {
+        return Ok(pcm);
+    }
+
+    let frames = pcm.len() / channels;
+    let mut output = vec![0.0; frames * target_channels];
+
+    let mut converter = ChannelConverter::new();
+    converter.set_input_channels(channels);
+    converter.set_output_channels(target_channels);
+    converter.process(&pcm[..frames * channels], &mut output);
+
+    Ok(output)
+}
+
+fn resample(
+    pcm: &[f32],
+    channels: usize,
+    sample_rate: f32,
+    target_sample_rate: f32,
+) -> Result<Vec<f32>, SampleError> {
+    if sample_rate == target_sample_rate {
+        return Ok(pcm.to_vec());
+    }
+
+    if !target_sample_rate.is_finite() || target_sample_rate <= 0.0 {
+        return Err(SampleError::InvalidSampleRate(target_sample_rate as u32));
+    }
+
+    // The resampler reads at its target rate and writes at its own rate
+    let mut resampler =
+        Resampler::new(channels, target_sample_rate).map_err(SampleError::from_other)?;
+    resampler.set_target_sample_rate(sample_rate);
+
+    let frames = pcm.len() / channels;
+    let expected = (frames as f64 * target_sample_rate as f64 / sample_rate as f64).ceil() as usize;
+    let mut output = vec![0.0; (expected + 1) * channels];
+
+    let written = resampler
+        .process(&pcm[..frames * channels], &mut output)
+        .map_err(SampleError::from_other)?;
+
+    output.truncate(written * channels);
+    Ok(output)
+}
@@ -1,24 +1,33 @@
 use std::sync::{Arc, Mutex, atomic::Ordering};
 
+pub(crate) mod convert;
 pub(crate) mod sampelchannel;
 pub(crate) mod sampleinner;
 
 use crate::{
     BufferInfoOwned,
-    audioreader::cache::AudioCache,
+    audioreader::{
+        cache::AudioCache,
+        decoder,
+        stream::{StreamInfo, StreamSource},
+        transform,
+    },
     device::Device,
     effects::AudioFXError,
     misc::{
         audioattributes::AudioAttributes,
         audiopropertyhandler::{PropertyError, PropertyHandler},
+        memoryusage::MemoryUsage,
     },
     sample::sampleinner::SampleChannelStatus,
+    utils::{self, TweenType},
 };
 
 pub use sampelchannel::SampleChannel;
 use thiserror::Error;
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct SampleAttributes {
     pub enable_fx: bool,
     pub enable_spatialization: bool,
@@ -50,9 +59,16 @@ pub struct SampleInfo<'a> {
     pub source: crate::Source<'a>,
     pub sample_rate: Option<f32>,
     pub channels: Option<usize>,
+    /// Decode a [Source::Path](crate::Source::Path) a chunk at a time while it plays instead
+    /// of all at once on load, for long music tracks. Every channel of the sample decodes on
+    /// its own. Files read by a registered [AudioDecoder](crate::AudioDecoder) such as Ogg,
+    /// and all files while an [asset transform](crate::set_asset_transform) is set, are always
+    /// decoded on load.
+    pub stream: Option<StreamInfo>,
 }
 
 #[derive(Default, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct SampleChannelInfo {
     pub sample_rate: Option<f32>,
     pub channels: Option<usize>,
@@ -61,8 +77,12 @@ pub struct SampleChannelInfo {
 #[derive(Debug, Clone)]
 pub struct Sample {
     pub(crate) cache: Option<Arc<AudioCache>>,
-    pub(crate) buffer: Option<BufferInfoOwned>,
-    #[allow(dead_code)]
+    // Shared with the slices of the sample, see [Sample::slice]
+    pub(crate) buffer: Option<Arc<BufferInfoOwned>>,
+    // Set instead of the cache and buffer for a streamed sample
+    pub(crate) stream: Option<StreamSource>,
+    // First frame of the cache or buffer the sample plays, see [Sample::slice]
+    pub(crate) offset: usize,
     pub(crate) pcm_length: usize,
     pub(crate) sample_rate: f32,
     pub(crate) channels: usize,
@@ -72,6 +92,12 @@ pub struct Sample {
 
 impl Sample {
     pub(crate) fn new(info: SampleInfo) -> Result<Self, SampleError> {
+        if let (Some(stream), crate::Source::Path(path)) = (info.stream, &info.source) {
+            if decoder::find_for_file(path).is_none() && transform::asset_transform().is_none() {
+                return Self::new_stream(path, stream);
+            }
+        }
+
         let (cache, buffer_info) = info.source.into_buffer();
 
         let (cache, buffer, pcm_length, sample_rate, channels) = match buffer_info {
@@ -82,7 +108,7 @@ impl Sample {
 
                 (
                     None::<Arc<AudioCache>>,
-                    Some(buffer_info.into_owned()),
+                    Some(Arc::new(buffer_info.into_owned())),
                     pcm_length,
                     sample_rate,
                     channels,
@@ -113,6 +139,8 @@ impl Sample {
         Ok(Self {
             cache,
             buffer,
+            stream: None,
+            offset: 0,
             pcm_length,
             sample_rate,
             channels,
@@ -121,6 +149,432 @@ impl Sample {
         })
     }
 
+    fn new_stream(path: &str, info: StreamInfo) -> Result<Self, SampleError> {
+        let stream = StreamSource::new(path, info).map_err(SampleError::from_other)?;
+        let format = stream.format;
+
+        let attributes = Arc::new(Mutex::new(SampleAttributes {
+            sample_rate: format.sample_rate,
+            ..Default::default()
+        }));
+
+        Ok(Self {
+            cache: None,
+            buffer: None,
+            stream: Some(stream),
+            offset: 0,
+            pcm_length: format.frames,
+            sample_rate: format.sample_rate,
+            channels: format.channels,
+            handles: vec![],
+            attributes,
+        })
+    }
+
+    /// A sample over decoded interleaved `pcm`.
+    pub(crate) fn from_pcm(pcm: Vec<f32>, channels: usize, sample_rate: f32) -> Self {
+        let pcm_length = pcm.len() / channels.max(1);
+
+        let attributes = Arc::new(Mutex::new(SampleAttributes {
+            sample_rate,
+            ..Default::default()
+        }));
+
+        Self {
+            cache: None,
+            buffer: Some(Arc::new(BufferInfoOwned {
+                data: pcm,
+                channels,
+                sample_rate,
+            })),
+            stream: None,
+            offset: 0,
+            pcm_length,
+            sample_rate,
+            channels,
+            attributes,
+            handles: vec![],
+        }
+    }
+
+    /// A sample of `frames` frames computed by `generate`, called with the frame and channel
+    /// index of every sample in order, such as sweeps and test signals.
+    pub fn from_fn<F>(
+        sample_rate: f32,
+        channels: usize,
+        frames: usize,
+        mut generate: F,
+    ) -> Result<Sample, SampleError>
+    where
+        F: FnMut(usize, usize) -> f32,
+    {
+        if channels == 0 {
+            return Err(SampleError::InvalidChannels(0));
+        }
+
+        if !sample_rate.is_finite() || sample_rate <= 0.0 {
+            return Err(SampleError::InvalidSampleRate(sample_rate as u32));
+        }
+
+        if frames == 0 {
+            return Err(SampleError::InvalidOperation("A sample needs at least one frame"));
+        }
+
+        let mut pcm = Vec::with_capacity(frames * channels);
+        for frame in 0..frames {
+            for channel in 0..channels {
+                pcm.push(generate(frame, channel));
+            }
+        }
+
+        Ok(Self::from_pcm(pcm, channels, sample_rate))
+    }
+
+    /// A sample playing `samples` one after the other, converted to the channel count and
+    /// sample rate of the first, such as an intro followed by its loop.
+    pub fn concat(samples: &[&Sample]) -> Result<Sample, SampleError> {
+        let Some(first) = samples.first() else {
+            return Err(SampleError::InvalidOperation("No samples to concatenate"));
+        };
+
+        if samples.iter().any(|sample| sample.is_streamed()) {
+            return Err(SampleError::InvalidOperation("Streamed samples cannot be concatenated"));
+        }
+
+        let (channels, sample_rate) = (first.channels, first.sample_rate);
+        let mut pcm = Vec::with_capacity(samples.iter().map(|sample| sample.pcm().len()).sum());
+
+        for sample in samples {
+            if sample.channels == channels && sample.sample_rate == sample_rate {
+                pcm.extend_from_slice(sample.pcm());
+                continue;
+            }
+
+            let converted = convert::convert_pcm(
+                sample.pcm(),
+                sample.channels,
+                sample.sample_rate,
+                channels,
+                sample_rate,
+            )?;
+            pcm.extend_from_slice(&converted);
+        }
+
+        let sample = Self::from_pcm(pcm, channels, sample_rate);
+        if let (Ok(source), Ok(mut target)) = (first.attributes.lock(), sample.attributes.lock()) {
+            *target = source.clone();
+        }
+
+        Ok(sample)
+    }
+
+    /// Append `other` to the end of the sample, converted to its channel count and sample
+    /// rate. Fails while a channel of the sample is still held.
+    pub fn append(&mut self, other: &Sample) -> Result<(), SampleError> {
+        let joined = Self::concat(&[&*self, other])?;
+        self.replace_pcm(joined)
+    }
+
+    /// A copy of the sample resampled to `sample_rate`, such as the rate of the device it
+    /// plays on so its channels skip resampling.
+    pub fn resampled(&self, sample_rate: f32) -> Result<Sample, SampleError> {
+        self.converted(self.channels, sample_rate)
+    }
+
+    /// A copy of the sample mixed to `channels` channels.
+    pub fn with_channels(&self, channels: usize) -> Result<Sample, SampleError> {
+        self.converted(channels, self.sample_rate)
+    }
+
+    /// A copy of the sample in `channels` channels at `sample_rate`, keeping its attributes
+    /// but the sample rate.
+    pub fn converted(&self, channels: usize, sample_rate: f32) -> Result<Sample, SampleError> {
+        if self.stream.is_some() {
+            return Err(SampleError::InvalidOperation("Streamed samples cannot be converted"));
+        }
+
+        let pcm = convert::convert_pcm(
+            self.pcm(),
+            self.channels,
+            self.sample_rate,
+            channels,
+            sample_rate,
+        )?;
+
+        let sample = Self::from_pcm(pcm, channels, sample_rate);
+        if let (Ok(source), Ok(mut target)) = (self.attributes.lock(), sample.attributes.lock()) {
+            *target = SampleAttributes {
+                sample_rate,
+                ..source.clone()
+            };
+        }
+
+        Ok(sample)
+    }
+
+    /// Largest absolute sample value, 1.0 being full scale.
+    pub fn peak(&self) -> f32 {
+        self.pcm()
+            .iter()
+            .fold(0.0f32, |peak, sample| peak.max(sample.abs()))
+    }
+
+    /// Scale the sample so its peak lands on `target_db` dBFS, returns the gain applied. A
+    /// silent sample is left as is.
+    pub fn normalize_peak(&mut self, target_db: f32) -> Result<f32, SampleError> {
+        if self.stream.is_some() {
+            return Err(SampleError::InvalidOperation("Streamed samples cannot be edited"));
+        }
+
+        let peak = self.peak();
+        if peak <= 0.0 || !target_db.is_finite() {
+            return Ok(1.0);
+        }
+
+        let gain = 10f32.powf(target_db / 20.0) / peak;
+        self.apply_gain(0, self.pcm_length, gain)?;
+
+        Ok(gain)
+    }
+
+    /// The sample without its leading and trailing silence, frames with every channel below
+    /// `threshold_db` dBFS. Silence shorter than `min_silence` frames is kept. The returned
+    /// sample shares the audio, see [Sample::slice].
+    pub fn trimmed(&self, threshold_db: f32, min_silence: usize) -> Result<Sample, SampleError> {
+        if self.stream.is_some() {
+            return Err(SampleError::InvalidOperation("Streamed samples cannot be trimmed"));
+        }
+
+        let threshold = 10f32.powf(threshold_db / 20.0);
+        let loud = |frame: &[f32]| frame.iter().any(|sample| sample.abs() >= threshold);
+
+        let mut frames = self.pcm().chunks_exact(self.channels);
+        let Some(first) = frames.position(loud) else {
+            return Err(SampleError::InvalidOperation("The sample is silent"));
+        };
+
+        let last = self.pcm_length
+            - 1
+            - self
+                .pcm()
+                .chunks_exact(self.channels)
+                .rev()
+                .position(loud)
+                .unwrap_or(0);
+
+        let start = if first >= min_silence { first } else { 0 };
+        let trailing = self.pcm_length - 1 - last;
+        let end = if trailing >= min_silence { last + 1 } else { self.pcm_length };
+
+        self.slice(start, end)
+    }
+
+    /// A copy of the sample playing backwards.
+    pub fn reversed(&self) -> Result<Sample, SampleError> {
+        let mut sample = self.clone();
+        sample.handles.clear();
+        sample.attributes = Arc::new(Mutex::new(
+            self.attributes
+                .lock()
+                .map_err(|_| SampleError::LockFailed)?
+                .clone(),
+        ));
+
+        sample.reverse()?;
+        Ok(sample)
+    }
+
+    /// Reverse the sample in place.
+    pub fn reverse(&mut self) -> Result<(), SampleError> {
+        let channels = self.channels;
+
+        self.edit(|pcm| {
+            let frames = pcm.len() / channels;
+
+            for frame in 0..frames / 2 {
+                let mirror = frames - 1 - frame;
+
+                for channel in 0..channels {
+                    pcm.swap(frame * channels + channel, mirror * channels + channel);
+                }
+            }
+        })
+    }
+
+    /// Insert `other` at frame `at`, converted to the channel count and sample rate of the
+    /// sample. Like every edit it fails while a channel of the sample is held or playing.
+    pub fn insert(&mut self, at: usize, other: &Sample) -> Result<(), SampleError> {
+        if self.stream.is_some() {
+            return Err(SampleError::InvalidOperation("Streamed samples cannot be edited"));
+        }
+
+        if at > self.pcm_length {
+            return Err(SampleError::InvalidOperation("Insert position out of the sample bounds"));
+        }
+
+        let head = self.slice_pcm(0, at);
+        let tail = self.slice_pcm(at, self.pcm_length);
+        let joined = Self::concat(&[&head, other, &tail])?;
+
+        self.replace_pcm(joined)
+    }
+
+    /// Cut frames `start` to `end` out of the sample.
+    pub fn remove(&mut self, start: usize, end: usize) -> Result<(), SampleError> {
+        self.check_region(start, end)?;
+
+        if end - start == self.pcm_length {
+            return Err(SampleError::InvalidOperation("Cannot remove the whole sample"));
+        }
+
+        let channels = self.channels;
+        self.edit(|pcm| {
+            pcm.drain(start * channels..end * channels);
+        })
+    }
+
+    /// Scale frames `start` to `end` by `gain`.
+    pub fn apply_gain(&mut self, start: usize, end: usize, gain: f32) -> Result<(), SampleError> {
+        self.check_region(start, end)?;
+
+        let channels = self.channels;
+        self.edit(|pcm| {
+            for sample in pcm[start * channels..end * channels].iter_mut() {
+                *sample *= gain;
+            }
+        })
+    }
+
+    /// Fade frames `start` to `end` in from silence along `tween`.
+    pub fn fade_in(&mut self, start: usize, end: usize, tween: TweenType) -> Result<(), SampleError> {
+        self.fade(start, end, tween, false)
+    }
+
+    /// Fade frames `start` to `end` out to silence along `tween`.
+    pub fn fade_out(&mut self, start: usize, end: usize, tween: TweenType) -> Result<(), SampleError> {
+        self.fade(start, end, tween, true)
+    }
+
+    fn fade(&mut self, start: usize, end: usize, tween: TweenType, out: bool) -> Result<(), SampleError> {
+        self.check_region(start, end)?;
+
+        let channels = self.channels;
+        let length = (end - start) as f32;
+
+        self.edit(|pcm| {
+            let region = &mut pcm[start * channels..end * channels];
+
+            for (index, frame) in region.chunks_exact_mut(channels).enumerate() {
+                let t = index as f32 / length;
+                let gain = utils::tween(tween, if out { 1.0 - t } else { t });
+
+                for sample in frame.iter_mut() {
+                    *sample *= gain;
+                }
+            }
+        })
+    }
+
+    fn check_region(&self, start: usize, end: usize) -> Result<(), SampleError> {
+        if start >= end || end > self.pcm_length {
+            return Err(SampleError::InvalidOperation("Region out of the sample bounds"));
+        }
+
+        Ok(())
+    }
+
+    /// A copy of frames `start` to `end` in the format of the sample, empty regions included.
+    fn slice_pcm(&self, start: usize, end: usize) -> Sample {
+        let channels = self.channels;
+        let pcm = self.pcm()[start * channels..end * channels].to_vec();
+
+        Self::from_pcm(pcm, channels, self.sample_rate)
+    }
+
+    /// Apply `edit` to a copy of the audio and swap it in.
+    fn edit(&mut self, edit: impl FnOnce(&mut Vec<f32>)) -> Result<(), SampleError> {
+        if self.stream.is_some() {
+            return Err(SampleError::InvalidOperation("Streamed samples cannot be edited"));
+        }
+
+        let mut pcm = self.pcm().to_vec();
+        edit(&mut pcm);
+
+        self.replace_pcm(Self::from_pcm(pcm, self.channels, self.sample_rate))
+    }
+
+    /// Swap the audio of the sample for the audio of `sample`, keeping its attributes.
+    fn replace_pcm(&mut self, sample: Sample) -> Result<(), SampleError> {
+        // Channels read the audio in place, it has to outlive them. A device only keeps a weak
+        // reference to a playing channel, so the status is checked along the handed out clones.
+        let in_use = |channel: &SampleChannel| {
+            channel.get_inner_counter() > 1
+                || channel.status.load(Ordering::Relaxed) == SampleChannelStatus::Playing
+        };
+
+        if self.handles.iter().any(in_use) {
+            return Err(SampleError::InvalidOperation("The sample has channels in use"));
+        }
+
+        self.handles.clear();
+        self.cache = sample.cache;
+        self.buffer = sample.buffer;
+        self.stream = sample.stream;
+        self.offset = sample.offset;
+        self.pcm_length = sample.pcm_length;
+        self.channels = sample.channels;
+        self.sample_rate = sample.sample_rate;
+
+        Ok(())
+    }
+
+    /// A sample playing frames `start` to `end` of this one, sharing its decoded audio instead
+    /// of copying it, such as one sound of a file packing many. Streamed samples cannot be
+    /// sliced.
+    pub fn slice(&self, start: usize, end: usize) -> Result<Sample, SampleError> {
+        if self.stream.is_some() {
+            return Err(SampleError::InvalidOperation("Streamed samples cannot be sliced"));
+        }
+
+        self.check_region(start, end)?;
+
+        let attributes = self
+            .attributes
+            .lock()
+            .map_err(|_| SampleError::LockFailed)?
+            .clone();
+
+        Ok(Self {
+            cache: self.cache.clone(),
+            buffer: self.buffer.clone(),
+            stream: None,
+            offset: self.offset + start,
+            pcm_length: end - start,
+            sample_rate: self.sample_rate,
+            channels: self.channels,
+            attributes: Arc::new(Mutex::new(attributes)),
+            handles: vec![],
+        })
+    }
+
+    /// Length of the sample in frames.
+    pub fn len(&self) -> usize {
+        self.pcm_length
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pcm_length == 0
+    }
+
+    fn end(&self) -> usize {
+        self.offset + self.pcm_length
+    }
+
+    /// Whether the sample is decoded while it plays, see [SampleInfo::stream].
+    pub fn is_streamed(&self) -> bool {
+        self.stream.is_some()
+    }
+
     pub fn get_channel(
         &mut self,
         info: Option<SampleChannelInfo>,
@@ -146,6 +600,8 @@ impl Sample {
                 let handle = SampleChannel::new(
                     &self.cache,
                     &self.buffer.as_ref().map(|e| e.get_ref()),
+                    &self.stream,
+                    self.offset..self.offset + self.pcm_length,
                     self.channels,
                     self.sample_rate,
                 ).map_err(SampleError::from_other)?;
@@ -188,6 +644,43 @@ impl Sample {
         Ok(channel)
     }
 
+    /// Decoded audio of the sample, the channels it hands out read it without a copy. A
+    /// streamed sample counts the chunks its channels hold.
+    pub fn memory_usage(&self) -> MemoryUsage {
+        let streamed = self
+            .handles
+            .iter()
+            .filter_map(|channel| channel.inner.lock().ok())
+            .map(|handle| handle.memory_usage())
+            .fold(MemoryUsage::default(), |total, usage| total + usage);
+
+        let usage = MemoryUsage {
+            decoded: self
+                .buffer
+                .as_ref()
+                .map_or(0, |buffer| MemoryUsage::f32_bytes(buffer.data.capacity())),
+            shared: self
+                .cache
+                .as_ref()
+                .map_or(0, |cache| cache.buffer.heap_bytes()),
+            scratch: 0,
+        };
+
+        match self.stream {
+            Some(_) => usage + streamed,
+            None => usage,
+        }
+    }
+
+    /// Interleaved PCM of the whole sample, empty for a streamed sample.
+    pub(crate) fn pcm(&self) -> &[f32] {
+        match (&self.cache, &self.buffer) {
+            (Some(cache), _) => &cache.buffer[self.offset * self.channels..self.end() * self.channels],
+            (None, Some(buffer)) => &buffer.data[self.offset * self.channels..self.end() * self.channels],
+            (None, None) => &[],
+        }
+    }
+
     fn get_unused_channel(&mut self) -> Option<SampleChannel> {
         for channel in &self.handles {
             if channel.get_inner_counter() == 1 && channel.is_finished() {
@@ -321,6 +814,18 @@ impl PropertyHandler for Sample {
             _ => Err(PropertyError::UnsupportedAttribute("Unknown attribute")),
         }
     }
+
+    fn supported_attributes(&self) -> &'static [AudioAttributes] {
+        &[
+            AudioAttributes::SampleRate,
+            AudioAttributes::Volume,
+            AudioAttributes::Pan,
+            AudioAttributes::FXPitch,
+            AudioAttributes::FXTempo,
+            AudioAttributes::FXEnabled,
+            AudioAttributes::SpatializationEnabled,
+        ]
+    }
 }
 
 #[derive(Debug, Error)]
@@ -350,3 +855,174 @@ impl SampleError {
         SampleError::Other(Box::new(error))
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // Every frame holds its index on all of its channels
+    fn ramp(frames: usize, channels: usize) -> Sample {
+        let pcm = (0..frames * channels)
+            .map(|index| (index / channels) as f32)
+            .collect();
+
+        Sample::from_pcm(pcm, channels, 48000.0)
+    }
+
+    #[test]
+    fn test_slice() {
+        let sample = ramp(10, 2);
+        let slice = sample.slice(2, 5).unwrap();
+        assert_eq!(slice.len(), 3);
+        assert_eq!(slice.pcm(), &[2.0, 2.0, 3.0, 3.0, 4.0, 4.0]);
+
+        // A slice of a slice is bound to the frames of its parent
+        let nested = slice.slice(1, 3).unwrap();
+        assert_eq!(nested.pcm(), &[3.0, 3.0, 4.0, 4.0]);
+        assert!(slice.slice(0, 4).is_err());
+    }
+
+    #[test]
+    fn test_slice_bounds() {
+        let sample = ramp(10, 2);
+        assert!(sample.slice(5, 5).is_err());
+        assert!(sample.slice(6, 5).is_err());
+        assert!(sample.slice(0, 11).is_err());
+        assert_eq!(sample.slice(0, 10).unwrap().len(), 10);
+    }
+
+    #[test]
+    fn test_concat() {
+        let head = ramp(2, 1);
+        let tail = ramp(3, 1);
+
+        let joined = Sample::concat(&[&head, &tail]).unwrap();
+        assert_eq!(joined.pcm(), &[0.0, 1.0, 0.0, 1.0, 2.0]);
+        assert!(Sample::concat(&[]).is_err());
+
+        // Converted to the channel count of the first sample
+        let stereo = Sample::concat(&[&ramp(2, 2), &tail]).unwrap();
+        assert_eq!(stereo.channels, 2);
+        assert_eq!(stereo.len(), 5);
+    }
+
+    #[test]
+    fn test_append() {
+        let mut sample = ramp(2, 1);
+        sample.append(&ramp(2, 1)).unwrap();
+        assert_eq!(sample.pcm(), &[0.0, 1.0, 0.0, 1.0]);
+    }
+
+    #[test]
+    fn test_insert_and_remove() {
+        let mut sample = ramp(4, 1);
+        assert!(sample.insert(5, &ramp(1, 1)).is_err());
+
+        sample.insert(4, &ramp(2, 1)).unwrap();
+        assert_eq!(sample.pcm(), &[0.0, 1.0, 2.0, 3.0, 0.0, 1.0]);
+
+        sample.remove(1, 3).unwrap();
+        assert_eq!(sample.pcm(), &[0.0, 3.0, 0.0, 1.0]);
+        assert!(sample.remove(0, 4).is_err());
+        assert!(sample.remove(2, 5).is_err());
+    }
+
+    #[test]
+    fn test_gain_and_fade() {
+        let mut sample = Sample::from_pcm(vec![1.0; 4], 1, 48000.0);
+        assert!(sample.apply_gain(2, 2, 0.5).is_err());
+        assert!(sample.apply_gain(3, 5, 0.5).is_err());
+
+        sample.apply_gain(0, 2, 0.5).unwrap();
+        assert_eq!(sample.pcm(), &[0.5, 0.5, 1.0, 1.0]);
+
+        let mut sample = Sample::from_pcm(vec![1.0; 4], 1, 48000.0);
+        sample.fade_in(0, 4, TweenType::Linear).unwrap();
+        assert_eq!(sample.pcm(), &[0.0, 0.25, 0.5, 0.75]);
+
+        let mut sample = Sample::from_pcm(vec![1.0; 4], 1, 48000.0);
+        sample.fade_out(2, 4, TweenType::Linear).unwrap();
+        assert_eq!(sample.pcm(), &[1.0, 1.0, 1.0, 0.5]);
+    }
+
+    #[test]
+    fn test_edit_held_channel() {
+        let mut sample = ramp(4, 1);
+
+        let channel = sample.get_channel(None).unwrap();
+        assert!(sample.apply_gain(0, 4, 0.5).is_err());
+
+        drop(channel);
+        assert!(sample.apply_gain(0, 4, 0.5).is_ok());
+    }
+
+    #[test]
+    fn test_converted() {
+        let sample = ramp(480, 1);
+
+        let stereo = sample.with_channels(2).unwrap();
+        assert_eq!(stereo.channels, 2);
+        assert_eq!(stereo.len(), 480);
+        assert!(sample.with_channels(0).is_err());
+
+        let resampled = sample.resampled(24000.0).unwrap();
+        assert_eq!(resampled.sample_rate, 24000.0);
+        assert!(resampled.len().abs_diff(240) <= 8);
+        assert!(sample.resampled(0.0).is_err());
+    }
+
+    #[test]
+    fn test_normalize_peak() {
+        let mut sample = Sample::from_pcm(vec![0.25, -0.5, 0.1], 1, 48000.0);
+        assert_eq!(sample.peak(), 0.5);
+
+        let gain = sample.normalize_peak(0.0).unwrap();
+        assert!((gain - 2.0).abs() < 1e-6);
+        assert!((sample.peak() - 1.0).abs() < 1e-6);
+
+        // Silent samples and non finite targets are left as is
+        let mut silent = Sample::from_pcm(vec![0.0; 4], 1, 48000.0);
+        assert_eq!(silent.normalize_peak(0.0).unwrap(), 1.0);
+        assert_eq!(sample.normalize_peak(f32::NAN).unwrap(), 1.0);
+    }
+
+    #[test]
+    fn test_trimmed() {
+        let sample = Sample::from_pcm(vec![0.0, 0.0, 0.0, 1.0, 0.5, 0.0, 0.0], 1, 48000.0);
+
+        let trimmed = sample.trimmed(-60.0, 0).unwrap();
+        assert_eq!(trimmed.pcm(), &[1.0, 0.5]);
+
+        // Only the leading silence is long enough to be cut
+        let trimmed = sample.trimmed(-60.0, 3).unwrap();
+        assert_eq!(trimmed.pcm(), &[1.0, 0.5, 0.0, 0.0]);
+
+        let silent = Sample::from_pcm(vec![0.0; 4], 1, 48000.0);
+        assert!(silent.trimmed(-60.0, 0).is_err());
+    }
+
+    #[test]
+    fn test_reverse() {
+        let sample = ramp(4, 2);
+        let reversed = sample.reversed().unwrap();
+        assert_eq!(reversed.pcm(), &[3.0, 3.0, 2.0, 2.0, 1.0, 1.0, 0.0, 0.0]);
+        assert_eq!(sample.pcm()[0], 0.0);
+
+        let mut odd = ramp(3, 1);
+        odd.reverse().unwrap();
+        assert_eq!(odd.pcm(), &[2.0, 1.0, 0.0]);
+    }
+
+    #[test]
+    fn test_from_fn() {
+        let sample = Sample::from_fn(48000.0, 2, 3, |frame, channel| {
+            (frame * 10 + channel) as f32
+        })
+        .unwrap();
+
+        assert_eq!(sample.pcm(), &[0.0, 1.0, 10.0, 11.0, 20.0, 21.0]);
+        assert!(Sample::from_fn(48000.0, 0, 3, |_, _| 0.0).is_err());
+        assert!(Sample::from_fn(48000.0, 2, 0, |_, _| 0.0).is_err());
+        assert!(Sample::from_fn(0.0, 2, 3, |_, _| 0.0).is_err());
+    }
+}
@@ -1,9 +1,14 @@
-use std::sync::{Arc, Mutex, atomic::Ordering};
+use std::{
+    ops::Range,
+    sync::{Arc, Mutex, atomic::Ordering},
+};
 
 use crate::{
-    audioreader::cache::AudioCache, device::Device, effects::AudioFX, misc::{
+    audioreader::{cache::AudioCache, stream::{StreamSource, StreamStatus}}, device::Device, effects::{AudioFX, PanMode, RoutingMatrix, RoutingMatrixError}, misc::{
         audioattributes::AudioAttributes,
         audiopropertyhandler::{PropertyError, PropertyHandler},
+        attributeobserver::{AttributeEvent, AttributeObservers},
+        customattributes::AttributeValue,
     }, sample::sampleinner::{AtomicSampleChannelStatus, SampleChannelError}
 };
 
@@ -14,6 +19,7 @@ pub struct SampleChannel {
     pub(crate) device_ref_id: u32,
     pub(crate) status: Arc<AtomicSampleChannelStatus>,
     pub(crate) inner: Arc<Mutex<SampleChannelHandle>>,
+    observers: AttributeObservers,
 }
 
 impl SampleChannel {
@@ -24,10 +30,12 @@ impl SampleChannel {
     pub(crate) fn new(
         cache: &Option<Arc<AudioCache>>,
         buffer: &Option<crate::BufferInfo>,
+        stream: &Option<StreamSource>,
+        range: Range<usize>,
         channel: usize,
         sample_rate: f32,
     ) -> Result<Self, SampleChannelError> {
-        let inner = SampleChannelHandle::new(cache, buffer, channel, sample_rate)?;
+        let inner = SampleChannelHandle::new(cache, buffer, stream, range, channel, sample_rate)?;
 
         let status = Arc::clone(&inner.status);
 
@@ -35,6 +43,7 @@ impl SampleChannel {
             device_ref_id: u32::MAX,
             status,
             inner: Arc::new(Mutex::new(inner)),
+            observers: AttributeObservers::default(),
         })
     }
 
@@ -69,6 +78,86 @@ impl SampleChannel {
         Ok(())
     }
 
+    /// Call `callback` whenever an attribute is changed through [PropertyHandler], with the
+    /// attribute and its new value.
+    pub fn on_attribute_changed<F>(&mut self, callback: F)
+    where
+        F: FnMut(&AttributeEvent) + Send + 'static,
+    {
+        self.observers.add(Box::new(callback));
+    }
+
+    /// Remove every callback added with [SampleChannel::on_attribute_changed].
+    pub fn clear_attribute_observers(&mut self) {
+        self.observers.clear();
+    }
+
+    /// Put the sample channel in a ducking group, see [DuckingRule](crate::DuckingRule).
+    pub fn set_ducking_group(&mut self, group: Option<u32>) -> Result<(), SampleError> {
+        let Ok(mut handle) = self.inner.lock() else {
+            return Err(SampleError::LockFailed);
+        };
+
+        handle.group = group;
+        Ok(())
+    }
+
+    /// Choose how [AudioAttributes::Pan] moves stereo content, see [PanMode].
+    pub fn set_pan_mode(&mut self, mode: PanMode) -> Result<(), SampleError> {
+        let Ok(mut handle) = self.inner.lock() else {
+            return Err(SampleError::LockFailed);
+        };
+
+        handle.panner.set_mode(mode);
+        Ok(())
+    }
+
+    pub fn get_pan_mode(&self) -> Result<PanMode, SampleError> {
+        let Ok(handle) = self.inner.lock() else {
+            return Err(SampleError::LockFailed);
+        };
+
+        Ok(handle.panner.mode)
+    }
+
+    /// Route the channel to the device speakers through `routing` instead of the default
+    /// channel conversion, see [Track::set_routing](crate::Track::set_routing).
+    pub fn set_routing(&mut self, routing: Option<RoutingMatrix>) -> Result<(), SampleError> {
+        let Ok(mut handle) = self.inner.lock() else {
+            return Err(SampleError::LockFailed);
+        };
+
+        if let Some(routing) = routing.as_ref() {
+            let channels = handle.channel_converter.get_output_channels();
+            if routing.inputs() != channels {
+                return Err(SampleError::from_other(RoutingMatrixError::InvalidChannels(
+                    routing.inputs(),
+                )));
+            }
+        }
+
+        handle.routing = routing;
+        Ok(())
+    }
+
+    pub fn get_routing(&self) -> Result<Option<RoutingMatrix>, SampleError> {
+        let Ok(handle) = self.inner.lock() else {
+            return Err(SampleError::LockFailed);
+        };
+
+        Ok(handle.routing.clone())
+    }
+
+    /// Prebuffer state of a channel of a streamed sample decoded by a thread, None for
+    /// other channels, see [StreamInfo::prebuffer_ms](crate::StreamInfo::prebuffer_ms).
+    pub fn stream_status(&self) -> Result<Option<StreamStatus>, SampleError> {
+        let Ok(handle) = self.inner.lock() else {
+            return Err(SampleError::LockFailed);
+        };
+
+        Ok(handle.reader.stream.as_ref().and_then(|stream| stream.status()))
+    }
+
     pub fn is_finished(&self) -> bool {
         self.status.load(Ordering::Relaxed) == SampleChannelStatus::Finished
     }
@@ -79,6 +168,9 @@ impl SampleChannel {
                 .status
                 .store(SampleChannelStatus::NotStarted, Ordering::Relaxed);
 
+            // Reused channels start without the attributes of their previous owner
+            handle.custom_attributes = Default::default();
+
             if let Some(info) = info {
                 if let Some(sample_rate) = info.sample_rate {
                     let _ = handle.resampler.set_target_sample_rate(sample_rate);
@@ -94,40 +186,8 @@ impl SampleChannel {
     }
 }
 
-impl PropertyHandler for SampleChannel {
-    fn get_attribute_f32(&self, _type: AudioAttributes) -> Result<f32, PropertyError> {
-        let lock = crate::macros::check!(
-            self.inner.lock(),
-            PropertyError::InvalidOperation("Failed to lock SampleChannelHandle")
-        );
-
-        match _type {
-            AudioAttributes::SampleRate => Ok(lock.resampler.sample_rate as f32),
-            AudioAttributes::Volume => Ok(lock.volume.volume),
-            AudioAttributes::Pan => Ok(lock.panner.pan),
-            AudioAttributes::FXPitch => {
-                if let Some(fx) = &lock.fx {
-                    Ok(fx.octave)
-                } else {
-                    Err(PropertyError::InvalidOperation(
-                        "FX must be enabled to get FXPitch",
-                    ))
-                }
-            }
-            AudioAttributes::FXTempo => {
-                if let Some(fx) = &lock.fx {
-                    Ok(fx.tempo)
-                } else {
-                    Err(PropertyError::InvalidOperation(
-                        "FX must be enabled to get FXTempo",
-                    ))
-                }
-            }
-            _ => Err(PropertyError::UnsupportedAttribute("Unknown attribute")),
-        }
-    }
-
-    fn set_attribute_f32(
+impl SampleChannel {
+    fn apply_attribute_f32(
         &mut self,
         _type: AudioAttributes,
         value: f32,
@@ -153,6 +213,11 @@ impl PropertyHandler for SampleChannel {
 
                 Ok(())
             }
+            AudioAttributes::Balance => {
+                lock.panner.set_balance(value);
+
+                Ok(())
+            }
             AudioAttributes::FXTempo => {
                 if let Some(fx) = &mut lock.fx {
                     fx.set_tempo(value).map_err(PropertyError::from_other)
@@ -175,19 +240,7 @@ impl PropertyHandler for SampleChannel {
         }
     }
 
-    fn get_attribute_bool(&self, _type: AudioAttributes) -> Result<bool, PropertyError> {
-        let lock = crate::macros::check!(
-            self.inner.lock(),
-            PropertyError::InvalidOperation("Failed to lock SampleChannelHandle")
-        );
-
-        match _type {
-            AudioAttributes::FXEnabled => Ok(lock.fx.is_some()),
-            _ => Err(PropertyError::UnsupportedAttribute("Unknown attribute")),
-        }
-    }
-
-    fn set_attribute_bool(
+    fn apply_attribute_bool(
         &mut self,
         _type: AudioAttributes,
         value: bool,
@@ -211,7 +264,151 @@ impl PropertyHandler for SampleChannel {
 
                 Ok(())
             }
+            AudioAttributes::ForceMono => {
+                lock.panner.force_mono = value;
+
+                Ok(())
+            }
+            AudioAttributes::SwapChannels => {
+                lock.panner.swap_channels = value;
+
+                Ok(())
+            }
+            _ => Err(PropertyError::UnsupportedAttribute("Unknown attribute")),
+        }
+    }
+
+    fn apply_custom_attribute(
+        &mut self,
+        name: &str,
+        value: AttributeValue,
+    ) -> Result<(), PropertyError> {
+        let Ok(mut inner) = self.inner.lock() else {
+            return Err(PropertyError::InvalidOperation("Failed to lock SampleChannelHandle"));
+        };
+
+        inner.custom_attributes.set(name, value);
+        Ok(())
+    }
+}
+
+impl PropertyHandler for SampleChannel {
+    fn get_attribute_f32(&self, _type: AudioAttributes) -> Result<f32, PropertyError> {
+        let lock = crate::macros::check!(
+            self.inner.lock(),
+            PropertyError::InvalidOperation("Failed to lock SampleChannelHandle")
+        );
+
+        match _type {
+            AudioAttributes::SampleRate => Ok(lock.resampler.sample_rate as f32),
+            AudioAttributes::Volume => Ok(lock.volume.volume),
+            AudioAttributes::Pan => Ok(lock.panner.pan),
+            AudioAttributes::Balance => Ok(lock.panner.get_balance()),
+            AudioAttributes::FXPitch => {
+                if let Some(fx) = &lock.fx {
+                    Ok(fx.octave)
+                } else {
+                    Err(PropertyError::InvalidOperation(
+                        "FX must be enabled to get FXPitch",
+                    ))
+                }
+            }
+            AudioAttributes::FXTempo => {
+                if let Some(fx) = &lock.fx {
+                    Ok(fx.tempo)
+                } else {
+                    Err(PropertyError::InvalidOperation(
+                        "FX must be enabled to get FXTempo",
+                    ))
+                }
+            }
+            _ => Err(PropertyError::UnsupportedAttribute("Unknown attribute")),
+        }
+    }
+
+    fn set_attribute_f32(
+        &mut self,
+        _type: AudioAttributes,
+        value: f32,
+    ) -> Result<(), PropertyError> {
+        self.apply_attribute_f32(_type, value)?;
+
+        let value = self.get_attribute_f32(_type).unwrap_or(value);
+        self.observers.notify(AttributeEvent {
+            attribute: _type,
+            name: None,
+            value: AttributeValue::F32(value),
+        });
+
+        Ok(())
+    }
+
+    fn get_attribute_bool(&self, _type: AudioAttributes) -> Result<bool, PropertyError> {
+        let lock = crate::macros::check!(
+            self.inner.lock(),
+            PropertyError::InvalidOperation("Failed to lock SampleChannelHandle")
+        );
+
+        match _type {
+            AudioAttributes::FXEnabled => Ok(lock.fx.is_some()),
+            AudioAttributes::ForceMono => Ok(lock.panner.force_mono),
+            AudioAttributes::SwapChannels => Ok(lock.panner.swap_channels),
             _ => Err(PropertyError::UnsupportedAttribute("Unknown attribute")),
         }
     }
+
+    fn set_attribute_bool(
+        &mut self,
+        _type: AudioAttributes,
+        value: bool,
+    ) -> Result<(), PropertyError> {
+        self.apply_attribute_bool(_type, value)?;
+
+        let value = self.get_attribute_bool(_type).unwrap_or(value);
+        self.observers.notify(AttributeEvent {
+            attribute: _type,
+            name: None,
+            value: AttributeValue::Bool(value),
+        });
+
+        Ok(())
+    }
+
+    fn get_custom_attribute(&self, name: &str) -> Result<AttributeValue, PropertyError> {
+        let Ok(inner) = self.inner.lock() else {
+            return Err(PropertyError::InvalidOperation("Failed to lock SampleChannelHandle"));
+        };
+
+        inner.custom_attributes.get(name)
+    }
+
+    fn set_custom_attribute(
+        &mut self,
+        name: &str,
+        value: AttributeValue,
+    ) -> Result<(), PropertyError> {
+        self.apply_custom_attribute(name, value)?;
+
+        self.observers.notify(AttributeEvent {
+            attribute: AudioAttributes::Unknown,
+            name: Some(name),
+            value,
+        });
+
+        Ok(())
+    }
+
+    fn supported_attributes(&self) -> &'static [AudioAttributes] {
+        &[
+            AudioAttributes::SampleRate,
+            AudioAttributes::Volume,
+            AudioAttributes::Pan,
+            AudioAttributes::Balance,
+            AudioAttributes::FXPitch,
+            AudioAttributes::FXTempo,
+            AudioAttributes::FXEnabled,
+            AudioAttributes::ForceMono,
+            AudioAttributes::SwapChannels,
+        ]
+    }
 }
@@ -1,13 +1,23 @@
-use std::sync::{Arc, atomic::Ordering};
+use std::{
+    ops::Range,
+    sync::{Arc, atomic::Ordering},
+};
 
 use thiserror::Error;
 
 use crate::{
-    audioreader::{AudioReader, cache::AudioCache},
+    audioreader::{AudioReader, cache::AudioCache, stream::StreamSource},
     effects::{
         AudioFX, AudioPanner, SpatializationListener, AudioVolume, ChannelConverter, Resampler,
+        RoutingMatrix,
+    },
+    math::{MathUtils, MathUtilsTrait as _},
+    misc::{
+        bufferpool::{BufferPool, ScratchBuffer},
+        customattributes::CustomAttributes,
+        memoryusage::MemoryUsage,
     },
-    math::{MathUtils, MathUtilsTrait as _}, utils,
+    utils,
 };
 
 #[derive(Debug)]
@@ -20,27 +30,45 @@ pub struct SampleChannelHandle {
     pub(crate) resampler: Resampler,
     pub(crate) channel_converter: ChannelConverter,
     pub(crate) fx: Option<AudioFX>,
+    // Used instead of the caller channel conversion when its outputs match the caller
+    pub(crate) routing: Option<RoutingMatrix>,
 
     pub(crate) status: Arc<AtomicSampleChannelStatus>,
+
+    // Ducking group, see [DuckingRule](crate::DuckingRule)
+    pub(crate) group: Option<u32>,
+
+    pub(crate) custom_attributes: CustomAttributes,
+
+    // Set when attached to a device
+    pub(crate) pool: Option<Arc<BufferPool>>,
 }
 
 impl SampleChannelHandle {
+    pub(crate) fn memory_usage(&self) -> MemoryUsage {
+        self.reader.memory_usage()
+    }
+
     pub(crate) fn new(
         cache: &Option<Arc<AudioCache>>,
         buffer: &Option<crate::BufferInfo>,
+        stream: &Option<StreamSource>,
+        range: Range<usize>,
         channel: usize,
         sample_rate: f32,
     ) -> Result<Self, SampleChannelError> {
         let reader = if let Some(cache_key) = cache {
-            AudioReader::load_cache(Arc::clone(cache_key))
+            AudioReader::load_cache_range(Arc::clone(cache_key), range.start, range.len())
+        } else if let Some(stream) = stream {
+            AudioReader::load_stream(stream)
         } else if let Some(buffer_info) = buffer {
-            let pcm_length = buffer_info.data.len() / buffer_info.channels;
+            let channels = buffer_info.channels;
 
             AudioReader::load_audio_buffer(
-                buffer_info.data,
+                &buffer_info.data[range.start * channels..range.end * channels],
                 buffer_info.sample_rate,
-                buffer_info.channels,
-                pcm_length,
+                channels,
+                range.len(),
                 false,
             )
         } else {
@@ -83,7 +111,11 @@ impl SampleChannelHandle {
             resampler,
             channel_converter,
             fx: None,
+            routing: None,
             status,
+            group: None,
+            custom_attributes: CustomAttributes::default(),
+            pool: None,
         })
     }
 
@@ -187,7 +219,7 @@ impl SampleChannelHandle {
 
                 channel_converter.set_input_channels(self.channel_converter.get_output_channels());
 
-                // Caller conversion
+                // Caller conversion, or the user routing to the caller speakers
                 {
                     let src = crate::macros::make_slice!(
                         buffer1,
@@ -200,7 +232,14 @@ impl SampleChannelHandle {
                         channel_converter.get_output_channels()
                     );
 
-                    channel_converter.process(src, dst);
+                    match self
+                        .routing
+                        .as_ref()
+                        .filter(|routing| routing.outputs() == channel_converter.get_output_channels())
+                    {
+                        Some(routing) => routing.process(src, dst),
+                        None => channel_converter.process(src, dst),
+                    }
                 }
             }
         } else {
@@ -230,7 +269,11 @@ impl SampleChannelHandle {
                 SampleChannelError::from_other
             );
             
-            let mut data = vec![0.0; latency * self.reader.channels];
+            let pool = self.pool.clone();
+            let len = latency * self.reader.channels;
+            let Some(mut data) = ScratchBuffer::new(pool.as_deref(), len) else {
+                return Err(SampleChannelError::ScratchExhausted(len));
+            };
 
             crate::macros::check_ret!(
                 self.reader.read(crate::macros::make_slice_mut!(
@@ -263,6 +306,8 @@ pub enum SampleChannelError {
     SeekOutOfBounds(usize),
     #[error("Failed to lock Sample")]
     LockFailed,
+    #[error("The scratch buffer pool has no free buffer of {0} samples")]
+    ScratchExhausted(usize),
     #[error("{0}")]
     Other(Box<dyn std::error::Error + Send + 'static>),
 }
@@ -0,0 +1,127 @@
+// Channels the ramp state is kept for, sources with more channels are not declicked
+const MAX_CHANNELS: usize = 8;
+
+/// Length of the ramp tracks are created with.
+pub(crate) const DEFAULT_DECLICK_MS: f32 = 2.0;
+
+/// Smooths the jumps a track makes when it starts, seeks or stops away from a zero crossing.
+///
+/// After a jump the difference between the last frame heard and the new audio is added back
+/// and faded out over the ramp, so the output moves continuously from one to the other. A
+/// stop fades the output to silence over the ramp before the track stops playing.
+#[derive(Debug, Clone)]
+pub(crate) struct Declicker {
+    frames: usize,
+    last: [f32; MAX_CHANNELS],
+    offset: [f32; MAX_CHANNELS],
+    remaining: usize,
+    pending: bool,
+    stop_remaining: Option<usize>,
+    stop_frames: usize,
+}
+
+impl Declicker {
+    pub fn new(frames: usize) -> Self {
+        Self {
+            frames,
+            last: [0.0; MAX_CHANNELS],
+            offset: [0.0; MAX_CHANNELS],
+            remaining: 0,
+            pending: false,
+            stop_remaining: None,
+            stop_frames: 0,
+        }
+    }
+
+    pub fn frames(&self) -> usize {
+        self.frames
+    }
+
+    /// Ramp length in output frames, 0 disables declicking.
+    pub fn set_frames(&mut self, frames: usize) {
+        self.frames = frames;
+        self.remaining = self.remaining.min(frames);
+    }
+
+    /// Forget the last frame heard, the next jump starts from silence.
+    pub fn reset(&mut self) {
+        self.last = [0.0; MAX_CHANNELS];
+        self.remaining = 0;
+        self.stop_remaining = None;
+    }
+
+    /// The next output does not continue the previous one.
+    pub fn mark_discontinuity(&mut self) {
+        self.pending = self.frames > 0;
+    }
+
+    /// Start fading out over the ramp, false when disabled and the caller has to stop right
+    /// away.
+    pub fn begin_stop(&mut self) -> bool {
+        self.fade_out(self.frames)
+    }
+
+    /// Start fading out over `frames`, a fade already running is kept. False when `frames`
+    /// is 0 and the caller has to stop right away.
+    pub fn fade_out(&mut self, frames: usize) -> bool {
+        if frames == 0 {
+            return false;
+        }
+
+        if self.stop_remaining.is_none() {
+            self.stop_remaining = Some(frames);
+            self.stop_frames = frames;
+        }
+
+        true
+    }
+
+    /// Ramp interleaved `output`, returns true once a stop finished fading out. The frames
+    /// after the end of the fade are silenced.
+    pub fn process(&mut self, output: &mut [f32], channels: usize) -> bool {
+        if channels == 0 || channels > MAX_CHANNELS || output.len() < channels {
+            return self.stop_remaining.take().is_some();
+        }
+
+        if self.pending {
+            for channel in 0..channels {
+                self.offset[channel] = self.last[channel] - output[channel];
+            }
+
+            self.remaining = self.frames;
+            self.pending = false;
+        }
+
+        let mut stopped = false;
+
+        for frame in output.chunks_exact_mut(channels) {
+            if self.remaining > 0 {
+                let gain = self.remaining as f32 / self.frames as f32;
+                for (sample, offset) in frame.iter_mut().zip(self.offset.iter()) {
+                    *sample += offset * gain;
+                }
+
+                self.remaining -= 1;
+            }
+
+            if let Some(stop_remaining) = self.stop_remaining.as_mut() {
+                let gain = *stop_remaining as f32 / self.stop_frames as f32;
+                frame.iter_mut().for_each(|sample| *sample *= gain);
+
+                *stop_remaining = stop_remaining.saturating_sub(1);
+                stopped |= *stop_remaining == 0;
+            }
+        }
+
+        let last = output.len() / channels * channels;
+        if last > 0 {
+            self.last[..channels].copy_from_slice(&output[last - channels..last]);
+        }
+
+        if stopped {
+            self.reset();
+        }
+
+        stopped
+    }
+}
@@ -1,17 +1,37 @@
 use crate::{
     BufferInfo,
     audioreader::{AudioReader, cache::AudioCache},
+    device::ducking::SourceDucking,
     effects::{
-        AudioFX, AudioPanner, Spatialization, SpatializationListener, AudioVolume,
-        ChannelConverter, Resampler,
+        AudioEffect, AudioFX, AudioFXError, AudioPanner, FXSeekMode, Spatialization, SpatializationListener, AudioVolume,
+        ChannelConverter, ListenerSelection, Resampler, RoutingMatrix,
     },
+    generators::AudioGenerator,
     math::{MathUtils, MathUtilsTrait},
-    track::TrackError,
+    misc::{
+        attributeobserver::AutomatedChanges,
+        audioattributes::AudioAttributes,
+        audiopropertyhandler::PropertyError,
+        bufferpool::{BufferPool, ScratchBuffer},
+        customattributes::{AttributeValue, CustomAttributes},
+        denormal,
+        envelope::{ENVELOPE_STEP, Envelopes},
+        finishsignal::{Completion, FinishSignal, PlaybackEnd},
+        memoryusage::MemoryUsage,
+    },
+    track::{
+        TrackError,
+        declick::{DEFAULT_DECLICK_MS, Declicker},
+        loopfade,
+        schedule::AttributeSchedule,
+        scrub::Scrubber,
+        trainer::TrainerState,
+    },
 };
 use std::{
     sync::{
         Arc,
-        atomic::{AtomicBool, AtomicUsize, Ordering},
+        atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
     },
     time::Instant,
 };
@@ -29,16 +49,64 @@ pub(crate) struct TrackChannel {
     pub resampler: Resampler,
     pub channel_converter: ChannelConverter,
     pub fx: Option<AudioFX>,
+    // Used instead of the caller channel conversion when its outputs match the caller
+    pub routing: Option<RoutingMatrix>,
 
     pub playing: Arc<AtomicBool>,
+    pub finished: Arc<FinishSignal>,
     pub is_looping: Arc<AtomicBool>,
     pub position: Arc<AtomicUsize>,
 
     pub spatializer: Option<Spatialization>,
+    // Device listener the spatializer renders against, see [ListenerSelection]
+    pub listener: ListenerSelection,
     pub callback: Option<Box<dyn FnMut(&mut [f32]) + Send + 'static>>,
+    // Run after the volume and pan at the channel count of the audio, see [Track::set_effects](crate::Track::set_effects)
+    pub effects: Vec<Box<dyn AudioEffect>>,
 
     pub start: Option<usize>,
     pub end: Option<usize>,
+    // Taken by the next play instead of start, set from TrackInfo::position
+    pub cue: Option<usize>,
+
+    // Ramps the jumps on play, seek and stop
+    pub declick: Declicker,
+    // Device time and fade length in frames, see [Track::stop_at](crate::Track::stop_at)
+    pub stop_at: Option<(u64, usize)>,
+    // Device time the output starts at, see [Track::play_at](crate::Track::play_at)
+    pub start_at: Option<u64>,
+
+    // Frames blended across the loop seam, see [Track::set_loop_crossfade_ms](crate::Track::set_loop_crossfade_ms)
+    pub loop_crossfade: usize,
+    // Source frames played over and over, see [Transport::set_loop_region](crate::Transport::set_loop_region)
+    pub loop_region: Option<(usize, usize)>,
+    // Raises the FXTempo each time the loop region wraps, see [Track::start_speed_trainer](crate::Track::start_speed_trainer)
+    pub trainer: Option<TrainerState>,
+
+    // How seeking primes the fx, see [FXSeekMode]
+    pub fx_seek_mode: FXSeekMode,
+
+    // Replaces the normal read while scrubbing, see [Track::scrub_to](crate::Track::scrub_to)
+    pub scrub: Option<Scrubber>,
+
+    // Ducking group, see [DuckingRule](crate::DuckingRule)
+    pub group: Option<u32>,
+    // Set by the device for the block being rendered, applied ahead of the spatial stage
+    pub ducking: SourceDucking,
+
+    pub custom_attributes: CustomAttributes,
+
+    // Changes waiting for the device clock, see [Track::set_attribute_f32_at](crate::Track::set_attribute_f32_at)
+    pub schedule: AttributeSchedule,
+    // Evaluated from the played position while reading, see [Track::set_envelope](crate::Track::set_envelope)
+    pub envelopes: Envelopes,
+    // Scheduled and envelope changes for the observers, see [Track::on_attribute_changed](crate::Track::on_attribute_changed)
+    pub automated: Option<AutomatedChanges>,
+
+    // Set when attached to a device
+    pub pool: Option<Arc<BufferPool>>,
+    // Device time of the block being rendered, set when attached to a device
+    pub clock: Option<Arc<AtomicU64>>,
 }
 
 impl std::fmt::Debug for TrackChannel {
@@ -69,6 +137,10 @@ impl std::fmt::Debug for TrackChannel {
 
 #[allow(dead_code)]
 impl TrackChannel {
+    pub fn memory_usage(&self) -> MemoryUsage {
+        self.reader.memory_usage()
+    }
+
     pub fn new(
         ref_id: usize,
         cache: Option<Arc<AudioCache>>,
@@ -94,6 +166,76 @@ impl TrackChannel {
             return Err(TrackError::CreateFailed);
         };
 
+        Self::with_reader(ref_id, reader, sample_rate, channels)
+    }
+
+    /// A track over a procedural source, see [Source::Generator](crate::Source::Generator).
+    pub fn from_generator(
+        ref_id: usize,
+        generator: Box<dyn AudioGenerator>,
+        sample_rate: Option<f32>,
+        channels: Option<usize>,
+    ) -> Result<Self, TrackError> {
+        let reader = crate::macros::check!(
+            AudioReader::load_generator(generator),
+            TrackError::CreateFailed
+        );
+
+        Self::with_reader(ref_id, reader, sample_rate, channels)
+    }
+
+    /// A track streaming the file at `path` from disk, see [TrackInfo::stream](crate::TrackInfo::stream).
+    pub fn from_stream(
+        ref_id: usize,
+        path: &str,
+        sample_rate: Option<f32>,
+        channels: Option<usize>,
+    ) -> Result<Self, TrackError> {
+        let reader = crate::macros::check!(
+            AudioReader::load_double_buffered(path),
+            TrackError::CreateFailed
+        );
+
+        Self::with_reader(ref_id, reader, sample_rate, channels)
+    }
+
+    /// A channel playing the same decoded audio from the start with the volume, pan, loop
+    /// and output format of this one, see [Track::duplicate](crate::Track::duplicate).
+    pub fn duplicate(&mut self, ref_id: usize) -> Result<Self, TrackError> {
+        let reader = match self.reader.share() {
+            Ok(Some(reader)) => reader,
+            Ok(None) => return Err(TrackError::NotDuplicable),
+            Err(e) => return Err(TrackError::from_other(e)),
+        };
+
+        let mut track = Self::with_reader(
+            ref_id,
+            reader,
+            Some(self.resampler.target_sample_rate),
+            Some(self.channel_converter.get_output_channels()),
+        )?;
+
+        track.gainer.set_volume(self.gainer.volume);
+        track.panner.set_pan(self.panner.pan);
+        track.panner.set_mode(self.panner.mode);
+        track
+            .is_looping
+            .store(self.is_looping.load(Ordering::SeqCst), Ordering::SeqCst);
+        track.start = self.start;
+        track.end = self.end;
+        track.loop_crossfade = self.loop_crossfade;
+        track.fx_seek_mode = self.fx_seek_mode;
+        track.group = self.group;
+
+        Ok(track)
+    }
+
+    fn with_reader(
+        ref_id: usize,
+        reader: AudioReader,
+        sample_rate: Option<f32>,
+        channels: Option<usize>,
+    ) -> Result<Self, TrackError> {
         let panner = crate::macros::check!(AudioPanner::new(reader.channels), TrackError::CreateFailed);
         let gainer = crate::macros::check!(AudioVolume::new(reader.channels), TrackError::CreateFailed);
         let mut resampler = crate::macros::check!(
@@ -123,17 +265,212 @@ impl TrackChannel {
             resampler,
             channel_converter,
             fx: None,
+            routing: None,
             playing: atomic_playing,
+            finished: Arc::new(FinishSignal::default()),
             is_looping: atomic_is_looping,
             position: atomic_position,
             spatializer: None,
+            listener: ListenerSelection::default(),
             callback: None,
+            effects: Vec::new(),
             start: None,
             end: None,
+            cue: None,
+            loop_crossfade: 0,
+            loop_region: None,
+            trainer: None,
+            declick: Declicker::new((sample_rate * DEFAULT_DECLICK_MS / 1000.0) as usize),
+            stop_at: None,
+            start_at: None,
+            fx_seek_mode: FXSeekMode::default(),
+            scrub: None,
+            group: None,
+            ducking: SourceDucking::default(),
+            custom_attributes: CustomAttributes::default(),
+            schedule: AttributeSchedule::default(),
+            envelopes: Envelopes::default(),
+            automated: None,
+            pool: None,
+            clock: None,
         })
     }
 
+    /// Read `frame_count` frames, splitting the block at the device time of every scheduled
+    /// change so it lands on the exact frame, and every [ENVELOPE_STEP] frames while an
+    /// envelope is attached.
     pub fn read(
+        &mut self,
+        mut spatializer_listener: Option<&mut SpatializationListener>,
+        channel_converter: &mut ChannelConverter,
+        output: &mut [f32],
+        buffer1: &mut [f32],
+        frame_count: usize,
+    ) -> Result<usize, TrackError> {
+        let block_time = self
+            .clock
+            .as_ref()
+            .filter(|_| {
+                !self.schedule.is_empty() || self.stop_at.is_some() || self.start_at.is_some()
+            })
+            .map(|clock| clock.load(Ordering::Acquire));
+
+        if block_time.is_none() && self.envelopes.is_empty() && self.loop_region.is_none() {
+            return self.read_block(
+                spatializer_listener,
+                channel_converter,
+                output,
+                buffer1,
+                frame_count,
+            );
+        }
+
+        if !self.playing.load(Ordering::SeqCst) {
+            return Ok(0);
+        }
+
+        let channels = channel_converter.get_output_channels() as usize;
+        let pool = self.pool.clone();
+        // Returning no frames detaches the track, so a block without a free buffer is left
+        // silent instead and counted in the device stats
+        let Some(mut rendered_output) = ScratchBuffer::new(pool.as_deref(), frame_count * channels)
+        else {
+            output[..frame_count * channels].fill(0.0);
+            return Ok(frame_count);
+        };
+        let mut rendered = 0;
+
+        while rendered < frame_count {
+            let mut count = frame_count - rendered;
+
+            if let Some(block_time) = block_time {
+                let time = block_time + rendered as u64;
+
+                match self.start_at {
+                    Some(start_time) if start_time > time => {
+                        let silence = count.min((start_time - time) as usize);
+                        rendered_output[rendered * channels..(rendered + silence) * channels]
+                            .fill(0.0);
+
+                        self.ducking.skip(silence);
+                        rendered += silence;
+                        continue;
+                    }
+                    Some(_) => self.start_at = None,
+                    None => {}
+                }
+
+                // Values are checked when scheduled, only an FX change while AudioFX is
+                // disabled can fail here and is dropped
+                while let Some(change) = self.schedule.pop_due(time) {
+                    self.apply_automated(change.attribute, change.value);
+                }
+
+                if let Some(next) = self.schedule.next_time() {
+                    count = count.min((next - time) as usize);
+                }
+
+                match self.stop_at {
+                    Some((stop_time, fade)) if stop_time <= time => {
+                        self.stop_at = None;
+
+                        if !self.declick.fade_out(fade) {
+                            self.playing.store(false, Ordering::SeqCst);
+                            self.finished.notify(PlaybackEnd::Stopped);
+                            break;
+                        }
+                    }
+                    Some((stop_time, _)) => count = count.min((stop_time - time) as usize),
+                    None => {}
+                }
+            }
+
+            if let Some((start, end)) = self.loop_region {
+                count = count.min(self.wrap_loop_region(start, end)?);
+            }
+
+            // Applied after the scheduled changes, an envelope wins over a change to the same
+            // attribute
+            if !self.envelopes.is_empty() {
+                self.apply_envelopes();
+                count = count.min(ENVELOPE_STEP);
+            }
+
+            let read = self.read_block(
+                spatializer_listener.as_deref_mut(),
+                channel_converter,
+                output,
+                buffer1,
+                count,
+            )?;
+
+            rendered_output[rendered * channels..(rendered + read) * channels]
+                .copy_from_slice(&output[..read * channels]);
+            rendered += read;
+
+            if read < count {
+                break;
+            }
+        }
+
+        output[..rendered * channels].copy_from_slice(&rendered_output[..rendered * channels]);
+        Ok(rendered)
+    }
+
+    /// Seek back to `start` once the reader reached `end`, returns the output frames left
+    /// until it reaches `end` again.
+    fn wrap_loop_region(&mut self, start: usize, end: usize) -> Result<usize, TrackError> {
+        if self.reader.position >= end {
+            self.seek(start)?;
+            self.finished.notify(PlaybackEnd::Looped);
+
+            if let Some(trainer) = self.trainer.as_mut() {
+                let tempo = trainer.next_repetition();
+                if let Some(fx) = self.fx.as_mut() {
+                    crate::macros::check!(fx.set_tempo(tempo), TrackError::ProcessingFailed);
+                }
+            }
+        }
+
+        let tempo = match self.fx.as_ref() {
+            Some(fx) if !fx.tempo_bypass() => fx.tempo,
+            _ => 1.0,
+        };
+
+        let remaining = end.saturating_sub(self.reader.position) as f32;
+        let frames = (remaining / (self.resampler.ratio() * tempo)).ceil() as usize;
+
+        Ok(frames.max(1))
+    }
+
+    /// Set an attribute from the audio thread, the change is queued for the observers when it
+    /// moved the value. False when the value was rejected.
+    fn apply_automated(&mut self, attribute: AudioAttributes, value: f32) -> bool {
+        let previous = self.automated_value(attribute);
+        if self.set_attribute_f32(attribute, value).is_err() {
+            return false;
+        }
+
+        let value = self.automated_value(attribute).unwrap_or(value);
+        if let Some(automated) = self.automated.as_mut().filter(|_| previous != Some(value)) {
+            automated.push(attribute, value);
+        }
+
+        true
+    }
+
+    fn apply_envelopes(&mut self) {
+        let time = self.get_played_position() as f64 / self.reader.sample_rate as f64;
+        let mut envelopes = std::mem::take(&mut self.envelopes);
+
+        envelopes.apply_at(time, |attribute, value| {
+            self.apply_automated(attribute, value)
+        });
+
+        self.envelopes = envelopes;
+    }
+
+    fn read_block(
         &mut self,
         spatializer_listener: Option<&mut SpatializationListener>,
         channel_converter: &mut ChannelConverter,
@@ -150,9 +487,16 @@ impl TrackChannel {
             return Ok(0);
         }
 
+        let crossfade = self.loop_crossfade_frames();
+        let mut looped = false;
         let mut frames_readed;
 
-        if self.fx.is_some() {
+        if let Some(scrub) = self.scrub.as_mut() {
+            frames_readed = crate::macros::check!(
+                scrub.render(&mut self.reader, output, buffer1, required_frame_count),
+                TrackError::ReadError
+            );
+        } else if self.fx.is_some() {
             let fx = self.fx.as_mut().unwrap();
 
             let mut target_frame_count = required_frame_count;
@@ -162,14 +506,24 @@ impl TrackChannel {
                 target_frame_count = fx.get_required_input(target_frame_count).unwrap_or(0);
             }
 
-            let available_frames = self.reader.available_frames();
+            // A crossfaded loop never runs out, the fx is not flushed at the seam
+            let available_frames = match crossfade {
+                0 => self.reader.available_frames(),
+                _ => usize::MAX,
+            };
+
             if available_frames > 0 {
-                target_frame_count = crate::macros::check!(
-                    self.reader.read(crate::macros::make_slice_mut!(
-                        buffer1,
-                        target_frame_count,
-                        self.reader.channels
-                    )),
+                (target_frame_count, looped) = crate::macros::check!(
+                    loopfade::read_crossfaded(
+                        &mut self.reader,
+                        crate::macros::make_slice_mut!(
+                            buffer1,
+                            target_frame_count,
+                            self.reader.channels
+                        ),
+                        crossfade,
+                        self.pool.as_deref(),
+                    ),
                     TrackError::ReadError
                 );
 
@@ -181,6 +535,12 @@ impl TrackChannel {
             }
 
             if fx.frame_available > 0 {
+                denormal::dither(crate::macros::make_slice_mut!(
+                    buffer1,
+                    target_frame_count,
+                    self.reader.channels
+                ));
+
                 crate::macros::check!(
                     fx.process(
                         crate::macros::make_slice!(
@@ -210,19 +570,28 @@ impl TrackChannel {
 
             frames_readed = readed_frame_count;
         } else {
-            frames_readed = crate::macros::check!(
-                self.reader.read(crate::macros::make_slice_mut!(
-                    output[..crate::macros::array_len_from!(
+            (frames_readed, looped) = crate::macros::check!(
+                loopfade::read_crossfaded(
+                    &mut self.reader,
+                    crate::macros::make_slice_mut!(
+                        output[..crate::macros::array_len_from!(
+                            required_frame_count,
+                            self.reader.channels
+                        )],
                         required_frame_count,
                         self.reader.channels
-                    )],
-                    required_frame_count,
-                    self.reader.channels
-                ),),
+                    ),
+                    crossfade,
+                    self.pool.as_deref(),
+                ),
                 TrackError::ReadError
             );
         }
 
+        if looped {
+            self.finished.notify(PlaybackEnd::Looped);
+        }
+
         if frames_readed > 0 {
             if !self.resampler.bypass_mode() {
                 let resampler_frame_count = crate::macros::check!(
@@ -253,53 +622,125 @@ impl TrackChannel {
                 TrackError::ProcessingFailed
             );
 
+            for effect in self.effects.iter_mut() {
+                effect.process(output, self.reader.channels);
+            }
+
             // User desired channels conversion
             self.channel_converter
                 .set_input_channels(self.reader.channels as usize);
             self.channel_converter.process(output, buffer1);
 
-            // Caller desired channels conversion
-            channel_converter
-                .set_input_channels(self.channel_converter.get_output_channels() as usize);
-            channel_converter.process(buffer1, output);
+            // Caller desired channels conversion, or the user routing to the caller speakers
+            match self
+                .routing
+                .as_ref()
+                .filter(|routing| routing.outputs() == channel_converter.get_output_channels())
+            {
+                Some(routing) => routing.process(buffer1, output),
+                None => {
+                    channel_converter
+                        .set_input_channels(self.channel_converter.get_output_channels() as usize);
+                    channel_converter.process(buffer1, output);
+                }
+            }
 
-            self.position.fetch_add(frames_readed, Ordering::SeqCst);
+            match self.scrub.as_ref() {
+                Some(scrub) => self.position.store(scrub.position as usize, Ordering::SeqCst),
+                None => _ = self.position.fetch_add(frames_readed, Ordering::SeqCst),
+            }
 
             if let Some(callback) = &mut self.callback {
                 callback(output);
             }
 
+            self.ducking
+                .process(output, channel_converter.get_output_channels() as usize);
+
             if let Some(spatializer) = &mut self.spatializer {
                 if let Some(listener) = spatializer_listener {
-                    crate::macros::check!(
-                        spatializer.process(listener, output, buffer1),
-                        TrackError::ProcessingFailed
+                    let hrtf = listener.hrtf.clone();
+                    let channels = channel_converter.get_output_channels() as usize;
+
+                    spatializer.process_occlusion(
+                        output,
+                        channels,
+                        self.resampler.target_sample_rate,
                     );
 
+                    spatializer.process_reverb_send(listener, output, channels);
+
+                    match hrtf.as_deref() {
+                        _ if listener.ambisonics.is_some() => crate::macros::check!(
+                            spatializer.process_ambisonics(listener, output, buffer1, channels),
+                            TrackError::ProcessingFailed
+                        ),
+                        Some(hrtf) => crate::macros::check!(
+                            spatializer.process_hrtf(listener, hrtf, output, buffer1, channels),
+                            TrackError::ProcessingFailed
+                        ),
+                        None => crate::macros::check!(
+                            spatializer.process(listener, output, buffer1),
+                            TrackError::ProcessingFailed
+                        ),
+                    };
+
                     MathUtils::simd_copy(buffer1.as_ref(), output.as_mut());
                 }
             }
         }
 
+        if frames_readed > 0 {
+            let channels = channel_converter.get_output_channels() as usize;
+
+            if self.declick.process(&mut output[..frames_readed * channels], channels) {
+                self.playing.store(false, Ordering::SeqCst);
+                self.finished.notify(PlaybackEnd::Stopped);
+            }
+        }
+
         if frames_readed < frame_count {
             if self.is_looping.load(Ordering::SeqCst) {
                 crate::macros::check!(self.reader.seek(0), TrackError::SeekFailed);
+                self.finished.notify(PlaybackEnd::Looped);
             } else {
                 self.playing.store(false, Ordering::SeqCst);
+                self.finished.notify(PlaybackEnd::Ended);
             }
         }
 
         return Ok(frames_readed);
     }
 
+    /// Start playing from the cue point, or the start of the track, and arm a new [Completion].
+    pub fn begin_playback(&mut self) -> Result<Completion, TrackError> {
+        let completion = self.finished.arm();
+        let position = self.cue.take().or(self.start).unwrap_or(0);
+
+        self.declick.reset();
+        self.stop_at = None;
+        self.start_at = None;
+        self.playing.store(true, Ordering::Release);
+        self.seek(position)?;
+
+        Ok(completion)
+    }
+
+    /// Seek to `position`, returns the compensated position playback resumes from which may be
+    /// later than `position` with [FXSeekMode::Fast].
     pub fn seek(&mut self, position: usize) -> Result<usize, TrackError> {
         if position >= self.reader.pcm_length {
             return Err(TrackError::SeekOutOfBounds);
         }
 
-        self.position.store(position, Ordering::SeqCst);
+        let mut compensated_position = position;
 
         crate::macros::check!(self.reader.seek(position), TrackError::SeekFailed);
+        self.declick.mark_discontinuity();
+
+        if let Some(scrub) = self.scrub.as_mut() {
+            scrub.reset(position);
+        }
 
         if self.fx.is_some() {
             let fx = self.fx.as_mut().unwrap();
@@ -310,23 +751,190 @@ impl TrackChannel {
 
             // Only seek when need to feed the fx
             if latency > 0 {
-                let mut input_buffer =
-                    vec![0.0f32; latency as usize * self.reader.channels as usize];
+                let channels = self.reader.channels as usize;
+                let (start, padding) = self.fx_seek_mode.preroll(position, latency);
+
+                let pool = self.pool.clone();
+                let len = latency as usize * channels;
+                let Some(mut input_buffer) = ScratchBuffer::new(pool.as_deref(), len) else {
+                    return Err(TrackError::ScratchExhausted(len));
+                };
+
+                input_buffer[..padding * channels].fill(0.0);
 
-                crate::macros::check!(self.reader.read(&mut input_buffer), TrackError::ReadError);
+                crate::macros::check!(self.reader.seek(start), TrackError::SeekFailed);
+                crate::macros::check!(
+                    self.reader.read(&mut input_buffer[padding * channels..]),
+                    TrackError::ReadError
+                );
                 crate::macros::check!(fx.seek(&input_buffer), TrackError::ProcessingFailed);
 
                 fx.frame_available += latency as isize;
+
+                compensated_position = self
+                    .fx_seek_mode
+                    .compensated_position(position, latency)
+                    .min(self.reader.pcm_length);
             }
         }
 
-        Ok(position)
+        self.position.store(compensated_position, Ordering::SeqCst);
+
+        Ok(compensated_position)
+    }
+
+    /// Length of the loop seam crossfade in source frames, 0 when not looping or when the
+    /// audio is too short to fit the crossfade twice.
+    fn loop_crossfade_frames(&self) -> usize {
+        if !self.is_looping.load(Ordering::SeqCst) || self.loop_crossfade * 2 > self.reader.pcm_length
+        {
+            return 0;
+        }
+
+        self.loop_crossfade
+    }
+
+    /// Source frame currently coming out of the track, accounting for the FX tempo and latency.
+    pub fn get_played_position(&self) -> usize {
+        if let Some(scrub) = self.scrub.as_ref() {
+            return scrub.position as usize;
+        }
+
+        match self.fx.as_ref() {
+            Some(fx) => self.reader.position.saturating_sub(fx.get_source_latency()),
+            None => self.reader.position,
+        }
     }
 
     pub fn is_playing(&self) -> bool {
         self.playing.load(Ordering::SeqCst)
     }
 
+    // Current value of an attribute automated from the audio thread
+    fn automated_value(&self, attribute: AudioAttributes) -> Option<f32> {
+        match attribute {
+            AudioAttributes::Volume => Some(self.gainer.volume),
+            AudioAttributes::Pan => Some(self.panner.pan),
+            AudioAttributes::Balance => Some(self.panner.get_balance()),
+            AudioAttributes::SampleRate => Some(self.resampler.target_sample_rate),
+            AudioAttributes::FXTempo => self.fx.as_ref().map(|fx| fx.tempo),
+            AudioAttributes::FXPitch => self.fx.as_ref().map(|fx| fx.octave),
+            _ => None,
+        }
+    }
+
+    pub fn set_attribute_f32(
+        &mut self,
+        attribute: AudioAttributes,
+        value: f32,
+    ) -> Result<(), PropertyError> {
+        match attribute {
+            AudioAttributes::FXTempo => {
+                if self.fx.is_none() {
+                    return Err(PropertyError::from_other(AudioFXError::NotEnabled));
+                }
+
+                let fx = self.fx.as_mut().unwrap();
+                fx.set_tempo(value).map_err(PropertyError::from_other)?;
+            }
+            AudioAttributes::FXPitch => {
+                if self.fx.is_none() {
+                    return Err(PropertyError::from_other(AudioFXError::NotEnabled));
+                }
+
+                let fx = self.fx.as_mut().unwrap();
+                fx.set_octave(value).map_err(PropertyError::from_other)?;
+            }
+            AudioAttributes::SampleRate => {
+                self.resampler.set_target_sample_rate(value);
+            }
+            AudioAttributes::Volume => {
+                self.gainer.set_volume(value);
+            }
+            AudioAttributes::Pan => {
+                self.panner.set_pan(value);
+            }
+            AudioAttributes::Balance => {
+                self.panner.set_balance(value);
+            }
+            _ => {
+                return Err(PropertyError::UnsupportedAttribute("Unknown attribute"));
+            }
+        };
+
+        Ok(())
+    }
+
+    pub fn set_attribute_bool(
+        &mut self,
+        attribute: AudioAttributes,
+        value: bool,
+    ) -> Result<(), PropertyError> {
+        match attribute {
+            AudioAttributes::FXEnabled => {
+                if value {
+                    if self.fx.is_none() {
+                        let fx = AudioFX::new(self.reader.channels, self.reader.sample_rate);
+
+                        if let Err(e) = fx {
+                            return Err(PropertyError::from_other(e));
+                        }
+
+                        self.fx = fx.ok();
+                    }
+                } else {
+                    self.fx = None;
+                }
+
+                let seek_pos = self.position.load(Ordering::SeqCst);
+                let seek_result = self.seek(seek_pos);
+
+                if let Err(e) = seek_result {
+                    return Err(PropertyError::from_other(e));
+                }
+            }
+            AudioAttributes::SpatializationEnabled => {
+                if value {
+                    if self.spatializer.is_none() {
+                        let spatializer =
+                            Spatialization::new(self.reader.channels, self.reader.channels);
+
+                        if let Err(e) = spatializer {
+                            return Err(PropertyError::from_other(e));
+                        }
+
+                        self.spatializer = spatializer.ok();
+                    }
+                } else {
+                    self.spatializer = None;
+                }
+            }
+            AudioAttributes::ForceMono => {
+                self.panner.force_mono = value;
+            }
+            AudioAttributes::SwapChannels => {
+                self.panner.swap_channels = value;
+            }
+            _ => {
+                return Err(PropertyError::UnsupportedAttribute("Unsupported attribute"));
+            }
+        }
+
+        Ok(())
+    }
+
+
+    pub fn set_attribute(
+        &mut self,
+        attribute: AudioAttributes,
+        value: AttributeValue,
+    ) -> Result<(), PropertyError> {
+        match value {
+            AttributeValue::F32(value) => self.set_attribute_f32(attribute, value),
+            AttributeValue::Bool(value) => self.set_attribute_bool(attribute, value),
+        }
+    }
+
     pub fn set_callback<F>(&mut self, callback: F)
     where
         F: FnMut(&mut [f32]) + Send + 'static,
@@ -338,5 +946,6 @@ impl TrackChannel {
 impl Drop for TrackChannel {
     fn drop(&mut self) {
         self.playing.store(false, Ordering::SeqCst);
+        self.finished.notify(PlaybackEnd::Stopped);
     }
 }
@@ -0,0 +1,168 @@
+use std::sync::{MutexGuard, atomic::Ordering};
+
+use crate::{
+    device::Device,
+    misc::{finishsignal::Completion, syncstart},
+};
+
+use super::{Track, TrackError, inner::TrackChannel};
+
+/// Tracks played, stopped and sought together on the same device frame, keeping stems of a
+/// multitrack phase aligned.
+///
+/// The changes are scheduled on the device clock a couple of blocks ahead, see
+/// [Track::play_at], so the members are held silent briefly after [LinkGroup::play] and
+/// [LinkGroup::seek] while playing.
+#[derive(Debug, Default)]
+pub struct LinkGroup {
+    members: Vec<Track>,
+    // Device frames the transport is scheduled ahead, set by play
+    lead: u64,
+}
+
+impl LinkGroup {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add `track` to the group, it follows the group from the next [LinkGroup::play] or
+    /// [LinkGroup::seek]. Adding a member twice does nothing.
+    pub fn add(&mut self, track: &Track) {
+        if !self.contains(track) {
+            self.members.push(track.clone());
+        }
+    }
+
+    /// Remove `track` from the group, it keeps playing on its own.
+    pub fn remove(&mut self, track: &Track) -> bool {
+        let count = self.members.len();
+        self.members.retain(|member| member.ref_id != track.ref_id);
+        self.members.len() != count
+    }
+
+    pub fn contains(&self, track: &Track) -> bool {
+        self.members.iter().any(|member| member.ref_id == track.ref_id)
+    }
+
+    pub fn members(&self) -> &[Track] {
+        &self.members
+    }
+
+    pub fn len(&self) -> usize {
+        self.members.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.members.is_empty()
+    }
+
+    /// Play every member from its current position on `device`, all starting on the same
+    /// device frame. Returns the [Completion] of each member in member order.
+    pub fn play(&mut self, device: &mut Device) -> Result<Vec<Completion>, TrackError> {
+        self.lead = syncstart::lead_frames(device);
+
+        // Held silent until every member is attached, then released together below
+        let completions = self
+            .members
+            .iter_mut()
+            .map(|track| track.start_playback(device, Some(u64::MAX)))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut members = self.lock_members()?;
+        let start = Self::start_time(&members, self.lead);
+
+        for member in members.iter_mut() {
+            member.start_at = start;
+        }
+
+        Ok(completions)
+    }
+
+    /// Stop every member on the same device frame.
+    pub fn stop(&mut self) -> Result<(), TrackError> {
+        let mut members = self.lock_members()?;
+
+        match Self::start_time(&members, self.lead) {
+            Some(time) => {
+                for member in members.iter_mut() {
+                    member.stop_at = Some((time, 0));
+                }
+            }
+            None => {
+                for member in members.iter_mut() {
+                    member.stop_at = None;
+                    member.playing.store(false, Ordering::Release);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Seek every member to `position` in source frames. While playing, the members resume
+    /// from it on the same device frame. A member shorter than `position` is left where it
+    /// is.
+    pub fn seek(&mut self, position: usize) -> Result<(), TrackError> {
+        let mut members = self.lock_members()?;
+        let start = Self::start_time(&members, self.lead);
+
+        for member in members.iter_mut() {
+            if position >= member.reader.pcm_length {
+                continue;
+            }
+
+            member.seek(position)?;
+
+            if start.is_some() && member.is_playing() {
+                member.start_at = start;
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn seek_secs(&mut self, position: f64) -> Result<(), TrackError> {
+        let Some(track) = self.members.first() else {
+            return Ok(());
+        };
+
+        let sample_rate = {
+            let Ok(inner) = track.inner.lock() else {
+                return Err(TrackError::LockFailed);
+            };
+
+            inner.reader.sample_rate
+        };
+
+        self.seek((position * sample_rate as f64) as usize)
+    }
+
+    /// Loop every member, members of the same length wrap on the same frame.
+    pub fn set_looping(&mut self, looping: bool) {
+        for member in self.members.iter_mut() {
+            member.set_looping(looping);
+        }
+    }
+
+    /// Position of the first member in source frames, the members share it while linked.
+    pub fn get_position(&self) -> usize {
+        self.members.first().map_or(0, |track| track.get_position())
+    }
+
+    pub fn is_playing(&self) -> bool {
+        self.members.iter().any(|track| track.is_playing())
+    }
+
+    pub(crate) fn lock_members(
+        &self,
+    ) -> Result<Vec<MutexGuard<'_, TrackChannel>>, TrackError> {
+        syncstart::lock_all(&self.members, |track| {
+            track.inner.lock().map_err(|_| TrackError::LockFailed)
+        })
+    }
+
+    // Device frame the members start on, None when none is attached
+    fn start_time(members: &[MutexGuard<'_, TrackChannel>], lead: u64) -> Option<u64> {
+        syncstart::start_time(members.iter().map(|member| member.clock.as_ref()), lead)
+    }
+}
@@ -0,0 +1,91 @@
+use std::f32::consts::FRAC_PI_2;
+
+use crate::{
+    audioreader::{AudioReader, AudioReaderError},
+    misc::bufferpool::{BufferPool, ScratchBuffer},
+};
+
+/// Read from `reader` as an endless loop whose last `fade` frames are blended into its first
+/// `fade` frames with an equal power crossfade. After the seam playback continues from frame
+/// `fade`, so every pass after the first is `fade` frames shorter than the audio.
+///
+/// Returns the frames read and whether the loop wrapped during this read.
+pub(crate) fn read_crossfaded(
+    reader: &mut AudioReader,
+    output: &mut [f32],
+    fade: usize,
+    pool: Option<&BufferPool>,
+) -> Result<(usize, bool), AudioReaderError> {
+    if fade == 0 {
+        return reader.read(output).map(|read| (read, false));
+    }
+
+    let channels = reader.channels;
+    let frame_count = output.len() / channels;
+    let fade_start = reader.pcm_length - fade;
+
+    let mut written = 0;
+    let mut looped = false;
+
+    while written < frame_count {
+        let position = reader.position;
+        let remaining = frame_count - written;
+        let output = &mut output[written * channels..];
+
+        if position < fade_start {
+            let count = remaining.min(fade_start - position);
+            let read = reader.read(&mut output[..count * channels])?;
+
+            written += read;
+            if read < count {
+                break;
+            }
+
+            continue;
+        }
+
+        // The tail fading out, mixed with the head fading in
+        let offset = position - fade_start;
+        let count = remaining.min(fade - offset);
+        let read = reader.read(&mut output[..count * channels])?;
+        if read == 0 {
+            break;
+        }
+
+        // Without a free buffer the tail plays on its own, counted in the device stats
+        if let Some(mut head) = ScratchBuffer::new(pool, read * channels) {
+            reader.seek(offset)?;
+            let head_read = reader.read(&mut head[..read * channels])?;
+            head[head_read * channels..].fill(0.0);
+
+            let frames = output[..read * channels]
+                .chunks_exact_mut(channels)
+                .zip(head.chunks_exact(channels));
+
+            for (index, (tail, head)) in frames.enumerate() {
+                let t = (offset + index) as f32 / fade as f32;
+                let (fade_in, fade_out) = (t * FRAC_PI_2).sin_cos();
+
+                for (tail, head) in tail.iter_mut().zip(head) {
+                    *tail = *tail * fade_out + head * fade_in;
+                }
+            }
+        }
+
+        written += read;
+
+        if offset + read >= fade {
+            // The head was already played up to `fade` while crossfading
+            reader.seek(fade)?;
+            looped = true;
+        } else {
+            reader.seek(position + read)?;
+        }
+
+        if read < count {
+            break;
+        }
+    }
+
+    Ok((written, looped))
+}
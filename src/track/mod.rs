@@ -1,22 +1,45 @@
-use std::sync::{
-    Arc, Mutex,
-    atomic::{AtomicBool, AtomicUsize, Ordering},
+use std::{
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+    },
+    time::{Duration, Instant},
 };
 
 use inner::TrackChannel;
+use schedule::ScheduledChange;
+use scrub::Scrubber;
+use trainer::TrainerState;
 use thiserror::Error;
 
 use crate::{
-    device::Device, effects::{
-        AttenuationModel, AudioFX, AudioFXError, Spatialization, SpatializationError,
-        SpatializationHandler, Positioning,
-    }, math::Vector3, misc::{
+    Source, audioreader::{decoder, stream::StreamStatus, transform}, device::{Device, tempo::Quantize}, effects::{
+        AudioEffect, AudioFX, AudioFXError, FXSeekMode, ListenerSelection, Spatialization,
+        SpatializationError, PanMode, SpatializationSource, RoutingMatrix, RoutingMatrixError,
+    }, misc::{
+        attributeobserver::{AttributeEvent, AttributeObservers},
         audioattributes::AudioAttributes,
-        audiopropertyhandler::{PropertyError, PropertyHandler},
+        audiopropertyhandler::{PropertyError, PropertyHandler, validate_channel_attributes},
+        customattributes::AttributeValue,
+        envelope::Envelope,
+        finishsignal::{Completion, FinishSignal, PlaybackEnd},
+        memoryusage::MemoryUsage,
     }, utils::TweenType
 };
 
+pub(crate) mod declick;
 pub(crate) mod inner;
+pub(crate) mod linkgroup;
+pub(crate) mod loopfade;
+pub(crate) mod schedule;
+pub(crate) mod scrub;
+pub(crate) mod stems;
+pub(crate) mod trainer;
+
+pub use linkgroup::LinkGroup;
+pub use scrub::ScrubMode;
+pub use stems::StemPlayer;
+pub use trainer::SpeedTrainer;
 
 #[derive(Debug, Error)]
 pub enum TrackError {
@@ -34,6 +57,16 @@ pub enum TrackError {
     ProcessingFailed,
     #[error("Failed to lock the track channel")]
     LockFailed,
+    #[error("The track is not scrubbing")]
+    NotScrubbing,
+    #[error("The track source is generated or streamed as it plays and cannot be shared")]
+    NotDuplicable,
+    #[error("The stems do not share the same sample rate, found {0} Hz")]
+    SampleRateMismatch(f32),
+    #[error("No stem at index {0}")]
+    StemNotFound(usize),
+    #[error("The scratch buffer pool has no free buffer of {0} samples")]
+    ScratchExhausted(usize),
     #[error("{0}")]
     Other(Box<dyn std::error::Error + Send + 'static>),
 }
@@ -55,11 +88,81 @@ pub(crate) struct TrackSliderInstance {
 static TRACK_ID: AtomicUsize = AtomicUsize::new(0);
 static INVALID_DEVICE_REF_ID: u32 = u32::MAX;
 
+// Attributes that can be scheduled or follow an envelope from the audio thread
+const AUTOMATABLE_ATTRIBUTES: &[AudioAttributes] = &[
+    AudioAttributes::Volume,
+    AudioAttributes::Pan,
+    AudioAttributes::Balance,
+    AudioAttributes::SampleRate,
+    AudioAttributes::FXTempo,
+    AudioAttributes::FXPitch,
+];
+
 #[derive(Debug, Default)]
 pub struct TrackInfo<'a> {
     pub source: crate::Source<'a>,
     pub sample_rate: Option<f32>,
     pub channel: Option<usize>,
+
+    // Initial state, applied before the track is returned
+    pub volume: Option<f32>,
+    pub pan: Option<f32>,
+    pub looping: bool,
+    /// Frame [Track::play] starts from, see [Track::set_start].
+    pub start: Option<usize>,
+    pub end: Option<usize>,
+    /// Frame the first [Track::play] starts from instead of `start`.
+    pub position: Option<usize>,
+    /// Enables AudioFX with this tempo.
+    pub fx_tempo: Option<f32>,
+    /// Enables AudioFX with this pitch.
+    pub fx_pitch: Option<f32>,
+    /// Stream a [Source::Path](crate::Source::Path) from disk instead of decoding it whole,
+    /// only two small buffers of decoded audio are held and refilled by a thread while the
+    /// track plays, for long music. Files read by a registered
+    /// [AudioDecoder](crate::AudioDecoder) such as Ogg, and files under an
+    /// [asset transform](crate::set_asset_transform), are always decoded whole.
+    pub stream: bool,
+}
+
+/// An owned [TrackInfo] loading its source from a file, so tracks can be described in data
+/// files. Create the track from [TrackConfig::info].
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct TrackConfig {
+    pub path: String,
+    pub sample_rate: Option<f32>,
+    pub channel: Option<usize>,
+    pub volume: Option<f32>,
+    pub pan: Option<f32>,
+    pub looping: bool,
+    pub start: Option<usize>,
+    pub end: Option<usize>,
+    pub position: Option<usize>,
+    pub fx_tempo: Option<f32>,
+    pub fx_pitch: Option<f32>,
+    pub stream: bool,
+}
+
+impl TrackConfig {
+    /// The config as a [TrackInfo] reading [TrackConfig::path].
+    pub fn info(&self) -> TrackInfo<'_> {
+        TrackInfo {
+            source: Source::Path(&self.path),
+            sample_rate: self.sample_rate,
+            channel: self.channel,
+            volume: self.volume,
+            pan: self.pan,
+            looping: self.looping,
+            start: self.start,
+            end: self.end,
+            position: self.position,
+            fx_tempo: self.fx_tempo,
+            fx_pitch: self.fx_pitch,
+            stream: self.stream,
+        }
+    }
 }
 
 /// Represents an audio track that can play audio data, apply effects, and be spatialized.
@@ -69,48 +172,159 @@ pub struct Track {
     pub(crate) inner: Arc<Mutex<TrackChannel>>,
 
     playing: Arc<AtomicBool>,
+    finished: Arc<FinishSignal>,
     is_looping: Arc<AtomicBool>,
     position: Arc<AtomicUsize>,
     sample_rate: f32,
     pcm_length: usize,
     device_ref_id: u32,
+    observers: AttributeObservers,
 }
 
 impl Track {
     pub(crate) fn new(info: TrackInfo) -> Result<Self, TrackError> {
-        let (cache, buffer_info) = info.source.into_buffer();
         let id = TRACK_ID.fetch_add(1, Ordering::SeqCst);
 
-        let Ok(track) =
-            TrackChannel::new(id, cache, buffer_info, info.sample_rate, info.channel, true)
-        else {
+        let track = match info.source {
+            Source::Generator(generator) => {
+                TrackChannel::from_generator(id, generator, info.sample_rate, info.channel)
+            }
+            Source::Path(path)
+                if info.stream
+                    && decoder::find_for_file(path).is_none()
+                    && transform::asset_transform().is_none() =>
+            {
+                TrackChannel::from_stream(id, path, info.sample_rate, info.channel)
+            }
+            source => {
+                let (cache, buffer_info) = source.into_buffer();
+                TrackChannel::new(id, cache, buffer_info, info.sample_rate, info.channel, true)
+            }
+        };
+
+        let Ok(mut track) = track else {
             return Err(TrackError::CreateFailed);
         };
 
+        if let Some(volume) = info.volume {
+            track.gainer.set_volume(volume);
+        }
+
+        if let Some(pan) = info.pan {
+            track.panner.set_pan(pan);
+        }
+
+        track.is_looping.store(info.looping, Ordering::SeqCst);
+
+        if info.fx_tempo.is_some() || info.fx_pitch.is_some() {
+            let mut fx = AudioFX::new(track.reader.channels, track.reader.sample_rate)
+                .map_err(TrackError::from_other)?;
+
+            if let Some(tempo) = info.fx_tempo {
+                fx.set_tempo(tempo).map_err(TrackError::from_other)?;
+            }
+
+            if let Some(pitch) = info.fx_pitch {
+                fx.set_octave(pitch).map_err(TrackError::from_other)?;
+            }
+
+            track.fx = Some(fx);
+        }
+
+        for position in [info.start, info.position].into_iter().flatten() {
+            if position >= track.reader.pcm_length {
+                return Err(TrackError::SeekOutOfBounds);
+            }
+        }
+
+        track.start = info.start;
+        track.end = info.end;
+
+        if let Some(position) = info.position {
+            track.seek(position)?;
+            track.cue = Some(position);
+        }
+
+        Ok(Self::from_channel(id, track))
+    }
+
+    fn from_channel(id: usize, mut track: TrackChannel) -> Self {
+        let (observers, automated) = AttributeObservers::with_automation();
+        track.automated = Some(automated);
+
         let pcm_length = track.reader.pcm_length;
         let sample_rate = track.resampler.target_sample_rate;
         let playing = Arc::clone(&track.playing);
+        let finished = Arc::clone(&track.finished);
         let position = Arc::clone(&track.position);
         let is_looping = Arc::clone(&track.is_looping);
         let inner = Arc::new(Mutex::new(track));
 
-        Ok(Self {
+        Self {
             ref_id: id,
             inner,
             playing,
+            finished,
             is_looping,
             position,
             sample_rate,
             pcm_length,
             device_ref_id: INVALID_DEVICE_REF_ID,
-        })
+            observers,
+        }
+    }
+
+    /// A new independent track playing the same decoded audio, without copying it, from the
+    /// start. The volume, pan, loop and output format are carried over, the effects, fx,
+    /// spatialization and callbacks are not.
+    ///
+    /// Fails with [TrackError::NotDuplicable] for generators and tracks streamed from disk.
+    pub fn duplicate(&self) -> Result<Track, TrackError> {
+        let Ok(mut inner) = self.inner.lock() else {
+            return Err(TrackError::LockFailed);
+        };
+
+        let id = TRACK_ID.fetch_add(1, Ordering::SeqCst);
+        let track = inner.duplicate(id)?;
+
+        Ok(Self::from_channel(id, track))
     }
 
     /// Play the track on the given audio device.
     ///
     /// By default, the track is parentless and can be played on any device. Once played, it becomes attached to that device
     /// and cannot be played on another device until stopped.
-    pub fn play(&mut self, device: &mut Device) -> Result<(), TrackError> {
+    ///
+    /// The returned [Completion] resolves once this playback ends, loops or is stopped.
+    pub fn play(&mut self, device: &mut Device) -> Result<Completion, TrackError> {
+        self.start_playback(device, None)
+    }
+
+    /// Play on the given device with the output held silent until the device clock reaches
+    /// `device_time`, see [Device::get_time]. A time already passed starts right away.
+    pub fn play_at(&mut self, device: &mut Device, device_time: u64) -> Result<Completion, TrackError> {
+        self.start_playback(device, Some(device_time))
+    }
+
+    /// Play on the given device starting exactly on the next beat or bar of its tempo grid,
+    /// see [Device::set_tempo]. Fails when the device has no tempo set.
+    pub fn play_quantized(
+        &mut self,
+        device: &mut Device,
+        quantize: Quantize,
+    ) -> Result<Completion, TrackError> {
+        let device_time = device
+            .next_grid_time(quantize)
+            .map_err(TrackError::from_other)?;
+
+        self.start_playback(device, Some(device_time))
+    }
+
+    pub(crate) fn start_playback(
+        &mut self,
+        device: &mut Device,
+        start_at: Option<u64>,
+    ) -> Result<Completion, TrackError> {
         let device_ref_id = device.get_ref_id();
         if self.device_ref_id != INVALID_DEVICE_REF_ID && self.device_ref_id != device_ref_id {
             return Err(TrackError::InvalidDeviceId);
@@ -126,601 +340,796 @@ impl Track {
             return Err(TrackError::SeekFailed);
         };
 
-        inner.playing.store(true, Ordering::Release);
-        inner.seek(0)?;
+        let completion = inner.begin_playback()?;
+        inner.start_at = start_at;
 
-        Ok(())
+        Ok(completion)
     }
 
+    /// Stop playing. While declicking is enabled and the track is playing on a device, the
+    /// output fades out first and the track stops once the fade is heard, see
+    /// [Track::set_declick_ms].
     pub fn stop(&mut self) -> Result<(), TrackError> {
-        let Some(inner) = self.inner.lock().ok() else {
+        let Some(mut inner) = self.inner.lock().ok() else {
             return Err(TrackError::LockFailed);
         };
 
-        inner.playing.store(false, Ordering::Release);
+        let attached = self.device_ref_id != INVALID_DEVICE_REF_ID;
         self.device_ref_id = INVALID_DEVICE_REF_ID;
 
+        if attached && inner.is_playing() && inner.declick.begin_stop() {
+            return Ok(());
+        }
+
+        inner.playing.store(false, Ordering::Release);
+        inner.finished.notify(PlaybackEnd::Stopped);
+
         Ok(())
     }
 
-    pub fn set_callback<F>(&mut self, callback: F) -> Result<(), TrackError>
-    where
-        F: FnMut(&mut [f32]) + Send + 'static,
-    {
+    /// Stop when the device clock reaches `device_time`, see [Device::get_time]. With a
+    /// `fade_ms` above 0 the output fades out from that frame and the track stops once the
+    /// fade is heard. Replaces the stop already scheduled, a time already passed stops at the
+    /// start of the next block.
+    ///
+    /// Only applied while the track is played directly on a device.
+    pub fn stop_at(&mut self, device_time: u64, fade_ms: f32) -> Result<(), TrackError> {
         let Ok(mut inner) = self.inner.lock() else {
             return Err(TrackError::LockFailed);
         };
 
-        inner.set_callback(callback);
+        let fade = fade_ms.max(0.0) / 1000.0 * inner.resampler.target_sample_rate;
+        inner.stop_at = Some((device_time, fade as usize));
         Ok(())
     }
 
-    pub fn set_start(&mut self, start: Option<usize>) -> Result<(), TrackError> {
+    /// Cancel the stop scheduled with [Track::stop_at], a fade already started keeps going.
+    pub fn cancel_stop_at(&mut self) -> Result<(), TrackError> {
         let Ok(mut inner) = self.inner.lock() else {
             return Err(TrackError::LockFailed);
         };
 
-        inner.start = start;
+        inner.stop_at = None;
         Ok(())
     }
 
-    pub fn set_end(&mut self, end: Option<usize>) -> Result<(), TrackError> {
+    /// Length of the gain ramp applied when the track starts, seeks or stops, so jumps away
+    /// from a zero crossing do not click. 0 disables it, tracks start with 2 ms.
+    pub fn set_declick_ms(&mut self, ms: f32) -> Result<(), TrackError> {
         let Ok(mut inner) = self.inner.lock() else {
             return Err(TrackError::LockFailed);
         };
 
-        inner.end = end;
+        let frames = ms.max(0.0) / 1000.0 * inner.resampler.target_sample_rate;
+        inner.declick.set_frames(frames as usize);
         Ok(())
     }
 
-    pub fn seek(&mut self, position: usize) -> Result<(), TrackError> {
-        if position >= self.pcm_length {
-            return Err(TrackError::SeekOutOfBounds);
-        }
+    pub fn get_declick_ms(&self) -> f32 {
+        let Ok(inner) = self.inner.lock() else {
+            return 0.0;
+        };
+
+        inner.declick.frames() as f32 * 1000.0 / inner.resampler.target_sample_rate
+    }
 
+    pub fn set_callback<F>(&mut self, callback: F) -> Result<(), TrackError>
+    where
+        F: FnMut(&mut [f32]) + Send + 'static,
+    {
         let Ok(mut inner) = self.inner.lock() else {
             return Err(TrackError::LockFailed);
         };
 
-        inner.seek(position)?;
-
+        inner.set_callback(callback);
         Ok(())
     }
 
-    pub fn seek_ms(&mut self, position: usize) -> Result<(), TrackError> {
-        let position = (position as f32 * self.sample_rate) / 1000.0;
-        self.seek(position as usize)
-    }
+    /// Replace the effects run on the track after its volume and pan, in order, at the channel
+    /// count of the audio. Each effect is reset before it is inserted.
+    pub fn set_effects(&mut self, mut effects: Vec<Box<dyn AudioEffect>>) -> Result<(), TrackError> {
+        let Ok(mut inner) = self.inner.lock() else {
+            return Err(TrackError::LockFailed);
+        };
 
-    pub fn is_playing(&self) -> bool {
-        self.playing.load(Ordering::SeqCst)
+        effects.iter_mut().for_each(|effect| effect.reset());
+        inner.effects = effects;
+        Ok(())
     }
 
-    pub fn get_position(&self) -> usize {
-        self.position.load(Ordering::SeqCst)
-    }
+    pub fn clear_effects(&mut self) -> Result<(), TrackError> {
+        let Ok(mut inner) = self.inner.lock() else {
+            return Err(TrackError::LockFailed);
+        };
 
-    pub fn set_looping(&mut self, looping: bool) {
-        self.is_looping.store(looping, Ordering::SeqCst);
+        inner.effects.clear();
+        Ok(())
     }
 
-    pub fn is_looping(&self) -> bool {
-        self.is_looping.load(Ordering::SeqCst)
-    }
+    /// Frame [Track::play] starts from, None starts from the beginning.
+    pub fn set_start(&mut self, start: Option<usize>) -> Result<(), TrackError> {
+        if start.is_some_and(|start| start >= self.pcm_length) {
+            return Err(TrackError::SeekOutOfBounds);
+        }
 
-    pub fn get_length(&self) -> usize {
-        self.pcm_length
-    }
+        let Ok(mut inner) = self.inner.lock() else {
+            return Err(TrackError::LockFailed);
+        };
 
-    pub fn ref_id(&self) -> usize {
-        self.ref_id
+        inner.start = start;
+        Ok(())
     }
-}
 
-impl PropertyHandler for Track {
-    fn get_attribute_f32(&self, _type: AudioAttributes) -> Result<f32, PropertyError> {
-        let Ok(inner) = self.inner.lock() else {
-            return Err(PropertyError::from_other(TrackError::LockFailed));
+    pub fn set_end(&mut self, end: Option<usize>) -> Result<(), TrackError> {
+        let Ok(mut inner) = self.inner.lock() else {
+            return Err(TrackError::LockFailed);
         };
 
-        let result = match _type {
-            AudioAttributes::FXTempo => {
-                if inner.fx.is_none() {
-                    return Err(PropertyError::from_other(AudioFXError::NotEnabled));
-                }
-
-                let fx = inner.fx.as_ref().unwrap();
-                fx.tempo
-            }
-            AudioAttributes::FXPitch => {
-                if inner.fx.is_none() {
-                    return Err(PropertyError::from_other(AudioFXError::NotEnabled));
-                }
+        inner.end = end;
+        Ok(())
+    }
 
-                let fx = inner.fx.as_ref().unwrap();
-                fx.octave
-            }
-            AudioAttributes::SampleRate => inner.resampler.target_sample_rate as f32,
-            AudioAttributes::Volume => inner.gainer.volume,
-            AudioAttributes::Pan => inner.panner.pan,
-            _ => {
-                return Err(PropertyError::UnsupportedAttribute("Unsupported attribute"));
-            }
+    /// Put the track in a ducking group, see [DuckingRule](crate::DuckingRule).
+    pub fn set_ducking_group(&mut self, group: Option<u32>) -> Result<(), TrackError> {
+        let Ok(mut inner) = self.inner.lock() else {
+            return Err(TrackError::LockFailed);
         };
 
-        Ok(result)
+        inner.group = group;
+        Ok(())
     }
 
-    fn set_attribute_f32(
-        &mut self,
-        _type: AudioAttributes,
-        _value: f32,
-    ) -> Result<(), PropertyError> {
+    /// Pick which device listener the track is spatialized against, see [ListenerSelection].
+    pub fn set_listener(&mut self, listener: ListenerSelection) -> Result<(), TrackError> {
         let Ok(mut inner) = self.inner.lock() else {
-            return Err(PropertyError::from_other(TrackError::LockFailed));
+            return Err(TrackError::LockFailed);
         };
 
-        match _type {
-            AudioAttributes::FXTempo => {
-                if inner.fx.is_none() {
-                    return Err(PropertyError::from_other(AudioFXError::NotEnabled));
-                }
+        inner.listener = listener;
+        Ok(())
+    }
 
-                let fx = inner.fx.as_mut().unwrap();
-                fx.set_tempo(_value).unwrap();
-            }
-            AudioAttributes::FXPitch => {
-                if inner.fx.is_none() {
-                    return Err(PropertyError::from_other(AudioFXError::NotEnabled));
-                }
+    pub fn get_listener(&self) -> Result<ListenerSelection, TrackError> {
+        let Ok(inner) = self.inner.lock() else {
+            return Err(TrackError::LockFailed);
+        };
 
-                let fx = inner.fx.as_mut().unwrap();
-                fx.set_octave(_value).unwrap();
-            }
-            AudioAttributes::SampleRate => {
-                inner.resampler.set_target_sample_rate(_value);
-            }
-            AudioAttributes::Volume => {
-                inner.gainer.set_volume(_value);
-            }
-            AudioAttributes::Pan => {
-                inner.panner.set_pan(_value);
-            }
-            _ => {
-                return Err(PropertyError::UnsupportedAttribute("Unknown attribute"));
-            }
+        Ok(inner.listener)
+    }
+
+    /// Choose how [AudioAttributes::Pan] moves stereo content, see [PanMode].
+    pub fn set_pan_mode(&mut self, mode: PanMode) -> Result<(), TrackError> {
+        let Ok(mut inner) = self.inner.lock() else {
+            return Err(TrackError::LockFailed);
         };
 
+        inner.panner.set_mode(mode);
         Ok(())
     }
 
-    fn get_attribute_bool(&self, _type: AudioAttributes) -> Result<bool, PropertyError> {
+    pub fn get_pan_mode(&self) -> Result<PanMode, TrackError> {
         let Ok(inner) = self.inner.lock() else {
-            return Err(PropertyError::from_other(TrackError::LockFailed));
+            return Err(TrackError::LockFailed);
         };
 
-        match _type {
-            AudioAttributes::FXEnabled => Ok(inner.fx.is_some()),
-            AudioAttributes::SpatializationEnabled => Ok(inner.spatializer.is_some()),
-            _ => Err(PropertyError::UnsupportedAttribute("Unsupported attribute")),
-        }
+        Ok(inner.panner.mode)
     }
 
-    fn set_attribute_bool(
-        &mut self,
-        _type: AudioAttributes,
-        _value: bool,
-    ) -> Result<(), PropertyError> {
+    /// Route the track channels to the device speakers through `routing` instead of the default
+    /// channel conversion, None goes back to the default.
+    ///
+    /// The matrix inputs must match the track channel count, the matrix is only used while its
+    /// outputs match the channel count of the device the track plays on.
+    pub fn set_routing(&mut self, routing: Option<RoutingMatrix>) -> Result<(), TrackError> {
         let Ok(mut inner) = self.inner.lock() else {
-            return Err(PropertyError::from_other(TrackError::LockFailed));
+            return Err(TrackError::LockFailed);
         };
 
-        match _type {
-            AudioAttributes::FXEnabled => {
-                if _value {
-                    if inner.fx.is_none() {
-                        let fx = AudioFX::new(inner.reader.channels, inner.reader.sample_rate);
-
-                        if let Err(e) = fx {
-                            return Err(PropertyError::from_other(e));
-                        }
-
-                        inner.fx = fx.ok();
-                    }
-                } else {
-                    inner.fx = None;
-                }
-
-                let seek_pos = inner.position.load(Ordering::SeqCst);
-                let seek_result = inner.seek(seek_pos);
-
-                if let Err(e) = seek_result {
-                    return Err(PropertyError::from_other(e));
-                }
-            }
-            AudioAttributes::SpatializationEnabled => {
-                if _value {
-                    if inner.spatializer.is_none() {
-                        let spatializer =
-                            Spatialization::new(inner.reader.channels, inner.reader.channels);
-
-                        if let Err(e) = spatializer {
-                            return Err(PropertyError::from_other(e));
-                        }
-
-                        inner.spatializer = spatializer.ok();
-                    }
-                } else {
-                    inner.spatializer = None;
-                }
-            }
-            _ => {
-                return Err(PropertyError::UnsupportedAttribute("Unsupported attribute"));
+        if let Some(routing) = routing.as_ref() {
+            let channels = inner.channel_converter.get_output_channels();
+            if routing.inputs() != channels {
+                return Err(TrackError::from_other(RoutingMatrixError::InvalidChannels(
+                    routing.inputs(),
+                )));
             }
         }
 
+        inner.routing = routing;
         Ok(())
     }
-}
 
-impl SpatializationHandler for Track {
-    fn spatial_set_position(&mut self, position: Vector3<f32>) -> Result<(), SpatializationError> {
-        let Ok(mut inner) = self.inner.lock() else {
-            return Err(SpatializationError::from_other(TrackError::LockFailed));
+    pub fn get_routing(&self) -> Result<Option<RoutingMatrix>, TrackError> {
+        let Ok(inner) = self.inner.lock() else {
+            return Err(TrackError::LockFailed);
         };
 
-        let Some(spatializer) = inner.spatializer.as_mut() else {
-            return Err(SpatializationError::NotInitialized);
-        };
-        
-        spatializer.set_position(position);
-        Ok(())
+        Ok(inner.routing.clone())
     }
 
-    fn spatial_get_position(&self) -> Result<Vector3<f32>, SpatializationError> {
-        let Ok(mut inner) = self.inner.lock() else {
-            return Err(SpatializationError::from_other(TrackError::LockFailed));
+    /// Decoded audio owned by the track, or shared through the file cache.
+    pub fn memory_usage(&self) -> Result<MemoryUsage, TrackError> {
+        let Ok(inner) = self.inner.lock() else {
+            return Err(TrackError::LockFailed);
         };
 
-        let Some(spatializer) = inner.spatializer.as_mut() else {
-            return Err(SpatializationError::NotInitialized);
+        Ok(inner.memory_usage())
+    }
+
+    /// Buffer state of a track streamed from disk, None for other tracks, see
+    /// [TrackInfo::stream].
+    pub fn stream_status(&self) -> Result<Option<StreamStatus>, TrackError> {
+        let Ok(inner) = self.inner.lock() else {
+            return Err(TrackError::LockFailed);
         };
 
-        Ok(spatializer.get_position())
+        Ok(inner.reader.stream.as_ref().and_then(|stream| stream.status()))
     }
 
-    fn spatial_set_velocity(&mut self, position: Vector3<f32>) -> Result<(), SpatializationError> {
+    pub fn seek(&mut self, position: usize) -> Result<(), TrackError> {
+        if position >= self.pcm_length {
+            return Err(TrackError::SeekOutOfBounds);
+        }
+
         let Ok(mut inner) = self.inner.lock() else {
-            return Err(SpatializationError::from_other(TrackError::LockFailed));
+            return Err(TrackError::LockFailed);
         };
 
-        let Some(spatializer) = inner.spatializer.as_mut() else {
-            return Err(SpatializationError::NotInitialized);
-        };
+        inner.seek(position)?;
 
-        spatializer.set_velocity(position);
         Ok(())
     }
 
-    fn spatial_get_velocity(&self) -> Result<Vector3<f32>, SpatializationError> {
+    /// Set how seeks prime the AudioFX, kept while FX is toggled.
+    ///
+    /// With [FXSeekMode::Fast] the position after a seek is the compensated one, see
+    /// [Track::get_position].
+    pub fn set_fx_seek_mode(&mut self, mode: FXSeekMode) -> Result<(), TrackError> {
         let Ok(mut inner) = self.inner.lock() else {
-            return Err(SpatializationError::from_other(TrackError::LockFailed));
+            return Err(TrackError::LockFailed);
         };
 
-        let Some(spatializer) = inner.spatializer.as_mut() else {
-            return Err(SpatializationError::NotInitialized);
+        inner.fx_seek_mode = mode;
+        Ok(())
+    }
+
+    pub fn get_fx_seek_mode(&self) -> Result<FXSeekMode, TrackError> {
+        let Ok(inner) = self.inner.lock() else {
+            return Err(TrackError::LockFailed);
         };
 
-        Ok(spatializer.get_velocity())
+        Ok(inner.fx_seek_mode)
     }
 
-    fn spatial_set_direction(&mut self, position: Vector3<f32>) -> Result<(), SpatializationError> {
-       let Ok(mut inner) = self.inner.lock() else {
-            return Err(SpatializationError::from_other(TrackError::LockFailed));
+    /// Start following positions set with [Track::scrub_to] instead of playing normally,
+    /// rendered with `mode`. AudioFX is bypassed while scrubbing.
+    pub fn start_scrub(&mut self, mode: ScrubMode) -> Result<(), TrackError> {
+        let Ok(mut inner) = self.inner.lock() else {
+            return Err(TrackError::LockFailed);
         };
 
-        let Some(spatializer) = inner.spatializer.as_mut() else {
-            return Err(SpatializationError::NotInitialized);
-        };
+        let position = inner.position.load(Ordering::SeqCst);
+        match inner.scrub.as_mut() {
+            Some(scrub) => scrub.mode = mode,
+            None => inner.scrub = Some(Scrubber::new(mode, position)),
+        }
 
-        spatializer.set_direction(position);
         Ok(())
     }
 
-    fn spatial_get_direction(&self) -> Result<Vector3<f32>, SpatializationError> {
+    /// Move the scrub target, the track catches up over the next blocks and is silent once
+    /// the target is reached.
+    pub fn scrub_to(&mut self, position: usize) -> Result<(), TrackError> {
+        if position >= self.pcm_length {
+            return Err(TrackError::SeekOutOfBounds);
+        }
+
         let Ok(mut inner) = self.inner.lock() else {
-            return Err(SpatializationError::from_other(TrackError::LockFailed));
+            return Err(TrackError::LockFailed);
         };
 
-        let Some(spatializer) = inner.spatializer.as_mut() else {
-            return Err(SpatializationError::NotInitialized);
+        let Some(scrub) = inner.scrub.as_mut() else {
+            return Err(TrackError::NotScrubbing);
         };
 
-        Ok(spatializer.get_direction())
+        scrub.target = position as f64;
+        Ok(())
     }
 
-    fn spatial_set_doppler_factor(&mut self, doppler_factor: f32) -> Result<(), SpatializationError> {
+    /// Go back to normal playback from the current scrub position.
+    pub fn stop_scrub(&mut self) -> Result<(), TrackError> {
         let Ok(mut inner) = self.inner.lock() else {
-            return Err(SpatializationError::from_other(TrackError::LockFailed));
+            return Err(TrackError::LockFailed);
         };
 
-        let Some(spatializer) = inner.spatializer.as_mut() else {
-            return Err(SpatializationError::NotInitialized);
+        let Some(scrub) = inner.scrub.take() else {
+            return Ok(());
         };
 
-        spatializer.set_doppler_factor(doppler_factor);
+        let position = (scrub.position as usize).min(self.pcm_length.saturating_sub(1));
+        inner.seek(position)?;
+
         Ok(())
     }
 
-    fn spatial_get_doppler_factor(&self) -> Result<f32, SpatializationError> {
-        let Ok(mut inner) = self.inner.lock() else {
-            return Err(SpatializationError::from_other(TrackError::LockFailed));
-        };
+    pub fn is_scrubbing(&self) -> bool {
+        self.inner
+            .lock()
+            .map(|inner| inner.scrub.is_some())
+            .unwrap_or(false)
+    }
 
-        let Some(spatializer) = inner.spatializer.as_mut() else {
-            return Err(SpatializationError::NotInitialized);
-        };
+    pub fn seek_ms(&mut self, position: usize) -> Result<(), TrackError> {
+        let position = (position as f32 * self.sample_rate) / 1000.0;
+        self.seek(position as usize)
+    }
+
+    /// Seek to a time in seconds, rounded to the nearest frame.
+    pub fn seek_secs(&mut self, position: f64) -> Result<(), TrackError> {
+        if !position.is_finite() || position < 0.0 {
+            return Err(TrackError::SeekOutOfBounds);
+        }
 
-        Ok(spatializer.get_doppler_factor())
+        let position = (position * self.sample_rate as f64).round();
+        self.seek(position as usize)
+    }
+
+    pub fn is_playing(&self) -> bool {
+        self.playing.load(Ordering::SeqCst)
+    }
+
+    /// Block the calling thread until the track ends or is stopped, or until `timeout`
+    /// elapsed. Returns false on timeout.
+    ///
+    /// A looping track only finishes when stopped.
+    pub fn wait_until_finished(&self, timeout: Option<Duration>) -> bool {
+        self.finished.wait(&self.playing, timeout)
+    }
+
+    /// Call `callback` whenever an attribute is changed through [PropertyHandler], with the
+    /// attribute and its new value.
+    ///
+    /// Changes made while playing by [Track::set_attribute_f32_at] and [Track::set_envelope]
+    /// are reported by [Track::dispatch_attribute_changes].
+    pub fn on_attribute_changed<F>(&mut self, callback: F)
+    where
+        F: FnMut(&AttributeEvent) + Send + 'static,
+    {
+        self.observers.add(Box::new(callback));
     }
 
-    fn spatial_set_attenuation_model(
+    /// Remove every callback added with [Track::on_attribute_changed].
+    pub fn clear_attribute_observers(&mut self) {
+        self.observers.clear();
+    }
+
+    /// Report the attributes changed by scheduled changes and envelopes since the last call to
+    /// the callbacks added with [Track::on_attribute_changed]. Call it regularly from the
+    /// control thread, such as once per UI frame.
+    pub fn dispatch_attribute_changes(&self) {
+        self.observers.dispatch();
+    }
+
+    /// Change an attribute when the device clock reaches `device_time`, see
+    /// [Device::get_time]. The block is split at that frame so the change is sample accurate,
+    /// a time already passed is applied at the start of the next block.
+    ///
+    /// Only applied while the track is played directly on a device. The change is reported by
+    /// [Track::dispatch_attribute_changes] once applied.
+    pub fn set_attribute_f32_at(
         &mut self,
-        attenuation_model: AttenuationModel,
-    ) -> Result<(), SpatializationError> {
+        attribute: AudioAttributes,
+        value: f32,
+        device_time: u64,
+    ) -> Result<(), PropertyError> {
+        if !AUTOMATABLE_ATTRIBUTES.contains(&attribute) {
+            return Err(PropertyError::UnsupportedAttribute("Unsupported attribute"));
+        }
+
+        attribute.check_value(value)?;
+
         let Ok(mut inner) = self.inner.lock() else {
-            return Err(SpatializationError::from_other(TrackError::LockFailed));
+            return Err(PropertyError::from_other(TrackError::LockFailed));
         };
 
-        let Some(spatializer) = inner.spatializer.as_mut() else {
-            return Err(SpatializationError::NotInitialized);
-        };
+        inner.schedule.push(ScheduledChange {
+            time: device_time,
+            attribute,
+            value,
+        });
 
-        spatializer.set_attenuation_model(attenuation_model);
         Ok(())
     }
 
-    fn spatial_get_attenuation_model(&self) -> Result<AttenuationModel, SpatializationError> {
+    /// Drop every change queued with [Track::set_attribute_f32_at] that was not applied yet.
+    pub fn clear_scheduled_attributes(&mut self) -> Result<(), TrackError> {
         let Ok(mut inner) = self.inner.lock() else {
-            return Err(SpatializationError::from_other(TrackError::LockFailed));
-        };
-
-        let Some(spatializer) = inner.spatializer.as_mut() else {
-            return Err(SpatializationError::NotInitialized);
+            return Err(TrackError::LockFailed);
         };
 
-        Ok(spatializer.get_attenuation_model())
+        inner.schedule.clear();
+        Ok(())
     }
 
-    fn spatial_set_positioning(
+    /// Automate an attribute with `envelope`, keyframe times are seconds of the track
+    /// position. Replaces the envelope already attached to `attribute`, None detaches it and
+    /// leaves the attribute at its last value.
+    ///
+    /// The envelope overrides [PropertyHandler::set_attribute_f32] for that attribute while
+    /// attached.
+    pub fn set_envelope(
         &mut self,
-        positioning: Positioning,
-    ) -> Result<(), SpatializationError> {
-        let Ok(mut inner) = self.inner.lock() else {
-            return Err(SpatializationError::from_other(TrackError::LockFailed));
-        };
+        attribute: AudioAttributes,
+        envelope: Option<Envelope>,
+    ) -> Result<(), PropertyError> {
+        if !AUTOMATABLE_ATTRIBUTES.contains(&attribute) {
+            return Err(PropertyError::UnsupportedAttribute("Unsupported attribute"));
+        }
 
-        let Some(spatializer) = inner.spatializer.as_mut() else {
-            return Err(SpatializationError::NotInitialized);
+        for keyframe in envelope.iter().flat_map(|envelope| envelope.keyframes()) {
+            attribute.check_value(keyframe.value)?;
+        }
+
+        let Ok(mut inner) = self.inner.lock() else {
+            return Err(PropertyError::from_other(TrackError::LockFailed));
         };
 
-        spatializer.set_positioning(positioning);
+        inner.envelopes.set(attribute, envelope);
         Ok(())
     }
 
-    fn spatial_get_positioning(&self) -> Result<Positioning, SpatializationError> {
-        let Ok(mut inner) = self.inner.lock() else {
-            return Err(SpatializationError::from_other(TrackError::LockFailed));
+    pub fn get_envelope(&self, attribute: AudioAttributes) -> Option<Envelope> {
+        let Ok(inner) = self.inner.lock() else {
+            return None;
         };
 
-        let Some(spatializer) = inner.spatializer.as_mut() else {
-            return Err(SpatializationError::NotInitialized);
+        inner.envelopes.get(attribute).cloned()
+    }
+
+    /// Whether the envelope of `attribute` was stopped while playing, after it produced a
+    /// value the attribute rejects such as an FXTempo change with AudioFX disabled.
+    pub fn is_envelope_stopped(&self, attribute: AudioAttributes) -> bool {
+        let Ok(inner) = self.inner.lock() else {
+            return false;
         };
 
-        Ok(spatializer.get_positioning())
+        inner.envelopes.is_stopped(attribute)
     }
 
-    fn spatial_set_rolloff(&mut self, rolloff: f32) -> Result<(), SpatializationError> {
+    /// Detach every envelope added with [Track::set_envelope].
+    pub fn clear_envelopes(&mut self) -> Result<(), TrackError> {
         let Ok(mut inner) = self.inner.lock() else {
-            return Err(SpatializationError::from_other(TrackError::LockFailed));
-        };
-
-        let Some(spatializer) = inner.spatializer.as_mut() else {
-            return Err(SpatializationError::NotInitialized);
+            return Err(TrackError::LockFailed);
         };
 
-        spatializer.set_rolloff(rolloff);
+        inner.envelopes.clear();
         Ok(())
     }
 
-    fn spatial_get_rolloff(&self) -> Result<f32, SpatializationError> {
-        let Ok(mut inner) = self.inner.lock() else {
-            return Err(SpatializationError::from_other(TrackError::LockFailed));
-        };
+    /// Current position in source frames. After a seek with AudioFX enabled this is the
+    /// position playback resumes from, compensated for the pre-roll.
+    pub fn get_position(&self) -> usize {
+        self.position.load(Ordering::SeqCst)
+    }
 
-        let Some(spatializer) = inner.spatializer.as_mut() else {
-            return Err(SpatializationError::NotInitialized);
+    /// Source frame the listener is hearing, unlike [Track::get_position] this follows the
+    /// FXTempo stretch ratio and the AudioFX latency.
+    pub fn get_played_position(&self) -> Result<usize, TrackError> {
+        let Ok(inner) = self.inner.lock() else {
+            return Err(TrackError::LockFailed);
         };
 
-        Ok(spatializer.get_rolloff())
+        Ok(inner.get_played_position())
     }
 
-    fn spatial_set_min_gain(&mut self, min_gain: f32) -> Result<(), SpatializationError> {
-        let Ok(mut inner) = self.inner.lock() else {
-            return Err(SpatializationError::from_other(TrackError::LockFailed));
-        };
-
-        let Some(spatializer) = inner.spatializer.as_mut() else {
-            return Err(SpatializationError::NotInitialized);
-        };
+    pub fn set_looping(&mut self, looping: bool) {
+        self.is_looping.store(looping, Ordering::SeqCst);
+    }
 
-        spatializer.set_min_gain(min_gain);
-        Ok(())
+    pub fn is_looping(&self) -> bool {
+        self.is_looping.load(Ordering::SeqCst)
     }
 
-    fn spatial_get_min_gain(&self) -> Result<f32, SpatializationError> {
+    /// Blend the last `ms` milliseconds of the audio into the first `ms` milliseconds with an
+    /// equal power crossfade when looping, so a loop that is not cut on a zero crossing does
+    /// not click at the seam. 0 disables it.
+    ///
+    /// Passes after the first start `ms` into the audio, the start was already heard during
+    /// the crossfade. Ignored while the audio is shorter than twice the crossfade.
+    pub fn set_loop_crossfade_ms(&mut self, ms: usize) -> Result<(), TrackError> {
         let Ok(mut inner) = self.inner.lock() else {
-            return Err(SpatializationError::from_other(TrackError::LockFailed));
+            return Err(TrackError::LockFailed);
         };
 
-        let Some(spatializer) = inner.spatializer.as_mut() else {
-            return Err(SpatializationError::NotInitialized);
+        inner.loop_crossfade = (ms as f64 / 1000.0 * inner.reader.sample_rate as f64) as usize;
+        Ok(())
+    }
+
+    pub fn get_loop_crossfade_ms(&self) -> usize {
+        let Ok(inner) = self.inner.lock() else {
+            return 0;
         };
 
-        Ok(spatializer.get_min_gain())
+        (inner.loop_crossfade as f64 * 1000.0 / inner.reader.sample_rate as f64).round() as usize
     }
 
-    fn spatial_set_max_gain(&mut self, max_gain: f32) -> Result<(), SpatializationError> {
-        let mut inner = self.inner.lock().unwrap();
-        if let Some(spatializer) = inner.spatializer.as_mut() {
-            spatializer.set_max_gain(max_gain);
-            Ok(())
-        } else {
-            Err(SpatializationError::NotInitialized)
+    /// Loop the frames between `start` and `end` for practice, the first repetition at the
+    /// start tempo of `trainer` and each next one an FXTempo step faster up to its limit,
+    /// stretched by AudioFX so the pitch is kept. AudioFX is enabled when it is not.
+    ///
+    /// Seeks to `start`, the track keeps playing if it was. Replaces the trainer already
+    /// running, [Track::stop_speed_trainer] still restores the loop region from before the
+    /// first one.
+    pub fn start_speed_trainer(
+        &mut self,
+        start: usize,
+        end: usize,
+        trainer: SpeedTrainer,
+    ) -> Result<(), TrackError> {
+        if start >= end || end > self.pcm_length {
+            return Err(TrackError::SeekOutOfBounds);
         }
-    }
 
-    fn spatial_get_max_gain(&self) -> Result<f32, SpatializationError> {
+        trainer.validate().map_err(TrackError::from_other)?;
+
         let Ok(mut inner) = self.inner.lock() else {
-            return Err(SpatializationError::from_other(TrackError::LockFailed));
+            return Err(TrackError::LockFailed);
         };
 
-        let Some(spatializer) = inner.spatializer.as_mut() else {
-            return Err(SpatializationError::NotInitialized);
+        if inner.fx.is_none() {
+            let fx = AudioFX::new(inner.reader.channels, inner.reader.sample_rate)
+                .map_err(TrackError::from_other)?;
+
+            inner.fx = Some(fx);
+        }
+
+        if let Some(fx) = inner.fx.as_mut() {
+            fx.set_tempo(trainer.start_tempo)
+                .map_err(TrackError::from_other)?;
+        }
+
+        let previous_region = match inner.trainer {
+            Some(state) => state.previous_region,
+            None => inner.loop_region,
         };
 
-        Ok(spatializer.get_max_gain())
+        inner.loop_region = Some((start, end));
+        inner.trainer = Some(TrainerState::new(trainer, previous_region));
+        inner.seek(start)?;
+
+        Ok(())
     }
 
-    fn spatial_set_min_distance(&mut self, min_distance: f32) -> Result<(), SpatializationError> {
+    /// Stop looping the practice region and restore the loop region set before the trainer
+    /// started, playback goes on at the FXTempo reached.
+    pub fn stop_speed_trainer(&mut self) -> Result<(), TrackError> {
         let Ok(mut inner) = self.inner.lock() else {
-            return Err(SpatializationError::from_other(TrackError::LockFailed));
+            return Err(TrackError::LockFailed);
         };
 
-        let Some(spatializer) = inner.spatializer.as_mut() else {
-            return Err(SpatializationError::NotInitialized);
-        };
+        if let Some(state) = inner.trainer.take() {
+            inner.loop_region = state.previous_region;
+        }
 
-        spatializer.set_min_distance(min_distance);
         Ok(())
     }
 
-    fn spatial_get_min_distance(&self) -> Result<f32, SpatializationError> {
-        let Ok(mut inner) = self.inner.lock() else {
-            return Err(SpatializationError::from_other(TrackError::LockFailed));
+    /// Repetitions of the practice region played so far, None when no trainer is running.
+    pub fn get_speed_trainer_repetitions(&self) -> Option<u32> {
+        let Ok(inner) = self.inner.lock() else {
+            return None;
         };
 
-        let Some(spatializer) = inner.spatializer.as_mut() else {
-            return Err(SpatializationError::NotInitialized);
+        inner.trainer.map(|trainer| trainer.repetitions)
+    }
+
+    pub fn get_length(&self) -> usize {
+        self.pcm_length
+    }
+
+    pub fn ref_id(&self) -> usize {
+        self.ref_id
+    }
+}
+
+impl Track {
+    fn apply_attribute_f32(
+        &mut self,
+        _type: AudioAttributes,
+        _value: f32,
+    ) -> Result<(), PropertyError> {
+        let Ok(mut inner) = self.inner.lock() else {
+            return Err(PropertyError::from_other(TrackError::LockFailed));
         };
 
-        Ok(spatializer.get_min_distance())
+        inner.set_attribute_f32(_type, _value)
     }
 
-    fn spatial_set_max_distance(&mut self, max_distance: f32) -> Result<(), SpatializationError> {
+    fn apply_attribute_bool(
+        &mut self,
+        _type: AudioAttributes,
+        _value: bool,
+    ) -> Result<(), PropertyError> {
         let Ok(mut inner) = self.inner.lock() else {
-            return Err(SpatializationError::from_other(TrackError::LockFailed));
+            return Err(PropertyError::from_other(TrackError::LockFailed));
         };
 
-        let Some(spatializer) = inner.spatializer.as_mut() else {
-            return Err(SpatializationError::NotInitialized);
+        inner.set_attribute_bool(_type, _value)
+    }
+
+    fn apply_custom_attribute(
+        &mut self,
+        name: &str,
+        value: AttributeValue,
+    ) -> Result<(), PropertyError> {
+        let Ok(mut inner) = self.inner.lock() else {
+            return Err(PropertyError::from_other(TrackError::LockFailed));
         };
 
-        spatializer.set_max_distance(max_distance);
+        inner.custom_attributes.set(name, value);
         Ok(())
     }
+}
 
-    fn spatial_get_max_distance(&self) -> Result<f32, SpatializationError> {
-        let Ok(mut inner) = self.inner.lock() else {
-            return Err(SpatializationError::from_other(TrackError::LockFailed));
+impl PropertyHandler for Track {
+    fn get_attribute_f32(&self, _type: AudioAttributes) -> Result<f32, PropertyError> {
+        let Ok(inner) = self.inner.lock() else {
+            return Err(PropertyError::from_other(TrackError::LockFailed));
         };
 
-        let Some(spatializer) = inner.spatializer.as_mut() else {
-            return Err(SpatializationError::NotInitialized);
+        let result = match _type {
+            AudioAttributes::FXTempo => {
+                if inner.fx.is_none() {
+                    return Err(PropertyError::from_other(AudioFXError::NotEnabled));
+                }
+
+                let fx = inner.fx.as_ref().unwrap();
+                fx.tempo
+            }
+            AudioAttributes::FXPitch => {
+                if inner.fx.is_none() {
+                    return Err(PropertyError::from_other(AudioFXError::NotEnabled));
+                }
+
+                let fx = inner.fx.as_ref().unwrap();
+                fx.octave
+            }
+            AudioAttributes::SampleRate => inner.resampler.target_sample_rate as f32,
+            AudioAttributes::Volume => inner.gainer.volume,
+            AudioAttributes::Pan => inner.panner.pan,
+            AudioAttributes::Balance => inner.panner.get_balance(),
+            _ => {
+                return Err(PropertyError::UnsupportedAttribute("Unsupported attribute"));
+            }
         };
 
-        Ok(spatializer.get_max_distance())
+        Ok(result)
     }
 
-    fn spatial_set_cone(
+    fn set_attribute_f32(
         &mut self,
-        inner_angle: f32,
-        outer_angle: f32,
-        outer_gain: f32,
-    ) -> Result<(), SpatializationError> {
-        let Ok(mut inner) = self.inner.lock() else {
-            return Err(SpatializationError::from_other(TrackError::LockFailed));
-        };
+        _type: AudioAttributes,
+        value: f32,
+    ) -> Result<(), PropertyError> {
+        self.apply_attribute_f32(_type, value)?;
 
-        let Some(spatializer) = inner.spatializer.as_mut() else {
-            return Err(SpatializationError::NotInitialized);
-        };
+        let value = self.get_attribute_f32(_type).unwrap_or(value);
+        self.observers.notify(AttributeEvent {
+            attribute: _type,
+            name: None,
+            value: AttributeValue::F32(value),
+        });
 
-        spatializer.set_cone(inner_angle, outer_angle, outer_gain);
         Ok(())
     }
 
-    fn spatial_get_cone(&self) -> Result<(f32, f32, f32), SpatializationError> {
-        let Ok(mut inner) = self.inner.lock() else {
-            return Err(SpatializationError::from_other(TrackError::LockFailed));
+    fn get_attribute_bool(&self, _type: AudioAttributes) -> Result<bool, PropertyError> {
+        let Ok(inner) = self.inner.lock() else {
+            return Err(PropertyError::from_other(TrackError::LockFailed));
         };
 
-        let Some(spatializer) = inner.spatializer.as_mut() else {
-            return Err(SpatializationError::NotInitialized);
+        match _type {
+            AudioAttributes::FXEnabled => Ok(inner.fx.is_some()),
+            AudioAttributes::SpatializationEnabled => Ok(inner.spatializer.is_some()),
+            AudioAttributes::ForceMono => Ok(inner.panner.force_mono),
+            AudioAttributes::SwapChannels => Ok(inner.panner.swap_channels),
+            _ => Err(PropertyError::UnsupportedAttribute("Unsupported attribute")),
+        }
+    }
+
+    fn set_attribute_bool(
+        &mut self,
+        _type: AudioAttributes,
+        value: bool,
+    ) -> Result<(), PropertyError> {
+        self.apply_attribute_bool(_type, value)?;
+
+        let value = self.get_attribute_bool(_type).unwrap_or(value);
+        self.observers.notify(AttributeEvent {
+            attribute: _type,
+            name: None,
+            value: AttributeValue::Bool(value),
+        });
+
+        Ok(())
+    }
+
+    fn get_custom_attribute(&self, name: &str) -> Result<AttributeValue, PropertyError> {
+        let Ok(inner) = self.inner.lock() else {
+            return Err(PropertyError::from_other(TrackError::LockFailed));
         };
 
-        Ok(spatializer.get_cone())
+        inner.custom_attributes.get(name)
     }
 
-    fn spatial_set_directional_attenuation_factor(
+    fn set_custom_attribute(
         &mut self,
-        directional_attenuation_factor: f32,
-    ) -> Result<(), SpatializationError> {
-        let Ok(mut inner) = self.inner.lock() else {
-            return Err(SpatializationError::from_other(TrackError::LockFailed));
-        };
+        name: &str,
+        value: AttributeValue,
+    ) -> Result<(), PropertyError> {
+        self.apply_custom_attribute(name, value)?;
 
-        let Some(spatializer) = inner.spatializer.as_mut() else {
-            return Err(SpatializationError::NotInitialized);
-        };
+        self.observers.notify(AttributeEvent {
+            attribute: AudioAttributes::Unknown,
+            name: Some(name),
+            value,
+        });
 
-        spatializer.set_directional_attenuation_factor(directional_attenuation_factor);
         Ok(())
     }
 
-    fn spatial_get_directional_attenuation_factor(&self) -> Result<f32, SpatializationError> {
+    fn supported_attributes(&self) -> &'static [AudioAttributes] {
+        &[
+            AudioAttributes::SampleRate,
+            AudioAttributes::Volume,
+            AudioAttributes::Pan,
+            AudioAttributes::Balance,
+            AudioAttributes::FXPitch,
+            AudioAttributes::FXTempo,
+            AudioAttributes::FXEnabled,
+            AudioAttributes::SpatializationEnabled,
+            AudioAttributes::ForceMono,
+            AudioAttributes::SwapChannels,
+        ]
+    }
+
+    fn set_attributes(
+        &mut self,
+        attributes: &[(AudioAttributes, AttributeValue)],
+    ) -> Result<(), PropertyError> {
         let Ok(mut inner) = self.inner.lock() else {
-            return Err(SpatializationError::from_other(TrackError::LockFailed));
+            return Err(PropertyError::from_other(TrackError::LockFailed));
         };
 
-        let Some(spatializer) = inner.spatializer.as_mut() else {
-            return Err(SpatializationError::NotInitialized);
-        };
+        validate_channel_attributes(attributes, inner.fx.is_some())?;
 
-        Ok(spatializer.get_directional_attenuation_factor())
+        let mut applied = 0;
+        let result = attributes.iter().try_for_each(|&(attribute, value)| {
+            inner.set_attribute(attribute, value)?;
+            applied += 1;
+            Ok(())
+        });
+
+        drop(inner);
+        self.observers.notify_attributes(&*self, &attributes[..applied]);
+        result
     }
+}
 
-    fn spatial_get_relative_position_and_direction(
+impl SpatializationSource for Track {
+    fn with_spatialization<R>(
         &self,
-        listener: &Device,
-    ) -> Result<(Vector3<f32>, Vector3<f32>), SpatializationError> {
+        f: impl FnOnce(&mut Spatialization, &mut Instant) -> R,
+    ) -> Result<R, SpatializationError> {
         let Ok(mut inner) = self.inner.lock() else {
             return Err(SpatializationError::from_other(TrackError::LockFailed));
         };
 
+        let inner = &mut *inner;
         let Some(spatializer) = inner.spatializer.as_mut() else {
             return Err(SpatializationError::NotInitialized);
         };
 
-        let Ok(mut listener_inner) = listener.inner.lock() else {
-            return Err(SpatializationError::from_other(TrackError::LockFailed));
-        };
-
-        let Some(listener_spatializer) = listener_inner.spatialization.as_mut() else {
-            return Err(SpatializationError::NotInitialized);
-        };
-
-        Ok(spatializer.get_relative_position_and_direction(listener_spatializer))
+        Ok(f(spatializer, &mut inner.last_time))
     }
 }
 
@@ -729,5 +1138,6 @@ impl Drop for Track {
         let mut inner = self.inner.lock().unwrap();
         inner.marked_as_deleted = true;
         inner.playing.store(false, Ordering::SeqCst);
+        inner.finished.notify(PlaybackEnd::Stopped);
     }
 }
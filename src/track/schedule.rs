@@ -0,0 +1,47 @@
+use std::collections::VecDeque;
+
+use crate::misc::audioattributes::AudioAttributes;
+
+/// An attribute change waiting for the device clock, see
+/// [Track::set_attribute_f32_at](crate::Track::set_attribute_f32_at).
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct ScheduledChange {
+    pub time: u64,
+    pub attribute: AudioAttributes,
+    pub value: f32,
+}
+
+/// Pending changes ordered by device time, pushed from the control thread and applied by the
+/// audio thread while reading.
+#[derive(Debug, Default)]
+pub(crate) struct AttributeSchedule {
+    changes: VecDeque<ScheduledChange>,
+}
+
+impl AttributeSchedule {
+    /// Queue `change`, changes for the same time are applied in the order they were pushed.
+    pub fn push(&mut self, change: ScheduledChange) {
+        let index = self.changes.partition_point(|queued| queued.time <= change.time);
+        self.changes.insert(index, change);
+    }
+
+    pub fn next_time(&self) -> Option<u64> {
+        self.changes.front().map(|change| change.time)
+    }
+
+    /// Take the next change due at or before `time`.
+    pub fn pop_due(&mut self, time: u64) -> Option<ScheduledChange> {
+        match self.changes.front() {
+            Some(change) if change.time <= time => self.changes.pop_front(),
+            _ => None,
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.changes.clear();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+}
@@ -0,0 +1,120 @@
+use crate::audioreader::{AudioReader, AudioReaderError};
+
+// Fastest the scrub position chases the target, in source frames per rendered frame. Also
+// bounds the span read per block to the scratch buffer headroom.
+const MAX_SPEED: f64 = 3.0;
+
+// Fade at both ends of a pitch-correct window, in frames
+const WINDOW_FADE: usize = 64;
+
+/// How a scrubbing track renders the audio between two scrub positions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[repr(C)]
+pub enum ScrubMode {
+    /// Play the audio between the positions sped up or slowed down, the pitch follows the
+    /// drag speed like a tape.
+    #[default]
+    Varispeed = 0,
+    /// Play short windows at normal speed from the current position, the pitch stays the same
+    /// regardless of the drag speed.
+    PitchCorrect = 1,
+}
+
+/// Follows a target position set from the control thread, see
+/// [Track::scrub_to](crate::Track::scrub_to).
+#[derive(Debug, Clone)]
+pub(crate) struct Scrubber {
+    pub mode: ScrubMode,
+    pub position: f64,
+    pub target: f64,
+}
+
+impl Scrubber {
+    pub fn new(mode: ScrubMode, position: usize) -> Self {
+        Self {
+            mode,
+            position: position as f64,
+            target: position as f64,
+        }
+    }
+
+    pub fn reset(&mut self, position: usize) {
+        self.position = position as f64;
+        self.target = position as f64;
+    }
+
+    /// Render `frame_count` frames into `output` moving from the current position towards the
+    /// target, `buffer` is used to read the source span. Silent while the target is reached.
+    pub fn render(
+        &mut self,
+        reader: &mut AudioReader,
+        output: &mut [f32],
+        buffer: &mut [f32],
+        frame_count: usize,
+    ) -> Result<usize, AudioReaderError> {
+        let channels = reader.channels;
+        let last_frame = reader.pcm_length.saturating_sub(1) as f64;
+        let size = frame_count * channels;
+
+        output[..size].fill(0.0);
+
+        let max_distance = frame_count as f64 * MAX_SPEED;
+        let target = self.target.clamp(0.0, last_frame);
+        let distance = (target - self.position).clamp(-max_distance, max_distance);
+
+        if distance.abs() < 0.5 || frame_count == 0 {
+            return Ok(frame_count);
+        }
+
+        let start = self.position;
+        let end = start + distance;
+
+        match self.mode {
+            ScrubMode::Varispeed => {
+                let low = start.min(end).floor() as usize;
+                let high = ((start.max(end).ceil() as usize) + 2).min(reader.pcm_length);
+                let span = (high - low).min(buffer.len() / channels);
+
+                reader.seek(low)?;
+                let read = reader.read(&mut buffer[..span * channels])?;
+                if read == 0 {
+                    return Ok(frame_count);
+                }
+
+                let step = distance / frame_count as f64;
+
+                for (index, frame) in output[..size].chunks_exact_mut(channels).enumerate() {
+                    let position = (start + step * (index + 1) as f64 - low as f64).max(0.0);
+                    let first = (position.floor() as usize).min(read - 1);
+                    let second = (first + 1).min(read - 1);
+                    let fraction = (position - first as f64).clamp(0.0, 1.0) as f32;
+
+                    for (channel, sample) in frame.iter_mut().enumerate() {
+                        let a = buffer[first * channels + channel];
+                        let b = buffer[second * channels + channel];
+                        *sample = a + (b - a) * fraction;
+                    }
+                }
+            }
+            ScrubMode::PitchCorrect => {
+                reader.seek(start.round() as usize)?;
+                let read = reader.read(&mut output[..size])?;
+
+                let fade = WINDOW_FADE.min(read / 2).max(1);
+                for (index, frame) in output[..read * channels].chunks_exact_mut(channels).enumerate() {
+                    let edge = index.min(read - 1 - index);
+                    if edge >= fade {
+                        continue;
+                    }
+
+                    let gain = edge as f32 / fade as f32;
+                    frame.iter_mut().for_each(|sample| *sample *= gain);
+                }
+            }
+        }
+
+        self.position = end;
+        Ok(frame_count)
+    }
+}
@@ -0,0 +1,209 @@
+use crate::{device::Device, misc::finishsignal::Completion};
+
+use super::{Track, TrackError, TrackInfo, linkgroup::LinkGroup};
+
+#[derive(Debug)]
+struct Stem {
+    name: String,
+    volume: f32,
+    muted: bool,
+    soloed: bool,
+}
+
+/// Aligned files of a multitrack, such as the drums, bass and vocals of a song, played as one
+/// with a shared transport through a [LinkGroup] so they never drift apart.
+///
+/// Every stem keeps its own volume, mute and solo. While any stem is soloed only the soloed
+/// stems are heard, a muted stem stays silent even when soloed.
+#[derive(Debug)]
+pub struct StemPlayer {
+    group: LinkGroup,
+    stems: Vec<Stem>,
+}
+
+impl StemPlayer {
+    /// Load every file in `paths` whole, see [create_stem_player](crate::create_stem_player).
+    pub(crate) fn new(paths: &[&str]) -> Result<Self, TrackError> {
+        let tracks = paths
+            .iter()
+            .map(|&path| {
+                Track::new(TrackInfo {
+                    source: crate::Source::Path(path),
+                    ..Default::default()
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut player = Self::from_tracks(tracks)?;
+        for (stem, path) in player.stems.iter_mut().zip(paths) {
+            stem.name = stem_name(path);
+        }
+
+        Ok(player)
+    }
+
+    /// Play already created tracks as stems, named by their index. The tracks must share the
+    /// same source sample rate so a position means the same instant in each.
+    pub fn from_tracks(tracks: Vec<Track>) -> Result<Self, TrackError> {
+        let mut group = LinkGroup::new();
+        let mut stems = Vec::with_capacity(tracks.len());
+        let mut sample_rate = None;
+
+        for (index, track) in tracks.iter().enumerate() {
+            let (rate, volume) = {
+                let Ok(inner) = track.inner.lock() else {
+                    return Err(TrackError::LockFailed);
+                };
+
+                (inner.reader.sample_rate, inner.gainer.volume)
+            };
+
+            match sample_rate {
+                Some(sample_rate) if sample_rate != rate => {
+                    return Err(TrackError::SampleRateMismatch(rate));
+                }
+                _ => sample_rate = Some(rate),
+            }
+
+            group.add(track);
+            stems.push(Stem {
+                name: index.to_string(),
+                volume,
+                muted: false,
+                soloed: false,
+            });
+        }
+
+        Ok(Self { group, stems })
+    }
+
+    pub fn len(&self) -> usize {
+        self.stems.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.stems.is_empty()
+    }
+
+    /// Name of every stem, the file name without its extension when loaded from paths.
+    pub fn names(&self) -> Vec<&str> {
+        self.stems.iter().map(|stem| stem.name.as_str()).collect()
+    }
+
+    /// Index of the stem called `name`.
+    pub fn find(&self, name: &str) -> Option<usize> {
+        self.stems.iter().position(|stem| stem.name == name)
+    }
+
+    /// The track of a stem, for its other attributes and effects. Its volume is managed by
+    /// the player, use [StemPlayer::set_volume] instead.
+    pub fn track(&self, index: usize) -> Option<&Track> {
+        self.group.members().get(index)
+    }
+
+    pub fn set_volume(&mut self, index: usize, volume: f32) -> Result<(), TrackError> {
+        self.stem_mut(index)?.volume = volume;
+        self.apply_gains()
+    }
+
+    pub fn get_volume(&self, index: usize) -> Result<f32, TrackError> {
+        Ok(self.stem(index)?.volume)
+    }
+
+    pub fn set_muted(&mut self, index: usize, muted: bool) -> Result<(), TrackError> {
+        self.stem_mut(index)?.muted = muted;
+        self.apply_gains()
+    }
+
+    pub fn is_muted(&self, index: usize) -> Result<bool, TrackError> {
+        Ok(self.stem(index)?.muted)
+    }
+
+    pub fn set_solo(&mut self, index: usize, soloed: bool) -> Result<(), TrackError> {
+        self.stem_mut(index)?.soloed = soloed;
+        self.apply_gains()
+    }
+
+    pub fn is_soloed(&self, index: usize) -> Result<bool, TrackError> {
+        Ok(self.stem(index)?.soloed)
+    }
+
+    /// Unsolo every stem, all stems that are not muted are heard again.
+    pub fn clear_solos(&mut self) -> Result<(), TrackError> {
+        for stem in self.stems.iter_mut() {
+            stem.soloed = false;
+        }
+
+        self.apply_gains()
+    }
+
+    /// Play every stem from the current position, see [LinkGroup::play].
+    pub fn play(&mut self, device: &mut Device) -> Result<Vec<Completion>, TrackError> {
+        self.group.play(device)
+    }
+
+    pub fn stop(&mut self) -> Result<(), TrackError> {
+        self.group.stop()
+    }
+
+    pub fn seek(&mut self, position: usize) -> Result<(), TrackError> {
+        self.group.seek(position)
+    }
+
+    pub fn seek_secs(&mut self, position: f64) -> Result<(), TrackError> {
+        self.group.seek_secs(position)
+    }
+
+    pub fn set_looping(&mut self, looping: bool) {
+        self.group.set_looping(looping);
+    }
+
+    /// Position shared by the stems in source frames.
+    pub fn get_position(&self) -> usize {
+        self.group.get_position()
+    }
+
+    /// Length of the longest stem in source frames.
+    pub fn get_length(&self) -> usize {
+        self.group
+            .members()
+            .iter()
+            .map(|track| track.get_length())
+            .max()
+            .unwrap_or(0)
+    }
+
+    pub fn is_playing(&self) -> bool {
+        self.group.is_playing()
+    }
+
+    fn stem(&self, index: usize) -> Result<&Stem, TrackError> {
+        self.stems.get(index).ok_or(TrackError::StemNotFound(index))
+    }
+
+    fn stem_mut(&mut self, index: usize) -> Result<&mut Stem, TrackError> {
+        self.stems.get_mut(index).ok_or(TrackError::StemNotFound(index))
+    }
+
+    // Every stem gain is set under one lock of all tracks, a solo is heard on the same frame
+    fn apply_gains(&mut self) -> Result<(), TrackError> {
+        let any_soloed = self.stems.iter().any(|stem| stem.soloed);
+        let mut members = self.group.lock_members()?;
+
+        for (member, stem) in members.iter_mut().zip(self.stems.iter()) {
+            let audible = !stem.muted && (!any_soloed || stem.soloed);
+            let volume = if audible { stem.volume } else { 0.0 };
+
+            member.gainer.set_volume(volume);
+        }
+
+        Ok(())
+    }
+}
+
+fn stem_name(path: &str) -> String {
+    std::path::Path::new(path)
+        .file_stem()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_else(|| path.to_string())
+}
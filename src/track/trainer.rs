@@ -0,0 +1,75 @@
+use crate::effects::AudioFXError;
+
+/// Tempo ramp of a practice loop, see
+/// [Track::start_speed_trainer](crate::Track::start_speed_trainer).
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SpeedTrainer {
+    /// FXTempo of the first repetition.
+    pub start_tempo: f32,
+    /// Added to the FXTempo after each step of repetitions.
+    pub step: f32,
+    /// Highest FXTempo, the region keeps looping at it once reached.
+    pub max_tempo: f32,
+    /// Repetitions played at a tempo before the next step, at least 1.
+    pub repetitions_per_step: u32,
+}
+
+impl Default for SpeedTrainer {
+    fn default() -> Self {
+        Self {
+            start_tempo: 0.7,
+            step: 0.05,
+            max_tempo: 1.0,
+            repetitions_per_step: 1,
+        }
+    }
+}
+
+impl SpeedTrainer {
+    /// FXTempo of the repetition after `repetitions` were played.
+    pub fn tempo_at(&self, repetitions: u32) -> f32 {
+        let steps = repetitions / self.repetitions_per_step.max(1);
+        (self.start_tempo + self.step * steps as f32).min(self.max_tempo)
+    }
+
+    /// The tempos have to fit the AudioFX tempo range of 0.5 to 2.0, and the ramp go upward.
+    pub(crate) fn validate(&self) -> Result<(), AudioFXError> {
+        let range = 0.5..=2.0;
+        if !range.contains(&self.start_tempo)
+            || !range.contains(&self.max_tempo)
+            || self.max_tempo < self.start_tempo
+            || !self.step.is_finite()
+            || self.step < 0.0
+        {
+            return Err(AudioFXError::InvalidTempo);
+        }
+
+        Ok(())
+    }
+}
+
+/// A running [SpeedTrainer] and the repetitions played so far.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct TrainerState {
+    pub trainer: SpeedTrainer,
+    pub repetitions: u32,
+    // Loop region set before the trainer started, restored when it stops
+    pub previous_region: Option<(usize, usize)>,
+}
+
+impl TrainerState {
+    pub fn new(trainer: SpeedTrainer, previous_region: Option<(usize, usize)>) -> Self {
+        Self {
+            trainer,
+            repetitions: 0,
+            previous_region,
+        }
+    }
+
+    /// Count a finished repetition, returns the tempo of the next one.
+    pub fn next_repetition(&mut self) -> f32 {
+        self.repetitions = self.repetitions.saturating_add(1);
+        self.trainer.tempo_at(self.repetitions)
+    }
+}
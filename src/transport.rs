@@ -0,0 +1,623 @@
+use std::sync::{Arc, Mutex, MutexGuard, atomic::Ordering};
+
+use thiserror::Error;
+
+use crate::{
+    device::Device,
+    misc::{finishsignal::PlaybackEnd, syncstart},
+    mixer::{Mixer, inner::MixerChannel},
+    track::{Track, inner::TrackChannel},
+};
+
+#[derive(Debug, Error)]
+pub enum TransportError {
+    #[error("Failed to lock a source following the transport")]
+    LockFailed,
+    #[error("The transport is playing on a different device")]
+    InvalidDeviceId,
+    #[error("Invalid transport rate: {0}")]
+    InvalidRate(f32),
+    #[error("Invalid transport position: {0}")]
+    InvalidPosition(f64),
+    #[error("The loop region must end after it starts")]
+    InvalidLoopRegion,
+    #[error("{0}")]
+    Other(Box<dyn std::error::Error + Send + 'static>),
+}
+
+impl TransportError {
+    pub fn from_other<E: std::error::Error + Send + 'static>(error: E) -> Self {
+        TransportError::Other(Box::new(error))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum TransportState {
+    #[default]
+    Stopped,
+    Playing,
+    Paused,
+}
+
+#[derive(Debug)]
+enum Follower {
+    // The rate is the SampleRate attribute of the source at a transport rate of 1.0
+    Track { track: Track, rate: f32 },
+    Mixer { ref_id: usize, inner: Arc<Mutex<MixerChannel>>, rate: f32 },
+}
+
+enum FollowerGuard<'a> {
+    Track(MutexGuard<'a, TrackChannel>),
+    Mixer(MutexGuard<'a, MixerChannel>),
+}
+
+/// One timeline followed by any number of tracks and mixers: play, pause, stop, seek, rate and
+/// a loop region are set once on the transport and applied to every follower together.
+///
+/// Positions are in seconds so followers of different sample rates stay on the same instant.
+/// Like a [LinkGroup](crate::LinkGroup) the changes are scheduled on the device clock a couple
+/// of blocks ahead, so the followers are held silent briefly after [Transport::play] and
+/// [Transport::seek] while playing.
+#[derive(Debug)]
+pub struct Transport {
+    followers: Vec<Follower>,
+    state: TransportState,
+    // Seconds the followers start from, only tracked while stopped
+    position: f64,
+    rate: f32,
+    loop_region: Option<(f64, f64)>,
+    // Device frames the transport is scheduled ahead, set by play
+    lead: u64,
+    device_ref_id: Option<u32>,
+}
+
+impl Default for Transport {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Transport {
+    pub fn new() -> Self {
+        Self {
+            followers: Vec::new(),
+            state: TransportState::Stopped,
+            position: 0.0,
+            rate: 1.0,
+            loop_region: None,
+            lead: 0,
+            device_ref_id: None,
+        }
+    }
+
+    /// Make `track` follow the transport. The rate and loop region apply right away, the
+    /// position from the next [Transport::play] or [Transport::seek]. Following twice does
+    /// nothing.
+    pub fn follow_track(&mut self, track: &Track) -> Result<(), TransportError> {
+        if self.follows_track(track) {
+            return Ok(());
+        }
+
+        let rate = {
+            let Ok(inner) = track.inner.lock() else {
+                return Err(TransportError::LockFailed);
+            };
+
+            inner.resampler.target_sample_rate
+        };
+
+        self.followers.push(Follower::Track {
+            track: track.clone(),
+            rate,
+        });
+
+        self.apply_settings()
+    }
+
+    /// Make `mixer` follow the transport, see [Transport::follow_track]. The mixer is played
+    /// by the transport and should not be played on its own.
+    pub fn follow_mixer(&mut self, mixer: &Mixer) -> Result<(), TransportError> {
+        if self.follows_mixer(mixer) {
+            return Ok(());
+        }
+
+        let rate = {
+            let Ok(inner) = mixer.inner.lock() else {
+                return Err(TransportError::LockFailed);
+            };
+
+            inner.resampler.target_sample_rate
+        };
+
+        self.followers.push(Follower::Mixer {
+            ref_id: mixer.ref_id(),
+            inner: mixer.inner.clone(),
+            rate,
+        });
+
+        self.apply_settings()
+    }
+
+    /// Stop `track` from following the transport, its rate is restored and its loop region
+    /// cleared. It keeps playing on its own.
+    pub fn unfollow_track(&mut self, track: &Track) -> Result<bool, TransportError> {
+        let Some(index) = self.followers.iter().position(|follower| {
+            matches!(follower, Follower::Track { track: t, .. } if t.ref_id == track.ref_id)
+        }) else {
+            return Ok(false);
+        };
+
+        self.release(index)
+    }
+
+    /// Stop `mixer` from following the transport, see [Transport::unfollow_track].
+    pub fn unfollow_mixer(&mut self, mixer: &Mixer) -> Result<bool, TransportError> {
+        let ref_id = mixer.ref_id();
+        let Some(index) = self.followers.iter().position(|follower| {
+            matches!(follower, Follower::Mixer { ref_id: id, .. } if *id == ref_id)
+        }) else {
+            return Ok(false);
+        };
+
+        self.release(index)
+    }
+
+    pub fn follows_track(&self, track: &Track) -> bool {
+        self.followers.iter().any(|follower| {
+            matches!(follower, Follower::Track { track: t, .. } if t.ref_id == track.ref_id)
+        })
+    }
+
+    pub fn follows_mixer(&self, mixer: &Mixer) -> bool {
+        let ref_id = mixer.ref_id();
+        self.followers.iter().any(|follower| {
+            matches!(follower, Follower::Mixer { ref_id: id, .. } if *id == ref_id)
+        })
+    }
+
+    /// Number of tracks and mixers following the transport.
+    pub fn len(&self) -> usize {
+        self.followers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.followers.is_empty()
+    }
+
+    /// Play every follower on `device` from the transport position, all starting on the same
+    /// device frame. While paused, resumes every follower where it was. Once every follower
+    /// played to its end the transport is stopped and plays again from the start.
+    pub fn play(&mut self, device: &mut Device) -> Result<(), TransportError> {
+        self.refresh_state();
+        if self.state == TransportState::Playing {
+            return Ok(());
+        }
+
+        let device_ref_id = device.get_ref_id();
+        if self.device_ref_id.is_some_and(|id| id != device_ref_id) {
+            return Err(TransportError::InvalidDeviceId);
+        }
+
+        self.device_ref_id = Some(device_ref_id);
+        self.lead = syncstart::lead_frames(device);
+
+        if self.state == TransportState::Paused {
+            self.state = TransportState::Playing;
+            return self.release_at_lead();
+        }
+
+        // Held silent until every follower is attached, then released together below
+        for follower in self.followers.iter_mut() {
+            match follower {
+                Follower::Track { track, .. } => {
+                    track
+                        .start_playback(device, Some(u64::MAX))
+                        .map_err(TransportError::from_other)?;
+                }
+                Follower::Mixer { inner, .. } => {
+                    device
+                        .attach_mixer_channel(inner)
+                        .map_err(TransportError::from_other)?;
+
+                    let Ok(mut inner) = inner.lock() else {
+                        return Err(TransportError::LockFailed);
+                    };
+
+                    inner.begin_playback().map_err(TransportError::from_other)?;
+                    inner.start_at = Some(u64::MAX);
+                }
+            }
+        }
+
+        let mut followers = self.lock_followers()?;
+        let start = Self::start_time(&followers, self.lead);
+
+        for follower in followers.iter_mut() {
+            follower.seek(self.position)?;
+            follower.set_start_at(start);
+        }
+
+        self.state = TransportState::Playing;
+        Ok(())
+    }
+
+    /// Hold every follower silent where it is, [Transport::play] resumes them together.
+    pub fn pause(&mut self) -> Result<(), TransportError> {
+        self.refresh_state();
+        if self.state != TransportState::Playing {
+            return Ok(());
+        }
+
+        let mut followers = self.lock_followers()?;
+        for follower in followers.iter_mut().filter(|follower| follower.is_playing()) {
+            follower.set_start_at(Some(u64::MAX));
+        }
+
+        self.state = TransportState::Paused;
+        Ok(())
+    }
+
+    /// Stop every follower and rewind the transport to the start.
+    pub fn stop(&mut self) -> Result<(), TransportError> {
+        let mut followers = self.lock_followers()?;
+        for follower in followers.iter_mut() {
+            follower.stop();
+        }
+
+        self.position = 0.0;
+        self.state = TransportState::Stopped;
+        Ok(())
+    }
+
+    /// Move the transport to `position` in seconds. While playing, the followers resume from
+    /// it on the same device frame, a track shorter than `position` and a follower that
+    /// already played to its end are left where they are.
+    pub fn seek(&mut self, position: f64) -> Result<(), TransportError> {
+        if !position.is_finite() || position < 0.0 {
+            return Err(TransportError::InvalidPosition(position));
+        }
+
+        self.refresh_state();
+        self.position = position;
+        if self.state == TransportState::Stopped {
+            return Ok(());
+        }
+
+        let mut followers = self.lock_followers()?;
+        let start = match self.state {
+            TransportState::Playing => Self::start_time(&followers, self.lead),
+            _ => Some(u64::MAX),
+        };
+
+        for follower in followers.iter_mut().filter(|follower| follower.is_playing()) {
+            follower.seek(position)?;
+            follower.set_start_at(start);
+        }
+
+        Ok(())
+    }
+
+    /// Position of the transport in seconds, the position of the first follower while
+    /// playing or paused.
+    pub fn get_position(&self) -> Result<f64, TransportError> {
+        if self.state == TransportState::Stopped {
+            return Ok(self.position);
+        }
+
+        let followers = self.lock_followers()?;
+        Ok(followers
+            .first()
+            .map_or(self.position, |follower| follower.position()))
+    }
+
+    /// Play every follower `rate` times faster, pitch included, by scaling its SampleRate
+    /// attribute. Use FXTempo on the followers to change the speed alone.
+    pub fn set_rate(&mut self, rate: f32) -> Result<(), TransportError> {
+        if !rate.is_finite() || rate <= 0.0 {
+            return Err(TransportError::InvalidRate(rate));
+        }
+
+        self.rate = rate;
+        self.apply_settings()
+    }
+
+    pub fn get_rate(&self) -> f32 {
+        self.rate
+    }
+
+    /// Play the region between `start` and `end` seconds over and over, every follower jumps
+    /// back to `start` as it reaches `end`. None plays through.
+    pub fn set_loop_region(&mut self, region: Option<(f64, f64)>) -> Result<(), TransportError> {
+        let valid = |(start, end): (f64, f64)| start.is_finite() && start >= 0.0 && end > start;
+        if !region.is_none_or(valid) {
+            return Err(TransportError::InvalidLoopRegion);
+        }
+
+        self.loop_region = region;
+        self.apply_settings()
+    }
+
+    pub fn get_loop_region(&self) -> Option<(f64, f64)> {
+        self.loop_region
+    }
+
+    /// Stopped once every follower played to its end.
+    pub fn get_state(&self) -> TransportState {
+        if self.state != TransportState::Stopped && self.finished() {
+            return TransportState::Stopped;
+        }
+
+        self.state
+    }
+
+    pub fn is_playing(&self) -> bool {
+        self.get_state() == TransportState::Playing
+    }
+
+    // Rate and loop region of every follower under one lock of all of them
+    fn apply_settings(&mut self) -> Result<(), TransportError> {
+        let rates = self.followers.iter().map(Follower::rate).collect::<Vec<_>>();
+        let mut followers = self.lock_followers()?;
+
+        for (follower, rate) in followers.iter_mut().zip(rates) {
+            follower.set_rate(rate * self.rate);
+            follower.set_loop_region(self.loop_region);
+        }
+
+        Ok(())
+    }
+
+    fn release_at_lead(&mut self) -> Result<(), TransportError> {
+        let mut followers = self.lock_followers()?;
+        let start = Self::start_time(&followers, self.lead);
+
+        for follower in followers.iter_mut().filter(|follower| follower.is_playing()) {
+            follower.set_start_at(start);
+        }
+
+        Ok(())
+    }
+
+    // Stop the transport once every follower played to its end
+    fn refresh_state(&mut self) {
+        if self.state != TransportState::Stopped && self.finished() {
+            self.position = 0.0;
+            self.state = TransportState::Stopped;
+        }
+    }
+
+    // Whether every follower played to its end, a looping follower never does
+    fn finished(&self) -> bool {
+        !self.followers.is_empty()
+            && self
+                .lock_followers()
+                .is_ok_and(|followers| followers.iter().all(|follower| !follower.is_playing()))
+    }
+
+    // Restore the follower at `index` as it was before following
+    fn release(&mut self, index: usize) -> Result<bool, TransportError> {
+        let follower = self.followers.remove(index);
+        let rate = follower.rate();
+
+        let mut guard = follower.lock()?;
+        guard.set_rate(rate);
+        guard.set_loop_region(None);
+
+        if self.state == TransportState::Paused {
+            guard.set_start_at(None);
+        }
+
+        Ok(true)
+    }
+
+    fn lock_followers(&self) -> Result<Vec<FollowerGuard<'_>>, TransportError> {
+        syncstart::lock_all(&self.followers, Follower::lock)
+    }
+
+    // Device frame the followers start on, None when none is attached
+    fn start_time(followers: &[FollowerGuard<'_>], lead: u64) -> Option<u64> {
+        syncstart::start_time(followers.iter().map(|follower| follower.clock()), lead)
+    }
+}
+
+impl Follower {
+    fn rate(&self) -> f32 {
+        match self {
+            Follower::Track { rate, .. } | Follower::Mixer { rate, .. } => *rate,
+        }
+    }
+
+    fn lock(&self) -> Result<FollowerGuard<'_>, TransportError> {
+        match self {
+            Follower::Track { track, .. } => track
+                .inner
+                .lock()
+                .map(FollowerGuard::Track)
+                .map_err(|_| TransportError::LockFailed),
+            Follower::Mixer { inner, .. } => inner
+                .lock()
+                .map(FollowerGuard::Mixer)
+                .map_err(|_| TransportError::LockFailed),
+        }
+    }
+}
+
+impl FollowerGuard<'_> {
+    fn sample_rate(&self) -> f64 {
+        match self {
+            FollowerGuard::Track(track) => track.reader.sample_rate as f64,
+            FollowerGuard::Mixer(mixer) => mixer.sample_rate as f64,
+        }
+    }
+
+    fn clock(&self) -> Option<&Arc<std::sync::atomic::AtomicU64>> {
+        match self {
+            FollowerGuard::Track(track) => track.clock.as_ref(),
+            FollowerGuard::Mixer(mixer) => mixer.clock.as_ref(),
+        }
+    }
+
+    // Held followers count as playing, only the end of playback clears it
+    fn is_playing(&self) -> bool {
+        match self {
+            FollowerGuard::Track(track) => track.playing.load(Ordering::SeqCst),
+            FollowerGuard::Mixer(mixer) => mixer.is_playing.load(Ordering::SeqCst),
+        }
+    }
+
+    fn position(&self) -> f64 {
+        let position = match self {
+            FollowerGuard::Track(track) => track.get_played_position(),
+            FollowerGuard::Mixer(mixer) => mixer.get_played_position(),
+        };
+
+        position as f64 / self.sample_rate()
+    }
+
+    fn seek(&mut self, position: f64) -> Result<(), TransportError> {
+        let frame = (position * self.sample_rate()).round() as usize;
+
+        match self {
+            FollowerGuard::Track(track) => {
+                if frame < track.reader.pcm_length {
+                    track.seek(frame).map_err(TransportError::from_other)?;
+                }
+            }
+            FollowerGuard::Mixer(mixer) => {
+                mixer.seek(Some(frame)).map_err(TransportError::from_other)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn set_start_at(&mut self, start_at: Option<u64>) {
+        match self {
+            FollowerGuard::Track(track) => track.start_at = start_at,
+            FollowerGuard::Mixer(mixer) => mixer.start_at = start_at,
+        }
+    }
+
+    fn stop(&mut self) {
+        match self {
+            FollowerGuard::Track(track) => {
+                track.stop_at = None;
+                track.start_at = None;
+                track.playing.store(false, Ordering::SeqCst);
+                track.finished.notify(PlaybackEnd::Stopped);
+            }
+            FollowerGuard::Mixer(mixer) => {
+                mixer.start_at = None;
+                mixer.stop();
+            }
+        }
+    }
+
+    fn set_rate(&mut self, rate: f32) {
+        match self {
+            FollowerGuard::Track(track) => track.resampler.set_target_sample_rate(rate),
+            FollowerGuard::Mixer(mixer) => mixer.resampler.set_target_sample_rate(rate),
+        }
+    }
+
+    fn set_loop_region(&mut self, region: Option<(f64, f64)>) {
+        let sample_rate = self.sample_rate();
+        let frames = region.map(|(start, end)| {
+            ((start * sample_rate) as usize, (end * sample_rate) as usize)
+        });
+
+        match self {
+            // A track ending inside the region loops up to its end, one ending before it
+            // plays through
+            FollowerGuard::Track(track) => {
+                let length = track.reader.pcm_length;
+                track.loop_region = frames
+                    .filter(|(start, _)| *start < length)
+                    .map(|(start, end)| (start, end.min(length)));
+            }
+            FollowerGuard::Mixer(mixer) => mixer.loop_region = frames,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{BufferInfo, Source, TrackInfo};
+
+    // One second of silence at 48000 Hz
+    fn track() -> Track {
+        let data = vec![0.0f32; 48000];
+
+        crate::create_track(TrackInfo {
+            source: Source::Buffer(BufferInfo {
+                data: &data,
+                channels: 1,
+                sample_rate: 48000.0,
+            }),
+            ..Default::default()
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn test_settings_validation() {
+        let mut transport = Transport::new();
+
+        assert!(transport.set_rate(0.0).is_err());
+        assert!(transport.set_rate(f32::NAN).is_err());
+        assert!(transport.seek(-1.0).is_err());
+        assert!(transport.set_loop_region(Some((2.0, 1.0))).is_err());
+        assert!(transport.set_loop_region(Some((-1.0, 1.0))).is_err());
+
+        transport.set_rate(1.5).unwrap();
+        transport.set_loop_region(Some((1.0, 2.0))).unwrap();
+        assert_eq!(transport.get_rate(), 1.5);
+        assert_eq!(transport.get_loop_region(), Some((1.0, 2.0)));
+    }
+
+    #[test]
+    fn test_stopped_position() {
+        let mut transport = Transport::new();
+        assert_eq!(transport.get_state(), TransportState::Stopped);
+
+        // Kept until the next play while stopped, pausing does nothing
+        transport.seek(2.0).unwrap();
+        transport.pause().unwrap();
+        assert_eq!(transport.get_position().unwrap(), 2.0);
+        assert_eq!(transport.get_state(), TransportState::Stopped);
+
+        transport.stop().unwrap();
+        assert_eq!(transport.get_position().unwrap(), 0.0);
+    }
+
+    #[test]
+    fn test_follow_track() {
+        let mut transport = Transport::new();
+        let track = track();
+        let rate = track.inner.lock().unwrap().resampler.target_sample_rate;
+
+        transport.follow_track(&track).unwrap();
+        transport.follow_track(&track).unwrap();
+        assert_eq!(transport.len(), 1);
+        assert!(transport.follows_track(&track));
+
+        // The loop region is cut to the end of the track
+        transport.set_rate(2.0).unwrap();
+        transport.set_loop_region(Some((0.25, 2.0))).unwrap();
+        {
+            let inner = track.inner.lock().unwrap();
+            assert_eq!(inner.resampler.target_sample_rate, rate * 2.0);
+            assert_eq!(inner.loop_region, Some((12000, 48000)));
+        }
+
+        assert!(transport.unfollow_track(&track).unwrap());
+        assert!(!transport.unfollow_track(&track).unwrap());
+        assert!(transport.is_empty());
+
+        let inner = track.inner.lock().unwrap();
+        assert_eq!(inner.resampler.target_sample_rate, rate);
+        assert_eq!(inner.loop_region, None);
+    }
+}
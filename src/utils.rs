@@ -3,6 +3,19 @@
 
 use miniaudio_sys::*;
 
+/// Frames rendered per block when no block size is configured.
+pub const DEFAULT_BLOCK_SIZE: usize = 4096;
+
+/// Scratch buffers hold this many blocks, sources read ahead of the output when the
+/// FX tempo or the resampler needs more input than the frames produced.
+pub const BLOCK_HEADROOM: usize = 4;
+
+pub fn block_buffer_len(block_size: usize, channels: usize) -> usize {
+    block_size * BLOCK_HEADROOM * channels
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum TweenType {
     Linear,
     Quadratic,